@@ -0,0 +1,184 @@
+use crate::*;
+
+// A folder that is there but couldn't be read.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct DirError {
+  // None for RECEIPTS_ROOT itself and `_logs/runs`.
+  pub(crate) store_id: Option<String>,
+  pub(crate) path: String,
+  // The command error code: `permission_denied`, or `io` for anything else.
+  pub(crate) code: String,
+  pub(crate) message: String,
+}
+
+impl DirError {
+  pub(crate) fn new(store_id: Option<&str>, path: &Path, err: &AppError) -> DirError {
+    DirError {
+      store_id: store_id.map(str::to_string),
+      path: path.to_string_lossy().to_string(),
+      code: err.code().to_string(),
+      message: err.message(),
+    }
+  }
+}
+
+// On the status and dashboard snapshots for as long as any folder an enabled
+// store uses can't be read, so an unreadable folder isn't taken for an empty one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct AccessWarning {
+  pub(crate) message: String,
+  pub(crate) dirs: Vec<DirError>,
+  // macOS privacy settings block folders like Dropbox for apps without Full
+  // Disk Access; the hint says so and `settings_command` opens the pane.
+  pub(crate) hint: Option<String>,
+  pub(crate) settings_command: Option<String>,
+}
+
+// The Full Disk Access list under Privacy & Security.
+#[cfg(target_os = "macos")]
+const FULL_DISK_ACCESS_URL: &str =
+  "x-apple.systempreferences:com.apple.preference.security?Privacy_AllFiles";
+
+// None when the folder doesn't exist (there is nothing in it yet); any other
+// error keeps its kind, so a folder the app may not read isn't counted as empty.
+pub(crate) fn read_dir_if_exists(path: &Path) -> Result<Option<fs::ReadDir>, AppError> {
+  match fs::read_dir(path) {
+    Ok(entries) => Ok(Some(entries)),
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+    Err(err) => Err(AppError::io(path, err)),
+  }
+}
+
+// The root, then each enabled store's inbox, processed and failed folders and
+// the run summaries. When the root itself can't be read, that is all there is
+// to report.
+pub(crate) fn unreadable_dirs(config: &AppConfig) -> Vec<DirError> {
+  if config.receipts_root.is_empty() {
+    return Vec::new();
+  }
+  let root = Path::new(&config.receipts_root);
+  if let Err(err) = read_dir_if_exists(root) {
+    return vec![DirError::new(None, root, &err)];
+  }
+  let mut dirs = Vec::new();
+  for store in config.stores.iter().filter(|store| store.enabled) {
+    for kind in ["inbox", "processed", "failed"] {
+      let path = root.join(kind).join(&store.id);
+      if let Err(err) = read_dir_if_exists(&path) {
+        dirs.push(DirError::new(Some(&store.id), &path, &err));
+      }
+    }
+  }
+  let runs = root.join("_logs").join("runs");
+  if let Err(err) = read_dir_if_exists(&runs) {
+    dirs.push(DirError::new(None, &runs, &err));
+  }
+  dirs
+}
+
+pub(crate) fn access_warning_for(dirs: Vec<DirError>) -> Option<AccessWarning> {
+  if dirs.is_empty() {
+    return None;
+  }
+  let denied = dirs.iter().any(|dir| dir.code == "permission_denied");
+  let message = match dirs.as_slice() {
+    [dir] => format!("{}; it is left out of counts and reports", dir.message),
+    _ => format!(
+      "{} folders can't be read ({}); they are left out of counts and reports",
+      dirs.len(),
+      if denied {
+        "permission denied"
+      } else {
+        "read errors"
+      }
+    ),
+  };
+  let macos = cfg!(target_os = "macos") && denied;
+  Some(AccessWarning {
+    message,
+    hint: macos.then(|| {
+      format!(
+        "macOS may be keeping {} out of these folders. Add it under System Settings > Privacy & Security > Full Disk Access, then restart it.",
+        APP_DISPLAY_NAME
+      )
+    }),
+    settings_command: macos.then(|| "open_full_disk_access_settings".to_string()),
+    dirs,
+  })
+}
+
+pub(crate) fn access_warning(config: &AppConfig) -> Option<AccessWarning> {
+  access_warning_for(unreadable_dirs(config))
+}
+
+pub(crate) fn open_full_disk_access_pane() -> Result<(), AppError> {
+  #[cfg(target_os = "macos")]
+  {
+    let mut command = Command::new("open");
+    command.arg(FULL_DISK_ACCESS_URL);
+    return run_opener(command, "open").map_err(AppError::Other);
+  }
+  #[cfg(not(target_os = "macos"))]
+  Err(AppError::Unsupported(
+    "Full Disk Access settings only exist on macOS".to_string(),
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[cfg(unix)]
+  #[test]
+  fn unreadable_store_folders_are_reported_as_permission_denied() {
+    use std::os::unix::fs::PermissionsExt;
+    let root = tempfile::tempdir().expect("tempdir");
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.path().to_string_lossy().to_string();
+    let inbox = root.path().join("inbox").join("lidl");
+    fs::create_dir_all(&inbox).expect("inbox");
+    fs::write(inbox.join("bon.jpg"), b"x").expect("receipt");
+    fs::create_dir_all(root.path().join("processed").join("lidl")).expect("processed");
+
+    // Missing folders are just empty.
+    assert!(
+      scan_inbox_files(&root.path().join("inbox").join("kaufland"))
+        .expect("missing inbox")
+        .is_empty()
+    );
+    assert!(unreadable_dirs(&config).is_empty());
+    assert_eq!(access_warning(&config), None);
+
+    fs::set_permissions(&inbox, fs::Permissions::from_mode(0o000)).expect("chmod");
+    let blocked = fs::read_dir(&inbox).is_err();
+    let scanned = scan_inbox_files(&inbox);
+    let dirs = unreadable_dirs(&config);
+    let stats = compute_inbox_stats(&inbox);
+    let status = directory_status("inbox", &inbox);
+    fs::set_permissions(&inbox, fs::Permissions::from_mode(0o755)).expect("chmod back");
+    // Root reads through the mode bits; nothing to check then.
+    if !blocked {
+      return;
+    }
+    assert_eq!(
+      scanned.expect_err("unreadable inbox").code(),
+      "permission_denied"
+    );
+    assert_eq!(stats.count, 0);
+    assert_eq!(stats.error.map(|err| err.code()), Some("permission_denied"));
+    assert_eq!(status.readable, Some(false));
+    assert_eq!(dirs.len(), 1);
+    assert_eq!(dirs[0].store_id.as_deref(), Some("lidl"));
+    assert_eq!(dirs[0].code, "permission_denied");
+    assert_eq!(dirs[0].path, inbox.to_string_lossy());
+
+    let warning = access_warning_for(dirs).expect("warning");
+    assert!(warning.message.contains("left out of counts"));
+    assert_eq!(warning.hint.is_some(), cfg!(target_os = "macos"));
+    assert_eq!(
+      warning.settings_command.as_deref(),
+      cfg!(target_os = "macos").then_some("open_full_disk_access_settings")
+    );
+  }
+}
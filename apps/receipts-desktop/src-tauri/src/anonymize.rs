@@ -0,0 +1,427 @@
+use crate::*;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use std::hash::BuildHasher;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct AnonymizedExport {
+  pub(crate) path: String,
+  pub(crate) summaries: usize,
+  pub(crate) receipts: usize,
+  // `mapping.json.enc` when the mapping was asked for, with the key to open it
+  // (base64). The key isn't stored anywhere else.
+  pub(crate) mapping_file: Option<String>,
+  pub(crate) mapping_key: Option<String>,
+}
+
+// Tokens for one export. Keys are random per export, so the same text gets
+// the same token within it but nothing links two exports. Amounts are all
+// scaled by one secret factor, so items still add up to their total.
+pub(crate) struct Anonymizer {
+  pub(crate) keys: std::collections::hash_map::RandomState,
+  pub(crate) scale: f64,
+  // token -> original, for the mapping file.
+  pub(crate) mapping: std::collections::BTreeMap<String, String>,
+}
+
+impl Anonymizer {
+  pub(crate) fn new() -> Anonymizer {
+    let keys = std::collections::hash_map::RandomState::new();
+    // Between 0.5 and 2.0.
+    let scale = 0.5 + (keys.hash_one("scale") % 1501) as f64 / 1000.0;
+    Anonymizer {
+      keys,
+      scale,
+      mapping: std::collections::BTreeMap::new(),
+    }
+  }
+
+  pub(crate) fn token(&mut self, kind: &str, text: &str) -> String {
+    let token = format!(
+      "{}-{:012x}",
+      kind,
+      self.keys.hash_one((kind, text)) & 0xffff_ffff_ffff
+    );
+    self
+      .mapping
+      .entry(token.clone())
+      .or_insert_with(|| text.to_string());
+    token
+  }
+
+  // Keeps everything from the first `.` (`a.jpg.json` -> `file-….jpg.json`), so
+  // an image and its output still share a name.
+  pub(crate) fn file_name(&mut self, name: &str) -> String {
+    let (stem, rest) = name.split_at(name.find('.').unwrap_or(name.len()));
+    format!("{}{}", self.token("file", stem), rest)
+  }
+
+  // Unparseable amounts become tokens rather than staying readable.
+  pub(crate) fn amount(&mut self, value: &Value) -> Value {
+    match parse_amount(value) {
+      Some(amount) => serde_json::json!(round_cents(amount * self.scale)),
+      None if value.is_null() => Value::Null,
+      None => Value::String(self.token("amount", &value.to_string())),
+    }
+  }
+
+  // Everything under `merchant` and every text field of `items`, plus
+  // `raw_text`, becomes a token; amounts are scaled. Dates, currency,
+  // quantities and the shape of the output stay as they are.
+  pub(crate) fn receipt(&mut self, value: &Value) -> Value {
+    self.walk(value, None, false)
+  }
+
+  pub(crate) fn walk(&mut self, value: &Value, key: Option<&str>, merchant: bool) -> Value {
+    match value {
+      Value::Object(fields) => Value::Object(
+        fields
+          .iter()
+          .map(|(name, field)| {
+            let inside = merchant || name == "merchant";
+            (name.clone(), self.walk(field, Some(name), inside))
+          })
+          .collect(),
+      ),
+      Value::Array(entries) => Value::Array(
+        entries
+          .iter()
+          .map(|entry| self.walk(entry, key, merchant))
+          .collect(),
+      ),
+      _ if key.is_some_and(|key| ANONYMIZED_AMOUNT_KEYS.contains(&key)) => self.amount(value),
+      Value::String(text) if merchant => Value::String(self.token("merchant", text)),
+      Value::String(text) if key.is_some_and(|key| ANONYMIZED_TEXT_KEYS.contains(&key)) => {
+        Value::String(self.token("text", text))
+      }
+      _ => value.clone(),
+    }
+  }
+
+  // File names in `processed`, `failures` and `warnings` become tokens, also
+  // where a message quotes them. Run ids, stores, counts and timings stay.
+  pub(crate) fn summary(&mut self, value: &Value) -> Value {
+    let mut names = Vec::new();
+    for kind in ["processed", "failures", "warnings"] {
+      for entry in value
+        .get(kind)
+        .and_then(|entries| entries.as_array())
+        .into_iter()
+        .flatten()
+      {
+        if let Some(name) = entry
+          .get("file")
+          .and_then(|file| file.as_str())
+          .and_then(|file| Path::new(file).file_name())
+        {
+          names.push(name.to_string_lossy().to_string());
+        }
+      }
+    }
+    names.sort();
+    names.dedup();
+    // Longest first, so `a.jpg.json` isn't cut short by `a.jpg`.
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    let replacements = names
+      .iter()
+      .map(|name| (name.clone(), self.file_name(name)))
+      .collect::<Vec<_>>();
+    let redact = |text: &str| {
+      replacements
+        .iter()
+        .fold(text.to_string(), |text, (name, token)| {
+          text.replace(name, token)
+        })
+    };
+
+    let mut summary = value.clone();
+    for kind in ["processed", "failures", "warnings"] {
+      if let Some(entries) = summary
+        .get_mut(kind)
+        .and_then(|entries| entries.as_array_mut())
+      {
+        for entry in entries.iter_mut() {
+          if let Some(fields) = entry.as_object_mut() {
+            for field in fields.values_mut() {
+              if let Value::String(text) = field {
+                *text = redact(text);
+              }
+            }
+          }
+        }
+      }
+    }
+    summary
+  }
+}
+
+// XChaCha20-Poly1305 with a fresh key: the 24-byte nonce, then the sealed
+// mapping JSON. Returns the file contents and the key.
+pub(crate) fn seal_mapping(
+  mapping: &std::collections::BTreeMap<String, String>,
+) -> Result<(Vec<u8>, [u8; 32]), AppError> {
+  let mut key = [0u8; 32];
+  let mut nonce = [0u8; 24];
+  getrandom::getrandom(&mut key).map_err(|err| err.to_string())?;
+  getrandom::getrandom(&mut nonce).map_err(|err| err.to_string())?;
+  let raw = serde_json::to_vec_pretty(mapping).map_err(|err| err.to_string())?;
+  let cipher = chacha20poly1305::XChaCha20Poly1305::new(&key.into());
+  let sealed = cipher
+    .encrypt(&nonce.into(), raw.as_slice())
+    .map_err(|_| AppError::Other("Could not encrypt the mapping".to_string()))?;
+  let mut file = nonce.to_vec();
+  file.extend_from_slice(&sealed);
+  Ok((file, key))
+}
+
+// Writes `summaries/<run>.summary.json` and `processed/<store>/<token>.json`
+// into `dest`, which has to be a new or empty folder outside the root. Runs
+// and receipts outside `from`..`to` are left out.
+pub(crate) fn export_anonymized_to(
+  receipts_root: &str,
+  store_ids: &[String],
+  from: Option<chrono::NaiveDate>,
+  to: Option<chrono::NaiveDate>,
+  dest: &Path,
+  include_mapping: bool,
+  anonymizer: &mut Anonymizer,
+) -> Result<AnonymizedExport, AppError> {
+  let display = dest.to_string_lossy().to_string();
+  if !dest.is_absolute() {
+    return Err(AppError::InvalidInput(format!(
+      "Export path must be absolute: {}",
+      display
+    )));
+  }
+  if dest.starts_with(receipts_root) {
+    return Err(AppError::InvalidInput(format!(
+      "{} is inside RECEIPTS_ROOT",
+      display
+    )));
+  }
+  if fs::read_dir(dest).is_ok_and(|mut entries| entries.next().is_some()) {
+    return Err(AppError::FileExists(display));
+  }
+  let in_range = |date: chrono::NaiveDate| {
+    from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+  };
+  let write = |path: &Path, value: &Value| -> Result<(), AppError> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).map_err(|err| AppError::io(parent, err))?;
+    }
+    let raw = serde_json::to_string_pretty(value).map_err(|err| err.to_string())?;
+    fs::write(path, raw).map_err(|err| AppError::io(path, err))
+  };
+
+  let mut summaries = 0;
+  for (summary, modified) in list_run_summaries(receipts_root) {
+    let run_id = extract_run_id(&summary).unwrap_or_default();
+    let started_at = summary.get("started_at").and_then(|time| time.as_str());
+    let run = RunId::new(&run_id, started_at, modified);
+    let date = run
+      .sort_time()
+      .map(|time| time.with_timezone(&chrono::Local).date_naive());
+    if !date.is_some_and(in_range) {
+      continue;
+    }
+    summaries += 1;
+    let name = if run_id.is_empty() {
+      format!("run-{}", summaries)
+    } else {
+      run_id.replace(['/', '\\'], "_")
+    };
+    write(
+      &dest
+        .join("summaries")
+        .join(format!("{}.summary.json", name)),
+      &anonymizer.summary(&summary),
+    )?;
+  }
+
+  let mut receipts = 0;
+  for store_id in store_ids {
+    let dir = processed_dir(receipts_root, store_id);
+    for name in receipt_output_names(&dir) {
+      let path = join_file_id(&dir, &name);
+      let value = match fs::read_to_string(&path).map(|raw| serde_json::from_str::<Value>(&raw)) {
+        Ok(Ok(value)) if value.is_object() => value,
+        _ => continue,
+      };
+      let date = receipt_total_from(store_id, &path, &value).and_then(|receipt| receipt.date);
+      if !date.is_some_and(in_range) {
+        continue;
+      }
+      receipts += 1;
+      write(
+        &dest
+          .join("processed")
+          .join(store_id)
+          .join(anonymizer.file_name(&name)),
+        &anonymizer.receipt(&value),
+      )?;
+    }
+  }
+  fs::create_dir_all(dest).map_err(|err| AppError::io(dest, err))?;
+
+  let (mapping_file, mapping_key) = if include_mapping {
+    let (sealed, key) = seal_mapping(&anonymizer.mapping)?;
+    let path = dest.join(ANONYMIZED_MAPPING_FILE);
+    fs::write(&path, sealed).map_err(|err| AppError::io(&path, err))?;
+    (
+      Some(path.to_string_lossy().to_string()),
+      Some(base64::engine::general_purpose::STANDARD.encode(key)),
+    )
+  } else {
+    (None, None)
+  };
+  Ok(AnonymizedExport {
+    path: display,
+    summaries,
+    receipts,
+    mapping_file,
+    mapping_key,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn anonymizer_replaces_names_and_amounts_consistently() {
+    let mut anonymizer = Anonymizer::new();
+    let receipt = serde_json::json!({
+      "date": "2024-03-02",
+      "currency": "RON",
+      "total": "12,50",
+      "merchant": {"name": "Lidl Militari", "address": "Str. Exemplu 1"},
+      "raw_text": "LIDL MILITARI\nLAPTE 12,50",
+      "items": [
+        {"name": "Lapte", "quantity": 2, "unit_price": 5.0, "paid_amount": 10.0},
+        {"name": "Lapte", "quantity": 1, "paid_amount": 2.5},
+      ],
+    });
+    let anonymized = anonymizer.receipt(&receipt);
+    assert_eq!(anonymized["date"], "2024-03-02");
+    assert_eq!(anonymized["currency"], "RON");
+    assert_eq!(anonymized["items"][0]["quantity"], 2);
+    let merchant = anonymized["merchant"]["name"].as_str().expect("merchant");
+    assert!(merchant.starts_with("merchant-"));
+    assert_ne!(anonymized["merchant"]["address"], merchant);
+    assert!(anonymized["raw_text"]
+      .as_str()
+      .is_some_and(|text| !text.contains("LIDL")));
+    assert_eq!(
+      anonymized["items"][0]["name"],
+      anonymized["items"][1]["name"]
+    );
+    let amount = |value: &Value| value.as_f64().expect("amount");
+    let total = amount(&anonymized["total"]);
+    let items = amount(&anonymized["items"][0]["paid_amount"])
+      + amount(&anonymized["items"][1]["paid_amount"]);
+    assert!((total - items).abs() < 0.02);
+    assert_eq!(anonymizer.receipt(&receipt), anonymized);
+    assert_eq!(
+      anonymizer.mapping.get(merchant).map(String::as_str),
+      Some("Lidl Militari")
+    );
+
+    let summary = serde_json::json!({
+      "run_id": "20240302-093000",
+      "stores": ["lidl"],
+      "processed": [{"store": "lidl", "file": "inbox/lidl/IMG_1.jpg"}],
+      "failures": [{"store": "lidl", "file": "IMG_2.jpg", "error": "IMG_2.jpg: no total"}],
+    });
+    let anonymized = anonymizer.summary(&summary);
+    assert_eq!(anonymized["run_id"], "20240302-093000");
+    let failure = &anonymized["failures"][0];
+    let file = failure["file"].as_str().expect("file");
+    assert!(file.starts_with("file-") && file.ends_with(".jpg"));
+    assert_eq!(failure["error"], format!("{}: no total", file));
+    assert!(anonymized["processed"][0]["file"]
+      .as_str()
+      .is_some_and(|path| path.starts_with("inbox/lidl/file-")));
+    assert_eq!(
+      anonymizer.file_name("IMG_2.jpg.json"),
+      format!("{}.json", file)
+    );
+  }
+
+  #[test]
+  fn anonymized_export_writes_the_range_and_an_encrypted_mapping() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path().join("receipts");
+    let runs = root.join("_logs").join("runs");
+    let processed = root.join("processed").join("lidl");
+    fs::create_dir_all(&runs).expect("runs");
+    fs::create_dir_all(&processed).expect("processed");
+    for (id, file) in [("20240302-093000", "a.jpg"), ("20230101-093000", "old.jpg")] {
+      fs::write(
+        runs.join(format!("{}.summary.json", id)),
+        serde_json::json!({"run_id": id, "stores": ["lidl"], "processed": [{"file": file}]})
+          .to_string(),
+      )
+      .expect("summary");
+    }
+    for (name, date) in [("a.jpg.json", "2024-03-02"), ("old.jpg.json", "2023-01-01")] {
+      fs::write(
+        processed.join(name),
+        serde_json::json!({"date": date, "total": 10, "merchant": {"name": "Lidl"}}).to_string(),
+      )
+      .expect("output");
+    }
+    let stores = vec!["lidl".to_string()];
+    let root_text = root.to_string_lossy().to_string();
+    let dest = dir.path().join("share");
+    let mut anonymizer = Anonymizer::new();
+    let export = export_anonymized_to(
+      &root_text,
+      &stores,
+      chrono::NaiveDate::from_ymd_opt(2024, 1, 1),
+      None,
+      &dest,
+      true,
+      &mut anonymizer,
+    )
+    .expect("export");
+    assert_eq!((export.summaries, export.receipts), (1, 1));
+    assert!(dest
+      .join("summaries")
+      .join("20240302-093000.summary.json")
+      .exists());
+    let outputs = fs::read_dir(dest.join("processed").join("lidl"))
+      .expect("outputs")
+      .flatten()
+      .map(|entry| entry.file_name().to_string_lossy().to_string())
+      .collect::<Vec<_>>();
+    assert_eq!(outputs.len(), 1);
+    assert!(outputs[0].starts_with("file-") && outputs[0].ends_with(".jpg.json"));
+
+    let sealed = fs::read(dest.join(ANONYMIZED_MAPPING_FILE)).expect("mapping");
+    assert!(!String::from_utf8_lossy(&sealed).contains("Lidl"));
+    use chacha20poly1305::aead::{Aead, KeyInit};
+    let key = base64::engine::general_purpose::STANDARD
+      .decode(export.mapping_key.expect("key"))
+      .expect("base64");
+    let cipher = chacha20poly1305::XChaCha20Poly1305::new_from_slice(&key).expect("key");
+    let (nonce, body) = sealed.split_at(24);
+    let raw = cipher
+      .decrypt(chacha20poly1305::XNonce::from_slice(nonce), body)
+      .expect("decrypt");
+    let mapping: std::collections::BTreeMap<String, String> =
+      serde_json::from_slice(&raw).expect("json");
+    assert!(mapping.values().any(|original| original == "Lidl"));
+
+    assert!(matches!(
+      export_anonymized_to(
+        &root_text,
+        &stores,
+        None,
+        None,
+        &dest,
+        false,
+        &mut Anonymizer::new()
+      ),
+      Err(AppError::FileExists(_))
+    ));
+  }
+}
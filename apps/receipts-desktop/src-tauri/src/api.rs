@@ -0,0 +1,443 @@
+use crate::*;
+
+// The optional HTTP API for the life-dashboard web UI. It serves the same
+// caches the commands do (dashboard scanner, issue runs, run index, monthly
+// report cache), so a request never starts a scan of its own.
+#[derive(Default)]
+pub(crate) struct ApiServer(pub(crate) Mutex<ApiServerState>);
+
+#[derive(Default)]
+pub(crate) struct ApiServerState {
+  pub(crate) running: Option<RunningApi>,
+  // Why the last start failed, e.g. the port was taken.
+  pub(crate) last_error: Option<String>,
+}
+
+pub(crate) struct RunningApi {
+  pub(crate) port: u16,
+  pub(crate) server: Arc<tiny_http::Server>,
+  pub(crate) thread: thread::JoinHandle<()>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ApiStatus {
+  pub(crate) enabled: bool,
+  pub(crate) running: bool,
+  pub(crate) port: u16,
+  pub(crate) url: Option<String>,
+  // Clients send its contents as `Authorization: Bearer <token>`.
+  pub(crate) token_path: Option<String>,
+  pub(crate) last_error: Option<String>,
+}
+
+pub(crate) fn api_token_path() -> Option<PathBuf> {
+  state_path(API_TOKEN_FILE)
+}
+
+// 32 random bytes as hex, written once and reused so clients keep working
+// across restarts; delete the file to rotate it.
+pub(crate) fn load_or_create_api_token(path: &Path) -> Result<String, AppError> {
+  if let Ok(raw) = fs::read_to_string(path) {
+    let token = raw.trim();
+    if token.len() >= 32 {
+      return Ok(token.to_string());
+    }
+  }
+  let mut bytes = [0u8; 32];
+  getrandom::getrandom(&mut bytes).map_err(|err| err.to_string())?;
+  let token = bytes
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect::<String>();
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| AppError::io(parent, err))?;
+  }
+  let temp = write_synced_temp(path, &token)?;
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(&temp, fs::Permissions::from_mode(0o600))
+      .map_err(|err| AppError::io(&temp, err))?;
+  }
+  fs::rename(&temp, path).map_err(|err| AppError::io(path, err))?;
+  Ok(token)
+}
+
+// Compared byte by byte to the end, so the time taken doesn't tell how much
+// of a guess was right.
+pub(crate) fn bearer_matches(header: Option<&str>, token: &str) -> bool {
+  let given = match header.and_then(|header| header.trim().strip_prefix("Bearer ")) {
+    Some(given) => given.trim().as_bytes(),
+    None => return false,
+  };
+  given.len() == token.len()
+    && given
+      .iter()
+      .zip(token.as_bytes())
+      .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+      == 0
+}
+
+pub(crate) fn percent_decode(raw: &str) -> String {
+  let bytes = raw.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+  while index < bytes.len() {
+    match bytes[index] {
+      b'+' => decoded.push(b' '),
+      b'%' if index + 2 < bytes.len() => {
+        let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).ok();
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+          Some(byte) => {
+            decoded.push(byte);
+            index += 2;
+          }
+          None => decoded.push(b'%'),
+        }
+      }
+      byte => decoded.push(byte),
+    }
+    index += 1;
+  }
+  String::from_utf8_lossy(&decoded).to_string()
+}
+
+// `/api/spending?from=2024-01-01` -> ("/api/spending", {"from": "2024-01-01"}).
+// Empty values count as absent.
+pub(crate) fn parse_api_url(url: &str) -> (String, HashMap<String, String>) {
+  let (path, query) = url.split_once('?').unwrap_or((url, ""));
+  let params = query
+    .split('&')
+    .filter_map(|pair| {
+      let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+      let value = percent_decode(value);
+      (!key.is_empty() && !value.is_empty()).then(|| (percent_decode(key), value))
+    })
+    .collect();
+  (path.trim_end_matches('/').to_string(), params)
+}
+
+pub(crate) fn param<T: std::str::FromStr>(
+  params: &HashMap<String, String>,
+  name: &str,
+) -> Result<Option<T>, AppError> {
+  params
+    .get(name)
+    .map(|value| {
+      value
+        .parse()
+        .map_err(|_| AppError::InvalidInput(format!("Invalid {}: {}", name, value)))
+    })
+    .transpose()
+}
+
+pub(crate) fn required_param<T: std::str::FromStr>(
+  params: &HashMap<String, String>,
+  name: &str,
+) -> Result<T, AppError> {
+  param(params, name)?.ok_or_else(|| AppError::InvalidInput(format!("Missing {}", name)))
+}
+
+pub(crate) fn to_json<T: Serialize>(result: Result<T, AppError>) -> Result<Value, AppError> {
+  result
+    .and_then(|value| serde_json::to_value(value).map_err(|err| AppError::Other(err.to_string())))
+}
+
+// get_last_runs, from the dashboard scanner's snapshot.
+pub(crate) fn api_runs(
+  app: &tauri::AppHandle,
+  params: &HashMap<String, String>,
+) -> Result<Vec<Value>, AppError> {
+  let limit = param::<usize>(params, "limit")?
+    .unwrap_or(5)
+    .min(DASHBOARD_LAST_RUNS);
+  let mut runs = dashboard_snapshot(app, None, false)?.last_runs;
+  runs.truncate(limit);
+  Ok(runs)
+}
+
+// get_run_stats, from the run index.
+pub(crate) fn api_run_stats(
+  app: &tauri::AppHandle,
+  params: &HashMap<String, String>,
+) -> Result<RunStats, AppError> {
+  let config = current_config(app);
+  let store_id = params.get("store_id");
+  if let Some(store_id) = store_id {
+    require_store(&config, store_id)?;
+  }
+  Ok(run_stats(app, &config, store_id.map(String::as_str)))
+}
+
+pub(crate) fn api_spending(
+  app: &tauri::AppHandle,
+  params: &HashMap<String, String>,
+) -> Result<SpendingSummary, AppError> {
+  let config = current_config(app);
+  let from = parse_date_bound("from", params.get("from").map(String::as_str))?;
+  let to = parse_date_bound("to", params.get("to").map(String::as_str))?;
+  let conversion = Conversion::new(&config, params.get("convert_to").map(String::as_str))?;
+  let store_id = params.get("store_id").cloned();
+  spending_summary(app, &config, from, to, store_id, &conversion)
+}
+
+pub(crate) fn api_monthly_report(
+  app: &tauri::AppHandle,
+  params: &HashMap<String, String>,
+) -> Result<MonthlyReport, AppError> {
+  let config = current_config(app);
+  let conversion = Conversion::new(&config, params.get("convert_to").map(String::as_str))?;
+  let (year, month) = (
+    required_param(params, "year")?,
+    required_param(params, "month")?,
+  );
+  cached_monthly_report(app, &config, year, month, &conversion)
+}
+
+// The read-only endpoints, each answering what its command would; None for an
+// unknown path.
+pub(crate) fn api_response(
+  app: &tauri::AppHandle,
+  path: &str,
+  params: &HashMap<String, String>,
+) -> Option<Result<Value, AppError>> {
+  match path {
+    "/api/status" => Some(to_json(build_status_snapshot(app).map_err(AppError::from))),
+    "/api/runs" => Some(to_json(api_runs(app, params))),
+    "/api/run-stats" => Some(to_json(api_run_stats(app, params))),
+    "/api/spending" => Some(to_json(api_spending(app, params))),
+    "/api/monthly-report" => Some(to_json(api_monthly_report(app, params))),
+    _ => None,
+  }
+}
+
+pub(crate) fn api_error_status(err: &AppError) -> u16 {
+  match err {
+    AppError::InvalidInput(_) | AppError::UnknownStore(_) | AppError::Parse { .. } => 400,
+    AppError::ConfigMissing(_) | AppError::HomeUnavailable => 503,
+    _ => 500,
+  }
+}
+
+pub(crate) fn json_response(
+  status: u16,
+  body: &Value,
+) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+  let mut response = tiny_http::Response::from_string(body.to_string()).with_status_code(status);
+  for (field, value) in [
+    ("Content-Type", "application/json"),
+    ("Cache-Control", "no-store"),
+  ] {
+    if let Ok(header) = tiny_http::Header::from_bytes(field.as_bytes(), value.as_bytes()) {
+      response.add_header(header);
+    }
+  }
+  response
+}
+
+pub(crate) fn handle_api_request(app: &tauri::AppHandle, token: &str, request: tiny_http::Request) {
+  let started = Instant::now();
+  let authorization = request
+    .headers()
+    .iter()
+    .find(|header| header.field.equiv("Authorization"))
+    .map(|header| header.value.as_str().to_string());
+  let (path, params) = parse_api_url(request.url());
+  let (status, body) = if !bearer_matches(authorization.as_deref(), token) {
+    (
+      401,
+      serde_json::json!({ "code": "unauthorized", "message": "Missing or wrong bearer token" }),
+    )
+  } else if *request.method() != tiny_http::Method::Get {
+    (
+      405,
+      serde_json::json!({ "code": "method_not_allowed", "message": "Only GET is served" }),
+    )
+  } else {
+    match api_response(app, &path, &params) {
+      Some(Ok(value)) => (200, value),
+      Some(Err(err)) => (
+        api_error_status(&err),
+        serde_json::to_value(&err).unwrap_or(Value::Null),
+      ),
+      None => (
+        404,
+        serde_json::json!({ "code": "not_found", "message": format!("No endpoint {}", path) }),
+      ),
+    }
+  };
+  debug!(
+    "api {} {} -> {} in {}ms",
+    request.method(),
+    path,
+    status,
+    started.elapsed().as_millis()
+  );
+  if let Err(err) = request.respond(json_response(status, &body)) {
+    debug!("api response for {} not sent: {}", path, err);
+  }
+}
+
+pub(crate) fn busy_response(request: tiny_http::Request) {
+  let body = serde_json::json!({ "code": "busy", "message": "Too many requests in flight" });
+  let _ = request.respond(json_response(503, &body));
+}
+
+// API_WORKER_THREADS requests are served at once, so a slow spending walk
+// doesn't hold up the status endpoint; past API_QUEUE_DEPTH waiting requests
+// the rest get a 503 rather than piling up. The workers exit once the accept
+// loop stops and the queue is drained.
+pub(crate) fn serve_api_requests(
+  app: tauri::AppHandle,
+  token: Arc<String>,
+  server: &tiny_http::Server,
+) {
+  let (sender, receiver) = std::sync::mpsc::sync_channel::<tiny_http::Request>(API_QUEUE_DEPTH);
+  let receiver = Arc::new(Mutex::new(receiver));
+  for _ in 0..API_WORKER_THREADS {
+    let (app, token, receiver) = (app.clone(), token.clone(), receiver.clone());
+    thread::spawn(move || loop {
+      let request = match receiver.lock().map(|receiver| receiver.recv()) {
+        Ok(Ok(request)) => request,
+        _ => return,
+      };
+      handle_api_request(&app, &token, request);
+    });
+  }
+  for request in server.incoming_requests() {
+    if let Err(std::sync::mpsc::TrySendError::Full(request)) = sender.try_send(request) {
+      debug!("api queue full; answering 503");
+      busy_response(request);
+    }
+  }
+}
+
+// Binds 127.0.0.1 only.
+pub(crate) fn start_api_server(app: &tauri::AppHandle, port: u16) -> Result<RunningApi, AppError> {
+  let token_path = api_token_path().ok_or(AppError::HomeUnavailable)?;
+  let token = Arc::new(load_or_create_api_token(&token_path)?);
+  let server = tiny_http::Server::http(("127.0.0.1", port))
+    .map(Arc::new)
+    .map_err(|err| AppError::Other(format!("Could not listen on 127.0.0.1:{}: {}", port, err)))?;
+  let (handle, accepting) = (app.clone(), server.clone());
+  let thread = thread::spawn(move || serve_api_requests(handle, token, &accepting));
+  info!("api listening on 127.0.0.1:{}", port);
+  Ok(RunningApi {
+    port,
+    server,
+    thread,
+  })
+}
+
+pub(crate) fn stop_running_api(running: RunningApi) {
+  running.server.unblock();
+  if running.thread.join().is_err() {
+    warn!("api server thread panicked");
+  }
+  info!("api on port {} stopped", running.port);
+}
+
+// Brings the server in line with the config: stopped when disabled, started
+// (or restarted on a new port) when enabled.
+pub(crate) fn sync_api_server(app: &tauri::AppHandle, config: &AppConfig) {
+  let api = app.state::<ApiServer>();
+  let mut state = match api.0.lock() {
+    Ok(state) => state,
+    Err(_) => return,
+  };
+  let wanted = config.api_enabled.then_some(config.api_port);
+  if state.running.as_ref().map(|running| running.port) == wanted {
+    return;
+  }
+  if let Some(running) = state.running.take() {
+    stop_running_api(running);
+  }
+  state.last_error = None;
+  if let Some(port) = wanted {
+    match start_api_server(app, port) {
+      Ok(running) => state.running = Some(running),
+      Err(err) => {
+        warn!("api: {}", err);
+        state.last_error = Some(err.message());
+      }
+    }
+  }
+}
+
+pub(crate) fn stop_api_server(app: &tauri::AppHandle) {
+  let running = app
+    .state::<ApiServer>()
+    .0
+    .lock()
+    .ok()
+    .and_then(|mut state| state.running.take());
+  if let Some(running) = running {
+    stop_running_api(running);
+  }
+}
+
+pub(crate) fn api_status(app: &tauri::AppHandle, config: &AppConfig) -> ApiStatus {
+  let (port, last_error) = app
+    .state::<ApiServer>()
+    .0
+    .lock()
+    .map(|state| {
+      (
+        state.running.as_ref().map(|running| running.port),
+        state.last_error.clone(),
+      )
+    })
+    .unwrap_or((None, None));
+  ApiStatus {
+    enabled: config.api_enabled,
+    running: port.is_some(),
+    port: port.unwrap_or(config.api_port),
+    url: port.map(|port| format!("http://127.0.0.1:{}/api", port)),
+    token_path: api_token_path().map(|path| path.to_string_lossy().to_string()),
+    last_error,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn api_parses_urls_checks_the_bearer_token_and_keeps_it() {
+    let (path, params) =
+      parse_api_url("/api/spending/?from=2024-01-01&convert_to=eur&store_id=&x%20y=a+b%2Fc");
+    assert_eq!(path, "/api/spending");
+    assert_eq!(params.get("from").map(String::as_str), Some("2024-01-01"));
+    assert_eq!(params.get("convert_to").map(String::as_str), Some("eur"));
+    assert_eq!(params.get("x y").map(String::as_str), Some("a b/c"));
+    assert!(!params.contains_key("store_id"));
+    assert_eq!(parse_api_url("/api/status").0, "/api/status");
+    assert_eq!(parse_api_url("/api/runs?limit=%zz").1["limit"], "%zz");
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("state").join(API_TOKEN_FILE);
+    let token = load_or_create_api_token(&path).expect("token");
+    assert_eq!(token.len(), 64);
+    assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(load_or_create_api_token(&path).expect("reused"), token);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = fs::metadata(&path)
+        .expect("token file")
+        .permissions()
+        .mode();
+      assert_eq!(mode & 0o777, 0o600);
+    }
+
+    assert!(bearer_matches(Some(&format!("Bearer {}", token)), &token));
+    assert!(!bearer_matches(Some(&token), &token));
+    assert!(!bearer_matches(Some("Bearer "), &token));
+    assert!(!bearer_matches(Some(&format!("Bearer {}x", token)), &token));
+    assert!(!bearer_matches(None, &token));
+
+    assert_eq!(api_error_status(&AppError::InvalidInput("x".into())), 400);
+    assert_eq!(api_error_status(&AppError::HomeUnavailable), 503);
+    assert_eq!(api_error_status(&AppError::Other("x".into())), 500);
+  }
+}
@@ -0,0 +1,298 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct AutostartStatus {
+  // What the user asked for (`system.autostart` in prefs).
+  pub(crate) enabled: bool,
+  // Whether the OS entry is there right now.
+  pub(crate) installed: bool,
+  // The Launch Agent plist, `.desktop` file or registry value.
+  pub(crate) location: Option<String>,
+  // This process was started by that entry.
+  pub(crate) launched_at_login: bool,
+}
+
+pub(crate) fn launched_at_login() -> bool {
+  std::env::args().skip(1).any(|arg| arg == AUTOSTART_ARG)
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn xml_escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+// ~/Library/LaunchAgents/<id>.plist: started once per login, never restarted.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn launch_agent_plist(exe: &Path) -> String {
+  format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>{}</string>
+  <key>ProgramArguments</key>
+  <array>
+    <string>{}</string>
+    <string>{}</string>
+  </array>
+  <key>RunAtLoad</key>
+  <true/>
+  <key>KeepAlive</key>
+  <false/>
+</dict>
+</plist>
+"#,
+    BUNDLE_IDENTIFIER,
+    xml_escape(&exe.to_string_lossy()),
+    AUTOSTART_ARG
+  )
+}
+
+// ~/.config/autostart/<id>.desktop. `Exec` quotes the path as the desktop
+// entry spec asks, so a path with spaces still launches.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn autostart_desktop_entry(exe: &Path) -> String {
+  let quoted = exe
+    .to_string_lossy()
+    .replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('`', "\\`")
+    .replace('$', "\\$");
+  format!(
+    "[Desktop Entry]\nType=Application\nName={}\nExec=\"{}\" {}\nX-GNOME-Autostart-enabled=true\nNoDisplay=true\n",
+    APP_DISPLAY_NAME, quoted, AUTOSTART_ARG
+  )
+}
+
+// Where the macOS or Linux entry goes under `home`; Linux honours
+// XDG_CONFIG_HOME like the desktop does.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn autostart_file_path(home: &Path, config_home: Option<&str>) -> Option<PathBuf> {
+  if cfg!(target_os = "macos") {
+    Some(
+      home
+        .join("Library")
+        .join("LaunchAgents")
+        .join(format!("{}.plist", BUNDLE_IDENTIFIER)),
+    )
+  } else if cfg!(target_os = "linux") {
+    let config = config_home
+      .filter(|dir| Path::new(dir).is_absolute())
+      .map(PathBuf::from)
+      .unwrap_or_else(|| home.join(".config"));
+    Some(
+      config
+        .join("autostart")
+        .join(format!("{}.desktop", BUNDLE_IDENTIFIER)),
+    )
+  } else {
+    None
+  }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn autostart_file_contents(exe: &Path) -> String {
+  if cfg!(target_os = "macos") {
+    launch_agent_plist(exe)
+  } else {
+    autostart_desktop_entry(exe)
+  }
+}
+
+pub(crate) fn current_exe() -> Result<PathBuf, AppError> {
+  std::env::current_exe()
+    .map_err(|err| AppError::Other(format!("Could not locate the app executable: {}", err)))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn autostart_location() -> Option<String> {
+  autostart_file_path(&home_dir()?, env_var("XDG_CONFIG_HOME").as_deref())
+    .map(|path| path.to_string_lossy().to_string())
+}
+
+// Some(true) when the entry is there and starts this executable, Some(false)
+// when it is there but points elsewhere (the app was moved), None when absent.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn autostart_entry_matches(exe: &Path) -> Option<bool> {
+  let path = PathBuf::from(autostart_location()?);
+  let raw = fs::read_to_string(path).ok()?;
+  Some(raw == autostart_file_contents(exe))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn install_autostart(exe: &Path) -> Result<(), AppError> {
+  let path = autostart_location()
+    .map(PathBuf::from)
+    .ok_or(AppError::HomeUnavailable)?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| AppError::io(parent, err))?;
+  }
+  fs::write(&path, autostart_file_contents(exe)).map_err(|err| AppError::io(&path, err))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn remove_autostart() -> Result<(), AppError> {
+  let path = match autostart_location() {
+    Some(path) => PathBuf::from(path),
+    None => return Ok(()),
+  };
+  match fs::remove_file(&path) {
+    Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(AppError::io(&path, err)),
+    _ => Ok(()),
+  }
+}
+
+// A value under HKCU\...\Run, named after the app, holding the quoted command.
+#[cfg(target_os = "windows")]
+const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+
+#[cfg(target_os = "windows")]
+pub(crate) fn autostart_location() -> Option<String> {
+  Some(format!(r"HKCU\{}\{}", RUN_KEY, APP_DISPLAY_NAME))
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn run_key_command(exe: &Path) -> String {
+  format!("\"{}\" {}", exe.to_string_lossy(), AUTOSTART_ARG)
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn autostart_entry_matches(exe: &Path) -> Option<bool> {
+  let key = winreg::RegKey::predef(winreg::enums::HKEY_CURRENT_USER)
+    .open_subkey(RUN_KEY)
+    .ok()?;
+  let command = key.get_value::<String, _>(APP_DISPLAY_NAME).ok()?;
+  Some(command == run_key_command(exe))
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn install_autostart(exe: &Path) -> Result<(), AppError> {
+  let location = autostart_location().unwrap_or_default();
+  let (key, _) = winreg::RegKey::predef(winreg::enums::HKEY_CURRENT_USER)
+    .create_subkey(RUN_KEY)
+    .map_err(|err| AppError::io(Path::new(&location), err))?;
+  key
+    .set_value(APP_DISPLAY_NAME, &run_key_command(exe))
+    .map_err(|err| AppError::io(Path::new(&location), err))
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn remove_autostart() -> Result<(), AppError> {
+  let location = autostart_location().unwrap_or_default();
+  let key = match winreg::RegKey::predef(winreg::enums::HKEY_CURRENT_USER)
+    .open_subkey_with_flags(RUN_KEY, winreg::enums::KEY_SET_VALUE)
+  {
+    Ok(key) => key,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+    Err(err) => return Err(AppError::io(Path::new(&location), err)),
+  };
+  match key.delete_value(APP_DISPLAY_NAME) {
+    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+      Err(AppError::io(Path::new(&location), err))
+    }
+    _ => Ok(()),
+  }
+}
+
+pub(crate) fn autostart_wanted(prefs: &HashMap<String, Value>) -> Option<bool> {
+  prefs.get(PREF_AUTOSTART).and_then(|value| value.as_bool())
+}
+
+pub(crate) fn autostart_status(prefs: &HashMap<String, Value>) -> AutostartStatus {
+  AutostartStatus {
+    enabled: autostart_wanted(prefs).unwrap_or(false),
+    installed: current_exe()
+      .ok()
+      .and_then(|exe| autostart_entry_matches(&exe))
+      .is_some(),
+    location: autostart_location(),
+    launched_at_login: launched_at_login(),
+  }
+}
+
+// Puts the OS entry back in line with prefs at startup: recreated if something
+// removed it, rewritten if the app moved, removed if autostart was turned off.
+// Nothing happens until the user has chosen either way.
+pub(crate) fn reconcile_autostart(prefs: &HashMap<String, Value>) {
+  let wanted = match autostart_wanted(prefs) {
+    Some(wanted) => wanted,
+    None => return,
+  };
+  let exe = match current_exe() {
+    Ok(exe) => exe,
+    Err(err) => {
+      warn!("autostart: {}", err.message());
+      return;
+    }
+  };
+  let result = match (wanted, autostart_entry_matches(&exe)) {
+    (true, Some(true)) | (false, None) => return,
+    (true, found) => {
+      info!(
+        "autostart entry {}; writing it again",
+        if found.is_some() {
+          "is stale"
+        } else {
+          "is missing"
+        }
+      );
+      install_autostart(&exe)
+    }
+    (false, Some(_)) => remove_autostart(),
+  };
+  if let Err(err) = result {
+    warn!("autostart: {}", err.message());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[cfg(not(target_os = "windows"))]
+  #[test]
+  fn autostart_entries_launch_this_executable_hidden() {
+    let exe = Path::new("/Applications/Receipts Operator.app/Contents/MacOS/R&D \"x\"");
+    let plist = launch_agent_plist(exe);
+    assert!(plist.contains("<string>com.life-dashboard.receipts</string>"));
+    assert!(plist.contains(
+      "<string>/Applications/Receipts Operator.app/Contents/MacOS/R&amp;D &quot;x&quot;</string>"
+    ));
+    assert!(plist.contains("<string>--autostart</string>"));
+    assert!(plist.contains("<key>RunAtLoad</key>\n  <true/>"));
+
+    let entry = autostart_desktop_entry(Path::new("/opt/receipts $HOME/receipts-desktop"));
+    assert!(entry.contains("Exec=\"/opt/receipts \\$HOME/receipts-desktop\" --autostart\n"));
+    assert!(entry.starts_with("[Desktop Entry]\nType=Application\nName=Receipts Operator\n"));
+
+    let home = Path::new("/home/mama");
+    let path = autostart_file_path(home, Some("/custom/config")).expect("path");
+    if cfg!(target_os = "macos") {
+      assert_eq!(
+        path,
+        home.join("Library/LaunchAgents/com.life-dashboard.receipts.plist")
+      );
+    } else {
+      assert_eq!(
+        path,
+        Path::new("/custom/config/autostart/com.life-dashboard.receipts.desktop")
+      );
+      assert_eq!(
+        autostart_file_path(home, Some("relative")),
+        Some(home.join(".config/autostart/com.life-dashboard.receipts.desktop"))
+      );
+    }
+
+    let mut prefs = HashMap::new();
+    assert_eq!(autostart_wanted(&prefs), None);
+    prefs.insert(PREF_AUTOSTART.to_string(), Value::Bool(true));
+    assert_eq!(autostart_wanted(&prefs), Some(true));
+    assert!(validate_pref(&badge_config("lidl"), PREF_AUTOSTART, &Value::Bool(false)).is_err());
+  }
+}
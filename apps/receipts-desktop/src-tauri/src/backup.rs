@@ -0,0 +1,374 @@
+use crate::*;
+
+// Also used for `restore-progress`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct BackupProgress {
+  pub(crate) files_done: usize,
+  pub(crate) files_total: usize,
+  pub(crate) bytes_done: u64,
+  pub(crate) bytes_total: u64,
+  pub(crate) done: bool,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct BackupResult {
+  // `ok` or `cancelled`; a cancelled backup leaves no archive behind.
+  pub(crate) status: String,
+  pub(crate) path: Option<String>,
+  pub(crate) file_count: usize,
+  // Of the files before compression.
+  pub(crate) bytes: u64,
+  // Of the archive.
+  pub(crate) size: u64,
+  pub(crate) elapsed_ms: u64,
+}
+
+// Renaming or removing a field, or moving entries, means bumping
+// BACKUP_SCHEMA_VERSION; restores refuse archives with a newer one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct BackupManifest {
+  pub(crate) schema_version: u32,
+  pub(crate) app_version: String,
+  pub(crate) created_at: String,
+  pub(crate) receipts_root: String,
+  pub(crate) include_images: bool,
+  pub(crate) file_count: usize,
+  pub(crate) bytes: u64,
+}
+
+// Sync and OS leftovers (`.DS_Store`, `.dropbox`, `Thumbs.db`), temp files and
+// the worker's staging folders.
+pub(crate) fn is_backup_junk(name: &str) -> bool {
+  name.starts_with('.')
+    || name.ends_with(".tmp")
+    || name.eq_ignore_ascii_case("thumbs.db")
+    || name.eq_ignore_ascii_case("desktop.ini")
+}
+
+// `_logs` and every `.json` (outputs, `.error.json`, summaries) always; the
+// receipt images and PDFs only with `include_images`.
+pub(crate) fn backup_wants(relative: &Path, include_images: bool) -> bool {
+  if relative.starts_with("_logs") {
+    return true;
+  }
+  let extension = relative
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .map(str::to_lowercase)
+    .unwrap_or_default();
+  extension == "json" || (include_images && INBOX_EXTENSIONS.contains(&extension.as_str()))
+}
+
+// (relative path, size), sorted. Symlinks are followed (a store's inbox may
+// live on another volume); `VisitedDirs` stops a link back into the tree from
+// looping.
+pub(crate) fn backup_files(root: &Path, include_images: bool) -> Vec<(PathBuf, u64)> {
+  let mut files = Vec::new();
+  let mut visited = VisitedDirs::default();
+  let mut pending = vec![PathBuf::new()];
+  visited.enter(root);
+  while let Some(relative_dir) = pending.pop() {
+    let entries = match fs::read_dir(root.join(&relative_dir)) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    for entry in entries.flatten() {
+      let name = entry.file_name().to_string_lossy().to_string();
+      if is_backup_junk(&name) {
+        continue;
+      }
+      let relative = relative_dir.join(&name);
+      match fs::metadata(entry.path()) {
+        Ok(meta) if meta.is_dir() && visited.enter(&entry.path()) => pending.push(relative),
+        Ok(meta) if meta.is_file() && backup_wants(&relative, include_images) => {
+          files.push((relative, meta.len()));
+        }
+        _ => {}
+      }
+    }
+  }
+  files.sort();
+  files
+}
+
+// The destination may not exist yet, so its folder is resolved instead.
+pub(crate) fn validate_backup_destination(root: &Path, dest: &Path) -> Result<(), AppError> {
+  let display = dest.to_string_lossy().to_string();
+  if !dest.is_absolute() {
+    return Err(AppError::InvalidInput(format!(
+      "Backup path must be absolute: {}",
+      display
+    )));
+  }
+  if dest.exists() {
+    return Err(AppError::FileExists(display));
+  }
+  let parent = dest
+    .parent()
+    .ok_or_else(|| AppError::InvalidInput(format!("Not a file path: {}", display)))?;
+  let parent = fs::canonicalize(parent).map_err(|err| AppError::io(parent, err))?;
+  let root = fs::canonicalize(root).map_err(|err| AppError::io(root, err))?;
+  if parent.starts_with(&root) {
+    return Err(AppError::InvalidInput(format!(
+      "{} is inside RECEIPTS_ROOT; the backup would include itself",
+      display
+    )));
+  }
+  Ok(())
+}
+
+// Streams each file into the zip; images are stored as they are, since they
+// don't compress. The seen state (`state_file`, outside the root) goes in as
+// BACKUP_STATE_ENTRY. Writes through `<dest>.tmp` so only a finished archive
+// ever has the real name.
+pub(crate) fn write_backup(
+  root: &Path,
+  state_file: Option<&Path>,
+  dest: &Path,
+  include_images: bool,
+  cancelled: &AtomicBool,
+  progress: &mut dyn FnMut(BackupProgress),
+) -> Result<BackupResult, AppError> {
+  let started = Instant::now();
+  validate_backup_destination(root, dest)?;
+  // (entry name, source, size)
+  let mut files = backup_files(root, include_images)
+    .into_iter()
+    .map(|(relative, size)| {
+      let name = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+      (name, root.join(relative), size)
+    })
+    .collect::<Vec<_>>();
+  if let Some(state_file) = state_file {
+    if let Ok(meta) = fs::metadata(state_file) {
+      files.push((
+        BACKUP_STATE_ENTRY.to_string(),
+        state_file.to_path_buf(),
+        meta.len(),
+      ));
+    }
+  }
+  let mut report = BackupProgress {
+    files_done: 0,
+    files_total: files.len(),
+    bytes_done: 0,
+    bytes_total: files.iter().map(|(_, _, size)| size).sum(),
+    done: false,
+  };
+  progress(report.clone());
+
+  let temp = dest.with_extension("zip.tmp");
+  let zip_error = |err: zip::result::ZipError| AppError::Io {
+    path: temp.to_string_lossy().to_string(),
+    reason: err.to_string(),
+  };
+  let outcome = (|| -> Result<bool, AppError> {
+    let file = fs::File::create(&temp).map_err(|err| AppError::io(&temp, err))?;
+    let mut zip = zip::ZipWriter::new(std::io::BufWriter::new(file));
+    let mut last_emit = Instant::now();
+    for (name, path, size) in &files {
+      if cancelled.load(Ordering::SeqCst) {
+        return Ok(false);
+      }
+      let mut source = match fs::File::open(path) {
+        Ok(source) => source,
+        // Gone since the listing (e.g. the worker moved it): not an error.
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+        Err(err) => return Err(AppError::io(path, err)),
+      };
+      let method = if name.ends_with(".json") || name.starts_with("_logs/") {
+        zip::CompressionMethod::Deflated
+      } else {
+        zip::CompressionMethod::Stored
+      };
+      let options = zip::write::FileOptions::default()
+        .compression_method(method)
+        .large_file(*size >= u32::MAX as u64);
+      zip.start_file(name, options).map_err(zip_error)?;
+      let copied = std::io::copy(&mut source, &mut zip).map_err(|err| AppError::io(path, err))?;
+      report.files_done += 1;
+      report.bytes_done += copied;
+      if last_emit.elapsed() >= Duration::from_millis(250) {
+        last_emit = Instant::now();
+        progress(report.clone());
+      }
+    }
+    let manifest = BackupManifest {
+      schema_version: BACKUP_SCHEMA_VERSION,
+      app_version: APP_VERSION.to_string(),
+      created_at: chrono::Utc::now().to_rfc3339(),
+      receipts_root: root.to_string_lossy().to_string(),
+      include_images,
+      file_count: report.files_done,
+      bytes: report.bytes_done,
+    };
+    let raw = serde_json::to_string_pretty(&manifest).map_err(|err| err.to_string())?;
+    zip
+      .start_file(BACKUP_MANIFEST_NAME, zip::write::FileOptions::default())
+      .map_err(zip_error)?;
+    zip
+      .write_all(raw.as_bytes())
+      .map_err(|err| AppError::io(&temp, err))?;
+    let writer = zip.finish().map_err(zip_error)?;
+    let file = writer
+      .into_inner()
+      .map_err(|err| AppError::io(&temp, err.into_error()))?;
+    file.sync_all().map_err(|err| AppError::io(&temp, err))?;
+    Ok(true)
+  })();
+
+  let complete = outcome
+    .and_then(|complete| {
+      if complete {
+        fs::rename(&temp, dest).map_err(|err| AppError::io(dest, err))?;
+      }
+      Ok(complete)
+    })
+    .inspect_err(|_| {
+      let _ = fs::remove_file(&temp);
+    })?;
+  if !complete {
+    let _ = fs::remove_file(&temp);
+  }
+  report.done = true;
+  progress(report.clone());
+  Ok(BackupResult {
+    status: if complete { "ok" } else { "cancelled" }.to_string(),
+    path: complete.then(|| dest.to_string_lossy().to_string()),
+    file_count: report.files_done,
+    bytes: report.bytes_done,
+    size: if complete {
+      fs::metadata(dest).map(|meta| meta.len()).unwrap_or(0)
+    } else {
+      0
+    },
+    elapsed_ms: started.elapsed().as_millis() as u64,
+  })
+}
+
+// Flags the running backup; `write_backup` stops at the next file and the job
+// keeps its slot until then.
+pub(crate) fn cancel_backup_job(registry: &ChildRegistry) -> Result<CancelResult, AppError> {
+  let jobs = registry.0.lock().map_err(|err| err.to_string())?;
+  let reason = match jobs.get("backup") {
+    Some(job) if !job.cancelled.swap(true, Ordering::SeqCst) => None,
+    Some(_) => Some("The backup is already stopping"),
+    None => Some("No backup is running"),
+  };
+  Ok(CancelResult {
+    cancelled: reason.is_none(),
+    reason: reason.map(str::to_string),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn backup_zips_outputs_and_logs_and_skips_junk() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path().join("receipts");
+    for (folder, name) in [
+      ("processed/lidl", "a.jpg"),
+      ("processed/lidl", "a.jpg.json"),
+      ("processed/lidl", ".DS_Store"),
+      ("failed/lidl", "bad.pdf"),
+      ("failed/lidl", "bad.pdf.error.json"),
+      ("inbox/lidl", "new.png"),
+      ("inbox/lidl", "notes.txt"),
+      ("inbox/lidl/.staged-1", "x.jpg.json"),
+      ("_logs/runs", "20240102-093000.summary.json"),
+      ("_logs/runs", "20240102-093000.log"),
+      ("_logs", "worker.json.tmp"),
+    ] {
+      let path = root.join(folder);
+      fs::create_dir_all(&path).expect("dir");
+      fs::write(path.join(name), b"{}").expect("file");
+    }
+    let listed = |include_images: bool| {
+      backup_files(&root, include_images)
+        .into_iter()
+        .map(|(path, _)| path.to_string_lossy().replace('\\', "/"))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      listed(false),
+      vec![
+        "_logs/runs/20240102-093000.log",
+        "_logs/runs/20240102-093000.summary.json",
+        "failed/lidl/bad.pdf.error.json",
+        "processed/lidl/a.jpg.json",
+      ]
+    );
+    assert_eq!(listed(true).len(), 7);
+
+    let cancelled = AtomicBool::new(false);
+    let inside = root.join("backup.zip");
+    assert!(matches!(
+      write_backup(&root, None, &inside, false, &cancelled, &mut |_| {}),
+      Err(AppError::InvalidInput(_))
+    ));
+    assert!(matches!(
+      write_backup(
+        &root,
+        None,
+        Path::new("backup.zip"),
+        false,
+        &cancelled,
+        &mut |_| {}
+      ),
+      Err(AppError::InvalidInput(_))
+    ));
+
+    let dest = dir.path().join("backup.zip");
+    let mut reports = Vec::new();
+    let result = write_backup(&root, None, &dest, true, &cancelled, &mut |progress| {
+      reports.push(progress)
+    })
+    .expect("backup");
+    assert_eq!(result.status, "ok");
+    assert_eq!(result.file_count, 7);
+    assert_eq!(result.size, fs::metadata(&dest).expect("zip").len());
+    let last = reports.last().expect("progress");
+    assert!(last.done && last.files_done == 7 && last.bytes_done == last.bytes_total);
+    assert!(matches!(
+      write_backup(&root, None, &dest, true, &cancelled, &mut |_| {}),
+      Err(AppError::FileExists(_))
+    ));
+
+    let other = dir.path().join("cancelled.zip");
+    cancelled.store(true, Ordering::SeqCst);
+    let result = write_backup(&root, None, &other, true, &cancelled, &mut |_| {}).expect("backup");
+    assert_eq!(result.status, "cancelled");
+    assert!(result.path.is_none());
+    assert!(!other.exists() && !other.with_extension("zip.tmp").exists());
+  }
+
+  #[test]
+  fn cancel_backup_flags_the_job_and_keeps_its_slot() {
+    let registry = ChildRegistry::default();
+    assert!(!cancel_backup_job(&registry).expect("idle").cancelled);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    registry.0.lock().unwrap().insert(
+      "backup".to_string(),
+      RunningJob {
+        pid: None,
+        stages: None,
+        cancelled: cancelled.clone(),
+      },
+    );
+    assert!(cancel_backup_job(&registry).expect("cancel").cancelled);
+    assert!(cancelled.load(Ordering::SeqCst));
+    assert!(registry.0.lock().unwrap().contains_key("backup"));
+    let again = cancel_backup_job(&registry).expect("cancel again");
+    assert_eq!(
+      again.reason.as_deref(),
+      Some("The backup is already stopping")
+    );
+  }
+}
@@ -0,0 +1,341 @@
+use crate::*;
+use chrono::Datelike;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct BudgetAmount {
+  pub(crate) amount: f64,
+  pub(crate) currency: String,
+}
+
+// Monthly limits from config.json: one over all stores and/or one per store.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub(crate) struct Budgets {
+  #[serde(default)]
+  pub(crate) monthly: Option<BudgetAmount>,
+  #[serde(default)]
+  pub(crate) stores: std::collections::BTreeMap<String, BudgetAmount>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct BudgetLine {
+  // None for the budget over all stores.
+  pub(crate) store_id: Option<String>,
+  pub(crate) budget: f64,
+  pub(crate) currency: String,
+  // Receipts in the budget's currency, plus those converted into it with
+  // `currency_rates`.
+  pub(crate) spent: f64,
+  pub(crate) percent: f64,
+  pub(crate) exceeded: bool,
+  // Spending that couldn't be converted into the budget's currency and so
+  // isn't part of `spent`; `currency_mismatch` is set when there is any.
+  pub(crate) unconverted: CurrencyTotals,
+  pub(crate) currency_mismatch: bool,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct BudgetStatus {
+  pub(crate) year: i32,
+  pub(crate) month: u32,
+  pub(crate) budgets: Vec<BudgetLine>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct BudgetExceededEvent {
+  pub(crate) year: i32,
+  pub(crate) month: u32,
+  #[serde(flatten)]
+  pub(crate) line: BudgetLine,
+}
+
+fn normalize_budget(scope: &str, budget: BudgetAmount) -> Option<BudgetAmount> {
+  let currency = currency_code(&budget.currency);
+  if !budget.amount.is_finite() || budget.amount <= 0.0 || currency.is_empty() {
+    warn!(
+      "budgets: ignoring {} (needs a positive amount and a currency)",
+      scope
+    );
+    return None;
+  }
+  Some(BudgetAmount {
+    amount: budget.amount,
+    currency,
+  })
+}
+
+// Currencies are uppercased; budgets without a positive amount or a currency,
+// and budgets for stores that aren't configured, are dropped with a warning.
+pub(crate) fn normalize_budgets(budgets: Budgets, stores: &[StoreConfig]) -> Budgets {
+  Budgets {
+    monthly: budgets
+      .monthly
+      .and_then(|budget| normalize_budget("the monthly budget", budget)),
+    stores: budgets
+      .stores
+      .into_iter()
+      .filter_map(|(store_id, budget)| {
+        if !stores.iter().any(|store| store.id == store_id) {
+          warn!("budgets: ignoring unknown store {}", store_id);
+          return None;
+        }
+        let budget = normalize_budget(&format!("the budget for {}", store_id), budget)?;
+        Some((store_id, budget))
+      })
+      .collect(),
+  }
+}
+
+pub(crate) fn budget_line(
+  store_id: Option<String>,
+  budget: &BudgetAmount,
+  figures: &[MonthFigures],
+) -> BudgetLine {
+  let mut spent = 0.0;
+  let mut unconverted = CurrencyTotals::new();
+  for figures in figures {
+    if figures.currency == budget.currency {
+      spent += figures.total;
+    } else if figures.total != 0.0 {
+      add_amount(&mut unconverted, figures.currency.clone(), figures.total);
+    }
+  }
+  let spent = round_cents(spent);
+  BudgetLine {
+    store_id,
+    budget: budget.amount,
+    currency: budget.currency.clone(),
+    spent,
+    percent: (spent / budget.amount * 1000.0).round() / 10.0,
+    exceeded: spent > budget.amount,
+    currency_mismatch: !unconverted.is_empty(),
+    unconverted: rounded_totals(unconverted),
+  }
+}
+
+// `reports` holds the month's report converted into each budget currency.
+pub(crate) fn budget_lines(
+  budgets: &Budgets,
+  reports: &HashMap<String, MonthlyReport>,
+) -> Vec<BudgetLine> {
+  let mut lines = Vec::new();
+  if let Some(budget) = &budgets.monthly {
+    if let Some(report) = reports.get(&budget.currency) {
+      lines.push(budget_line(None, budget, &report.by_currency));
+    }
+  }
+  for (store_id, budget) in &budgets.stores {
+    let store = reports.get(&budget.currency).and_then(|report| {
+      report
+        .stores
+        .iter()
+        .find(|store| store.store_id == *store_id)
+    });
+    if let Some(store) = store {
+      lines.push(budget_line(
+        Some(store_id.clone()),
+        budget,
+        &store.by_currency,
+      ));
+    }
+  }
+  lines
+}
+
+// Amounts in other currencies are converted when `currency_rates` has both
+// rates; what can't be converted is reported, never added in as is.
+pub(crate) fn budget_status(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  year: i32,
+  month: u32,
+) -> Result<BudgetStatus, AppError> {
+  month_start(year, month)?;
+  let mut reports = HashMap::new();
+  let currencies = config
+    .budgets
+    .monthly
+    .iter()
+    .chain(config.budgets.stores.values())
+    .map(|budget| budget.currency.clone())
+    .collect::<std::collections::BTreeSet<_>>();
+  for currency in currencies {
+    let conversion = Conversion::new(config, Some(&currency)).unwrap_or_default();
+    reports.insert(
+      currency,
+      cached_monthly_report(app, config, year, month, &conversion)?,
+    );
+  }
+  Ok(BudgetStatus {
+    year,
+    month,
+    budgets: budget_lines(&config.budgets, &reports),
+  })
+}
+
+// `2024-05:all` or `2024-05:store:lidl`, as kept in `SeenState::budgets_notified`.
+pub(crate) fn budget_notice_key(year: i32, month: u32, store_id: Option<&str>) -> String {
+  match store_id {
+    Some(store_id) => format!("{}-{:02}:store:{}", year, month, store_id),
+    None => format!("{}-{:02}:all", year, month),
+  }
+}
+
+// The exceeded lines not notified yet this month, which are recorded as
+// notified. Keys from before the previous month are dropped.
+pub(crate) fn take_budget_notices(
+  state: &mut SeenState,
+  year: i32,
+  month: u32,
+  lines: &[BudgetLine],
+) -> Vec<BudgetLine> {
+  let (previous_year, previous) = previous_month(year, month);
+  let keep = [
+    format!("{}-{:02}:", year, month),
+    format!("{}-{:02}:", previous_year, previous),
+  ];
+  state
+    .budgets_notified
+    .retain(|key| keep.iter().any(|prefix| key.starts_with(prefix.as_str())));
+  let mut fresh = Vec::new();
+  for line in lines.iter().filter(|line| line.exceeded) {
+    let key = budget_notice_key(year, month, line.store_id.as_deref());
+    if !state.budgets_notified.contains(&key) {
+      state.budgets_notified.push(key);
+      fresh.push(line.clone());
+    }
+  }
+  fresh
+}
+
+// Evaluates this month's budgets, e.g. after a worker run: each budget that is
+// over its limit emits `budget-exceeded` and a notification once per month.
+// Quiet hours skip the notification but still record it.
+pub(crate) fn check_budgets(app: &tauri::AppHandle) -> Result<(), AppError> {
+  let config = current_config(app);
+  if config.budgets.monthly.is_none() && config.budgets.stores.is_empty() {
+    return Ok(());
+  }
+  let today = chrono::Local::now().date_naive();
+  let (year, month) = (today.year(), today.month());
+  let status = budget_status(app, &config, year, month)?;
+  let fresh = {
+    let seen_state = app.state::<SeenStateStore>();
+    let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+    let fresh = take_budget_notices(&mut state, year, month, &status.budgets);
+    if !fresh.is_empty() {
+      save_state(&state)?;
+    }
+    fresh
+  };
+  let quiet = in_quiet_hours(&config, chrono::Local::now().time());
+  for line in fresh {
+    let scope = match &line.store_id {
+      Some(store_id) => config
+        .stores
+        .iter()
+        .find(|store| store.id == *store_id)
+        .map(|store| store.name.clone())
+        .unwrap_or_else(|| store_id.clone()),
+      None => "All stores".to_string(),
+    };
+    info!(
+      "budget exceeded: {} {:.2}/{:.2} {}",
+      scope, line.spent, line.budget, line.currency
+    );
+    if config.notifications_enabled && !quiet {
+      let _ = tauri::api::notification::Notification::new(&app.config().tauri.bundle.identifier)
+        .title(format!("{} budget exceeded", scope))
+        .body(format!(
+          "{:.2} of {:.2} {} spent this month ({}%)",
+          line.spent, line.budget, line.currency, line.percent
+        ))
+        .show();
+    }
+    let _ = app.emit_all("budget-exceeded", BudgetExceededEvent { year, month, line });
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn budgets_convert_what_they_can_and_notify_once_a_month() {
+    let receipt = |store_id: &str, date: &str, total: f64, currency: &str| ReceiptTotal {
+      store_id: store_id.to_string(),
+      file_name: format!("{}-{}.jpg.json", store_id, date),
+      date: chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok(),
+      date_estimated: false,
+      total: Some(total),
+      currency: Some(currency.to_string()),
+    };
+    let receipts = vec![
+      receipt("lidl", "2024-05-02", 300.0, "RON"),
+      receipt("lidl", "2024-05-09", 20.0, "EUR"),
+      receipt("kaufland", "2024-05-10", 150.0, "RON"),
+      receipt("kaufland", "2024-05-12", 5000.0, "HUF"),
+    ];
+    let stores = vec!["lidl".to_string(), "kaufland".to_string()];
+    let mut config = badge_config("lidl");
+    config.currency_rates = normalize_currency_rates(CurrencyRates::from([
+      ("RON".to_string(), 1.0),
+      ("EUR".to_string(), 5.0),
+    ]));
+    let budget = |amount: f64, currency: &str| BudgetAmount {
+      amount,
+      currency: currency.to_string(),
+    };
+    let budgets = normalize_budgets(
+      Budgets {
+        monthly: Some(budget(400.0, "ron")),
+        stores: std::collections::BTreeMap::from([
+          ("kaufland".to_string(), budget(200.0, "RON")),
+          ("lidl".to_string(), budget(0.0, "RON")),
+          ("aldi".to_string(), budget(50.0, "RON")),
+        ]),
+      },
+      &[
+        config.stores[0].clone(),
+        StoreConfig {
+          id: "kaufland".to_string(),
+          ..config.stores[0].clone()
+        },
+      ],
+    );
+    assert_eq!(budgets.monthly, Some(budget(400.0, "RON")));
+    assert_eq!(budgets.stores.keys().collect::<Vec<_>>(), vec!["kaufland"]);
+
+    let to_ron = Conversion::new(&config, Some("RON")).expect("conversion");
+    let reports = HashMap::from([(
+      "RON".to_string(),
+      monthly_report(receipts, &stores, 2024, 5, &to_ron),
+    )]);
+    let lines = budget_lines(&budgets, &reports);
+    // EUR is converted; HUF has no rate and is reported next to the total.
+    assert_eq!(lines[0].store_id, None);
+    assert_eq!((lines[0].spent, lines[0].percent), (550.0, 137.5));
+    assert!(lines[0].exceeded && lines[0].currency_mismatch);
+    assert_eq!(
+      lines[0].unconverted,
+      CurrencyTotals::from([("HUF".to_string(), 5000.0)])
+    );
+    assert_eq!(lines[1].store_id.as_deref(), Some("kaufland"));
+    assert_eq!((lines[1].spent, lines[1].percent), (150.0, 75.0));
+    assert!(!lines[1].exceeded);
+
+    let mut state = SeenState {
+      budgets_notified: vec!["2024-03:all".to_string(), "2024-04:all".to_string()],
+      ..SeenState::default()
+    };
+    let fresh = take_budget_notices(&mut state, 2024, 5, &lines);
+    assert_eq!(fresh.len(), 1);
+    assert_eq!(state.budgets_notified, vec!["2024-04:all", "2024-05:all"]);
+    assert!(take_budget_notices(&mut state, 2024, 5, &lines).is_empty());
+    assert_eq!(
+      budget_notice_key(2024, 5, Some("kaufland")),
+      "2024-05:store:kaufland"
+    );
+  }
+}
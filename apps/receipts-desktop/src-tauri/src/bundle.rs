@@ -0,0 +1,445 @@
+use crate::*;
+
+// What goes into a diagnostics bundle, gathered by the command so the archive
+// itself can be written (and tested) without an app.
+pub(crate) struct BundleSources {
+  pub(crate) diagnostics: Value,
+  pub(crate) config: Value,
+  pub(crate) config_origins: Vec<ConfigOrigin>,
+  // The stores.json that won resolution; None when the built-in stores apply.
+  pub(crate) stores_file: Option<PathBuf>,
+  pub(crate) stores: Vec<StoreConfig>,
+  pub(crate) state_file: Option<PathBuf>,
+  pub(crate) include_tags: bool,
+  pub(crate) logs_dir: Option<PathBuf>,
+  pub(crate) crashes_dir: Option<PathBuf>,
+  // Newest first.
+  pub(crate) run_summaries: Vec<Value>,
+  // Anything left out before writing (e.g. the runs of an unreachable root).
+  pub(crate) notes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct BundleEntry {
+  pub(crate) name: String,
+  pub(crate) bytes: u64,
+}
+
+// A log cut to fit the size cap: `kept_bytes` of the newest lines, or 0 when
+// the file was left out.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct TruncatedLog {
+  pub(crate) name: String,
+  pub(crate) bytes: u64,
+  pub(crate) kept_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct BundleManifest {
+  pub(crate) app_version: String,
+  pub(crate) created_at: String,
+  pub(crate) max_bytes: u64,
+  pub(crate) include_tags: bool,
+  pub(crate) stores_file: Option<String>,
+  pub(crate) entries: Vec<BundleEntry>,
+  pub(crate) truncated_logs: Vec<TruncatedLog>,
+  pub(crate) notes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct DiagnosticsBundle {
+  pub(crate) path: String,
+  // Of the archive.
+  pub(crate) size: u64,
+  pub(crate) file_count: usize,
+  pub(crate) truncated: bool,
+}
+
+// The last bundle written, for `reveal_diagnostics_bundle`.
+#[derive(Default)]
+pub(crate) struct LastDiagnosticsBundle(pub(crate) Mutex<Option<PathBuf>>);
+
+// Words that look like secrets (see `looks_like_secret`), values of
+// secret-looking `key=value` / `key: value` pairs and what follows `Bearer`
+// become `[redacted]`.
+pub(crate) fn redact_secret_words(line: &str) -> String {
+  let mut after_bearer = false;
+  line
+    .split(' ')
+    .map(|word| {
+      let token = word.trim_matches(|c: char| "\"',;()[]{}".contains(c));
+      let (key, value) = match token.split_once(['=', ':']) {
+        Some((key, value)) => (key.to_lowercase(), value),
+        None => (String::new(), token),
+      };
+      let secret = !value.is_empty()
+        && (after_bearer
+          || looks_like_secret(value)
+          || SECRET_KEY_MARKERS.iter().any(|marker| key.contains(marker)));
+      after_bearer = token == "Bearer";
+      if secret {
+        word.replacen(value, "[redacted]", 1)
+      } else {
+        word.to_string()
+      }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+pub(crate) fn redact_text(text: &str) -> String {
+  text
+    .split('\n')
+    .map(redact_secret_words)
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+pub(crate) fn bundle_json(value: &Value) -> Vec<u8> {
+  serde_json::to_vec_pretty(&redact_secrets(value)).unwrap_or_default()
+}
+
+// state.json as JSON, redacted; without `include_tags` the file tags and run
+// notes are dropped from the top level and every store, orphaned ones included.
+pub(crate) fn bundle_state(raw: &str, include_tags: bool) -> Vec<u8> {
+  let mut state = match serde_json::from_str::<Value>(raw) {
+    Ok(state) => state,
+    Err(_) => return redact_text(raw).into_bytes(),
+  };
+  if !include_tags {
+    let strip = |value: &mut Value| {
+      if let Some(object) = value.as_object_mut() {
+        for key in BUNDLE_PRIVATE_STATE_KEYS {
+          object.remove(key);
+        }
+      }
+    };
+    strip(&mut state);
+    for section in ["stores", "orphaned"] {
+      if let Some(stores) = state.get_mut(section).and_then(Value::as_object_mut) {
+        for store in stores.values_mut() {
+          match store.get_mut("state") {
+            Some(orphaned) => strip(orphaned),
+            None => strip(store),
+          }
+        }
+      }
+    }
+  }
+  bundle_json(&state)
+}
+
+// The last `limit` bytes of `raw`, starting at a line.
+pub(crate) fn log_tail(raw: &[u8], limit: usize) -> &[u8] {
+  if raw.len() <= limit {
+    return raw;
+  }
+  let tail = &raw[raw.len() - limit..];
+  match tail.iter().position(|byte| *byte == b'\n') {
+    Some(newline) => &tail[newline + 1..],
+    None => &[],
+  }
+}
+
+// A directory gets a timestamped bundle name; a file path must not exist yet.
+pub(crate) fn bundle_destination(dest: &Path) -> Result<PathBuf, AppError> {
+  let display = dest.to_string_lossy().to_string();
+  if !dest.is_absolute() {
+    return Err(AppError::InvalidInput(format!(
+      "Bundle path must be absolute: {}",
+      display
+    )));
+  }
+  let dest = if dest.is_dir() {
+    dest.join(format!(
+      "receipts-diagnostics-{}.zip",
+      chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ))
+  } else {
+    dest.to_path_buf()
+  };
+  if dest.exists() {
+    return Err(AppError::FileExists(dest.to_string_lossy().to_string()));
+  }
+  Ok(dest)
+}
+
+// Everything but the app logs goes in whole; the logs fill what is left of
+// `max_bytes` (uncompressed), newest first, so the oldest are cut or left out.
+// Each cut is listed in the manifest. Writes through `<dest>.tmp`.
+pub(crate) fn write_diagnostics_bundle(
+  sources: &BundleSources,
+  dest: &Path,
+  max_bytes: u64,
+) -> Result<DiagnosticsBundle, AppError> {
+  let dest = bundle_destination(dest)?;
+  let mut notes = sources.notes.clone();
+  let mut files: Vec<(String, Vec<u8>)> = vec![
+    (
+      "diagnostics.json".to_string(),
+      bundle_json(&sources.diagnostics),
+    ),
+    (
+      "config.json".to_string(),
+      bundle_json(&serde_json::json!({
+        "config": sources.config,
+        "origins": sources.config_origins,
+      })),
+    ),
+  ];
+  let stores = sources
+    .stores_file
+    .as_ref()
+    .and_then(|path| fs::read_to_string(path).ok())
+    .and_then(|raw| serde_json::from_str::<Value>(&raw).ok())
+    .unwrap_or_else(|| serde_json::to_value(&sources.stores).unwrap_or(Value::Null));
+  files.push(("stores.json".to_string(), bundle_json(&stores)));
+  match sources.state_file.as_ref().map(fs::read_to_string) {
+    Some(Ok(raw)) => files.push((
+      "state.json".to_string(),
+      bundle_state(&raw, sources.include_tags),
+    )),
+    Some(Err(err)) => notes.push(format!("state.json left out: {}", err)),
+    None => notes.push("state.json left out: no state folder".to_string()),
+  }
+  for (index, summary) in sources.run_summaries.iter().enumerate() {
+    let run_id = summary
+      .get("run_id")
+      .and_then(Value::as_str)
+      .filter(|id| !id.contains(['/', '\\']) && !id.starts_with('.'))
+      .unwrap_or("run");
+    files.push((
+      format!("runs/{:02}-{}.summary.json", index + 1, run_id),
+      bundle_json(summary),
+    ));
+  }
+  if let Some(dir) = &sources.crashes_dir {
+    for name in crash_report_names(dir) {
+      if let Ok(raw) = fs::read_to_string(dir.join(&name)) {
+        files.push((format!("crashes/{}", name), redact_text(&raw).into_bytes()));
+      }
+    }
+  }
+
+  let fixed_bytes: u64 = files.iter().map(|(_, raw)| raw.len() as u64).sum();
+  let mut remaining = max_bytes.saturating_sub(fixed_bytes);
+  let mut truncated_logs = Vec::new();
+  if let Some(dir) = &sources.logs_dir {
+    for index in 0..APP_LOG_KEEP_FILES {
+      let path = rotated_log_path(dir, index);
+      let raw = match fs::read(&path) {
+        Ok(raw) => redact_text(&String::from_utf8_lossy(&raw)).into_bytes(),
+        Err(_) => continue,
+      };
+      let name = format!(
+        "logs/{}",
+        path.file_name().unwrap_or_default().to_string_lossy()
+      );
+      let kept = log_tail(&raw, remaining as usize);
+      remaining -= kept.len() as u64;
+      if kept.len() < raw.len() {
+        truncated_logs.push(TruncatedLog {
+          name: name.clone(),
+          bytes: raw.len() as u64,
+          kept_bytes: kept.len() as u64,
+        });
+      }
+      if !kept.is_empty() {
+        files.push((name, kept.to_vec()));
+      }
+    }
+  }
+  if !truncated_logs.is_empty() {
+    notes.push(format!(
+      "{} app log files were cut to keep the bundle under {} bytes",
+      truncated_logs.len(),
+      max_bytes
+    ));
+  }
+
+  let manifest = BundleManifest {
+    app_version: APP_VERSION.to_string(),
+    created_at: chrono::Utc::now().to_rfc3339(),
+    max_bytes,
+    include_tags: sources.include_tags,
+    stores_file: sources
+      .stores_file
+      .as_ref()
+      .map(|path| path.to_string_lossy().to_string()),
+    entries: files
+      .iter()
+      .map(|(name, raw)| BundleEntry {
+        name: name.clone(),
+        bytes: raw.len() as u64,
+      })
+      .collect(),
+    truncated_logs,
+    notes,
+  };
+  let truncated = !manifest.truncated_logs.is_empty();
+  files.push((
+    "manifest.json".to_string(),
+    bundle_json(&serde_json::to_value(&manifest).unwrap_or(Value::Null)),
+  ));
+
+  let temp = dest.with_extension("zip.tmp");
+  let zip_error = |err: zip::result::ZipError| AppError::Io {
+    path: temp.to_string_lossy().to_string(),
+    reason: err.to_string(),
+  };
+  let outcome = (|| -> Result<(), AppError> {
+    let file = fs::File::create(&temp).map_err(|err| AppError::io(&temp, err))?;
+    let mut zip = zip::ZipWriter::new(std::io::BufWriter::new(file));
+    let options =
+      zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, raw) in &files {
+      zip.start_file(name, options).map_err(zip_error)?;
+      zip.write_all(raw).map_err(|err| AppError::io(&temp, err))?;
+    }
+    let writer = zip.finish().map_err(zip_error)?;
+    let file = writer
+      .into_inner()
+      .map_err(|err| AppError::io(&temp, err.into_error()))?;
+    file.sync_all().map_err(|err| AppError::io(&temp, err))?;
+    fs::rename(&temp, &dest).map_err(|err| AppError::io(&dest, err))
+  })();
+  if let Err(err) = outcome {
+    let _ = fs::remove_file(&temp);
+    return Err(err);
+  }
+  Ok(DiagnosticsBundle {
+    path: dest.to_string_lossy().to_string(),
+    size: fs::metadata(&dest).map(|meta| meta.len()).unwrap_or(0),
+    file_count: files.len(),
+    truncated,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn bundle_state_drops_tags_and_run_notes_unless_asked() {
+    let raw = r#"{
+      "stores": {
+        "lidl": {
+          "snoozed_until": "2024-01-02T00:00:00Z",
+          "file_tags": { "a.jpg": { "size": 1, "tags": ["refund"] } },
+          "run_notes": { "20240101-080000": "ask partner" }
+        }
+      },
+      "orphaned": { "penny": { "state": { "notes": { "20240101-080000": "x" } } } }
+    }"#;
+    let stripped: Value = serde_json::from_slice(&bundle_state(raw, false)).expect("json");
+    assert_eq!(
+      stripped["stores"]["lidl"],
+      serde_json::json!({ "snoozed_until": "2024-01-02T00:00:00Z" })
+    );
+    assert_eq!(
+      stripped["orphaned"]["penny"]["state"],
+      serde_json::json!({})
+    );
+
+    let kept: Value = serde_json::from_slice(&bundle_state(raw, true)).expect("json");
+    assert_eq!(
+      kept["stores"]["lidl"]["run_notes"]["20240101-080000"],
+      "ask partner"
+    );
+    assert_eq!(
+      kept["stores"]["lidl"]["file_tags"]["a.jpg"]["tags"][0],
+      "refund"
+    );
+  }
+
+  #[test]
+  fn diagnostics_bundle_redacts_drops_tags_and_cuts_the_oldest_logs() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let logs = dir.path().join("logs");
+    let crashes = dir.path().join("crashes");
+    fs::create_dir_all(&logs).expect("logs");
+    fs::create_dir_all(&crashes).expect("crashes");
+    let line = "2026-10-15T10:00:00Z INFO run finished\n";
+    fs::write(
+      rotated_log_path(&logs, 0),
+      format!("{}INFO webhook token=hunter2 Bearer abc\n", line),
+    )
+    .expect("log 0");
+    fs::write(rotated_log_path(&logs, 1), line.repeat(200)).expect("log 1");
+    fs::write(rotated_log_path(&logs, 2), line.repeat(200)).expect("log 2");
+    fs::write(crashes.join("crash-20261015-100000.txt"), "Message: boom\n").expect("crash");
+    let state_file = dir.path().join("state.json");
+    fs::write(
+      &state_file,
+      serde_json::json!({
+        "stores": { "lidl": { "file_tags": { "a.jpg": { "size": 1, "tags": ["x"] } } } },
+      })
+      .to_string(),
+    )
+    .expect("state");
+    let config = badge_config("lidl");
+    let sources = BundleSources {
+      diagnostics: serde_json::json!({ "app_version": APP_VERSION }),
+      config: serde_json::to_value(&config).expect("config"),
+      config_origins: Vec::new(),
+      stores_file: None,
+      stores: config.stores.clone(),
+      state_file: Some(state_file),
+      include_tags: false,
+      logs_dir: Some(logs),
+      crashes_dir: Some(crashes),
+      run_summaries: vec![serde_json::json!({ "run_id": "r1", "status": "ok" })],
+      notes: Vec::new(),
+    };
+
+    let relative = write_diagnostics_bundle(&sources, Path::new("bundle.zip"), 1 << 20);
+    assert_eq!(relative.expect_err("relative").code(), "invalid_input");
+    // Room for everything but the newest log and part of the next one.
+    let out = tempfile::tempdir().expect("out");
+    let fixed = 4096;
+    let bundle = write_diagnostics_bundle(&sources, out.path(), fixed).expect("bundle");
+    assert!(bundle.path.ends_with(".zip"));
+    assert!(bundle.truncated);
+    assert_eq!(bundle.size, fs::metadata(&bundle.path).expect("zip").len());
+    assert_eq!(
+      write_diagnostics_bundle(&sources, Path::new(&bundle.path), fixed)
+        .expect_err("exists")
+        .code(),
+      "file_exists"
+    );
+
+    let mut archive =
+      zip::ZipArchive::new(fs::File::open(&bundle.path).expect("open")).expect("zip");
+    let mut read = |name: &str| {
+      let mut raw = String::new();
+      archive
+        .by_name(name)
+        .expect(name)
+        .read_to_string(&mut raw)
+        .expect("read");
+      raw
+    };
+    let newest = read("logs/receipts-desktop.log");
+    assert!(newest.contains("token=[redacted]") && newest.contains("Bearer [redacted]"));
+    assert!(!read("state.json").contains("file_tags"));
+    assert!(read("runs/01-r1.summary.json").contains("\"ok\""));
+    assert!(read("crashes/crash-20261015-100000.txt").contains("boom"));
+    let manifest: BundleManifest = serde_json::from_str(&read("manifest.json")).expect("manifest");
+    assert!(!manifest.include_tags);
+    assert_eq!(manifest.truncated_logs.len(), 2);
+    assert_eq!(
+      manifest.truncated_logs[0].name,
+      "logs/receipts-desktop.log.1"
+    );
+    assert!(manifest.truncated_logs[0].kept_bytes > 0);
+    assert_eq!(manifest.truncated_logs[1].kept_bytes, 0);
+    assert!(archive.by_name("logs/receipts-desktop.log.2").is_err());
+    let entries = manifest
+      .entries
+      .iter()
+      .map(|entry| entry.bytes)
+      .sum::<u64>();
+    assert!(entries <= fixed);
+  }
+}
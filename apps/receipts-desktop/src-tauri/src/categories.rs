@@ -0,0 +1,349 @@
+use crate::*;
+
+pub(crate) type CategoryMapping = std::collections::BTreeMap<String, String>;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct CategoryTotal {
+  pub(crate) category: String,
+  pub(crate) currency: String,
+  pub(crate) total: f64,
+  pub(crate) item_count: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct StoreCategories {
+  pub(crate) store_id: String,
+  pub(crate) totals: CurrencyTotals,
+  pub(crate) categories: Vec<CategoryTotal>,
+}
+
+// A receipt whose line items don't add up to its total (or that has no
+// parseable total to check against), in the receipt's own currency.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct CategoryDiscrepancy {
+  pub(crate) store_id: String,
+  pub(crate) file_name: String,
+  pub(crate) date: Option<chrono::NaiveDate>,
+  pub(crate) currency: String,
+  pub(crate) receipt_total: Option<f64>,
+  pub(crate) items_total: f64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct CategoryBreakdown {
+  pub(crate) from: Option<chrono::NaiveDate>,
+  pub(crate) to: Option<chrono::NaiveDate>,
+  pub(crate) convert_to: Option<String>,
+  pub(crate) totals: CurrencyTotals,
+  pub(crate) receipt_count: usize,
+  pub(crate) categories: Vec<CategoryTotal>,
+  pub(crate) stores: Vec<StoreCategories>,
+  pub(crate) discrepancies: Vec<CategoryDiscrepancy>,
+}
+
+pub(crate) fn category_mapping_path() -> Option<PathBuf> {
+  state_path(CATEGORY_MAPPING_FILE)
+}
+
+// A missing or unreadable file maps nothing.
+pub(crate) fn load_category_mapping() -> CategoryMapping {
+  category_mapping_path()
+    .and_then(|path| fs::read_to_string(path).ok())
+    .and_then(|raw| serde_json::from_str(&raw).ok())
+    .unwrap_or_default()
+}
+
+pub(crate) fn category_key(category: &str) -> String {
+  category.trim().to_lowercase()
+}
+
+// Keys and values are compared lowercased; a mapping only takes one hop, so
+// `sweets -> snacks` and `snacks -> groceries` leave sweets under snacks.
+pub(crate) fn normalize_category_mapping(
+  mapping: HashMap<String, String>,
+) -> Result<CategoryMapping, AppError> {
+  let mut normalized = CategoryMapping::new();
+  for (from, to) in mapping {
+    let (from, to) = (category_key(&from), category_key(&to));
+    if from.is_empty() || to.is_empty() {
+      return Err(AppError::InvalidInput(
+        "Category names can't be empty".to_string(),
+      ));
+    }
+    if from.len() > 64 || to.len() > 64 {
+      return Err(AppError::InvalidInput(format!(
+        "Category name too long: {}",
+        if from.len() > 64 { from } else { to }
+      )));
+    }
+    if from != to {
+      normalized.insert(from, to);
+    }
+  }
+  Ok(normalized)
+}
+
+pub(crate) fn save_category_mapping(mapping: &CategoryMapping) -> Result<(), AppError> {
+  let path = category_mapping_path().ok_or(AppError::HomeUnavailable)?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| AppError::io(parent, err))?;
+  }
+  let raw = serde_json::to_string_pretty(mapping).map_err(|err| err.to_string())?;
+  let temp = write_synced_temp(&path, &raw)?;
+  fs::rename(&temp, &path).map_err(|err| AppError::io(&path, err))?;
+  Ok(())
+}
+
+// The worker's `category` (or the first of `categories`), remapped; items
+// without one are UNCATEGORIZED.
+pub(crate) fn item_category(item: &Value, mapping: &CategoryMapping) -> String {
+  let raw = item
+    .get("category")
+    .and_then(|category| category.as_str())
+    .or_else(|| {
+      item
+        .get("categories")
+        .and_then(|categories| categories.as_array())
+        .and_then(|categories| categories.first())
+        .and_then(|category| category.as_str())
+    })
+    .map(category_key)
+    .filter(|category| !category.is_empty());
+  match raw {
+    Some(raw) => mapping.get(&raw).cloned().unwrap_or(raw),
+    None => UNCATEGORIZED.to_string(),
+  }
+}
+
+// What the item cost: `paid_amount`, else `unit_price` times `quantity`.
+pub(crate) fn item_amount(item: &Value) -> Option<f64> {
+  item.get("paid_amount").and_then(parse_amount).or_else(|| {
+    let unit_price = item.get("unit_price").and_then(parse_amount)?;
+    let quantity = item.get("quantity").and_then(parse_amount).unwrap_or(1.0);
+    Some(unit_price * quantity)
+  })
+}
+
+// (currency, category) -> (amount, items)
+pub(crate) type CategorySums = std::collections::BTreeMap<(String, String), (f64, usize)>;
+
+// Grouped by currency, largest category first within each.
+pub(crate) fn category_totals(sums: CategorySums) -> Vec<CategoryTotal> {
+  let mut categories = sums
+    .into_iter()
+    .map(
+      |((currency, category), (total, item_count))| CategoryTotal {
+        category,
+        currency,
+        total: round_cents(total),
+        item_count,
+      },
+    )
+    .collect::<Vec<_>>();
+  categories.sort_by(|a, b| {
+    a.currency
+      .cmp(&b.currency)
+      .then_with(|| b.total.total_cmp(&a.total))
+      .then_with(|| a.category.cmp(&b.category))
+  });
+  categories
+}
+
+pub(crate) fn currency_totals(categories: &[CategoryTotal]) -> CurrencyTotals {
+  let mut totals = CurrencyTotals::new();
+  for category in categories {
+    add_amount(&mut totals, category.currency.clone(), category.total);
+  }
+  rounded_totals(totals)
+}
+
+// Reads every processed output in range; nothing under processed/ is written.
+pub(crate) fn category_breakdown(
+  receipts_root: &str,
+  store_ids: &[String],
+  from: Option<chrono::NaiveDate>,
+  to: Option<chrono::NaiveDate>,
+  mapping: &CategoryMapping,
+  conversion: &Conversion,
+) -> CategoryBreakdown {
+  let mut all = CategorySums::new();
+  let mut stores = Vec::new();
+  let mut discrepancies = Vec::new();
+  let mut receipt_count = 0;
+  for store_id in store_ids {
+    let dir = processed_dir(receipts_root, store_id);
+    let mut names = receipt_output_names(&dir);
+    names.sort();
+    let mut by_category = CategorySums::new();
+    for name in names {
+      let path = join_file_id(&dir, &name);
+      let value = match fs::read_to_string(&path) {
+        Ok(raw) => serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null),
+        Err(_) => continue,
+      };
+      let receipt = match receipt_total_from(store_id, &path, &value) {
+        Some(receipt) => receipt,
+        None => continue,
+      };
+      let in_range = receipt
+        .date
+        .is_some_and(|date| from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to));
+      if !in_range {
+        continue;
+      }
+      receipt_count += 1;
+      let currency = receipt_currency(&receipt);
+      let mut items_total = 0.0;
+      for item in value
+        .get("items")
+        .and_then(|items| items.as_array())
+        .into_iter()
+        .flatten()
+      {
+        let amount = match item_amount(item) {
+          Some(amount) => amount,
+          None => continue,
+        };
+        items_total += amount;
+        let (bucket, converted) = conversion.apply(&currency, amount);
+        let key = (bucket, item_category(item, mapping));
+        for sums in [&mut by_category, &mut all] {
+          let entry = sums.entry(key.clone()).or_default();
+          entry.0 += converted;
+          entry.1 += 1;
+        }
+      }
+      let balanced = receipt
+        .total
+        .is_some_and(|total| (total - items_total).abs() <= CATEGORY_SUM_TOLERANCE);
+      if !balanced {
+        discrepancies.push(CategoryDiscrepancy {
+          store_id: store_id.clone(),
+          file_name: name,
+          date: receipt.date,
+          currency: currency.clone(),
+          receipt_total: receipt.total,
+          items_total: round_cents(items_total),
+        });
+      }
+    }
+    let categories = category_totals(by_category);
+    stores.push(StoreCategories {
+      store_id: store_id.clone(),
+      totals: currency_totals(&categories),
+      categories,
+    });
+  }
+  let categories = category_totals(all);
+  CategoryBreakdown {
+    from,
+    to,
+    convert_to: conversion.target.clone(),
+    totals: currency_totals(&categories),
+    receipt_count,
+    categories,
+    stores,
+    discrepancies,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn category_breakdown_maps_categories_and_reports_unbalanced_receipts() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    for (store, name, raw) in [
+      (
+        "lidl",
+        "a.jpg.json",
+        r#"{"timestamp": "2024-01-05T10:00:00", "total": 30.0, "items": [
+          {"name": "Lapte", "category": "Groceries", "paid_amount": 10.0},
+          {"name": "Ciocolată", "category": "sweets", "paid_amount": "5,00"},
+          {"name": "Vin", "categories": ["alcohol"], "unit_price": 7.5, "quantity": 2}]}"#,
+      ),
+      (
+        "lidl",
+        "b.jpg.json",
+        r#"{"timestamp": "2024-01-06T10:00:00", "total": 20.0, "items": [
+          {"name": "Burete", "paid_amount": 4.0}]}"#,
+      ),
+      (
+        "lidl",
+        "old.jpg.json",
+        r#"{"timestamp": "2023-12-31T10:00:00", "total": 9.0, "items": []}"#,
+      ),
+      (
+        "penny",
+        "c.jpg.json",
+        r#"{"timestamp": "2024-01-07T10:00:00", "total": 3.0, "items": [
+          {"name": "Pâine", "category": "groceries", "paid_amount": 3.02}]}"#,
+      ),
+    ] {
+      let processed = dir.path().join("processed").join(store);
+      fs::create_dir_all(&processed).expect("processed");
+      fs::write(processed.join(name), raw).expect("output");
+    }
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string(), "penny".to_string()];
+    let from = chrono::NaiveDate::from_ymd_opt(2024, 1, 1);
+    let mapping = normalize_category_mapping(HashMap::from([
+      (" Sweets ".to_string(), "GROCERIES".to_string()),
+      ("same".to_string(), "same".to_string()),
+    ]))
+    .expect("mapping");
+    assert_eq!(
+      mapping,
+      CategoryMapping::from([("sweets".to_string(), "groceries".to_string())])
+    );
+    assert!(
+      normalize_category_mapping(HashMap::from([(" ".to_string(), "x".to_string())])).is_err()
+    );
+
+    let same = Conversion::default();
+    let breakdown = category_breakdown(&root, &stores, from, None, &mapping, &same);
+    assert_eq!(breakdown.receipt_count, 3);
+    let categories = |totals: &[CategoryTotal]| {
+      totals
+        .iter()
+        .map(|total| (total.category.clone(), total.total, total.item_count))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      categories(&breakdown.categories),
+      vec![
+        ("groceries".to_string(), 18.02, 3),
+        ("alcohol".to_string(), 15.0, 1),
+        ("uncategorized".to_string(), 4.0, 1),
+      ]
+    );
+    assert_eq!(
+      breakdown.totals,
+      CurrencyTotals::from([("unknown".to_string(), 37.02)])
+    );
+    assert_eq!(breakdown.stores[0].totals["unknown"], 34.0);
+    assert_eq!(
+      categories(&breakdown.stores[1].categories),
+      vec![("groceries".to_string(), 3.02, 1)]
+    );
+    // a.jpg (30 = 10 + 5 + 15) and c.jpg (3.02 against 3.00) balance.
+    assert_eq!(
+      breakdown.discrepancies,
+      vec![CategoryDiscrepancy {
+        store_id: "lidl".to_string(),
+        file_name: "b.jpg.json".to_string(),
+        date: chrono::NaiveDate::from_ymd_opt(2024, 1, 6),
+        currency: "unknown".to_string(),
+        receipt_total: Some(20.0),
+        items_total: 4.0,
+      }]
+    );
+
+    let unmapped = category_breakdown(&root, &stores, from, None, &CategoryMapping::new(), &same);
+    assert!(unmapped
+      .categories
+      .iter()
+      .any(|total| total.category == "sweets" && total.total == 5.0));
+  }
+}
@@ -0,0 +1,305 @@
+use crate::*;
+
+// Any of these makes the launch headless: no window, tray or instance lock.
+const HEADLESS_FLAGS: [&str; 3] = ["--run-worker", "--status", "--help"];
+
+pub(crate) const CLI_USAGE: &str = "Usage:
+  receipts-desktop --run-worker [--stores <id>[,<id>...]] [--json]
+  receipts-desktop --status [--json]
+
+--run-worker runs the worker for the given stores (every enabled store without
+--stores) and streams its output. --status prints the status snapshot. With
+--json, stdout carries one JSON document (the run result, the snapshot, or
+{\"error\": ...}) and everything else goes to stderr.
+
+Exit codes: 0 done, 1 the run failed or was cancelled, 2 bad arguments,
+3 nothing ran (missing config, unknown store, unreachable RECEIPTS_ROOT).";
+
+pub(crate) const EXIT_OK: i32 = 0;
+pub(crate) const EXIT_RUN_FAILED: i32 = 1;
+pub(crate) const EXIT_USAGE: i32 = 2;
+pub(crate) const EXIT_ERROR: i32 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CliAction {
+  // Empty `stores` runs every enabled store, like "Run all".
+  RunWorker { stores: Vec<String> },
+  Status,
+  Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CliRequest {
+  pub(crate) action: CliAction,
+  pub(crate) json: bool,
+}
+
+// Ok(None) without a headless flag, so the app starts as before (`--autostart`
+// and deep links are left to it). With one, every argument has to be known.
+pub(crate) fn parse_cli_args(
+  args: impl IntoIterator<Item = String>,
+) -> Result<Option<CliRequest>, String> {
+  let args = args.into_iter().collect::<Vec<_>>();
+  if !args
+    .iter()
+    .any(|arg| HEADLESS_FLAGS.contains(&arg.as_str()))
+  {
+    return Ok(None);
+  }
+  let mut action = None;
+  let mut stores = None;
+  let mut json = false;
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    let (name, inline) = match arg.split_once('=') {
+      Some((name @ ("--stores" | "--store"), value)) => (name, Some(value.to_string())),
+      _ => (arg.as_str(), None),
+    };
+    let next = match name {
+      "--run-worker" => CliAction::RunWorker { stores: Vec::new() },
+      "--status" => CliAction::Status,
+      "--help" => CliAction::Help,
+      "--json" => {
+        json = true;
+        continue;
+      }
+      "--stores" | "--store" => {
+        let value = match inline.or_else(|| args.next()) {
+          Some(value) => value,
+          None => return Err(format!("{} needs a store id", name)),
+        };
+        stores = Some(parse_store_list(&value)?);
+        continue;
+      }
+      _ => return Err(format!("Unknown argument: {}", arg)),
+    };
+    if action.as_ref().is_some_and(|action| *action != next) {
+      return Err("Pass only one of --run-worker, --status and --help".to_string());
+    }
+    action = Some(next);
+  }
+  let action = match (action, stores) {
+    (Some(CliAction::RunWorker { .. }), stores) => CliAction::RunWorker {
+      stores: stores.unwrap_or_default(),
+    },
+    (Some(action), None) => action,
+    _ => return Err("--stores only goes with --run-worker".to_string()),
+  };
+  Ok(Some(CliRequest { action, json }))
+}
+
+pub(crate) fn parse_store_list(value: &str) -> Result<Vec<String>, String> {
+  let mut stores: Vec<String> = Vec::new();
+  for store in value.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+    if !stores.iter().any(|known| known == store) {
+      stores.push(store.to_string());
+    }
+  }
+  if stores.is_empty() {
+    return Err(format!("No store ids in --stores {:?}", value));
+  }
+  Ok(stores)
+}
+
+// For `--status` without `--json`: one line per store, the totals, then the
+// access warning if there is one.
+pub(crate) fn status_lines(snapshot: &StatusSnapshot) -> Vec<String> {
+  let mut lines = Vec::new();
+  for store in &snapshot.stores {
+    let mut line = format!(
+      "{} ({}): {} pending",
+      store.name, store.store_id, store.inbox_count
+    );
+    if let Some(days) = store.oldest_pending_days {
+      line.push_str(&format!(", oldest {}d", days));
+    }
+    line.push_str(&format!(
+      ", {} unread failures, {} unread warnings",
+      store.failures_unread_count, store.warnings_unread_count
+    ));
+    if let Some(until) = &store.snoozed_until {
+      line.push_str(&format!(", snoozed until {}", until));
+    }
+    if !store.enabled {
+      line.push_str(", disabled");
+    }
+    lines.push(line);
+  }
+  lines.push(format!(
+    "Total: {} pending, {} stores with unread issues",
+    snapshot.totals.inbox_count, snapshot.totals.stores_with_issues
+  ));
+  if let Some(warning) = &snapshot.access_warning {
+    lines.push(format!("Warning: {}", warning.message));
+    if let Some(hint) = &warning.hint {
+      lines.push(hint.clone());
+    }
+  }
+  lines
+}
+
+// Reads the state as startup would, but doesn't save it: headless runs may
+// overlap with the app, which owns state.json.
+pub(crate) fn headless_status(config: &AppConfig) -> Result<StatusSnapshot, AppError> {
+  probe_root_once(config)?;
+  let (mut state, recovered) = load_state();
+  if let Some(message) = recovered {
+    warn!("state recovery: {}", message);
+  }
+  prepare_loaded_state(&mut state, config);
+  let (issue_runs, runs_cached) = cached_issue_runs(&IssueRunsCache::default(), config);
+  let data_issues = check_data_integrity_for(config, true)
+    .ok()
+    .map(|report| report.counts);
+  Ok(status_snapshot_from(
+    config,
+    &issue_runs,
+    runs_cached,
+    &state,
+    &InboxCountCache::default(),
+    data_issues,
+  ))
+}
+
+pub(crate) fn headless_run_worker(
+  config: &AppConfig,
+  stores: &[String],
+  json: bool,
+) -> Result<i32, AppError> {
+  for store_id in stores {
+    require_store(config, store_id)?;
+  }
+  let sink = EventSink::Terminal { json };
+  if let Err(err) = start_worker(&sink, config, stores) {
+    // start_worker has written the reason to stderr already.
+    if json {
+      print_json(&serde_json::json!({ "error": err }))?;
+    }
+    return Ok(EXIT_ERROR);
+  }
+  let result = spawn_worker(&sink, config, store_args(stores), stores.to_vec())?;
+  if json {
+    print_json(&result)?;
+  } else {
+    eprintln!(
+      "Run finished: {}{}",
+      result.status,
+      result
+        .exit_code
+        .map(|code| format!(" (exit code {})", code))
+        .unwrap_or_default()
+    );
+  }
+  Ok(if result.status == "ok" {
+    EXIT_OK
+  } else {
+    EXIT_RUN_FAILED
+  })
+}
+
+pub(crate) fn print_json<T: Serialize>(value: &T) -> Result<(), AppError> {
+  let raw = serde_json::to_string(value).map_err(|err| AppError::Parse {
+    path: None,
+    reason: err.to_string(),
+  })?;
+  println!("{}", raw);
+  Ok(())
+}
+
+// Runs the request and returns the exit code. Config comes from the
+// environment and config.json as in the app, without the bundled resources.
+pub(crate) fn run_headless(request: CliRequest) -> i32 {
+  if request.action == CliAction::Help {
+    println!("{}", CLI_USAGE);
+    return EXIT_OK;
+  }
+  let config = read_app_config(None);
+  info!("headless {:?}", request.action);
+  let outcome = match &request.action {
+    CliAction::Help => Ok(EXIT_OK),
+    CliAction::RunWorker { stores } => headless_run_worker(&config, stores, request.json),
+    CliAction::Status => headless_status(&config).and_then(|snapshot| {
+      if request.json {
+        print_json(&snapshot)?;
+      } else {
+        for line in status_lines(&snapshot) {
+          println!("{}", line);
+        }
+      }
+      Ok(EXIT_OK)
+    }),
+  };
+  match outcome {
+    Ok(code) => code,
+    Err(err) => {
+      eprintln!("{}", err.message());
+      if request.json {
+        let _ = print_json(&serde_json::json!({ "error": err }));
+      }
+      EXIT_ERROR
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn headless_flags_are_parsed_and_everything_else_starts_the_app() {
+    let parse = |args: &[&str]| parse_cli_args(args.iter().map(|arg| arg.to_string()));
+    assert_eq!(parse(&[]), Ok(None));
+    assert_eq!(parse(&["--autostart"]), Ok(None));
+    assert_eq!(parse(&["receipts://run/20240501-101500"]), Ok(None));
+    assert_eq!(
+      parse(&["--run-worker", "--stores", "lidl, kaufland,lidl", "--json"]),
+      Ok(Some(CliRequest {
+        action: CliAction::RunWorker {
+          stores: vec!["lidl".to_string(), "kaufland".to_string()],
+        },
+        json: true,
+      }))
+    );
+    assert_eq!(
+      parse(&["--store=lidl", "--run-worker"]),
+      Ok(Some(CliRequest {
+        action: CliAction::RunWorker {
+          stores: vec!["lidl".to_string()],
+        },
+        json: false,
+      }))
+    );
+    assert_eq!(
+      parse(&["--status"]),
+      Ok(Some(CliRequest {
+        action: CliAction::Status,
+        json: false,
+      }))
+    );
+    assert!(parse(&["--status", "--run-worker"]).is_err());
+    assert!(parse(&["--status", "--stores", "lidl"]).is_err());
+    assert!(parse(&["--run-worker", "--stores"]).is_err());
+    assert!(parse(&["--run-worker", "--stores", " , "]).is_err());
+    assert!(parse(&["--run-worker", "--autostart"]).is_err());
+    assert!(parse(&["--json=1", "--status"]).is_err());
+
+    let mut config = badge_config("lidl");
+    config.stores[0].name = "Lidl".to_string();
+    let snapshot = status_snapshot_from(
+      &config,
+      &HashMap::new(),
+      true,
+      &SeenState::default(),
+      &InboxCountCache::default(),
+      None,
+    );
+    assert_eq!(
+      status_lines(&snapshot),
+      vec![
+        "Lidl (lidl): 0 pending, 0 unread failures, 0 unread warnings".to_string(),
+        "Total: 0 pending, 0 stores with unread issues".to_string(),
+      ]
+    );
+  }
+}
@@ -0,0 +1,2390 @@
+use crate::*;
+
+#[tauri::command]
+pub(crate) fn get_recent_locations(
+  prefs: tauri::State<PrefsStore>,
+  limit: Option<usize>,
+) -> Result<RecentLocations, AppError> {
+  let prefs = prefs.prefs.lock().map_err(|err| err.to_string())?;
+  Ok(select_recent_locations(
+    stored_recent_locations(&prefs),
+    limit.unwrap_or(DEFAULT_RECENT_LOCATIONS_LIMIT),
+    |path| Path::new(path).exists(),
+  ))
+}
+
+#[tauri::command]
+pub(crate) fn get_config(app: tauri::AppHandle) -> AppConfig {
+  current_config(&app)
+}
+
+// Re-reads env vars, config.json and stores.json into the managed config and
+// emits `config-changed` with the result.
+#[tauri::command]
+pub(crate) fn reload_config(app: tauri::AppHandle) -> AppConfig {
+  let config = refresh_config(&app);
+  sync_api_server(&app, &config);
+  sync_shortcuts(&app);
+  config
+}
+
+#[tauri::command]
+pub(crate) fn set_config(
+  app: tauri::AppHandle,
+  receipts_root: String,
+  worker_dir: Option<String>,
+  worker_run_cmd: Option<String>,
+  import_name_template: Option<String>,
+) -> Result<(), AppError> {
+  let mut config = load_local_config();
+  config.receipts_root = Some(receipts_root);
+  config.worker_dir = worker_dir;
+  config.worker_run_cmd = worker_run_cmd;
+  if import_name_template.is_some() {
+    config.import_name_template = import_name_template;
+  }
+  save_local_config(&config)?;
+  refresh_config(&app);
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn set_notification_settings(
+  app: tauri::AppHandle,
+  enabled: bool,
+  notify_warnings: Option<bool>,
+  quiet_hours_start: Option<String>,
+  quiet_hours_end: Option<String>,
+) -> Result<(), AppError> {
+  for value in [&quiet_hours_start, &quiet_hours_end].into_iter().flatten() {
+    if parse_quiet_time(value).is_none() {
+      return Err(AppError::InvalidInput(format!(
+        "Invalid quiet hours time (expected HH:MM): {}",
+        value
+      )));
+    }
+  }
+  let mut config = load_local_config();
+  config.notifications_enabled = Some(enabled);
+  if notify_warnings.is_some() {
+    config.notify_warnings = notify_warnings;
+  }
+  config.quiet_hours_start = quiet_hours_start;
+  config.quiet_hours_end = quiet_hours_end;
+  save_local_config(&config)?;
+  refresh_config(&app);
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn set_store_notifications(
+  app: tauri::AppHandle,
+  store_id: String,
+  enabled: bool,
+) -> Result<(), AppError> {
+  require_store(&current_config(&app), &store_id)?;
+  let mut config = load_local_config();
+  config.store_notifications.insert(store_id, enabled);
+  save_local_config(&config)?;
+  refresh_config(&app);
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn set_badge_metric(app: tauri::AppHandle, metric: String) -> Result<(), AppError> {
+  if !BADGE_METRICS.contains(&metric.as_str()) {
+    return Err(AppError::InvalidInput(format!(
+      "Unknown badge metric: {}",
+      metric
+    )));
+  }
+  let mut config = load_local_config();
+  config.badge_metric = Some(metric);
+  save_local_config(&config)?;
+  refresh_config(&app);
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn get_pref(
+  prefs: tauri::State<PrefsStore>,
+  key: String,
+) -> Result<Option<Value>, AppError> {
+  let prefs = prefs.prefs.lock().map_err(|err| err.to_string())?;
+  Ok(prefs.get(&key).cloned())
+}
+
+#[tauri::command]
+pub(crate) fn set_pref(app: tauri::AppHandle, key: String, value: Value) -> Result<(), AppError> {
+  if !value.is_null() {
+    validate_pref(&current_config(&app), &key, &value).map_err(AppError::InvalidInput)?;
+  }
+  let store = app.state::<PrefsStore>();
+  let mut prefs = store.prefs.lock().map_err(|err| err.to_string())?;
+  if value.is_null() {
+    prefs.remove(&key);
+  } else {
+    prefs.insert(key.clone(), value);
+  }
+  save_prefs(&prefs)?;
+  drop(prefs);
+  if key == PREF_SHORTCUTS {
+    sync_shortcuts(&app);
+  }
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn get_autostart(prefs: tauri::State<PrefsStore>) -> Result<AutostartStatus, AppError> {
+  let prefs = prefs.prefs.lock().map_err(|err| err.to_string())?;
+  Ok(autostart_status(&prefs))
+}
+
+// Writes or removes the OS login entry first, so a failure (e.g. no permission
+// on ~/Library/LaunchAgents) comes back as that error and prefs stay as they
+// were.
+#[tauri::command]
+pub(crate) fn set_autostart(
+  prefs: tauri::State<PrefsStore>,
+  enabled: bool,
+) -> Result<AutostartStatus, AppError> {
+  if enabled {
+    install_autostart(&current_exe()?)?;
+  } else {
+    remove_autostart()?;
+  }
+  let mut prefs = prefs.prefs.lock().map_err(|err| err.to_string())?;
+  prefs.insert(PREF_AUTOSTART.to_string(), Value::Bool(enabled));
+  save_prefs(&prefs)?;
+  info!("autostart {}", if enabled { "on" } else { "off" });
+  Ok(autostart_status(&prefs))
+}
+
+#[tauri::command]
+pub(crate) fn get_shortcuts(app: tauri::AppHandle) -> Vec<ShortcutBinding> {
+  registered_shortcuts(&app)
+}
+
+// Binds `action` (one of SHORTCUT_ACTIONS) to `accelerator` system-wide, or
+// unbinds it when `accelerator` is null or empty. A combination the OS won't
+// give us fails with `shortcut_unavailable` and leaves the old binding in
+// place.
+#[tauri::command]
+pub(crate) fn set_shortcut(
+  app: tauri::AppHandle,
+  action: String,
+  accelerator: Option<String>,
+) -> Result<Vec<ShortcutBinding>, AppError> {
+  if !SHORTCUT_ACTIONS.contains(&action.as_str()) {
+    return Err(AppError::InvalidInput(format!(
+      "Unknown shortcut action: {}",
+      action
+    )));
+  }
+  let accelerator = match accelerator.as_deref().map(str::trim) {
+    Some(raw) if !raw.is_empty() => Some(normalize_accelerator(raw)?),
+    _ => None,
+  };
+  let store = app.state::<PrefsStore>();
+  let previous = {
+    let mut prefs = store.prefs.lock().map_err(|err| err.to_string())?;
+    if let Some(accelerator) = &accelerator {
+      if let Some((other, _)) = shortcut_bindings(&prefs)
+        .into_iter()
+        .find(|(other, bound)| {
+          *other != action
+            && bound
+              .as_deref()
+              .is_some_and(|bound| bound.eq_ignore_ascii_case(accelerator))
+        })
+      {
+        return Err(AppError::InvalidInput(format!(
+          "{} is already bound to {}",
+          accelerator, other
+        )));
+      }
+    }
+    store_shortcut(
+      &mut prefs,
+      &action,
+      Some(accelerator.clone().map(Value::from).unwrap_or(Value::Null)),
+    )
+  };
+  let bindings = sync_shortcuts(&app);
+  let failed = bindings
+    .iter()
+    .find(|binding| binding.action == action)
+    .and_then(|binding| binding.error.clone());
+  let mut prefs = store.prefs.lock().map_err(|err| err.to_string())?;
+  if let (Some(reason), Some(accelerator)) = (failed, accelerator) {
+    store_shortcut(&mut prefs, &action, previous);
+    drop(prefs);
+    sync_shortcuts(&app);
+    return Err(AppError::ShortcutUnavailable {
+      action,
+      accelerator,
+      reason,
+    });
+  }
+  save_prefs(&prefs)?;
+  Ok(bindings)
+}
+
+#[tauri::command]
+pub(crate) fn set_minimize_to_tray(app: tauri::AppHandle, enabled: bool) -> Result<(), AppError> {
+  let mut config = load_local_config();
+  config.minimize_to_tray = Some(enabled);
+  save_local_config(&config)?;
+  refresh_config(&app);
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn ensure_store_dirs(
+  app: tauri::AppHandle,
+  store_id: Option<String>,
+) -> Result<EnsuredDirs, AppError> {
+  let config = current_config(&app);
+  require_reachable_root(&app, &config)?;
+  ensure_dirs(&config, store_id.as_deref())
+}
+
+// Where the receipts folder could go, best first, for the first-run wizard.
+// Only looks; nothing is created.
+#[tauri::command]
+pub(crate) fn detect_receipts_locations() -> Vec<ReceiptsLocation> {
+  receipts_location_candidates()
+}
+
+// Saves `path` as RECEIPTS_ROOT in config.json and marks the first run done;
+// with `create_structure` the folder and the enabled stores' folders are
+// created as well. Calling it again with the same folder changes nothing.
+#[tauri::command]
+pub(crate) fn initialize_receipts_root(
+  app: tauri::AppHandle,
+  path: String,
+  create_structure: bool,
+) -> Result<InitializedRoot, AppError> {
+  let config = current_config(&app);
+  let (receipts_root, dirs) = prepare_receipts_root(&path, &config.stores, create_structure)?;
+  let mut local = load_local_config();
+  if local.receipts_root.as_deref() != Some(receipts_root.as_str()) {
+    local.receipts_root = Some(receipts_root.clone());
+    save_local_config(&local)?;
+  }
+  {
+    let store = app.state::<PrefsStore>();
+    let mut prefs = store.prefs.lock().map_err(|err| err.to_string())?;
+    if prefs.get(PREF_FIRST_RUN_COMPLETED) != Some(&Value::Bool(true)) {
+      prefs.insert(PREF_FIRST_RUN_COMPLETED.to_string(), Value::Bool(true));
+      save_prefs(&prefs)?;
+    }
+  }
+  refresh_config(&app);
+  info!(
+    "receipts root set to {} ({} folders created)",
+    receipts_root,
+    dirs.created.len()
+  );
+  Ok(InitializedRoot {
+    receipts_root,
+    dirs,
+    env_override: env_var("RECEIPTS_ROOT").is_some(),
+  })
+}
+
+#[tauri::command]
+pub(crate) fn import_files(
+  app: tauri::AppHandle,
+  store_id: String,
+  paths: Vec<String>,
+  normalize_names: Option<bool>,
+) -> Result<Vec<ImportedFile>, AppError> {
+  let config = current_config(&app);
+  require_reachable_root(&app, &config)?;
+  import_paths(&config, &store_id, paths, normalize_names.unwrap_or(false))
+}
+
+#[tauri::command]
+pub(crate) fn import_from_clipboard(
+  app: tauri::AppHandle,
+  store_id: String,
+) -> Result<ClipboardImport, AppError> {
+  let config = current_config(&app);
+  require_store(&config, &store_id)?;
+  require_reachable_root(&app, &config)?;
+  let mut clipboard = arboard::Clipboard::new().map_err(|err| err.to_string())?;
+
+  if let Ok(image) = clipboard.get_image() {
+    let encoded = encode_png(image.width, image.height, &image.bytes)?;
+    let inbox = Path::new(&config.receipts_root)
+      .join("inbox")
+      .join(&store_id);
+    fs::create_dir_all(&inbox).map_err(|err| AppError::io(&inbox, err))?;
+    let file_name = format!(
+      "clipboard_{}.png",
+      chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let destination = unique_destination(&inbox, &file_name);
+    fs::write(&destination, encoded).map_err(|err| AppError::io(&destination, err))?;
+    return Ok(ClipboardImport {
+      status: "imported".to_string(),
+      source: "image".to_string(),
+      files: vec![ImportedFile {
+        source: "clipboard".to_string(),
+        status: "imported".to_string(),
+        file_name: destination
+          .file_name()
+          .map(|name| name.to_string_lossy().to_string()),
+        path: Some(destination.to_string_lossy().to_string()),
+        message: None,
+      }],
+    });
+  }
+
+  let paths = clipboard_file_paths(&mut clipboard);
+  if paths.is_empty() {
+    return Err(AppError::ClipboardEmpty);
+  }
+  Ok(ClipboardImport {
+    status: "imported".to_string(),
+    source: "files".to_string(),
+    files: import_paths(&config, &store_id, paths, false)?,
+  })
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn list_inbox_files(
+  app: tauri::AppHandle,
+  seen_state: tauri::State<SeenStateStore>,
+  store_id: String,
+  sort: Option<String>,
+  descending: Option<bool>,
+  offset: Option<usize>,
+  limit: Option<usize>,
+  filter: Option<String>,
+) -> Result<InboxPage, AppError> {
+  let config = current_config(&app);
+  require_store(&config, &store_id)?;
+  require_reachable_root(&app, &config)?;
+  let query = InboxQuery {
+    sort: parse_inbox_sort(sort.as_deref()).map_err(AppError::InvalidInput)?,
+    descending: descending.unwrap_or(false),
+    offset: offset.unwrap_or(0),
+    limit: limit.unwrap_or(DEFAULT_INBOX_PAGE_SIZE),
+    filter,
+  };
+  let inbox = Path::new(&config.receipts_root)
+    .join("inbox")
+    .join(&store_id);
+  let mut page = query_inbox_files(scan_inbox_files(&inbox)?, &query);
+  let state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  let seen = state.stores.get(&store_id);
+  for file in &mut page.files {
+    file.tags = file_tags_for(seen, &file.id, file.size);
+  }
+  Ok(page)
+}
+
+#[tauri::command]
+pub(crate) fn set_file_tag(
+  app: tauri::AppHandle,
+  seen_state: tauri::State<SeenStateStore>,
+  store_id: String,
+  file_name: String,
+  tags: Vec<String>,
+) -> Result<Vec<String>, AppError> {
+  let config = current_config(&app);
+  require_reachable_root(&app, &config)?;
+  let path = resolve_inbox_file(&config, &store_id, &file_name)?;
+  let size = fs::metadata(&path)
+    .map_err(|err| AppError::io(&path, err))?
+    .len();
+  let mut cleaned: Vec<String> = Vec::new();
+  for tag in tags {
+    let tag = tag.trim().to_string();
+    if !tag.is_empty() && !cleaned.contains(&tag) {
+      cleaned.push(tag);
+    }
+  }
+
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  prune_file_tags(&mut state, &config);
+  let seen = state.stores.entry(store_id).or_default();
+  if cleaned.is_empty() {
+    seen.file_tags.remove(&file_name);
+  } else {
+    seen.file_tags.insert(
+      file_name,
+      FileTags {
+        size,
+        tags: cleaned.clone(),
+      },
+    );
+  }
+  save_state(&state)?;
+  Ok(cleaned)
+}
+
+#[tauri::command]
+pub(crate) fn list_tags(seen_state: tauri::State<SeenStateStore>) -> Result<Vec<String>, AppError> {
+  let state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  let mut tags = state
+    .stores
+    .values()
+    .flat_map(|seen| seen.file_tags.values())
+    .flat_map(|entry| entry.tags.iter().cloned())
+    .collect::<Vec<_>>();
+  tags.sort();
+  tags.dedup();
+  Ok(tags)
+}
+
+#[tauri::command]
+pub(crate) fn read_inbox_file(
+  config_store: tauri::State<ConfigStore>,
+  store_id: String,
+  file_name: String,
+  max_bytes: Option<u64>,
+) -> Result<InboxFileContent, AppError> {
+  let config = config_store.get();
+  read_inbox_file_at(
+    &config,
+    &store_id,
+    &file_name,
+    max_bytes.unwrap_or(DEFAULT_PREVIEW_MAX_BYTES),
+  )
+}
+
+#[tauri::command]
+pub(crate) fn delete_inbox_file(
+  app: tauri::AppHandle,
+  seen_state: tauri::State<SeenStateStore>,
+  store_id: String,
+  file_name: String,
+) -> Result<(), AppError> {
+  let config = current_config(&app);
+  require_reachable_root(&app, &config)?;
+  delete_inbox_file_at(&config, &store_id, &file_name)?;
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  if prune_file_tags(&mut state, &config) {
+    save_state(&state)?;
+  }
+  drop(state);
+  schedule_badges_update(&app);
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) async fn hydrate_file(
+  app: tauri::AppHandle,
+  store_id: String,
+  file_name: String,
+  timeout_secs: Option<u64>,
+) -> Result<HydrateResult, AppError> {
+  run_blocking(app.clone(), "hydrate_file", move || {
+    let config = current_config(&app);
+    let path = resolve_inbox_file(&config, &store_id, &file_name)?;
+    let total_bytes = fs::metadata(&path)
+      .map_err(|err| AppError::io(&path, err))?
+      .len();
+    let timeout = Duration::from_secs(timeout_secs.unwrap_or(DEFAULT_HYDRATE_TIMEOUT_SECS));
+    let (sender, receiver) = std::sync::mpsc::channel::<Result<u64, String>>();
+
+    let reader_path = path.clone();
+    let progress_app = app.clone();
+    let progress_store = store_id.clone();
+    let progress_file = file_name.clone();
+    thread::spawn(move || {
+      let read = (|| -> Result<u64, String> {
+        let mut file = fs::File::open(&reader_path).map_err(|err| err.to_string())?;
+        let mut buffer = vec![0u8; 256 * 1024];
+        let mut bytes_read = 0u64;
+        let mut last_emit = Instant::now();
+        loop {
+          let read = file.read(&mut buffer).map_err(|err| err.to_string())?;
+          if read == 0 {
+            break;
+          }
+          bytes_read += read as u64;
+          if last_emit.elapsed() >= Duration::from_millis(250) {
+            last_emit = Instant::now();
+            let _ = progress_app.emit_all(
+              "hydrate-progress",
+              HydrateProgress {
+                store_id: progress_store.clone(),
+                file_name: progress_file.clone(),
+                bytes_read,
+                total_bytes,
+                done: false,
+              },
+            );
+          }
+        }
+        Ok(bytes_read)
+      })();
+      let _ = sender.send(read);
+    });
+
+    let (status, bytes_read) = match receiver.recv_timeout(timeout) {
+      Ok(Ok(bytes_read)) => ("ok", bytes_read),
+      Ok(Err(err)) => {
+        return Err(AppError::Io {
+          path: path.to_string_lossy().to_string(),
+          reason: err,
+        })
+      }
+      Err(_) => ("timeout", 0),
+    };
+    let hydrated = fs::metadata(&path)
+      .map(|meta| !is_placeholder(&meta))
+      .unwrap_or(false);
+    let _ = app.emit_all(
+      "hydrate-progress",
+      HydrateProgress {
+        store_id,
+        file_name: file_name.clone(),
+        bytes_read,
+        total_bytes,
+        done: status == "ok",
+      },
+    );
+    Ok(HydrateResult {
+      status: status.to_string(),
+      file_name,
+      bytes_read,
+      total_bytes,
+      hydrated,
+    })
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) async fn check_inbox_integrity(
+  app: tauri::AppHandle,
+  store_id: String,
+) -> Result<InboxIntegrity, AppError> {
+  run_blocking(app.clone(), "check_inbox_integrity", move || {
+    let config = current_config(&app);
+    require_store(&config, &store_id)?;
+    Ok(inbox_integrity(&config, &store_id))
+  })
+  .await
+}
+
+// Cross-checks the run summaries against inbox/, processed/ and failed/;
+// `count_only` skips the per-file issues.
+#[tauri::command]
+pub(crate) async fn check_data_integrity(
+  app: tauri::AppHandle,
+  count_only: Option<bool>,
+) -> Result<DataIntegrityReport, AppError> {
+  run_blocking(app.clone(), "check_data_integrity", move || {
+    check_data_integrity_for(&current_config(&app), count_only.unwrap_or(false))
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) async fn preview_run(
+  app: tauri::AppHandle,
+  stores: Vec<String>,
+) -> Result<RunPreview, AppError> {
+  run_blocking(app.clone(), "preview_run", move || {
+    let config = current_config(&app);
+    let targets = if stores.is_empty() {
+      config
+        .stores
+        .iter()
+        .filter(|store| store.enabled)
+        .map(|store| store.id.clone())
+        .collect::<Vec<_>>()
+    } else {
+      stores.clone()
+    };
+    let mut previews = Vec::new();
+    let mut warnings = Vec::new();
+    for store_id in targets {
+      require_store(&config, &store_id)?;
+      let integrity = inbox_integrity(&config, &store_id);
+      let unhydrated = integrity
+        .issues
+        .iter()
+        .filter(|issue| !issue.hydrated)
+        .count();
+      if unhydrated > 0 {
+        warnings.push(format!(
+          "{}: {} files are online-only and must be downloaded first",
+          store_id, unhydrated
+        ));
+      }
+      previews.push(StoreRunPreview {
+        store_id,
+        pending: integrity.checked,
+        unhydrated,
+        integrity,
+      });
+    }
+    let config_error = worker_config_error(&config);
+    Ok(RunPreview {
+      ready: config_error.is_none(),
+      config_error,
+      warnings,
+      args: store_args(&stores),
+      stores: previews,
+    })
+  })
+  .await
+}
+
+// The read commands below answer from the dashboard snapshot unless `root`
+// points them elsewhere; `max_age` (seconds) forces a rescan of an older one.
+#[tauri::command]
+pub(crate) async fn get_inbox_counts(
+  app: tauri::AppHandle,
+  force_refresh: Option<bool>,
+  root: Option<String>,
+  max_age: Option<u64>,
+) -> Result<Vec<InboxCount>, AppError> {
+  run_blocking(app.clone(), "get_inbox_counts", move || {
+    if root.is_none() {
+      let snapshot = dashboard_snapshot(&app, max_age, force_refresh.unwrap_or(false))?;
+      return Ok(snapshot.inbox_counts);
+    }
+    let config = current_config(&app).with_root(root)?;
+    inbox_counts(&app, &config, force_refresh.unwrap_or(false))
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) async fn get_disk_usage(
+  app: tauri::AppHandle,
+  force: Option<bool>,
+) -> Result<DiskUsage, AppError> {
+  run_blocking(app.clone(), "get_disk_usage", move || {
+    let config = current_config(&app);
+    require_reachable_root(&app, &config)?;
+    Ok(cached_disk_usage(
+      &app.state::<DiskUsageCache>(),
+      &config,
+      force.unwrap_or(false),
+    ))
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) async fn get_last_runs(
+  app: tauri::AppHandle,
+  limit: Option<usize>,
+  root: Option<String>,
+  max_age: Option<u64>,
+) -> Result<Vec<Value>, AppError> {
+  run_blocking(app.clone(), "get_last_runs", move || {
+    let limit = limit.unwrap_or(5);
+    if root.is_none() && limit <= DASHBOARD_LAST_RUNS {
+      let mut runs = dashboard_snapshot(&app, max_age, false)?.last_runs;
+      runs.truncate(limit);
+      return Ok(runs);
+    }
+    let config = current_config(&app).with_root(root)?;
+    if config.root_override.is_some() {
+      return Ok(latest_run_summaries(&config, limit));
+    }
+    Ok(latest_runs(&app, &config, limit))
+  })
+  .await
+}
+
+// Run, issue and receipt totals over every summary, from the run index when it
+// is fresh.
+#[tauri::command]
+pub(crate) async fn get_run_stats(
+  app: tauri::AppHandle,
+  store_id: Option<String>,
+) -> Result<RunStats, AppError> {
+  run_blocking(app.clone(), "get_run_stats", move || {
+    let config = current_config(&app);
+    if let Some(store_id) = &store_id {
+      require_store(&config, store_id)?;
+    }
+    Ok(run_stats(&app, &config, store_id.as_deref()))
+  })
+  .await
+}
+
+// Newest runs whose id, result, stores or issue/receipt entries contain `query`.
+#[tauri::command]
+pub(crate) async fn search_runs(
+  app: tauri::AppHandle,
+  query: String,
+  limit: Option<usize>,
+) -> Result<Vec<Value>, AppError> {
+  run_blocking(app.clone(), "search_runs", move || {
+    let query = query.trim();
+    if query.is_empty() {
+      return Err(AppError::InvalidInput(
+        "query must not be empty".to_string(),
+      ));
+    }
+    let config = current_config(&app);
+    let limit = limit.unwrap_or(DEFAULT_RUN_SEARCH_LIMIT);
+    Ok(search_runs_in(&app, &config, query, limit))
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) fn get_status_snapshot(app: tauri::AppHandle) -> Result<StatusSnapshot, AppError> {
+  Ok(build_status_snapshot(&app)?)
+}
+
+// Whether RECEIPTS_ROOT is answering, for a window that missed the
+// `root-unreachable` / `root-recovered` events. Doesn't probe.
+#[tauri::command]
+pub(crate) fn get_root_status(app: tauri::AppHandle) -> RootStatus {
+  root_status(&app, &current_config(&app))
+}
+
+// What `access_warning.settings_command` names on macOS; `unsupported` elsewhere.
+#[tauri::command]
+pub(crate) fn open_full_disk_access_settings() -> Result<(), AppError> {
+  open_full_disk_access_pane()
+}
+
+// The startup phases so far, for a window that missed their events.
+#[tauri::command]
+pub(crate) fn get_startup_status(state: tauri::State<StartupState>) -> StartupStatus {
+  state
+    .status
+    .lock()
+    .map(|status| status.clone())
+    .unwrap_or_default()
+}
+
+// Summaries are re-parsed only when the runs folder changed (IssueRunsCache).
+#[tauri::command]
+pub(crate) async fn get_unread_badges(
+  app: tauri::AppHandle,
+  root: Option<String>,
+  max_age: Option<u64>,
+) -> Result<Vec<UnreadBadge>, AppError> {
+  run_blocking(app.clone(), "get_unread_badges", move || {
+    if root.is_none() {
+      return Ok(dashboard_snapshot(&app, max_age, false)?.badges);
+    }
+    let config = current_config(&app).with_root(root)?;
+    let (issue_runs, _) = cached_issue_runs(&app.state::<IssueRunsCache>(), &config);
+    let badges = {
+      let seen_state = app.state::<SeenStateStore>();
+      let state = seen_state.0.lock().map_err(|err| err.to_string())?;
+      unread_badges(&config, &issue_runs, &state)
+    };
+    if config.root_override.is_none() {
+      refresh_tray_badge(&app, &config, &badges);
+    }
+    Ok(badges)
+  })
+  .await
+}
+
+// Runs filesystem-heavy or long-running command bodies on Tauri's blocking
+// pool instead of the thread that serves IPC.
+pub(crate) async fn run_blocking<T: Send + 'static>(
+  app: tauri::AppHandle,
+  command: &'static str,
+  work: impl FnOnce() -> Result<T, AppError> + Send + 'static,
+) -> Result<T, AppError> {
+  let started = Instant::now();
+  let result = tauri::async_runtime::spawn_blocking(work)
+    .await
+    .map_err(|err| AppError::Other(format!("Background task failed: {}", err)))?;
+  app
+    .state::<CommandMetrics>()
+    .record(command, started.elapsed());
+  result
+}
+
+#[tauri::command]
+pub(crate) fn mark_store_badges_seen(
+  app: tauri::AppHandle,
+  seen_state: tauri::State<SeenStateStore>,
+  store_id: String,
+  kind: Option<String>,
+) -> Result<UnreadBadge, AppError> {
+  let config = current_config(&app);
+  require_store(&config, &store_id)?;
+  require_reachable_root(&app, &config)?;
+  let issue_runs = issue_runs_for(&config);
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  mark_badges_seen(&mut state, &config, &issue_runs, &store_id, kind.as_deref())?;
+  save_state(&state)?;
+  schedule_badges_update(&app);
+  unread_badges(&config, &issue_runs, &state)
+    .into_iter()
+    .find(|badge| badge.store_id == store_id)
+    .ok_or_else(|| AppError::UnknownStore(store_id.clone()))
+}
+
+#[tauri::command]
+pub(crate) fn snooze_store(
+  app: tauri::AppHandle,
+  seen_state: tauri::State<SeenStateStore>,
+  store_id: String,
+  until: Option<String>,
+) -> Result<(), AppError> {
+  let config = current_config(&app);
+  require_store(&config, &store_id)?;
+  let until = match until {
+    Some(value) => {
+      let parsed = chrono::DateTime::parse_from_rfc3339(&value)
+        .map_err(|err| AppError::InvalidInput(format!("Invalid snooze time {}: {}", value, err)))?;
+      Some(parsed.with_timezone(&chrono::Utc).to_rfc3339())
+    }
+    None => None,
+  };
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  state.stores.entry(store_id).or_default().snoozed_until = until;
+  save_state(&state)?;
+  schedule_badges_update(&app);
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn list_state_backups() -> Result<Vec<StateBackup>, AppError> {
+  let dir = state_file_path()
+    .and_then(|path| path.parent().map(Path::to_path_buf))
+    .ok_or(AppError::HomeUnavailable)?;
+  Ok(state_backups_in(&dir))
+}
+
+#[tauri::command]
+pub(crate) fn restore_state_backup(
+  app: tauri::AppHandle,
+  seen_state: tauri::State<SeenStateStore>,
+  name: String,
+) -> Result<(), AppError> {
+  let dir = state_file_path()
+    .and_then(|path| path.parent().map(Path::to_path_buf))
+    .ok_or(AppError::HomeUnavailable)?;
+  let mut restored = read_state_backup(&dir, &name)?;
+  let config = current_config(&app);
+  prepare_loaded_state(&mut restored, &config);
+
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  save_state(&restored)?;
+  *state = restored;
+  schedule_badges_update(&app);
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn mark_all_badges_seen(
+  app: tauri::AppHandle,
+  seen_state: tauri::State<SeenStateStore>,
+  store_ids: Option<Vec<String>>,
+) -> Result<Vec<UnreadBadge>, AppError> {
+  let config = current_config(&app);
+  if let Some(store_ids) = &store_ids {
+    for store_id in store_ids {
+      require_store(&config, store_id)?;
+    }
+  }
+  require_reachable_root(&app, &config)?;
+  let issue_runs = issue_runs_for(&config);
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  mark_all_seen(&mut state, &config, &issue_runs, store_ids.as_deref())?;
+  save_state(&state)?;
+  schedule_badges_update(&app);
+  Ok(unread_badges(&config, &issue_runs, &state))
+}
+
+#[tauri::command]
+pub(crate) async fn run_worker(
+  window: tauri::Window,
+  stores: Vec<String>,
+  mode: String,
+) -> Result<RunWorkerResult, AppError> {
+  run_blocking(window.app_handle(), "run_worker", move || {
+    let config = current_config(&window);
+    let sink = EventSink::from(&window);
+    start_worker(&sink, &config, &stores)?;
+    let _ = mode;
+
+    spawn_worker(&sink, &config, store_args(&stores), stores)
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) async fn run_worker_for_files(
+  window: tauri::Window,
+  store_id: String,
+  file_names: Vec<String>,
+) -> Result<RunWorkerResult, AppError> {
+  run_blocking(window.app_handle(), "run_worker_for_files", move || {
+    let config = current_config(&window);
+    let stores = vec![store_id.clone()];
+    if file_names.is_empty() {
+      return Err(AppError::InvalidInput("No files selected".to_string()));
+    }
+    require_reachable_root(&window.app_handle(), &config)?;
+    for file_name in &file_names {
+      resolve_inbox_file(&config, &store_id, file_name)?;
+    }
+    let inbox = Path::new(&config.receipts_root)
+      .join("inbox")
+      .join(&store_id);
+    let sink = EventSink::from(&window);
+    start_worker(&sink, &config, &stores)?;
+
+    let mut args = store_args(&stores);
+    // `--file` takes text, so a name that isn't UTF-8 goes through staging.
+    let plain_names = file_names
+      .iter()
+      .all(|file_name| !file_name.starts_with(RAW_FILE_ID_PREFIX));
+    if config.worker_supports_file_args && plain_names {
+      for file_name in &file_names {
+        args.push("--file".to_string());
+        args.push(file_name.clone());
+      }
+      return spawn_worker(&sink, &config, args, stores);
+    }
+
+    // The slot is held from before staging until the leftovers are back, so
+    // no other run sees the inbox half moved.
+    let started = std::time::SystemTime::now();
+    let job = reserve_worker_job(&window.app_handle())?;
+    let staging = match stage_selected_files(&inbox, &file_names, &new_staging_id()) {
+      Ok(staging) => staging,
+      Err(err) => {
+        release_worker_job(Some(job));
+        return Err(AppError::Other(err));
+      }
+    };
+    args.push("--inbox".to_string());
+    args.push(staging.to_string_lossy().to_string());
+    emit_worker_line(
+      &sink,
+      "stdout",
+      format!("Running {} selected files", file_names.len()),
+      &stores,
+    );
+    let result = run_reserved_worker(&sink, &config, args, &stores, Some(&job));
+    match restore_staged_files(&staging) {
+      Ok(0) => {}
+      Ok(count) => emit_worker_line(
+        &sink,
+        "stdout",
+        format!(
+          "{} selected files were not processed and are back in the inbox",
+          count
+        ),
+        &stores,
+      ),
+      Err(err) => emit_worker_line(
+        &sink,
+        "stderr",
+        format!("Failed to restore staged files: {}", err),
+        &stores,
+      ),
+    }
+    release_worker_job(Some(job));
+    finish_worker_run(&sink, &config, &stores, started, result?)
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) fn get_worker_status(app: tauri::AppHandle) -> WorkerStatus {
+  worker_status(&app)
+}
+
+#[tauri::command]
+pub(crate) fn cancel_run(app: tauri::AppHandle) -> Result<CancelResult, AppError> {
+  cancel_worker_run(&app)
+}
+
+// The check fetches the source repo, so it never runs while a worker run or
+// update is going; the last result (if any) stands in until they finish.
+#[tauri::command]
+pub(crate) async fn get_update_status(
+  app: tauri::AppHandle,
+  force: Option<bool>,
+) -> Result<UpdateStatus, AppError> {
+  let cached = app
+    .state::<UpdateStatusCache>()
+    .0
+    .lock()
+    .map_err(|err| err.to_string())?
+    .clone();
+  if let (Some(cached), false) = (&cached, force.unwrap_or(false)) {
+    return Ok(cached.clone());
+  }
+  if let Some(job) = busy_jobs(&app).into_iter().next() {
+    return cached.ok_or(AppError::AlreadyRunning(job));
+  }
+  run_blocking(app.clone(), "get_update_status", move || {
+    let status = check_update_status(&app)?;
+    record_update_status(&app, &status);
+    Ok(status)
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) async fn get_update_changelog(
+  app: tauri::AppHandle,
+) -> Result<UpdateChangelog, AppError> {
+  run_blocking(app.clone(), "get_update_changelog", move || {
+    let installed_version = app.package_info().version.to_string();
+    let source_dir = match resolve_source_dir() {
+      Some(source_dir) => source_dir,
+      None => {
+        return Ok(UpdateChangelog {
+          installed_version,
+          source_version: None,
+          source: "none".to_string(),
+          entries: Vec::new(),
+          raw: None,
+        })
+      }
+    };
+    let source_version = read_source_version(&source_dir)?;
+    let cache = app.state::<ChangelogCache>();
+    let cached = cache
+      .0
+      .lock()
+      .map_err(|err| err.to_string())?
+      .get(&source_version)
+      .cloned();
+    if let Some(cached) = cached {
+      return Ok(cached);
+    }
+    // Built without the lock held: it reads CHANGELOG.md and may run `git log`.
+    let changelog = build_update_changelog(installed_version, &source_dir, &source_version);
+    cache
+      .0
+      .lock()
+      .map_err(|err| err.to_string())?
+      .insert(source_version, changelog.clone());
+    Ok(changelog)
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) fn get_command_metrics(
+  metrics: tauri::State<CommandMetrics>,
+  inbox_cache: tauri::State<InboxCountCache>,
+  issue_cache: tauri::State<IssueRunsCache>,
+) -> Result<CommandMetricsReport, AppError> {
+  let mut commands = metrics
+    .timings
+    .lock()
+    .map_err(|err| err.to_string())?
+    .values()
+    .cloned()
+    .collect::<Vec<_>>();
+  commands.sort_by(|a, b| a.command.cmp(&b.command));
+  Ok(CommandMetricsReport {
+    since: metrics.since.lock().map_err(|err| err.to_string())?.clone(),
+    bucket_bounds_ms: METRICS_BUCKETS_MS.to_vec(),
+    commands,
+    inbox_cache: inbox_cache.1.counts(),
+    summary_cache: issue_cache.1.counts(),
+    worker_output_peak_bytes: metrics.worker_output_peak_bytes.load(Ordering::Relaxed),
+  })
+}
+
+#[tauri::command]
+pub(crate) fn reset_command_metrics(
+  metrics: tauri::State<CommandMetrics>,
+  inbox_cache: tauri::State<InboxCountCache>,
+  issue_cache: tauri::State<IssueRunsCache>,
+) {
+  metrics.reset();
+  inbox_cache.1.reset();
+  issue_cache.1.reset();
+}
+
+// One document with the resolved config and where each value came from, the
+// stores/state/config files, worker and source dir resolution, platform and
+// the expected folders. Secret-looking values are redacted.
+#[tauri::command]
+pub(crate) fn get_diagnostics(app: tauri::AppHandle) -> Diagnostics {
+  collect_diagnostics(&app)
+}
+
+// Zips logs, crash reports, state, config, stores.json, the latest run
+// summaries and `get_diagnostics` into one file for a bug report. `dest_path`
+// may be a folder. File tags and run notes are left out of the state unless
+// `include_tags` is true.
+#[tauri::command]
+pub(crate) async fn export_diagnostics_bundle(
+  app: tauri::AppHandle,
+  dest_path: String,
+  include_tags: Option<bool>,
+) -> Result<DiagnosticsBundle, AppError> {
+  run_blocking(app.clone(), "export_diagnostics_bundle", move || {
+    let config = current_config(&app);
+    let mut notes = Vec::new();
+    // The bundle is most wanted when the root is gone; it goes without runs then.
+    let run_summaries = match require_reachable_root(&app, &config) {
+      Ok(()) => latest_run_summaries(&config, DIAGNOSTICS_BUNDLE_RUNS),
+      Err(err) => {
+        notes.push(format!("Run summaries left out: {}", err.message()));
+        Vec::new()
+      }
+    };
+    let (stores, stores_file) =
+      load_stores_config_with_source(Some((app.package_info(), &app.env())));
+    let sources = BundleSources {
+      diagnostics: serde_json::to_value(collect_diagnostics(&app)).unwrap_or(Value::Null),
+      config: serde_json::to_value(&config).unwrap_or(Value::Null),
+      config_origins: config_origins(&load_local_config()),
+      stores_file,
+      stores,
+      state_file: state_file_path(),
+      include_tags: include_tags.unwrap_or(false),
+      logs_dir: app_logs_dir(),
+      crashes_dir: crashes_dir(),
+      run_summaries,
+      notes,
+    };
+    let bundle = write_diagnostics_bundle(
+      &sources,
+      Path::new(&dest_path),
+      DIAGNOSTICS_BUNDLE_MAX_BYTES,
+    )?;
+    info!(
+      "diagnostics bundle: {} files, {} bytes to {}{}",
+      bundle.file_count,
+      bundle.size,
+      bundle.path,
+      if bundle.truncated { " (logs cut)" } else { "" }
+    );
+    if let Ok(mut last) = app.state::<LastDiagnosticsBundle>().0.lock() {
+      *last = Some(PathBuf::from(&bundle.path));
+    }
+    Ok(bundle)
+  })
+  .await
+}
+
+// Selects the last exported bundle in Finder/Explorer so it can be attached.
+#[tauri::command]
+pub(crate) fn reveal_diagnostics_bundle(
+  last: tauri::State<LastDiagnosticsBundle>,
+) -> Result<RevealResult, AppError> {
+  let path = last
+    .0
+    .lock()
+    .map_err(|err| err.to_string())?
+    .clone()
+    .ok_or_else(|| AppError::InvalidInput("No diagnostics bundle has been exported".to_string()))?;
+  if !path.is_file() {
+    return Err(AppError::PathNotFound(path.to_string_lossy().to_string()));
+  }
+  let mechanism = reveal_with_system(&path)?;
+  Ok(RevealResult {
+    path: path.to_string_lossy().to_string(),
+    mechanism: mechanism.to_string(),
+  })
+}
+
+// `level` keeps that level and above (e.g. "warn" for warnings and errors).
+#[tauri::command]
+pub(crate) fn get_app_logs(
+  lines: Option<usize>,
+  level: Option<String>,
+) -> Result<AppLogs, AppError> {
+  let dir = app_logs_dir().ok_or(AppError::HomeUnavailable)?;
+  let min_rank = match level.as_deref() {
+    Some(level) => LOG_LEVELS
+      .iter()
+      .position(|candidate| candidate.eq_ignore_ascii_case(level))
+      .ok_or_else(|| AppError::InvalidInput(format!("Unknown log level: {}", level)))?,
+    None => 0,
+  };
+  Ok(AppLogs {
+    path: rotated_log_path(&dir, 0).to_string_lossy().to_string(),
+    lines: tail_app_logs(&dir, lines.unwrap_or(DEFAULT_APP_LOG_LINES), min_rank),
+  })
+}
+
+// Newest first. Reports are left in place until deleted, so the UI can offer
+// them on every launch until the user dismisses them.
+#[tauri::command]
+pub(crate) fn get_crash_reports() -> Result<Vec<CrashReport>, AppError> {
+  let dir = crashes_dir().ok_or(AppError::HomeUnavailable)?;
+  Ok(read_crash_reports(&dir))
+}
+
+#[tauri::command]
+pub(crate) fn delete_crash_report(name: String) -> Result<(), AppError> {
+  if !is_crash_report_name(&name) {
+    return Err(AppError::InvalidInput(format!(
+      "Not a crash report: {}",
+      name
+    )));
+  }
+  let path = crashes_dir().ok_or(AppError::HomeUnavailable)?.join(&name);
+  fs::remove_file(&path).map_err(|err| AppError::io(&path, err))
+}
+
+// Called by the frontend once its `navigate` and `deep-link-warning` listeners
+// are up: returns the links that arrived before then, and emits later ones.
+#[tauri::command]
+pub(crate) fn take_pending_deep_links(queue: tauri::State<DeepLinkQueue>) -> Vec<DeepLinkOutcome> {
+  queue
+    .0
+    .lock()
+    .ok()
+    .and_then(|mut pending| pending.take())
+    .unwrap_or_default()
+}
+
+// Totals from the worker's JSON output next to each processed receipt, per
+// store and per month, one per currency unless `convert_to` converts them.
+// `from`/`to` are inclusive YYYY-MM-DD dates.
+#[tauri::command]
+pub(crate) async fn get_spending_summary(
+  app: tauri::AppHandle,
+  from: Option<String>,
+  to: Option<String>,
+  store_id: Option<String>,
+  convert_to: Option<String>,
+) -> Result<SpendingSummary, AppError> {
+  run_blocking(app.clone(), "get_spending_summary", move || {
+    let config = current_config(&app);
+    let from = parse_date_bound("from", from.as_deref())?;
+    let to = parse_date_bound("to", to.as_deref())?;
+    let conversion = Conversion::new(&config, convert_to.as_deref())?;
+    spending_summary(&app, &config, from, to, store_id, &conversion)
+  })
+  .await
+}
+
+// Per-store totals, receipt counts, average basket and the change against the
+// previous month, plus the grand total; stores without receipts report zeros.
+#[tauri::command]
+pub(crate) async fn get_monthly_report(
+  app: tauri::AppHandle,
+  year: i32,
+  month: u32,
+  convert_to: Option<String>,
+) -> Result<MonthlyReport, AppError> {
+  run_blocking(app.clone(), "get_monthly_report", move || {
+    let config = current_config(&app);
+    let conversion = Conversion::new(&config, convert_to.as_deref())?;
+    cached_monthly_report(&app, &config, year, month, &conversion)
+  })
+  .await
+}
+
+// How the unit price of products matching `query` moved, one series per
+// normalized item name; see `normalize_item_name`.
+#[tauri::command]
+pub(crate) async fn get_price_history(
+  app: tauri::AppHandle,
+  query: String,
+  store_id: Option<String>,
+) -> Result<PriceHistory, AppError> {
+  run_blocking(app.clone(), "get_price_history", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, store_id.as_deref())?;
+    let mut history = price_history(root, &store_ids, &query)?;
+    history.store_id = store_id;
+    Ok(history)
+  })
+  .await
+}
+
+// Writes receipts-summary.json into `summary_dir` now.
+#[tauri::command]
+pub(crate) async fn publish_dashboard_summary(
+  app: tauri::AppHandle,
+) -> Result<PublishResult, AppError> {
+  run_blocking(app.clone(), "publish_dashboard_summary", move || {
+    publish_summary(&app, false)
+  })
+  .await
+}
+
+// Spending of the month against each configured budget.
+#[tauri::command]
+pub(crate) async fn get_budget_status(
+  app: tauri::AppHandle,
+  year: i32,
+  month: u32,
+) -> Result<BudgetStatus, AppError> {
+  run_blocking(app.clone(), "get_budget_status", move || {
+    budget_status(&app, &current_config(&app), year, month)
+  })
+  .await
+}
+
+// Receipt counts and totals per day of `year`, combined and per store, as
+// arrays indexed by day of the year.
+#[tauri::command]
+pub(crate) async fn get_activity_heatmap(
+  app: tauri::AppHandle,
+  year: i32,
+) -> Result<ActivityHeatmap, AppError> {
+  run_blocking(app.clone(), "get_activity_heatmap", move || {
+    cached_activity_heatmap(&app, &current_config(&app), year)
+  })
+  .await
+}
+
+// One row per receipt, or per line item with `include_line_items`; receipts
+// without a parseable total (or items) go to `<name>.skipped.csv`. Errors:
+// `file_exists` unless `overwrite` is set.
+#[tauri::command]
+pub(crate) async fn export_receipts_csv(
+  app: tauri::AppHandle,
+  path: String,
+  from: Option<String>,
+  to: Option<String>,
+  store_id: Option<String>,
+  include_line_items: Option<bool>,
+  overwrite: Option<bool>,
+) -> Result<CsvExport, AppError> {
+  run_blocking(app.clone(), "export_receipts_csv", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, store_id.as_deref())?;
+    let export = export_receipts_csv_to(
+      root,
+      &store_ids,
+      parse_date_bound("from", from.as_deref())?,
+      parse_date_bound("to", to.as_deref())?,
+      include_line_items.unwrap_or(false),
+      Path::new(&path),
+      overwrite.unwrap_or(false),
+    )?;
+    info!(
+      "exported {} rows to {} ({} skipped)",
+      export.rows, export.path, export.skipped
+    );
+    Ok(export)
+  })
+  .await
+}
+
+// One workbook for `year`: a summary sheet of monthly totals per store and a
+// sheet of receipts per month with any. Errors: `file_exists` unless
+// `overwrite` is set.
+#[tauri::command]
+pub(crate) async fn export_receipts_xlsx(
+  app: tauri::AppHandle,
+  path: String,
+  year: i32,
+  store_id: Option<String>,
+  overwrite: Option<bool>,
+) -> Result<XlsxExport, AppError> {
+  run_blocking(app.clone(), "export_receipts_xlsx", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, store_id.as_deref())?;
+    let export = export_receipts_xlsx_to(
+      root,
+      &store_ids,
+      year,
+      Path::new(&path),
+      overwrite.unwrap_or(false),
+    )?;
+    info!(
+      "exported {} sheets to {} ({} undated)",
+      export.sheets.len(),
+      export.path,
+      export.undated
+    );
+    Ok(export)
+  })
+  .await
+}
+
+// Copies the run summaries and processed outputs dated `from`..`to` into the
+// folder `path` with merchants, item names, amounts and file names replaced
+// by tokens. With `include_mapping` the token mapping is added encrypted and
+// its key returned.
+#[tauri::command]
+pub(crate) async fn export_anonymized(
+  app: tauri::AppHandle,
+  path: String,
+  from: Option<String>,
+  to: Option<String>,
+  include_mapping: Option<bool>,
+) -> Result<AnonymizedExport, AppError> {
+  run_blocking(app.clone(), "export_anonymized", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, None)?;
+    let export = export_anonymized_to(
+      root,
+      &store_ids,
+      parse_date_bound("from", from.as_deref())?,
+      parse_date_bound("to", to.as_deref())?,
+      Path::new(&path),
+      include_mapping.unwrap_or(false),
+      &mut Anonymizer::new(),
+    )?;
+    info!(
+      "anonymized {} summaries and {} receipts to {}",
+      export.summaries, export.receipts, export.path
+    );
+    Ok(export)
+  })
+  .await
+}
+
+// Zips RECEIPTS_ROOT to `dest_path` (outside it): `_logs` and the `.json`
+// outputs always, the images and PDFs with `include_images`. Emits
+// `backup-progress`; `cancel_backup` stops it between files.
+#[tauri::command]
+pub(crate) async fn create_backup(
+  app: tauri::AppHandle,
+  dest_path: String,
+  include_images: bool,
+) -> Result<BackupResult, AppError> {
+  run_blocking(app.clone(), "create_backup", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let job = reserve_job(&app, "backup", None)?;
+    let mut progress = |progress: BackupProgress| {
+      let _ = app.emit_all("backup-progress", progress);
+    };
+    let result = write_backup(
+      Path::new(root),
+      state_file_path().as_deref(),
+      Path::new(&dest_path),
+      include_images,
+      &job.cancelled,
+      &mut progress,
+    )?;
+    info!(
+      "backup {}: {} files, {} bytes to {}",
+      result.status, result.file_count, result.size, dest_path
+    );
+    Ok(result)
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) fn cancel_backup(
+  registry: tauri::State<ChildRegistry>,
+) -> Result<CancelResult, AppError> {
+  cancel_backup_job(&registry)
+}
+
+// Lists what `restore_backup` would extract from the archive for `what`
+// (`logs`, `processed_json`, `state` or `all`) and which files it would replace.
+#[tauri::command]
+pub(crate) async fn preview_restore(
+  app: tauri::AppHandle,
+  archive_path: String,
+  what: String,
+) -> Result<RestorePreview, AppError> {
+  run_blocking(app.clone(), "preview_restore", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    preview_restore_from(
+      Path::new(root),
+      state_file_path().as_deref(),
+      Path::new(&archive_path),
+      RestoreSelection::parse(&what)?,
+    )
+  })
+  .await
+}
+
+// Needs the `preview_id` of a preview of the same archive and selection.
+// Existing files are skipped unless `overwrite` is set, and then moved to
+// `.pre-restore/` first; the seen state keeps its usual `state.json.1` copy.
+#[tauri::command]
+pub(crate) async fn restore_backup(
+  app: tauri::AppHandle,
+  archive_path: String,
+  what: String,
+  preview_id: String,
+  overwrite: Option<bool>,
+) -> Result<RestoreResult, AppError> {
+  run_blocking(app.clone(), "restore_backup", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    require_reachable_root(&app, &config)?;
+    let _job = reserve_job(&app, "restore", None)?;
+    let mut progress = |progress: BackupProgress| {
+      let _ = app.emit_all("restore-progress", progress);
+    };
+    let (mut result, state) = restore_backup_to(
+      Path::new(root),
+      state_file_path().as_deref(),
+      Path::new(&archive_path),
+      RestoreSelection::parse(&what)?,
+      &preview_id,
+      overwrite.unwrap_or(false),
+      &mut progress,
+    )?;
+    if let Some(raw) = state {
+      let mut restored =
+        serde_json::from_str::<SeenState>(&raw).map_err(|err| AppError::Parse {
+          path: Some(archive_path.clone()),
+          reason: format!("{} is not a valid state file: {}", BACKUP_STATE_ENTRY, err),
+        })?;
+      prepare_loaded_state(&mut restored, &config);
+      let seen_state = app.state::<SeenStateStore>();
+      let mut current = seen_state.0.lock().map_err(|err| err.to_string())?;
+      save_state(&restored)?;
+      *current = restored;
+      result.restored += 1;
+      result.state_restored = true;
+    }
+    if result.restored > 0 {
+      schedule_badges_update(&app);
+    }
+    info!(
+      "restored {} files from {} ({} skipped, {} overwritten)",
+      result.restored, archive_path, result.skipped, result.overwritten
+    );
+    Ok(result)
+  })
+  .await
+}
+
+// Receipts whose merchant, item names or OCR text contain every word of
+// `query` (ignoring case and diacritics), within the date and total bounds.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub(crate) async fn search_receipts(
+  app: tauri::AppHandle,
+  query: Option<String>,
+  from: Option<String>,
+  to: Option<String>,
+  min_total: Option<f64>,
+  max_total: Option<f64>,
+  store_id: Option<String>,
+  limit: Option<usize>,
+) -> Result<ReceiptSearch, AppError> {
+  run_blocking(app.clone(), "search_receipts", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, store_id.as_deref())?;
+    let filter = ReceiptFilter {
+      terms: search_terms(query.as_deref().unwrap_or("")),
+      from: parse_date_bound("from", from.as_deref())?,
+      to: parse_date_bound("to", to.as_deref())?,
+      min_total,
+      max_total,
+    };
+    let limit = limit.unwrap_or(DEFAULT_RECEIPT_SEARCH_LIMIT);
+    Ok(search_receipts_in(&app, root, &store_ids, &filter, limit))
+  })
+  .await
+}
+
+// Lines of the worker's `.txt` OCR files under processed/ that contain every
+// word of `query`, for receipts whose structured parse lost the item names.
+#[tauri::command]
+pub(crate) async fn search_ocr_text(
+  app: tauri::AppHandle,
+  query: String,
+  store_id: Option<String>,
+  limit: Option<usize>,
+) -> Result<OcrTextSearch, AppError> {
+  run_blocking(app.clone(), "search_ocr_text", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, store_id.as_deref())?;
+    let terms = search_terms(&query);
+    if terms.is_empty() {
+      return Err(AppError::InvalidInput(
+        "query must contain at least one word".to_string(),
+      ));
+    }
+    let limit = limit.unwrap_or(DEFAULT_RECEIPT_SEARCH_LIMIT);
+    Ok(search_ocr_text_in(&app, root, &store_ids, &terms, limit))
+  })
+  .await
+}
+
+// Line-item amounts per category (after the user's mapping), overall and per
+// store, plus the receipts whose items don't add up to their total.
+#[tauri::command]
+pub(crate) async fn get_category_breakdown(
+  app: tauri::AppHandle,
+  from: Option<String>,
+  to: Option<String>,
+  convert_to: Option<String>,
+) -> Result<CategoryBreakdown, AppError> {
+  run_blocking(app.clone(), "get_category_breakdown", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, None)?;
+    Ok(category_breakdown(
+      root,
+      &store_ids,
+      parse_date_bound("from", from.as_deref())?,
+      parse_date_bound("to", to.as_deref())?,
+      &load_category_mapping(),
+      &Conversion::new(&config, convert_to.as_deref())?,
+    ))
+  })
+  .await
+}
+
+// `year` against the year before, month by month and for the whole year,
+// overall, per store and per item category. Deltas stay within a currency.
+#[tauri::command]
+pub(crate) async fn get_yearly_comparison(
+  app: tauri::AppHandle,
+  year: i32,
+  convert_to: Option<String>,
+) -> Result<YearlyComparison, AppError> {
+  run_blocking(app.clone(), "get_yearly_comparison", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, None)?;
+    yearly_comparison(
+      root,
+      &store_ids,
+      year,
+      &load_category_mapping(),
+      &Conversion::new(&config, convert_to.as_deref())?,
+    )
+  })
+  .await
+}
+
+// Where the money went between `from` and `to`: the largest stores, merchants
+// (as printed on the receipt) or products (see `normalize_item_name`), with
+// their share of the total.
+#[tauri::command]
+pub(crate) async fn get_top_spending(
+  app: tauri::AppHandle,
+  from: Option<String>,
+  to: Option<String>,
+  group_by: String,
+  limit: Option<usize>,
+  convert_to: Option<String>,
+) -> Result<TopSpending, AppError> {
+  run_blocking(app.clone(), "get_top_spending", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, None)?;
+    Ok(top_spending(
+      root,
+      &store_ids,
+      parse_date_bound("from", from.as_deref())?,
+      parse_date_bound("to", to.as_deref())?,
+      TopSpendingGroup::parse(&group_by)?,
+      limit
+        .unwrap_or(DEFAULT_TOP_SPENDING_LIMIT)
+        .clamp(1, TOP_SPENDING_MAX_LIMIT),
+      TOP_SPENDING_MAX_KEYS,
+      &Conversion::new(&config, convert_to.as_deref())?,
+    ))
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) fn get_category_mapping() -> CategoryMapping {
+  load_category_mapping()
+}
+
+// Replaces the whole mapping (raw worker category -> category to report it
+// under) and returns it normalized.
+#[tauri::command]
+pub(crate) fn set_category_mapping(
+  mapping: HashMap<String, String>,
+) -> Result<CategoryMapping, AppError> {
+  let mapping = normalize_category_mapping(mapping)?;
+  save_category_mapping(&mapping)?;
+  info!("category mapping saved ({} entries)", mapping.len());
+  Ok(mapping)
+}
+
+// Groups of processed receipts from the same store with the same total within
+// `days` of each other (and similar items when both have them). Detection
+// only: nothing is moved or deleted.
+#[tauri::command]
+pub(crate) async fn find_duplicate_receipts(
+  app: tauri::AppHandle,
+  days: Option<u32>,
+) -> Result<Vec<DuplicateGroup>, AppError> {
+  run_blocking(app.clone(), "find_duplicate_receipts", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let days = days.unwrap_or(DEFAULT_DUPLICATE_WINDOW_DAYS);
+    if days > MAX_DUPLICATE_WINDOW_DAYS {
+      return Err(AppError::InvalidInput(format!(
+        "days must be at most {}",
+        MAX_DUPLICATE_WINDOW_DAYS
+      )));
+    }
+    let store_ids = receipt_store_ids(&config, None)?;
+    let state = app
+      .state::<SeenStateStore>()
+      .0
+      .lock()
+      .map_err(|err| err.to_string())?
+      .clone();
+    Ok(find_duplicates(root, &store_ids, days as i64, &state))
+  })
+  .await
+}
+
+// Stops flagging the receipts of a `find_duplicate_receipts` group as
+// duplicates of each other.
+#[tauri::command]
+pub(crate) fn mark_not_duplicate(
+  app: tauri::AppHandle,
+  seen_state: tauri::State<SeenStateStore>,
+  group_id: String,
+) -> Result<(), AppError> {
+  let (store_id, names) = parse_duplicate_group(&current_config(&app), &group_id)?;
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  let seen = state.stores.entry(store_id).or_default();
+  if dismiss_duplicate_pairs(seen, &names) > 0 {
+    save_state(&state)?;
+  }
+  Ok(())
+}
+
+#[tauri::command]
+pub(crate) fn get_api_status(app: tauri::AppHandle) -> ApiStatus {
+  api_status(&app, &current_config(&app))
+}
+
+// Saves the setting and starts, moves or stops the server right away; a port
+// that can't be bound shows up as `last_error` while `enabled` stays set.
+#[tauri::command]
+pub(crate) fn set_api_enabled(
+  app: tauri::AppHandle,
+  enabled: bool,
+  port: Option<u16>,
+) -> Result<ApiStatus, AppError> {
+  if port.is_some_and(|port| port < 1024) {
+    return Err(AppError::InvalidInput(
+      "API port must be 1024 or above".to_string(),
+    ));
+  }
+  let mut local = load_local_config();
+  local.api_enabled = Some(enabled);
+  if port.is_some() {
+    local.api_port = port;
+  }
+  save_local_config(&local)?;
+  let config = refresh_config(&app);
+  sync_api_server(&app, &config);
+  Ok(api_status(&app, &config))
+}
+
+// POSTs a sample payload (`test: true`) once, without the retry runs get, and
+// reports the HTTP status; an error only when no response came back.
+#[tauri::command]
+pub(crate) async fn test_webhook(
+  app: tauri::AppHandle,
+  url: String,
+) -> Result<WebhookTestResult, AppError> {
+  run_blocking(app, "test_webhook", move || {
+    validate_webhook_url(&url)?;
+    let started = Instant::now();
+    let status = post_webhook(url.trim(), &sample_webhook_payload()).map_err(|err| {
+      AppError::Other(format!(
+        "Webhook {} did not respond: {}",
+        webhook_label(&url),
+        err
+      ))
+    })?;
+    info!("webhook {} test: {}", webhook_label(&url), status);
+    Ok(WebhookTestResult {
+      status,
+      ok: (200..300).contains(&status),
+      elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+  })
+  .await
+}
+
+// The processed image and its parsed output (totals, items, raw OCR text,
+// worker warnings); `file_name` may name either. `parsed` is null when the
+// receipt has no output.
+#[tauri::command]
+pub(crate) fn get_receipt_detail(
+  app: tauri::AppHandle,
+  store_id: String,
+  file_name: String,
+) -> Result<ReceiptDetail, AppError> {
+  receipt_detail(&current_config(&app), &store_id, &file_name)
+}
+
+// Drops and refills the run index from the summary files; the index otherwise
+// only picks up changes incrementally.
+#[tauri::command]
+pub(crate) async fn rebuild_index(app: tauri::AppHandle) -> Result<IndexStats, AppError> {
+  run_blocking(app.clone(), "rebuild_index", move || {
+    let config = current_config(&app);
+    require_receipts_root(&config)?;
+    let index = app.state::<RunIndex>();
+    let mut conn = index.0.lock().map_err(|err| err.to_string())?;
+    let conn = conn.as_mut().ok_or("The run index could not be opened")?;
+    let stats = rebuild_index_at(conn, &config.receipts_root).map_err(|err| err.to_string())?;
+    info!(
+      "run index rebuilt: {} runs, {} issues, {} receipts in {}ms",
+      stats.runs, stats.issues, stats.receipts, stats.elapsed_ms
+    );
+    Ok(stats)
+  })
+  .await
+}
+
+// Recomputes the per-month spending buckets from the receipt rows; they are
+// otherwise kept up to date one changed output at a time.
+#[tauri::command]
+pub(crate) async fn rebuild_stats_cache(
+  app: tauri::AppHandle,
+) -> Result<StatsCacheRebuild, AppError> {
+  run_blocking(app.clone(), "rebuild_stats_cache", move || {
+    let config = current_config(&app);
+    let root = require_receipts_root(&config)?;
+    let store_ids = receipt_store_ids(&config, None)?;
+    let index = app.state::<RunIndex>();
+    let mut conn = index.0.lock().map_err(|err| err.to_string())?;
+    let conn = conn.as_mut().ok_or("The run index could not be opened")?;
+    let rebuilt = rebuild_stats_cache_at(conn, root, &store_ids).map_err(|err| err.to_string())?;
+    info!(
+      "stats cache rebuilt: {} buckets over {} receipts in {}ms",
+      rebuilt.buckets, rebuilt.receipts, rebuilt.elapsed_ms
+    );
+    Ok(rebuilt)
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) fn get_update_history(limit: Option<usize>) -> Vec<UpdateHistoryEntry> {
+  read_update_history()
+    .into_iter()
+    .rev()
+    .take(limit.unwrap_or(DEFAULT_UPDATE_HISTORY_LIMIT))
+    .collect()
+}
+
+#[tauri::command]
+pub(crate) fn relaunch_app(app: tauri::AppHandle) {
+  // The new process waits for this one's instance lock instead of focusing it.
+  std::env::set_var(RELAUNCH_ENV, std::process::id().to_string());
+  // restart() exits without a RunEvent::Exit; free the port for the new process.
+  stop_api_server(&app);
+  app.restart();
+}
+
+#[tauri::command]
+pub(crate) async fn run_update(
+  window: tauri::Window,
+  allow_dirty: Option<bool>,
+) -> Result<RunWorkerResult, AppError> {
+  run_blocking(window.app_handle(), "run_update", move || {
+    let source_dir = resolve_source_dir().ok_or_else(|| {
+      AppError::ConfigMissing(
+        "Source code not found. Set RECEIPTS_APP_SOURCE to the repo path.".to_string(),
+      )
+    })?;
+    ensure_clean_checkout(&source_dir, allow_dirty.unwrap_or(false))?;
+    let command = app_update_command(&source_dir)?;
+    let stages = Arc::new(Mutex::new(StageTracker::new(
+      "update-progress",
+      &UPDATE_STAGES,
+    )));
+    let job = reserve_job(&window.app_handle(), "update", Some(stages.clone()))?;
+    let attempt = UpdateAttempt::start(&window.app_handle(), "app", &source_dir);
+    let mut result = match run_command_stream_with_stages(
+      &EventSink::from(&window),
+      command,
+      Vec::new(),
+      true,
+      Some(stages),
+      Some(&job),
+    ) {
+      Ok(result) => result,
+      Err(err) => {
+        attempt.finish("fail", None, None, None, &err);
+        return Err(AppError::Other(err));
+      }
+    };
+    if job.was_cancelled() {
+      result.status = "cancelled".to_string();
+    }
+    attempt.finish(
+      &result.status,
+      result.exit_code,
+      None,
+      None,
+      &format!("{}{}", result.stdout, result.stderr),
+    );
+    if result.status == "ok" {
+      if let Err(err) = record_installed_update(&window.app_handle(), &source_dir) {
+        warn!("update verification failed: {}", err);
+      }
+    }
+    Ok(result)
+  })
+  .await
+}
+
+// Updates the Python worker (git pull, then requirements into its .venv) and
+// then runs the app update script. The worker goes first because the script
+// quits and relaunches this app, so nothing queued after it would run.
+#[tauri::command]
+pub(crate) async fn run_full_update(
+  window: tauri::Window,
+  allow_dirty: Option<bool>,
+) -> Result<FullUpdateResult, AppError> {
+  run_blocking(window.app_handle(), "run_full_update", move || {
+    let source_dir = resolve_source_dir().ok_or_else(|| {
+      AppError::ConfigMissing(
+        "Source code not found. Set RECEIPTS_APP_SOURCE to the repo path.".to_string(),
+      )
+    })?;
+    let worker_dir = current_config(&window)
+      .worker_dir
+      .filter(|dir| !dir.trim().is_empty())
+      .map(PathBuf::from)
+      .filter(|dir| dir.exists())
+      .ok_or_else(|| {
+        AppError::WorkerNotConfigured("WORKER_DIR is not set or does not exist".to_string())
+      })?;
+    if !is_git_checkout(&worker_dir) {
+      return Err(AppError::InvalidInput(format!(
+        "{} is not a git checkout",
+        worker_dir.to_string_lossy()
+      )));
+    }
+    let allow_dirty = allow_dirty.unwrap_or(false);
+    ensure_clean_checkout(&worker_dir, allow_dirty)?;
+    ensure_clean_checkout(&source_dir, allow_dirty)?;
+    let app_command = app_update_command(&source_dir)?;
+    let toplevel = |dir: &Path| git_output(dir, &["rev-parse", "--show-toplevel"]).ok();
+    let shared_checkout =
+      toplevel(&worker_dir).is_some() && toplevel(&worker_dir) == toplevel(&source_dir);
+
+    let stages = Arc::new(Mutex::new(StageTracker::new(
+      "update-progress",
+      &FULL_UPDATE_STAGES,
+    )));
+    let job = reserve_job(&window.app_handle(), "update", Some(stages.clone()))?;
+    let attempt = UpdateAttempt::start(&window.app_handle(), "full", &source_dir);
+
+    let mut pull = Command::new("git");
+    pull
+      .current_dir(&worker_dir)
+      .env("GIT_TERMINAL_PROMPT", "0")
+      .args(["pull", "--ff-only"]);
+    let python = venv_python(&worker_dir);
+    let mut deps = Vec::new();
+    if !python.exists() {
+      let mut venv = Command::new(if cfg!(target_os = "windows") {
+        "python"
+      } else {
+        "python3"
+      });
+      venv.current_dir(&worker_dir).args(["-m", "venv", ".venv"]);
+      deps.push(venv);
+    }
+    let mut install = Command::new(&python);
+    install
+      .current_dir(&worker_dir)
+      .args(["-m", "pip", "install", "-r", "requirements.txt"]);
+    deps.push(install);
+
+    let plan: Vec<(&str, Option<&str>, Vec<Command>)> = vec![
+      ("worker-pull", Some("worker-pull"), vec![pull]),
+      ("worker-deps", Some("worker-deps"), deps),
+      ("app", None, vec![app_command]),
+    ];
+    let mut result = FullUpdateResult {
+      status: "ok".to_string(),
+      failed_phase: None,
+      phases: Vec::new(),
+      shared_checkout,
+      worker_commit: None,
+    };
+    let mut log = String::new();
+    for (phase, stage, commands) in plan {
+      let outcome = run_update_phase(&window, &stages, &job, phase, stage, commands, &mut log);
+      let status = outcome.status.clone();
+      result.phases.push(outcome);
+      if phase.starts_with("worker-") {
+        result.worker_commit = git_output(&worker_dir, &["rev-parse", "--short", "HEAD"]).ok();
+      }
+      if status != "ok" {
+        result.status = status;
+        result.failed_phase = Some(phase.to_string());
+        break;
+      }
+    }
+    let exit_code = result.phases.last().and_then(|phase| phase.exit_code);
+    attempt.finish(
+      &result.status,
+      exit_code,
+      result.failed_phase.clone(),
+      result.worker_commit.clone(),
+      &log,
+    );
+    if result.status != "ok" {
+      return Ok(result);
+    }
+    if let Err(err) = record_installed_update(&window.app_handle(), &source_dir) {
+      warn!("update verification failed: {}", err);
+    }
+    Ok(result)
+  })
+  .await
+}
+
+// The answer to `confirm-quit`: `cancel_run_and_quit`, `quit_leave_running`
+// (not on Windows) or `stay`.
+#[tauri::command]
+pub(crate) fn resolve_quit(app: tauri::AppHandle, action: String) -> Result<QuitResult, AppError> {
+  resolve_quit_with(&app, &action)
+}
+
+#[tauri::command]
+pub(crate) fn cancel_update(
+  registry: tauri::State<ChildRegistry>,
+) -> Result<CancelResult, AppError> {
+  cancel_update_job(&registry)
+}
+
+// With `create_if_missing`, a store's inbox/processed/failed folder is created
+// (with parents) before opening; `created` reports whether that happened.
+#[tauri::command]
+pub(crate) fn open_path(
+  window: tauri::Window,
+  path_type: String,
+  store_id: Option<String>,
+  file_path: Option<String>,
+  create_if_missing: Option<bool>,
+  root: Option<String>,
+) -> Result<OpenedPath, AppError> {
+  let config = current_config(&window).with_root(root)?;
+  let mut created = false;
+  if create_if_missing.unwrap_or(false)
+    && ["inbox", "processed", "failed"].contains(&path_type.as_str())
+  {
+    let store = store_id.as_deref().ok_or("store_id required")?;
+    require_store(&config, store)?;
+    let dir = Path::new(&config.receipts_root)
+      .join(&path_type)
+      .join(store);
+    if !dir.exists() {
+      fs::create_dir_all(&dir).map_err(|err| AppError::io(&dir, err))?;
+      created = true;
+    }
+  }
+  let resolved = resolve_open_path(&config, &path_type, store_id.clone(), file_path)?;
+  open_resolved(&window, &resolved)?;
+  record_recent_location(&window.app_handle(), &path_type, store_id, &resolved);
+  Ok(OpenedPath {
+    path: resolved.to_string_lossy().to_string(),
+    created,
+  })
+}
+
+#[tauri::command]
+pub(crate) fn copy_path_to_clipboard(
+  config_store: tauri::State<ConfigStore>,
+  path_type: String,
+  store_id: Option<String>,
+  file_path: Option<String>,
+) -> Result<ClipboardCopy, AppError> {
+  let config = config_store.get();
+  let resolved = resolve_open_path(&config, &path_type, store_id, file_path)?;
+  let path = resolved.to_string_lossy().to_string();
+  let mut clipboard = arboard::Clipboard::new().map_err(|err| err.to_string())?;
+  clipboard
+    .set_text(path.clone())
+    .map_err(|err| err.to_string())?;
+  Ok(ClipboardCopy {
+    path,
+    content: "path".to_string(),
+  })
+}
+
+// Puts the file itself on the clipboard, ready to paste into Finder/Explorer
+// or an email. Linux has no common file-clipboard format, so it is refused there.
+#[tauri::command]
+pub(crate) fn copy_file_to_clipboard(
+  config_store: tauri::State<ConfigStore>,
+  path_type: String,
+  store_id: Option<String>,
+  file_path: Option<String>,
+) -> Result<ClipboardCopy, AppError> {
+  let config = config_store.get();
+  let resolved = resolve_open_path(&config, &path_type, store_id, file_path)?;
+  let path = resolved.to_string_lossy().to_string();
+  #[cfg(target_os = "macos")]
+  let output = Command::new("osascript")
+    .arg("-e")
+    .arg(format!(
+      "set the clipboard to (POSIX file \"{}\")",
+      path.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+    .output();
+  #[cfg(target_os = "windows")]
+  let output = Command::new("powershell")
+    .args(["-NoProfile", "-Command"])
+    .arg(format!(
+      "Set-Clipboard -LiteralPath '{}'",
+      path.replace('\'', "''")
+    ))
+    .output();
+  #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+  let output: std::io::Result<std::process::Output> = Err(std::io::Error::new(
+    std::io::ErrorKind::Unsupported,
+    "copying files to the clipboard is only supported on macOS and Windows",
+  ));
+  match output {
+    Ok(output) if output.status.success() => Ok(ClipboardCopy {
+      path,
+      content: "file".to_string(),
+    }),
+    Ok(output) => Err(
+      format!(
+        "Copying the file failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+      )
+      .into(),
+    ),
+    Err(err) if err.kind() == std::io::ErrorKind::Unsupported => {
+      Err(AppError::Unsupported(err.to_string()))
+    }
+    Err(err) => Err(err.to_string().into()),
+  }
+}
+
+// Opens a terminal window in WORKER_DIR, RECEIPTS_ROOT or the app source dir.
+#[tauri::command]
+pub(crate) fn open_terminal(
+  config_store: tauri::State<ConfigStore>,
+  location: String,
+) -> Result<TerminalLaunch, AppError> {
+  let config = config_store.get();
+  let dir = match location.as_str() {
+    "worker_dir" => config.worker_dir.clone().map(PathBuf::from),
+    "receipts_root" => Some(PathBuf::from(&config.receipts_root)),
+    "source_dir" => resolve_source_dir(),
+    _ => {
+      return Err(AppError::InvalidInput(format!(
+        "Unknown location: {}",
+        location
+      )))
+    }
+  }
+  .filter(|dir| !dir.as_os_str().is_empty())
+  .ok_or_else(|| AppError::ConfigMissing(format!("{} is not configured", location)))?;
+  let dir = dir.canonicalize().map_err(|err| AppError::io(&dir, err))?;
+  if !dir.is_dir() {
+    return Err(AppError::PathNotFound(dir.to_string_lossy().to_string()));
+  }
+  let terminal = launch_terminal(&dir)?;
+  Ok(TerminalLaunch {
+    path: dir.to_string_lossy().to_string(),
+    terminal,
+  })
+}
+
+#[tauri::command]
+pub(crate) fn list_openers(
+  prefs: tauri::State<PrefsStore>,
+  extension: String,
+) -> Result<OpenerList, AppError> {
+  let prefs = prefs.prefs.lock().map_err(|err| err.to_string())?;
+  Ok(openers_for(&prefs, &extension))
+}
+
+// Opens a resolved path with a named application. `unknown_app` means the app
+// could not be launched; the message includes the command that was tried.
+#[tauri::command]
+pub(crate) fn open_with(
+  config_store: tauri::State<ConfigStore>,
+  path_type: String,
+  store_id: Option<String>,
+  file_path: Option<String>,
+  app: String,
+) -> Result<OpenedPath, AppError> {
+  let config = config_store.get();
+  let resolved = resolve_open_path(&config, &path_type, store_id, file_path)?;
+  if app.trim().is_empty() {
+    return Err("app required".into());
+  }
+  let path = resolved.to_string_lossy().to_string();
+  #[cfg(target_os = "macos")]
+  let (attempted, result) = {
+    let mut command = Command::new("open");
+    command.arg("-a").arg(&app).arg(&resolved);
+    (
+      format!("open -a \"{}\" \"{}\"", app, path),
+      run_opener(command, "open -a"),
+    )
+  };
+  #[cfg(target_os = "windows")]
+  let (attempted, result) = {
+    use std::os::windows::process::CommandExt;
+    let args = format!("/C start \"\" {} {}", cmd_quote(&app), cmd_quote(&path));
+    let result = Command::new("cmd")
+      .raw_arg(&args)
+      .output()
+      .map_err(|err| err.to_string())
+      .and_then(|output| {
+        if output.status.success() {
+          Ok(())
+        } else {
+          Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+      });
+    (format!("cmd {}", args), result)
+  };
+  #[cfg(all(unix, not(target_os = "macos")))]
+  let (attempted, result) = {
+    let result = Command::new(&app)
+      .arg(&resolved)
+      .stdin(Stdio::null())
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .spawn()
+      .map(|mut child| {
+        thread::spawn(move || child.wait());
+      })
+      .map_err(|err| err.to_string());
+    (format!("{} \"{}\"", app, path), result)
+  };
+  match result {
+    Ok(()) => Ok(OpenedPath {
+      path,
+      created: false,
+    }),
+    Err(reason) => Err(AppError::UnknownApp {
+      app,
+      attempted,
+      reason,
+    }),
+  }
+}
+
+// Shows the path selected in Finder/Explorer/the file manager. `mechanism`
+// names what actually ran, so platform problems can be told apart.
+#[tauri::command]
+pub(crate) fn reveal_path(
+  app: tauri::AppHandle,
+  path_type: String,
+  store_id: Option<String>,
+  file_path: Option<String>,
+) -> Result<RevealResult, AppError> {
+  let config = current_config(&app);
+  let resolved = resolve_open_path(&config, &path_type, store_id.clone(), file_path)?;
+  let mechanism = reveal_with_system(&resolved)?;
+  record_recent_location(&app, &path_type, store_id, &resolved);
+  Ok(RevealResult {
+    path: resolved.to_string_lossy().to_string(),
+    mechanism: mechanism.to_string(),
+  })
+}
+
+// Opens a run's summary or log by run id, so the UI never builds log paths.
+// Errors: `unknown_run` (no summary for that id) and `log_not_found`.
+#[tauri::command]
+pub(crate) fn open_run_artifact(
+  window: tauri::Window,
+  run_id: String,
+  kind: String,
+) -> Result<(), AppError> {
+  let config = current_config(&window);
+  let unknown = || AppError::UnknownRun(run_id.clone());
+  if run_id.is_empty() || run_id.contains(['/', '\\']) || run_id.starts_with('.') {
+    return Err(unknown());
+  }
+  let (summary_path, summary) =
+    find_run_summary(&config.receipts_root, &run_id).ok_or_else(unknown)?;
+  let target = match kind.as_str() {
+    "summary" => summary_path,
+    "log" => {
+      let log = run_log_path(&summary_path, &summary, &run_id);
+      if !log.exists() {
+        return Err(AppError::LogNotFound {
+          run_id,
+          path: log.to_string_lossy().to_string(),
+        });
+      }
+      log
+    }
+    _ => {
+      return Err(AppError::InvalidInput(format!(
+        "Unknown artifact kind: {}",
+        kind
+      )))
+    }
+  };
+  let resolved = validate_allowed_path(&target, &allowed_roots(&config))?;
+  Ok(open_resolved(&window, &resolved)?)
+}
+
+// Opens (or focuses) a window on the `#/run/<id>` route and returns its label.
+// The id has to be one a `receipts://run/<id>` link could carry and have a
+// summary; otherwise `invalid_input` or `unknown_run`.
+#[tauri::command]
+pub(crate) async fn open_run_window(
+  app: tauri::AppHandle,
+  run_id: String,
+) -> Result<String, AppError> {
+  run_blocking(app.clone(), "open_run_window", move || {
+    if !is_link_id(&run_id) {
+      return Err(AppError::InvalidInput(format!(
+        "Invalid run id: {}",
+        run_id
+      )));
+    }
+    let config = current_config(&app);
+    require_receipts_root(&config)?;
+    find_run_summary(&config.receipts_root, &run_id)
+      .ok_or_else(|| AppError::UnknownRun(run_id.clone()))?;
+    open_run_window_for(&app, &run_id)
+  })
+  .await
+}
+
+#[tauri::command]
+pub(crate) fn open_latest_inbox_file(
+  window: tauri::Window,
+  store_id: String,
+) -> Result<OpenedInboxFile, AppError> {
+  let config = current_config(&window);
+  require_store(&config, &store_id)?;
+  let inbox = Path::new(&config.receipts_root)
+    .join("inbox")
+    .join(&store_id);
+  let latest = match latest_inbox_file(&inbox)? {
+    Some(file) => file,
+    None => {
+      return Ok(OpenedInboxFile {
+        status: "empty".to_string(),
+        file_name: None,
+      })
+    }
+  };
+
+  open_resolved(&window, &join_file_id(&inbox, &latest.id))?;
+  Ok(OpenedInboxFile {
+    status: "opened".to_string(),
+    file_name: Some(latest.name),
+  })
+}
@@ -0,0 +1,285 @@
+use crate::*;
+use chrono::Datelike;
+
+// One currency's figures; amounts in different currencies are never netted.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ComparisonFigures {
+  pub(crate) currency: String,
+  pub(crate) current: f64,
+  pub(crate) previous: f64,
+  pub(crate) delta: f64,
+  // None when there is nothing to compare against (`previous` is 0).
+  pub(crate) change_percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ComparisonPeriod {
+  // 1-12; 0 for the whole year.
+  pub(crate) month: u32,
+  // `current`, `previous` or `both`: the year(s) without any receipts (items,
+  // for a category) in the period, whose figures are zeros rather than data.
+  pub(crate) no_data: Option<String>,
+  pub(crate) by_currency: Vec<ComparisonFigures>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ComparisonSeries {
+  // The store id or category; `all` for every store together.
+  pub(crate) key: String,
+  // Always twelve, January first.
+  pub(crate) months: Vec<ComparisonPeriod>,
+  pub(crate) total: ComparisonPeriod,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct YearlyComparison {
+  pub(crate) year: i32,
+  pub(crate) previous_year: i32,
+  pub(crate) convert_to: Option<String>,
+  pub(crate) overall: ComparisonSeries,
+  // Every configured store, with or without receipts.
+  pub(crate) stores: Vec<ComparisonSeries>,
+  // Item categories (see `item_category`) seen in either year, by name.
+  pub(crate) categories: Vec<ComparisonSeries>,
+}
+
+// Sums for one series: (year index, month, currency) -> amount, where index 0
+// is the year asked for and 1 the year before, plus how many receipts (or
+// items) each month had.
+#[derive(Default)]
+pub(crate) struct ComparisonSums {
+  pub(crate) amounts: std::collections::BTreeMap<(usize, u32, String), f64>,
+  pub(crate) counts: [[usize; 12]; 2],
+}
+
+impl ComparisonSums {
+  pub(crate) fn add(&mut self, year: usize, month: u32, amount: Option<(String, f64)>) {
+    self.counts[year][month as usize - 1] += 1;
+    if let Some((currency, amount)) = amount {
+      *self.amounts.entry((year, month, currency)).or_default() += amount;
+    }
+  }
+
+  pub(crate) fn period(&self, month: u32) -> ComparisonPeriod {
+    let in_period = |candidate: u32| month == 0 || candidate == month;
+    let count = |year: usize| -> usize {
+      (1..=12)
+        .filter(|month| in_period(*month))
+        .map(|month| self.counts[year][month as usize - 1])
+        .sum()
+    };
+    let mut sums = std::collections::BTreeMap::<String, (f64, f64)>::new();
+    for ((year, candidate, currency), amount) in &self.amounts {
+      if in_period(*candidate) {
+        let entry = sums.entry(currency.clone()).or_default();
+        if *year == 0 {
+          entry.0 += amount;
+        } else {
+          entry.1 += amount;
+        }
+      }
+    }
+    ComparisonPeriod {
+      month,
+      no_data: match (count(0), count(1)) {
+        (0, 0) => Some("both".to_string()),
+        (0, _) => Some("current".to_string()),
+        (_, 0) => Some("previous".to_string()),
+        _ => None,
+      },
+      by_currency: sums
+        .into_iter()
+        .map(|(currency, (current, previous))| ComparisonFigures {
+          currency,
+          current: round_cents(current),
+          previous: round_cents(previous),
+          delta: round_cents(current - previous),
+          change_percent: (round_cents(previous) != 0.0)
+            .then(|| ((current - previous) / previous * 1000.0).round() / 10.0),
+        })
+        .collect(),
+    }
+  }
+
+  pub(crate) fn series(&self, key: &str) -> ComparisonSeries {
+    ComparisonSeries {
+      key: key.to_string(),
+      months: (1..=12).map(|month| self.period(month)).collect(),
+      total: self.period(0),
+    }
+  }
+}
+
+// Reads every processed output once; receipts count for the month of their
+// own date. Nothing under processed/ is written.
+pub(crate) fn yearly_comparison(
+  receipts_root: &str,
+  store_ids: &[String],
+  year: i32,
+  mapping: &CategoryMapping,
+  conversion: &Conversion,
+) -> Result<YearlyComparison, AppError> {
+  year_start(year)?;
+  year_start(year - 1)?;
+  let mut overall = ComparisonSums::default();
+  let mut stores = std::collections::BTreeMap::<String, ComparisonSums>::new();
+  let mut categories = std::collections::BTreeMap::<String, ComparisonSums>::new();
+  for store_id in store_ids {
+    let store = stores.entry(store_id.clone()).or_default();
+    let dir = processed_dir(receipts_root, store_id);
+    for name in receipt_output_names(&dir) {
+      let path = join_file_id(&dir, &name);
+      let value = match fs::read_to_string(&path) {
+        Ok(raw) => serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null),
+        Err(_) => continue,
+      };
+      let receipt = match receipt_total_from(store_id, &path, &value) {
+        Some(receipt) => receipt,
+        None => continue,
+      };
+      let date = match receipt.date {
+        Some(date) if date.year() == year || date.year() == year - 1 => date,
+        _ => continue,
+      };
+      let index = usize::from(date.year() != year);
+      let currency = receipt_currency(&receipt);
+      let amount = receipt
+        .total
+        .map(|total| conversion.apply(&currency, total));
+      overall.add(index, date.month(), amount.clone());
+      store.add(index, date.month(), amount);
+      for item in value
+        .get("items")
+        .and_then(|items| items.as_array())
+        .into_iter()
+        .flatten()
+      {
+        if let Some(amount) = item_amount(item) {
+          categories
+            .entry(item_category(item, mapping))
+            .or_default()
+            .add(
+              index,
+              date.month(),
+              Some(conversion.apply(&currency, amount)),
+            );
+        }
+      }
+    }
+  }
+  Ok(YearlyComparison {
+    year,
+    previous_year: year - 1,
+    convert_to: conversion.target.clone(),
+    overall: overall.series("all"),
+    stores: store_ids
+      .iter()
+      .map(|store_id| stores[store_id].series(store_id))
+      .collect(),
+    categories: categories
+      .iter()
+      .map(|(category, sums)| sums.series(category))
+      .collect(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn yearly_comparison_keeps_currencies_apart_and_marks_gaps() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path().to_string_lossy().to_string();
+    for (store, name, raw) in [
+      (
+        "lidl",
+        "a.jpg.json",
+        r#"{"timestamp": "2024-01-10T10:00:00", "total": 150, "currency": "RON",
+          "items": [{"name": "Lapte", "category": "Dairy", "paid_amount": 150}]}"#,
+      ),
+      (
+        "lidl",
+        "b.jpg.json",
+        r#"{"timestamp": "2023-01-12T10:00:00", "total": 100, "currency": "RON",
+          "items": [{"name": "Paine", "category": "bakery", "paid_amount": 100}]}"#,
+      ),
+      (
+        "lidl",
+        "c.jpg.json",
+        r#"{"timestamp": "2023-03-01T10:00:00", "total": 20, "currency": "EUR"}"#,
+      ),
+      (
+        "lidl",
+        "d.jpg.json",
+        r#"{"timestamp": "2022-01-01T10:00:00", "total": 999, "currency": "RON"}"#,
+      ),
+    ] {
+      let path = dir.path().join("processed").join(store);
+      fs::create_dir_all(&path).expect("dir");
+      fs::write(path.join(name), raw).expect("output");
+    }
+    let stores = vec!["lidl".to_string(), "penny".to_string()];
+    let comparison = yearly_comparison(
+      &root,
+      &stores,
+      2024,
+      &CategoryMapping::new(),
+      &Conversion::default(),
+    )
+    .expect("comparison");
+    assert_eq!(comparison.previous_year, 2023);
+    let january = &comparison.overall.months[0];
+    assert_eq!(january.no_data, None);
+    assert_eq!(
+      january.by_currency,
+      vec![ComparisonFigures {
+        currency: "RON".to_string(),
+        current: 150.0,
+        previous: 100.0,
+        delta: 50.0,
+        change_percent: Some(50.0),
+      }]
+    );
+    let march = &comparison.overall.months[2];
+    assert_eq!(march.no_data.as_deref(), Some("current"));
+    assert_eq!(march.by_currency[0].currency, "EUR");
+    assert_eq!(march.by_currency[0].delta, -20.0);
+    assert_eq!(
+      comparison.overall.months[5].no_data.as_deref(),
+      Some("both")
+    );
+    let total = &comparison.overall.total;
+    assert_eq!(total.month, 0);
+    assert_eq!(
+      total
+        .by_currency
+        .iter()
+        .map(|figures| (figures.currency.as_str(), figures.current, figures.previous))
+        .collect::<Vec<_>>(),
+      vec![("EUR", 0.0, 20.0), ("RON", 150.0, 100.0)]
+    );
+    assert_eq!(comparison.stores.len(), 2);
+    assert_eq!(comparison.stores[1].total.no_data.as_deref(), Some("both"));
+    let keys = comparison
+      .categories
+      .iter()
+      .map(|series| series.key.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(keys, vec!["bakery", "dairy"]);
+    let bakery = &comparison.categories[0].months[0];
+    assert_eq!(bakery.no_data.as_deref(), Some("current"));
+    assert_eq!(bakery.by_currency[0].change_percent, Some(-100.0));
+
+    assert!(matches!(
+      yearly_comparison(
+        &root,
+        &stores,
+        1970,
+        &CategoryMapping::new(),
+        &Conversion::default()
+      ),
+      Err(AppError::InvalidInput(_))
+    ));
+  }
+}
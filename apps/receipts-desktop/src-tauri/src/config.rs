@@ -0,0 +1,652 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct StoreConfig {
+  pub(crate) id: String,
+  pub(crate) name: String,
+  pub(crate) enabled: bool,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) backlog_warn_count: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) backlog_warn_days: Option<u64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) notifications_enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct AppConfig {
+  pub(crate) receipts_root: String,
+  pub(crate) worker_dir: Option<String>,
+  pub(crate) worker_run_cmd: Option<String>,
+  pub(crate) import_name_template: String,
+  pub(crate) worker_supports_file_args: bool,
+  pub(crate) backlog_warn_count: Option<usize>,
+  pub(crate) backlog_warn_days: Option<u64>,
+  pub(crate) inbox_cache_ttl_secs: u64,
+  pub(crate) badge_window_days: Option<u64>,
+  pub(crate) badge_metric: String,
+  pub(crate) notifications_enabled: bool,
+  pub(crate) notify_warnings: bool,
+  pub(crate) quiet_hours_start: Option<String>,
+  pub(crate) quiet_hours_end: Option<String>,
+  pub(crate) minimize_to_tray: bool,
+  pub(crate) seen_run_ids_cap: usize,
+  pub(crate) notified_run_ids_cap: usize,
+  pub(crate) update_check_hours: Option<u64>,
+  // Static rates for `convert_to`: the value of one unit of each currency in a
+  // common base of the user's choosing.
+  pub(crate) currency_rates: CurrencyRates,
+  // The local HTTP API (see api.rs); off unless turned on.
+  pub(crate) api_enabled: bool,
+  pub(crate) api_port: u16,
+  // POSTed to after every worker run; see webhook.rs.
+  pub(crate) webhooks: Vec<WebhookConfig>,
+  // Monthly spending limits; see budget.rs.
+  pub(crate) budgets: Budgets,
+  // Where receipts-summary.json is published; see publish.rs.
+  pub(crate) summary_dir: Option<String>,
+  pub(crate) publish_summary_after_run: bool,
+  pub(crate) stores: Vec<StoreConfig>,
+  pub(crate) config_ready: bool,
+  pub(crate) home_available: bool,
+  // Canonical root a read command was pointed at instead of `receipts_root`
+  // from the config; see `with_root`.
+  #[serde(skip)]
+  pub(crate) root_override: Option<String>,
+  // `resolve_allowed_roots` as of loading; None when RECEIPTS_ROOT didn't
+  // resolve then.
+  #[serde(skip)]
+  pub(crate) resolved_roots: Option<Vec<PathBuf>>,
+}
+
+impl AppConfig {
+  // The config with `receipts_root` swapped for an explicit root, which must be
+  // an existing directory. A root that canonicalizes to the configured one is
+  // not treated as an override.
+  pub(crate) fn with_root(mut self, root: Option<String>) -> Result<AppConfig, AppError> {
+    let root = match root {
+      Some(root) => canonical_root(&root)?,
+      None => return Ok(self),
+    };
+    let configured = fs::canonicalize(&self.receipts_root)
+      .map(|path| path.to_string_lossy().to_string())
+      .ok();
+    if configured.as_deref() != Some(root.as_str()) {
+      self.receipts_root = root.clone();
+      self.root_override = Some(root);
+      self.resolve_roots();
+    }
+    Ok(self)
+  }
+
+  pub(crate) fn resolve_roots(&mut self) {
+    self.resolved_roots = Path::new(self.receipts_root.trim())
+      .is_dir()
+      .then(|| resolve_allowed_roots(self));
+  }
+
+  // Key for a store's seen state and cached inbox stats. Under a root override
+  // the root is part of the key, so badges for different roots stay apart.
+  pub(crate) fn seen_key(&self, store_id: &str) -> String {
+    match &self.root_override {
+      Some(root) => format!("{}@{}", store_id, root),
+      None => store_id.to_string(),
+    }
+  }
+}
+
+pub(crate) fn canonical_root(root: &str) -> Result<String, AppError> {
+  let root = root.trim();
+  if root.is_empty() {
+    return Err(AppError::InvalidInput("root must not be empty".to_string()));
+  }
+  let path = fs::canonicalize(root).map_err(|err| AppError::io(Path::new(root), err))?;
+  if !path.is_dir() {
+    return Err(AppError::InvalidInput(format!(
+      "root is not a directory: {}",
+      path.to_string_lossy()
+    )));
+  }
+  Ok(path.to_string_lossy().to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct LocalConfig {
+  pub(crate) receipts_root: Option<String>,
+  pub(crate) worker_dir: Option<String>,
+  pub(crate) worker_run_cmd: Option<String>,
+  pub(crate) import_name_template: Option<String>,
+  pub(crate) worker_supports_file_args: Option<bool>,
+  pub(crate) backlog_warn_count: Option<usize>,
+  pub(crate) backlog_warn_days: Option<u64>,
+  pub(crate) inbox_cache_ttl_secs: Option<u64>,
+  pub(crate) badge_window_days: Option<u64>,
+  pub(crate) badge_metric: Option<String>,
+  pub(crate) notifications_enabled: Option<bool>,
+  pub(crate) notify_warnings: Option<bool>,
+  pub(crate) quiet_hours_start: Option<String>,
+  pub(crate) quiet_hours_end: Option<String>,
+  #[serde(default)]
+  pub(crate) store_notifications: HashMap<String, bool>,
+  pub(crate) minimize_to_tray: Option<bool>,
+  pub(crate) seen_run_ids_cap: Option<usize>,
+  pub(crate) notified_run_ids_cap: Option<usize>,
+  pub(crate) update_check_hours: Option<u64>,
+  #[serde(default)]
+  pub(crate) currency_rates: CurrencyRates,
+  pub(crate) api_enabled: Option<bool>,
+  pub(crate) api_port: Option<u16>,
+  #[serde(default)]
+  pub(crate) webhooks: Vec<WebhookConfig>,
+  #[serde(default)]
+  pub(crate) budgets: Budgets,
+  pub(crate) summary_dir: Option<String>,
+  pub(crate) publish_summary_after_run: Option<bool>,
+}
+
+// None without a home directory: the default root is relative to it, and a
+// bare relative path would resolve against whatever the working directory is.
+pub(crate) fn default_receipts_root(paths: &AppPaths) -> Option<String> {
+  let home = paths.home.as_ref()?;
+  Some(
+    home
+      .join(DEFAULT_RECEIPTS_ROOT)
+      .to_string_lossy()
+      .to_string(),
+  )
+}
+
+pub(crate) fn default_worker_dir() -> Option<String> {
+  let home = home_dir()?;
+  let path = home.join(DEFAULT_WORKER_DIR);
+  if path.exists() {
+    Some(path.to_string_lossy().to_string())
+  } else {
+    None
+  }
+}
+
+pub(crate) fn default_worker_cmd(worker_dir: &Option<String>) -> Option<String> {
+  let dir = worker_dir.as_deref()?;
+  let path = Path::new(dir).join("run.sh");
+  if path.exists() {
+    Some(path.to_string_lossy().to_string())
+  } else {
+    None
+  }
+}
+
+pub(crate) fn env_var(key: &str) -> Option<String> {
+  std::env::var(key)
+    .ok()
+    .filter(|value| !value.trim().is_empty())
+}
+
+pub(crate) fn load_stores_config(package_env: Option<(&PackageInfo, &Env)>) -> Vec<StoreConfig> {
+  load_stores_config_with_source(package_env).0
+}
+
+// Where stores.json is looked for, in order: RECEIPTS_STORES_PATH, `config/`
+// under and next to the working directory, then the bundled resource.
+pub(crate) fn stores_config_candidates(package_env: Option<(&PackageInfo, &Env)>) -> Vec<PathBuf> {
+  let mut paths = Vec::new();
+  if let Some(custom) = env_var("RECEIPTS_STORES_PATH") {
+    paths.push(PathBuf::from(custom));
+  }
+  if let Ok(current) = std::env::current_dir() {
+    paths.push(current.join("config").join("stores.json"));
+    paths.push(current.join("..").join("config").join("stores.json"));
+  }
+  if let Some((package_info, env)) = package_env {
+    if let Some(resource_base) = resource_dir(package_info, env) {
+      paths.push(resource_base.join("stores.json"));
+    }
+  }
+  paths
+}
+
+// The stores and the file they came from; None for the built-in defaults.
+pub(crate) fn load_stores_config_with_source(
+  package_env: Option<(&PackageInfo, &Env)>,
+) -> (Vec<StoreConfig>, Option<PathBuf>) {
+  for path in stores_config_candidates(package_env) {
+    if path.exists() {
+      if let Ok(raw) = fs::read_to_string(&path) {
+        if let Ok(stores) = serde_json::from_str::<Vec<StoreConfig>>(&raw) {
+          return (stores, Some(path));
+        }
+      }
+    }
+  }
+
+  let defaults = vec![
+    StoreConfig {
+      id: "lidl".to_string(),
+      name: "Lidl".to_string(),
+      enabled: true,
+      backlog_warn_count: None,
+      backlog_warn_days: None,
+      notifications_enabled: None,
+    },
+    StoreConfig {
+      id: "kaufland".to_string(),
+      name: "Kaufland".to_string(),
+      enabled: false,
+      backlog_warn_count: None,
+      backlog_warn_days: None,
+      notifications_enabled: None,
+    },
+    StoreConfig {
+      id: "carrefour".to_string(),
+      name: "Carrefour".to_string(),
+      enabled: false,
+      backlog_warn_count: None,
+      backlog_warn_days: None,
+      notifications_enabled: None,
+    },
+  ];
+  (defaults, None)
+}
+
+// Which layer a config value came from: its env var, config.json, or the
+// built-in default.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ConfigOrigin {
+  pub(crate) key: &'static str,
+  pub(crate) env: Option<&'static str>,
+  pub(crate) origin: &'static str,
+}
+
+pub(crate) fn config_origins(local: &LocalConfig) -> Vec<ConfigOrigin> {
+  let layers: [(&'static str, Option<&'static str>, bool); 26] = [
+    (
+      "receipts_root",
+      Some("RECEIPTS_ROOT"),
+      local.receipts_root.is_some(),
+    ),
+    ("worker_dir", Some("WORKER_DIR"), local.worker_dir.is_some()),
+    (
+      "worker_run_cmd",
+      Some("WORKER_RUN_CMD"),
+      local.worker_run_cmd.is_some(),
+    ),
+    (
+      "import_name_template",
+      Some("RECEIPTS_IMPORT_TEMPLATE"),
+      local.import_name_template.is_some(),
+    ),
+    (
+      "worker_supports_file_args",
+      Some("WORKER_FILE_ARGS"),
+      local.worker_supports_file_args.is_some(),
+    ),
+    (
+      "backlog_warn_count",
+      Some("RECEIPTS_BACKLOG_WARN_COUNT"),
+      local.backlog_warn_count.is_some(),
+    ),
+    (
+      "backlog_warn_days",
+      Some("RECEIPTS_BACKLOG_WARN_DAYS"),
+      local.backlog_warn_days.is_some(),
+    ),
+    (
+      "inbox_cache_ttl_secs",
+      Some("RECEIPTS_INBOX_CACHE_TTL"),
+      local.inbox_cache_ttl_secs.is_some(),
+    ),
+    (
+      "badge_window_days",
+      Some("RECEIPTS_BADGE_WINDOW_DAYS"),
+      local.badge_window_days.is_some(),
+    ),
+    (
+      "badge_metric",
+      Some("RECEIPTS_BADGE_METRIC"),
+      local.badge_metric.is_some(),
+    ),
+    (
+      "seen_run_ids_cap",
+      Some("RECEIPTS_SEEN_RUN_IDS_CAP"),
+      local.seen_run_ids_cap.is_some(),
+    ),
+    (
+      "notified_run_ids_cap",
+      Some("RECEIPTS_NOTIFIED_RUN_IDS_CAP"),
+      local.notified_run_ids_cap.is_some(),
+    ),
+    (
+      "update_check_hours",
+      Some("RECEIPTS_UPDATE_CHECK_HOURS"),
+      local.update_check_hours.is_some(),
+    ),
+    (
+      "notifications_enabled",
+      None,
+      local.notifications_enabled.is_some(),
+    ),
+    ("notify_warnings", None, local.notify_warnings.is_some()),
+    ("quiet_hours_start", None, local.quiet_hours_start.is_some()),
+    ("quiet_hours_end", None, local.quiet_hours_end.is_some()),
+    ("minimize_to_tray", None, local.minimize_to_tray.is_some()),
+    (
+      "store_notifications",
+      None,
+      !local.store_notifications.is_empty(),
+    ),
+    ("currency_rates", None, !local.currency_rates.is_empty()),
+    ("api_enabled", None, local.api_enabled.is_some()),
+    ("api_port", None, local.api_port.is_some()),
+    ("webhooks", None, !local.webhooks.is_empty()),
+    ("budgets", None, local.budgets != Budgets::default()),
+    ("summary_dir", None, local.summary_dir.is_some()),
+    (
+      "publish_summary_after_run",
+      None,
+      local.publish_summary_after_run.is_some(),
+    ),
+  ];
+  layers
+    .into_iter()
+    .map(|(key, env, in_file)| ConfigOrigin {
+      key,
+      env,
+      origin: if env.and_then(env_var).is_some() {
+        "env"
+      } else if in_file {
+        "config.json"
+      } else {
+        "default"
+      },
+    })
+    .collect()
+}
+
+pub(crate) fn read_app_config(package_env: Option<(&PackageInfo, &Env)>) -> AppConfig {
+  let paths = app_paths();
+  let local = load_local_config();
+  let receipts_root = env_var("RECEIPTS_ROOT")
+    .or(local.receipts_root)
+    .or_else(|| default_receipts_root(&paths))
+    .unwrap_or_default();
+  let worker_dir = env_var("WORKER_DIR")
+    .or(local.worker_dir)
+    .or_else(default_worker_dir);
+  let worker_run_cmd = env_var("WORKER_RUN_CMD")
+    .or(local.worker_run_cmd)
+    .or_else(|| default_worker_cmd(&worker_dir));
+  let import_name_template = env_var("RECEIPTS_IMPORT_TEMPLATE")
+    .or(local.import_name_template)
+    .unwrap_or_else(|| DEFAULT_IMPORT_NAME_TEMPLATE.to_string());
+  let worker_supports_file_args = env_var("WORKER_FILE_ARGS")
+    .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+    .or(local.worker_supports_file_args)
+    .unwrap_or(false);
+  let backlog_warn_count = env_var("RECEIPTS_BACKLOG_WARN_COUNT")
+    .and_then(|value| value.parse().ok())
+    .or(local.backlog_warn_count)
+    .or(Some(DEFAULT_BACKLOG_WARN_COUNT))
+    .filter(|count| *count > 0);
+  let backlog_warn_days = env_var("RECEIPTS_BACKLOG_WARN_DAYS")
+    .and_then(|value| value.parse().ok())
+    .or(local.backlog_warn_days)
+    .or(Some(DEFAULT_BACKLOG_WARN_DAYS))
+    .filter(|days| *days > 0);
+  let inbox_cache_ttl_secs = env_var("RECEIPTS_INBOX_CACHE_TTL")
+    .and_then(|value| value.parse().ok())
+    .or(local.inbox_cache_ttl_secs)
+    .unwrap_or(DEFAULT_INBOX_CACHE_TTL_SECS);
+  let badge_window_days = env_var("RECEIPTS_BADGE_WINDOW_DAYS")
+    .and_then(|value| value.parse().ok())
+    .or(local.badge_window_days)
+    .or(Some(DEFAULT_BADGE_WINDOW_DAYS))
+    .filter(|days| *days > 0);
+  let badge_metric = env_var("RECEIPTS_BADGE_METRIC")
+    .or(local.badge_metric)
+    .filter(|metric| BADGE_METRICS.contains(&metric.as_str()))
+    .unwrap_or_else(|| BADGE_METRICS[0].to_string());
+  let seen_run_ids_cap = env_var("RECEIPTS_SEEN_RUN_IDS_CAP")
+    .and_then(|value| value.parse().ok())
+    .or(local.seen_run_ids_cap)
+    .filter(|cap| *cap > 0)
+    .unwrap_or(DEFAULT_SEEN_RUN_IDS_CAP);
+  let notified_run_ids_cap = env_var("RECEIPTS_NOTIFIED_RUN_IDS_CAP")
+    .and_then(|value| value.parse().ok())
+    .or(local.notified_run_ids_cap)
+    .filter(|cap| *cap > 0)
+    .unwrap_or(DEFAULT_NOTIFIED_RUN_IDS_CAP);
+  let update_check_hours = env_var("RECEIPTS_UPDATE_CHECK_HOURS")
+    .and_then(|value| value.parse().ok())
+    .or(local.update_check_hours)
+    .or(Some(DEFAULT_UPDATE_CHECK_HOURS))
+    .filter(|hours| *hours > 0);
+  let mut stores = load_stores_config(package_env);
+  for store in &mut stores {
+    if let Some(enabled) = local.store_notifications.get(&store.id) {
+      store.notifications_enabled = Some(*enabled);
+    }
+  }
+  let receipts_ok = Path::new(receipts_root.trim()).exists();
+  let worker_dir_ok = worker_dir
+    .as_deref()
+    .map(|path| Path::new(path).exists())
+    .unwrap_or(false);
+  let worker_cmd_ok = worker_run_cmd
+    .as_deref()
+    .map(|path| Path::new(path).exists())
+    .unwrap_or(false);
+  let config_ready = receipts_ok && (worker_cmd_ok || worker_dir_ok);
+
+  let mut config = AppConfig {
+    receipts_root,
+    worker_dir,
+    worker_run_cmd,
+    import_name_template,
+    worker_supports_file_args,
+    backlog_warn_count,
+    backlog_warn_days,
+    inbox_cache_ttl_secs,
+    badge_window_days,
+    badge_metric,
+    notifications_enabled: local.notifications_enabled.unwrap_or(true),
+    notify_warnings: local.notify_warnings.unwrap_or(false),
+    quiet_hours_start: local.quiet_hours_start,
+    quiet_hours_end: local.quiet_hours_end,
+    minimize_to_tray: local.minimize_to_tray.unwrap_or(true),
+    seen_run_ids_cap,
+    notified_run_ids_cap,
+    update_check_hours,
+    currency_rates: normalize_currency_rates(local.currency_rates),
+    api_enabled: local.api_enabled.unwrap_or(false),
+    api_port: local.api_port.unwrap_or(DEFAULT_API_PORT),
+    webhooks: normalize_webhooks(local.webhooks),
+    budgets: normalize_budgets(local.budgets, &stores),
+    summary_dir: local
+      .summary_dir
+      .map(|dir| dir.trim().to_string())
+      .filter(|dir| !dir.is_empty()),
+    publish_summary_after_run: local.publish_summary_after_run.unwrap_or(false),
+    stores,
+    config_ready,
+    home_available: paths.home.is_some(),
+    root_override: None,
+    resolved_roots: None,
+  };
+  config.resolve_roots();
+  config
+}
+
+pub(crate) fn default_source_dir() -> Option<PathBuf> {
+  let home = home_dir()?;
+  Some(
+    home
+      .join("Documents")
+      .join("Github repos")
+      .join("life-dashboard")
+      .join("apps")
+      .join("receipts-desktop"),
+  )
+}
+
+pub(crate) fn resolve_source_dir() -> Option<PathBuf> {
+  if let Some(custom) = env_var("RECEIPTS_APP_SOURCE") {
+    let path = PathBuf::from(custom);
+    if path.exists() {
+      return Some(path);
+    }
+  }
+  let fallback = default_source_dir();
+  if let Some(path) = fallback.clone() {
+    if path.exists() {
+      return Some(path);
+    }
+  }
+  None
+}
+
+pub(crate) fn config_file_path() -> Option<PathBuf> {
+  state_path("config.json")
+}
+
+pub(crate) fn load_local_config() -> LocalConfig {
+  let path = match config_file_path() {
+    Some(path) => path,
+    None => return LocalConfig::default(),
+  };
+  if let Ok(raw) = fs::read_to_string(path) {
+    if let Ok(config) = serde_json::from_str::<LocalConfig>(&raw) {
+      return config;
+    }
+  }
+  LocalConfig::default()
+}
+
+// Loaded once in setup; `refresh_config` swaps in a fresh read after a settings
+// change or `reload_config`.
+pub(crate) struct ConfigStore(pub(crate) RwLock<AppConfig>);
+
+impl ConfigStore {
+  pub(crate) fn get(&self) -> AppConfig {
+    match self.0.read() {
+      Ok(config) => config.clone(),
+      Err(poisoned) => poisoned.into_inner().clone(),
+    }
+  }
+}
+
+pub(crate) fn current_config<R: tauri::Runtime>(manager: &impl Manager<R>) -> AppConfig {
+  manager.state::<ConfigStore>().get()
+}
+
+pub(crate) fn refresh_config<R: tauri::Runtime>(app: &tauri::AppHandle<R>) -> AppConfig {
+  let config = read_app_config(Some((app.package_info(), &app.env())));
+  if let Ok(mut current) = app.state::<ConfigStore>().0.write() {
+    *current = config.clone();
+  }
+  let _ = app.tray_handle().set_menu(tray_menu(&config));
+  let _ = app.emit_all("config-changed", &config);
+  app.state::<DashboardScanner>().request();
+  config
+}
+
+pub(crate) fn save_local_config(config: &LocalConfig) -> Result<(), String> {
+  let path = config_file_path().ok_or_else(|| AppError::HomeUnavailable.message())?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+  }
+  let raw = serde_json::to_string_pretty(config).map_err(|err| err.to_string())?;
+  fs::write(path, raw).map_err(|err| err.to_string())?;
+  Ok(())
+}
+
+// An empty receipts_root means none was set and there was no home directory
+// to put the default under.
+pub(crate) fn require_receipts_root(config: &AppConfig) -> Result<&str, AppError> {
+  match config.receipts_root.trim() {
+    "" if !config.home_available => Err(AppError::HomeUnavailable),
+    "" => Err(AppError::ConfigMissing(
+      "RECEIPTS_ROOT is not set".to_string(),
+    )),
+    root => Ok(root),
+  }
+}
+
+// Store folders all live under receipts_root, so a missing root fails here too.
+pub(crate) fn require_store(config: &AppConfig, store_id: &str) -> Result<(), AppError> {
+  require_receipts_root(config)?;
+  if config.stores.iter().any(|store| store.id == store_id) {
+    Ok(())
+  } else {
+    Err(AppError::UnknownStore(store_id.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn root_override_scopes_seen_state_and_inbox_cache() {
+    let roots = [2, 3].map(|count| {
+      let dir = tempfile::tempdir().expect("tempdir");
+      let inbox = dir.path().join("inbox").join("lidl");
+      fs::create_dir_all(&inbox).expect("inbox");
+      for index in 0..count {
+        fs::write(inbox.join(format!("receipt-{}.pdf", index)), b"%PDF-1.4").expect("receipt");
+      }
+      dir
+    });
+    let [configured, other] = &roots;
+    let mut config = badge_config("lidl");
+    config.receipts_root = configured.path().to_string_lossy().to_string();
+
+    assert!(matches!(
+      config.clone().with_root(Some(" ".to_string())),
+      Err(AppError::InvalidInput(_))
+    ));
+    let missing = other.path().join("missing").to_string_lossy().to_string();
+    assert!(matches!(
+      config.clone().with_root(Some(missing)),
+      Err(AppError::PathNotFound(_))
+    ));
+    let same = config
+      .clone()
+      .with_root(Some(config.receipts_root.clone()))
+      .expect("configured root");
+    assert!(same.root_override.is_none());
+    assert_eq!(same.seen_key("lidl"), "lidl");
+
+    let dotted = other.path().join("inbox").join("..");
+    let scoped = config
+      .clone()
+      .with_root(Some(dotted.to_string_lossy().to_string()))
+      .expect("override root");
+    let canonical = fs::canonicalize(other.path()).unwrap();
+    assert_eq!(scoped.receipts_root, canonical.to_string_lossy());
+    let key = scoped.seen_key("lidl");
+    assert_eq!(seen_key_store(&key), "lidl");
+    assert_ne!(key, "lidl");
+
+    let cache = InboxCountCache::default();
+    assert_eq!(
+      cached_inbox_stats(&cache, &config, "lidl", false).0.count,
+      2
+    );
+    assert_eq!(
+      cached_inbox_stats(&cache, &scoped, "lidl", false).0.count,
+      3
+    );
+    assert!(cached_inbox_stats(&cache, &config, "lidl", false).1);
+
+    let mut state = SeenState::default();
+    let issue_runs = issue_fixture("lidl");
+    let seen = state.stores.entry(key.clone()).or_default();
+    mark_store_seen(seen, &issue_runs["lidl"], None, DEFAULT_SEEN_RUN_IDS_CAP).expect("mark seen");
+    assert!(!unread_badges(&scoped, &issue_runs, &state)[0].failures_unread);
+    assert!(unread_badges(&config, &issue_runs, &state)[0].failures_unread);
+
+    assert!(prune_orphaned_stores(&mut state, &config, APP_VERSION).is_empty());
+    assert!(state.stores.contains_key(&key));
+  }
+}
@@ -0,0 +1,161 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct CrashReport {
+  pub(crate) name: String,
+  pub(crate) message: String,
+  pub(crate) contents: String,
+}
+
+pub(crate) fn crashes_dir() -> Option<PathBuf> {
+  state_path(CRASHES_DIR)
+}
+
+// Writes a report for every panic, on any thread, then hands over to the
+// default hook. It deliberately doesn't log: the panic may have happened while
+// the log writer was locked.
+pub(crate) fn install_panic_hook() {
+  let default_hook = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    let payload = info.payload();
+    let message = payload
+      .downcast_ref::<&str>()
+      .map(|message| message.to_string())
+      .or_else(|| payload.downcast_ref::<String>().cloned())
+      .unwrap_or_else(|| "unknown panic payload".to_string());
+    let location = info
+      .location()
+      .map(|location| format!("{}:{}", location.file(), location.line()))
+      .unwrap_or_default();
+    let log_lines = app_logs_dir()
+      .map(|dir| tail_app_logs(&dir, CRASH_LOG_LINES, 0))
+      .unwrap_or_default();
+    let report = format_crash_report(
+      &message,
+      &location,
+      thread::current().name().unwrap_or("unnamed"),
+      &std::backtrace::Backtrace::force_capture().to_string(),
+      &log_lines,
+    );
+    if let Some(dir) = crashes_dir() {
+      if let Err(err) = write_crash_report(&dir, &report, chrono::Utc::now()) {
+        eprintln!("could not write crash report to {}: {}", dir.display(), err);
+      }
+    }
+    default_hook(info);
+  }));
+}
+
+pub(crate) fn format_crash_report(
+  message: &str,
+  location: &str,
+  thread_name: &str,
+  backtrace: &str,
+  log_lines: &[String],
+) -> String {
+  format!(
+    "Receipts Operator {} crashed at {}\nMessage: {}\nLocation: {}\nThread: {}\n\n\
+     Backtrace:\n{}\n\nLast {} app log lines:\n{}\n",
+    APP_VERSION,
+    chrono::Utc::now().to_rfc3339(),
+    message,
+    location,
+    thread_name,
+    backtrace.trim_end(),
+    log_lines.len(),
+    log_lines.join("\n")
+  )
+}
+
+// Keeps the newest CRASH_REPORTS_KEEP reports; names sort by time.
+pub(crate) fn write_crash_report(
+  dir: &Path,
+  report: &str,
+  now: chrono::DateTime<chrono::Utc>,
+) -> std::io::Result<PathBuf> {
+  fs::create_dir_all(dir)?;
+  let path = dir.join(format!("crash-{}.txt", now.format("%Y%m%dT%H%M%S%3fZ")));
+  fs::write(&path, report)?;
+  let names = crash_report_names(dir);
+  for name in names.iter().skip(CRASH_REPORTS_KEEP) {
+    let _ = fs::remove_file(dir.join(name));
+  }
+  Ok(path)
+}
+
+// Newest first.
+pub(crate) fn crash_report_names(dir: &Path) -> Vec<String> {
+  let mut names = fs::read_dir(dir)
+    .map(|entries| {
+      entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .filter(|name| is_crash_report_name(name))
+        .collect::<Vec<_>>()
+    })
+    .unwrap_or_default();
+  names.sort_by(|a, b| b.cmp(a));
+  names
+}
+
+pub(crate) fn is_crash_report_name(name: &str) -> bool {
+  name.starts_with("crash-")
+    && name.ends_with(".txt")
+    && !name.contains(['/', '\\'])
+    && !name.contains("..")
+}
+
+pub(crate) fn read_crash_reports(dir: &Path) -> Vec<CrashReport> {
+  crash_report_names(dir)
+    .into_iter()
+    .filter_map(|name| {
+      let contents = fs::read_to_string(dir.join(&name)).ok()?;
+      let message = contents
+        .lines()
+        .find_map(|line| line.strip_prefix("Message: "))
+        .unwrap_or("")
+        .to_string();
+      Some(CrashReport {
+        name,
+        message,
+        contents,
+      })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn crash_reports_are_written_listed_capped_and_named_safely() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let crashes = dir.path().join(CRASHES_DIR);
+    let report = format_crash_report(
+      "index out of bounds",
+      "src/inbox.rs:42",
+      "scanner",
+      "0: main",
+      &["INFO started".to_string()],
+    );
+    assert!(report.contains(APP_VERSION));
+    assert!(report.contains("Last 1 app log lines:\nINFO started"));
+
+    let start = chrono::Utc::now();
+    for offset in 0..(CRASH_REPORTS_KEEP as i64 + 2) {
+      write_crash_report(&crashes, &report, start + chrono::Duration::seconds(offset))
+        .expect("write report");
+    }
+    fs::write(crashes.join("notes.txt"), "not a report").expect("junk");
+    let reports = read_crash_reports(&crashes);
+    assert_eq!(reports.len(), CRASH_REPORTS_KEEP);
+    assert!(reports[0].name > reports[1].name);
+    assert_eq!(reports[0].message, "index out of bounds");
+
+    assert!(is_crash_report_name(&reports[0].name));
+    assert!(!is_crash_report_name("notes.txt"));
+    assert!(!is_crash_report_name("crash-../../state.txt"));
+    assert!(!is_crash_report_name("crash-a/b.txt"));
+  }
+}
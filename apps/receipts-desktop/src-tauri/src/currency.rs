@@ -0,0 +1,83 @@
+use crate::*;
+
+pub(crate) type CurrencyRates = std::collections::BTreeMap<String, f64>;
+// Currency -> amount; never summed across keys.
+pub(crate) type CurrencyTotals = std::collections::BTreeMap<String, f64>;
+
+// How aggregates treat currencies: by default every currency keeps its own
+// total. With a target, amounts in currencies that have a rate are converted
+// to it; the rest (and UNKNOWN_CURRENCY) still stay in their own bucket.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Conversion {
+  pub(crate) target: Option<String>,
+  pub(crate) rates: CurrencyRates,
+}
+
+impl Conversion {
+  // `convert_to` needs a rate of its own in `currency_rates`.
+  pub(crate) fn new(config: &AppConfig, convert_to: Option<&str>) -> Result<Self, AppError> {
+    let target = convert_to
+      .map(currency_code)
+      .filter(|target| !target.is_empty());
+    if let Some(target) = &target {
+      if !config.currency_rates.contains_key(target) {
+        return Err(AppError::InvalidInput(format!(
+          "No rate for {} in currency_rates",
+          target
+        )));
+      }
+    }
+    Ok(Conversion {
+      target,
+      rates: config.currency_rates.clone(),
+    })
+  }
+
+  // The bucket `amount` in `currency` lands in, and the amount in it.
+  pub(crate) fn apply(&self, currency: &str, amount: f64) -> (String, f64) {
+    let target = match &self.target {
+      Some(target) if target != currency => target,
+      _ => return (currency.to_string(), amount),
+    };
+    match (self.rates.get(currency), self.rates.get(target)) {
+      (Some(from), Some(to)) => (target.clone(), amount * from / to),
+      _ => (currency.to_string(), amount),
+    }
+  }
+
+  // Part of cache keys, so a changed rate or target isn't served stale.
+  pub(crate) fn cache_key(&self) -> String {
+    format!("{:?}:{:?}", self.target, self.rates)
+  }
+}
+
+pub(crate) fn currency_code(raw: &str) -> String {
+  raw.trim().to_uppercase()
+}
+
+// Codes are uppercased; rates that aren't positive numbers are dropped.
+pub(crate) fn normalize_currency_rates(rates: CurrencyRates) -> CurrencyRates {
+  rates
+    .into_iter()
+    .map(|(currency, rate)| (currency_code(&currency), rate))
+    .filter(|(currency, rate)| !currency.is_empty() && rate.is_finite() && *rate > 0.0)
+    .collect()
+}
+
+pub(crate) fn receipt_currency(receipt: &ReceiptTotal) -> String {
+  receipt
+    .currency
+    .clone()
+    .unwrap_or_else(|| UNKNOWN_CURRENCY.to_string())
+}
+
+pub(crate) fn add_amount(totals: &mut CurrencyTotals, currency: String, amount: f64) {
+  *totals.entry(currency).or_default() += amount;
+}
+
+pub(crate) fn rounded_totals(totals: CurrencyTotals) -> CurrencyTotals {
+  totals
+    .into_iter()
+    .map(|(currency, total)| (currency, round_cents(total)))
+    .collect()
+}
@@ -0,0 +1,185 @@
+use crate::*;
+
+// Where a `receipts://` link points.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Destination {
+  Store { store_id: String },
+  Run { run_id: String },
+}
+
+// A handled link: emitted as `navigate` (the destination) or
+// `deep-link-warning` (url, code, message).
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum DeepLinkOutcome {
+  Navigate {
+    destination: Destination,
+  },
+  Warning {
+    url: String,
+    code: String,
+    message: String,
+  },
+}
+
+// Links that arrive before the webview has registered its listeners (the one
+// that launched the app, usually) wait here until `take_pending_deep_links`.
+// None once the frontend is ready.
+pub(crate) struct DeepLinkQueue(pub(crate) Mutex<Option<Vec<DeepLinkOutcome>>>);
+
+impl Default for DeepLinkQueue {
+  fn default() -> Self {
+    DeepLinkQueue(Mutex::new(Some(Vec::new())))
+  }
+}
+
+// `receipts://store/<store id>` or `receipts://run/<run id>`; the scheme is
+// case-insensitive, a trailing slash, query or fragment is ignored.
+pub(crate) fn parse_deep_link(url: &str) -> Result<Destination, String> {
+  let prefix = format!("{}://", DEEP_LINK_SCHEME);
+  let rest = match url.trim().get(..prefix.len()) {
+    Some(scheme) if scheme.eq_ignore_ascii_case(&prefix) => &url.trim()[prefix.len()..],
+    _ => return Err(format!("Not a {} link: {}", prefix, url)),
+  };
+  let path = rest.split(['?', '#']).next().unwrap_or("");
+  let parts = path.trim_end_matches('/').split('/').collect::<Vec<_>>();
+  match parts.as_slice() {
+    ["store", id] if is_link_id(id) => Ok(Destination::Store {
+      store_id: id.to_string(),
+    }),
+    ["run", id] if is_link_id(id) => Ok(Destination::Run {
+      run_id: id.to_string(),
+    }),
+    _ => Err(format!("Unrecognized link: {}", url)),
+  }
+}
+
+pub(crate) fn is_link_id(id: &str) -> bool {
+  !id.is_empty()
+    && !id.starts_with('.')
+    && id
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+// Parses the link and checks that its store is configured or its run has a
+// summary.
+pub(crate) fn resolve_deep_link(config: &AppConfig, url: &str) -> Result<Destination, AppError> {
+  let destination = parse_deep_link(url).map_err(AppError::InvalidInput)?;
+  match &destination {
+    Destination::Store { store_id } => require_store(config, store_id)?,
+    Destination::Run { run_id } => {
+      require_receipts_root(config)?;
+      find_run_summary(&config.receipts_root, run_id)
+        .ok_or_else(|| AppError::UnknownRun(run_id.clone()))?;
+    }
+  }
+  Ok(destination)
+}
+
+// The first launch argument that is a `receipts://` link; Windows and Linux
+// pass links this way, macOS through the deep-link plugin.
+pub(crate) fn deep_link_arg(args: impl IntoIterator<Item = String>) -> Option<String> {
+  let prefix = format!("{}://", DEEP_LINK_SCHEME);
+  args.into_iter().find(|arg| {
+    arg
+      .get(..prefix.len())
+      .is_some_and(|scheme| scheme.eq_ignore_ascii_case(&prefix))
+  })
+}
+
+pub(crate) fn handle_deep_link(app: &tauri::AppHandle, url: &str) {
+  show_main_window(app);
+  let outcome = match resolve_deep_link(&current_config(app), url) {
+    Ok(destination) => {
+      info!("deep link {} -> {:?}", url, destination);
+      DeepLinkOutcome::Navigate { destination }
+    }
+    Err(err) => {
+      warn!("deep link {}: {}", url, err.message());
+      DeepLinkOutcome::Warning {
+        url: url.to_string(),
+        code: err.code().to_string(),
+        message: err.message(),
+      }
+    }
+  };
+  if let Ok(mut pending) = app.state::<DeepLinkQueue>().0.lock() {
+    if let Some(pending) = pending.as_mut() {
+      pending.push(outcome);
+      return;
+    }
+  }
+  emit_deep_link(app, &outcome);
+}
+
+pub(crate) fn emit_deep_link(app: &tauri::AppHandle, outcome: &DeepLinkOutcome) {
+  let _ = match outcome {
+    DeepLinkOutcome::Navigate { destination } => app.emit_all("navigate", destination),
+    DeepLinkOutcome::Warning { .. } => app.emit_all("deep-link-warning", outcome),
+  };
+}
+
+pub(crate) fn register_deep_links(app: &tauri::AppHandle) {
+  let handle = app.clone();
+  if let Err(err) =
+    tauri_plugin_deep_link::register(DEEP_LINK_SCHEME, move |url| handle_deep_link(&handle, &url))
+  {
+    warn!("could not register {}:// links: {}", DEEP_LINK_SCHEME, err);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn deep_links_parse_validate_and_come_from_launch_args() {
+    assert_eq!(
+      parse_deep_link("receipts://store/lidl"),
+      Ok(Destination::Store {
+        store_id: "lidl".to_string()
+      })
+    );
+    assert_eq!(
+      parse_deep_link("RECEIPTS://run/20240101-093000/?from=mail#top"),
+      Ok(Destination::Run {
+        run_id: "20240101-093000".to_string()
+      })
+    );
+    for bad in [
+      "https://store/lidl",
+      "receipts://store/",
+      "receipts://store/lidl/extra",
+      "receipts://run/../state",
+      "receipts://run/a%2Fb",
+      "receipts://settings",
+    ] {
+      assert!(parse_deep_link(bad).is_err(), "{}", bad);
+    }
+
+    let dir = runs_fixture(&[(
+      "20240101-093000.summary.json",
+      r#"{"run_id": "20240101-093000", "stores": ["lidl"]}"#,
+    )]);
+    let mut config = badge_config("lidl");
+    config.receipts_root = dir.path().to_string_lossy().to_string();
+    assert!(resolve_deep_link(&config, "receipts://store/lidl").is_ok());
+    assert!(resolve_deep_link(&config, "receipts://run/20240101-093000").is_ok());
+    let err = resolve_deep_link(&config, "receipts://store/aldi").unwrap_err();
+    assert_eq!(err.code(), "unknown_store");
+    let err = resolve_deep_link(&config, "receipts://run/20990101-000000").unwrap_err();
+    assert_eq!(err.code(), "unknown_run");
+    let err = resolve_deep_link(&config, "receipts://nowhere").unwrap_err();
+    assert_eq!(err.code(), "invalid_input");
+
+    let args = ["--minimized", "receipts://store/lidl"].map(str::to_string);
+    assert_eq!(
+      deep_link_arg(args),
+      Some("receipts://store/lidl".to_string())
+    );
+    assert_eq!(deep_link_arg(["--minimized".to_string()]), None);
+  }
+}
@@ -0,0 +1,285 @@
+use crate::*;
+
+// Everything needed to debug an install remotely, in one document. Env values
+// and config fields that look like secrets are redacted.
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct Diagnostics {
+  pub(crate) generated_at: String,
+  pub(crate) app_version: String,
+  pub(crate) platform: PlatformInfo,
+  pub(crate) home_dir: Option<String>,
+  pub(crate) state_dir: Option<String>,
+  pub(crate) config: Value,
+  pub(crate) config_origins: Vec<ConfigOrigin>,
+  pub(crate) config_file: FileStatus,
+  pub(crate) stores_file: Option<String>,
+  pub(crate) stores_candidates: Vec<String>,
+  pub(crate) state_file: FileStatus,
+  pub(crate) worker: Option<WorkerLaunch>,
+  // Why no worker could be resolved, or what `run_worker` would refuse over.
+  pub(crate) worker_error: Option<String>,
+  pub(crate) source_dir: SourceDirResolution,
+  pub(crate) directories: Vec<DirectoryStatus>,
+  pub(crate) environment: Value,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PlatformInfo {
+  pub(crate) os: &'static str,
+  pub(crate) family: &'static str,
+  pub(crate) arch: &'static str,
+}
+
+// `status` is `ok`, `missing`, `unreadable` or `invalid` (not the expected JSON).
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct FileStatus {
+  pub(crate) path: Option<String>,
+  pub(crate) status: &'static str,
+  pub(crate) error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct SourceDirResolution {
+  pub(crate) env: Option<String>,
+  pub(crate) default: Option<String>,
+  pub(crate) resolved: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct DirectoryStatus {
+  pub(crate) name: String,
+  pub(crate) path: String,
+  pub(crate) exists: bool,
+  pub(crate) is_dir: bool,
+  pub(crate) readonly: Option<bool>,
+  // Whether its entries could be listed; None when it isn't a directory.
+  pub(crate) readable: Option<bool>,
+}
+
+pub(crate) fn platform_info() -> PlatformInfo {
+  PlatformInfo {
+    os: std::env::consts::OS,
+    family: std::env::consts::FAMILY,
+    arch: std::env::consts::ARCH,
+  }
+}
+
+pub(crate) fn json_file_status<T: serde::de::DeserializeOwned>(
+  path: Option<PathBuf>,
+) -> FileStatus {
+  let path = match path {
+    Some(path) => path,
+    None => {
+      return FileStatus {
+        path: None,
+        status: "missing",
+        error: Some(AppError::HomeUnavailable.message()),
+      }
+    }
+  };
+  let (status, error) = match fs::read_to_string(&path) {
+    Ok(raw) => match serde_json::from_str::<T>(&raw) {
+      Ok(_) => ("ok", None),
+      Err(err) => ("invalid", Some(err.to_string())),
+    },
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => ("missing", None),
+    Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+      ("permission_denied", Some(err.to_string()))
+    }
+    Err(err) => ("unreadable", Some(err.to_string())),
+  };
+  FileStatus {
+    path: Some(path.to_string_lossy().to_string()),
+    status,
+    error,
+  }
+}
+
+pub(crate) fn directory_status(name: &str, path: &Path) -> DirectoryStatus {
+  let metadata = fs::metadata(path).ok();
+  DirectoryStatus {
+    name: name.to_string(),
+    path: path.to_string_lossy().to_string(),
+    exists: metadata.is_some(),
+    is_dir: metadata.as_ref().is_some_and(|meta| meta.is_dir()),
+    readonly: metadata.as_ref().map(|meta| meta.permissions().readonly()),
+    readable: metadata
+      .filter(|meta| meta.is_dir())
+      .map(|_| fs::read_dir(path).is_ok()),
+  }
+}
+
+// The app's own folders, the worker and source checkouts, and every folder the
+// worker expects under the receipts root.
+pub(crate) fn expected_directories(
+  config: &AppConfig,
+  paths: &AppPaths,
+  source_dir: Option<&Path>,
+) -> Vec<DirectoryStatus> {
+  let mut dirs = Vec::new();
+  if let Some(state_dir) = &paths.state_dir {
+    dirs.push(directory_status("state", state_dir));
+    dirs.push(directory_status("app_logs", &state_dir.join(APP_LOGS_DIR)));
+    dirs.push(directory_status("crashes", &state_dir.join(CRASHES_DIR)));
+    dirs.push(directory_status(
+      "update_logs",
+      &state_dir.join(UPDATE_LOGS_DIR),
+    ));
+  }
+  if let Some(worker_dir) = &config.worker_dir {
+    dirs.push(directory_status("worker", Path::new(worker_dir)));
+  }
+  if let Some(source_dir) = source_dir {
+    dirs.push(directory_status("source", source_dir));
+  }
+  if let Ok(root) = require_receipts_root(config) {
+    let root = Path::new(root);
+    dirs.push(directory_status("receipts_root", root));
+    for dir in expected_dirs(root, &config.stores) {
+      let name = dir
+        .strip_prefix(root)
+        .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_default();
+      dirs.push(directory_status(&name, &dir));
+    }
+  }
+  dirs
+}
+
+// The env vars the app reads (RECEIPTS_*, WORKER_*), redacted like logged
+// command arguments.
+pub(crate) fn app_environment(vars: impl IntoIterator<Item = (String, String)>) -> Value {
+  let map = vars
+    .into_iter()
+    .filter(|(key, _)| key.starts_with("RECEIPTS_") || key.starts_with("WORKER_"))
+    .map(|(key, value)| (key, Value::from(value)))
+    .collect::<serde_json::Map<_, _>>();
+  redact_secrets(&Value::Object(map))
+}
+
+pub(crate) fn collect_diagnostics(app: &tauri::AppHandle) -> Diagnostics {
+  let config = current_config(app);
+  let paths = app_paths();
+  let (_, stores_file) = load_stores_config_with_source(Some((app.package_info(), &app.env())));
+  let stores_candidates = stores_config_candidates(Some((app.package_info(), &app.env())));
+  let source_dir = SourceDirResolution {
+    env: env_var("RECEIPTS_APP_SOURCE"),
+    default: default_source_dir().map(|dir| dir.to_string_lossy().to_string()),
+    resolved: resolve_source_dir().map(|dir| dir.to_string_lossy().to_string()),
+  };
+  let worker = resolve_worker_launch(&config);
+  let directories = expected_directories(
+    &config,
+    &paths,
+    source_dir.resolved.as_deref().map(Path::new),
+  );
+  Diagnostics {
+    generated_at: chrono::Utc::now().to_rfc3339(),
+    app_version: APP_VERSION.to_string(),
+    platform: platform_info(),
+    home_dir: paths
+      .home
+      .as_ref()
+      .map(|home| home.to_string_lossy().to_string()),
+    state_dir: paths
+      .state_dir
+      .as_ref()
+      .map(|dir| dir.to_string_lossy().to_string()),
+    config: redact_secrets(&serde_json::to_value(&config).unwrap_or(Value::Null)),
+    config_origins: config_origins(&load_local_config()),
+    config_file: json_file_status::<LocalConfig>(config_file_path()),
+    stores_file: stores_file.map(|path| path.to_string_lossy().to_string()),
+    stores_candidates: stores_candidates
+      .iter()
+      .map(|path| path.to_string_lossy().to_string())
+      .collect(),
+    state_file: json_file_status::<SeenState>(state_path(STATE_FILE)),
+    worker_error: worker
+      .as_ref()
+      .err()
+      .cloned()
+      .or_else(|| worker_config_error(&config)),
+    worker: worker.ok(),
+    source_dir,
+    directories,
+    environment: app_environment(std::env::vars()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn diagnostics_pieces_report_files_dirs_worker_and_redacted_env() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let good = dir.path().join("config.json");
+    fs::write(&good, r#"{"minimize_to_tray": false}"#).expect("config");
+    let bad = dir.path().join("state.json");
+    fs::write(&bad, "{").expect("state");
+    assert_eq!(json_file_status::<LocalConfig>(Some(good)).status, "ok");
+    let invalid = json_file_status::<SeenState>(Some(bad));
+    assert_eq!(invalid.status, "invalid");
+    assert!(invalid.error.is_some());
+    let missing = json_file_status::<SeenState>(Some(dir.path().join("nope.json")));
+    assert_eq!((missing.status, missing.error), ("missing", None));
+
+    let local = LocalConfig {
+      minimize_to_tray: Some(false),
+      ..LocalConfig::default()
+    };
+    let origins = config_origins(&local);
+    let origin = |key: &str| {
+      origins
+        .iter()
+        .find(|origin| origin.key == key)
+        .map(|origin| origin.origin)
+    };
+    assert_eq!(origin("minimize_to_tray"), Some("config.json"));
+    assert_eq!(origin("quiet_hours_start"), Some("default"));
+
+    let mut config = badge_config("lidl");
+    config.receipts_root = dir.path().to_string_lossy().to_string();
+    fs::create_dir_all(dir.path().join("inbox").join("lidl")).expect("inbox");
+    let paths = AppPaths {
+      home: None,
+      state_dir: Some(dir.path().join("state")),
+    };
+    let dirs = expected_directories(&config, &paths, None);
+    let status = |name: &str| dirs.iter().find(|dir| dir.name == name).cloned();
+    assert!(status("inbox/lidl").is_some_and(|dir| dir.exists && dir.is_dir));
+    assert!(status("failed/lidl").is_some_and(|dir| !dir.exists && dir.readonly.is_none()));
+    assert!(status("_logs/runs").is_some());
+    assert!(status("state").is_some_and(|dir| !dir.exists));
+
+    config.worker_run_cmd = None;
+    config.worker_dir = Some(dir.path().to_string_lossy().to_string());
+    let launch = resolve_worker_launch(&config).expect("launch");
+    assert_eq!(launch.mode, "python_module");
+    assert_eq!(launch.program, "python3");
+    config.worker_run_cmd = Some("/opt/worker/run.sh".to_string());
+    assert_eq!(
+      resolve_worker_launch(&config).expect("launch").mode,
+      "run_cmd"
+    );
+    config.worker_run_cmd = None;
+    config.worker_dir = None;
+    assert!(resolve_worker_launch(&config).is_err());
+
+    let env = app_environment([
+      ("RECEIPTS_ROOT".to_string(), "/receipts".to_string()),
+      ("WORKER_API_TOKEN".to_string(), "abc".to_string()),
+      ("RECEIPTS_NOTE".to_string(), "sk-live-123".to_string()),
+      ("PATH".to_string(), "/usr/bin".to_string()),
+    ]);
+    assert_eq!(
+      env,
+      serde_json::json!({
+        "RECEIPTS_ROOT": "/receipts",
+        "WORKER_API_TOKEN": "[redacted]",
+        "RECEIPTS_NOTE": "[redacted]",
+      })
+    );
+  }
+}
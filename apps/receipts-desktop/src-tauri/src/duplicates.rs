@@ -0,0 +1,375 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct DuplicateReceipt {
+  pub(crate) file_name: String,
+  pub(crate) date: Option<chrono::NaiveDate>,
+  pub(crate) total: Option<f64>,
+  pub(crate) currency: Option<String>,
+  pub(crate) item_count: usize,
+  // The image/PDF when it is still there, else the JSON output.
+  pub(crate) path: String,
+}
+
+// Receipts of one store that look like the same purchase processed twice.
+// `group_id` is `<store>:<output>|<output>…`, which `mark_not_duplicate` takes.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct DuplicateGroup {
+  pub(crate) group_id: String,
+  pub(crate) store_id: String,
+  // 0..1; the best of the pairs that put the receipts together.
+  pub(crate) confidence: f64,
+  pub(crate) receipts: Vec<DuplicateReceipt>,
+}
+
+// What a processed output contributes to the comparison.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DuplicateCandidate {
+  pub(crate) receipt: ReceiptTotal,
+  // Folded item names, sorted.
+  pub(crate) items: Vec<String>,
+}
+
+// Multiset Jaccard similarity of two sorted name lists.
+pub(crate) fn item_similarity(a: &[String], b: &[String]) -> f64 {
+  if a.is_empty() && b.is_empty() {
+    return 1.0;
+  }
+  let (mut i, mut j, mut common) = (0, 0, 0);
+  while i < a.len() && j < b.len() {
+    match a[i].cmp(&b[j]) {
+      std::cmp::Ordering::Equal => {
+        common += 1;
+        i += 1;
+        j += 1;
+      }
+      std::cmp::Ordering::Less => i += 1,
+      std::cmp::Ordering::Greater => j += 1,
+    }
+  }
+  common as f64 / (a.len() + b.len() - common) as f64
+}
+
+// None when the two can't be the same purchase: different currency or total,
+// more than `days` apart, or item lists that mostly differ. Otherwise 0.5 for
+// store and total, 0.2 more on the same day, and up to 0.3 for matching items
+// (0.15 when either side has none to compare).
+pub(crate) fn duplicate_confidence(
+  a: &DuplicateCandidate,
+  b: &DuplicateCandidate,
+  days: i64,
+) -> Option<f64> {
+  let (date_a, date_b) = (a.receipt.date?, b.receipt.date?);
+  let apart = (date_a - date_b).num_days().abs();
+  let (total_a, total_b) = (a.receipt.total?, b.receipt.total?);
+  if apart > days
+    || a.receipt.currency != b.receipt.currency
+    || (total_a - total_b).abs() > DUPLICATE_TOTAL_TOLERANCE
+  {
+    return None;
+  }
+  let items = if a.items.is_empty() || b.items.is_empty() {
+    0.15
+  } else {
+    let similarity = item_similarity(&a.items, &b.items);
+    if similarity < DUPLICATE_MIN_ITEM_SIMILARITY {
+      return None;
+    }
+    0.3 * similarity
+  };
+  let same_day = if apart == 0 { 0.2 } else { 0.0 };
+  Some(((0.5 + same_day + items) * 100.0).round() / 100.0)
+}
+
+// `a|b` with the names sorted, as kept in `StoreSeenState::not_duplicates`.
+pub(crate) fn duplicate_pair_key(a: &str, b: &str) -> String {
+  if a <= b {
+    format!("{}|{}", a, b)
+  } else {
+    format!("{}|{}", b, a)
+  }
+}
+
+pub(crate) fn duplicate_candidate(store_id: &str, path: &Path) -> Option<DuplicateCandidate> {
+  let raw = fs::read_to_string(path).ok()?;
+  let value = serde_json::from_str::<Value>(&raw).ok()?;
+  let receipt = receipt_total_from(store_id, path, &value)?;
+  let mut items = value
+    .get("items")
+    .and_then(|items| items.as_array())
+    .into_iter()
+    .flatten()
+    .filter_map(|item| item.get("name").and_then(|name| name.as_str()))
+    .map(|name| fold_text(name.trim()))
+    .filter(|name| !name.is_empty())
+    .collect::<Vec<_>>();
+  items.sort();
+  Some(DuplicateCandidate { receipt, items })
+}
+
+// Pairs within `days` of each other (receipts sorted by date, so the window
+// slides), minus dismissed ones, joined into groups.
+pub(crate) fn duplicate_groups(
+  receipts_root: &str,
+  store_id: &str,
+  mut candidates: Vec<DuplicateCandidate>,
+  days: i64,
+  dismissed: &[String],
+) -> Vec<DuplicateGroup> {
+  candidates.retain(|candidate| candidate.receipt.date.is_some());
+  candidates.sort_by(|a, b| {
+    a.receipt
+      .date
+      .cmp(&b.receipt.date)
+      .then_with(|| a.receipt.file_name.cmp(&b.receipt.file_name))
+  });
+  let dates = candidates
+    .iter()
+    .filter_map(|candidate| candidate.receipt.date)
+    .collect::<Vec<_>>();
+  // Union-find over candidate indexes; `best` keeps each root's confidence.
+  let mut parent = (0..candidates.len()).collect::<Vec<_>>();
+  let mut best = vec![0.0_f64; candidates.len()];
+  fn find(parent: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while parent[root] != root {
+      root = parent[root];
+    }
+    parent[index] = root;
+    root
+  }
+  for (i, a) in candidates.iter().enumerate() {
+    for (j, b) in candidates.iter().enumerate().skip(i + 1) {
+      if (dates[j] - dates[i]).num_days() > days {
+        break;
+      }
+      let key = duplicate_pair_key(&a.receipt.file_name, &b.receipt.file_name);
+      if dismissed.contains(&key) {
+        continue;
+      }
+      if let Some(confidence) = duplicate_confidence(a, b, days) {
+        let (root_a, root_b) = (find(&mut parent, i), find(&mut parent, j));
+        parent[root_b] = root_a;
+        best[root_a] = best[root_a].max(best[root_b]).max(confidence);
+      }
+    }
+  }
+  let mut members = std::collections::BTreeMap::<usize, Vec<usize>>::new();
+  for index in 0..candidates.len() {
+    let root = find(&mut parent, index);
+    members.entry(root).or_default().push(index);
+  }
+  let dir = processed_dir(receipts_root, store_id);
+  members
+    .into_iter()
+    .filter(|(_, indexes)| indexes.len() > 1)
+    .map(|(root, indexes)| {
+      let receipts = indexes
+        .iter()
+        .map(|&index| {
+          let candidate = &candidates[index];
+          let receipt = &candidate.receipt;
+          let image = dir.join(receipt_image_name(&receipt.file_name));
+          let path = if image.is_file() {
+            image
+          } else {
+            join_file_id(&dir, &receipt.file_name)
+          };
+          DuplicateReceipt {
+            file_name: receipt.file_name.clone(),
+            date: receipt.date,
+            total: receipt.total,
+            currency: receipt.currency.clone(),
+            item_count: candidate.items.len(),
+            path: path.to_string_lossy().to_string(),
+          }
+        })
+        .collect::<Vec<_>>();
+      let mut names = receipts
+        .iter()
+        .map(|receipt| receipt.file_name.as_str())
+        .collect::<Vec<_>>();
+      names.sort();
+      DuplicateGroup {
+        group_id: format!("{}:{}", store_id, names.join("|")),
+        store_id: store_id.to_string(),
+        confidence: best[root],
+        receipts,
+      }
+    })
+    .collect()
+}
+
+// Reads every processed output; nothing is moved or deleted.
+pub(crate) fn find_duplicates(
+  receipts_root: &str,
+  store_ids: &[String],
+  days: i64,
+  state: &SeenState,
+) -> Vec<DuplicateGroup> {
+  let mut groups = Vec::new();
+  for store_id in store_ids {
+    let dir = processed_dir(receipts_root, store_id);
+    let candidates = receipt_output_names(&dir)
+      .into_iter()
+      .filter_map(|name| duplicate_candidate(store_id, &join_file_id(&dir, &name)))
+      .collect();
+    let dismissed = state
+      .stores
+      .get(store_id)
+      .map(|seen| seen.not_duplicates.as_slice())
+      .unwrap_or_default();
+    groups.extend(duplicate_groups(
+      receipts_root,
+      store_id,
+      candidates,
+      days,
+      dismissed,
+    ));
+  }
+  groups.sort_by(|a, b| {
+    b.confidence
+      .total_cmp(&a.confidence)
+      .then_with(|| a.group_id.cmp(&b.group_id))
+  });
+  groups
+}
+
+// Splits a group id back into its store and output names.
+pub(crate) fn parse_duplicate_group(
+  config: &AppConfig,
+  group_id: &str,
+) -> Result<(String, Vec<String>), AppError> {
+  let invalid = || AppError::InvalidInput(format!("Not a duplicate group id: {}", group_id));
+  let (store_id, names) = group_id.split_once(':').ok_or_else(invalid)?;
+  require_store(config, store_id)?;
+  let names = names.split('|').map(str::to_string).collect::<Vec<_>>();
+  if names.len() < 2 || !names.iter().all(|name| is_receipt_file_name(name)) {
+    return Err(invalid());
+  }
+  Ok((store_id.to_string(), names))
+}
+
+// Records every pair in the group, so none of them is grouped again; returns
+// how many pairs were new.
+pub(crate) fn dismiss_duplicate_pairs(seen: &mut StoreSeenState, names: &[String]) -> usize {
+  let mut added = 0;
+  for (index, a) in names.iter().enumerate() {
+    for b in &names[index + 1..] {
+      let key = duplicate_pair_key(a, b);
+      if !seen.not_duplicates.contains(&key) {
+        seen.not_duplicates.push(key);
+        added += 1;
+      }
+    }
+  }
+  added
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn duplicate_receipts_group_by_total_date_and_items_until_dismissed() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(&processed).expect("processed");
+    let output = |date: &str, total: f64, items: &[&str]| {
+      serde_json::json!({
+        "timestamp": format!("{}T10:00:00", date),
+        "total": total,
+        "currency": "RON",
+        "items": items.iter().map(|name| serde_json::json!({"name": name})).collect::<Vec<_>>(),
+      })
+      .to_string()
+    };
+    for (name, raw) in [
+      (
+        "a.jpg.json",
+        output("2024-01-05", 45.9, &["Lapte", "Pâine", "Ouă"]),
+      ),
+      (
+        "b.jpg.json",
+        output("2024-01-05", 45.9, &["LAPTE", "Paine", "Oua"]),
+      ),
+      ("c.jpg.json", output("2024-01-06", 45.9, &[])),
+      (
+        "e.jpg.json",
+        output("2024-01-20", 45.9, &["Lapte", "Pâine", "Ouă"]),
+      ),
+      ("f.jpg.json", output("2024-01-05", 12.0, &["Lapte"])),
+    ] {
+      fs::write(processed.join(name), raw).expect("output");
+    }
+    fs::write(processed.join("a.jpg"), b"jpg").expect("image");
+    assert_eq!(
+      item_similarity(&["a".into(), "b".into()], &["b".into(), "c".into()]),
+      1.0 / 3.0
+    );
+    // Same store, day and total, different shopping.
+    let candidate = |items: &[&str]| DuplicateCandidate {
+      receipt: ReceiptTotal {
+        store_id: "lidl".to_string(),
+        file_name: "x.jpg.json".to_string(),
+        date: chrono::NaiveDate::from_ymd_opt(2024, 1, 5),
+        date_estimated: false,
+        total: Some(45.9),
+        currency: Some("RON".to_string()),
+      },
+      items: items.iter().map(|item| item.to_string()).collect(),
+    };
+    assert_eq!(
+      duplicate_confidence(
+        &candidate(&["lapte", "oua"]),
+        &candidate(&["mere", "vin"]),
+        1
+      ),
+      None
+    );
+    assert_eq!(
+      duplicate_confidence(&candidate(&["lapte", "oua"]), &candidate(&[]), 1),
+      Some(0.85)
+    );
+
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string()];
+    let mut state = SeenState::default();
+    let groups = find_duplicates(&root, &stores, 1, &state);
+    assert_eq!(groups.len(), 1);
+    let group = &groups[0];
+    assert_eq!(group.group_id, "lidl:a.jpg.json|b.jpg.json|c.jpg.json");
+    assert_eq!(group.confidence, 1.0);
+    assert!(group.receipts[0].path.ends_with("a.jpg"));
+    assert!(group.receipts[1].path.ends_with("b.jpg.json"));
+    assert_eq!(
+      find_duplicates(&root, &stores, 0, &state)[0].receipts.len(),
+      2
+    );
+    assert_eq!(
+      find_duplicates(&root, &stores, 31, &state)[0]
+        .receipts
+        .len(),
+      4
+    );
+
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.clone();
+    let (store_id, names) = parse_duplicate_group(&config, &group.group_id).expect("group id");
+    assert_eq!(
+      dismiss_duplicate_pairs(state.stores.entry(store_id).or_default(), &names),
+      3
+    );
+    assert!(find_duplicates(&root, &stores, 1, &state).is_empty());
+    for bad in [
+      "lidl",
+      "lidl:a.jpg.json",
+      "lidl:../x.json|a.jpg.json",
+      "penny:a|b",
+    ] {
+      assert!(parse_duplicate_group(&config, bad).is_err(), "{}", bad);
+    }
+    assert_eq!(fs::read_dir(&processed).expect("listing").count(), 6);
+  }
+}
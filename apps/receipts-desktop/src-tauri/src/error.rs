@@ -0,0 +1,271 @@
+use crate::*;
+
+// Error returned by every command. It reaches the frontend as
+// `{ code, message, details }`: the UI switches on `code`, shows `message`, and
+// `details` carries the variant's fields. Plain string errors become `Other`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum AppError {
+  ConfigMissing(String),
+  HomeUnavailable,
+  UnknownStore(String),
+  PathNotFound(String),
+  PathNotAllowed(String),
+  // RECEIPTS_ROOT (or the root passed in) didn't answer a probe in time.
+  RootUnreachable(String),
+  PermissionDenied(String),
+  FileExists(String),
+  FileTooLarge {
+    path: String,
+    size: u64,
+    max_bytes: u64,
+  },
+  WorkerNotConfigured(String),
+  WorkerSpawnFailed(String),
+  AlreadyRunning(String),
+  UnknownRun(String),
+  ShortcutUnavailable {
+    action: String,
+    accelerator: String,
+    reason: String,
+  },
+  LogNotFound {
+    run_id: String,
+    path: String,
+  },
+  UnknownApp {
+    app: String,
+    attempted: String,
+    reason: String,
+  },
+  NoTerminalFound(Vec<String>),
+  // Neither an image nor file paths on the clipboard.
+  ClipboardEmpty,
+  Unsupported(String),
+  InvalidInput(String),
+  Io {
+    path: String,
+    reason: String,
+  },
+  Parse {
+    path: Option<String>,
+    reason: String,
+  },
+  Other(String),
+}
+
+impl AppError {
+  pub(crate) fn code(&self) -> &'static str {
+    match self {
+      AppError::ConfigMissing(_) => "config_missing",
+      AppError::HomeUnavailable => "home_unavailable",
+      AppError::UnknownStore(_) => "unknown_store",
+      // Shipped as `not_found` before the typed errors; the frontend matches on it.
+      AppError::PathNotFound(_) => "not_found",
+      AppError::PathNotAllowed(_) => "path_not_allowed",
+      AppError::RootUnreachable(_) => "root_unreachable",
+      AppError::PermissionDenied(_) => "permission_denied",
+      AppError::FileExists(_) => "file_exists",
+      AppError::FileTooLarge { .. } => "too_large",
+      AppError::WorkerNotConfigured(_) => "worker_not_configured",
+      AppError::WorkerSpawnFailed(_) => "worker_spawn_failed",
+      AppError::AlreadyRunning(_) => "already_running",
+      AppError::UnknownRun(_) => "unknown_run",
+      AppError::ShortcutUnavailable { .. } => "shortcut_unavailable",
+      AppError::LogNotFound { .. } => "log_not_found",
+      AppError::UnknownApp { .. } => "unknown_app",
+      AppError::NoTerminalFound(_) => "no_terminal_found",
+      AppError::ClipboardEmpty => "clipboard_empty",
+      AppError::Unsupported(_) => "unsupported",
+      AppError::InvalidInput(_) => "invalid_input",
+      AppError::Io { .. } => "io",
+      AppError::Parse { .. } => "parse",
+      AppError::Other(_) => "error",
+    }
+  }
+
+  pub(crate) fn message(&self) -> String {
+    match self {
+      AppError::HomeUnavailable => format!(
+        "The home directory could not be determined; set RECEIPTS_ROOT and {}",
+        STATE_DIR_ENV
+      ),
+      AppError::UnknownStore(store_id) => format!("Unknown store: {}", store_id),
+      AppError::PathNotFound(path) => format!("Path not found: {}", path),
+      AppError::PathNotAllowed(path) => format!(
+        "{} is outside RECEIPTS_ROOT, WORKER_DIR and the app state folder",
+        path
+      ),
+      AppError::RootUnreachable(root) => format!(
+        "{} is not responding (a sleeping or disconnected network drive?)",
+        root
+      ),
+      AppError::PermissionDenied(path) => format!("Permission denied: {}", path),
+      AppError::FileExists(path) => format!("{} already exists", path),
+      AppError::FileTooLarge {
+        path,
+        size,
+        max_bytes,
+      } => format!(
+        "{} is {} bytes, over the {} byte limit",
+        path, size, max_bytes
+      ),
+      AppError::WorkerSpawnFailed(reason) => format!("Failed to start the worker: {}", reason),
+      AppError::AlreadyRunning(job) => format!("An {} is already running", job),
+      AppError::UnknownRun(run_id) => format!("No run summary for {}", run_id),
+      AppError::ShortcutUnavailable {
+        accelerator,
+        reason,
+        ..
+      } => format!(
+        "{} is not available as a shortcut (another app or the OS may use it): {}",
+        accelerator, reason
+      ),
+      AppError::LogNotFound { run_id, path } => {
+        format!("Log file for run {} not found: {}", run_id, path)
+      }
+      AppError::UnknownApp {
+        app,
+        attempted,
+        reason,
+      } => format!(
+        "Could not open with {} (tried: {}): {}",
+        app, attempted, reason
+      ),
+      AppError::NoTerminalFound(probed) => {
+        format!(
+          "No terminal could be started (tried: {})",
+          probed.join(", ")
+        )
+      }
+      AppError::ClipboardEmpty => "The clipboard has no image or files to import".to_string(),
+      AppError::Io { path, reason } => format!("{}: {}", path, reason),
+      AppError::Parse {
+        path: Some(path),
+        reason,
+      } => format!("{}: {}", path, reason),
+      AppError::Parse { path: None, reason } => reason.clone(),
+      AppError::ConfigMissing(message)
+      | AppError::WorkerNotConfigured(message)
+      | AppError::Unsupported(message)
+      | AppError::InvalidInput(message)
+      | AppError::Other(message) => message.clone(),
+    }
+  }
+
+  pub(crate) fn details(&self) -> Value {
+    match self {
+      AppError::UnknownStore(store_id) => serde_json::json!({ "store_id": store_id }),
+      AppError::PathNotFound(path)
+      | AppError::PathNotAllowed(path)
+      | AppError::RootUnreachable(path)
+      | AppError::PermissionDenied(path)
+      | AppError::FileExists(path) => {
+        serde_json::json!({ "path": path })
+      }
+      AppError::FileTooLarge {
+        path,
+        size,
+        max_bytes,
+      } => serde_json::json!({ "path": path, "size": size, "max_bytes": max_bytes }),
+      AppError::AlreadyRunning(job) => serde_json::json!({ "job": job }),
+      AppError::UnknownRun(run_id) => serde_json::json!({ "run_id": run_id }),
+      AppError::ShortcutUnavailable {
+        action,
+        accelerator,
+        ..
+      } => serde_json::json!({ "action": action, "accelerator": accelerator }),
+      AppError::LogNotFound { run_id, path } => {
+        serde_json::json!({ "run_id": run_id, "path": path })
+      }
+      AppError::UnknownApp { app, attempted, .. } => {
+        serde_json::json!({ "app": app, "attempted": attempted })
+      }
+      AppError::NoTerminalFound(probed) => serde_json::json!({ "probed": probed }),
+      AppError::Io { path, .. } => serde_json::json!({ "path": path }),
+      AppError::Parse { path, .. } => serde_json::json!({ "path": path }),
+      _ => Value::Null,
+    }
+  }
+
+  pub(crate) fn io(path: &Path, err: std::io::Error) -> AppError {
+    let display = path.to_string_lossy().to_string();
+    match err.kind() {
+      std::io::ErrorKind::NotFound => AppError::PathNotFound(display),
+      std::io::ErrorKind::PermissionDenied => AppError::PermissionDenied(display),
+      _ => AppError::Io {
+        path: display,
+        reason: err.to_string(),
+      },
+    }
+  }
+}
+
+impl std::fmt::Display for AppError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(&self.message())
+  }
+}
+
+impl Serialize for AppError {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeStruct;
+    let mut error = serializer.serialize_struct("AppError", 3)?;
+    error.serialize_field("code", self.code())?;
+    error.serialize_field("message", &self.message())?;
+    error.serialize_field("details", &self.details())?;
+    error.end()
+  }
+}
+
+impl From<String> for AppError {
+  fn from(message: String) -> AppError {
+    AppError::Other(message)
+  }
+}
+
+impl From<&str> for AppError {
+  fn from(message: &str) -> AppError {
+    AppError::Other(message.to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn app_error_serializes_code_message_and_details() {
+    let err = AppError::UnknownStore("aldi".to_string());
+    assert_eq!(
+      serde_json::to_value(&err).unwrap(),
+      serde_json::json!({
+        "code": "unknown_store",
+        "message": "Unknown store: aldi",
+        "details": { "store_id": "aldi" }
+      })
+    );
+    let mut config = badge_config("lidl");
+    config.receipts_root = "/receipts".to_string();
+    assert_eq!(
+      require_store(&config, "aldi").unwrap_err().code(),
+      "unknown_store"
+    );
+    assert!(require_store(&config, "lidl").is_ok());
+
+    let missing = AppError::io(
+      Path::new("/missing"),
+      std::io::Error::new(std::io::ErrorKind::NotFound, "gone"),
+    );
+    assert_eq!(missing.code(), "not_found");
+    let empty = serde_json::to_value(AppError::ClipboardEmpty).unwrap();
+    assert_eq!(empty["code"], "clipboard_empty");
+    assert_eq!(empty["details"], Value::Null);
+    let other = AppError::from("boom");
+    assert_eq!(serde_json::to_value(&other).unwrap()["code"], "error");
+    assert_eq!(
+      serde_json::to_value(&other).unwrap()["details"],
+      Value::Null
+    );
+  }
+}
@@ -0,0 +1,317 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct CsvExport {
+  pub(crate) path: String,
+  pub(crate) rows: usize,
+  pub(crate) skipped: usize,
+  // The companion `<name>.skipped.csv`; None when nothing was skipped.
+  pub(crate) skipped_path: Option<String>,
+}
+
+// RFC 4180: fields with a separator, quote or line break are quoted and their
+// quotes doubled.
+pub(crate) fn csv_field(value: &str) -> String {
+  if value.contains([',', '"', '\n', '\r']) || value.trim() != value {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_string()
+  }
+}
+
+pub(crate) fn write_csv_row(out: &mut impl Write, fields: &[String]) -> std::io::Result<()> {
+  let line = fields
+    .iter()
+    .map(|field| csv_field(field))
+    .collect::<Vec<_>>()
+    .join(",");
+  out.write_all(line.as_bytes())?;
+  out.write_all(b"\r\n")
+}
+
+pub(crate) fn csv_text(value: Option<&Value>) -> String {
+  match value {
+    Some(Value::String(text)) => text.clone(),
+    Some(Value::Null) | None => String::new(),
+    Some(other) => other.to_string(),
+  }
+}
+
+pub(crate) fn csv_amount(value: Option<&Value>) -> String {
+  value
+    .and_then(parse_amount)
+    .map(|amount| format!("{:.2}", amount))
+    .unwrap_or_default()
+}
+
+// `report.csv` -> `report.skipped.csv`
+pub(crate) fn skipped_csv_path(path: &Path) -> PathBuf {
+  let stem = path
+    .file_stem()
+    .map(|stem| stem.to_string_lossy().to_string())
+    .unwrap_or_default();
+  path.with_file_name(format!("{}.skipped.csv", stem))
+}
+
+// The rows one receipt output contributes, or why it can't contribute any.
+pub(crate) fn receipt_csv_rows(
+  receipt: &ReceiptTotal,
+  value: &Value,
+  include_line_items: bool,
+) -> Result<Vec<Vec<String>>, String> {
+  if !value.is_object() {
+    return Err("output is not valid JSON".to_string());
+  }
+  let total = receipt.total.ok_or("no parseable total")?;
+  let date = receipt
+    .date
+    .map(|date| date.to_string())
+    .unwrap_or_default();
+  let merchant = csv_text(
+    value
+      .get("merchant")
+      .and_then(|merchant| merchant.get("name")),
+  );
+  let currency = receipt.currency.clone().unwrap_or_default();
+  if !include_line_items {
+    return Ok(vec![vec![
+      date,
+      receipt.store_id.clone(),
+      merchant,
+      format!("{:.2}", total),
+      currency,
+      receipt.file_name.clone(),
+    ]]);
+  }
+  let items = value
+    .get("items")
+    .and_then(|items| items.as_array())
+    .filter(|items| !items.is_empty())
+    .ok_or("no line items")?;
+  Ok(
+    items
+      .iter()
+      .map(|item| {
+        vec![
+          date.clone(),
+          receipt.store_id.clone(),
+          merchant.clone(),
+          csv_text(item.get("name")),
+          csv_text(item.get("quantity")),
+          csv_text(item.get("unit")),
+          csv_amount(item.get("unit_price")),
+          csv_amount(item.get("discount")),
+          csv_amount(item.get("paid_amount")),
+          currency.clone(),
+          receipt.file_name.clone(),
+        ]
+      })
+      .collect(),
+  )
+}
+
+// Creates `path` through a temp file next to it, so a failed export never
+// leaves half a CSV behind. Starts with a UTF-8 BOM for Excel.
+pub(crate) fn write_csv_file(
+  path: &Path,
+  columns: &[&str],
+  rows: impl FnOnce(&mut dyn FnMut(&[String]) -> std::io::Result<()>) -> std::io::Result<()>,
+) -> Result<(), AppError> {
+  let temp = path.with_extension("csv.tmp");
+  let result = (|| {
+    let mut out = std::io::BufWriter::new(fs::File::create(&temp)?);
+    out.write_all("\u{feff}".as_bytes())?;
+    let header = columns
+      .iter()
+      .map(|column| column.to_string())
+      .collect::<Vec<_>>();
+    write_csv_row(&mut out, &header)?;
+    rows(&mut |fields| write_csv_row(&mut out, fields))?;
+    out.into_inner()?.sync_all()?;
+    fs::rename(&temp, path)
+  })();
+  result.map_err(|err| {
+    let _ = fs::remove_file(&temp);
+    AppError::io(path, err)
+  })
+}
+
+// Reads processed/ one output at a time and only ever writes `path` and its
+// `.skipped.csv` companion.
+pub(crate) fn export_receipts_csv_to(
+  receipts_root: &str,
+  store_ids: &[String],
+  from: Option<chrono::NaiveDate>,
+  to: Option<chrono::NaiveDate>,
+  include_line_items: bool,
+  path: &Path,
+  overwrite: bool,
+) -> Result<CsvExport, AppError> {
+  if !path.is_absolute() {
+    return Err(AppError::InvalidInput(format!(
+      "Export path must be absolute: {}",
+      path.to_string_lossy()
+    )));
+  }
+  let skipped_path = skipped_csv_path(path);
+  if !overwrite {
+    for target in [path, skipped_path.as_path()] {
+      if target.exists() {
+        return Err(AppError::FileExists(target.to_string_lossy().to_string()));
+      }
+    }
+  }
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| AppError::io(parent, err))?;
+  }
+
+  let columns: &[&str] = if include_line_items {
+    &LINE_ITEM_CSV_COLUMNS
+  } else {
+    &RECEIPT_CSV_COLUMNS
+  };
+  let mut rows = 0;
+  let mut skipped = Vec::new();
+  write_csv_file(path, columns, |write_row| {
+    for store_id in store_ids {
+      let dir = processed_dir(receipts_root, store_id);
+      let mut names = receipt_output_names(&dir);
+      names.sort();
+      for name in names {
+        let file = join_file_id(&dir, &name);
+        let raw = match fs::read_to_string(&file) {
+          Ok(raw) => raw,
+          Err(_) => continue,
+        };
+        let value = serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null);
+        let receipt = match receipt_total_from(store_id, &file, &value) {
+          Some(receipt) => receipt,
+          None => continue,
+        };
+        let in_range = receipt.date.is_some_and(|date| {
+          from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+        });
+        if !in_range {
+          continue;
+        }
+        match receipt_csv_rows(&receipt, &value, include_line_items) {
+          Ok(receipt_rows) => {
+            for row in receipt_rows {
+              write_row(row.as_slice())?;
+              rows += 1;
+            }
+          }
+          Err(reason) => skipped.push(vec![
+            receipt
+              .date
+              .map(|date| date.to_string())
+              .unwrap_or_default(),
+            store_id.clone(),
+            name,
+            reason,
+          ]),
+        }
+      }
+    }
+    Ok(())
+  })?;
+
+  let skipped_written = if skipped.is_empty() {
+    let _ = fs::remove_file(&skipped_path);
+    None
+  } else {
+    write_csv_file(&skipped_path, &SKIPPED_CSV_COLUMNS, |write_row| {
+      skipped.iter().try_for_each(|row| write_row(row.as_slice()))
+    })?;
+    Some(skipped_path.to_string_lossy().to_string())
+  };
+  Ok(CsvExport {
+    path: path.to_string_lossy().to_string(),
+    rows,
+    skipped: skipped.len(),
+    skipped_path: skipped_written,
+  })
+}
+
+pub(crate) fn receipt_output_names(dir: &Path) -> Vec<String> {
+  fs::read_dir(dir)
+    .map(|entries| {
+      entries
+        .flatten()
+        .map(|entry| entry.file_name())
+        .filter(|name| is_receipt_output(&name.to_string_lossy()))
+        .map(|name| file_name_id(&name))
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn receipts_csv_export_quotes_fields_lists_skipped_and_refuses_overwrite() {
+    assert_eq!(csv_field("plain"), "plain");
+    assert_eq!(csv_field("Lidl, Cluj"), "\"Lidl, Cluj\"");
+    assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(&processed).expect("processed");
+    fs::write(
+      processed.join("a.jpg.json"),
+      r#"{"timestamp": "2024-01-05T10:00:00", "total": "45,90", "currency": "RON",
+        "merchant": {"name": "Lidl, Cluj"},
+        "items": [{"name": "Lapte \"Zuzu\"", "quantity": 2, "unit_price": 6.5, "paid_amount": 13.0},
+                  {"name": "Pâine", "quantity": 1, "paid_amount": "32,90"}]}"#,
+    )
+    .expect("receipt");
+    fs::write(
+      processed.join("b.jpg.json"),
+      r#"{"timestamp": "2024-01-06T10:00:00", "total": null}"#,
+    )
+    .expect("no total");
+    fs::write(processed.join("c.jpg.json"), "garbage").expect("garbage");
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string()];
+    let from = chrono::NaiveDate::from_ymd_opt(2024, 1, 1);
+    let out = dir.path().join("exports").join("2024").join("receipts.csv");
+
+    let export =
+      export_receipts_csv_to(&root, &stores, from, None, false, &out, false).expect("export");
+    assert_eq!((export.rows, export.skipped), (1, 2));
+    let raw = fs::read_to_string(&out).expect("csv");
+    assert!(raw.starts_with('\u{feff}'));
+    assert_eq!(
+      raw.trim_start_matches('\u{feff}'),
+      "date,store,merchant,total,currency,file_name\r\n\
+     2024-01-05,lidl,\"Lidl, Cluj\",45.90,RON,a.jpg.json\r\n"
+    );
+    let skipped = fs::read_to_string(export.skipped_path.expect("skipped")).expect("skipped csv");
+    assert!(skipped.contains("2024-01-06,lidl,b.jpg.json,no parseable total"));
+    // Dated by its mtime, which is inside the open-ended range.
+    assert!(skipped.contains(",lidl,c.jpg.json,output is not valid JSON"));
+
+    let err = export_receipts_csv_to(&root, &stores, from, None, true, &out, false).unwrap_err();
+    assert_eq!(err.code(), "file_exists");
+    let export =
+      export_receipts_csv_to(&root, &stores, from, None, true, &out, true).expect("overwrite");
+    assert_eq!(export.rows, 2);
+    let raw = fs::read_to_string(&out).expect("csv");
+    assert!(raw.contains("\"Lapte \"\"Zuzu\"\"\",2,,6.50,,13.00,RON,a.jpg.json"));
+    assert!(raw.contains("Pâine,1,,,,32.90,RON"));
+
+    let relative = export_receipts_csv_to(
+      &root,
+      &stores,
+      None,
+      None,
+      false,
+      Path::new("out.csv"),
+      true,
+    );
+    assert_eq!(relative.unwrap_err().code(), "invalid_input");
+  }
+}
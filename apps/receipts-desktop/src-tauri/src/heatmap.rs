@@ -0,0 +1,217 @@
+use crate::*;
+use chrono::Datelike;
+
+// Per-day figures as arrays indexed by day of the year (0 = 1 January), so a
+// calendar can be drawn without looking anything up. Totals are per currency,
+// like every other aggregate, with 0 on days without that currency.
+#[derive(Debug, Serialize, Clone, PartialEq, Default)]
+pub(crate) struct ActivitySeries {
+  pub(crate) counts: Vec<u32>,
+  pub(crate) totals: std::collections::BTreeMap<String, Vec<f64>>,
+  pub(crate) receipt_count: usize,
+  // Receipts of any year whose output has no date; they can't be placed.
+  pub(crate) undated: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct StoreActivity {
+  pub(crate) store_id: String,
+  #[serde(flatten)]
+  pub(crate) series: ActivitySeries,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ActivityHeatmap {
+  pub(crate) year: i32,
+  pub(crate) days: usize,
+  // Weekday of 1 January, 0 = Monday.
+  pub(crate) first_weekday: u32,
+  // The busiest day's count, for scaling the colours.
+  pub(crate) max_count: u32,
+  #[serde(flatten)]
+  pub(crate) combined: ActivitySeries,
+  pub(crate) stores: Vec<StoreActivity>,
+}
+
+// Walk results only: with the index the figures are rebuilt from its rows,
+// which is cheap. Keyed by root and year, checked against processed/.
+#[derive(Default)]
+pub(crate) struct ActivityHeatmapCache(pub(crate) Mutex<ActivityHeatmapEntries>);
+
+pub(crate) type ActivityHeatmapEntries =
+  HashMap<(String, i32), (Vec<(String, RunsSignature)>, ActivityHeatmap)>;
+
+pub(crate) fn year_start(year: i32) -> Result<chrono::NaiveDate, AppError> {
+  chrono::NaiveDate::from_ymd_opt(year, 1, 1)
+    .filter(|_| (1970..=9999).contains(&year))
+    .ok_or_else(|| AppError::InvalidInput(format!("Not a valid year: {}", year)))
+}
+
+impl ActivitySeries {
+  pub(crate) fn new(days: usize) -> Self {
+    ActivitySeries {
+      counts: vec![0; days],
+      ..Default::default()
+    }
+  }
+
+  pub(crate) fn add(&mut self, day: Option<usize>, receipt: &ReceiptTotal) {
+    let day = match day {
+      Some(day) => day,
+      None => {
+        self.undated += 1;
+        return;
+      }
+    };
+    let days = self.counts.len();
+    self.counts[day] += 1;
+    self.receipt_count += 1;
+    if let Some(total) = receipt.total {
+      self
+        .totals
+        .entry(receipt_currency(receipt))
+        .or_insert_with(|| vec![0.0; days])[day] += total;
+    }
+  }
+
+  pub(crate) fn rounded(mut self) -> Self {
+    for totals in self.totals.values_mut() {
+      for total in totals.iter_mut() {
+        *total = round_cents(*total);
+      }
+    }
+    self
+  }
+}
+
+// Buckets by the receipt's own date. Receipts dated only by their output's
+// mtime count as undated instead of landing on the day they were processed.
+pub(crate) fn activity_heatmap(
+  receipts: impl IntoIterator<Item = ReceiptTotal>,
+  store_ids: &[String],
+  year: i32,
+) -> Result<ActivityHeatmap, AppError> {
+  let start = year_start(year)?;
+  let days = year_start(year + 1)
+    .map(|next| (next - start).num_days() as usize)
+    .unwrap_or(365);
+  let mut combined = ActivitySeries::new(days);
+  let mut stores = store_ids
+    .iter()
+    .map(|store_id| (store_id.clone(), ActivitySeries::new(days)))
+    .collect::<Vec<_>>();
+  for receipt in receipts {
+    let series = match stores
+      .iter_mut()
+      .find(|(store_id, _)| *store_id == receipt.store_id)
+    {
+      Some((_, series)) => series,
+      None => continue,
+    };
+    let day = match receipt.date.filter(|_| !receipt.date_estimated) {
+      Some(date) if date.year() == year => Some((date - start).num_days() as usize),
+      Some(_) => continue,
+      None => None,
+    };
+    series.add(day, &receipt);
+    combined.add(day, &receipt);
+  }
+  let combined = combined.rounded();
+  Ok(ActivityHeatmap {
+    year,
+    days,
+    first_weekday: start.weekday().num_days_from_monday(),
+    max_count: combined.counts.iter().copied().max().unwrap_or(0),
+    combined,
+    stores: stores
+      .into_iter()
+      .map(|(store_id, series)| StoreActivity {
+        store_id,
+        series: series.rounded(),
+      })
+      .collect(),
+  })
+}
+
+pub(crate) fn cached_activity_heatmap(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  year: i32,
+) -> Result<ActivityHeatmap, AppError> {
+  year_start(year)?;
+  let root = require_receipts_root(config)?;
+  let store_ids = receipt_store_ids(config, None)?;
+  if let Some(receipts) = indexed_receipts(app, root, &store_ids) {
+    return activity_heatmap(receipts, &store_ids, year);
+  }
+  let signature = processed_signature(root, &store_ids);
+  let key = (root.to_string(), year);
+  let cache = app.state::<ActivityHeatmapCache>();
+  if let Ok(cached) = cache.0.lock() {
+    if let Some((cached_signature, heatmap)) = cached.get(&key) {
+      if *cached_signature == signature {
+        return Ok(heatmap.clone());
+      }
+    }
+  }
+  let heatmap = activity_heatmap(walk_receipt_totals(root, &store_ids), &store_ids, year)?;
+  if let Ok(mut cached) = cache.0.lock() {
+    cached.insert(key, (signature, heatmap.clone()));
+  }
+  Ok(heatmap)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn activity_heatmap_counts_days_per_store_and_keeps_undated_apart() {
+    let receipt = |store_id: &str, date: &str, total: Option<f64>, currency: &str| ReceiptTotal {
+      store_id: store_id.to_string(),
+      file_name: format!("{}-{}.jpg.json", store_id, date),
+      date: chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok(),
+      date_estimated: false,
+      total,
+      currency: Some(currency.to_string()),
+    };
+    let mut estimated = receipt("lidl", "2024-06-01", Some(5.0), "RON");
+    estimated.date_estimated = true;
+    let receipts = vec![
+      receipt("lidl", "2024-01-01", Some(10.0), "RON"),
+      receipt("lidl", "2024-01-01", Some(2.5), "EUR"),
+      receipt("kaufland", "2024-01-01", None, "RON"),
+      receipt("kaufland", "2024-12-31", Some(7.25), "RON"),
+      receipt("lidl", "2023-12-31", Some(99.0), "RON"),
+      receipt("aldi", "2024-03-03", Some(1.0), "RON"),
+      estimated,
+      receipt("lidl", "not a date", Some(3.0), "RON"),
+    ];
+    let stores = vec!["lidl".to_string(), "kaufland".to_string()];
+    let heatmap = activity_heatmap(receipts, &stores, 2024).expect("heatmap");
+    assert_eq!(heatmap.days, 366);
+    assert_eq!(heatmap.first_weekday, 0);
+    assert_eq!(heatmap.combined.counts.len(), 366);
+    assert_eq!(heatmap.combined.counts[0], 3);
+    assert_eq!(heatmap.combined.counts[365], 1);
+    assert_eq!(heatmap.max_count, 3);
+    assert_eq!(heatmap.combined.receipt_count, 4);
+    assert_eq!(heatmap.combined.undated, 2);
+    assert_eq!(heatmap.combined.totals["RON"][0], 10.0);
+    assert_eq!(heatmap.combined.totals["RON"][365], 7.25);
+    assert_eq!(heatmap.combined.totals["EUR"][0], 2.5);
+    let lidl = &heatmap.stores[0];
+    assert_eq!((lidl.store_id.as_str(), lidl.series.counts[0]), ("lidl", 2));
+    assert_eq!(lidl.series.undated, 2);
+    let kaufland = &heatmap.stores[1];
+    assert_eq!(kaufland.series.counts[0], 1);
+    assert!(!kaufland.series.totals.contains_key("EUR"));
+    assert_eq!(
+      activity_heatmap(Vec::new(), &stores, 2023)
+        .expect("empty")
+        .days,
+      365
+    );
+    assert!(activity_heatmap(Vec::new(), &stores, 0).is_err());
+  }
+}
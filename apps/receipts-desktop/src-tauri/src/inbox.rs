@@ -0,0 +1,1595 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct EnsuredDirs {
+  pub(crate) created: Vec<String>,
+  pub(crate) existing: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ImportedFile {
+  pub(crate) source: String,
+  pub(crate) status: String,
+  pub(crate) file_name: Option<String>,
+  pub(crate) path: Option<String>,
+  pub(crate) message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ClipboardImport {
+  pub(crate) status: String,
+  pub(crate) source: String,
+  pub(crate) files: Vec<ImportedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InboxFileContent {
+  pub(crate) file_name: String,
+  pub(crate) size: u64,
+  pub(crate) max_bytes: u64,
+  pub(crate) mime: Option<String>,
+  // Images and PDFs alike, so the UI can build a `data:` URL from it.
+  pub(crate) content_base64: String,
+  // PDFs only: the raw bytes for the frontend's PDF viewer.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) bytes: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InboxFile {
+  // For display; pass `id` back to act on the file (see `file_name_id`).
+  pub(crate) name: String,
+  #[serde(default)]
+  pub(crate) id: String,
+  pub(crate) size: u64,
+  pub(crate) hydrated: bool,
+  pub(crate) modified: Option<String>,
+  #[serde(default)]
+  pub(crate) tags: Vec<String>,
+  #[serde(skip)]
+  pub(crate) modified_at: Option<std::time::SystemTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct OpenedInboxFile {
+  pub(crate) status: String,
+  pub(crate) file_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InboxPage {
+  pub(crate) total: usize,
+  pub(crate) offset: usize,
+  pub(crate) files: Vec<InboxFile>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum InboxSort {
+  Name,
+  Mtime,
+  Size,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct InboxQuery {
+  pub(crate) sort: InboxSort,
+  pub(crate) descending: bool,
+  pub(crate) offset: usize,
+  pub(crate) limit: usize,
+  pub(crate) filter: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct FileIntegrityIssue {
+  pub(crate) file_name: String,
+  pub(crate) size: u64,
+  pub(crate) hydrated: bool,
+  pub(crate) issue: String,
+  pub(crate) detail: String,
+  pub(crate) suggested_action: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InboxIntegrity {
+  pub(crate) store_id: String,
+  pub(crate) checked: usize,
+  pub(crate) issues: Vec<FileIntegrityIssue>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct StoreRunPreview {
+  pub(crate) store_id: String,
+  pub(crate) pending: usize,
+  pub(crate) unhydrated: usize,
+  pub(crate) integrity: InboxIntegrity,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct RunPreview {
+  pub(crate) ready: bool,
+  pub(crate) config_error: Option<String>,
+  pub(crate) warnings: Vec<String>,
+  pub(crate) args: Vec<String>,
+  pub(crate) stores: Vec<StoreRunPreview>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct HydrateProgress {
+  pub(crate) store_id: String,
+  pub(crate) file_name: String,
+  pub(crate) bytes_read: u64,
+  pub(crate) total_bytes: u64,
+  pub(crate) done: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct HydrateResult {
+  pub(crate) status: String,
+  pub(crate) file_name: String,
+  pub(crate) bytes_read: u64,
+  pub(crate) total_bytes: u64,
+  pub(crate) hydrated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InboxCount {
+  pub(crate) store_id: String,
+  pub(crate) count: usize,
+  pub(crate) backlog_warning: Option<String>,
+  pub(crate) computed_at: String,
+  // From the last scan before RECEIPTS_ROOT stopped answering.
+  #[serde(default)]
+  pub(crate) stale: bool,
+  // Set when the inbox is there but couldn't be read; `count` is 0 then.
+  #[serde(default)]
+  pub(crate) error: Option<DirError>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct InboxStats {
+  pub(crate) count: usize,
+  pub(crate) oldest: Option<std::time::SystemTime>,
+  pub(crate) dir_modified: Option<std::time::SystemTime>,
+  pub(crate) computed: Instant,
+  pub(crate) computed_at: String,
+  pub(crate) error: Option<AppError>,
+}
+
+#[derive(Default)]
+pub(crate) struct InboxCountCache(
+  pub(crate) Mutex<HashMap<String, InboxStats>>,
+  pub(crate) CacheCounters,
+);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct BacklogWarningEvent {
+  pub(crate) store_id: String,
+  pub(crate) count: usize,
+  pub(crate) oldest_days: Option<u64>,
+  pub(crate) message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct DirUsage {
+  pub(crate) bytes: u64,
+  pub(crate) files: usize,
+  pub(crate) incomplete: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct StoreDiskUsage {
+  pub(crate) store_id: String,
+  pub(crate) inbox: DirUsage,
+  pub(crate) processed: DirUsage,
+  pub(crate) failed: DirUsage,
+  pub(crate) total_bytes: u64,
+  pub(crate) total_files: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct DiskUsage {
+  pub(crate) stores: Vec<StoreDiskUsage>,
+  pub(crate) logs: DirUsage,
+  pub(crate) free_bytes: Option<u64>,
+  pub(crate) disk_total_bytes: Option<u64>,
+  pub(crate) incomplete: bool,
+  pub(crate) computed_at: String,
+}
+
+#[derive(Default)]
+pub(crate) struct DiskUsageCache(pub(crate) Mutex<Option<(Instant, DiskUsage)>>);
+
+pub(crate) fn walk_dir_usage(
+  path: &Path,
+  depth: usize,
+  visited: &mut VisitedDirs,
+  usage: &mut DirUsage,
+) {
+  let entries = match fs::read_dir(path) {
+    Ok(entries) => entries,
+    Err(err) => {
+      if err.kind() != std::io::ErrorKind::NotFound {
+        usage.incomplete = true;
+      }
+      return;
+    }
+  };
+
+  for entry in entries {
+    let entry = match entry {
+      Ok(entry) => entry,
+      Err(_) => {
+        usage.incomplete = true;
+        continue;
+      }
+    };
+    let meta = match fs::metadata(entry.path()) {
+      Ok(meta) => meta,
+      Err(_) => {
+        usage.incomplete = true;
+        continue;
+      }
+    };
+    if meta.is_dir() {
+      if depth >= DISK_USAGE_MAX_DEPTH {
+        usage.incomplete = true;
+        continue;
+      }
+      if visited.enter(&entry.path()) {
+        walk_dir_usage(&entry.path(), depth + 1, visited, usage);
+      }
+    } else if meta.is_file() {
+      usage.bytes += meta.len();
+      usage.files += 1;
+    }
+  }
+}
+
+pub(crate) fn dir_usage(path: &Path) -> DirUsage {
+  let mut usage = DirUsage::default();
+  let mut visited = VisitedDirs::default();
+  visited.enter(path);
+  walk_dir_usage(path, 0, &mut visited, &mut usage);
+  usage
+}
+
+pub(crate) fn compute_disk_usage(config: &AppConfig) -> DiskUsage {
+  let base = Path::new(&config.receipts_root);
+  let mut incomplete = false;
+  let mut stores = Vec::new();
+  for store in &config.stores {
+    let inbox = dir_usage(&base.join("inbox").join(&store.id));
+    let processed = dir_usage(&base.join("processed").join(&store.id));
+    let failed = dir_usage(&base.join("failed").join(&store.id));
+    incomplete |= inbox.incomplete || processed.incomplete || failed.incomplete;
+    stores.push(StoreDiskUsage {
+      store_id: store.id.clone(),
+      total_bytes: inbox.bytes + processed.bytes + failed.bytes,
+      total_files: inbox.files + processed.files + failed.files,
+      inbox,
+      processed,
+      failed,
+    });
+  }
+  let logs = dir_usage(&base.join("_logs"));
+  incomplete |= logs.incomplete;
+
+  DiskUsage {
+    stores,
+    logs,
+    free_bytes: fs2::available_space(base).ok(),
+    disk_total_bytes: fs2::total_space(base).ok(),
+    incomplete,
+    computed_at: chrono::Utc::now().to_rfc3339(),
+  }
+}
+
+// Cached for DISK_USAGE_TTL. The walk runs outside the lock, so a slow root
+// doesn't hold up other callers; two misses at once may both walk.
+pub(crate) fn cached_disk_usage(
+  cache: &DiskUsageCache,
+  config: &AppConfig,
+  force: bool,
+) -> DiskUsage {
+  if !force {
+    if let Ok(cached) = cache.0.lock() {
+      if let Some((computed, usage)) = cached.as_ref() {
+        if computed.elapsed() < DISK_USAGE_TTL {
+          return usage.clone();
+        }
+      }
+    }
+  }
+  let usage = compute_disk_usage(config);
+  if let Ok(mut cached) = cache.0.lock() {
+    *cached = Some((Instant::now(), usage.clone()));
+  }
+  usage
+}
+
+// Every folder the worker expects under the receipts root.
+pub(crate) fn expected_dirs(base: &Path, stores: &[StoreConfig]) -> Vec<PathBuf> {
+  let mut targets = Vec::new();
+  for store in stores {
+    for kind in ["inbox", "processed", "failed"] {
+      targets.push(base.join(kind).join(&store.id));
+    }
+  }
+  targets.push(base.join("_logs").join("runs"));
+  targets
+}
+
+pub(crate) fn ensure_dirs(
+  config: &AppConfig,
+  store_id: Option<&str>,
+) -> Result<EnsuredDirs, AppError> {
+  let base = Path::new(require_receipts_root(config)?);
+  let targets = match store_id {
+    Some(store_id) => {
+      require_store(config, store_id)?;
+      ["inbox", "processed", "failed"]
+        .iter()
+        .map(|kind| base.join(kind).join(store_id))
+        .collect::<Vec<_>>()
+    }
+    None => expected_dirs(base, &config.stores),
+  };
+
+  let mut result = EnsuredDirs::default();
+  for path in targets {
+    let display = path.to_string_lossy().to_string();
+    if path.is_dir() {
+      result.existing.push(display);
+      continue;
+    }
+    fs::create_dir_all(&path).map_err(|err| AppError::io(&path, err))?;
+    result.created.push(display);
+  }
+  Ok(result)
+}
+
+pub(crate) fn is_inbox_candidate(path: &Path) -> bool {
+  if !path.is_file() {
+    return false;
+  }
+  path
+    .extension()
+    .and_then(|ext| ext.to_str())
+    .map(|ext| INBOX_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    .unwrap_or(false)
+}
+
+pub(crate) fn resolve_inbox_file(
+  config: &AppConfig,
+  store_id: &str,
+  file_name: &str,
+) -> Result<PathBuf, AppError> {
+  require_store(config, store_id)?;
+  let raw_name = file_name_from_id(file_name);
+  let name = Path::new(&raw_name);
+  let is_plain_name = name
+    .file_name()
+    .map(|base| base == name.as_os_str())
+    .unwrap_or(false)
+    && !raw_name.to_string_lossy().contains(['/', '\\']);
+  let path = Path::new(&config.receipts_root)
+    .join("inbox")
+    .join(store_id)
+    .join(&raw_name);
+  if !is_plain_name || !is_inbox_candidate(&path) {
+    return Err(AppError::PathNotFound(path.to_string_lossy().to_string()));
+  }
+  Ok(path)
+}
+
+// `file_name` is the file's `id` from `list_inbox_files` (a plain UTF-8 name
+// works too).
+pub(crate) fn delete_inbox_file_at(
+  config: &AppConfig,
+  store_id: &str,
+  file_name: &str,
+) -> Result<(), AppError> {
+  let path = resolve_inbox_file(config, store_id, file_name)?;
+  fs::remove_file(&path).map_err(|err| AppError::io(&path, err))?;
+  info!("deleted inbox file {}", path.to_string_lossy());
+  Ok(())
+}
+
+pub(crate) fn sniff_mime(header: &[u8]) -> Option<&'static str> {
+  if header.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+    Some("image/png")
+  } else if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+    Some("image/jpeg")
+  } else if header.starts_with(b"%PDF") {
+    Some("application/pdf")
+  } else {
+    None
+  }
+}
+
+pub(crate) fn mime_from_extension(path: &Path) -> Option<&'static str> {
+  let ext = path.extension()?.to_str()?.to_lowercase();
+  match ext.as_str() {
+    "png" => Some("image/png"),
+    "jpg" | "jpeg" => Some("image/jpeg"),
+    "pdf" => Some("application/pdf"),
+    _ => None,
+  }
+}
+
+pub(crate) fn read_inbox_file_at(
+  config: &AppConfig,
+  store_id: &str,
+  file_name: &str,
+  max_bytes: u64,
+) -> Result<InboxFileContent, AppError> {
+  let path = resolve_inbox_file(config, store_id, file_name)?;
+  let size = fs::metadata(&path)
+    .map_err(|err| AppError::io(&path, err))?
+    .len();
+  if size > max_bytes {
+    return Err(AppError::FileTooLarge {
+      path: path.to_string_lossy().to_string(),
+      size,
+      max_bytes,
+    });
+  }
+  let raw = fs::read(&path).map_err(|err| AppError::io(&path, err))?;
+  let mime = sniff_mime(&raw).or_else(|| mime_from_extension(&path));
+  Ok(InboxFileContent {
+    file_name: file_name.to_string(),
+    size,
+    max_bytes,
+    mime: mime.map(|mime| mime.to_string()),
+    content_base64: base64::engine::general_purpose::STANDARD.encode(&raw),
+    bytes: (mime == Some("application/pdf")).then_some(raw),
+  })
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn is_placeholder(meta: &fs::Metadata) -> bool {
+  use std::os::macos::fs::MetadataExt;
+  const SF_DATALESS: u32 = 0x4000_0000;
+  meta.len() == 0 || meta.st_flags() & SF_DATALESS != 0 || (meta.st_blocks() == 0 && meta.len() > 0)
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn is_placeholder(meta: &fs::Metadata) -> bool {
+  use std::os::windows::fs::MetadataExt;
+  const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+  const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+  const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x4_0000;
+  const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x40_0000;
+  let placeholder_bits = FILE_ATTRIBUTE_REPARSE_POINT
+    | FILE_ATTRIBUTE_OFFLINE
+    | FILE_ATTRIBUTE_RECALL_ON_OPEN
+    | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS;
+  meta.len() == 0 || meta.file_attributes() & placeholder_bits != 0
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub(crate) fn is_placeholder(meta: &fs::Metadata) -> bool {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::MetadataExt;
+    if meta.blocks() == 0 && meta.len() > 0 {
+      return true;
+    }
+  }
+  meta.len() == 0
+}
+
+pub(crate) fn read_head_and_tail(path: &Path, size: u64) -> std::io::Result<(Vec<u8>, Vec<u8>)> {
+  let mut file = fs::File::open(path)?;
+  let head_len = size.min(INTEGRITY_SNIFF_BYTES);
+  let mut head = vec![0u8; head_len as usize];
+  file.read_exact(&mut head)?;
+  let tail_len = size.min(1024);
+  let mut tail = vec![0u8; tail_len as usize];
+  file.seek(SeekFrom::End(-(tail_len as i64)))?;
+  file.read_exact(&mut tail)?;
+  Ok((head, tail))
+}
+
+pub(crate) fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+  haystack
+    .windows(needle.len())
+    .any(|window| window == needle)
+}
+
+pub(crate) fn check_file_integrity(path: &Path) -> Option<FileIntegrityIssue> {
+  let file_name = path
+    .file_name()
+    .map(|name| name.to_string_lossy().to_string())
+    .unwrap_or_default();
+  let meta = match fs::metadata(path) {
+    Ok(meta) => meta,
+    Err(err) => {
+      return Some(FileIntegrityIssue {
+        file_name,
+        size: 0,
+        hydrated: true,
+        issue: "unreadable".to_string(),
+        detail: err.to_string(),
+        suggested_action: "retry".to_string(),
+      })
+    }
+  };
+  let size = meta.len();
+  let hydrated = !is_placeholder(&meta);
+  let recent = meta
+    .modified()
+    .ok()
+    .and_then(|modified| modified.elapsed().ok())
+    .map(|age| age.as_secs() < INTEGRITY_RECENT_SECS)
+    .unwrap_or(false);
+  let issue = |issue: &str, detail: &str, action: &str| FileIntegrityIssue {
+    file_name: file_name.clone(),
+    size,
+    hydrated,
+    issue: issue.to_string(),
+    detail: detail.to_string(),
+    suggested_action: action.to_string(),
+  };
+  let truncated_action = if recent { "wait_for_sync" } else { "retry" };
+
+  if size == 0 {
+    return Some(issue("empty", "File is zero bytes", "wait_for_sync"));
+  }
+  if !hydrated {
+    return Some(issue(
+      "not_hydrated",
+      "File is an online-only placeholder",
+      "hydrate",
+    ));
+  }
+  let (head, tail) = match read_head_and_tail(path, size) {
+    Ok(parts) => parts,
+    Err(err) => return Some(issue("unreadable", &err.to_string(), "retry")),
+  };
+
+  match mime_from_extension(path) {
+    Some("image/png") => {
+      if sniff_mime(&head) != Some("image/png") || head.get(12..16) != Some(b"IHDR".as_slice()) {
+        return Some(issue(
+          "bad_header",
+          "Missing PNG signature or IHDR chunk",
+          "delete",
+        ));
+      }
+      if !contains_bytes(&tail, b"IEND") {
+        return Some(issue(
+          "truncated",
+          "PNG ends before the IEND chunk",
+          truncated_action,
+        ));
+      }
+    }
+    Some("image/jpeg") => {
+      if sniff_mime(&head) != Some("image/jpeg") {
+        return Some(issue(
+          "bad_header",
+          "Missing JPEG start-of-image marker",
+          "delete",
+        ));
+      }
+      if !contains_bytes(&tail, &[0xFF, 0xD9]) {
+        return Some(issue(
+          "truncated",
+          "JPEG has no end-of-image marker",
+          truncated_action,
+        ));
+      }
+    }
+    Some("application/pdf") => {
+      if !head.starts_with(b"%PDF") {
+        return Some(issue("bad_header", "Missing %PDF header", "delete"));
+      }
+      if !contains_bytes(&tail, b"%%EOF") {
+        return Some(issue(
+          "truncated",
+          "PDF has no %%EOF trailer",
+          truncated_action,
+        ));
+      }
+    }
+    _ => {}
+  }
+  None
+}
+
+pub(crate) fn inbox_integrity(config: &AppConfig, store_id: &str) -> InboxIntegrity {
+  let inbox = Path::new(&config.receipts_root)
+    .join("inbox")
+    .join(store_id);
+  let mut checked = 0usize;
+  let mut issues = Vec::new();
+  if let Ok(entries) = fs::read_dir(inbox) {
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if !is_inbox_candidate(&path) {
+        continue;
+      }
+      checked += 1;
+      if let Some(issue) = check_file_integrity(&path) {
+        issues.push(issue);
+      }
+    }
+  }
+  issues.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+  InboxIntegrity {
+    store_id: store_id.to_string(),
+    checked,
+    issues,
+  }
+}
+
+pub(crate) fn parse_inbox_sort(value: Option<&str>) -> Result<InboxSort, String> {
+  match value.unwrap_or("name") {
+    "name" => Ok(InboxSort::Name),
+    "mtime" => Ok(InboxSort::Mtime),
+    "size" => Ok(InboxSort::Size),
+    other => Err(format!("Unknown sort: {}", other)),
+  }
+}
+
+// A missing inbox is empty; one that can't be read is an error.
+pub(crate) fn scan_inbox_files(inbox: &Path) -> Result<Vec<InboxFile>, AppError> {
+  let mut files = Vec::new();
+  if let Some(entries) = read_dir_if_exists(inbox)? {
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if !is_inbox_candidate(&path) {
+        continue;
+      }
+      let meta = fs::metadata(&path).ok();
+      let modified_at = meta.as_ref().and_then(|meta| meta.modified().ok());
+      files.push(InboxFile {
+        name: entry.file_name().to_string_lossy().to_string(),
+        id: file_name_id(&entry.file_name()),
+        hydrated: meta
+          .as_ref()
+          .map(|meta| !is_placeholder(meta))
+          .unwrap_or(true),
+        size: meta.map(|meta| meta.len()).unwrap_or(0),
+        modified: modified_at.map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339()),
+        tags: Vec::new(),
+        modified_at,
+      });
+    }
+  }
+  Ok(files)
+}
+
+// Newest by mtime; ties go to the first name so the pick is stable.
+pub(crate) fn latest_inbox_file(inbox: &Path) -> Result<Option<InboxFile>, AppError> {
+  Ok(
+    scan_inbox_files(inbox)?
+      .into_iter()
+      .max_by(|a, b| a.modified_at.cmp(&b.modified_at).then(b.name.cmp(&a.name))),
+  )
+}
+
+pub(crate) fn query_inbox_files(mut files: Vec<InboxFile>, query: &InboxQuery) -> InboxPage {
+  if let Some(filter) = query
+    .filter
+    .as_deref()
+    .map(str::trim)
+    .filter(|f| !f.is_empty())
+  {
+    let needle = filter.to_lowercase();
+    files.retain(|file| file.name.to_lowercase().contains(&needle));
+  }
+  files.sort_by(|a, b| {
+    let primary = match query.sort {
+      InboxSort::Name => std::cmp::Ordering::Equal,
+      InboxSort::Mtime => a.modified_at.cmp(&b.modified_at),
+      InboxSort::Size => a.size.cmp(&b.size),
+    };
+    let primary = if query.descending {
+      primary.reverse()
+    } else {
+      primary
+    };
+    let by_name = a.name.cmp(&b.name);
+    let by_name = if query.descending && query.sort == InboxSort::Name {
+      by_name.reverse()
+    } else {
+      by_name
+    };
+    primary.then(by_name)
+  });
+
+  let total = files.len();
+  let files = files
+    .into_iter()
+    .skip(query.offset)
+    .take(query.limit)
+    .collect();
+  InboxPage {
+    total,
+    offset: query.offset,
+    files,
+  }
+}
+
+pub(crate) fn exif_capture_date(path: &Path) -> Option<String> {
+  let file = fs::File::open(path).ok()?;
+  let mut reader = BufReader::new(file);
+  let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+  let field = exif
+    .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+    .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))?;
+  match &field.value {
+    exif::Value::Ascii(values) => {
+      let raw = values.first()?;
+      let datetime = exif::DateTime::from_ascii(raw).ok()?;
+      Some(format!(
+        "{:04}-{:02}-{:02}",
+        datetime.year, datetime.month, datetime.day
+      ))
+    }
+    _ => None,
+  }
+}
+
+pub(crate) fn file_date(path: &Path) -> String {
+  if let Some(date) = exif_capture_date(path) {
+    return date;
+  }
+  let modified = fs::metadata(path)
+    .and_then(|meta| meta.modified())
+    .map(chrono::DateTime::<chrono::Local>::from)
+    .unwrap_or_else(|_| chrono::Local::now());
+  modified.format("%Y-%m-%d").to_string()
+}
+
+// Built as an OsString so `{original}` keeps the bytes of a name that isn't
+// UTF-8.
+pub(crate) fn render_import_name(
+  template: &str,
+  date: &str,
+  original: impl AsRef<OsStr>,
+  store_id: &str,
+) -> OsString {
+  let original = original.as_ref();
+  let mut rendered = OsString::new();
+  for (index, part) in template.split("{original}").enumerate() {
+    if index > 0 {
+      match original.to_str() {
+        Some(original) => rendered.push(original.replace(['/', '\\'], "_")),
+        None => rendered.push(original),
+      }
+    }
+    rendered.push(
+      part
+        .replace("{date}", date)
+        .replace("{store}", store_id)
+        .replace(['/', '\\'], "_"),
+    );
+  }
+  match Path::new(original).extension() {
+    Some(ext) if Path::new(&rendered).extension() != Some(ext) => {
+      rendered.push(".");
+      rendered.push(ext);
+      rendered
+    }
+    _ => rendered,
+  }
+}
+
+// `name-N.ext` built from the raw name, so a name that isn't UTF-8 keeps its
+// bytes.
+pub(crate) fn unique_destination(dir: &Path, file_name: impl AsRef<OsStr>) -> PathBuf {
+  let file_name = file_name.as_ref();
+  let candidate = dir.join(file_name);
+  if !candidate.exists() {
+    return candidate;
+  }
+  let name = Path::new(file_name);
+  let stem = name.file_stem().unwrap_or(file_name);
+  let extension = name.extension();
+  let mut counter = 1;
+  loop {
+    let mut next = stem.to_os_string();
+    next.push(format!("-{}", counter));
+    if let Some(ext) = extension {
+      next.push(".");
+      next.push(ext);
+    }
+    let candidate = dir.join(next);
+    if !candidate.exists() {
+      return candidate;
+    }
+    counter += 1;
+  }
+}
+
+pub(crate) fn import_one(
+  source: &Path,
+  inbox: &Path,
+  store_id: &str,
+  template: Option<&str>,
+) -> Result<PathBuf, String> {
+  if !is_inbox_candidate(source) {
+    return Err("Not a receipt image or PDF".to_string());
+  }
+  let original = source.file_name().ok_or("Missing file name")?;
+  let file_name = match template {
+    Some(template) => render_import_name(template, &file_date(source), original, store_id),
+    None => original.to_os_string(),
+  };
+  let destination = unique_destination(inbox, &file_name);
+  fs::copy(source, &destination).map_err(|err| err.to_string())?;
+  Ok(destination)
+}
+
+pub(crate) fn import_paths(
+  config: &AppConfig,
+  store_id: &str,
+  paths: Vec<String>,
+  normalize_names: bool,
+) -> Result<Vec<ImportedFile>, AppError> {
+  require_store(config, store_id)?;
+  let inbox = Path::new(&config.receipts_root)
+    .join("inbox")
+    .join(store_id);
+  fs::create_dir_all(&inbox).map_err(|err| AppError::io(&inbox, err))?;
+  let template = if normalize_names {
+    Some(config.import_name_template.as_str())
+  } else {
+    None
+  };
+
+  let results = paths
+    .into_iter()
+    .map(
+      |source| match import_one(Path::new(&source), &inbox, store_id, template) {
+        Ok(destination) => ImportedFile {
+          source,
+          status: "imported".to_string(),
+          file_name: destination
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string()),
+          path: Some(destination.to_string_lossy().to_string()),
+          message: None,
+        },
+        Err(err) => ImportedFile {
+          source,
+          status: "skipped".to_string(),
+          file_name: None,
+          path: None,
+          message: Some(err),
+        },
+      },
+    )
+    .collect();
+  Ok(results)
+}
+
+pub(crate) fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Result<Vec<u8>, String> {
+  let mut out = Vec::new();
+  {
+    let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer
+      .write_image_data(rgba)
+      .map_err(|err| err.to_string())?;
+  }
+  Ok(out)
+}
+
+pub(crate) fn clipboard_file_paths(clipboard: &mut arboard::Clipboard) -> Vec<String> {
+  if let Ok(files) = clipboard.get().file_list() {
+    if !files.is_empty() {
+      return files
+        .into_iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    }
+  }
+  clipboard
+    .get_text()
+    .map(|text| {
+      text
+        .lines()
+        .map(|line| line.trim().trim_start_matches("file://").to_string())
+        .filter(|line| !line.is_empty() && Path::new(line).is_file())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+pub(crate) fn backlog_warning(
+  config: &AppConfig,
+  store: &StoreConfig,
+  count: usize,
+  oldest_days: Option<u64>,
+) -> Option<String> {
+  let mut reasons = Vec::new();
+  if let Some(limit) = store.backlog_warn_count.or(config.backlog_warn_count) {
+    if count >= limit {
+      reasons.push(format!("{} receipts pending (limit {})", count, limit));
+    }
+  }
+  if let (Some(limit), Some(days)) = (
+    store.backlog_warn_days.or(config.backlog_warn_days),
+    oldest_days,
+  ) {
+    if days >= limit {
+      reasons.push(format!(
+        "oldest receipt is {} days old (limit {})",
+        days, limit
+      ));
+    }
+  }
+  if reasons.is_empty() {
+    None
+  } else {
+    Some(reasons.join("; "))
+  }
+}
+
+pub(crate) fn compute_inbox_stats(inbox: &Path) -> InboxStats {
+  let dir_modified = fs::metadata(inbox).and_then(|meta| meta.modified()).ok();
+  let (files, error) = match scan_inbox_files(inbox) {
+    Ok(files) => (files, None),
+    Err(err) => (Vec::new(), Some(err)),
+  };
+  InboxStats {
+    count: files.len(),
+    oldest: files.iter().filter_map(|file| file.modified_at).min(),
+    dir_modified,
+    computed: Instant::now(),
+    computed_at: chrono::Utc::now().to_rfc3339(),
+    error,
+  }
+}
+
+pub(crate) fn cached_inbox_stats(
+  cache: &InboxCountCache,
+  config: &AppConfig,
+  store_id: &str,
+  force_refresh: bool,
+) -> (InboxStats, bool) {
+  let inbox = Path::new(&config.receipts_root)
+    .join("inbox")
+    .join(store_id);
+  let key = config.seen_key(store_id);
+  let ttl = Duration::from_secs(config.inbox_cache_ttl_secs);
+  if !force_refresh {
+    if let Ok(stats) = cache.0.lock() {
+      if let Some(cached) = stats.get(&key) {
+        let dir_modified = fs::metadata(&inbox).and_then(|meta| meta.modified()).ok();
+        if cached.computed.elapsed() < ttl && cached.dir_modified == dir_modified {
+          cache.1.record(true);
+          return (cached.clone(), true);
+        }
+      }
+    }
+  }
+  cache.1.record(false);
+  let fresh = compute_inbox_stats(&inbox);
+  if let Ok(mut stats) = cache.0.lock() {
+    stats.insert(key, fresh.clone());
+  }
+  (fresh, false)
+}
+
+// Inboxes are walked before the seen state is locked, so a slow mount doesn't
+// hold up other commands waiting on the state. Counts for an overridden root
+// leave the tray and backlog notifications alone.
+pub(crate) fn inbox_counts(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  force_refresh: bool,
+) -> Result<Vec<InboxCount>, AppError> {
+  let cache = app.state::<InboxCountCache>();
+  let stats = config
+    .stores
+    .iter()
+    .map(|store| cached_inbox_stats(&cache, config, &store.id, force_refresh).0)
+    .collect::<Vec<_>>();
+  let seen_state = app.state::<SeenStateStore>();
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  let mut state_changed = false;
+  let mut results = Vec::new();
+  for (store, stats) in config.stores.iter().zip(stats) {
+    let count = stats.count;
+    let oldest_days = stats
+      .oldest
+      .and_then(|oldest| oldest.elapsed().ok())
+      .map(|age| age.as_secs() / 86_400);
+    let warning = backlog_warning(config, store, count, oldest_days);
+
+    let seen = state.stores.entry(config.seen_key(&store.id)).or_default();
+    if warning.is_some() != seen.backlog_warning_notified {
+      seen.backlog_warning_notified = warning.is_some();
+      state_changed = true;
+      if let (Some(message), None) = (&warning, &config.root_override) {
+        let _ = app.emit_all(
+          "backlog-warning",
+          BacklogWarningEvent {
+            store_id: store.id.clone(),
+            count,
+            oldest_days,
+            message: message.clone(),
+          },
+        );
+      }
+    }
+
+    results.push(InboxCount {
+      store_id: store.id.clone(),
+      count,
+      backlog_warning: warning,
+      computed_at: stats.computed_at,
+      stale: false,
+      error: stats.error.as_ref().map(|err| {
+        let inbox = Path::new(&config.receipts_root)
+          .join("inbox")
+          .join(&store.id);
+        DirError::new(Some(&store.id), &inbox, err)
+      }),
+    });
+  }
+  if state_changed {
+    save_state(&state)?;
+  }
+  if config.root_override.is_none() {
+    refresh_tray_counts(app, config, &results);
+  }
+  Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+  use std::time::SystemTime;
+
+  fn inbox_fixture(count: usize) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let base = SystemTime::now() - Duration::from_secs(86_400);
+    for i in 0..count {
+      let ext = match i % 3 {
+        0 => "jpg",
+        1 => "png",
+        _ => "pdf",
+      };
+      let path = dir.path().join(format!("IMG_{:04}.{}", i, ext));
+      fs::write(&path, vec![0u8; (i * 7) % 50]).expect("write fixture");
+      let file = fs::File::options()
+        .write(true)
+        .open(&path)
+        .expect("open fixture");
+      file
+        .set_modified(base + Duration::from_secs(((i * 13) % 17) as u64 * 60))
+        .expect("set mtime");
+    }
+    fs::write(dir.path().join("notes.txt"), "ignored").expect("write junk");
+    dir
+  }
+
+  fn query(sort: InboxSort, descending: bool, offset: usize, limit: usize) -> InboxQuery {
+    InboxQuery {
+      sort,
+      descending,
+      offset,
+      limit,
+      filter: None,
+    }
+  }
+
+  #[test]
+  fn pages_cover_every_file_exactly_once() {
+    let dir = inbox_fixture(320);
+    let files = scan_inbox_files(dir.path()).expect("scan");
+    let mut seen = Vec::new();
+    let mut offset = 0;
+    loop {
+      let page = query_inbox_files(files.clone(), &query(InboxSort::Name, false, offset, 50));
+      assert_eq!(page.total, 320);
+      if page.files.is_empty() {
+        break;
+      }
+      seen.extend(page.files.into_iter().map(|file| file.name));
+      offset += 50;
+    }
+    let mut sorted = seen.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(seen, sorted);
+    assert_eq!(seen.len(), 320);
+  }
+
+  #[test]
+  fn sort_by_size_and_mtime_is_stable() {
+    let dir = inbox_fixture(300);
+    let files = scan_inbox_files(dir.path()).expect("scan");
+    for sort in [InboxSort::Size, InboxSort::Mtime] {
+      for descending in [false, true] {
+        let first = query_inbox_files(files.clone(), &query(sort, descending, 0, 300));
+        let mut reversed = files.clone();
+        reversed.reverse();
+        let second = query_inbox_files(reversed, &query(sort, descending, 0, 300));
+        let names = |page: &InboxPage| {
+          page
+            .files
+            .iter()
+            .map(|f| f.name.clone())
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&first), names(&second));
+        for pair in first.files.windows(2) {
+          let ordering = match sort {
+            InboxSort::Size => pair[0].size.cmp(&pair[1].size),
+            _ => pair[0].modified_at.cmp(&pair[1].modified_at),
+          };
+          let ordering = if descending {
+            ordering.reverse()
+          } else {
+            ordering
+          };
+          assert_ne!(ordering, std::cmp::Ordering::Greater);
+          if ordering == std::cmp::Ordering::Equal {
+            assert!(pair[0].name < pair[1].name);
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn filter_is_reflected_in_total() {
+    let dir = inbox_fixture(300);
+    let files = scan_inbox_files(dir.path()).expect("scan");
+    let mut filtered = query(InboxSort::Name, true, 0, 10);
+    filtered.filter = Some("img_01".to_string());
+    let page = query_inbox_files(files, &filtered);
+    assert_eq!(page.total, 100);
+    assert_eq!(page.files.len(), 10);
+    assert_eq!(page.files[0].name, "IMG_0199.png");
+    assert!(page
+      .files
+      .iter()
+      .all(|file| file.name.starts_with("IMG_01")));
+  }
+
+  #[test]
+  fn concurrent_reads_against_a_fixture_tree_agree() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let inbox = dir.path().join("inbox").join("lidl");
+    fs::create_dir_all(&inbox).expect("inbox");
+    for index in 0..25 {
+      fs::write(inbox.join(format!("receipt-{}.pdf", index)), b"%PDF-1.4").expect("receipt");
+    }
+    let runs = dir.path().join("_logs").join("runs");
+    fs::create_dir_all(&runs).expect("runs dir");
+    for day in 1..=9 {
+      let failures = if day % 3 == 0 { "{}" } else { "" };
+      fs::write(
+        runs.join(format!("2024010{}-093000.summary.json", day)),
+        format!(
+          r#"{{"run_id": "2024010{}-093000", "stores": ["lidl"], "failures": [{}]}}"#,
+          day, failures
+        ),
+      )
+      .expect("summary");
+    }
+    let mut config = badge_config("lidl");
+    config.receipts_root = dir.path().to_string_lossy().to_string();
+
+    let config = Arc::new(config);
+    let inbox_cache = Arc::new(InboxCountCache::default());
+    let issue_cache = Arc::new(IssueRunsCache::default());
+    let state = Arc::new(Mutex::new(SeenState::default()));
+    let workers = (0..8)
+      .map(|worker| {
+        let config = config.clone();
+        let inbox_cache = inbox_cache.clone();
+        let issue_cache = issue_cache.clone();
+        let state = state.clone();
+        thread::spawn(move || {
+          for round in 0..20 {
+            let force = (worker + round) % 4 == 0;
+            let (stats, _) = cached_inbox_stats(&inbox_cache, &config, "lidl", force);
+            assert_eq!(stats.count, 25);
+            assert_eq!(list_run_summaries(&config.receipts_root).len(), 9);
+            let (issue_runs, _) = cached_issue_runs(&issue_cache, &config);
+            let badges = unread_badges(&config, &issue_runs, &state.lock().unwrap());
+            assert_eq!(badges[0].failures_unread_count, 3);
+          }
+        })
+      })
+      .collect::<Vec<_>>();
+    for worker in workers {
+      worker.join().expect("worker panicked");
+    }
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn non_utf8_inbox_names_can_be_listed_staged_and_deleted() {
+    use std::os::unix::ffi::OsStrExt;
+    let root = tempfile::tempdir().expect("tempdir");
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.path().to_string_lossy().to_string();
+    let inbox = root.path().join("inbox").join("lidl");
+    fs::create_dir_all(&inbox).expect("inbox");
+    let raw = OsStr::from_bytes(b"receipt-\xff\xfe.jpg");
+    fs::write(inbox.join(raw), b"jpg").expect("raw name");
+    fs::write(inbox.join(":raw:41.png"), b"png").expect("prefixed name");
+    fs::write(inbox.join("plain.pdf"), b"pdf").expect("plain name");
+
+    let mut files = scan_inbox_files(&inbox).expect("scan");
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    let names = files
+      .iter()
+      .map(|file| file.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec![":raw:41.png", "plain.pdf", "receipt-\u{fffd}\u{fffd}.jpg"]
+    );
+    assert_eq!(files[1].id, "plain.pdf");
+    assert_eq!(files[2].id, ":raw:726563656970742dfffe2e6a7067");
+    assert_ne!(files[0].id, files[0].name);
+    for file in &files {
+      let path = resolve_inbox_file(&config, "lidl", &file.id).expect("resolves");
+      assert!(path.is_file());
+    }
+    assert_eq!(file_name_from_id(&files[2].id), raw);
+    assert_eq!(file_name_from_id(&files[0].id), ":raw:41.png");
+    assert!(resolve_inbox_file(&config, "lidl", &files[2].name).is_err());
+    assert!(resolve_inbox_file(&config, "lidl", ":raw:2e2e2f782e6a7067").is_err());
+
+    let staging =
+      stage_selected_files(&inbox, &[files[2].id.clone()], "20240101-000000").expect("staged");
+    assert!(staging.join(raw).is_file());
+    assert!(!inbox.join(raw).exists());
+    assert!(inbox.join(":raw:41.png").is_file());
+    assert!(inbox.join("plain.pdf").is_file());
+    assert_eq!(scan_inbox_files(&inbox).expect("scan").len(), 2);
+    assert_eq!(restore_staged_files(&staging).expect("restored"), 1);
+    assert!(inbox.join(raw).is_file());
+
+    delete_inbox_file_at(&config, "lidl", &files[2].id).expect("deleted");
+    delete_inbox_file_at(&config, "lidl", &files[0].id).expect("deleted");
+    assert!(!inbox.join(raw).exists());
+    let left = scan_inbox_files(&inbox).expect("scan");
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].name, "plain.pdf");
+  }
+
+  #[test]
+  fn disk_usage_is_cached_until_the_ttl_or_a_forced_refresh() {
+    let root = tempfile::tempdir().expect("root");
+    let inbox = root.path().join("inbox").join("lidl");
+    fs::create_dir_all(&inbox).expect("inbox");
+    fs::create_dir_all(root.path().join("_logs").join("runs")).expect("logs");
+    fs::write(inbox.join("a.jpg"), b"1234").expect("a");
+    fs::write(root.path().join("_logs").join("runs").join("r.log"), b"12").expect("log");
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.path().to_string_lossy().to_string();
+    let cache = DiskUsageCache::default();
+
+    let first = cached_disk_usage(&cache, &config, false);
+    assert_eq!(first.stores[0].inbox.files, 1);
+    assert_eq!(first.stores[0].total_bytes, 4);
+    assert_eq!(first.logs.bytes, 2);
+    assert!(!first.incomplete);
+
+    fs::write(inbox.join("b.jpg"), b"56").expect("b");
+    let cached = cached_disk_usage(&cache, &config, false);
+    assert_eq!(cached.computed_at, first.computed_at);
+    assert_eq!(cached.stores[0].inbox.files, 1);
+    // The lock isn't held between calls.
+    assert!(cache.0.try_lock().is_ok());
+
+    let forced = cached_disk_usage(&cache, &config, true);
+    assert_eq!(forced.stores[0].inbox.files, 2);
+    assert_eq!(forced.stores[0].inbox.bytes, 6);
+    assert_eq!(
+      cached_disk_usage(&cache, &config, false).stores[0]
+        .inbox
+        .files,
+      2
+    );
+  }
+
+  #[test]
+  fn inbox_files_read_as_base64_with_pdf_bytes_and_a_typed_size_limit() {
+    let root = tempfile::tempdir().expect("tempdir");
+    let inbox = root.path().join("inbox").join("lidl");
+    fs::create_dir_all(&inbox).expect("inbox");
+    fs::write(inbox.join("scan.pdf"), b"%PDF-1.4 body").expect("write");
+    fs::write(inbox.join("photo.jpg"), [0xFF, 0xD8, 0xFF, 0xD9]).expect("write");
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.path().to_string_lossy().to_string();
+
+    let content = read_inbox_file_at(&config, "lidl", "scan.pdf", 1024).expect("read");
+    assert_eq!(content.mime.as_deref(), Some("application/pdf"));
+    assert_eq!(content.bytes.as_deref(), Some(b"%PDF-1.4 body".as_slice()));
+    let value = serde_json::to_value(&content).expect("json");
+    assert_eq!(value["content_base64"], "JVBERi0xLjQgYm9keQ==");
+
+    let image = read_inbox_file_at(&config, "lidl", "photo.jpg", 1024).expect("read");
+    assert_eq!(image.mime.as_deref(), Some("image/jpeg"));
+    assert_eq!(image.content_base64, "/9j/2Q==");
+    let value = serde_json::to_value(&image).expect("json");
+    assert!(value.get("bytes").is_none());
+
+    match read_inbox_file_at(&config, "lidl", "scan.pdf", 4) {
+      Err(AppError::FileTooLarge {
+        size, max_bytes, ..
+      }) => assert_eq!((size, max_bytes), (13, 4)),
+      other => panic!("expected too_large, got {:?}", other.map(|c| c.size)),
+    }
+  }
+
+  fn set_mtime(path: &Path, mtime: SystemTime) {
+    fs::File::options()
+      .write(true)
+      .open(path)
+      .expect("open")
+      .set_modified(mtime)
+      .expect("set mtime");
+  }
+
+  #[test]
+  fn import_names_follow_the_template_and_never_overwrite() {
+    use chrono::TimeZone;
+    assert_eq!(
+      render_import_name(
+        "{date}_{store}_{original}",
+        "2024-01-05",
+        "IMG_1.JPG",
+        "lidl"
+      ),
+      "2024-01-05_lidl_IMG_1.JPG"
+    );
+    assert_eq!(
+      render_import_name("{store}/{date}", "2024-01-05", "scan.pdf", "lidl"),
+      "lidl_2024-01-05.pdf"
+    );
+
+    let root = tempfile::tempdir().expect("root");
+    let sources = tempfile::tempdir().expect("sources");
+    let photo = sources.path().join("IMG_1.jpg");
+    fs::write(&photo, b"jpeg").expect("photo");
+    let noon = chrono::Local
+      .with_ymd_and_hms(2024, 1, 5, 12, 0, 0)
+      .single()
+      .expect("local time");
+    set_mtime(&photo, noon.into());
+    fs::write(sources.path().join("notes.txt"), b"text").expect("notes");
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.path().to_string_lossy().to_string();
+    config.import_name_template = "{date}_{store}".to_string();
+    let paths = vec![
+      photo.to_string_lossy().to_string(),
+      photo.to_string_lossy().to_string(),
+      sources
+        .path()
+        .join("notes.txt")
+        .to_string_lossy()
+        .to_string(),
+    ];
+
+    let results = import_paths(&config, "lidl", paths, true).expect("import");
+    let names = results
+      .iter()
+      .map(|result| (result.status.as_str(), result.file_name.as_deref()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec![
+        ("imported", Some("2024-01-05_lidl.jpg")),
+        ("imported", Some("2024-01-05_lidl-1.jpg")),
+        ("skipped", None),
+      ]
+    );
+    assert!(matches!(
+      import_paths(&config, "aldi", Vec::new(), true),
+      Err(AppError::UnknownStore(_))
+    ));
+  }
+
+  #[test]
+  fn integrity_flags_empty_bad_and_truncated_files() {
+    let root = tempfile::tempdir().expect("root");
+    let inbox = root.path().join("inbox").join("lidl");
+    fs::create_dir_all(&inbox).expect("inbox");
+    fs::write(inbox.join("empty.jpg"), b"").expect("empty");
+    fs::write(inbox.join("fake.png"), b"not a png at all").expect("fake");
+    fs::write(inbox.join("fresh.pdf"), b"%PDF-1.4 half a document").expect("fresh");
+    fs::write(inbox.join("old.pdf"), b"%PDF-1.4 half a document").expect("old");
+    set_mtime(
+      &inbox.join("old.pdf"),
+      SystemTime::now() - Duration::from_secs(INTEGRITY_RECENT_SECS + 60),
+    );
+    fs::write(
+      inbox.join("good.jpg"),
+      [0xFF, 0xD8, 0xFF, 0xE0, 0, 0, 0xFF, 0xD9],
+    )
+    .expect("good");
+    fs::write(inbox.join("notes.txt"), b"skipped").expect("notes");
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.path().to_string_lossy().to_string();
+
+    let report = inbox_integrity(&config, "lidl");
+    assert_eq!(report.checked, 5);
+    let issues = report
+      .issues
+      .iter()
+      .map(|issue| {
+        (
+          issue.file_name.as_str(),
+          issue.issue.as_str(),
+          issue.suggested_action.as_str(),
+        )
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(
+      issues,
+      vec![
+        ("empty.jpg", "empty", "wait_for_sync"),
+        ("fake.png", "bad_header", "delete"),
+        ("fresh.pdf", "truncated", "wait_for_sync"),
+        ("old.pdf", "truncated", "retry"),
+      ]
+    );
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn sparse_files_count_as_placeholders() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("cloud.pdf");
+    fs::File::create(&path)
+      .expect("create")
+      .set_len(64 * 1024)
+      .expect("set_len");
+    let meta = fs::metadata(&path).expect("metadata");
+    // Some filesystems allocate eagerly; there's nothing to check there.
+    if std::os::unix::fs::MetadataExt::blocks(&meta) != 0 {
+      return;
+    }
+    assert!(is_placeholder(&meta));
+    let issue = check_file_integrity(&path).expect("issue");
+    assert_eq!(
+      (issue.issue.as_str(), issue.hydrated),
+      ("not_hydrated", false)
+    );
+    assert_eq!(issue.suggested_action, "hydrate");
+  }
+
+  #[test]
+  fn backlog_warning_uses_store_limits_over_the_global_ones() {
+    let mut config = badge_config("lidl");
+    config.backlog_warn_count = Some(10);
+    config.backlog_warn_days = Some(30);
+    let mut store = config.stores[0].clone();
+
+    assert_eq!(backlog_warning(&config, &store, 9, Some(29)), None);
+    assert_eq!(
+      backlog_warning(&config, &store, 10, Some(2)).as_deref(),
+      Some("10 receipts pending (limit 10)")
+    );
+
+    store.backlog_warn_days = Some(7);
+    assert_eq!(
+      backlog_warning(&config, &store, 12, Some(7)).as_deref(),
+      Some("12 receipts pending (limit 10); oldest receipt is 7 days old (limit 7)")
+    );
+    assert_eq!(backlog_warning(&config, &store, 0, None), None);
+  }
+
+  // Directories can only be opened as files on unix.
+  #[cfg(unix)]
+  fn set_dir_mtime(path: &Path, mtime: SystemTime) {
+    fs::File::open(path)
+      .expect("open dir")
+      .set_modified(mtime)
+      .expect("set dir mtime");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn inbox_stats_are_cached_until_the_folder_changes() {
+    let root = tempfile::tempdir().expect("root");
+    let inbox = root.path().join("inbox").join("lidl");
+    fs::create_dir_all(&inbox).expect("inbox");
+    fs::write(inbox.join("a.jpg"), b"a").expect("a");
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.path().to_string_lossy().to_string();
+    config.inbox_cache_ttl_secs = 3600;
+    let cache = InboxCountCache::default();
+
+    let (first, hit) = cached_inbox_stats(&cache, &config, "lidl", false);
+    assert_eq!((first.count, hit), (1, false));
+    let (again, hit) = cached_inbox_stats(&cache, &config, "lidl", false);
+    assert_eq!((again.count, hit), (1, true));
+
+    fs::write(inbox.join("b.jpg"), b"b").expect("b");
+    // Pin the folder mtime so the change can't land in the same tick.
+    set_dir_mtime(&inbox, SystemTime::now() + Duration::from_secs(5));
+    let (changed, hit) = cached_inbox_stats(&cache, &config, "lidl", false);
+    assert_eq!((changed.count, hit), (2, false));
+    let (_, hit) = cached_inbox_stats(&cache, &config, "lidl", true);
+    assert!(!hit);
+
+    config.inbox_cache_ttl_secs = 0;
+    let (_, hit) = cached_inbox_stats(&cache, &config, "lidl", false);
+    assert!(!hit);
+  }
+
+  #[test]
+  fn ensure_dirs_creates_once_and_checks_the_store() {
+    let root = tempfile::tempdir().expect("root");
+    let mut config = badge_config("lidl");
+    assert!(matches!(
+      ensure_dirs(&config, None),
+      Err(AppError::ConfigMissing(_))
+    ));
+    config.receipts_root = root.path().to_string_lossy().to_string();
+
+    let first = ensure_dirs(&config, None).expect("ensure");
+    assert_eq!((first.created.len(), first.existing.len()), (4, 0));
+    assert!(root.path().join("inbox").join("lidl").is_dir());
+    assert!(root.path().join("_logs").join("runs").is_dir());
+    let second = ensure_dirs(&config, Some("lidl")).expect("ensure store");
+    assert_eq!((second.created.len(), second.existing.len()), (0, 3));
+    assert!(matches!(
+      ensure_dirs(&config, Some("aldi")),
+      Err(AppError::UnknownStore(_))
+    ));
+  }
+
+  #[test]
+  fn latest_inbox_file_is_the_newest_with_a_stable_tie_break() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    assert!(latest_inbox_file(dir.path()).expect("empty").is_none());
+    assert!(latest_inbox_file(&dir.path().join("missing"))
+      .expect("missing")
+      .is_none());
+
+    let base = SystemTime::now() - Duration::from_secs(3600);
+    for (name, offset) in [("a.jpg", 0), ("c.jpg", 60), ("b.jpg", 60), ("z.txt", 120)] {
+      let path = dir.path().join(name);
+      fs::write(&path, b"x").expect("write");
+      set_mtime(&path, base + Duration::from_secs(offset));
+    }
+    let latest = latest_inbox_file(dir.path()).expect("scan").expect("file");
+    assert_eq!(latest.name, "b.jpg");
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn imports_keep_non_utf8_source_names() {
+    use std::os::unix::ffi::OsStrExt;
+    let sources = tempfile::tempdir().expect("sources");
+    let inbox = tempfile::tempdir().expect("inbox");
+    let raw = OsStr::from_bytes(b"bon-\xff.jpg");
+    let source = sources.path().join(raw);
+    fs::write(&source, b"jpeg").expect("source");
+
+    let copied = import_one(&source, inbox.path(), "lidl", None).expect("import");
+    assert_eq!(copied.file_name(), Some(raw));
+    let again = import_one(&source, inbox.path(), "lidl", None).expect("import again");
+    assert_eq!(
+      again.file_name(),
+      Some(OsStr::from_bytes(b"bon-\xff-1.jpg"))
+    );
+
+    let renamed = import_one(&source, inbox.path(), "lidl", Some("{store}_{original}"))
+      .expect("import with template");
+    assert_eq!(
+      renamed.file_name(),
+      Some(OsStr::from_bytes(b"lidl_bon-\xff.jpg"))
+    );
+  }
+}
@@ -0,0 +1,1235 @@
+use crate::*;
+use rusqlite::{params, Connection, OptionalExtension};
+
+// Bump when the tables below change; an index stamped with another version is
+// dropped and refilled from the summaries and processed/ on open.
+const INDEX_SCHEMA_VERSION: &str = "6";
+const INDEX_SCHEMA: &str = "
+  CREATE TABLE runs (
+    run_id TEXT PRIMARY KEY,
+    file_name TEXT NOT NULL,
+    file_mtime_ms INTEGER NOT NULL,
+    started_at TEXT,
+    result TEXT,
+    stores TEXT NOT NULL,
+    failure_count INTEGER NOT NULL,
+    warning_count INTEGER NOT NULL,
+    processed_count INTEGER,
+    summary TEXT NOT NULL
+  );
+  CREATE INDEX runs_by_mtime ON runs (file_mtime_ms DESC, file_name DESC);
+  CREATE INDEX runs_by_file ON runs (file_name);
+  CREATE TABLE run_issues (
+    run_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    store_id TEXT,
+    file TEXT,
+    detail TEXT NOT NULL
+  );
+  CREATE INDEX run_issues_by_run ON run_issues (run_id);
+  CREATE TABLE receipts (
+    run_id TEXT NOT NULL,
+    store_id TEXT,
+    file TEXT,
+    detail TEXT NOT NULL
+  );
+  CREATE INDEX receipts_by_run ON receipts (run_id);
+  CREATE TABLE receipt_totals (
+    store_id TEXT NOT NULL,
+    file_name TEXT NOT NULL,
+    file_mtime_ms INTEGER NOT NULL,
+    date TEXT,
+    date_estimated INTEGER NOT NULL,
+    total REAL,
+    currency TEXT,
+    PRIMARY KEY (store_id, file_name)
+  );
+  CREATE TABLE month_stats (
+    store_id TEXT NOT NULL,
+    month TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    receipt_count INTEGER NOT NULL,
+    priced_count INTEGER NOT NULL,
+    total_cents INTEGER NOT NULL,
+    PRIMARY KEY (store_id, month, currency)
+  );
+  CREATE TABLE ocr_text_files (
+    store_id TEXT NOT NULL,
+    file TEXT NOT NULL,
+    file_mtime_ms INTEGER NOT NULL,
+    PRIMARY KEY (store_id, file)
+  );
+";
+// Stamped into meta as `month_stats` once month_stats matches receipt_totals;
+// bump when `MonthStat::of` changes so existing buckets are recomputed.
+const MONTH_STATS_VERSION: &str = "1";
+// Full-text search over each receipt_totals row's merchant, item names and OCR
+// text, and over each line of the worker's `.txt` OCR files (listed in
+// ocr_text_files). `folded` is the text lowercased without diacritics
+// (`fold_text`), `text` the original for snippets. Created apart from the
+// tables above: a SQLite built without FTS5 still gets an index, and search
+// scans instead.
+const RECEIPT_SEARCH_SCHEMA: &str = "
+  CREATE VIRTUAL TABLE receipt_search USING fts5(
+    store_id UNINDEXED,
+    file_name UNINDEXED,
+    merchant UNINDEXED,
+    text UNINDEXED,
+    folded
+  );
+  CREATE VIRTUAL TABLE ocr_text_search USING fts5(
+    store_id UNINDEXED,
+    file UNINDEXED,
+    line UNINDEXED,
+    byte_offset UNINDEXED,
+    text UNINDEXED,
+    folded
+  );
+";
+
+// SQLite copy of the run summaries under `_logs/runs`, for queries that would
+// otherwise parse every summary file. The files stay the source of truth: the
+// index is refreshed from them and can always be rebuilt from scratch.
+#[derive(Default)]
+pub(crate) struct RunIndex(pub(crate) Mutex<Option<Connection>>);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub(crate) struct IndexSync {
+  pub(crate) added: usize,
+  pub(crate) updated: usize,
+  pub(crate) removed: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct IndexStats {
+  pub(crate) runs: usize,
+  pub(crate) issues: usize,
+  pub(crate) receipts: usize,
+  pub(crate) elapsed_ms: u64,
+}
+
+// Totals over the run summaries, for all stores or the runs that include one.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub(crate) struct RunStats {
+  pub(crate) runs: usize,
+  pub(crate) runs_with_failures: usize,
+  pub(crate) runs_with_warnings: usize,
+  pub(crate) failures: usize,
+  pub(crate) warnings: usize,
+  pub(crate) processed: usize,
+  // `index` or `scan`.
+  pub(crate) source: String,
+}
+
+pub(crate) fn open_run_index() -> Option<Connection> {
+  let path = state_path(INDEX_FILE)?;
+  if let Some(parent) = path.parent() {
+    let _ = fs::create_dir_all(parent);
+  }
+  match Connection::open(&path).and_then(|conn| prepare_index(&conn).map(|_| conn)) {
+    Ok(conn) => Some(conn),
+    Err(err) => {
+      warn!("run index unavailable at {}: {}", path.display(), err);
+      None
+    }
+  }
+}
+
+pub(crate) fn prepare_index(conn: &Connection) -> rusqlite::Result<()> {
+  conn.execute_batch(
+    "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+  )?;
+  if get_meta(conn, "schema_version")?.as_deref() == Some(INDEX_SCHEMA_VERSION) {
+    return Ok(());
+  }
+  conn.execute_batch(
+    "DROP TABLE IF EXISTS ocr_text_search;
+     DROP TABLE IF EXISTS ocr_text_files;
+     DROP TABLE IF EXISTS receipt_search;
+     DROP TABLE IF EXISTS month_stats;
+     DROP TABLE IF EXISTS receipt_totals;
+     DROP TABLE IF EXISTS receipts;
+     DROP TABLE IF EXISTS run_issues;
+     DROP TABLE IF EXISTS runs;
+     DELETE FROM meta;",
+  )?;
+  conn.execute_batch(INDEX_SCHEMA)?;
+  if let Err(err) = conn.execute_batch(RECEIPT_SEARCH_SCHEMA) {
+    warn!(
+      "receipt search will scan processed/ (no full-text index): {}",
+      err
+    );
+  }
+  set_meta(conn, "schema_version", INDEX_SCHEMA_VERSION)
+}
+
+pub(crate) fn has_receipt_search(conn: &Connection) -> bool {
+  conn
+    .query_row(
+      "SELECT 1 FROM sqlite_master WHERE name = 'receipt_search'",
+      [],
+      |_| Ok(()),
+    )
+    .optional()
+    .ok()
+    .flatten()
+    .is_some()
+}
+
+// Replaces the full-text row for one output; `value` None just removes it.
+pub(crate) fn index_receipt_search(
+  conn: &Connection,
+  store_id: &str,
+  file_name: &str,
+  value: Option<&Value>,
+) -> rusqlite::Result<()> {
+  conn.execute(
+    "DELETE FROM receipt_search WHERE store_id = ?1 AND file_name = ?2",
+    params![store_id, file_name],
+  )?;
+  if let Some(value) = value {
+    let (merchant, text) = receipt_search_text(value);
+    conn.execute(
+      "INSERT INTO receipt_search (store_id, file_name, merchant, text, folded)
+       VALUES (?1, ?2, ?3, ?4, ?5)",
+      params![store_id, file_name, merchant, text, fold_text(&text)],
+    )?;
+  }
+  Ok(())
+}
+
+pub(crate) fn get_meta(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+  conn
+    .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+      row.get(0)
+    })
+    .optional()
+}
+
+pub(crate) fn set_meta(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+  conn.execute(
+    "INSERT INTO meta (key, value) VALUES (?1, ?2)
+     ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+    params![key, value],
+  )?;
+  Ok(())
+}
+
+pub(crate) fn signature_text(signature: &RunsSignature) -> String {
+  format!("{}:{}", mtime_ms(signature.0), signature.1)
+}
+
+// -1 for an unknown mtime, so those runs sort last like they do on disk.
+pub(crate) fn mtime_ms(modified: Option<std::time::SystemTime>) -> i64 {
+  modified
+    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+    .map(|age| age.as_millis() as i64)
+    .unwrap_or(-1)
+}
+
+pub(crate) fn summary_files(runs_dir: &Path) -> HashMap<String, i64> {
+  let entries = match fs::read_dir(runs_dir) {
+    Ok(entries) => entries,
+    Err(_) => return HashMap::new(),
+  };
+  entries
+    .flatten()
+    .filter_map(|entry| {
+      let name = entry.file_name();
+      if !name.to_string_lossy().ends_with(".summary.json") {
+        return None;
+      }
+      let modified = fs::metadata(entry.path())
+        .and_then(|meta| meta.modified())
+        .ok();
+      Some((file_name_id(&name), mtime_ms(modified)))
+    })
+    .collect()
+}
+
+// Brings the index in line with the summary files: new and changed files are
+// (re)parsed, rows for deleted files dropped. Pointing it at another root
+// starts over.
+pub(crate) fn sync_index(
+  conn: &mut Connection,
+  receipts_root: &str,
+) -> rusqlite::Result<IndexSync> {
+  let runs_dir = Path::new(receipts_root).join("_logs").join("runs");
+  let signature = runs_signature_for(receipts_root);
+  let tx = conn.transaction()?;
+  if get_meta(&tx, "receipts_root")?.as_deref() != Some(receipts_root) {
+    tx.execute_batch(
+      "DELETE FROM receipts; DELETE FROM run_issues; DELETE FROM runs;
+       DELETE FROM receipt_totals; DELETE FROM month_stats; DELETE FROM ocr_text_files;
+       DELETE FROM meta WHERE key IN ('receipt_totals_root', 'month_stats', 'ocr_text_root');",
+    )?;
+    if has_receipt_search(&tx) {
+      tx.execute("DELETE FROM receipt_search", [])?;
+      tx.execute("DELETE FROM ocr_text_search", [])?;
+    }
+    set_meta(&tx, "receipts_root", receipts_root)?;
+  }
+  let indexed = {
+    let mut statement = tx.prepare("SELECT file_name, file_mtime_ms FROM runs")?;
+    let rows = statement.query_map([], |row| {
+      Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    rows.collect::<rusqlite::Result<HashMap<_, _>>>()?
+  };
+  let on_disk = summary_files(&runs_dir);
+  let mut sync = IndexSync::default();
+  for (name, mtime) in &on_disk {
+    match indexed.get(name) {
+      Some(indexed_mtime) if indexed_mtime == mtime => continue,
+      Some(_) => sync.updated += 1,
+      None => sync.added += 1,
+    }
+    remove_indexed_file(&tx, name)?;
+    let value = fs::read_to_string(join_file_id(&runs_dir, name))
+      .ok()
+      .and_then(|raw| serde_json::from_str::<Value>(&raw).ok());
+    if let Some(value) = value {
+      insert_summary(&tx, name, *mtime, &value)?;
+    }
+  }
+  for name in indexed.keys().filter(|name| !on_disk.contains_key(*name)) {
+    remove_indexed_file(&tx, name)?;
+    sync.removed += 1;
+  }
+  set_meta(&tx, "runs_signature", &signature_text(&signature))?;
+  tx.commit()?;
+  Ok(sync)
+}
+
+pub(crate) fn receipt_output_files(dir: &Path) -> HashMap<String, i64> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return HashMap::new(),
+  };
+  entries
+    .flatten()
+    .filter_map(|entry| {
+      let name = entry.file_name();
+      if !is_receipt_output(&name.to_string_lossy()) {
+        return None;
+      }
+      let modified = fs::metadata(entry.path())
+        .and_then(|meta| meta.modified())
+        .ok();
+      Some((file_name_id(&name), mtime_ms(modified)))
+    })
+    .collect()
+}
+
+pub(crate) fn receipt_total_row(
+  store_id: &str,
+  row: &rusqlite::Row,
+) -> rusqlite::Result<ReceiptTotal> {
+  Ok(ReceiptTotal {
+    store_id: store_id.to_string(),
+    file_name: row.get(0)?,
+    date: row
+      .get::<_, Option<String>>(1)?
+      .and_then(|date| chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+    date_estimated: row.get(2)?,
+    total: row.get(3)?,
+    currency: row.get(4)?,
+  })
+}
+
+// Adds one receipt's bucket to month_stats (`sign` 1) or takes it out (-1);
+// buckets left without receipts are dropped.
+pub(crate) fn add_month_stat(
+  conn: &Connection,
+  stat: &MonthStat,
+  sign: i64,
+) -> rusqlite::Result<()> {
+  conn.execute(
+    "INSERT INTO month_stats
+       (store_id, month, currency, receipt_count, priced_count, total_cents)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+     ON CONFLICT (store_id, month, currency) DO UPDATE SET
+       receipt_count = receipt_count + excluded.receipt_count,
+       priced_count = priced_count + excluded.priced_count,
+       total_cents = total_cents + excluded.total_cents",
+    params![
+      stat.store_id,
+      stat.month,
+      stat.currency,
+      stat.receipt_count as i64 * sign,
+      stat.priced_count as i64 * sign,
+      stat.total_cents * sign
+    ],
+  )?;
+  conn.execute(
+    "DELETE FROM month_stats
+     WHERE store_id = ?1 AND month = ?2 AND currency = ?3 AND receipt_count <= 0",
+    params![stat.store_id, stat.month, stat.currency],
+  )?;
+  Ok(())
+}
+
+// Whether month_stats can be updated in place: stamped with this version and
+// still counting exactly the receipt_totals rows. Anything else (an older
+// stamp, a half-applied change, a hand-edited database) means a rebuild.
+pub(crate) fn month_stats_usable(conn: &Connection) -> rusqlite::Result<bool> {
+  if get_meta(conn, "month_stats")?.as_deref() != Some(MONTH_STATS_VERSION) {
+    return Ok(false);
+  }
+  let (bucketed, negative) = conn.query_row(
+    "SELECT COALESCE(SUM(receipt_count), 0),
+       COALESCE(SUM(priced_count < 0 OR priced_count > receipt_count), 0)
+     FROM month_stats",
+    [],
+    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+  )?;
+  let receipts = conn.query_row("SELECT COUNT(*) FROM receipt_totals", [], |row| {
+    row.get::<_, i64>(0)
+  })?;
+  Ok(bucketed == receipts && negative == 0)
+}
+
+// Recomputes month_stats from every receipt_totals row.
+pub(crate) fn rebuild_month_stats(conn: &Connection) -> rusqlite::Result<usize> {
+  conn.execute("DELETE FROM month_stats", [])?;
+  let receipts = {
+    let mut statement = conn.prepare(
+      "SELECT file_name, date, date_estimated, total, currency, store_id FROM receipt_totals
+       ORDER BY store_id, file_name",
+    )?;
+    let rows = statement.query_map([], |row| {
+      let store_id = row.get::<_, String>(5)?;
+      receipt_total_row(&store_id, row)
+    })?;
+    rows.collect::<rusqlite::Result<Vec<_>>>()?
+  };
+  for receipt in &receipts {
+    add_month_stat(conn, &MonthStat::of(receipt), 1)?;
+  }
+  set_meta(conn, "month_stats", MONTH_STATS_VERSION)?;
+  Ok(receipts.len())
+}
+
+// Re-reads the processed/ outputs of these stores that are new or changed
+// since the last call and drops rows for removed ones. The full-text rows,
+// when there is a table for them, follow along, and so does month_stats: each
+// changed row's old bucket is taken out and its new one added, unless the
+// buckets are unusable, in which case they are rebuilt at the end.
+pub(crate) fn sync_receipt_totals(
+  tx: &rusqlite::Transaction,
+  receipts_root: &str,
+  store_ids: &[String],
+) -> rusqlite::Result<()> {
+  let search = has_receipt_search(tx);
+  if get_meta(tx, "receipt_totals_root")?.as_deref() != Some(receipts_root) {
+    tx.execute("DELETE FROM receipt_totals", [])?;
+    tx.execute("DELETE FROM month_stats", [])?;
+    if search {
+      tx.execute("DELETE FROM receipt_search", [])?;
+    }
+    set_meta(tx, "receipt_totals_root", receipts_root)?;
+  }
+  let stats = month_stats_usable(tx)?;
+  let previous = |store_id: &str, name: &str| -> rusqlite::Result<Option<ReceiptTotal>> {
+    tx.query_row(
+      "SELECT file_name, date, date_estimated, total, currency FROM receipt_totals
+       WHERE store_id = ?1 AND file_name = ?2",
+      params![store_id, name],
+      |row| receipt_total_row(store_id, row),
+    )
+    .optional()
+  };
+  for store_id in store_ids {
+    let dir = processed_dir(receipts_root, store_id);
+    let indexed = {
+      let mut statement =
+        tx.prepare("SELECT file_name, file_mtime_ms FROM receipt_totals WHERE store_id = ?1")?;
+      let rows = statement.query_map([store_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+      })?;
+      rows.collect::<rusqlite::Result<HashMap<_, _>>>()?
+    };
+    let on_disk = receipt_output_files(&dir);
+    for (name, mtime) in &on_disk {
+      if indexed.get(name) == Some(mtime) {
+        continue;
+      }
+      let path = join_file_id(&dir, name);
+      let value = fs::read_to_string(&path)
+        .ok()
+        .map(|raw| serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null));
+      let receipt = value
+        .as_ref()
+        .and_then(|value| receipt_total_from(store_id, &path, value));
+      if search {
+        index_receipt_search(
+          tx,
+          store_id,
+          name,
+          value.as_ref().filter(|_| receipt.is_some()),
+        )?;
+      }
+      if stats {
+        if let Some(old) = previous(store_id, name)? {
+          add_month_stat(tx, &MonthStat::of(&old), -1)?;
+        }
+        if let Some(receipt) = &receipt {
+          add_month_stat(tx, &MonthStat::of(receipt), 1)?;
+        }
+      }
+      match receipt {
+        Some(receipt) => {
+          tx.execute(
+            "INSERT OR REPLACE INTO receipt_totals
+               (store_id, file_name, file_mtime_ms, date, date_estimated, total, currency)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+              store_id,
+              name,
+              mtime,
+              receipt.date.map(|date| date.to_string()),
+              receipt.date_estimated,
+              receipt.total,
+              receipt.currency
+            ],
+          )?;
+        }
+        None => {
+          tx.execute(
+            "DELETE FROM receipt_totals WHERE store_id = ?1 AND file_name = ?2",
+            params![store_id, name],
+          )?;
+        }
+      }
+    }
+    for name in indexed.keys().filter(|name| !on_disk.contains_key(*name)) {
+      if stats {
+        if let Some(old) = previous(store_id, name)? {
+          add_month_stat(tx, &MonthStat::of(&old), -1)?;
+        }
+      }
+      tx.execute(
+        "DELETE FROM receipt_totals WHERE store_id = ?1 AND file_name = ?2",
+        params![store_id, name],
+      )?;
+      if search {
+        index_receipt_search(tx, store_id, name, None)?;
+      }
+    }
+  }
+  if !stats {
+    let receipts = rebuild_month_stats(tx)?;
+    debug!("month stats rebuilt over {} receipts", receipts);
+  }
+  Ok(())
+}
+
+// Re-reads the `.txt` files under these stores' processed/ folders that are
+// new or changed since the last call, one ocr_text_search row per line, and
+// drops the lines of removed ones. Only called when the full-text tables exist.
+pub(crate) fn sync_ocr_text(
+  tx: &rusqlite::Transaction,
+  receipts_root: &str,
+  store_ids: &[String],
+) -> rusqlite::Result<()> {
+  if get_meta(tx, "ocr_text_root")?.as_deref() != Some(receipts_root) {
+    tx.execute_batch("DELETE FROM ocr_text_files; DELETE FROM ocr_text_search;")?;
+    set_meta(tx, "ocr_text_root", receipts_root)?;
+  }
+  let forget = |store_id: &str, file: &str| -> rusqlite::Result<()> {
+    for table in ["ocr_text_files", "ocr_text_search"] {
+      tx.execute(
+        &format!("DELETE FROM {} WHERE store_id = ?1 AND file = ?2", table),
+        params![store_id, file],
+      )?;
+    }
+    Ok(())
+  };
+  for store_id in store_ids {
+    let indexed = {
+      let mut statement =
+        tx.prepare("SELECT file, file_mtime_ms FROM ocr_text_files WHERE store_id = ?1")?;
+      let rows = statement.query_map([store_id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+      })?;
+      rows.collect::<rusqlite::Result<HashMap<_, _>>>()?
+    };
+    let on_disk = list_ocr_text_files(&processed_dir(receipts_root, store_id));
+    for (file, path, mtime) in &on_disk {
+      if indexed.get(file) == Some(mtime) {
+        continue;
+      }
+      forget(store_id, file)?;
+      for (line, offset, text) in ocr_text_lines(path).unwrap_or_default() {
+        tx.execute(
+          "INSERT INTO ocr_text_search (store_id, file, line, byte_offset, text, folded)
+           VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+          params![
+            store_id,
+            file,
+            line as i64,
+            offset as i64,
+            text,
+            fold_text(&text)
+          ],
+        )?;
+      }
+      tx.execute(
+        "INSERT INTO ocr_text_files (store_id, file, file_mtime_ms) VALUES (?1, ?2, ?3)",
+        params![store_id, file, mtime],
+      )?;
+    }
+    for file in indexed
+      .keys()
+      .filter(|file| !on_disk.iter().any(|(name, _, _)| name == *file))
+    {
+      forget(store_id, file)?;
+    }
+  }
+  Ok(())
+}
+
+// Syncs receipt_totals (see `sync_receipt_totals`) and returns the rows of
+// these stores.
+pub(crate) fn indexed_receipt_totals(
+  conn: &mut Connection,
+  receipts_root: &str,
+  store_ids: &[String],
+) -> rusqlite::Result<Vec<ReceiptTotal>> {
+  let tx = conn.transaction()?;
+  sync_receipt_totals(&tx, receipts_root, store_ids)?;
+  let mut receipts = Vec::new();
+  for store_id in store_ids {
+    let mut statement = tx.prepare(
+      "SELECT file_name, date, date_estimated, total, currency FROM receipt_totals
+       WHERE store_id = ?1",
+    )?;
+    let rows = statement.query_map([store_id], |row| receipt_total_row(store_id, row))?;
+    for receipt in rows {
+      receipts.push(receipt?);
+    }
+  }
+  tx.commit()?;
+  Ok(receipts)
+}
+
+// Syncs receipt_totals and returns these stores' month_stats buckets, so a
+// report reads one row per store, month and currency instead of one per
+// receipt.
+pub(crate) fn indexed_month_stats(
+  conn: &mut Connection,
+  receipts_root: &str,
+  store_ids: &[String],
+) -> rusqlite::Result<Vec<MonthStat>> {
+  let tx = conn.transaction()?;
+  sync_receipt_totals(&tx, receipts_root, store_ids)?;
+  let mut stats = Vec::new();
+  for store_id in store_ids {
+    let mut statement = tx.prepare(
+      "SELECT month, currency, receipt_count, priced_count, total_cents FROM month_stats
+       WHERE store_id = ?1 ORDER BY month, currency",
+    )?;
+    let rows = statement.query_map([store_id], |row| {
+      Ok(MonthStat {
+        store_id: store_id.clone(),
+        month: row.get(0)?,
+        currency: row.get(1)?,
+        receipt_count: row.get::<_, i64>(2)? as usize,
+        priced_count: row.get::<_, i64>(3)? as usize,
+        total_cents: row.get(4)?,
+      })
+    })?;
+    for stat in rows {
+      stats.push(stat?);
+    }
+  }
+  tx.commit()?;
+  Ok(stats)
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct StatsCacheRebuild {
+  pub(crate) buckets: usize,
+  pub(crate) receipts: usize,
+  pub(crate) elapsed_ms: u64,
+}
+
+// Drops the month_stats stamp so the next sync recomputes every bucket, then
+// runs it.
+pub(crate) fn rebuild_stats_cache_at(
+  conn: &mut Connection,
+  receipts_root: &str,
+  store_ids: &[String],
+) -> rusqlite::Result<StatsCacheRebuild> {
+  let started = Instant::now();
+  conn.execute("DELETE FROM meta WHERE key = 'month_stats'", [])?;
+  let stats = indexed_month_stats(conn, receipts_root, store_ids)?;
+  Ok(StatsCacheRebuild {
+    buckets: stats.len(),
+    receipts: stats.iter().map(|stat| stat.receipt_count).sum(),
+    elapsed_ms: started.elapsed().as_millis() as u64,
+  })
+}
+
+pub(crate) fn rebuild_index_at(
+  conn: &mut Connection,
+  receipts_root: &str,
+) -> rusqlite::Result<IndexStats> {
+  let started = Instant::now();
+  conn.execute("DELETE FROM meta WHERE key = 'receipts_root'", [])?;
+  sync_index(conn, receipts_root)?;
+  let count = |table: &str| -> rusqlite::Result<usize> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+      row.get::<_, i64>(0).map(|count| count as usize)
+    })
+  };
+  Ok(IndexStats {
+    runs: count("runs")?,
+    issues: count("run_issues")?,
+    receipts: count("receipts")?,
+    elapsed_ms: started.elapsed().as_millis() as u64,
+  })
+}
+
+pub(crate) fn remove_indexed_file(conn: &Connection, file_name: &str) -> rusqlite::Result<()> {
+  for table in ["run_issues", "receipts"] {
+    conn.execute(
+      &format!(
+        "DELETE FROM {} WHERE run_id IN (SELECT run_id FROM runs WHERE file_name = ?1)",
+        table
+      ),
+      [file_name],
+    )?;
+  }
+  conn.execute("DELETE FROM runs WHERE file_name = ?1", [file_name])?;
+  Ok(())
+}
+
+// Summaries without a run id are skipped, as they are by the file-based readers.
+pub(crate) fn insert_summary(
+  conn: &Connection,
+  file_name: &str,
+  mtime: i64,
+  value: &Value,
+) -> rusqlite::Result<()> {
+  let run_id = match extract_run_id(value) {
+    Some(run_id) => run_id,
+    None => return Ok(()),
+  };
+  let text = |key: &str| {
+    value
+      .get(key)
+      .and_then(|field| field.as_str())
+      .map(str::to_string)
+  };
+  conn.execute(
+    "INSERT OR REPLACE INTO runs (run_id, file_name, file_mtime_ms, started_at, result, stores,
+       failure_count, warning_count, processed_count, summary)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    params![
+      run_id,
+      file_name,
+      mtime,
+      text("started_at"),
+      text("result"),
+      Value::from(extract_stores(value)).to_string(),
+      extract_issue_count(value, "failures") as i64,
+      extract_issue_count(value, "warnings") as i64,
+      extract_processed_count(value).map(|count| count as i64),
+      value.to_string(),
+    ],
+  )?;
+  let entries = |key: &str| {
+    value
+      .get(key)
+      .and_then(|entries| entries.as_array())
+      .cloned()
+      .unwrap_or_default()
+  };
+  let entry_text = |entry: &Value, key: &str| {
+    entry
+      .get(key)
+      .and_then(|field| field.as_str())
+      .map(str::to_string)
+  };
+  for kind in ["failures", "warnings"] {
+    for entry in entries(kind) {
+      conn.execute(
+        "INSERT INTO run_issues (run_id, kind, store_id, file, detail) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+          run_id,
+          kind,
+          entry_text(&entry, "store"),
+          entry_text(&entry, "file"),
+          entry.to_string()
+        ],
+      )?;
+    }
+  }
+  for entry in entries("processed") {
+    conn.execute(
+      "INSERT INTO receipts (run_id, store_id, file, detail) VALUES (?1, ?2, ?3, ?4)",
+      params![
+        run_id,
+        entry_text(&entry, "store"),
+        entry_text(&entry, "file"),
+        entry.to_string()
+      ],
+    )?;
+  }
+  Ok(())
+}
+
+// The index is fresh when it was last synced against this root and the runs
+// folder still has the same signature.
+pub(crate) fn index_is_fresh(conn: &Connection, receipts_root: &str) -> bool {
+  let signature = signature_text(&runs_signature_for(receipts_root));
+  get_meta(conn, "receipts_root").ok().flatten().as_deref() == Some(receipts_root)
+    && get_meta(conn, "runs_signature").ok().flatten() == Some(signature)
+}
+
+// Same order as `latest_run_summaries`: newest summary file first.
+pub(crate) fn indexed_latest_runs(conn: &Connection, limit: usize) -> rusqlite::Result<Vec<Value>> {
+  let mut statement = conn
+    .prepare("SELECT summary FROM runs ORDER BY file_mtime_ms DESC, file_name DESC LIMIT ?1")?;
+  let rows = statement.query_map([limit as i64], |row| row.get::<_, String>(0))?;
+  let mut runs = Vec::new();
+  for raw in rows {
+    if let Ok(value) = serde_json::from_str(&raw?) {
+      runs.push(value);
+    }
+  }
+  Ok(runs)
+}
+
+pub(crate) fn indexed_run_stats(
+  conn: &Connection,
+  store_id: Option<&str>,
+) -> rusqlite::Result<RunStats> {
+  conn.query_row(
+    "SELECT COUNT(*), COALESCE(SUM(failure_count > 0), 0), COALESCE(SUM(warning_count > 0), 0),
+       COALESCE(SUM(failure_count), 0), COALESCE(SUM(warning_count), 0),
+       COALESCE(SUM(processed_count), 0)
+     FROM runs
+     WHERE ?1 IS NULL OR EXISTS (SELECT 1 FROM json_each(runs.stores) WHERE value = ?1)",
+    [store_id],
+    |row| {
+      Ok(RunStats {
+        runs: row.get::<_, i64>(0)? as usize,
+        runs_with_failures: row.get::<_, i64>(1)? as usize,
+        runs_with_warnings: row.get::<_, i64>(2)? as usize,
+        failures: row.get::<_, i64>(3)? as usize,
+        warnings: row.get::<_, i64>(4)? as usize,
+        processed: row.get::<_, i64>(5)? as usize,
+        source: "index".to_string(),
+      })
+    },
+  )
+}
+
+// The same totals from the summary files; summaries without a run id are
+// skipped, as the index skips them.
+pub(crate) fn scanned_run_stats(summaries: &[Value], store_id: Option<&str>) -> RunStats {
+  let mut stats = RunStats {
+    source: "scan".to_string(),
+    ..RunStats::default()
+  };
+  for value in summaries {
+    if extract_run_id(value).is_none() {
+      continue;
+    }
+    if store_id.is_some_and(|store_id| !extract_stores(value).iter().any(|s| s == store_id)) {
+      continue;
+    }
+    let failures = extract_issue_count(value, "failures");
+    let warnings = extract_issue_count(value, "warnings");
+    stats.runs += 1;
+    stats.runs_with_failures += usize::from(failures > 0);
+    stats.runs_with_warnings += usize::from(warnings > 0);
+    stats.failures += failures;
+    stats.warnings += warnings;
+    stats.processed += extract_processed_count(value).unwrap_or(0);
+  }
+  stats
+}
+
+// Match on the run id, result, stores, and the file and detail of each
+// failure, warning and processed receipt. Case is folded for ASCII only, as
+// SQLite's LIKE does.
+pub(crate) fn run_matches(value: &Value, needle: &str) -> bool {
+  let needle = needle.to_ascii_lowercase();
+  let contains = |text: &str| text.to_ascii_lowercase().contains(&needle);
+  let field = |key: &str| value.get(key).and_then(|field| field.as_str());
+  if extract_run_id(value).is_none() {
+    return false;
+  }
+  if field("run_id").is_some_and(contains)
+    || field("result").is_some_and(contains)
+    || extract_stores(value).iter().any(|store| contains(store))
+  {
+    return true;
+  }
+  ["failures", "warnings", "processed"].iter().any(|key| {
+    value
+      .get(*key)
+      .and_then(|entries| entries.as_array())
+      .is_some_and(|entries| entries.iter().any(|entry| contains(&entry.to_string())))
+  })
+}
+
+// LIKE narrows the rows to summaries containing the text; `run_matches` then
+// applies the same rule as a scan. Newest first, as `indexed_latest_runs`.
+pub(crate) fn indexed_search_runs(
+  conn: &Connection,
+  query: &str,
+  limit: usize,
+) -> rusqlite::Result<Vec<Value>> {
+  let pattern = format!(
+    "%{}%",
+    query
+      .replace('\\', "\\\\")
+      .replace('%', "\\%")
+      .replace('_', "\\_")
+  );
+  let mut statement = conn.prepare(
+    "SELECT summary FROM runs WHERE summary LIKE ?1 ESCAPE '\\'
+     ORDER BY file_mtime_ms DESC, file_name DESC",
+  )?;
+  let rows = statement.query_map([pattern], |row| row.get::<_, String>(0))?;
+  let mut runs = Vec::new();
+  for raw in rows {
+    if runs.len() >= limit {
+      break;
+    }
+    if let Ok(value) = serde_json::from_str::<Value>(&raw?) {
+      if run_matches(&value, query) {
+        runs.push(value);
+      }
+    }
+  }
+  Ok(runs)
+}
+
+// Runs `query` on the index when it is fresh for this root; None means the
+// caller reads the summary files instead.
+pub(crate) fn with_fresh_index<T>(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  query: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+) -> Option<T> {
+  let index = app.state::<RunIndex>();
+  let conn = index.0.lock().ok()?;
+  let conn = conn.as_ref()?;
+  if !index_is_fresh(conn, &config.receipts_root) {
+    return None;
+  }
+  query(conn)
+    .map_err(|err| warn!("run index query failed: {}", err))
+    .ok()
+}
+
+// Served from the index when it is fresh, else by reading the summary files.
+pub(crate) fn latest_runs(app: &tauri::AppHandle, config: &AppConfig, limit: usize) -> Vec<Value> {
+  with_fresh_index(app, config, |conn| indexed_latest_runs(conn, limit))
+    .unwrap_or_else(|| latest_run_summaries(config, limit))
+}
+
+pub(crate) fn run_stats(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  store_id: Option<&str>,
+) -> RunStats {
+  with_fresh_index(app, config, |conn| indexed_run_stats(conn, store_id))
+    .unwrap_or_else(|| scanned_run_stats(&latest_run_summaries(config, usize::MAX), store_id))
+}
+
+pub(crate) fn search_runs_in(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  query: &str,
+  limit: usize,
+) -> Vec<Value> {
+  with_fresh_index(app, config, |conn| indexed_search_runs(conn, query, limit)).unwrap_or_else(
+    || {
+      latest_run_summaries(config, usize::MAX)
+        .into_iter()
+        .filter(|value| run_matches(value, query))
+        .take(limit)
+        .collect()
+    },
+  )
+}
+
+pub(crate) fn sync_run_index(app: &tauri::AppHandle, config: &AppConfig) {
+  if require_receipts_root(config).is_err() {
+    return;
+  }
+  let index = app.state::<RunIndex>();
+  let mut conn = match index.0.lock() {
+    Ok(conn) => conn,
+    Err(_) => return,
+  };
+  if let Some(conn) = conn.as_mut() {
+    match sync_index(conn, &config.receipts_root) {
+      Ok(sync) if sync != IndexSync::default() => debug!("run index sync: {:?}", sync),
+      Ok(_) => {}
+      Err(err) => warn!("run index sync failed: {}", err),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn run_index_matches_the_summary_files_and_syncs_incrementally() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let runs = dir.path().join("_logs").join("runs");
+    fs::create_dir_all(&runs).expect("runs dir");
+    let epoch = std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    for n in 0..5000u64 {
+      let path = runs.join(format!("run-{:05}.summary.json", n));
+      let failures = if n % 7 == 0 {
+        serde_json::json!([{"store": "lidl", "file": "a.pdf"}])
+      } else {
+        serde_json::json!([])
+      };
+      let summary = serde_json::json!({
+        "run_id": format!("run-{:05}", n),
+        "stores": ["lidl"],
+        "failures": failures,
+        "processed": [{"store": "lidl", "file": format!("{}.pdf", n)}],
+      });
+      fs::write(&path, summary.to_string()).expect("summary");
+      fs::File::options()
+        .write(true)
+        .open(&path)
+        .and_then(|file| file.set_modified(epoch + Duration::from_secs(n)))
+        .expect("mtime");
+    }
+    let root = dir.path().to_string_lossy().to_string();
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.clone();
+
+    let mut conn = rusqlite::Connection::open_in_memory().expect("index");
+    prepare_index(&conn).expect("schema");
+    let stats = rebuild_index_at(&mut conn, &root).expect("rebuild");
+    assert_eq!(
+      (stats.runs, stats.issues, stats.receipts),
+      (5000, 715, 5000)
+    );
+    assert!(index_is_fresh(&conn, &root));
+
+    let started = Instant::now();
+    let walked = latest_run_summaries(&config, DASHBOARD_LAST_RUNS);
+    let walk_time = started.elapsed();
+    let started = Instant::now();
+    let indexed = indexed_latest_runs(&conn, DASHBOARD_LAST_RUNS).expect("query");
+    let query_time = started.elapsed();
+    assert_eq!(indexed, walked);
+    // One query against reading 5k summary files.
+    assert!(query_time < walk_time);
+    assert_eq!(indexed[0]["run_id"], "run-04999");
+
+    fs::remove_file(runs.join("run-04999.summary.json")).expect("remove");
+    fs::write(
+      runs.join("run-05000.summary.json"),
+      r#"{"run_id": "run-05000"}"#,
+    )
+    .expect("add");
+    assert!(!index_is_fresh(&conn, &root));
+    let sync = sync_index(&mut conn, &root).expect("sync");
+    assert_eq!(
+      sync,
+      IndexSync {
+        added: 1,
+        updated: 0,
+        removed: 1
+      }
+    );
+    assert!(index_is_fresh(&conn, &root));
+    assert_eq!(
+      indexed_latest_runs(&conn, 1000).expect("query"),
+      latest_run_summaries(&config, 1000)
+    );
+
+    conn
+      .execute(
+        "UPDATE meta SET value = '0' WHERE key = 'schema_version'",
+        [],
+      )
+      .expect("stamp");
+    prepare_index(&conn).expect("schema");
+    assert!(!index_is_fresh(&conn, &root));
+    assert!(indexed_latest_runs(&conn, 1).expect("query").is_empty());
+  }
+
+  #[test]
+  fn run_stats_and_search_agree_between_index_and_scan() {
+    let dir = runs_fixture(&[
+      (
+        "a.summary.json",
+        r#"{"run_id": "20240101-080000", "stores": ["lidl"], "result": "ok",
+           "processed": [{"store": "lidl", "file": "Bon_50%.jpg"}]}"#,
+      ),
+      (
+        "b.summary.json",
+        r#"{"run_id": "20240102-080000", "stores": ["lidl", "penny"], "result": "warn",
+           "warnings": [{"store": "penny", "file": "x.pdf", "reason": "Blurry scan"}],
+           "failures": [{"store": "lidl", "file": "y.pdf"}, {"store": "lidl", "file": "z.pdf"}],
+           "processed_count": 3}"#,
+      ),
+      (
+        "c.summary.json",
+        r#"{"stores": ["lidl"], "failures": [{"file": "q.pdf"}]}"#,
+      ),
+    ]);
+    let root = dir.path().to_string_lossy().to_string();
+    let mut config = badge_config("lidl");
+    config.receipts_root = root.clone();
+    let mut conn = rusqlite::Connection::open_in_memory().expect("index");
+    prepare_index(&conn).expect("schema");
+    rebuild_index_at(&mut conn, &root).expect("rebuild");
+    let summaries = latest_run_summaries(&config, usize::MAX);
+
+    for store_id in [None, Some("lidl"), Some("penny"), Some("aldi")] {
+      let indexed = indexed_run_stats(&conn, store_id).expect("stats");
+      let scanned = scanned_run_stats(&summaries, store_id);
+      assert_eq!(
+        (indexed.source.as_str(), scanned.source.as_str()),
+        ("index", "scan")
+      );
+      assert_eq!(
+        RunStats {
+          source: String::new(),
+          ..indexed
+        },
+        RunStats {
+          source: String::new(),
+          ..scanned
+        }
+      );
+    }
+    let penny = scanned_run_stats(&summaries, Some("penny"));
+    assert_eq!(
+      (penny.runs, penny.failures, penny.warnings, penny.processed),
+      (1, 2, 1, 3)
+    );
+
+    for (query, expected) in [
+      ("blurry", vec!["20240102-080000"]),
+      ("50%", vec!["20240101-080000"]),
+      ("lidl", vec!["20240102-080000", "20240101-080000"]),
+      ("q.pdf", vec![]),
+      ("_", vec!["20240101-080000"]),
+    ] {
+      let indexed = indexed_search_runs(&conn, query, 10).expect("search");
+      let scanned: Vec<Value> = summaries
+        .iter()
+        .filter(|value| run_matches(value, query))
+        .cloned()
+        .collect();
+      let ids = |runs: &[Value]| {
+        let mut ids: Vec<String> = runs.iter().filter_map(extract_run_id).collect();
+        ids.sort_by(|a, b| b.cmp(a));
+        ids
+      };
+      assert_eq!(ids(&indexed), expected, "index: {}", query);
+      assert_eq!(ids(&scanned), expected, "scan: {}", query);
+    }
+  }
+
+  #[test]
+  fn month_stats_updated_in_place_match_a_rebuild() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string(), "penny".to_string()];
+    let write = |store: &str, name: &str, raw: &str| {
+      let path = dir.path().join("processed").join(store);
+      fs::create_dir_all(&path).expect("dir");
+      fs::write(path.join(name), raw).expect("output");
+    };
+    for index in 0..40 {
+      write(
+        if index % 3 == 0 { "penny" } else { "lidl" },
+        &format!("r{}.jpg.json", index),
+        &format!(
+          r#"{{"timestamp": "2024-{:02}-{:02}T10:00:00", "total": "{}.{:02}", "currency": "{}"}}"#,
+          index % 5 + 1,
+          index % 27 + 1,
+          index * 7,
+          index * 13 % 100,
+          if index % 4 == 0 { "EUR" } else { "RON" }
+        ),
+      );
+    }
+    write(
+      "lidl",
+      "nototal.jpg.json",
+      r#"{"timestamp": "2024-02-01T10:00:00"}"#,
+    );
+
+    let mut conn = rusqlite::Connection::open_in_memory().expect("index");
+    prepare_index(&conn).expect("schema");
+    indexed_month_stats(&mut conn, &root, &stores).expect("stats");
+
+    // Added, changed (new month and currency), removed, and one dated by its mtime.
+    write(
+      "penny",
+      "new.jpg.json",
+      r#"{"timestamp": "2024-03-09T10:00:00", "total": 0.1}"#,
+    );
+    write(
+      "lidl",
+      "r1.jpg.json",
+      r#"{"timestamp": "2024-06-01T10:00:00", "total": "0,2", "currency": "EUR"}"#,
+    );
+    fs::remove_file(dir.path().join("processed/lidl/r2.jpg.json")).expect("remove");
+    write("lidl", "undated.jpg.json", r#"{"total": 3}"#);
+    let filetime = std::time::SystemTime::now() + Duration::from_secs(5);
+    fs::File::options()
+      .write(true)
+      .open(dir.path().join("processed/lidl/r1.jpg.json"))
+      .and_then(|file| file.set_modified(filetime))
+      .expect("mtime");
+    let incremental = indexed_month_stats(&mut conn, &root, &stores).expect("stats");
+
+    let rebuilt = rebuild_stats_cache_at(&mut conn, &root, &stores).expect("rebuild");
+    let full = indexed_month_stats(&mut conn, &root, &stores).expect("stats");
+    assert_eq!(incremental, full);
+    assert_eq!(rebuilt.receipts, 42);
+    let same = Conversion::default();
+    let json = |stats: &[MonthStat]| {
+      serde_json::to_vec(&summarize_month_stats(stats.to_vec(), None, None, &same)).expect("json")
+    };
+    assert_eq!(json(&incremental), json(&full));
+    assert_eq!(
+      summarize_month_stats(full.clone(), None, None, &same),
+      summarize_spending(walk_receipt_totals(&root, &stores), None, None, &same)
+    );
+    assert_eq!(
+      monthly_report_from_stats(full, &stores, 2024, 3, &same),
+      monthly_report(walk_receipt_totals(&root, &stores), &stores, 2024, 3, &same)
+    );
+
+    // Buckets that no longer add up are recomputed on the next sync.
+    conn
+      .execute(
+        "UPDATE month_stats SET receipt_count = receipt_count + 1",
+        [],
+      )
+      .expect("corrupt");
+    assert_eq!(
+      indexed_month_stats(&mut conn, &root, &stores).expect("stats"),
+      incremental
+    );
+    set_meta(&conn, "month_stats", "0").expect("stamp");
+    assert!(!month_stats_usable(&conn).expect("check"));
+    assert_eq!(
+      indexed_month_stats(&mut conn, &root, &stores).expect("stats"),
+      incremental
+    );
+  }
+}
@@ -0,0 +1,154 @@
+use crate::*;
+
+// Contents of instance.lock: who holds it and where it listens for focus
+// requests from later launches.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InstanceInfo {
+  pub(crate) pid: u32,
+  pub(crate) port: u16,
+}
+
+pub(crate) enum InstanceLock {
+  Acquired(fs::File, TcpListener),
+  // Another instance holds the lock; its info is None when the file can't be
+  // read while locked (Windows).
+  Running(Option<InstanceInfo>),
+}
+
+pub(crate) fn process_alive(pid: u32) -> bool {
+  #[cfg(unix)]
+  {
+    return Command::new("kill")
+      .args(["-0", &pid.to_string()])
+      .stderr(Stdio::null())
+      .status()
+      .map(|status| status.success())
+      .unwrap_or(false);
+  }
+  #[cfg(target_os = "windows")]
+  {
+    return Command::new("tasklist")
+      .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+      .output()
+      .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+      .unwrap_or(false);
+  }
+  #[allow(unreachable_code)]
+  true
+}
+
+pub(crate) fn instance_lock_path() -> Option<PathBuf> {
+  state_path(INSTANCE_LOCK_FILE)
+}
+
+// Takes an exclusive lock on instance.lock for the life of the process. The OS
+// drops the lock when a process dies, so a leftover file only matters when its
+// PID is gone; that file is cleared and the lock retried.
+pub(crate) fn acquire_instance_lock() -> Result<InstanceLock, String> {
+  let path = instance_lock_path().ok_or_else(|| AppError::HomeUnavailable.message())?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+  }
+  for _ in 0..2 {
+    let mut file = fs::OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(&path)
+      .map_err(|err| err.to_string())?;
+    let mut raw = String::new();
+    let _ = file.read_to_string(&mut raw);
+    let previous = serde_json::from_str::<InstanceInfo>(&raw).ok();
+    if fs2::FileExt::try_lock_exclusive(&file).is_err() {
+      match previous {
+        Some(info) if !process_alive(info.pid) => {
+          info!("instance lock: clearing stale lock from pid {}", info.pid);
+          drop(file);
+          let _ = fs::remove_file(&path);
+          continue;
+        }
+        info => return Ok(InstanceLock::Running(info)),
+      }
+    }
+    if let Some(info) = previous {
+      warn!(
+        "instance lock: previous instance (pid {}) did not exit cleanly",
+        info.pid
+      );
+    }
+    let listener = TcpListener::bind(("127.0.0.1", 0)).map_err(|err| err.to_string())?;
+    let info = InstanceInfo {
+      pid: std::process::id(),
+      port: listener.local_addr().map_err(|err| err.to_string())?.port(),
+    };
+    let raw = serde_json::to_string(&info).map_err(|err| err.to_string())?;
+    file.set_len(0).map_err(|err| err.to_string())?;
+    file
+      .seek(SeekFrom::Start(0))
+      .map_err(|err| err.to_string())?;
+    file
+      .write_all(raw.as_bytes())
+      .map_err(|err| err.to_string())?;
+    file.sync_all().map_err(|err| err.to_string())?;
+    return Ok(InstanceLock::Acquired(file, listener));
+  }
+  Err(format!("Could not lock {}", path.to_string_lossy()))
+}
+
+// After relaunch_app the new process starts before the old one exits, so it
+// waits for the old pid to release the lock rather than handing focus back.
+pub(crate) fn acquire_instance_lock_after_relaunch() -> Result<InstanceLock, String> {
+  let relaunched_from = env_var(RELAUNCH_ENV).and_then(|pid| pid.parse::<u32>().ok());
+  for _ in 0..40 {
+    match acquire_instance_lock()? {
+      InstanceLock::Running(Some(info)) if Some(info.pid) == relaunched_from => {
+        thread::sleep(Duration::from_millis(250));
+      }
+      lock => return Ok(lock),
+    }
+  }
+  acquire_instance_lock()
+}
+
+pub(crate) fn request_focus(info: &InstanceInfo) {
+  send_instance_request(info, "focus");
+}
+
+// Hands a `receipts://` link to the running instance, which also focuses it.
+pub(crate) fn request_open(info: &InstanceInfo, url: &str) {
+  send_instance_request(info, &format!("open {}", url));
+}
+
+pub(crate) fn send_instance_request(info: &InstanceInfo, request: &str) {
+  let addr = std::net::SocketAddr::from(([127, 0, 0, 1], info.port));
+  if let Ok(mut stream) = TcpStream::connect_timeout(&addr, Duration::from_secs(2)) {
+    let _ = stream.write_all(format!("{}\n", request.trim()).as_bytes());
+  }
+}
+
+// Later launches connect here to bring the running window forward, or to
+// forward the link they were started with, before they exit.
+pub(crate) fn serve_focus_requests(app: tauri::AppHandle, listener: TcpListener) {
+  for stream in listener.incoming() {
+    let stream = match stream {
+      Ok(stream) => stream,
+      Err(_) => continue,
+    };
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(2)));
+    let mut line = String::new();
+    if BufReader::new(stream).read_line(&mut line).is_err() {
+      continue;
+    }
+    match line.trim() {
+      "focus" => {
+        show_main_window(&app);
+      }
+      request => {
+        if let Some(url) = request.strip_prefix("open ") {
+          handle_deep_link(&app, url);
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,365 @@
+use crate::*;
+
+// Where the folders and the run summaries disagree. Only ever reads; fixing
+// anything is left to the commands that move or delete files.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub(crate) struct DataIntegrityCounts {
+  // Listed as processed in a summary but not in processed/<store>.
+  pub(crate) missing_processed: usize,
+  // In failed/<store> without a failure entry in any summary.
+  pub(crate) unrecorded_failed: usize,
+  // In inbox/<store> under a name processed/<store> already has.
+  pub(crate) inbox_and_processed: usize,
+  // Stores named by summaries that aren't configured.
+  pub(crate) unknown_stores: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct DataIntegrityIssue {
+  pub(crate) store_id: String,
+  pub(crate) file_name: Option<String>,
+  // The file in question; for `missing_processed`, where it should be.
+  pub(crate) path: Option<String>,
+  // The runs whose summaries mention the file (or store), oldest id first.
+  pub(crate) run_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct DataIntegrityGroup {
+  pub(crate) kind: String,
+  pub(crate) count: usize,
+  pub(crate) issues: Vec<DataIntegrityIssue>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct DataIntegrityReport {
+  pub(crate) checked_at: String,
+  // Groups carry counts only, without issues.
+  pub(crate) count_only: bool,
+  pub(crate) summaries_scanned: usize,
+  pub(crate) counts: DataIntegrityCounts,
+  pub(crate) groups: Vec<DataIntegrityGroup>,
+}
+
+// Counts for the status snapshot, recomputed at most every DATA_INTEGRITY_TTL.
+#[derive(Default)]
+pub(crate) struct DataIntegrityCache(
+  pub(crate) Mutex<Option<(String, Instant, DataIntegrityCounts)>>,
+);
+
+// (store, file name) -> run ids, from the summaries' `processed` or `failures`.
+pub(crate) type SummaryFiles = std::collections::BTreeMap<(String, String), Vec<String>>;
+
+#[derive(Default)]
+pub(crate) struct SummaryRecords {
+  pub(crate) processed: SummaryFiles,
+  pub(crate) failures: SummaryFiles,
+  // Every store a summary names, with its runs.
+  pub(crate) stores: std::collections::BTreeMap<String, Vec<String>>,
+  pub(crate) scanned: usize,
+}
+
+pub(crate) fn push_run_id(run_ids: &mut Vec<String>, run_id: &str) {
+  if !run_ids.iter().any(|known| known == run_id) {
+    run_ids.push(run_id.to_string());
+    run_ids.sort();
+  }
+}
+
+// An entry's store is its own `store`, else the summary's only store. Only the
+// file name of `file` counts, in case a worker wrote a relative path.
+pub(crate) fn summary_records(summaries: &[Value]) -> SummaryRecords {
+  let mut records = SummaryRecords::default();
+  for summary in summaries {
+    records.scanned += 1;
+    let run_id = extract_run_id(summary).unwrap_or_default();
+    let stores = extract_stores(summary);
+    for store in &stores {
+      push_run_id(records.stores.entry(store.clone()).or_default(), &run_id);
+    }
+    for (kind, files) in [
+      ("processed", &mut records.processed),
+      ("failures", &mut records.failures),
+    ] {
+      for entry in summary
+        .get(kind)
+        .and_then(|entries| entries.as_array())
+        .into_iter()
+        .flatten()
+      {
+        let store = match entry.get("store").and_then(|store| store.as_str()) {
+          Some(store) => store.to_string(),
+          None if stores.len() == 1 => stores[0].clone(),
+          None => continue,
+        };
+        let file = entry
+          .get("file")
+          .and_then(|file| file.as_str())
+          .and_then(|file| Path::new(file).file_name())
+          .map(|name| name.to_string_lossy().to_string());
+        let file = match file {
+          Some(file) => file,
+          None => continue,
+        };
+        push_run_id(records.stores.entry(store.clone()).or_default(), &run_id);
+        push_run_id(files.entry((store, file)).or_default(), &run_id);
+      }
+    }
+  }
+  records
+}
+
+// The receipt files in a folder, without the worker's `.json` sidecars or
+// hidden files.
+pub(crate) fn receipt_file_names(dir: &Path) -> Vec<String> {
+  let mut names = fs::read_dir(dir)
+    .map(|entries| {
+      entries
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name())
+        .filter(|name| {
+          let name = name.to_string_lossy();
+          !name.starts_with('.') && !name.ends_with(".json")
+        })
+        .map(|name| file_name_id(&name))
+        .collect::<Vec<_>>()
+    })
+    .unwrap_or_default();
+  names.sort();
+  names
+}
+
+pub(crate) fn data_integrity_report(
+  receipts_root: &str,
+  store_ids: &[String],
+  summaries: &[Value],
+  count_only: bool,
+) -> DataIntegrityReport {
+  let root = Path::new(receipts_root);
+  let records = summary_records(summaries);
+  let configured = |store: &str| store_ids.iter().any(|id| id == store);
+  let mut groups = std::collections::BTreeMap::<&str, (usize, Vec<DataIntegrityIssue>)>::new();
+  let mut counts = DataIntegrityCounts::default();
+  let mut report = |kind: &'static str, issue: DataIntegrityIssue| {
+    match kind {
+      "missing_processed" => counts.missing_processed += 1,
+      "unrecorded_failed" => counts.unrecorded_failed += 1,
+      "inbox_and_processed" => counts.inbox_and_processed += 1,
+      _ => counts.unknown_stores += 1,
+    }
+    let group = groups.entry(kind).or_default();
+    group.0 += 1;
+    if !count_only {
+      group.1.push(issue);
+    }
+  };
+  let issue =
+    |store_id: &str, file_name: Option<&str>, path: Option<PathBuf>, run_ids: &[String]| {
+      DataIntegrityIssue {
+        store_id: store_id.to_string(),
+        file_name: file_name.map(str::to_string),
+        path: path.map(|path| path.to_string_lossy().to_string()),
+        run_ids: run_ids.to_vec(),
+      }
+    };
+
+  for ((store, file), run_ids) in &records.processed {
+    let path = root.join("processed").join(store).join(file);
+    if configured(store) && !path.exists() {
+      report(
+        "missing_processed",
+        issue(store, Some(file), Some(path), run_ids),
+      );
+    }
+  }
+  for store in store_ids {
+    let processed = receipt_file_names(&root.join("processed").join(store));
+    let failed_dir = root.join("failed").join(store);
+    for name in receipt_file_names(&failed_dir) {
+      if !records
+        .failures
+        .contains_key(&(store.clone(), name.clone()))
+      {
+        report(
+          "unrecorded_failed",
+          issue(
+            store,
+            Some(&name),
+            Some(join_file_id(&failed_dir, &name)),
+            &[],
+          ),
+        );
+      }
+    }
+    let inbox = root.join("inbox").join(store);
+    for name in receipt_file_names(&inbox) {
+      if processed.binary_search(&name).is_ok() {
+        let run_ids = records
+          .processed
+          .get(&(store.clone(), name.clone()))
+          .cloned()
+          .unwrap_or_default();
+        report(
+          "inbox_and_processed",
+          issue(
+            store,
+            Some(&name),
+            Some(join_file_id(&inbox, &name)),
+            &run_ids,
+          ),
+        );
+      }
+    }
+  }
+  for (store, run_ids) in &records.stores {
+    if !configured(store) {
+      report("unknown_store", issue(store, None, None, run_ids));
+    }
+  }
+
+  DataIntegrityReport {
+    checked_at: chrono::Utc::now().to_rfc3339(),
+    count_only,
+    summaries_scanned: records.scanned,
+    counts,
+    groups: groups
+      .into_iter()
+      .map(|(kind, (count, issues))| DataIntegrityGroup {
+        kind: kind.to_string(),
+        count,
+        issues,
+      })
+      .collect(),
+  }
+}
+
+pub(crate) fn check_data_integrity_for(
+  config: &AppConfig,
+  count_only: bool,
+) -> Result<DataIntegrityReport, AppError> {
+  let root = require_receipts_root(config)?;
+  let store_ids = config
+    .stores
+    .iter()
+    .map(|store| store.id.clone())
+    .collect::<Vec<_>>();
+  let summaries = list_run_summaries(root)
+    .into_iter()
+    .map(|(summary, _)| summary)
+    .collect::<Vec<_>>();
+  Ok(data_integrity_report(
+    root, &store_ids, &summaries, count_only,
+  ))
+}
+
+// None when there is no receipts root to check.
+pub(crate) fn cached_data_integrity_counts(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+) -> Option<DataIntegrityCounts> {
+  let cache = app.state::<DataIntegrityCache>();
+  if let Ok(cached) = cache.0.lock() {
+    if let Some((root, computed, counts)) = cached.as_ref() {
+      if *root == config.receipts_root && computed.elapsed() < DATA_INTEGRITY_TTL {
+        return Some(counts.clone());
+      }
+    }
+  }
+  let counts = check_data_integrity_for(config, true).ok()?.counts;
+  if let Ok(mut cached) = cache.0.lock() {
+    *cached = Some((config.receipts_root.clone(), Instant::now(), counts.clone()));
+  }
+  Some(counts)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn data_integrity_cross_checks_summaries_and_folders() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path();
+    for (folder, name) in [
+      ("processed", "a.jpg"),
+      ("processed", "a.jpg.json"),
+      ("processed", "dup.jpg"),
+      ("failed", "bad.jpg"),
+      ("failed", "bad.jpg.error.json"),
+      ("failed", "lost.jpg"),
+      ("inbox", "dup.jpg"),
+      ("inbox", "new.jpg"),
+    ] {
+      let path = root.join(folder).join("lidl");
+      fs::create_dir_all(&path).expect("dir");
+      fs::write(path.join(name), b"x").expect("file");
+    }
+    let summaries = vec![
+      serde_json::json!({
+        "run_id": "20240102-093000",
+        "stores": ["lidl"],
+        "processed": [{"file": "a.jpg"}, {"store": "lidl", "file": "inbox/lidl/gone.jpg"}],
+        "failures": [{"store": "lidl", "file": "bad.jpg"}],
+      }),
+      serde_json::json!({
+        "run_id": "20240103-093000",
+        "stores": ["lidl", "penny"],
+        "processed": [{"store": "lidl", "file": "dup.jpg"}, {"store": "lidl", "file": "gone.jpg"}],
+      }),
+    ];
+    let stores = vec!["lidl".to_string()];
+    let root_text = root.to_string_lossy().to_string();
+
+    let report = data_integrity_report(&root_text, &stores, &summaries, false);
+    assert_eq!(report.summaries_scanned, 2);
+    assert_eq!(
+      report.counts,
+      DataIntegrityCounts {
+        missing_processed: 1,
+        unrecorded_failed: 1,
+        inbox_and_processed: 1,
+        unknown_stores: 1,
+      }
+    );
+    let group = |kind: &str| {
+      report
+        .groups
+        .iter()
+        .find(|group| group.kind == kind)
+        .map(|group| group.issues.clone())
+        .unwrap_or_default()
+    };
+    let missing = group("missing_processed");
+    assert_eq!(missing[0].file_name.as_deref(), Some("gone.jpg"));
+    assert_eq!(
+      missing[0].run_ids,
+      vec!["20240102-093000", "20240103-093000"]
+    );
+    assert_eq!(
+      group("unrecorded_failed")[0].file_name.as_deref(),
+      Some("lost.jpg")
+    );
+    let doubled = group("inbox_and_processed");
+    assert_eq!(
+      doubled[0].path.as_deref(),
+      Some(
+        root
+          .join("inbox")
+          .join("lidl")
+          .join("dup.jpg")
+          .to_string_lossy()
+          .as_ref()
+      )
+    );
+    assert_eq!(doubled[0].run_ids, vec!["20240103-093000"]);
+    assert_eq!(group("unknown_store")[0].store_id, "penny");
+
+    let counts = data_integrity_report(&root_text, &stores, &summaries, true);
+    assert_eq!(counts.counts, report.counts);
+    assert!(counts
+      .groups
+      .iter()
+      .all(|group| group.count == 1 && group.issues.is_empty()));
+  }
+}
@@ -0,0 +1,311 @@
+use crate::*;
+
+pub(crate) fn app_logs_dir() -> Option<PathBuf> {
+  state_path(APP_LOGS_DIR)
+}
+
+pub(crate) fn rotated_log_path(dir: &Path, index: usize) -> PathBuf {
+  if index == 0 {
+    dir.join(APP_LOG_FILE)
+  } else {
+    dir.join(format!("{}.{}", APP_LOG_FILE, index))
+  }
+}
+
+// Size-capped app log: when a write would push the current file past
+// `max_bytes` it becomes `.1`, older files shift up and the oldest of `keep`
+// files is deleted.
+pub(crate) struct RotatingLog {
+  pub(crate) dir: PathBuf,
+  pub(crate) file: Option<fs::File>,
+  pub(crate) size: u64,
+  pub(crate) max_bytes: u64,
+  pub(crate) keep: usize,
+}
+
+impl RotatingLog {
+  pub(crate) fn open(dir: PathBuf, max_bytes: u64, keep: usize) -> std::io::Result<RotatingLog> {
+    fs::create_dir_all(&dir)?;
+    let file = fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(rotated_log_path(&dir, 0))?;
+    let size = file.metadata()?.len();
+    Ok(RotatingLog {
+      dir,
+      file: Some(file),
+      size,
+      max_bytes,
+      keep: keep.max(1),
+    })
+  }
+
+  // The current file is closed first; Windows refuses to rename open files.
+  pub(crate) fn rotate(&mut self) -> std::io::Result<()> {
+    self.file = None;
+    let _ = fs::remove_file(rotated_log_path(&self.dir, self.keep - 1));
+    for index in (0..self.keep - 1).rev() {
+      let from = rotated_log_path(&self.dir, index);
+      if from.exists() {
+        fs::rename(&from, rotated_log_path(&self.dir, index + 1))?;
+      }
+    }
+    self.file = Some(
+      fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(rotated_log_path(&self.dir, 0))?,
+    );
+    self.size = 0;
+    Ok(())
+  }
+}
+
+impl Write for RotatingLog {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+      self.rotate()?;
+    }
+    let file = match self.file.as_mut() {
+      Some(file) => file,
+      None => return Ok(buf.len()),
+    };
+    let written = file.write(buf)?;
+    self.size += written as u64;
+    Ok(written)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    match self.file.as_mut() {
+      Some(file) => file.flush(),
+      None => Ok(()),
+    }
+  }
+}
+
+#[derive(Clone)]
+pub(crate) struct AppLogWriter(pub(crate) Arc<Mutex<RotatingLog>>);
+
+impl Write for AppLogWriter {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    match self.0.lock() {
+      Ok(mut log) => log.write(buf),
+      Err(_) => Ok(buf.len()),
+    }
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    match self.0.lock() {
+      Ok(mut log) => log.flush(),
+      Err(_) => Ok(()),
+    }
+  }
+}
+
+// Events go to stdout and, when the state dir is writable, to the rotating
+// file. RECEIPTS_LOG_LEVEL overrides the default debug level. Headless runs
+// keep stdout and stderr for their own output, so they log to the file only
+// (to stderr when there is none).
+pub(crate) fn init_logging(headless: bool) {
+  use tracing_subscriber::fmt::writer::MakeWriterExt;
+  let level = std::env::var(APP_LOG_LEVEL_ENV)
+    .ok()
+    .and_then(|value| value.parse::<tracing::Level>().ok())
+    .unwrap_or(tracing::Level::DEBUG);
+  let builder = tracing_subscriber::fmt()
+    .with_max_level(level)
+    .with_ansi(false);
+  let log = app_logs_dir().map(|dir| RotatingLog::open(dir, APP_LOG_MAX_BYTES, APP_LOG_KEEP_FILES));
+  match log {
+    Some(Ok(log)) => {
+      let writer = AppLogWriter(Arc::new(Mutex::new(log)));
+      if headless {
+        let _ = builder.with_writer(move || writer.clone()).try_init();
+      } else {
+        let _ = builder
+          .with_writer((move || writer.clone()).and(std::io::stdout))
+          .try_init();
+      }
+    }
+    Some(Err(err)) => {
+      if headless {
+        let _ = builder.with_writer(std::io::stderr).try_init();
+      } else {
+        let _ = builder.try_init();
+      }
+      warn!("app log file unavailable: {}", err);
+    }
+    None if headless => {
+      let _ = builder.with_writer(std::io::stderr).try_init();
+    }
+    None => {
+      let _ = builder.try_init();
+    }
+  }
+}
+
+pub(crate) fn log_line_rank(line: &str) -> Option<usize> {
+  let level = line.split_whitespace().nth(1)?;
+  LOG_LEVELS.iter().position(|candidate| *candidate == level)
+}
+
+// Newest lines last. Lines without a level (multi-line messages) take the
+// level of the event they continue.
+pub(crate) fn tail_app_logs(dir: &Path, limit: usize, min_rank: usize) -> Vec<String> {
+  let mut kept: Vec<String> = Vec::new();
+  for index in 0..APP_LOG_KEEP_FILES {
+    let raw = match fs::read(rotated_log_path(dir, index)) {
+      Ok(raw) => raw,
+      Err(_) => continue,
+    };
+    let mut rank = min_rank;
+    let mut matched = Vec::new();
+    for line in String::from_utf8_lossy(&raw).lines() {
+      if let Some(line_rank) = log_line_rank(line) {
+        rank = line_rank;
+      }
+      if rank >= min_rank {
+        matched.push(line.to_string());
+      }
+    }
+    matched.append(&mut kept);
+    kept = matched;
+    if kept.len() >= limit {
+      break;
+    }
+  }
+  let skip = kept.len().saturating_sub(limit);
+  kept.split_off(skip)
+}
+
+pub(crate) fn looks_like_secret(value: &str) -> bool {
+  if SECRET_VALUE_PREFIXES
+    .iter()
+    .any(|prefix| value.starts_with(prefix))
+  {
+    return true;
+  }
+  value.len() >= 32
+    && value
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    && value.chars().any(|c| c.is_ascii_digit())
+    && value.chars().any(|c| c.is_ascii_uppercase())
+    && value.chars().any(|c| c.is_ascii_lowercase())
+}
+
+// Invocation arguments as logged: secret-looking keys and values are replaced
+// and Tauri's own `__` fields are dropped.
+pub(crate) fn redact_secrets(value: &Value) -> Value {
+  match value {
+    Value::Object(map) => Value::Object(
+      map
+        .iter()
+        .filter(|(key, _)| !key.starts_with("__"))
+        .map(|(key, value)| {
+          let lower = key.to_lowercase();
+          let redacted = if SECRET_KEY_MARKERS
+            .iter()
+            .any(|marker| lower.contains(marker))
+          {
+            Value::from("[redacted]")
+          } else {
+            redact_secrets(value)
+          };
+          (key.clone(), redacted)
+        })
+        .collect(),
+    ),
+    Value::Array(items) => Value::Array(items.iter().map(redact_secrets).collect()),
+    Value::String(text) if looks_like_secret(text) => Value::from("[redacted]"),
+    _ => value.clone(),
+  }
+}
+
+pub(crate) fn log_invocations<R: tauri::Runtime>(
+  handler: impl Fn(tauri::Invoke<R>) + Send + Sync + 'static,
+) -> impl Fn(tauri::Invoke<R>) + Send + Sync + 'static {
+  move |invoke| {
+    let command = invoke.message.command().to_string();
+    debug!(
+      "invoke {} {}",
+      command,
+      redact_secrets(invoke.message.payload())
+    );
+    let window = invoke.message.window();
+    let started = Instant::now();
+    handler(invoke);
+    // Blocking-pool commands return here before their work is done; run_blocking
+    // records those.
+    if !BLOCKING_COMMANDS.contains(&command.as_str()) {
+      window
+        .state::<CommandMetrics>()
+        .record(&command, started.elapsed());
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub(crate) struct AppLogs {
+  pub(crate) path: String,
+  pub(crate) lines: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn rotating_log_caps_size_and_tail_filters_by_level() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut log = RotatingLog::open(dir.path().to_path_buf(), 120, 3).expect("open log");
+    for index in 0..12 {
+      let level = if index % 3 == 0 { "WARN" } else { "INFO" };
+      let line = format!(
+        "2026-10-15T10:00:{:02}.000000Z  {} receipts_desktop: event {}\n",
+        index, level, index
+      );
+      log.write_all(line.as_bytes()).expect("write");
+    }
+    log.flush().expect("flush");
+    assert!(rotated_log_path(dir.path(), 1).exists());
+    assert!(rotated_log_path(dir.path(), 2).exists());
+    assert!(!rotated_log_path(dir.path(), 3).exists());
+    for index in 0..3 {
+      let size = fs::metadata(rotated_log_path(dir.path(), index))
+        .unwrap()
+        .len();
+      assert!(size <= 120);
+    }
+
+    let tail = tail_app_logs(dir.path(), 2, 0);
+    assert_eq!(tail.len(), 2);
+    assert!(tail[1].ends_with("event 11"));
+    let warnings = tail_app_logs(dir.path(), 100, log_line_rank("x WARN").unwrap());
+    assert!(!warnings.is_empty());
+    assert!(warnings.iter().all(|line| line.contains(" WARN ")));
+    assert!(warnings.last().unwrap().ends_with("event 9"));
+  }
+
+  #[test]
+  fn redact_secrets_hides_secret_keys_and_values() {
+    let args = serde_json::json!({
+      "__invokeKey": 42,
+      "storeId": "lidl",
+      "apiToken": "abc",
+      "value": { "password": "hunter2", "note": "sk-live-123" },
+      "paths": ["/tmp/a.pdf", "Ab3dEf6hIj9kLm2nOp5qRs8tUv1wXy4zAb"],
+      "commit": "0123456789abcdef0123456789abcdef01234567"
+    });
+    assert_eq!(
+      redact_secrets(&args),
+      serde_json::json!({
+        "storeId": "lidl",
+        "apiToken": "[redacted]",
+        "value": { "password": "[redacted]", "note": "[redacted]" },
+        "paths": ["/tmp/a.pdf", "[redacted]"],
+        "commit": "0123456789abcdef0123456789abcdef01234567"
+      })
+    );
+  }
+}
@@ -1,868 +1,685 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::process::Stdio;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
 use tauri::api::path::{home_dir, resource_dir};
 use tauri::api::shell;
-use tauri::{Env, Manager, PackageInfo};
+use tauri::{
+  CustomMenuItem, Env, Manager, PackageInfo, SystemTray, SystemTrayEvent, SystemTrayMenu,
+  SystemTrayMenuItem,
+};
+use tracing::{debug, error, info, warn};
 
 const DEFAULT_RECEIPTS_ROOT: &str = "Dropbox/bonuri";
+// What the first-run wizard proposes under each Dropbox root it finds.
+const DEFAULT_RECEIPTS_FOLDER: &str = "bonuri";
 const STATE_DIR: &str = ".life-dashboard/receipts-desktop";
+const STATE_DIR_ENV: &str = "RECEIPTS_STATE_DIR";
+// Matches tauri.conf.json; the deep-link plugin registers links under it.
+const BUNDLE_IDENTIFIER: &str = "com.life-dashboard.receipts";
+// productName in tauri.conf.json.
+const APP_DISPLAY_NAME: &str = "Receipts Operator";
+// Passed by the login item so the app starts hidden in the tray.
+const AUTOSTART_ARG: &str = "--autostart";
+// Under the platform data dir, for when there is no home directory.
+const APP_DATA_DIR_NAME: &str = BUNDLE_IDENTIFIER;
 const STATE_FILE: &str = "state.json";
+const DEEP_LINK_SCHEME: &str = "receipts";
 const DEFAULT_WORKER_DIR: &str = "Documents/Github repos/life-dashboard/apps/receipts-worker";
+const DISK_USAGE_TTL: Duration = Duration::from_secs(5 * 60);
+const DISK_USAGE_MAX_DEPTH: usize = 8;
+const DEFAULT_IMPORT_NAME_TEMPLATE: &str = "{date}_{original}";
+const INBOX_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "pdf"];
+const STAGING_DIR_PREFIX: &str = ".staged-";
+// Starts the id of a file name that isn't UTF-8 (see `file_name_id`).
+const RAW_FILE_ID_PREFIX: &str = ":raw:";
+const DEFAULT_PREVIEW_MAX_BYTES: u64 = 20 * 1024 * 1024;
+const INTEGRITY_SNIFF_BYTES: u64 = 4096;
+const INTEGRITY_RECENT_SECS: u64 = 10 * 60;
+const DEFAULT_INBOX_PAGE_SIZE: usize = 100;
+const DEFAULT_BACKLOG_WARN_COUNT: usize = 20;
+const DEFAULT_BACKLOG_WARN_DAYS: u64 = 14;
+const DEFAULT_HYDRATE_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_INBOX_CACHE_TTL_SECS: u64 = 30;
+const DEFAULT_BADGE_WINDOW_DAYS: u64 = 30;
+const BADGE_METRICS: [&str; 3] = ["failures", "failures_warnings", "inbox"];
+const RUNS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_NOTIFIED_RUN_IDS_CAP: usize = 50;
+const DEFAULT_SEEN_RUN_IDS_CAP: usize = 200;
+const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+const STATE_BACKUP_COUNT: usize = 5;
+const STAGE_MARKER: &str = "::stage::";
+const UPDATE_STAGES: [&str; 5] = [
+  "check-versions",
+  "close-app",
+  "build",
+  "install-bundle",
+  "relaunch",
+];
+const FULL_UPDATE_STAGES: [&str; 7] = [
+  "worker-pull",
+  "worker-deps",
+  "check-versions",
+  "close-app",
+  "build",
+  "install-bundle",
+  "relaunch",
+];
+const UPDATE_OUTPUT_TAIL_LINES: usize = 20;
+const DEFAULT_UPDATE_CHECK_HOURS: u64 = 6;
+const UPDATE_CHECK_POLL_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const STATUS_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
+const DATA_INTEGRITY_TTL: Duration = Duration::from_secs(10 * 60);
+const DASHBOARD_SCAN_INTERVAL: Duration = Duration::from_secs(60);
+const DASHBOARD_BACKOFF_START: Duration = Duration::from_secs(5);
+const DASHBOARD_BACKOFF_MAX: Duration = Duration::from_secs(10 * 60);
+const DASHBOARD_LAST_RUNS: usize = 50;
+// How long a probe of RECEIPTS_ROOT may take before the root counts as
+// unreachable; a sleeping NAS otherwise stalls the first metadata call for 30 s+.
+const ROOT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+// In order; `config` and `caches` run in the setup hook, the rest on the
+// dashboard scanner's thread.
+const STARTUP_PHASES: [&str; 4] = ["config", "caches", "inbox_scan", "run_summaries"];
+const BADGE_EVENT_DEBOUNCE: Duration = Duration::from_millis(300);
+const INSTANCE_LOCK_FILE: &str = "instance.lock";
+const RELAUNCH_ENV: &str = "RECEIPTS_RELAUNCHED_FROM";
+const INSTALLED_BUNDLE_PATH: &str = "/Applications/Receipts Operator.app";
+const PREFS_FILE: &str = "prefs.json";
+const UPDATE_HISTORY_FILE: &str = "update-history.jsonl";
+const UPDATE_LOGS_DIR: &str = "update-logs";
+const DEFAULT_UPDATE_HISTORY_LIMIT: usize = 20;
+const APP_LOGS_DIR: &str = "logs";
+const APP_LOG_FILE: &str = "receipts-desktop.log";
+const APP_LOG_MAX_BYTES: u64 = 2 * 1024 * 1024;
+const APP_LOG_KEEP_FILES: usize = 5;
+const APP_LOG_LEVEL_ENV: &str = "RECEIPTS_LOG_LEVEL";
+const DEFAULT_APP_LOG_LINES: usize = 200;
+const CRASHES_DIR: &str = "crashes";
+const CRASH_LOG_LINES: usize = 50;
+const CRASH_REPORTS_KEEP: usize = 20;
+const INDEX_FILE: &str = "runs-index.sqlite";
+const RECEIPT_CSV_COLUMNS: [&str; 6] = [
+  "date",
+  "store",
+  "merchant",
+  "total",
+  "currency",
+  "file_name",
+];
+const LINE_ITEM_CSV_COLUMNS: [&str; 11] = [
+  "date",
+  "store",
+  "merchant",
+  "item",
+  "quantity",
+  "unit",
+  "unit_price",
+  "discount",
+  "paid_amount",
+  "currency",
+  "file_name",
+];
+const SKIPPED_CSV_COLUMNS: [&str; 4] = ["date", "store", "file_name", "reason"];
+const XLSX_RECEIPT_COLUMNS: [&str; 6] = ["Date", "Store", "Merchant", "Total", "Currency", "File"];
+const XLSX_SHEET_NAME_MAX: usize = 31;
+const XLSX_AMOUNT_FORMAT: &str = "#,##0.00";
+const XLSX_DATE_FORMAT: &str = "yyyy-mm-dd";
+const DEFAULT_RECEIPT_SEARCH_LIMIT: usize = 50;
+const DEFAULT_RUN_SEARCH_LIMIT: usize = 50;
+const RECEIPT_SEARCH_SCAN_BUDGET: Duration = Duration::from_secs(5);
+const RECEIPT_SEARCH_SNIPPET_CHARS: usize = 80;
+const OCR_TEXT_MAX_BYTES: u64 = 1024 * 1024;
+const DEFAULT_TOP_SPENDING_LIMIT: usize = 10;
+const TOP_SPENDING_MAX_LIMIT: usize = 100;
+const TOP_SPENDING_MAX_KEYS: usize = 5000;
+const UNKNOWN_CURRENCY: &str = "unknown";
+const CATEGORY_MAPPING_FILE: &str = "category-mapping.json";
+const UNCATEGORIZED: &str = "uncategorized";
+const CATEGORY_SUM_TOLERANCE: f64 = 0.05;
+const DEFAULT_DUPLICATE_WINDOW_DAYS: u32 = 1;
+const MAX_DUPLICATE_WINDOW_DAYS: u32 = 31;
+const DUPLICATE_TOTAL_TOLERANCE: f64 = 0.01;
+const DUPLICATE_MIN_ITEM_SIMILARITY: f64 = 0.5;
+const PRICE_HISTORY_MAX_PRODUCTS: usize = 10;
+const DEFAULT_API_PORT: u16 = 8765;
+const API_TOKEN_FILE: &str = "api-token";
+const API_WORKER_THREADS: usize = 4;
+const API_QUEUE_DEPTH: usize = 16;
+const WEBHOOK_EVENTS: [&str; 3] = ["success", "warning", "failure"];
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_secs(2);
+const WEBHOOK_SUMMARY_SLACK: Duration = Duration::from_secs(2);
+const SUMMARY_FILE_NAME: &str = "receipts-summary.json";
+const SUMMARY_SCHEMA_VERSION: u32 = 1;
+const BACKUP_MANIFEST_NAME: &str = "manifest.json";
+const BACKUP_SCHEMA_VERSION: u32 = 1;
+// The seen state, which lives in the app state folder rather than the root.
+const BACKUP_STATE_ENTRY: &str = "_state/state.json";
+const RESTORE_HOLDING_DIR: &str = ".pre-restore";
+// Uncompressed; the oldest app logs are cut to stay under it.
+const DIAGNOSTICS_BUNDLE_MAX_BYTES: u64 = 20 * 1024 * 1024;
+const DIAGNOSTICS_BUNDLE_RUNS: usize = 20;
+// What the user wrote about their receipts: dropped from a bundle's state.json
+// unless tags are asked for. Stripped wherever a store's state has them.
+const BUNDLE_PRIVATE_STATE_KEYS: [&str; 3] = ["file_tags", "notes", "run_notes"];
+const ANONYMIZED_AMOUNT_KEYS: [&str; 9] = [
+  "total",
+  "subtotal",
+  "tax",
+  "vat",
+  "discount",
+  "paid_amount",
+  "unit_price",
+  "price",
+  "amount",
+];
+const ANONYMIZED_TEXT_KEYS: [&str; 5] = ["name", "description", "text", "raw_text", "label"];
+const ANONYMIZED_MAPPING_FILE: &str = "mapping.json.enc";
+// Async commands whose work runs in `run_blocking`, which times them itself.
+const BLOCKING_COMMANDS: [&str; 39] = [
+  "get_inbox_counts",
+  "get_disk_usage",
+  "hydrate_file",
+  "get_last_runs",
+  "get_run_stats",
+  "search_runs",
+  "get_unread_badges",
+  "run_worker",
+  "run_worker_for_files",
+  "run_update",
+  "run_full_update",
+  "get_update_status",
+  "get_update_changelog",
+  "rebuild_index",
+  "get_spending_summary",
+  "get_monthly_report",
+  "export_receipts_csv",
+  "search_receipts",
+  "search_ocr_text",
+  "get_category_breakdown",
+  "find_duplicate_receipts",
+  "test_webhook",
+  "get_activity_heatmap",
+  "get_price_history",
+  "get_budget_status",
+  "publish_dashboard_summary",
+  "export_receipts_xlsx",
+  "get_top_spending",
+  "check_inbox_integrity",
+  "preview_run",
+  "check_data_integrity",
+  "create_backup",
+  "preview_restore",
+  "restore_backup",
+  "export_anonymized",
+  "rebuild_stats_cache",
+  "get_yearly_comparison",
+  "open_run_window",
+  "export_diagnostics_bundle",
+];
+// Upper bounds (ms) of the command latency histogram; a last bucket counts the rest.
+const METRICS_BUCKETS_MS: [u64; 8] = [1, 5, 10, 50, 100, 500, 1000, 5000];
+const LOG_LEVELS: [&str; 5] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+const SECRET_KEY_MARKERS: [&str; 8] = [
+  "token",
+  "secret",
+  "password",
+  "passwd",
+  "credential",
+  "apikey",
+  "api_key",
+  "authorization",
+];
+const SECRET_VALUE_PREFIXES: [&str; 8] = [
+  "sk-",
+  "ghp_",
+  "gho_",
+  "github_pat_",
+  "xoxb-",
+  "xoxp-",
+  "AKIA",
+  "Bearer ",
+];
+const PREF_MAX_BYTES: usize = 16 * 1024;
+const PREF_WINDOW_GEOMETRY: &str = "window.geometry";
+const PREF_RUN_WINDOW_GEOMETRY: &str = "window.run_geometry";
+const PREF_THEME: &str = "ui.theme";
+const PREF_LAST_SELECTED_STORE: &str = "ui.last_selected_store";
+const PREF_OPENERS: &str = "open.openers";
+const PREF_RECENT_LOCATIONS: &str = "open.recent_locations";
+const PREF_SHORTCUTS: &str = "shortcuts.bindings";
+const PREF_AUTOSTART: &str = "system.autostart";
+// Set once `initialize_receipts_root` has run (or the folder was already there
+// at startup); until then the frontend shows the first-run wizard.
+const PREF_FIRST_RUN_COMPLETED: &str = "system.first_run_completed";
+// What quitting does while a run or update is in progress: `ask` (default) or
+// one of the QUIT_ACTIONS other than `stay`.
+const PREF_QUIT_WHILE_BUSY: &str = "system.quit_while_busy";
+const QUIT_ACTIONS: [&str; 3] = ["cancel_run_and_quit", "quit_leave_running", "stay"];
+// How long a cancelled run gets to unwind before the app exits anyway.
+const QUIT_GRACE: Duration = Duration::from_secs(5);
+const SHORTCUT_ACTIONS: [&str; 3] = ["run_all", "show_window", "open_latest_failure"];
+const DEFAULT_SHORTCUTS: [(&str, &str); 1] = [("run_all", "CmdOrCtrl+Shift+R")];
+const RECENT_LOCATIONS_CAP: usize = 30;
+const DEFAULT_RECENT_LOCATIONS_LIMIT: usize = 5;
+// Offered by list_openers until the user stores their own list for an extension.
+#[cfg(target_os = "macos")]
+const DEFAULT_OPENERS: &[(&str, &[&str])] = &[
+  ("pdf", &["Preview"]),
+  ("jpg", &["Preview"]),
+  ("jpeg", &["Preview"]),
+  ("png", &["Preview"]),
+  ("heic", &["Preview"]),
+];
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_OPENERS: &[(&str, &[&str])] = &[];
+const THEMES: [&str; 3] = ["light", "dark", "system"];
+const PREFS_SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+// Run detail windows: labels are the prefix plus the escaped run id.
+const RUN_WINDOW_PREFIX: &str = "run-";
+const RUN_WINDOW_SIZE: (f64, f64) = (900.0, 700.0);
+const RUN_WINDOW_CASCADE: i32 = 24;
+const TRAY_ICON: &[u8] = include_bytes!("../icons/icon.iconset/icon_32x32@2x.png");
+
+mod access;
+mod anonymize;
+mod api;
+mod autostart;
+mod backup;
+mod budget;
+mod bundle;
+mod categories;
+mod cli;
+mod commands;
+mod comparison;
+mod config;
+mod crash;
+mod currency;
+mod deeplink;
+mod diagnostics;
+mod duplicates;
+mod error;
+mod export;
+mod heatmap;
+mod inbox;
+mod index;
+mod instance;
+mod integrity;
+mod logging;
+mod menu;
+mod metrics;
+mod ocr;
+mod onboarding;
+mod open;
+mod paths;
+mod prefs;
+mod prices;
+mod publish;
+mod quit;
+mod reachability;
+mod receipts;
+mod restore;
+mod runs;
+mod scanner;
+mod search;
+mod shortcuts;
+mod spending;
+mod startup;
+mod state;
+#[cfg(test)]
+mod test_support;
+mod top;
+mod tray;
+mod update;
+mod webhook;
+mod windows;
+mod worker;
+mod xlsx;
+
+use access::*;
+use anonymize::*;
+use api::*;
+use autostart::*;
+use backup::*;
+use budget::*;
+use bundle::*;
+use categories::*;
+use cli::*;
+use comparison::*;
+use config::*;
+use crash::*;
+use currency::*;
+use deeplink::*;
+use diagnostics::*;
+use duplicates::*;
+use error::*;
+use export::*;
+use heatmap::*;
+use inbox::*;
+use index::*;
+use instance::*;
+use integrity::*;
+use logging::*;
+use menu::*;
+use metrics::*;
+use ocr::*;
+use onboarding::*;
+use open::*;
+use paths::*;
+use prefs::*;
+use prices::*;
+use publish::*;
+use quit::*;
+use reachability::*;
+use receipts::*;
+use restore::*;
+use runs::*;
+use scanner::*;
+use search::*;
+use shortcuts::*;
+use spending::*;
+use startup::*;
+use state::*;
+use top::*;
+use tray::*;
+use update::*;
+use webhook::*;
+use windows::*;
+use worker::*;
+use xlsx::*;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct StoreConfig {
-  id: String,
-  name: String,
-  enabled: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct AppConfig {
-  receipts_root: String,
-  worker_dir: Option<String>,
-  worker_run_cmd: Option<String>,
-  stores: Vec<StoreConfig>,
-  config_ready: bool,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
-struct LocalConfig {
-  receipts_root: Option<String>,
-  worker_dir: Option<String>,
-  worker_run_cmd: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct InboxCount {
-  store_id: String,
-  count: usize,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
-struct StoreSeenState {
-  last_seen_failure_run_id: Option<String>,
-  last_seen_warning_run_id: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
-struct SeenState {
-  stores: HashMap<String, StoreSeenState>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct UnreadBadge {
-  store_id: String,
-  warnings_unread: bool,
-  failures_unread: bool,
-  last_warning_run_id: Option<String>,
-  last_failure_run_id: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct RunWorkerResult {
-  status: String,
-  exit_code: Option<i32>,
-  stdout: String,
-  stderr: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct WorkerLogEvent {
-  stream: String,
-  line: String,
-  stores: Vec<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct UpdateStatus {
-  status: String,
-  installed_version: String,
-  source_version: Option<String>,
-  source_path: Option<String>,
-  message: Option<String>,
-}
-
-fn default_receipts_root() -> String {
-  if let Some(home) = home_dir() {
-    return home.join(DEFAULT_RECEIPTS_ROOT).to_string_lossy().to_string();
-  }
-  DEFAULT_RECEIPTS_ROOT.to_string()
-}
-
-fn default_worker_dir() -> Option<String> {
-  let home = home_dir()?;
-  let path = home.join(DEFAULT_WORKER_DIR);
-  if path.exists() {
-    Some(path.to_string_lossy().to_string())
-  } else {
-    None
-  }
-}
-
-fn default_worker_cmd(worker_dir: &Option<String>) -> Option<String> {
-  let dir = worker_dir.as_deref()?;
-  let path = Path::new(dir).join("run.sh");
-  if path.exists() {
-    Some(path.to_string_lossy().to_string())
-  } else {
-    None
-  }
-}
-
-fn env_var(key: &str) -> Option<String> {
-  std::env::var(key).ok().filter(|value| !value.trim().is_empty())
-}
-
-fn load_stores_config(package_env: Option<(&PackageInfo, &Env)>) -> Vec<StoreConfig> {
-  let mut paths = Vec::new();
-  if let Some(custom) = env_var("RECEIPTS_STORES_PATH") {
-    paths.push(PathBuf::from(custom));
-  }
-  if let Ok(current) = std::env::current_dir() {
-    paths.push(current.join("config").join("stores.json"));
-    paths.push(current.join("..").join("config").join("stores.json"));
-  }
-  if let Some((package_info, env)) = package_env {
-    if let Some(resource_base) = resource_dir(package_info, env) {
-      paths.push(resource_base.join("stores.json"));
-    }
-  }
-
-  for path in paths {
-    if path.exists() {
-      if let Ok(raw) = fs::read_to_string(&path) {
-        if let Ok(stores) = serde_json::from_str::<Vec<StoreConfig>>(&raw) {
-          return stores;
-        }
-      }
-    }
-  }
-
-  vec![
-    StoreConfig {
-      id: "lidl".to_string(),
-      name: "Lidl".to_string(),
-      enabled: true,
-    },
-    StoreConfig {
-      id: "kaufland".to_string(),
-      name: "Kaufland".to_string(),
-      enabled: false,
-    },
-    StoreConfig {
-      id: "carrefour".to_string(),
-      name: "Carrefour".to_string(),
-      enabled: false,
-    },
-  ]
-}
-
-fn read_app_config(package_env: Option<(&PackageInfo, &Env)>) -> AppConfig {
-  let local = load_local_config();
-  let receipts_root = env_var("RECEIPTS_ROOT")
-    .or(local.receipts_root)
-    .unwrap_or_else(default_receipts_root);
-  let worker_dir = env_var("WORKER_DIR")
-    .or(local.worker_dir)
-    .or_else(default_worker_dir);
-  let worker_run_cmd = env_var("WORKER_RUN_CMD")
-    .or(local.worker_run_cmd)
-    .or_else(|| default_worker_cmd(&worker_dir));
-  let receipts_ok = Path::new(receipts_root.trim()).exists();
-  let worker_dir_ok = worker_dir
-    .as_deref()
-    .map(|path| Path::new(path).exists())
-    .unwrap_or(false);
-  let worker_cmd_ok = worker_run_cmd
-    .as_deref()
-    .map(|path| Path::new(path).exists())
-    .unwrap_or(false);
-  let config_ready = receipts_ok && (worker_cmd_ok || worker_dir_ok);
-
-  AppConfig {
-    receipts_root,
-    worker_dir,
-    worker_run_cmd,
-    stores: load_stores_config(package_env),
-    config_ready,
-  }
-}
-
-fn default_source_dir() -> Option<PathBuf> {
-  let home = home_dir()?;
-  Some(
-    home
-      .join("Documents")
-      .join("Github repos")
-      .join("life-dashboard")
-      .join("apps")
-      .join("receipts-desktop"),
-  )
-}
-
-fn resolve_source_dir() -> Option<PathBuf> {
-  if let Some(custom) = env_var("RECEIPTS_APP_SOURCE") {
-    let path = PathBuf::from(custom);
-    if path.exists() {
-      return Some(path);
-    }
-  }
-  let fallback = default_source_dir();
-  if let Some(path) = fallback.clone() {
-    if path.exists() {
-      return Some(path);
+fn main() {
+  // Headless flags are handled before anything of the app is built, so they
+  // work without a display.
+  let headless = match parse_cli_args(std::env::args().skip(1)) {
+    Ok(headless) => headless,
+    Err(message) => {
+      eprintln!("{}\n\n{}", message, CLI_USAGE);
+      std::process::exit(EXIT_USAGE);
     }
-  }
-  None
-}
-
-fn read_source_version(source_dir: &Path) -> Result<String, String> {
-  let path = source_dir.join("src-tauri").join("tauri.conf.json");
-  let raw = fs::read_to_string(&path).map_err(|err| err.to_string())?;
-  let value: Value = serde_json::from_str(&raw).map_err(|err| err.to_string())?;
-  value
-    .get("package")
-    .and_then(|pkg| pkg.get("version"))
-    .and_then(|ver| ver.as_str())
-    .map(|ver| ver.to_string())
-    .ok_or_else(|| "Missing package.version in tauri.conf.json".to_string())
-}
-
-fn state_file_path() -> Option<PathBuf> {
-  let home = home_dir()?;
-  Some(home.join(STATE_DIR).join(STATE_FILE))
-}
-
-fn config_file_path() -> Option<PathBuf> {
-  let home = home_dir()?;
-  Some(home.join(STATE_DIR).join("config.json"))
-}
-
-fn load_local_config() -> LocalConfig {
-  let path = match config_file_path() {
-    Some(path) => path,
-    None => return LocalConfig::default(),
   };
-  if let Ok(raw) = fs::read_to_string(path) {
-    if let Ok(config) = serde_json::from_str::<LocalConfig>(&raw) {
-      return config;
-    }
+  init_logging(headless.is_some());
+  install_panic_hook();
+  if let Some(request) = headless {
+    std::process::exit(run_headless(request));
   }
-  LocalConfig::default()
-}
-
-fn save_local_config(config: &LocalConfig) -> Result<(), String> {
-  let path = config_file_path().ok_or("Missing home directory")?;
-  if let Some(parent) = path.parent() {
-    fs::create_dir_all(parent).map_err(|err| err.to_string())?;
-  }
-  let raw = serde_json::to_string_pretty(config).map_err(|err| err.to_string())?;
-  fs::write(path, raw).map_err(|err| err.to_string())?;
-  Ok(())
-}
-
-fn load_state() -> SeenState {
-  if let Some(path) = state_file_path() {
-    if let Ok(raw) = fs::read_to_string(path) {
-      if let Ok(state) = serde_json::from_str(&raw) {
-        return state;
+  info!("receipts-desktop {} starting", APP_VERSION);
+  let launch_link = deep_link_arg(std::env::args().skip(1));
+  // Held until the process exits; a second launch focuses this instance and quits.
+  let (_instance_lock, focus_listener) = match acquire_instance_lock_after_relaunch() {
+    Ok(InstanceLock::Acquired(file, listener)) => (Some(file), Some(listener)),
+    Ok(InstanceLock::Running(info)) => {
+      match (info, &launch_link) {
+        (Some(info), Some(url)) => request_open(&info, url),
+        (Some(info), None) => request_focus(&info),
+        (None, _) => {}
       }
+      info!("receipts-desktop is already running");
+      return;
     }
-  }
-  SeenState::default()
-}
-
-fn save_state(state: &SeenState) -> Result<(), String> {
-  let path = state_file_path().ok_or("Missing home directory")?;
-  if let Some(parent) = path.parent() {
-    fs::create_dir_all(parent).map_err(|err| err.to_string())?;
-  }
-  let raw = serde_json::to_string_pretty(state).map_err(|err| err.to_string())?;
-  fs::write(path, raw).map_err(|err| err.to_string())?;
-  Ok(())
-}
-
-fn list_run_summaries(receipts_root: &str) -> Vec<(Value, Option<std::time::SystemTime>)> {
-  let runs_dir = Path::new(receipts_root).join("_logs").join("runs");
-  let mut summaries = Vec::new();
-  let entries = match fs::read_dir(runs_dir) {
-    Ok(entries) => entries,
-    Err(_) => return summaries,
-  };
-
-  for entry in entries.flatten() {
-    let path = entry.path();
-    if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
-      continue;
-    }
-    if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
-      if !name.ends_with(".summary.json") {
-        continue;
-      }
-    }
-
-    if let Ok(raw) = fs::read_to_string(&path) {
-      if let Ok(value) = serde_json::from_str::<Value>(&raw) {
-        let modified = entry.metadata().and_then(|meta| meta.modified()).ok();
-        summaries.push((value, modified));
-      }
+    Err(err) => {
+      warn!("instance lock: {}; continuing without it", err);
+      (None, None)
     }
+  };
+  if let Err(err) = tauri_plugin_deep_link::set_identifier(BUNDLE_IDENTIFIER) {
+    warn!("deep links: {}", err);
   }
-
-  summaries
-}
-
-fn extract_run_id(value: &Value) -> Option<String> {
-  value
-    .get("run_id")
-    .and_then(|id| id.as_str())
-    .map(|id| id.to_string())
-}
-
-fn extract_stores(value: &Value) -> Vec<String> {
-  value
-    .get("stores")
-    .and_then(|stores| stores.as_array())
-    .map(|arr| {
-      arr
-        .iter()
-        .filter_map(|item| item.as_str().map(|s| s.to_string()))
-        .collect()
+  tauri::Builder::default()
+    .manage(DiskUsageCache::default())
+    .manage(InboxCountCache::default())
+    .manage(PendingNotificationStore::default())
+    .manage(BadgeEvents::default())
+    .manage(IssueRunsCache::default())
+    .manage(UpdateStatusCache::default())
+    .manage(ChangelogCache::default())
+    .manage(CommandsRunning::default())
+    .manage(ChildRegistry::default())
+    .manage(CommandMetrics::default())
+    .manage(LastDiagnosticsBundle::default())
+    .manage(DashboardScanner::default())
+    .manage(RootHealth::default())
+    .manage(DeepLinkQueue::default())
+    .manage(MonthlyReportCache::default())
+    .manage(ActivityHeatmapCache::default())
+    .manage(DataIntegrityCache::default())
+    .manage(ApiServer::default())
+    .manage(RunIndex(Mutex::new(open_run_index())))
+    .manage(InstalledUpdateState::default())
+    .manage(ShortcutRegistry::default())
+    .manage(MenuState::default())
+    .manage(StartupState::new())
+    .manage(QuitState::default())
+    .manage(PrefsStore {
+      prefs: Mutex::new(load_prefs()),
+      save_pending: Mutex::new(false),
     })
-    .unwrap_or_default()
-}
-
-fn extract_has_issues(value: &Value, key: &str) -> bool {
-  value
-    .get(key)
-    .and_then(|entries| entries.as_array())
-    .map(|arr| !arr.is_empty())
-    .unwrap_or(false)
-}
-
-fn latest_issue_runs(summaries: &[Value]) -> HashMap<String, (Option<String>, Option<String>)> {
-  let mut map: HashMap<String, (Option<String>, Option<String>)> = HashMap::new();
-
-  for summary in summaries {
-    let run_id = match extract_run_id(summary) {
-      Some(id) => id,
-      None => continue,
-    };
-    let stores = extract_stores(summary);
-    let has_failures = extract_has_issues(summary, "failures");
-    let has_warnings = extract_has_issues(summary, "warnings");
-
-    for store in stores {
-      let entry = map.entry(store).or_insert((None, None));
-      if has_failures {
-        if entry
-          .0
-          .as_deref()
-          .map(|prev| run_id.as_str() > prev)
-          .unwrap_or(true)
-        {
-          entry.0 = Some(run_id.clone());
+    .system_tray(SystemTray::new().with_menu(tray_menu(&read_app_config(None))))
+    .on_system_tray_event(handle_tray_event)
+    .menu(app_menu())
+    .on_menu_event(handle_menu_event)
+    .setup(move |app| {
+      let handle = app.handle();
+      // A missing worker setup is reported, but the dashboard still loads.
+      let config = startup_phase(&handle, "config", || {
+        let config = read_app_config(Some((app.package_info(), &app.env())));
+        let problem = worker_config_error(&config);
+        (config, problem)
+      });
+      app.manage(ConfigStore(RwLock::new(config.clone())));
+      startup_phase(&handle, "caches", || {
+        restore_leftover_staging(&config);
+        let (mut state, recovered) = load_state();
+        if let Some(message) = recovered {
+          warn!("state recovery: {}", message);
         }
-      }
-      if has_warnings {
-        if entry
-          .1
-          .as_deref()
-          .map(|prev| run_id.as_str() > prev)
-          .unwrap_or(true)
-        {
-          entry.1 = Some(run_id.clone());
+        if prepare_loaded_state(&mut state, &config) {
+          let _ = save_state(&state);
         }
+        app.manage(SeenStateStore(Mutex::new(state)));
+        restore_installed_update(&handle);
+        let index_open = app
+          .state::<RunIndex>()
+          .0
+          .lock()
+          .is_ok_and(|conn| conn.is_some());
+        let problem = (!index_open)
+          .then(|| "The run index could not be opened; runs are read from the folders".to_string());
+        ((), problem)
+      });
+      let _ = app.tray_handle().set_menu(tray_menu(&config));
+      let prefs_store = app.state::<PrefsStore>();
+      let prefs = prefs_store.prefs.lock().map(|mut prefs| {
+        if reconcile_first_run(&mut prefs, &config) {
+          if let Err(err) = save_prefs(&prefs) {
+            warn!("prefs save failed: {}", err);
+          }
+        }
+        prefs.clone()
+      });
+      if let Ok(prefs) = &prefs {
+        reconcile_autostart(prefs);
       }
-    }
-  }
-
-  map
-}
-
-#[tauri::command]
-fn get_config(app: tauri::AppHandle) -> AppConfig {
-  read_app_config(Some((app.package_info(), &app.env())))
-}
-
-#[tauri::command]
-fn set_config(
-  receipts_root: String,
-  worker_dir: Option<String>,
-  worker_run_cmd: Option<String>,
-) -> Result<(), String> {
-  let config = LocalConfig {
-    receipts_root: Some(receipts_root),
-    worker_dir,
-    worker_run_cmd,
-  };
-  save_local_config(&config)
-}
-
-#[tauri::command]
-fn get_inbox_counts() -> Result<Vec<InboxCount>, String> {
-  let config = read_app_config(None);
-  let mut results = Vec::new();
-  for store in config.stores {
-    let inbox_path = Path::new(&config.receipts_root)
-      .join("inbox")
-      .join(&store.id);
-    let mut count = 0usize;
-    if let Ok(entries) = fs::read_dir(inbox_path) {
-      for entry in entries.flatten() {
-        let path = entry.path();
-        if !path.is_file() {
-          continue;
+      if let Some(window) = app.get_window("main") {
+        if let Ok(prefs) = &prefs {
+          restore_window_geometry(&window, prefs);
         }
-        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
-          let ext = ext.to_lowercase();
-          if ext == "png" || ext == "jpg" || ext == "jpeg" || ext == "pdf" {
-            count += 1;
-          }
+        // The window starts hidden (tauri.conf.json) so a login launch never
+        // flashes it.
+        if !launched_at_login() {
+          let _ = window.show();
         }
       }
-    }
-    results.push(InboxCount {
-      store_id: store.id,
-      count,
-    });
-  }
-  Ok(results)
-}
-
-#[tauri::command]
-fn get_last_runs(limit: Option<usize>) -> Result<Vec<Value>, String> {
-  let config = read_app_config(None);
-  let mut summaries = list_run_summaries(&config.receipts_root);
-  summaries.sort_by(|a, b| b.1.cmp(&a.1));
-
-  let capped = summaries
-    .into_iter()
-    .map(|(value, _)| value)
-    .take(limit.unwrap_or(5))
-    .collect::<Vec<_>>();
-  Ok(capped)
-}
-
-#[tauri::command]
-fn get_unread_badges() -> Result<Vec<UnreadBadge>, String> {
-  let config = read_app_config(None);
-  let summaries = list_run_summaries(&config.receipts_root)
-    .into_iter()
-    .map(|(value, _)| value)
-    .collect::<Vec<_>>();
-  let latest_map = latest_issue_runs(&summaries);
-  let state = load_state();
-
-  let mut badges = Vec::new();
-  for store in config.stores {
-    let seen = state.stores.get(&store.id).cloned().unwrap_or_default();
-    let (latest_failure, latest_warning) = latest_map
-      .get(&store.id)
-      .cloned()
-      .unwrap_or((None, None));
-
-    let failures_unread = latest_failure
-      .as_deref()
-      .and_then(|latest| {
-        seen
-          .last_seen_failure_run_id
-          .as_deref()
-          .map(|seen_id| latest > seen_id)
-          .or(Some(true))
-      })
-      .unwrap_or(false);
-
-    let warnings_unread = latest_warning
-      .as_deref()
-      .and_then(|latest| {
-        seen
-          .last_seen_warning_run_id
-          .as_deref()
-          .map(|seen_id| latest > seen_id)
-          .or(Some(true))
-      })
-      .unwrap_or(false);
-
-    badges.push(UnreadBadge {
-      store_id: store.id,
-      warnings_unread,
-      failures_unread,
-      last_warning_run_id: latest_warning,
-      last_failure_run_id: latest_failure,
-    });
-  }
-
-  Ok(badges)
-}
-
-#[tauri::command]
-fn mark_store_badges_seen(store_id: String) -> Result<(), String> {
-  let config = read_app_config(None);
-  let summaries = list_run_summaries(&config.receipts_root)
-    .into_iter()
-    .map(|(value, _)| value)
-    .collect::<Vec<_>>();
-  let latest_map = latest_issue_runs(&summaries);
-  let mut state = load_state();
-  let latest = latest_map.get(&store_id).cloned().unwrap_or((None, None));
-  let entry = state
-    .stores
-    .entry(store_id)
-    .or_insert_with(StoreSeenState::default);
-  entry.last_seen_failure_run_id = latest.0;
-  entry.last_seen_warning_run_id = latest.1;
-  save_state(&state)
-}
-
-#[tauri::command]
-fn run_worker(
-  window: tauri::Window,
-  stores: Vec<String>,
-  mode: String,
-) -> Result<RunWorkerResult, String> {
-  let config = read_app_config(None);
-  if !config.config_ready {
-    let mut missing = Vec::new();
-    if !Path::new(config.receipts_root.trim()).exists() {
-      missing.push(format!("RECEIPTS_ROOT not found: {}", config.receipts_root));
-    }
-    if config.worker_run_cmd.as_deref().unwrap_or("").trim().is_empty() {
-      missing.push("WORKER_RUN_CMD is empty".to_string());
-    } else if let Some(cmd) = &config.worker_run_cmd {
-      if !Path::new(cmd).exists() {
-        missing.push(format!("WORKER_RUN_CMD not found: {}", cmd));
+      register_deep_links(&app.handle());
+      if let Some(url) = launch_link.as_deref() {
+        handle_deep_link(&app.handle(), url);
       }
-    }
-    if config.worker_dir.as_deref().unwrap_or("").trim().is_empty() {
-      missing.push("WORKER_DIR is empty".to_string());
-    } else if let Some(dir) = &config.worker_dir {
-      if !Path::new(dir).exists() {
-        missing.push(format!("WORKER_DIR not found: {}", dir));
+      if let Some(listener) = focus_listener {
+        let handle = app.handle();
+        thread::spawn(move || serve_focus_requests(handle, listener));
       }
-    }
-    let details = if missing.is_empty() {
-      "Missing config: set RECEIPTS_ROOT and WORKER_DIR/WORKER_RUN_CMD".to_string()
-    } else {
-      format!("Missing config: {}", missing.join(" | "))
-    };
-    let _ = window.emit(
-      "worker-log",
-      WorkerLogEvent {
-        stream: "stderr".to_string(),
-        line: details.clone(),
-        stores: stores.clone(),
-      },
-    );
-    return Err(details);
-  }
-  let _ = window.emit(
-    "worker-log",
-    WorkerLogEvent {
-      stream: "stdout".to_string(),
-      line: format!(
-        "Starting worker: root={} dir={} cmd={}",
-        config.receipts_root,
-        config.worker_dir.clone().unwrap_or_else(|| "—".to_string()),
-        config.worker_run_cmd.clone().unwrap_or_else(|| "—".to_string())
-      ),
-      stores: stores.clone(),
-    },
-  );
-  let mut args: Vec<String> = Vec::new();
-  if stores.is_empty() {
-    args.push("--all".to_string());
-  } else if stores.len() == 1 {
-    args.push("--store".to_string());
-    args.push(stores.join(","));
-  } else {
-    args.push("--stores".to_string());
-    args.push(stores.join(","));
-  }
-  let _ = mode;
-
-  if let Some(run_cmd) = config.worker_run_cmd {
-    let mut command = Command::new(run_cmd);
-    command.args(args);
-    if let Some(worker_dir) = config.worker_dir {
-      command.current_dir(worker_dir);
-    }
-    return run_command_stream(&window, command, stores, false);
-  }
-
-  let worker_dir = config
-    .worker_dir
-    .ok_or_else(|| "WORKER_DIR is not set".to_string())?;
-  let mut python_path = Path::new(&worker_dir).join(".venv").join("bin").join("python");
-  if !python_path.exists() {
-    python_path = PathBuf::from("python3");
-  }
-
-  let mut command = Command::new(python_path);
-  command
-    .current_dir(worker_dir)
-    .arg("-m")
-    .arg("src.runner");
-
-  if stores.is_empty() {
-    command.arg("--all");
-  } else if stores.len() == 1 {
-    command.arg("--store").arg(stores.join(","));
-  } else {
-    command.arg("--stores").arg(stores.join(","));
-  }
-  let _ = mode;
-
-  run_command_stream(&window, command, stores, false)
-}
-
-fn run_command_stream(
-  window: &tauri::Window,
-  mut command: Command,
-  stores: Vec<String>,
-  stderr_as_stdout: bool,
-) -> Result<RunWorkerResult, String> {
-  let mut child = command
-    .stdout(Stdio::piped())
-    .stderr(Stdio::piped())
-    .spawn()
-    .map_err(|err| err.to_string())?;
-
-  let stdout = child.stdout.take().ok_or("Missing stdout")?;
-  let stderr = child.stderr.take().ok_or("Missing stderr")?;
-
-  let stdout_buffer = Arc::new(Mutex::new(String::new()));
-  let stderr_buffer = Arc::new(Mutex::new(String::new()));
-
-  let stdout_clone = stdout_buffer.clone();
-  let stderr_clone = stderr_buffer.clone();
-  let stores_clone = stores.clone();
-  let window_stdout = window.clone();
-  let window_stderr = window.clone();
-  let stderr_to_stdout = stderr_as_stdout;
-
-  let stdout_handle = thread::spawn(move || {
-    let reader = BufReader::new(stdout);
-    for line in reader.lines().flatten() {
-      let _ = window_stdout.emit(
-        "worker-log",
-        WorkerLogEvent {
-          stream: "stdout".to_string(),
-          line: line.clone(),
-          stores: stores_clone.clone(),
-        },
-      );
-      if let Ok(mut buf) = stdout_clone.lock() {
-        buf.push_str(&line);
-        buf.push('\n');
+      let handle = app.handle();
+      thread::spawn(move || watch_runs_dir(handle));
+      let handle = app.handle();
+      thread::spawn(move || emit_status_snapshots(handle));
+      let handle = app.handle();
+      thread::spawn(move || run_dashboard_scanner(handle));
+      let handle = app.handle();
+      thread::spawn(move || check_updates_periodically(handle));
+      sync_api_server(&app.handle(), &config);
+      sync_shortcuts(&app.handle());
+      refresh_menu_state(&app.handle(), None);
+      Ok(())
+    })
+    .on_window_event(|event| match event.event() {
+      // Clicking a notification focuses the app; use that to open the store it was about.
+      tauri::WindowEvent::Focused(true) if event.window().label() == "main" => {
+        let pending = event
+          .window()
+          .state::<PendingNotificationStore>()
+          .0
+          .lock()
+          .ok()
+          .and_then(|mut pending| pending.take());
+        if let Some(store_id) = pending {
+          let _ = event.window().emit("open-store", store_id);
+        }
       }
-    }
-  });
-
-  let stderr_handle = thread::spawn(move || {
-    let reader = BufReader::new(stderr);
-    for line in reader.lines().flatten() {
-      let stream_label = if stderr_to_stdout { "stdout" } else { "stderr" };
-      let _ = window_stderr.emit(
-        "worker-log",
-        WorkerLogEvent {
-          stream: stream_label.to_string(),
-          line: line.clone(),
-          stores: stores.clone(),
-        },
-      );
-      if let Ok(mut buf) = stderr_clone.lock() {
-        buf.push_str(&line);
-        buf.push('\n');
+      tauri::WindowEvent::Resized(_) | tauri::WindowEvent::Moved(_) => {
+        capture_window_geometry(event.window());
       }
-    }
-  });
-
-  let status = child.wait().map_err(|err| err.to_string())?;
-  let _ = stdout_handle.join();
-  let _ = stderr_handle.join();
-
-  let stdout_text = stdout_buffer
-    .lock()
-    .map(|buf| buf.clone())
-    .unwrap_or_default();
-  let stderr_text = stderr_buffer
-    .lock()
-    .map(|buf| buf.clone())
-    .unwrap_or_default();
-
-  Ok(RunWorkerResult {
-    status: if status.success() {
-      "ok".to_string()
-    } else {
-      "fail".to_string()
-    },
-    exit_code: status.code(),
-    stdout: stdout_text,
-    stderr: stderr_text,
-  })
-}
-
-#[tauri::command]
-fn get_update_status(app: tauri::AppHandle) -> Result<UpdateStatus, String> {
-  let installed_version = app.package_info().version.to_string();
-  let source_dir = resolve_source_dir();
-
-  if source_dir.is_none() {
-    return Ok(UpdateStatus {
-      status: "source_missing".to_string(),
-      installed_version,
-      source_version: None,
-      source_path: None,
-      message: Some("Source code not found. Set RECEIPTS_APP_SOURCE.".to_string()),
+      tauri::WindowEvent::CloseRequested { api, .. } => {
+        handle_close_requested(event.window(), api);
+      }
+      _ => {}
+    })
+    .invoke_handler(log_invocations(tauri::generate_handler![
+      commands::get_config,
+      commands::set_config,
+      commands::set_notification_settings,
+      commands::set_store_notifications,
+      commands::set_minimize_to_tray,
+      commands::get_autostart,
+      commands::set_autostart,
+      commands::get_shortcuts,
+      commands::set_shortcut,
+      commands::set_badge_metric,
+      commands::get_pref,
+      commands::set_pref,
+      commands::ensure_store_dirs,
+      commands::detect_receipts_locations,
+      commands::initialize_receipts_root,
+      commands::import_files,
+      commands::import_from_clipboard,
+      commands::list_inbox_files,
+      commands::set_file_tag,
+      commands::list_tags,
+      commands::read_inbox_file,
+      commands::delete_inbox_file,
+      commands::hydrate_file,
+      commands::check_inbox_integrity,
+      commands::check_data_integrity,
+      commands::preview_run,
+      commands::get_inbox_counts,
+      commands::get_disk_usage,
+      commands::get_last_runs,
+      commands::get_run_stats,
+      commands::search_runs,
+      commands::get_unread_badges,
+      commands::get_status_snapshot,
+      commands::get_startup_status,
+      commands::get_root_status,
+      commands::open_full_disk_access_settings,
+      commands::run_worker,
+      commands::run_worker_for_files,
+      commands::get_worker_status,
+      commands::cancel_run,
+      commands::get_update_status,
+      commands::get_update_changelog,
+      commands::run_update,
+      commands::run_full_update,
+      commands::get_update_history,
+      commands::cancel_update,
+      commands::relaunch_app,
+      commands::resolve_quit,
+      commands::mark_store_badges_seen,
+      commands::mark_all_badges_seen,
+      commands::snooze_store,
+      commands::list_state_backups,
+      commands::restore_state_backup,
+      commands::open_path,
+      commands::reveal_path,
+      commands::open_with,
+      commands::list_openers,
+      commands::get_recent_locations,
+      commands::open_terminal,
+      commands::copy_path_to_clipboard,
+      commands::copy_file_to_clipboard,
+      commands::open_run_artifact,
+      commands::open_run_window,
+      commands::open_latest_inbox_file,
+      commands::get_app_logs,
+      commands::get_crash_reports,
+      commands::delete_crash_report,
+      commands::rebuild_index,
+      commands::rebuild_stats_cache,
+      commands::take_pending_deep_links,
+      commands::get_diagnostics,
+      commands::export_diagnostics_bundle,
+      commands::reveal_diagnostics_bundle,
+      commands::get_spending_summary,
+      commands::get_monthly_report,
+      commands::get_activity_heatmap,
+      commands::get_budget_status,
+      commands::export_receipts_csv,
+      commands::export_receipts_xlsx,
+      commands::export_anonymized,
+      commands::create_backup,
+      commands::cancel_backup,
+      commands::preview_restore,
+      commands::restore_backup,
+      commands::get_receipt_detail,
+      commands::search_receipts,
+      commands::search_ocr_text,
+      commands::get_category_breakdown,
+      commands::get_top_spending,
+      commands::get_yearly_comparison,
+      commands::get_category_mapping,
+      commands::set_category_mapping,
+      commands::find_duplicate_receipts,
+      commands::get_price_history,
+      commands::mark_not_duplicate,
+      commands::get_api_status,
+      commands::set_api_enabled,
+      commands::test_webhook,
+      commands::publish_dashboard_summary,
+      commands::reload_config,
+      commands::get_command_metrics,
+      commands::reset_command_metrics
+    ]))
+    .build(tauri::generate_context!())
+    .expect("error while running tauri application")
+    .run(|app, event| match event {
+      // Cmd+Q, the last window closing, logout/shutdown where the OS asks
+      // first. A plain SIGTERM/SIGKILL can't be intercepted.
+      tauri::RunEvent::ExitRequested { api, .. } if !busy_jobs(app).is_empty() => {
+        api.prevent_exit();
+        request_quit(app, "app");
+      }
+      tauri::RunEvent::Exit => stop_api_server(app),
+      _ => {}
     });
-  }
-
-  let source_dir = source_dir.unwrap();
-  let source_version = read_source_version(&source_dir)?;
-  let status = if source_version == installed_version {
-    "up_to_date"
-  } else {
-    "update_available"
-  };
-
-  Ok(UpdateStatus {
-    status: status.to_string(),
-    installed_version,
-    source_version: Some(source_version),
-    source_path: Some(source_dir.to_string_lossy().to_string()),
-    message: None,
-  })
-}
-
-#[tauri::command]
-fn run_update(window: tauri::Window) -> Result<RunWorkerResult, String> {
-  let source_dir = resolve_source_dir().ok_or_else(|| {
-    "Source code not found. Set RECEIPTS_APP_SOURCE to the repo path.".to_string()
-  })?;
-  let script = source_dir.join("scripts").join("update.receipts.operator");
-  if !script.exists() {
-    return Err(format!("Update script not found: {}", script.to_string_lossy()));
-  }
-
-  let mut command = Command::new(script);
-  command.current_dir(&source_dir);
-  run_command_stream(&window, command, Vec::new(), true)
-}
-
-#[tauri::command]
-fn open_path(
-  window: tauri::Window,
-  path_type: String,
-  store_id: Option<String>,
-  file_path: Option<String>,
-) -> Result<(), String> {
-  let config = read_app_config(None);
-  let base = PathBuf::from(&config.receipts_root);
-  let store_value = store_id.clone();
-  let file_value = file_path.clone();
-  let resolved = match path_type.as_str() {
-    "inbox" => base.join("inbox").join(store_id.ok_or("store_id required")?),
-    "processed" => base.join("processed").join(store_id.ok_or("store_id required")?),
-    "failed" => base.join("failed").join(store_id.ok_or("store_id required")?),
-    "logs" => base.join("_logs"),
-    "logFile" => PathBuf::from(file_path.ok_or("file_path required")?),
-    "errorFile" => PathBuf::from(file_path.ok_or("file_path required")?),
-    _ => return Err("Unknown path type".to_string()),
-  };
-
-  println!(
-    "open_path: type={}, store={:?}, file={:?}, resolved={}",
-    path_type,
-    store_value,
-    file_value,
-    resolved.to_string_lossy()
-  );
-
-  if !resolved.exists() {
-    return Err(format!("Path not found: {}", resolved.to_string_lossy()));
-  }
-
-  match shell::open(
-    &window.shell_scope(),
-    resolved.to_string_lossy().to_string(),
-    None,
-  ) {
-    Ok(()) => Ok(()),
-    Err(err) => {
-      let shell_error = err.to_string();
-      println!("shell::open failed: {}", shell_error);
-      open_with_system(&resolved)
-        .map_err(|fallback| format!("open failed: {}; fallback: {}", shell_error, fallback))
-    }
-  }
-}
-
-fn open_with_system(path: &Path) -> Result<(), String> {
-  #[cfg(target_os = "macos")]
-  {
-    let status = Command::new("open")
-      .arg(path)
-      .status()
-      .map_err(|err| err.to_string())?;
-    return if status.success() {
-      Ok(())
-    } else {
-      Err(format!("open exited with status {}", status))
-    };
-  }
-  #[cfg(target_os = "linux")]
-  {
-    let status = Command::new("xdg-open")
-      .arg(path)
-      .status()
-      .map_err(|err| err.to_string())?;
-    return if status.success() {
-      Ok(())
-    } else {
-      Err(format!("xdg-open exited with status {}", status))
-    };
-  }
-  #[cfg(target_os = "windows")]
-  {
-    let status = Command::new("cmd")
-      .args(["/C", "start", "", &path.to_string_lossy()])
-      .status()
-      .map_err(|err| err.to_string())?;
-    return if status.success() {
-      Ok(())
-    } else {
-      Err(format!("cmd start exited with status {}", status))
-    };
-  }
-  #[allow(unreachable_code)]
-  Err("Unsupported platform for open".to_string())
-}
-
-fn main() {
-  tauri::Builder::default()
-    .invoke_handler(tauri::generate_handler![
-      get_config,
-      set_config,
-      get_inbox_counts,
-      get_last_runs,
-      get_unread_badges,
-      run_worker,
-      get_update_status,
-      run_update,
-      mark_store_badges_seen,
-      open_path
-    ])
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
 }
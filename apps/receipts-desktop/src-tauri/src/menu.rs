@@ -0,0 +1,215 @@
+use crate::*;
+use tauri::{Menu, MenuItem, Submenu, WindowMenuEvent};
+
+// Last enablement set per item, so a status or badge event only touches the
+// items whose state actually changed.
+#[derive(Default)]
+pub(crate) struct MenuState(pub(crate) Mutex<HashMap<&'static str, bool>>);
+
+// Items that need something only the frontend has (a file dialog, the selected
+// store, the view itself); they are passed on as a `menu-action` event.
+const FRONTEND_MENU_ACTIONS: [&str; 4] = [
+  "import_receipts",
+  "export_csv",
+  "run_selected_store",
+  "open_diagnostics",
+];
+
+// A custom item rather than the native Quit, so quitting mid-run can ask first.
+pub(crate) fn quit_item() -> CustomMenuItem {
+  CustomMenuItem::new("quit", "Quit").accelerator("CmdOrCtrl+Q")
+}
+
+pub(crate) fn app_menu() -> Menu {
+  let mut file = Menu::new()
+    .add_item(CustomMenuItem::new("import_receipts", "Import receipts…"))
+    .add_item(CustomMenuItem::new("export_csv", "Export CSV…"));
+  let run = Menu::new()
+    .add_item(CustomMenuItem::new("run_all", "Run all"))
+    .add_item(CustomMenuItem::new(
+      "run_selected_store",
+      "Run selected store",
+    ))
+    .add_native_item(MenuItem::Separator)
+    .add_item(CustomMenuItem::new("cancel_run", "Cancel run").disabled());
+  let view = Menu::new()
+    .add_item(CustomMenuItem::new("refresh", "Refresh"))
+    .add_item(CustomMenuItem::new("open_logs", "Open logs"));
+  let help = Menu::new()
+    .add_item(CustomMenuItem::new("open_diagnostics", "Open diagnostics"))
+    .add_item(CustomMenuItem::new("check_updates", "Check for updates"));
+  let mut menu = Menu::new();
+  // A custom menu replaces the default one, and on macOS the webview's
+  // copy/paste shortcuts come from the Edit menu, so both are rebuilt here.
+  if cfg!(target_os = "macos") {
+    menu = menu.add_submenu(Submenu::new(
+      APP_DISPLAY_NAME,
+      Menu::new()
+        .add_native_item(MenuItem::Hide)
+        .add_native_item(MenuItem::HideOthers)
+        .add_native_item(MenuItem::ShowAll)
+        .add_native_item(MenuItem::Separator)
+        .add_item(quit_item()),
+    ));
+  } else {
+    file = file
+      .add_native_item(MenuItem::Separator)
+      .add_item(quit_item());
+  }
+  menu = menu.add_submenu(Submenu::new("File", file));
+  if cfg!(target_os = "macos") {
+    menu = menu.add_submenu(Submenu::new(
+      "Edit",
+      Menu::new()
+        .add_native_item(MenuItem::Undo)
+        .add_native_item(MenuItem::Redo)
+        .add_native_item(MenuItem::Separator)
+        .add_native_item(MenuItem::Cut)
+        .add_native_item(MenuItem::Copy)
+        .add_native_item(MenuItem::Paste)
+        .add_native_item(MenuItem::SelectAll),
+    ));
+  }
+  menu
+    .add_submenu(Submenu::new("Run", run))
+    .add_submenu(Submenu::new("View", view))
+    .add_submenu(Submenu::new("Help", help))
+}
+
+// Run items are off while a run is in progress or the worker isn't configured;
+// Cancel run is only on while there is a run to stop.
+pub(crate) fn menu_enablement(
+  status: &WorkerStatus,
+  configured: bool,
+) -> [(&'static str, bool); 3] {
+  let can_run = configured && !status.running;
+  [
+    ("run_all", can_run),
+    ("run_selected_store", can_run),
+    ("cancel_run", status.running),
+  ]
+}
+
+// Called on `worker-status` (with that status) and after badge recomputes,
+// which follow config and runs-folder changes.
+pub(crate) fn refresh_menu_state(app: &tauri::AppHandle, status: Option<&WorkerStatus>) {
+  let window = match app.get_window("main") {
+    Some(window) => window,
+    None => return,
+  };
+  let status = match status {
+    Some(status) => status.clone(),
+    None => worker_status(app),
+  };
+  let configured = worker_config_error(&current_config(app)).is_none();
+  let menu_state = app.state::<MenuState>();
+  let mut applied = match menu_state.0.lock() {
+    Ok(applied) => applied,
+    Err(_) => return,
+  };
+  let menu = window.menu_handle();
+  for (id, enabled) in menu_enablement(&status, configured) {
+    if applied.get(id) == Some(&enabled) {
+      continue;
+    }
+    if let Some(item) = menu.try_get_item(id) {
+      match item.set_enabled(enabled) {
+        Ok(()) => {
+          applied.insert(id, enabled);
+        }
+        Err(err) => warn!("menu item {}: {}", id, err),
+      }
+    }
+  }
+}
+
+// Runs on the main thread, so anything slow goes to a thread of its own.
+pub(crate) fn handle_menu_event(event: WindowMenuEvent) {
+  let id = event.menu_item_id();
+  let window = event.window().clone();
+  let app = window.app_handle();
+  info!("menu: {}", id);
+  match id {
+    "quit" => {
+      request_quit(&app, "menu");
+    }
+    "run_all" => {
+      thread::spawn(move || run_all_stores(&window));
+    }
+    "cancel_run" => match cancel_worker_run(&app) {
+      Ok(CancelResult {
+        reason: Some(reason),
+        ..
+      }) => emit_worker_line(&EventSink::from(&window), "stderr", reason, &[]),
+      Ok(_) => {}
+      Err(err) => emit_worker_line(&EventSink::from(&window), "stderr", err.message(), &[]),
+    },
+    "open_logs" => {
+      let opened = app_logs_dir()
+        .ok_or_else(|| AppError::HomeUnavailable.message())
+        .and_then(|dir| {
+          fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+          open_resolved(&window, &dir)
+        });
+      if let Err(err) = opened {
+        warn!("open logs: {}", err);
+      }
+    }
+    "check_updates" => {
+      let _ = window.emit("menu-action", id);
+      thread::spawn(move || match check_update_status(&app) {
+        Ok(status) => {
+          record_update_status(&app, &status);
+          let _ = app.emit_all("update-status", status);
+        }
+        Err(err) => warn!("update check failed: {}", err),
+      });
+    }
+    "refresh" => {
+      schedule_badges_update(&app);
+      let _ = window.emit("menu-action", id);
+    }
+    _ if FRONTEND_MENU_ACTIONS.contains(&id) => {
+      let _ = window.emit("menu-action", id);
+    }
+    _ => {}
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn menu_run_items_follow_worker_status() {
+    let idle = WorkerStatus {
+      running: false,
+      pid: None,
+      cancellable: false,
+    };
+    let running = WorkerStatus {
+      running: true,
+      pid: Some(4242),
+      cancellable: true,
+    };
+    assert_eq!(
+      menu_enablement(&idle, true),
+      [
+        ("run_all", true),
+        ("run_selected_store", true),
+        ("cancel_run", false)
+      ]
+    );
+    assert_eq!(
+      menu_enablement(&running, true),
+      [
+        ("run_all", false),
+        ("run_selected_store", false),
+        ("cancel_run", true)
+      ]
+    );
+    assert!(menu_enablement(&idle, false)
+      .iter()
+      .all(|(_, enabled)| !enabled));
+  }
+}
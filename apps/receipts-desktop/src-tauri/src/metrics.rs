@@ -0,0 +1,154 @@
+use crate::*;
+
+#[derive(Default)]
+pub(crate) struct CacheCounters {
+  pub(crate) hits: AtomicU64,
+  pub(crate) misses: AtomicU64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct CacheCounts {
+  pub(crate) hits: u64,
+  pub(crate) misses: u64,
+}
+
+impl CacheCounters {
+  pub(crate) fn record(&self, hit: bool) {
+    let counter = if hit { &self.hits } else { &self.misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub(crate) fn counts(&self) -> CacheCounts {
+    CacheCounts {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+    }
+  }
+
+  pub(crate) fn reset(&self) {
+    self.hits.store(0, Ordering::Relaxed);
+    self.misses.store(0, Ordering::Relaxed);
+  }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct CommandTiming {
+  pub(crate) command: String,
+  pub(crate) count: u64,
+  pub(crate) last_ms: f64,
+  pub(crate) max_ms: f64,
+  pub(crate) total_ms: f64,
+  // One count per METRICS_BUCKETS_MS bound, plus one for slower calls.
+  pub(crate) histogram: Vec<u64>,
+}
+
+// Local-only latency table; nothing leaves the machine.
+pub(crate) struct CommandMetrics {
+  pub(crate) since: Mutex<String>,
+  pub(crate) timings: Mutex<HashMap<String, CommandTiming>>,
+  pub(crate) worker_output_peak_bytes: AtomicU64,
+}
+
+impl Default for CommandMetrics {
+  fn default() -> Self {
+    CommandMetrics {
+      since: Mutex::new(chrono::Utc::now().to_rfc3339()),
+      timings: Mutex::new(HashMap::new()),
+      worker_output_peak_bytes: AtomicU64::new(0),
+    }
+  }
+}
+
+impl CommandMetrics {
+  pub(crate) fn record(&self, command: &str, elapsed: Duration) {
+    let ms = elapsed.as_secs_f64() * 1000.0;
+    let bucket = METRICS_BUCKETS_MS
+      .iter()
+      .position(|bound| ms <= *bound as f64)
+      .unwrap_or(METRICS_BUCKETS_MS.len());
+    if let Ok(mut timings) = self.timings.lock() {
+      let timing = timings
+        .entry(command.to_string())
+        .or_insert_with(|| CommandTiming {
+          command: command.to_string(),
+          count: 0,
+          last_ms: 0.0,
+          max_ms: 0.0,
+          total_ms: 0.0,
+          histogram: vec![0; METRICS_BUCKETS_MS.len() + 1],
+        });
+      timing.count += 1;
+      timing.last_ms = ms;
+      timing.max_ms = timing.max_ms.max(ms);
+      timing.total_ms += ms;
+      timing.histogram[bucket] += 1;
+    }
+  }
+
+  pub(crate) fn record_worker_output(&self, bytes: usize) {
+    self
+      .worker_output_peak_bytes
+      .fetch_max(bytes as u64, Ordering::Relaxed);
+  }
+
+  pub(crate) fn reset(&self) {
+    if let Ok(mut timings) = self.timings.lock() {
+      timings.clear();
+    }
+    if let Ok(mut since) = self.since.lock() {
+      *since = chrono::Utc::now().to_rfc3339();
+    }
+    self.worker_output_peak_bytes.store(0, Ordering::Relaxed);
+  }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct CommandMetricsReport {
+  pub(crate) since: String,
+  pub(crate) bucket_bounds_ms: Vec<u64>,
+  pub(crate) commands: Vec<CommandTiming>,
+  pub(crate) inbox_cache: CacheCounts,
+  pub(crate) summary_cache: CacheCounts,
+  pub(crate) worker_output_peak_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn command_metrics_bucket_latencies_and_count_cache_hits() {
+    let metrics = CommandMetrics::default();
+    metrics.record("get_config", Duration::from_micros(300));
+    metrics.record("get_config", Duration::from_millis(70));
+    metrics.record("get_config", Duration::from_secs(9));
+    metrics.record_worker_output(2048);
+    metrics.record_worker_output(512);
+    {
+      let timings = metrics.timings.lock().unwrap();
+      let timing = &timings["get_config"];
+      assert_eq!(timing.count, 3);
+      assert_eq!(timing.histogram, vec![1, 0, 0, 0, 1, 0, 0, 0, 1]);
+      assert_eq!(timing.max_ms, 9000.0);
+      assert_eq!(timing.last_ms, 9000.0);
+    }
+    assert_eq!(
+      metrics.worker_output_peak_bytes.load(Ordering::Relaxed),
+      2048
+    );
+    metrics.reset();
+    assert!(metrics.timings.lock().unwrap().is_empty());
+    assert_eq!(metrics.worker_output_peak_bytes.load(Ordering::Relaxed), 0);
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let mut config = badge_config("lidl");
+    config.receipts_root = dir.path().to_string_lossy().to_string();
+    fs::create_dir_all(dir.path().join("inbox").join("lidl")).expect("inbox");
+    let cache = InboxCountCache::default();
+    cached_inbox_stats(&cache, &config, "lidl", false);
+    cached_inbox_stats(&cache, &config, "lidl", false);
+    cached_inbox_stats(&cache, &config, "lidl", true);
+    assert_eq!(cache.1.counts(), CacheCounts { hits: 1, misses: 2 });
+  }
+}
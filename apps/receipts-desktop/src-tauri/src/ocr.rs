@@ -0,0 +1,297 @@
+use crate::*;
+use rusqlite::{params, Connection};
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct OcrTextMatch {
+  pub(crate) store_id: String,
+  // Relative to processed/<store>, `/`-separated.
+  pub(crate) file: String,
+  pub(crate) path: String,
+  // The image/PDF (else the JSON output) the text was read from, when it is
+  // still next to it.
+  pub(crate) receipt_path: Option<String>,
+  // 1-based.
+  pub(crate) line: usize,
+  // Of the line's first byte in the file.
+  pub(crate) offset: u64,
+  pub(crate) text: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct OcrTextSearch {
+  pub(crate) matches: Vec<OcrTextMatch>,
+  // `index` or `scan`, as for `ReceiptSearch`.
+  pub(crate) source: &'static str,
+  pub(crate) truncated: bool,
+  pub(crate) timed_out: bool,
+}
+
+// (relative name, path, mtime) of every `.txt` under `dir`, sorted. Hidden
+// files and folders are skipped; symlinks are followed, but not round a cycle.
+pub(crate) fn list_ocr_text_files(dir: &Path) -> Vec<(String, PathBuf, i64)> {
+  let mut files = Vec::new();
+  let mut visited = VisitedDirs::default();
+  let mut pending = vec![PathBuf::new()];
+  visited.enter(dir);
+  while let Some(relative_dir) = pending.pop() {
+    let entries = match fs::read_dir(dir.join(&relative_dir)) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    for entry in entries.flatten() {
+      let name = entry.file_name().to_string_lossy().to_string();
+      if name.starts_with('.') {
+        continue;
+      }
+      let relative = relative_dir.join(&name);
+      match fs::metadata(entry.path()) {
+        Ok(meta) if meta.is_dir() && visited.enter(&entry.path()) => pending.push(relative),
+        Ok(meta) if meta.is_file() && name.ends_with(".txt") => {
+          let modified = meta.modified().ok();
+          let file = relative
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+          files.push((file, dir.join(&relative), mtime_ms(modified)));
+        }
+        _ => {}
+      }
+    }
+  }
+  files.sort();
+  files
+}
+
+// (line number, byte offset, text) of each non-blank line in the first
+// OCR_TEXT_MAX_BYTES of the file, read a line at a time. Bytes that aren't
+// UTF-8 (OCR of older receipts is often Latin-2) come out as U+FFFD.
+pub(crate) fn ocr_text_lines(path: &Path) -> std::io::Result<Vec<(usize, u64, String)>> {
+  let file = fs::File::open(path)?;
+  let mut reader = BufReader::new(file.take(OCR_TEXT_MAX_BYTES));
+  let mut lines = Vec::new();
+  let mut raw = Vec::new();
+  let mut number = 0;
+  let mut offset = 0u64;
+  loop {
+    raw.clear();
+    let read = reader.read_until(b'\n', &mut raw)?;
+    if read == 0 {
+      break;
+    }
+    number += 1;
+    let text = String::from_utf8_lossy(&raw);
+    let text = text.trim_end_matches(['\r', '\n']);
+    if !text.trim().is_empty() {
+      lines.push((number, offset, text.to_string()));
+    }
+    offset += read as u64;
+  }
+  Ok(lines)
+}
+
+// `a.jpg.txt` belongs to `a.jpg`, or to `a.jpg.json` once the image is gone.
+pub(crate) fn ocr_receipt_path(path: &Path) -> Option<PathBuf> {
+  let base = path.with_extension("");
+  let output = PathBuf::from(format!("{}.json", base.to_string_lossy()));
+  [base, output]
+    .into_iter()
+    .find(|candidate| candidate.is_file())
+}
+
+// None unless every term starts a word of this line.
+pub(crate) fn ocr_match(
+  receipts_root: &str,
+  store_id: &str,
+  file: &str,
+  (line, offset, text): (usize, u64, String),
+  terms: &[String],
+) -> Option<OcrTextMatch> {
+  term_positions(&text, terms)?;
+  let path = processed_dir(receipts_root, store_id).join(file);
+  Some(OcrTextMatch {
+    store_id: store_id.to_string(),
+    file: file.to_string(),
+    receipt_path: ocr_receipt_path(&path).map(|path| path.to_string_lossy().to_string()),
+    path: path.to_string_lossy().to_string(),
+    line,
+    offset,
+    text,
+  })
+}
+
+pub(crate) fn finish_ocr_search(
+  mut matches: Vec<OcrTextMatch>,
+  source: &'static str,
+  limit: usize,
+  timed_out: bool,
+) -> OcrTextSearch {
+  matches.sort_by(|a, b| (&a.store_id, &a.file, a.line).cmp(&(&b.store_id, &b.file, b.line)));
+  let truncated = matches.len() > limit;
+  matches.truncate(limit);
+  OcrTextSearch {
+    matches,
+    source,
+    truncated,
+    timed_out,
+  }
+}
+
+// Reads the text files in order until `limit` is passed or `budget` runs out.
+pub(crate) fn scan_ocr_text(
+  receipts_root: &str,
+  store_ids: &[String],
+  terms: &[String],
+  limit: usize,
+  budget: Duration,
+) -> OcrTextSearch {
+  let started = Instant::now();
+  let mut sorted = store_ids.to_vec();
+  sorted.sort();
+  let mut matches = Vec::new();
+  let mut timed_out = false;
+  'stores: for store_id in &sorted {
+    for (file, path, _) in list_ocr_text_files(&processed_dir(receipts_root, store_id)) {
+      if matches.len() > limit {
+        break 'stores;
+      }
+      if started.elapsed() > budget {
+        timed_out = true;
+        break 'stores;
+      }
+      for line in ocr_text_lines(&path).unwrap_or_default() {
+        matches.extend(ocr_match(receipts_root, store_id, &file, line, terms));
+      }
+    }
+  }
+  finish_ocr_search(matches, "scan", limit, timed_out)
+}
+
+// Syncs the OCR lines (see `sync_ocr_text`) first, then lets the full-text
+// table pick the lines.
+pub(crate) fn indexed_ocr_search(
+  conn: &mut Connection,
+  receipts_root: &str,
+  store_ids: &[String],
+  terms: &[String],
+  limit: usize,
+) -> rusqlite::Result<OcrTextSearch> {
+  let tx = conn.transaction()?;
+  sync_ocr_text(&tx, receipts_root, store_ids)?;
+  let mut matches = Vec::new();
+  {
+    let mut statement = tx.prepare(
+      "SELECT file, line, byte_offset, text FROM ocr_text_search
+       WHERE store_id = ?1 AND ocr_text_search MATCH ?2",
+    )?;
+    let query = fts_query(terms);
+    for store_id in store_ids {
+      let mut rows = statement.query(params![store_id, query])?;
+      while let Some(row) = rows.next()? {
+        let file = row.get::<_, String>(0)?;
+        let line = (
+          row.get::<_, i64>(1)? as usize,
+          row.get::<_, i64>(2)? as u64,
+          row.get::<_, String>(3)?,
+        );
+        matches.extend(ocr_match(receipts_root, store_id, &file, line, terms));
+      }
+    }
+  }
+  tx.commit()?;
+  Ok(finish_ocr_search(matches, "index", limit, false))
+}
+
+// The index when its full-text tables exist, a time-boxed scan otherwise.
+pub(crate) fn search_ocr_text_in(
+  app: &tauri::AppHandle,
+  receipts_root: &str,
+  store_ids: &[String],
+  terms: &[String],
+  limit: usize,
+) -> OcrTextSearch {
+  let index = app.state::<RunIndex>();
+  if let Ok(mut conn) = index.0.lock() {
+    if let Some(conn) = conn.as_mut().filter(|conn| has_receipt_search(conn)) {
+      match indexed_ocr_search(conn, receipts_root, store_ids, terms, limit) {
+        Ok(search) => return search,
+        Err(err) => warn!("OCR text index failed; scanning processed/: {}", err),
+      }
+    }
+  }
+  scan_ocr_text(
+    receipts_root,
+    store_ids,
+    terms,
+    limit,
+    RECEIPT_SEARCH_SCAN_BUDGET,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ocr_text_search_reads_lines_lossily_and_agrees_with_index() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(processed.join("2024")).expect("processed");
+    fs::write(processed.join("a.jpg"), b"jpg").expect("image");
+    let mut raw = b"LIDL\r\n\nP\xe2ine alb\xe3 3,50\nTOTAL 3,50\n".to_vec();
+    raw.extend(b"Paine de casa 4,00");
+    fs::write(processed.join("a.jpg.txt"), &raw).expect("ocr");
+    fs::write(processed.join("2024").join("b.jpg.json"), "{}").expect("output");
+    fs::write(processed.join("2024").join("b.jpg.txt"), "Pâine integrală").expect("nested ocr");
+    fs::write(processed.join(".hidden.txt"), "paine").expect("hidden");
+
+    let lines = ocr_text_lines(&processed.join("a.jpg.txt")).expect("lines");
+    assert_eq!(lines[0], (1, 0, "LIDL".to_string()));
+    assert_eq!(
+      lines[1],
+      (3, 7, "P\u{fffd}ine alb\u{fffd} 3,50".to_string())
+    );
+
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string()];
+    let budget = Duration::from_secs(60);
+    let scanned = scan_ocr_text(&root, &stores, &search_terms("PAINE"), 10, budget);
+    let found = scanned
+      .matches
+      .iter()
+      .map(|found| (found.file.as_str(), found.line, found.offset))
+      .collect::<Vec<_>>();
+    assert_eq!(found, vec![("2024/b.jpg.txt", 1, 0), ("a.jpg.txt", 5, 34)]);
+    assert!(scanned.matches[0]
+      .receipt_path
+      .as_deref()
+      .is_some_and(|path| path.ends_with("b.jpg.json")));
+    assert!(scanned.matches[1]
+      .receipt_path
+      .as_deref()
+      .is_some_and(|path| path.ends_with("a.jpg")));
+    // Terms have to share a line.
+    assert!(
+      scan_ocr_text(&root, &stores, &search_terms("lidl total"), 10, budget)
+        .matches
+        .is_empty()
+    );
+
+    let mut conn = rusqlite::Connection::open_in_memory().expect("index");
+    prepare_index(&conn).expect("schema");
+    let indexed =
+      indexed_ocr_search(&mut conn, &root, &stores, &search_terms("paine"), 10).expect("fts");
+    assert_eq!(
+      (indexed.source, &indexed.matches),
+      ("index", &scanned.matches)
+    );
+    let limited =
+      indexed_ocr_search(&mut conn, &root, &stores, &search_terms("paine"), 1).expect("fts");
+    assert!(limited.truncated && limited.matches.len() == 1);
+
+    fs::remove_file(processed.join("2024").join("b.jpg.txt")).expect("remove");
+    let indexed =
+      indexed_ocr_search(&mut conn, &root, &stores, &search_terms("paine"), 10).expect("fts");
+    assert_eq!(indexed.matches.len(), 1);
+  }
+}
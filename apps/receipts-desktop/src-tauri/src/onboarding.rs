@@ -0,0 +1,295 @@
+use crate::*;
+
+// One place the receipts folder could go, for the first-run wizard.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ReceiptsLocation {
+  // The receipts folder itself (`<Dropbox>/bonuri`), whether or not it exists.
+  pub(crate) path: String,
+  pub(crate) dropbox_root: String,
+  // `dropbox_info` (the sync root Dropbox reports in its info.json) or
+  // `common` (a usual Dropbox folder that exists).
+  pub(crate) source: &'static str,
+  // The Dropbox account from info.json: `personal` or `business`.
+  pub(crate) account: Option<String>,
+  pub(crate) exists: bool,
+  // Already holds `inbox`, `processed` or `failed`: an earlier install's folder.
+  pub(crate) has_store_folders: bool,
+  // Higher ranks first.
+  pub(crate) score: u32,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct InitializedRoot {
+  pub(crate) receipts_root: String,
+  // Empty unless `create_structure` was set.
+  pub(crate) dirs: EnsuredDirs,
+  // RECEIPTS_ROOT is set in the environment, which wins over config.json;
+  // the choice is saved but takes effect once it is unset.
+  pub(crate) env_override: bool,
+}
+
+// Dropbox writes info.json to ~/.dropbox on macOS and Linux, and under
+// %APPDATA% or %LOCALAPPDATA% on Windows.
+pub(crate) fn dropbox_info_paths(home: &Path, app_data_dirs: &[PathBuf]) -> Vec<PathBuf> {
+  let mut paths = vec![home.join(".dropbox").join("info.json")];
+  paths.extend(
+    app_data_dirs
+      .iter()
+      .map(|dir| dir.join("Dropbox").join("info.json")),
+  );
+  paths
+}
+
+// `{"personal": {"path": ...}, "business": {"path": ...}}`; personal first.
+pub(crate) fn parse_dropbox_info(raw: &str) -> Vec<(String, PathBuf)> {
+  let value = match serde_json::from_str::<Value>(raw) {
+    Ok(value) => value,
+    Err(_) => return Vec::new(),
+  };
+  ["personal", "business"]
+    .iter()
+    .filter_map(|account| {
+      let path = value.get(*account)?.get("path")?.as_str()?;
+      Some((account.to_string(), PathBuf::from(path)))
+    })
+    .collect()
+}
+
+// Where Dropbox usually lives when info.json is missing: ~/Dropbox, and the
+// File Provider folders under ~/Library/CloudStorage on current macOS.
+pub(crate) fn common_dropbox_roots(home: &Path) -> Vec<PathBuf> {
+  let mut roots = vec![home.join("Dropbox")];
+  let cloud_storage = home.join("Library").join("CloudStorage");
+  if let Ok(entries) = fs::read_dir(&cloud_storage) {
+    let mut names = entries
+      .flatten()
+      .map(|entry| entry.file_name().to_string_lossy().to_string())
+      .filter(|name| name == "Dropbox" || name.starts_with("Dropbox-"))
+      .collect::<Vec<_>>();
+    names.sort();
+    roots.extend(names.into_iter().map(|name| cloud_storage.join(name)));
+  }
+  roots
+}
+
+pub(crate) fn has_store_folders(path: &Path) -> bool {
+  ["inbox", "processed", "failed"]
+    .iter()
+    .any(|kind| path.join(kind).is_dir())
+}
+
+// An existing receipts folder with store folders ranks first, then an empty
+// one, then a Dropbox root where it would be created; what Dropbox reports
+// beats a guessed folder. Roots are deduplicated by their canonical path, so
+// ~/Dropbox and the CloudStorage folder it links to come up once.
+pub(crate) fn receipts_location_candidates_in(
+  home: &Path,
+  app_data_dirs: &[PathBuf],
+) -> Vec<ReceiptsLocation> {
+  let mut roots: Vec<(&'static str, Option<String>, PathBuf)> = Vec::new();
+  for info in dropbox_info_paths(home, app_data_dirs) {
+    if let Ok(raw) = fs::read_to_string(&info) {
+      for (account, root) in parse_dropbox_info(&raw) {
+        roots.push(("dropbox_info", Some(account), root));
+      }
+    }
+  }
+  for root in common_dropbox_roots(home) {
+    roots.push(("common", None, root));
+  }
+
+  let mut seen = Vec::new();
+  let mut locations = Vec::new();
+  for (source, account, root) in roots {
+    let canonical = match fs::canonicalize(&root) {
+      Ok(canonical) if canonical.is_dir() => canonical,
+      _ => continue,
+    };
+    if seen.contains(&canonical) {
+      continue;
+    }
+    seen.push(canonical);
+    let path = root.join(DEFAULT_RECEIPTS_FOLDER);
+    let exists = path.is_dir();
+    let has_store_folders = exists && has_store_folders(&path);
+    let score = match (has_store_folders, exists) {
+      (true, _) => 30,
+      (false, true) => 20,
+      _ => 10,
+    } + match (source, account.as_deref()) {
+      ("dropbox_info", Some("personal")) => 2,
+      ("dropbox_info", _) => 1,
+      _ => 0,
+    };
+    locations.push(ReceiptsLocation {
+      path: path.to_string_lossy().to_string(),
+      dropbox_root: root.to_string_lossy().to_string(),
+      source,
+      account,
+      exists,
+      has_store_folders,
+      score,
+    });
+  }
+  // Stable, so equal scores keep the order they were found in.
+  locations.sort_by_key(|location| std::cmp::Reverse(location.score));
+  locations
+}
+
+pub(crate) fn receipts_location_candidates() -> Vec<ReceiptsLocation> {
+  let home = match home_dir() {
+    Some(home) => home,
+    None => return Vec::new(),
+  };
+  let app_data_dirs = ["APPDATA", "LOCALAPPDATA"]
+    .iter()
+    .filter_map(|key| env_var(key))
+    .map(PathBuf::from)
+    .collect::<Vec<_>>();
+  receipts_location_candidates_in(&home, &app_data_dirs)
+}
+
+// The folder must be absolute, and exist unless it is to be created. With
+// `create_structure` the store folders of every enabled store and `_logs/runs`
+// are created too; ones already there are left alone.
+pub(crate) fn prepare_receipts_root(
+  path: &str,
+  stores: &[StoreConfig],
+  create_structure: bool,
+) -> Result<(String, EnsuredDirs), AppError> {
+  let root = Path::new(path.trim());
+  if !root.is_absolute() {
+    return Err(AppError::InvalidInput(format!(
+      "The receipts folder must be an absolute path: {}",
+      path
+    )));
+  }
+  let mut dirs = EnsuredDirs::default();
+  if !root.is_dir() {
+    if root.exists() {
+      return Err(AppError::InvalidInput(format!(
+        "{} is not a folder",
+        root.to_string_lossy()
+      )));
+    }
+    if !create_structure {
+      return Err(AppError::PathNotFound(root.to_string_lossy().to_string()));
+    }
+    fs::create_dir_all(root).map_err(|err| AppError::io(root, err))?;
+    dirs.created.push(root.to_string_lossy().to_string());
+  }
+  if create_structure {
+    let enabled = stores
+      .iter()
+      .filter(|store| store.enabled)
+      .cloned()
+      .collect::<Vec<_>>();
+    for dir in expected_dirs(root, &enabled) {
+      let display = dir.to_string_lossy().to_string();
+      if dir.is_dir() {
+        dirs.existing.push(display);
+        continue;
+      }
+      fs::create_dir_all(&dir).map_err(|err| AppError::io(&dir, err))?;
+      dirs.created.push(display);
+    }
+  }
+  Ok((root.to_string_lossy().to_string(), dirs))
+}
+
+// Installs from before the wizard count as onboarded when their receipts
+// folder (set or the default) is there. Returns whether prefs changed.
+pub(crate) fn reconcile_first_run(prefs: &mut HashMap<String, Value>, config: &AppConfig) -> bool {
+  if prefs.contains_key(PREF_FIRST_RUN_COMPLETED) || !Path::new(&config.receipts_root).is_dir() {
+    return false;
+  }
+  prefs.insert(PREF_FIRST_RUN_COMPLETED.to_string(), Value::Bool(true));
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn receipts_locations_are_detected_ranked_and_initialized_repeatably() {
+    let home = tempfile::tempdir().expect("home");
+    let business = home.path().join("Dropbox (Acme)");
+    let personal = home
+      .path()
+      .join("Library")
+      .join("CloudStorage")
+      .join("Dropbox");
+    fs::create_dir_all(personal.join("bonuri").join("inbox").join("lidl")).expect("personal");
+    fs::create_dir_all(&business).expect("business");
+    fs::create_dir_all(home.path().join(".dropbox")).expect("info dir");
+    fs::write(
+      home.path().join(".dropbox").join("info.json"),
+      serde_json::json!({
+        "business": { "path": business, "host": 1 },
+        "personal": { "path": personal, "host": 2 },
+      })
+      .to_string(),
+    )
+    .expect("info.json");
+    assert_eq!(
+      parse_dropbox_info("not json"),
+      Vec::<(String, PathBuf)>::new()
+    );
+
+    let locations = receipts_location_candidates_in(home.path(), &[]);
+    // The CloudStorage folder also turns up as a common root; it is listed once.
+    assert_eq!(locations.len(), 2);
+    assert_eq!(locations[0].path, personal.join("bonuri").to_string_lossy());
+    assert_eq!(locations[0].source, "dropbox_info");
+    assert_eq!(locations[0].account.as_deref(), Some("personal"));
+    assert!(locations[0].exists && locations[0].has_store_folders);
+    assert_eq!(locations[1].account.as_deref(), Some("business"));
+    assert!(!locations[1].exists);
+    assert!(locations[0].score > locations[1].score);
+
+    let mut config = badge_config("lidl");
+    config.stores.push(StoreConfig {
+      id: "kaufland".to_string(),
+      name: "Kaufland".to_string(),
+      enabled: false,
+      backlog_warn_count: None,
+      backlog_warn_days: None,
+      notifications_enabled: None,
+    });
+    let target = business.join("bonuri");
+    let target_text = target.to_string_lossy().to_string();
+    assert_eq!(
+      prepare_receipts_root("bonuri", &config.stores, true)
+        .expect_err("relative")
+        .code(),
+      "invalid_input"
+    );
+    assert_eq!(
+      prepare_receipts_root(&target_text, &config.stores, false)
+        .expect_err("missing")
+        .code(),
+      "not_found"
+    );
+    let (root, dirs) = prepare_receipts_root(&target_text, &config.stores, true).expect("create");
+    assert_eq!(root, target_text);
+    assert!(dirs.created.contains(&target_text));
+    assert!(target.join("inbox").join("lidl").is_dir());
+    assert!(target.join("_logs").join("runs").is_dir());
+    assert!(!target.join("inbox").join("kaufland").exists());
+    let (_, again) = prepare_receipts_root(&target_text, &config.stores, true).expect("again");
+    assert!(again.created.is_empty());
+    assert_eq!(again.existing.len(), dirs.created.len() - 1);
+
+    let mut prefs = HashMap::new();
+    config.receipts_root = home.path().join("nowhere").to_string_lossy().to_string();
+    assert!(!reconcile_first_run(&mut prefs, &config));
+    config.receipts_root = target_text;
+    assert!(reconcile_first_run(&mut prefs, &config));
+    assert_eq!(prefs[PREF_FIRST_RUN_COMPLETED], Value::Bool(true));
+    assert!(!reconcile_first_run(&mut prefs, &config));
+    assert!(validate_pref(&config, PREF_FIRST_RUN_COMPLETED, &Value::Bool(false)).is_ok());
+    assert!(validate_pref(&config, PREF_FIRST_RUN_COMPLETED, &serde_json::json!("yes")).is_err());
+  }
+}
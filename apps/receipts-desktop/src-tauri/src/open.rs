@@ -0,0 +1,534 @@
+use crate::*;
+
+// `content` is "path" (the path as text) or "file" (a file reference).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ClipboardCopy {
+  pub(crate) path: String,
+  pub(crate) content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct OpenedPath {
+  pub(crate) path: String,
+  pub(crate) created: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct TerminalLaunch {
+  pub(crate) path: String,
+  pub(crate) terminal: String,
+}
+
+// `source` is "prefs" when the list comes from the `open.openers` pref.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct OpenerList {
+  pub(crate) extension: String,
+  pub(crate) openers: Vec<String>,
+  pub(crate) source: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct RevealResult {
+  pub(crate) path: String,
+  pub(crate) mechanism: String,
+}
+
+// Roots the UI may point file commands at, canonicalized: RECEIPTS_ROOT, the
+// folders the worker expects under it (any of which may be a link to another
+// volume), the state folder and WORKER_DIR. A store folder that links back to
+// one of its own ancestors is refused. Missing roots are skipped.
+pub(crate) fn resolve_allowed_roots(config: &AppConfig) -> Vec<PathBuf> {
+  let mut roots = Vec::new();
+  if !config.receipts_root.trim().is_empty() {
+    let base = Path::new(&config.receipts_root);
+    roots.push(base.to_path_buf());
+    let mut tree = expected_dirs(base, &config.stores);
+    tree.push(base.join("_logs"));
+    for dir in tree {
+      if links_to_ancestor(&dir) {
+        warn!(
+          "{} links back to a folder above it; ignoring it",
+          dir.to_string_lossy()
+        );
+        continue;
+      }
+      roots.push(dir);
+    }
+  }
+  if let Ok(dir) = state_dir() {
+    roots.push(dir);
+  }
+  if let Some(worker_dir) = &config.worker_dir {
+    roots.push(PathBuf::from(worker_dir));
+  }
+  let mut roots = roots
+    .into_iter()
+    .filter(|root| !root.as_os_str().is_empty())
+    .filter_map(|root| root.canonicalize().ok())
+    .collect::<Vec<_>>();
+  // Folders inside another root add nothing.
+  roots.sort();
+  let mut kept: Vec<PathBuf> = Vec::new();
+  for root in roots {
+    if !kept.iter().any(|other| root.starts_with(other)) {
+      kept.push(root);
+    }
+  }
+  kept
+}
+
+// Resolved when the config is loaded; a root that wasn't there yet (a volume
+// mounted later) is resolved again on every call until the next reload.
+pub(crate) fn allowed_roots(config: &AppConfig) -> Vec<PathBuf> {
+  match &config.resolved_roots {
+    Some(roots) => roots.clone(),
+    None => resolve_allowed_roots(config),
+  }
+}
+
+// Canonicalizes `path` (resolving `..` and symlinks) and requires the result to
+// sit inside one of the canonical `roots`. Every command that opens or reads a
+// path coming from the frontend goes through this.
+pub(crate) fn validate_allowed_path(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, AppError> {
+  let canonical = path.canonicalize().map_err(|err| AppError::io(path, err))?;
+  if roots.iter().any(|root| canonical.starts_with(root)) {
+    Ok(canonical)
+  } else {
+    Err(AppError::PathNotAllowed(path.to_string_lossy().to_string()))
+  }
+}
+
+pub(crate) fn resolve_open_path(
+  config: &AppConfig,
+  path_type: &str,
+  store_id: Option<String>,
+  file_path: Option<String>,
+) -> Result<PathBuf, AppError> {
+  let base = PathBuf::from(&config.receipts_root);
+  let store_value = store_id.clone();
+  let file_value = file_path.clone();
+  let resolved = match path_type {
+    "inbox" => base
+      .join("inbox")
+      .join(store_id.ok_or("store_id required")?),
+    "processed" => base
+      .join("processed")
+      .join(store_id.ok_or("store_id required")?),
+    "failed" => base
+      .join("failed")
+      .join(store_id.ok_or("store_id required")?),
+    "logs" => base.join("_logs"),
+    "appLogs" => app_logs_dir().ok_or(AppError::HomeUnavailable)?,
+    "logFile" => PathBuf::from(file_path.ok_or("file_path required")?),
+    "errorFile" => PathBuf::from(file_path.ok_or("file_path required")?),
+    _ => return Err("Unknown path type".into()),
+  };
+
+  debug!(
+    "open_path: type={}, store={:?}, file={:?}, resolved={}",
+    path_type,
+    store_value,
+    file_value,
+    resolved.to_string_lossy()
+  );
+
+  validate_allowed_path(&resolved, &allowed_roots(config))
+}
+
+// Returns the terminal that was started; `no_terminal_found` lists what was tried.
+pub(crate) fn launch_terminal(dir: &Path) -> Result<String, AppError> {
+  let mut candidates: Vec<(&str, Command)> = Vec::new();
+  #[cfg(target_os = "macos")]
+  {
+    let mut command = Command::new("open");
+    command.args(["-a", "Terminal"]).arg(dir);
+    candidates.push(("Terminal.app", command));
+  }
+  #[cfg(target_os = "windows")]
+  {
+    let mut command = Command::new("wt");
+    command.arg("-d").arg(dir);
+    candidates.push(("wt", command));
+    let mut command = Command::new("cmd");
+    command.args(["/C", "start", "cmd"]).current_dir(dir);
+    candidates.push(("cmd", command));
+  }
+  #[cfg(all(unix, not(target_os = "macos")))]
+  {
+    let mut command = Command::new("x-terminal-emulator");
+    command.current_dir(dir);
+    candidates.push(("x-terminal-emulator", command));
+    let mut command = Command::new("gnome-terminal");
+    command.arg(format!("--working-directory={}", dir.to_string_lossy()));
+    candidates.push(("gnome-terminal", command));
+    let mut command = Command::new("konsole");
+    command.arg("--workdir").arg(dir);
+    candidates.push(("konsole", command));
+    let mut command = Command::new("xterm");
+    command.current_dir(dir);
+    candidates.push(("xterm", command));
+  }
+  let mut probed = Vec::new();
+  for (name, mut command) in candidates {
+    match command
+      .stdin(Stdio::null())
+      .stdout(Stdio::null())
+      .stderr(Stdio::null())
+      .spawn()
+    {
+      Ok(mut child) => {
+        // Reaped in the background; the terminal outlives this call.
+        thread::spawn(move || child.wait());
+        return Ok(name.to_string());
+      }
+      Err(err) => {
+        debug!("open_terminal: {} failed: {}", name, err);
+        probed.push(name);
+      }
+    }
+  }
+  Err(AppError::NoTerminalFound(
+    probed.into_iter().map(|name| name.to_string()).collect(),
+  ))
+}
+
+pub(crate) fn openers_for(prefs: &HashMap<String, Value>, extension: &str) -> OpenerList {
+  let extension = extension.trim_start_matches('.').to_lowercase();
+  let configured = prefs
+    .get(PREF_OPENERS)
+    .and_then(|value| serde_json::from_value::<HashMap<String, Vec<String>>>(value.clone()).ok())
+    .and_then(|openers| openers.get(&extension).cloned());
+  match configured {
+    Some(openers) => OpenerList {
+      extension,
+      openers,
+      source: "prefs".to_string(),
+    },
+    None => OpenerList {
+      openers: DEFAULT_OPENERS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, apps)| apps.iter().map(|app| app.to_string()).collect())
+        .unwrap_or_default(),
+      extension,
+      source: "default".to_string(),
+    },
+  }
+}
+
+pub(crate) fn open_resolved(window: &tauri::Window, resolved: &Path) -> Result<(), String> {
+  match shell::open(&window.shell_scope(), resolved.to_string_lossy(), None) {
+    Ok(()) => Ok(()),
+    Err(err) => {
+      let shell_error = err.to_string();
+      warn!("shell::open failed: {}", shell_error);
+      open_with_system(resolved)
+        .map_err(|fallback| format!("open failed: {}; fallback: {}", shell_error, fallback))
+    }
+  }
+}
+
+pub(crate) fn reveal_with_system(path: &Path) -> Result<&'static str, String> {
+  #[cfg(target_os = "macos")]
+  {
+    let mut command = Command::new("open");
+    command.arg("-R").arg(path);
+    return run_opener(command, "open -R").map(|_| "open -R");
+  }
+  #[cfg(target_os = "linux")]
+  {
+    let shown = Command::new("dbus-send")
+      .args([
+        "--session",
+        "--print-reply",
+        "--dest=org.freedesktop.FileManager1",
+        "--type=method_call",
+        "/org/freedesktop/FileManager1",
+        "org.freedesktop.FileManager1.ShowItems",
+      ])
+      .arg(format!("array:string:{}", file_uri(path)))
+      .arg("string:")
+      .output();
+    match shown {
+      Ok(output) if output.status.success() => return Ok("dbus FileManager1.ShowItems"),
+      Ok(output) => debug!(
+        "reveal: ShowItems failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+      ),
+      Err(err) => debug!("reveal: dbus-send unavailable: {}", err),
+    }
+    let mut command = Command::new("xdg-open");
+    command.arg(path.parent().unwrap_or(path));
+    return run_opener(command, "xdg-open").map(|_| "xdg-open parent");
+  }
+  #[cfg(target_os = "windows")]
+  {
+    use std::os::windows::process::CommandExt;
+    // explorer exits 1 even when it worked, so only a spawn failure counts.
+    Command::new("explorer")
+      .raw_arg(format!("/select,\"{}\"", path.to_string_lossy()))
+      .status()
+      .map_err(|err| err.to_string())?;
+    return Ok("explorer /select");
+  }
+  #[allow(unreachable_code)]
+  Err("Unsupported platform for reveal".to_string())
+}
+
+// file:// URI with everything outside the unreserved set percent-encoded.
+#[cfg(any(target_os = "linux", test))]
+pub(crate) fn file_uri(path: &Path) -> String {
+  let mut uri = String::from("file://");
+  for byte in path.to_string_lossy().bytes() {
+    if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+      uri.push(byte as char);
+    } else {
+      uri.push_str(&format!("%{:02X}", byte));
+    }
+  }
+  uri
+}
+
+// Runs an opener and reports its stderr, not just the exit status, on failure.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub(crate) fn run_opener(mut command: Command, name: &str) -> Result<(), String> {
+  let output = command
+    .output()
+    .map_err(|err| format!("{}: {}", name, err))?;
+  if output.status.success() {
+    return Ok(());
+  }
+  let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+  if stderr.is_empty() {
+    Err(format!("{} exited with status {}", name, output.status))
+  } else {
+    Err(format!(
+      "{} exited with status {}: {}",
+      name, output.status, stderr
+    ))
+  }
+}
+
+pub(crate) fn open_with_system(path: &Path) -> Result<(), String> {
+  #[cfg(target_os = "macos")]
+  {
+    let mut command = Command::new("open");
+    command.arg(path);
+    return run_opener(command, "open");
+  }
+  #[cfg(target_os = "linux")]
+  {
+    let mut command = Command::new("xdg-open");
+    command.arg(path);
+    return run_opener(command, "xdg-open");
+  }
+  #[cfg(target_os = "windows")]
+  {
+    use std::os::windows::process::CommandExt;
+    // explorer's exit code is unreliable (1 on success), so only spawning counts.
+    if path.is_dir() {
+      Command::new("explorer.exe")
+        .arg(path)
+        .status()
+        .map_err(|err| format!("explorer: {}", err))?;
+      return Ok(());
+    }
+    let output = Command::new("cmd")
+      .raw_arg(cmd_start_args(path))
+      .output()
+      .map_err(|err| format!("cmd start: {}", err))?;
+    return if output.status.success() {
+      Ok(())
+    } else {
+      Err(format!(
+        "cmd start exited with status {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+      ))
+    };
+  }
+  #[allow(unreachable_code)]
+  Err("Unsupported platform for open".to_string())
+}
+
+// Quoting covers spaces, `&`, `^` and parentheses; `%` would still expand
+// inside quotes, so it is closed around a caret-escaped `^%`.
+#[cfg(target_os = "windows")]
+pub(crate) fn cmd_quote(value: &str) -> String {
+  format!("\"{}\"", value.replace('%', "\"^%\""))
+}
+
+// `start` takes its first quoted argument as the window title, hence the empty "".
+#[cfg(target_os = "windows")]
+pub(crate) fn cmd_start_args(path: &Path) -> String {
+  format!("/C start \"\" {}", cmd_quote(&path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn file_uri_percent_encodes_path() {
+    assert_eq!(
+      file_uri(Path::new(
+        "/home/xan/Dropbox/bonuri/failed/lidl/Bon #3 (ü).jpg"
+      )),
+      "file:///home/xan/Dropbox/bonuri/failed/lidl/Bon%20%233%20%28%C3%BC%29.jpg"
+    );
+  }
+
+  #[test]
+  fn allowed_paths_reject_traversal_and_escaping_symlinks() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path().join("bonuri");
+    let outside = dir.path().join("private");
+    fs::create_dir_all(root.join("failed").join("lidl")).expect("root");
+    fs::create_dir_all(&outside).expect("outside");
+    fs::write(root.join("failed").join("lidl").join("bon.jpg"), b"x").expect("receipt");
+    fs::write(outside.join("secret.txt"), b"x").expect("secret");
+    let roots = vec![root.canonicalize().expect("canonical root")];
+
+    let inside = validate_allowed_path(&root.join("failed").join("lidl").join("bon.jpg"), &roots);
+    assert!(inside.is_ok());
+    let dotted = root.join("failed").join("..").join("failed").join("lidl");
+    assert!(validate_allowed_path(&dotted, &roots).is_ok());
+
+    let traversal = root
+      .join("failed")
+      .join("..")
+      .join("..")
+      .join("private")
+      .join("secret.txt");
+    let err = validate_allowed_path(&traversal, &roots).expect_err("traversal");
+    assert_eq!(err.code(), "path_not_allowed");
+    let err = validate_allowed_path(&outside.join("secret.txt"), &roots).expect_err("absolute");
+    assert_eq!(err.code(), "path_not_allowed");
+    let err = validate_allowed_path(&root.join("missing.jpg"), &roots).expect_err("missing");
+    assert_eq!(err.code(), "not_found");
+
+    #[cfg(unix)]
+    {
+      std::os::unix::fs::symlink(&outside, root.join("failed").join("escape")).expect("symlink");
+      let escaped = root.join("failed").join("escape").join("secret.txt");
+      let err = validate_allowed_path(&escaped, &roots).expect_err("symlink escape");
+      assert_eq!(err.code(), "path_not_allowed");
+      std::os::unix::fs::symlink(root.join("failed").join("lidl"), root.join("lidl-failed"))
+        .expect("inner symlink");
+      assert!(validate_allowed_path(&root.join("lidl-failed").join("bon.jpg"), &roots).is_ok());
+    }
+  }
+
+  #[cfg(target_os = "windows")]
+  #[test]
+  fn cmd_start_quotes_paths_with_special_characters() {
+    assert_eq!(
+      cmd_start_args(Path::new(
+        r"C:\Users\Name (Work)\Dropbox\bonuri\failed\lidl\a&b ^c.jpg"
+      )),
+      r#"/C start "" "C:\Users\Name (Work)\Dropbox\bonuri\failed\lidl\a&b ^c.jpg""#
+    );
+    assert_eq!(
+      cmd_start_args(Path::new(r"C:\bonuri\100% done.pdf")),
+      r#"/C start "" "C:\bonuri\100"^%" done.pdf""#
+    );
+  }
+
+  #[test]
+  fn openers_prefer_the_stored_list() {
+    let mut prefs = HashMap::new();
+    prefs.insert(
+      PREF_OPENERS.to_string(),
+      serde_json::json!({ "pdf": ["PDF Expert", "Preview"] }),
+    );
+    let pdf = openers_for(&prefs, ".PDF");
+    assert_eq!(pdf.extension, "pdf");
+    assert_eq!(
+      pdf.openers,
+      vec!["PDF Expert".to_string(), "Preview".to_string()]
+    );
+    assert_eq!(pdf.source, "prefs");
+    assert_eq!(openers_for(&prefs, "txt").source, "default");
+    let invalid = serde_json::json!({ "pdf": "Preview" });
+    assert!(validate_pref(&badge_config("lidl"), PREF_OPENERS, &invalid).is_err());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn symlinked_root_and_store_inbox_are_allowed_and_scanned() {
+    use std::os::unix::fs::symlink;
+    let dir = tempfile::tempdir().expect("tempdir");
+    let volume = dir.path().join("volume");
+    let scans = dir.path().join("scans");
+    let outside = dir.path().join("private");
+    for folder in [
+      volume.join("inbox"),
+      volume.join("processed"),
+      volume.join("failed").join("lidl"),
+      scans.clone(),
+      outside.clone(),
+    ] {
+      fs::create_dir_all(folder).expect("folder");
+    }
+    fs::write(scans.join("bon.jpg"), b"jpg").expect("receipt");
+    fs::write(outside.join("secret.json"), b"{}").expect("secret");
+    symlink(&scans, volume.join("inbox").join("lidl")).expect("store link");
+    let link = dir.path().join("receipts");
+    symlink(&volume, &link).expect("root link");
+
+    let mut config = badge_config("lidl");
+    config.receipts_root = link.to_string_lossy().to_string();
+    config.resolve_roots();
+    let roots = allowed_roots(&config);
+    assert!(roots.contains(&volume.canonicalize().expect("volume")));
+    assert!(roots.contains(&scans.canonicalize().expect("scans")));
+
+    let inbox = resolve_open_path(&config, "inbox", Some("lidl".to_string()), None).expect("inbox");
+    assert_eq!(inbox, scans.canonicalize().expect("scans"));
+    assert!(resolve_open_path(&config, "failed", Some("lidl".to_string()), None).is_ok());
+    let file = link.join("inbox").join("lidl").join("bon.jpg");
+    assert!(validate_allowed_path(&file, &roots).is_ok());
+    symlink(&outside, volume.join("failed").join("lidl").join("escape")).expect("escape");
+    let escaped = link
+      .join("failed")
+      .join("lidl")
+      .join("escape")
+      .join("secret.json");
+    let err = validate_allowed_path(&escaped, &roots).expect_err("escape");
+    assert_eq!(err.code(), "path_not_allowed");
+
+    let files = scan_inbox_files(&link.join("inbox").join("lidl")).expect("scan");
+    assert_eq!(files.len(), 1);
+    assert_eq!((files[0].name.as_str(), files[0].size), ("bon.jpg", 3));
+    symlink(scans.join("bon.jpg"), scans.join("linked.jpg")).expect("file link");
+    let sizes = scan_inbox_files(&link.join("inbox").join("lidl"))
+      .expect("scan")
+      .into_iter()
+      .map(|file| file.size)
+      .collect::<Vec<_>>();
+    assert_eq!(sizes, vec![3, 3]);
+
+    // Links back up the tree are refused rather than walked forever.
+    symlink(
+      volume.join("processed"),
+      volume.join("processed").join("lidl"),
+    )
+    .expect("cycle");
+    symlink(&volume, scans.join("loop")).expect("loop");
+    assert!(links_to_ancestor(&volume.join("processed").join("lidl")));
+    assert!(!links_to_ancestor(&volume.join("inbox").join("lidl")));
+    config.resolve_roots();
+    assert!(!allowed_roots(&config).is_empty());
+    let usage = dir_usage(&link);
+    assert_eq!(usage.files, 3);
+    let backed_up = backup_files(&link, true)
+      .into_iter()
+      .map(|(relative, _)| relative)
+      .collect::<Vec<_>>();
+    assert!(backed_up.contains(&PathBuf::from("inbox/lidl/bon.jpg")));
+    assert!(backed_up
+      .iter()
+      .all(|path| !path.starts_with("inbox/lidl/loop")));
+  }
+}
@@ -0,0 +1,187 @@
+use crate::*;
+
+// Where the app's own files live. `home` is None when the OS can't tell us the
+// home directory (e.g. a locked-down Windows account); the state folder then
+// falls back to RECEIPTS_STATE_DIR or the platform data dir.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AppPaths {
+  pub(crate) home: Option<PathBuf>,
+  pub(crate) state_dir: Option<PathBuf>,
+}
+
+// RECEIPTS_STATE_DIR wins over `~/.life-dashboard/receipts-desktop`, which wins
+// over `<data dir>/<bundle identifier>`.
+pub(crate) fn resolve_app_paths(
+  home: Option<PathBuf>,
+  state_dir_override: Option<String>,
+  data_dir: Option<PathBuf>,
+) -> AppPaths {
+  let state_dir = state_dir_override
+    .map(PathBuf::from)
+    .or_else(|| home.as_ref().map(|home| home.join(STATE_DIR)))
+    .or_else(|| data_dir.map(|dir| dir.join(APP_DATA_DIR_NAME)));
+  AppPaths { home, state_dir }
+}
+
+pub(crate) fn app_paths() -> AppPaths {
+  resolve_app_paths(
+    home_dir(),
+    env_var(STATE_DIR_ENV),
+    tauri::api::path::data_dir(),
+  )
+}
+
+pub(crate) fn state_dir() -> Result<PathBuf, AppError> {
+  app_paths().state_dir.ok_or(AppError::HomeUnavailable)
+}
+
+// A file directly in the state folder, or None when there is no state folder.
+pub(crate) fn state_path(name: &str) -> Option<PathBuf> {
+  state_dir().ok().map(|dir| dir.join(name))
+}
+
+// File names go to the UI and come back as text. A UTF-8 name is its own id.
+// Anything else (a mangled encoding from an old camera, say) becomes
+// RAW_FILE_ID_PREFIX plus the hex of its bytes (UTF-16 units on Windows), and
+// `file_name_from_id` turns that back into the exact name, so the file can
+// still be opened, moved and deleted. Show `to_string_lossy` instead.
+pub(crate) fn file_name_id(name: &OsStr) -> String {
+  match name.to_str() {
+    Some(text) if !text.starts_with(RAW_FILE_ID_PREFIX) => text.to_string(),
+    _ => format!("{}{}", RAW_FILE_ID_PREFIX, raw_name_hex(name)),
+  }
+}
+
+#[cfg(unix)]
+pub(crate) fn raw_name_hex(name: &OsStr) -> String {
+  use std::os::unix::ffi::OsStrExt;
+  name
+    .as_bytes()
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect()
+}
+
+#[cfg(windows)]
+pub(crate) fn raw_name_hex(name: &OsStr) -> String {
+  use std::os::windows::ffi::OsStrExt;
+  name
+    .encode_wide()
+    .map(|unit| format!("{:04x}", unit))
+    .collect()
+}
+
+#[cfg(unix)]
+pub(crate) fn name_from_hex(hex: &str) -> Option<OsString> {
+  use std::os::unix::ffi::OsStringExt;
+  if !hex.len().is_multiple_of(2) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+    return None;
+  }
+  let bytes = (0..hex.len())
+    .step_by(2)
+    .map(|start| u8::from_str_radix(&hex[start..start + 2], 16).ok())
+    .collect::<Option<Vec<_>>>()?;
+  Some(OsString::from_vec(bytes))
+}
+
+#[cfg(windows)]
+pub(crate) fn name_from_hex(hex: &str) -> Option<OsString> {
+  use std::os::windows::ffi::OsStringExt;
+  if hex.len() % 4 != 0 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+    return None;
+  }
+  let units = (0..hex.len())
+    .step_by(4)
+    .map(|start| u16::from_str_radix(&hex[start..start + 4], 16).ok())
+    .collect::<Option<Vec<_>>>()?;
+  Some(OsString::from_wide(&units))
+}
+
+pub(crate) fn file_name_from_id(id: &str) -> OsString {
+  id.strip_prefix(RAW_FILE_ID_PREFIX)
+    .and_then(name_from_hex)
+    .unwrap_or_else(|| OsString::from(id))
+}
+
+pub(crate) fn join_file_id(dir: &Path, id: &str) -> PathBuf {
+  dir.join(file_name_from_id(id))
+}
+
+// A folder that resolves to one of its own ancestors (`inbox/lidl -> ..`),
+// which would make anything walking down from it loop.
+pub(crate) fn links_to_ancestor(dir: &Path) -> bool {
+  match (fs::canonicalize(dir), dir.parent().map(fs::canonicalize)) {
+    (Ok(target), Some(Ok(parent))) => parent.starts_with(&target),
+    _ => false,
+  }
+}
+
+// The folders a walk down the receipts tree has entered, by canonical path.
+// Symlinked folders are followed, but one that leads somewhere already
+// entered (back up the tree, or to a folder walked through another link) is
+// a cycle and is refused.
+#[derive(Default)]
+pub(crate) struct VisitedDirs(pub(crate) std::collections::BTreeSet<PathBuf>);
+
+impl VisitedDirs {
+  pub(crate) fn enter(&mut self, dir: &Path) -> bool {
+    let canonical = match fs::canonicalize(dir) {
+      Ok(canonical) => canonical,
+      Err(_) => return false,
+    };
+    if !self.0.insert(canonical) {
+      warn!(
+        "{} leads back to a folder already walked; skipping it",
+        dir.to_string_lossy()
+      );
+      return false;
+    }
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn missing_home_falls_back_for_state_and_fails_typed_for_the_root() {
+    let data = PathBuf::from("/data");
+    let paths = resolve_app_paths(None, None, Some(data.clone()));
+    assert_eq!(paths.state_dir, Some(data.join(APP_DATA_DIR_NAME)));
+    let paths = resolve_app_paths(None, Some("/explicit".to_string()), Some(data.clone()));
+    assert_eq!(paths.state_dir, Some(PathBuf::from("/explicit")));
+    let home = PathBuf::from("/home/user");
+    let with_home = resolve_app_paths(Some(home.clone()), None, Some(data));
+    assert_eq!(with_home.state_dir, Some(home.join(STATE_DIR)));
+    assert!(resolve_app_paths(None, None, None).state_dir.is_none());
+
+    let no_home = resolve_app_paths(None, None, None);
+    assert_eq!(default_receipts_root(&no_home), None);
+    assert_eq!(
+      default_receipts_root(&with_home),
+      Some(
+        home
+          .join(DEFAULT_RECEIPTS_ROOT)
+          .to_string_lossy()
+          .to_string()
+      )
+    );
+
+    let mut config = badge_config("lidl");
+    config.home_available = false;
+    let err = require_store(&config, "lidl").unwrap_err();
+    assert_eq!(err.code(), "home_unavailable");
+    assert!(err.message().contains(STATE_DIR_ENV));
+    assert_eq!(
+      ensure_dirs(&config, None).unwrap_err(),
+      AppError::HomeUnavailable
+    );
+    config.home_available = true;
+    assert_eq!(
+      require_store(&config, "lidl").unwrap_err().code(),
+      "config_missing"
+    );
+  }
+}
@@ -0,0 +1,335 @@
+use crate::*;
+
+// UI preferences live in their own prefs.json so state backups and badge
+// resets never touch them.
+#[derive(Default)]
+pub(crate) struct PrefsStore {
+  pub(crate) prefs: Mutex<HashMap<String, Value>>,
+  pub(crate) save_pending: Mutex<bool>,
+}
+
+// One entry per resolved path, kept in prefs under `open.recent_locations`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct RecentLocation {
+  pub(crate) path_type: String,
+  pub(crate) store_id: Option<String>,
+  pub(crate) path: String,
+  pub(crate) opened_at: String,
+  pub(crate) count: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct RecentLocations {
+  pub(crate) recent: Vec<RecentLocation>,
+  pub(crate) frequent: Vec<RecentLocation>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct WindowGeometry {
+  pub(crate) width: u32,
+  pub(crate) height: u32,
+  pub(crate) x: i32,
+  pub(crate) y: i32,
+  pub(crate) maximized: bool,
+}
+
+pub(crate) fn prefs_file_path() -> Option<PathBuf> {
+  state_path(PREFS_FILE)
+}
+
+pub(crate) fn load_prefs() -> HashMap<String, Value> {
+  prefs_file_path()
+    .and_then(|path| fs::read_to_string(path).ok())
+    .and_then(|raw| serde_json::from_str(&raw).ok())
+    .unwrap_or_default()
+}
+
+pub(crate) fn save_prefs(prefs: &HashMap<String, Value>) -> Result<(), String> {
+  let path = prefs_file_path().ok_or_else(|| AppError::HomeUnavailable.message())?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+  }
+  let raw = serde_json::to_string_pretty(prefs).map_err(|err| err.to_string())?;
+  let temp = write_synced_temp(&path, &raw)?;
+  fs::rename(&temp, &path).map_err(|err| err.to_string())?;
+  Ok(())
+}
+
+pub(crate) fn validate_pref(config: &AppConfig, key: &str, value: &Value) -> Result<(), String> {
+  let namespaced = key.len() <= 64
+    && key.split('.').count() >= 2
+    && key.split('.').all(|part| {
+      !part.is_empty()
+        && part
+          .chars()
+          .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    });
+  if !namespaced {
+    return Err(format!(
+      "Invalid pref key {} (expected namespace.name)",
+      key
+    ));
+  }
+  let size = serde_json::to_string(value)
+    .map_err(|err| err.to_string())?
+    .len();
+  if size > PREF_MAX_BYTES {
+    return Err(format!(
+      "Pref {} is too large ({} bytes, max {})",
+      key, size, PREF_MAX_BYTES
+    ));
+  }
+  match key {
+    PREF_THEME => match value.as_str() {
+      Some(theme) if THEMES.contains(&theme) => Ok(()),
+      _ => Err(format!("Unknown theme: {}", value)),
+    },
+    PREF_LAST_SELECTED_STORE => match value.as_str() {
+      Some(store_id) if config.stores.iter().any(|store| store.id == store_id) => Ok(()),
+      _ => Err(format!("Unknown store: {}", value)),
+    },
+    PREF_WINDOW_GEOMETRY | PREF_RUN_WINDOW_GEOMETRY => {
+      serde_json::from_value::<WindowGeometry>(value.clone())
+        .map(|_| ())
+        .map_err(|err| format!("Invalid window geometry: {}", err))
+    }
+    PREF_SHORTCUTS => validate_shortcut_pref(value),
+    PREF_QUIT_WHILE_BUSY => validate_quit_pref(value),
+    PREF_FIRST_RUN_COMPLETED => match value {
+      Value::Bool(_) => Ok(()),
+      _ => Err(format!(
+        "{} must be true or false",
+        PREF_FIRST_RUN_COMPLETED
+      )),
+    },
+    PREF_AUTOSTART => Err(format!(
+      "{} is set through set_autostart, which also updates the login item",
+      PREF_AUTOSTART
+    )),
+    PREF_OPENERS => serde_json::from_value::<HashMap<String, Vec<String>>>(value.clone())
+      .map(|_| ())
+      .map_err(|err| {
+        format!(
+          "Invalid openers (expected {{\"pdf\": [\"Preview\"]}}): {}",
+          err
+        )
+      }),
+    _ => Ok(()),
+  }
+}
+
+pub(crate) fn schedule_prefs_save(app: &tauri::AppHandle) {
+  if let Ok(mut pending) = app.state::<PrefsStore>().save_pending.lock() {
+    if *pending {
+      return;
+    }
+    *pending = true;
+  }
+  let app = app.clone();
+  thread::spawn(move || {
+    thread::sleep(PREFS_SAVE_DEBOUNCE);
+    let store = app.state::<PrefsStore>();
+    if let Ok(mut pending) = store.save_pending.lock() {
+      *pending = false;
+    }
+    // Saved under the lock so it never races set_pref on the temp file.
+    let prefs = match store.prefs.lock() {
+      Ok(prefs) => prefs,
+      Err(_) => return,
+    };
+    if let Err(err) = save_prefs(&prefs) {
+      error!("prefs save failed: {}", err);
+    }
+  });
+}
+
+// Keeps the last normal size and position while maximized so un-maximizing
+// after a restart lands where the user left it.
+pub(crate) fn capture_window_geometry(window: &tauri::Window) {
+  let key = match geometry_pref_key(window.label()) {
+    Some(key) => key,
+    None => return,
+  };
+  let maximized = window.is_maximized().unwrap_or(false);
+  if window.is_minimized().unwrap_or(false) {
+    return;
+  }
+  let app = window.app_handle();
+  {
+    let store = app.state::<PrefsStore>();
+    let mut prefs = match store.prefs.lock() {
+      Ok(prefs) => prefs,
+      Err(_) => return,
+    };
+    let mut geometry = prefs
+      .get(key)
+      .and_then(|value| serde_json::from_value::<WindowGeometry>(value.clone()).ok())
+      .unwrap_or_default();
+    geometry.maximized = maximized;
+    if !maximized {
+      if let (Ok(size), Ok(position)) = (window.outer_size(), window.outer_position()) {
+        geometry.width = size.width;
+        geometry.height = size.height;
+        geometry.x = position.x;
+        geometry.y = position.y;
+      }
+    }
+    if let Ok(value) = serde_json::to_value(&geometry) {
+      prefs.insert(key.to_string(), value);
+    }
+  }
+  schedule_prefs_save(&app);
+}
+
+pub(crate) fn stored_recent_locations(prefs: &HashMap<String, Value>) -> Vec<RecentLocation> {
+  prefs
+    .get(PREF_RECENT_LOCATIONS)
+    .and_then(|value| serde_json::from_value(value.clone()).ok())
+    .unwrap_or_default()
+}
+
+// Moves the path to the front (bumping its count) and drops the least recently
+// opened entries past the cap.
+pub(crate) fn push_recent_location(
+  locations: &mut Vec<RecentLocation>,
+  mut location: RecentLocation,
+) {
+  if let Some(index) = locations
+    .iter()
+    .position(|entry| entry.path == location.path)
+  {
+    location.count = locations.remove(index).count + 1;
+  }
+  locations.insert(0, location);
+  locations.truncate(RECENT_LOCATIONS_CAP);
+}
+
+pub(crate) fn record_recent_location(
+  app: &tauri::AppHandle,
+  path_type: &str,
+  store_id: Option<String>,
+  path: &Path,
+) {
+  {
+    let store = app.state::<PrefsStore>();
+    let mut prefs = match store.prefs.lock() {
+      Ok(prefs) => prefs,
+      Err(_) => return,
+    };
+    let mut locations = stored_recent_locations(&prefs);
+    push_recent_location(
+      &mut locations,
+      RecentLocation {
+        path_type: path_type.to_string(),
+        store_id,
+        path: path.to_string_lossy().to_string(),
+        opened_at: chrono::Utc::now().to_rfc3339(),
+        count: 1,
+      },
+    );
+    if let Ok(value) = serde_json::to_value(&locations) {
+      prefs.insert(PREF_RECENT_LOCATIONS.to_string(), value);
+    }
+  }
+  schedule_prefs_save(app);
+}
+
+// Paths that no longer exist are dropped here rather than when recording.
+pub(crate) fn select_recent_locations(
+  locations: Vec<RecentLocation>,
+  limit: usize,
+  exists: impl Fn(&str) -> bool,
+) -> RecentLocations {
+  let locations = locations
+    .into_iter()
+    .filter(|entry| exists(&entry.path))
+    .collect::<Vec<_>>();
+  let mut frequent = locations.clone();
+  // Stable sort: equal counts keep their most-recent-first order.
+  frequent.sort_by_key(|location| std::cmp::Reverse(location.count));
+  frequent.truncate(limit);
+  let mut recent = locations;
+  recent.truncate(limit);
+  RecentLocations { recent, frequent }
+}
+
+pub(crate) fn restore_window_geometry(window: &tauri::Window, prefs: &HashMap<String, Value>) {
+  let geometry = match geometry_pref_key(window.label())
+    .and_then(|key| prefs.get(key))
+    .and_then(|value| serde_json::from_value::<WindowGeometry>(value.clone()).ok())
+  {
+    Some(geometry) => geometry,
+    None => return,
+  };
+  if geometry.width > 0 && geometry.height > 0 {
+    let _ = window.set_size(tauri::PhysicalSize::new(geometry.width, geometry.height));
+    let _ = window.set_position(tauri::PhysicalPosition::new(geometry.x, geometry.y));
+  }
+  if geometry.maximized {
+    let _ = window.maximize();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn validate_pref_checks_keys_and_known_values() {
+    let config = badge_config("kaufland");
+    let store = Value::String("kaufland".to_string());
+    assert!(validate_pref(&config, PREF_LAST_SELECTED_STORE, &store).is_ok());
+    assert!(validate_pref(&config, PREF_LAST_SELECTED_STORE, &Value::from("lidl")).is_err());
+    assert!(validate_pref(&config, PREF_THEME, &Value::from("dark")).is_ok());
+    assert!(validate_pref(&config, PREF_THEME, &Value::from("sepia")).is_err());
+    assert!(validate_pref(&config, "theme", &Value::from("dark")).is_err());
+    assert!(validate_pref(&config, "ui..x", &Value::from(1)).is_err());
+    assert!(validate_pref(&config, "ui.columns", &Value::from(vec!["name", "size"])).is_ok());
+    let large = Value::String("x".repeat(PREF_MAX_BYTES));
+    assert!(validate_pref(&config, "ui.notes", &large).is_err());
+  }
+
+  #[test]
+  fn recent_locations_dedupe_by_path_and_rank_by_count() {
+    let location = |path: &str| RecentLocation {
+      path_type: "inbox".to_string(),
+      store_id: Some("lidl".to_string()),
+      path: path.to_string(),
+      opened_at: "2026-10-01T08:00:00+00:00".to_string(),
+      count: 1,
+    };
+    let mut locations = Vec::new();
+    for path in [
+      "/r/inbox/lidl",
+      "/r/failed/lidl",
+      "/r/inbox/lidl",
+      "/r/_logs",
+      "/r/gone",
+    ] {
+      push_recent_location(&mut locations, location(path));
+    }
+    let paths = |entries: &[RecentLocation]| {
+      entries
+        .iter()
+        .map(|entry| entry.path.clone())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      paths(&locations),
+      vec!["/r/gone", "/r/_logs", "/r/inbox/lidl", "/r/failed/lidl"]
+    );
+
+    let selected = select_recent_locations(locations, 2, |path| path != "/r/gone");
+    assert_eq!(paths(&selected.recent), vec!["/r/_logs", "/r/inbox/lidl"]);
+    assert_eq!(paths(&selected.frequent), vec!["/r/inbox/lidl", "/r/_logs"]);
+    assert_eq!(selected.frequent[0].count, 2);
+
+    let mut capped = Vec::new();
+    for index in 0..RECENT_LOCATIONS_CAP + 5 {
+      push_recent_location(&mut capped, location(&format!("/r/{}", index)));
+    }
+    assert_eq!(capped.len(), RECENT_LOCATIONS_CAP);
+    assert_eq!(capped.last().map(|entry| entry.path.as_str()), Some("/r/5"));
+  }
+}
@@ -0,0 +1,379 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PricePoint {
+  pub(crate) date: Option<chrono::NaiveDate>,
+  // The receipt has no date of its own; `date` is when it was processed.
+  pub(crate) date_estimated: bool,
+  pub(crate) unit_price: f64,
+  pub(crate) currency: String,
+  pub(crate) store_id: String,
+  pub(crate) file_name: String,
+  // The item name as printed on the receipt.
+  pub(crate) name: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PriceSeries {
+  // The normalized name the points were grouped by.
+  pub(crate) name: String,
+  // Oldest first.
+  pub(crate) points: Vec<PricePoint>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PriceHistory {
+  pub(crate) query: String,
+  pub(crate) store_id: Option<String>,
+  // Most often bought first, at most PRICE_HISTORY_MAX_PRODUCTS.
+  pub(crate) products: Vec<PriceSeries>,
+  // Products that matched, before the cap.
+  pub(crate) product_count: usize,
+  pub(crate) receipts_scanned: usize,
+}
+
+// Unit spellings seen on Lidl and Kaufland receipts, to one form each.
+pub(crate) fn item_unit(raw: &str) -> Option<&'static str> {
+  match raw {
+    "l" | "lt" | "ltr" | "litri" | "litru" => Some("l"),
+    "ml" => Some("ml"),
+    "g" | "gr" | "grame" => Some("g"),
+    "kg" | "kgr" => Some("kg"),
+    "buc" | "bc" | "pcs" | "pc" | "bucati" => Some("buc"),
+    _ => None,
+  }
+}
+
+// "1.50" -> "1.5", "1000" -> "1000".
+pub(crate) fn trim_number(number: f64) -> String {
+  let text = format!("{:.3}", number);
+  text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+pub(crate) fn item_size(number: f64, unit: &str) -> String {
+  match unit {
+    "ml" if number >= 1000.0 => format!("{}l", trim_number(number / 1000.0)),
+    "g" if number >= 1000.0 => format!("{}kg", trim_number(number / 1000.0)),
+    _ => format!("{}{}", trim_number(number), unit),
+  }
+}
+
+// `500g`, `1,5l`, `2x`, `x2` split into number and suffix.
+pub(crate) fn split_number_suffix(token: &str) -> Option<(f64, &str)> {
+  if let Some(count) = token.strip_prefix('x') {
+    return count.parse().ok().map(|count| (count, "x"));
+  }
+  let split = token.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+  let number = token[..split].parse().ok()?;
+  Some((number, &token[split..]))
+}
+
+// The name a product is grouped and matched by. Lidl prints `Lapte 1,5% 1L`,
+// Kaufland `LAPTE 1.5% 1 L` or `LAPTE 1.5% 1000ML 2 BUC X`; all of them become
+// `lapte 1.5% 1l`:
+// - case and diacritics are folded (as in search),
+// - decimal commas become points, punctuation other than `%` and `*` goes,
+// - a number and its unit are joined, units are spelled one way, and
+//   1000 ml / 1000 g become 1 l / 1 kg,
+// - trailing quantities (`2 buc x`, `x2`, `2x`, `x 2`, `* 3`) are dropped.
+pub(crate) fn normalize_item_name(raw: &str) -> String {
+  let chars = fold_text(raw).chars().collect::<Vec<_>>();
+  let mut cleaned = String::with_capacity(chars.len());
+  for (index, &c) in chars.iter().enumerate() {
+    let decimal = index > 0
+      && chars[index - 1].is_ascii_digit()
+      && chars
+        .get(index + 1)
+        .is_some_and(|next| next.is_ascii_digit());
+    match c {
+      ',' | '.' if decimal => cleaned.push('.'),
+      '%' => cleaned.push('%'),
+      '*' => cleaned.push_str(" * "),
+      c if c.is_alphanumeric() => cleaned.push(c),
+      _ => cleaned.push(' '),
+    }
+  }
+  let tokens = cleaned.split_whitespace().collect::<Vec<_>>();
+  // (word, whether it is part of a purchase count)
+  let mut words: Vec<(String, bool)> = Vec::new();
+  let mut index = 0;
+  while index < tokens.len() {
+    let token = tokens[index];
+    let next = tokens.get(index + 1).copied();
+    index += 1;
+    if let Some(percent) = token.strip_suffix('%') {
+      if let Ok(number) = percent.parse::<f64>() {
+        words.push((format!("{}%", trim_number(number)), false));
+        continue;
+      }
+    }
+    if token == "%" {
+      if let Some((last, false)) = words.last_mut() {
+        if last.parse::<f64>().is_ok() {
+          last.push('%');
+          continue;
+        }
+      }
+    }
+    if let Ok(number) = token.parse::<f64>() {
+      match next.and_then(item_unit) {
+        Some("buc") => {
+          words.push((item_size(number, "buc"), true));
+          index += 1;
+        }
+        Some(unit) => {
+          words.push((item_size(number, unit), false));
+          index += 1;
+        }
+        None => {
+          let after_marker = words
+            .last()
+            .is_some_and(|(word, _)| word == "x" || word == "*");
+          words.push((
+            trim_number(number),
+            after_marker || next == Some("x") || next == Some("*"),
+          ));
+        }
+      }
+      continue;
+    }
+    match split_number_suffix(token) {
+      Some((number, "x")) => words.push((trim_number(number), true)),
+      Some((number, suffix)) => match item_unit(suffix) {
+        Some(unit) => words.push((item_size(number, unit), unit == "buc")),
+        None => words.push((token.to_string(), false)),
+      },
+      None => words.push((token.to_string(), matches!(token, "x" | "*" | "buc"))),
+    }
+  }
+  while words.len() > 1 && words.last().is_some_and(|(_, count)| *count) {
+    words.pop();
+  }
+  words
+    .into_iter()
+    .map(|(word, _)| word)
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+// Every word of the query has to start a word of the name.
+pub(crate) fn item_name_matches(name: &str, query: &[String]) -> bool {
+  let words = name.split(' ').collect::<Vec<_>>();
+  query
+    .iter()
+    .all(|term| words.iter().any(|word| word.starts_with(term.as_str())))
+}
+
+// `unit_price`, else `paid_amount` over `quantity` (default 1).
+pub(crate) fn item_unit_price(item: &Value) -> Option<f64> {
+  item
+    .get("unit_price")
+    .and_then(parse_amount)
+    .or_else(|| {
+      let paid = item.get("paid_amount").and_then(parse_amount)?;
+      let quantity = item.get("quantity").and_then(parse_amount).unwrap_or(1.0);
+      (quantity > 0.0).then(|| paid / quantity)
+    })
+    .filter(|price| *price > 0.0)
+}
+
+// Reads every processed output of the stores; nothing is written.
+pub(crate) fn price_history(
+  receipts_root: &str,
+  store_ids: &[String],
+  query: &str,
+) -> Result<PriceHistory, AppError> {
+  let terms = normalize_item_name(query)
+    .split(' ')
+    .filter(|term| !term.is_empty())
+    .map(str::to_string)
+    .collect::<Vec<_>>();
+  if terms.is_empty() {
+    return Err(AppError::InvalidInput(
+      "Price history needs a product name".to_string(),
+    ));
+  }
+  let mut products = std::collections::BTreeMap::<String, Vec<PricePoint>>::new();
+  let mut receipts_scanned = 0;
+  for store_id in store_ids {
+    let dir = processed_dir(receipts_root, store_id);
+    for name in receipt_output_names(&dir) {
+      let path = join_file_id(&dir, &name);
+      let value = match fs::read_to_string(&path) {
+        Ok(raw) => serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null),
+        Err(_) => continue,
+      };
+      let receipt = match receipt_total_from(store_id, &path, &value) {
+        Some(receipt) => receipt,
+        None => continue,
+      };
+      receipts_scanned += 1;
+      for item in value
+        .get("items")
+        .and_then(|items| items.as_array())
+        .into_iter()
+        .flatten()
+      {
+        let raw_name = match item.get("name").and_then(|name| name.as_str()) {
+          Some(raw_name) => raw_name,
+          None => continue,
+        };
+        let normalized = normalize_item_name(raw_name);
+        if !item_name_matches(&normalized, &terms) {
+          continue;
+        }
+        let unit_price = match item_unit_price(item) {
+          Some(unit_price) => unit_price,
+          None => continue,
+        };
+        products.entry(normalized).or_default().push(PricePoint {
+          date: receipt.date,
+          date_estimated: receipt.date_estimated,
+          unit_price: round_cents(unit_price),
+          currency: receipt_currency(&receipt),
+          store_id: store_id.clone(),
+          file_name: name.clone(),
+          name: raw_name.trim().to_string(),
+        });
+      }
+    }
+  }
+  let product_count = products.len();
+  let mut products = products
+    .into_iter()
+    .map(|(name, mut points)| {
+      points.sort_by(|a, b| {
+        a.date
+          .cmp(&b.date)
+          .then_with(|| a.store_id.cmp(&b.store_id))
+          .then_with(|| a.file_name.cmp(&b.file_name))
+      });
+      PriceSeries { name, points }
+    })
+    .collect::<Vec<_>>();
+  products.sort_by(|a, b| {
+    b.points
+      .len()
+      .cmp(&a.points.len())
+      .then_with(|| a.name.cmp(&b.name))
+  });
+  products.truncate(PRICE_HISTORY_MAX_PRODUCTS);
+  Ok(PriceHistory {
+    query: query.trim().to_string(),
+    store_id: None,
+    products,
+    product_count,
+    receipts_scanned,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn item_names_normalize_across_store_formats() {
+    for (raw, normalized) in [
+      ("Lapte 1,5% 1L", "lapte 1.5% 1l"),
+      ("LAPTE 1.5% 1 L", "lapte 1.5% 1l"),
+      ("LAPTE 1.5 % 1000ML 2 BUC X", "lapte 1.5% 1l"),
+      ("Lapte  1,50%  1 LTR x2", "lapte 1.5% 1l"),
+      ("Pâine albă 500 GR", "paine alba 500g"),
+      ("Făină 1000g 3x", "faina 1kg"),
+      ("Apă plată 0,5L * 6", "apa plata 0.5l"),
+      ("Bere 500ml x 4", "bere 500ml"),
+      ("Ouă M10", "oua m10"),
+      ("Tricou XL", "tricou xl"),
+      ("x", "x"),
+    ] {
+      assert_eq!(normalize_item_name(raw), normalized, "{}", raw);
+    }
+    assert!(item_name_matches(
+      "lapte 1.5% 1l",
+      &["lapt".into(), "1l".into()]
+    ));
+    assert!(!item_name_matches("lapte 1.5% 1l", &["apa".into()]));
+    assert_eq!(
+      item_unit_price(&serde_json::json!({"paid_amount": "13,50", "quantity": 3})),
+      Some(4.5)
+    );
+    assert_eq!(
+      item_unit_price(&serde_json::json!({"unit_price": 6.49, "paid_amount": 12.98})),
+      Some(6.49)
+    );
+    assert_eq!(
+      item_unit_price(&serde_json::json!({"paid_amount": 0})),
+      None
+    );
+  }
+
+  #[test]
+  fn price_history_groups_matching_items_by_normalized_name() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path().to_string_lossy().to_string();
+    for (store_id, name, date, items) in [
+      (
+        "lidl",
+        "a.jpg.json",
+        "2024-03-01",
+        serde_json::json!([
+          {"name": "Lapte 1,5% 1L", "unit_price": 6.49},
+          {"name": "Paine", "unit_price": 4.0},
+        ]),
+      ),
+      (
+        "lidl",
+        "b.jpg.json",
+        "2024-01-10",
+        serde_json::json!([{"name": "Lapte 1,5% 1L", "paid_amount": 11.98, "quantity": 2}]),
+      ),
+      (
+        "kaufland",
+        "c.jpg.json",
+        "2024-02-05",
+        serde_json::json!([
+          {"name": "LAPTE 1.5% 1000ML 2 BUC X", "unit_price": "6,29"},
+          {"name": "LAPTE BATUT 2% 330G", "unit_price": 3.5},
+        ]),
+      ),
+      (
+        "kaufland",
+        "d.jpg.json",
+        "2024-02-06",
+        serde_json::json!([{"name": "Apa 2L", "unit_price": 2.5}]),
+      ),
+    ] {
+      let processed = dir.path().join("processed").join(store_id);
+      fs::create_dir_all(&processed).expect("processed");
+      let output = serde_json::json!({"timestamp": date, "currency": "RON", "items": items});
+      fs::write(processed.join(name), output.to_string()).expect("output");
+    }
+    let stores = vec!["lidl".to_string(), "kaufland".to_string()];
+    let history = price_history(&root, &stores, "lapte").expect("history");
+    assert_eq!(history.receipts_scanned, 4);
+    assert_eq!(history.product_count, 2);
+    let milk = &history.products[0];
+    assert_eq!(milk.name, "lapte 1.5% 1l");
+    assert_eq!(
+      milk
+        .points
+        .iter()
+        .map(|point| (point.date.map(|date| date.to_string()), point.unit_price))
+        .collect::<Vec<_>>(),
+      vec![
+        (Some("2024-01-10".to_string()), 5.99),
+        (Some("2024-02-05".to_string()), 6.29),
+        (Some("2024-03-01".to_string()), 6.49),
+      ]
+    );
+    assert_eq!(milk.points[1].store_id, "kaufland");
+    assert_eq!(milk.points[1].name, "LAPTE 1.5% 1000ML 2 BUC X");
+    assert_eq!(milk.points[1].currency, "RON");
+    assert_eq!(history.products[1].name, "lapte batut 2% 330g");
+
+    let only = price_history(&root, &stores, "LAPTE 1,5% 1 L").expect("exact");
+    assert_eq!(only.products.len(), 1);
+    assert_eq!(only.products[0].points.len(), 3);
+    assert!(price_history(&root, &stores, " ,. ").is_err());
+  }
+}
@@ -0,0 +1,299 @@
+use crate::*;
+use chrono::Datelike;
+
+// `receipts-summary.json`, for the parts of life-dashboard that read the shared
+// data directory instead of talking to the app. Fields are only ever added;
+// renaming or removing one means bumping SUMMARY_SCHEMA_VERSION.
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct PublishedSummary {
+  pub(crate) schema_version: u32,
+  pub(crate) generated_at: String,
+  pub(crate) app_version: String,
+  pub(crate) status: StatusSnapshot,
+  pub(crate) month: PublishedMonth,
+  pub(crate) pending: PublishedPending,
+  pub(crate) last_run: Option<PublishedRun>,
+}
+
+// Totals per currency as recorded, without conversion.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PublishedMonth {
+  pub(crate) year: i32,
+  pub(crate) month: u32,
+  pub(crate) receipt_count: usize,
+  pub(crate) totals: CurrencyTotals,
+  pub(crate) stores: Vec<PublishedStoreMonth>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PublishedStoreMonth {
+  pub(crate) store_id: String,
+  pub(crate) receipt_count: usize,
+  pub(crate) totals: CurrencyTotals,
+}
+
+// Inbox files waiting to be processed.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PublishedPending {
+  pub(crate) total: usize,
+  pub(crate) stores: std::collections::BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PublishedRun {
+  pub(crate) run_id: Option<String>,
+  pub(crate) stores: Vec<String>,
+  pub(crate) failure_count: usize,
+  pub(crate) warning_count: usize,
+  pub(crate) processed_count: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PublishResult {
+  pub(crate) path: String,
+  pub(crate) bytes: usize,
+  pub(crate) generated_at: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct PublishFailedEvent {
+  pub(crate) path: Option<String>,
+  #[serde(flatten)]
+  pub(crate) error: AppError,
+}
+
+pub(crate) fn published_month(report: &MonthlyReport) -> PublishedMonth {
+  let totals = |figures: &[MonthFigures]| {
+    figures
+      .iter()
+      .filter(|figures| figures.receipt_count > 0)
+      .map(|figures| (figures.currency.clone(), figures.total))
+      .collect::<CurrencyTotals>()
+  };
+  PublishedMonth {
+    year: report.year,
+    month: report.month,
+    receipt_count: report.receipt_count,
+    totals: totals(&report.by_currency),
+    stores: report
+      .stores
+      .iter()
+      .map(|store| PublishedStoreMonth {
+        store_id: store.store_id.clone(),
+        receipt_count: store.receipt_count,
+        totals: totals(&store.by_currency),
+      })
+      .collect(),
+  }
+}
+
+pub(crate) fn published_run(summary: &Value) -> PublishedRun {
+  PublishedRun {
+    run_id: extract_run_id(summary),
+    stores: extract_stores(summary),
+    failure_count: extract_issue_count(summary, "failures"),
+    warning_count: extract_issue_count(summary, "warnings"),
+    processed_count: extract_processed_count(summary),
+  }
+}
+
+// `summary_dir` has to be an absolute path to an existing folder. It is never
+// created: on an unmounted drive that would write to the bare mount point.
+pub(crate) fn summary_file_path(summary_dir: &str) -> Result<PathBuf, AppError> {
+  let dir = Path::new(summary_dir.trim());
+  if !dir.is_absolute() {
+    return Err(AppError::InvalidInput(format!(
+      "summary_dir must be an absolute path: {}",
+      summary_dir
+    )));
+  }
+  if !dir.is_dir() {
+    return Err(AppError::PathNotFound(dir.to_string_lossy().to_string()));
+  }
+  Ok(dir.join(SUMMARY_FILE_NAME))
+}
+
+// Written to a temp file next to it and renamed, so readers never see half a file.
+pub(crate) fn write_summary_file(
+  summary_dir: &str,
+  summary: &PublishedSummary,
+) -> Result<PublishResult, AppError> {
+  let path = summary_file_path(summary_dir)?;
+  let raw = serde_json::to_string_pretty(summary).map_err(|err| err.to_string())?;
+  let temp = write_synced_temp(&path, &raw).map_err(|reason| AppError::Io {
+    path: path.to_string_lossy().to_string(),
+    reason,
+  })?;
+  if let Err(err) = fs::rename(&temp, &path) {
+    let _ = fs::remove_file(&temp);
+    return Err(AppError::io(&path, err));
+  }
+  Ok(PublishResult {
+    path: path.to_string_lossy().to_string(),
+    bytes: raw.len(),
+    generated_at: summary.generated_at.clone(),
+  })
+}
+
+// `refresh` rescans the runs folder first, as after a worker run.
+pub(crate) fn build_published_summary(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  refresh: bool,
+) -> Result<PublishedSummary, AppError> {
+  let status = build_status_snapshot(app)?;
+  let today = chrono::Local::now().date_naive();
+  let report = cached_monthly_report(
+    app,
+    config,
+    today.year(),
+    today.month(),
+    &Conversion::default(),
+  )?;
+  let last_run = dashboard_snapshot(app, None, refresh)?
+    .last_runs
+    .first()
+    .map(published_run);
+  Ok(PublishedSummary {
+    schema_version: SUMMARY_SCHEMA_VERSION,
+    generated_at: chrono::Utc::now().to_rfc3339(),
+    app_version: APP_VERSION.to_string(),
+    month: published_month(&report),
+    pending: PublishedPending {
+      total: status.totals.inbox_count,
+      stores: status
+        .stores
+        .iter()
+        .map(|store| (store.store_id.clone(), store.inbox_count))
+        .collect(),
+    },
+    status,
+    last_run,
+  })
+}
+
+// Failures are logged and emitted as `summary-publish-failed` as well as
+// returned, since after a run nobody is waiting on the result.
+pub(crate) fn publish_summary(
+  app: &tauri::AppHandle,
+  refresh: bool,
+) -> Result<PublishResult, AppError> {
+  let config = current_config(app);
+  let result = match config.summary_dir.as_deref() {
+    Some(summary_dir) => build_published_summary(app, &config, refresh)
+      .and_then(|summary| write_summary_file(summary_dir, &summary)),
+    None => Err(AppError::ConfigMissing(
+      "summary_dir is not set".to_string(),
+    )),
+  };
+  match &result {
+    Ok(published) => info!(
+      "summary published to {} ({} bytes)",
+      published.path, published.bytes
+    ),
+    Err(err) => {
+      error!("summary not published: {}", err);
+      let _ = app.emit_all(
+        "summary-publish-failed",
+        PublishFailedEvent {
+          path: config
+            .summary_dir
+            .as_deref()
+            .map(|dir| Path::new(dir).join(SUMMARY_FILE_NAME))
+            .map(|path| path.to_string_lossy().to_string()),
+          error: err.clone(),
+        },
+      );
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dashboard_summary_is_written_atomically_into_an_existing_folder() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let report = monthly_report(
+      vec![ReceiptTotal {
+        store_id: "lidl".to_string(),
+        file_name: "a.jpg.json".to_string(),
+        date: chrono::NaiveDate::from_ymd_opt(2024, 5, 3),
+        date_estimated: false,
+        total: Some(42.5),
+        currency: Some("RON".to_string()),
+      }],
+      &["lidl".to_string(), "kaufland".to_string()],
+      2024,
+      5,
+      &Conversion::default(),
+    );
+    let summary = PublishedSummary {
+      schema_version: SUMMARY_SCHEMA_VERSION,
+      generated_at: "2024-05-04T10:00:00+00:00".to_string(),
+      app_version: APP_VERSION.to_string(),
+      status: StatusSnapshot {
+        generated_at: "2024-05-04T10:00:00+00:00".to_string(),
+        stores: Vec::new(),
+        totals: StatusTotals::default(),
+        inbox_cached: true,
+        runs_cached: true,
+        data_issues: None,
+        access_warning: None,
+      },
+      month: published_month(&report),
+      pending: PublishedPending {
+        total: 0,
+        stores: Default::default(),
+      },
+      last_run: Some(published_run(&serde_json::json!({
+        "run_id": "r1",
+        "stores": ["lidl"],
+        "failures": [{"file": "x.jpg"}],
+        "processed_count": 3,
+      }))),
+    };
+    assert_eq!(
+      summary.month.stores[1],
+      PublishedStoreMonth {
+        store_id: "kaufland".to_string(),
+        receipt_count: 0,
+        totals: CurrencyTotals::new(),
+      }
+    );
+
+    let root = dir.path().to_string_lossy().to_string();
+    let published = write_summary_file(&root, &summary).expect("publish");
+    let path = dir.path().join(SUMMARY_FILE_NAME);
+    assert_eq!(published.path, path.to_string_lossy());
+    let written: Value =
+      serde_json::from_str(&fs::read_to_string(&path).expect("summary")).expect("json");
+    assert_eq!(written["schema_version"], 1);
+    assert_eq!(written["month"]["totals"]["RON"], 42.5);
+    assert_eq!(written["last_run"]["failure_count"], 1);
+    let names = fs::read_dir(dir.path())
+      .expect("read dir")
+      .map(|entry| {
+        entry
+          .expect("entry")
+          .file_name()
+          .to_string_lossy()
+          .to_string()
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec![SUMMARY_FILE_NAME.to_string()]);
+
+    let missing = dir.path().join("unmounted").to_string_lossy().to_string();
+    assert_eq!(
+      write_summary_file(&missing, &summary).unwrap_err().code(),
+      "not_found"
+    );
+    assert!(!dir.path().join("unmounted").exists());
+    assert_eq!(
+      write_summary_file("shared", &summary).unwrap_err().code(),
+      "invalid_input"
+    );
+  }
+}
@@ -0,0 +1,238 @@
+use crate::*;
+
+// The `confirm-quit` payload.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct QuitRequest {
+  // `worker` and/or `update`.
+  pub(crate) jobs: Vec<String>,
+  // What asked: `window`, `tray`, `menu` or `app` (Cmd+Q, logout).
+  pub(crate) source: String,
+  // The `resolve_quit` actions this platform offers.
+  pub(crate) actions: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct QuitResult {
+  pub(crate) quitting: bool,
+  pub(crate) reason: Option<String>,
+}
+
+// Set once a quit that waits for the run to finish is under way; asking to
+// quit again then quits right away.
+#[derive(Default)]
+pub(crate) struct QuitState(pub(crate) AtomicBool);
+
+pub(crate) fn quit_actions() -> Vec<&'static str> {
+  QUIT_ACTIONS
+    .iter()
+    .copied()
+    .filter(|action| cfg!(unix) || *action != "quit_leave_running")
+    .collect()
+}
+
+// For `validate_pref`: `ask` or one of the actions other than `stay`.
+pub(crate) fn validate_quit_pref(value: &Value) -> Result<(), String> {
+  match value.as_str() {
+    Some("ask") => Ok(()),
+    Some(action) if action != "stay" && quit_actions().contains(&action) => Ok(()),
+    _ => Err(format!(
+      "Invalid {} (expected ask or one of {})",
+      PREF_QUIT_WHILE_BUSY,
+      quit_actions()
+        .into_iter()
+        .filter(|action| *action != "stay")
+        .collect::<Vec<_>>()
+        .join(", ")
+    )),
+  }
+}
+
+pub(crate) fn busy_jobs(app: &tauri::AppHandle) -> Vec<String> {
+  let registry = app.state::<ChildRegistry>();
+  let jobs = match registry.0.lock() {
+    Ok(jobs) => jobs,
+    Err(_) => return Vec::new(),
+  };
+  ["worker", "update"]
+    .iter()
+    .filter(|job| jobs.contains_key(**job))
+    .map(|job| job.to_string())
+    .collect()
+}
+
+pub(crate) fn commands_running(app: &tauri::AppHandle) -> bool {
+  app
+    .state::<CommandsRunning>()
+    .0
+    .lock()
+    .map(|running| *running > 0)
+    .unwrap_or(false)
+}
+
+// Every way of quitting comes through here (the close button with
+// `minimize_to_tray` off, tray and menu Quit, and exit requests from the OS).
+// Nothing running: the app exits. Otherwise `system.quit_while_busy` decides;
+// with `ask` (the default) the window comes up and gets `confirm-quit`.
+// Returns whether the app is exiting now.
+pub(crate) fn request_quit(app: &tauri::AppHandle, source: &str) -> bool {
+  let jobs = busy_jobs(app);
+  if jobs.is_empty() || app.state::<QuitState>().0.load(Ordering::SeqCst) {
+    app.exit(0);
+    return true;
+  }
+  let behaviour = app
+    .state::<PrefsStore>()
+    .prefs
+    .lock()
+    .ok()
+    .and_then(|prefs| prefs.get(PREF_QUIT_WHILE_BUSY).cloned())
+    .and_then(|value| value.as_str().map(str::to_string))
+    .filter(|action| action != "stay" && quit_actions().contains(&action.as_str()));
+  if let Some(action) = behaviour {
+    info!(
+      "quit from {} with {} running: {}",
+      source,
+      jobs.join(", "),
+      action
+    );
+    return match resolve_quit_with(app, &action) {
+      Ok(result) => result.quitting,
+      Err(err) => {
+        warn!("quit: {}", err.message());
+        false
+      }
+    };
+  }
+  info!(
+    "quit from {} with {} running; asking",
+    source,
+    jobs.join(", ")
+  );
+  let window = show_main_window(app);
+  let request = QuitRequest {
+    jobs,
+    source: source.to_string(),
+    actions: quit_actions(),
+  };
+  match window {
+    Some(window) => {
+      let _ = window.emit("confirm-quit", request);
+    }
+    None => {
+      let _ = app.emit_all("confirm-quit", request);
+    }
+  }
+  false
+}
+
+// Waits (up to QUIT_GRACE) for runs that were just stopped to finish
+// unwinding, so run_worker_for_files can put staged files back, then exits.
+// Anything left staged is restored at the next start.
+pub(crate) fn exit_after_commands(app: &tauri::AppHandle, grace: Option<Duration>) {
+  let started = Instant::now();
+  while commands_running(app) && grace.is_none_or(|grace| started.elapsed() < grace) {
+    thread::sleep(Duration::from_millis(100));
+  }
+  app.exit(0);
+}
+
+pub(crate) fn resolve_quit_with(
+  app: &tauri::AppHandle,
+  action: &str,
+) -> Result<QuitResult, AppError> {
+  if !quit_actions().contains(&action) {
+    return Err(AppError::InvalidInput(format!(
+      "Unknown quit action: {} (expected {})",
+      action,
+      quit_actions().join(", ")
+    )));
+  }
+  match action {
+    "stay" => Ok(QuitResult {
+      quitting: false,
+      reason: None,
+    }),
+    "cancel_run_and_quit" => {
+      let jobs = busy_jobs(app);
+      // The update goes first: once it is past the point where it can be
+      // stopped, the run is left alone too and nothing quits.
+      if jobs.iter().any(|job| job == "update") {
+        let result = cancel_update_job(&app.state::<ChildRegistry>())?;
+        if !result.cancelled {
+          return Ok(QuitResult {
+            quitting: false,
+            reason: result.reason,
+          });
+        }
+      }
+      if jobs.iter().any(|job| job == "worker") {
+        let result = cancel_worker_run(app)?;
+        if !result.cancelled && !busy_jobs(app).is_empty() {
+          return Ok(QuitResult {
+            quitting: false,
+            reason: result.reason,
+          });
+        }
+      }
+      let app = app.clone();
+      thread::spawn(move || exit_after_commands(&app, Some(QUIT_GRACE)));
+      Ok(QuitResult {
+        quitting: true,
+        reason: None,
+      })
+    }
+    // The windows go away now; the process stays until the run ends, since
+    // the run's output pipes and the staged-file restore live in it and the
+    // worker would otherwise die on its next write. The worker has its own
+    // process group, so closing a terminal the app was started from doesn't
+    // reach it either.
+    _ => {
+      app.state::<QuitState>().0.store(true, Ordering::SeqCst);
+      for window in app.windows().values() {
+        let _ = window.hide();
+      }
+      info!("windows closed; exiting once the run finishes");
+      let app = app.clone();
+      thread::spawn(move || {
+        while !busy_jobs(&app).is_empty() {
+          thread::sleep(Duration::from_millis(500));
+        }
+        exit_after_commands(&app, None);
+      });
+      Ok(QuitResult {
+        quitting: true,
+        reason: None,
+      })
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn quit_pref_accepts_ask_and_the_platforms_quit_actions() {
+    let config = badge_config("lidl");
+    for value in ["ask", "cancel_run_and_quit"] {
+      assert!(validate_pref(
+        &config,
+        PREF_QUIT_WHILE_BUSY,
+        &Value::String(value.to_string())
+      )
+      .is_ok());
+    }
+    for value in [
+      Value::String("stay".to_string()),
+      Value::String("later".to_string()),
+      Value::Bool(true),
+    ] {
+      assert!(validate_pref(&config, PREF_QUIT_WHILE_BUSY, &value).is_err());
+    }
+    let leave = Value::String("quit_leave_running".to_string());
+    assert_eq!(validate_quit_pref(&leave).is_ok(), cfg!(unix));
+    assert_eq!(quit_actions().contains(&"quit_leave_running"), cfg!(unix));
+    assert!(quit_actions().contains(&"stay"));
+  }
+}
@@ -0,0 +1,225 @@
+use crate::*;
+
+// The `root-unreachable` / `root-recovered` payload and what
+// `get_root_status` returns.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct RootStatus {
+  pub(crate) root: String,
+  pub(crate) reachable: bool,
+  // When the root stopped answering; on `root-recovered`, when it had.
+  pub(crate) unreachable_since: Option<String>,
+  // Until the scanner probes again.
+  pub(crate) retry_in_secs: Option<u64>,
+}
+
+#[derive(Default)]
+pub(crate) struct RootHealth {
+  // Since when the configured root has been unreachable.
+  pub(crate) unreachable_since: Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+  // Set while a probe is out. One stuck on a hung mount keeps it set, and
+  // probes after it fail straight away instead of piling up threads.
+  pub(crate) probing: Arc<AtomicBool>,
+}
+
+impl RootHealth {
+  pub(crate) fn is_unreachable(&self) -> bool {
+    self
+      .unreachable_since
+      .lock()
+      .map(|since| since.is_some())
+      .unwrap_or(false)
+  }
+
+  // Some(since when it was gone) when the root just came back.
+  pub(crate) fn mark_reachable(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+    match self.unreachable_since.lock() {
+      Ok(mut since) => since.take(),
+      Err(poisoned) => poisoned.into_inner().take(),
+    }
+  }
+
+  // True when the root was reachable until now.
+  pub(crate) fn mark_unreachable(&self) -> bool {
+    let mut since = match self.unreachable_since.lock() {
+      Ok(since) => since,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+    if since.is_some() {
+      return false;
+    }
+    *since = Some(chrono::Utc::now());
+    true
+  }
+}
+
+// Reads the folder's metadata and opens it on a thread of its own and waits
+// at most `timeout` for it.
+pub(crate) fn probe_path(path: &Path, timeout: Duration, probing: &Arc<AtomicBool>) -> bool {
+  if probing.swap(true, Ordering::SeqCst) {
+    return false;
+  }
+  let (sender, receiver) = std::sync::mpsc::channel();
+  let path = path.to_path_buf();
+  let probing = probing.clone();
+  thread::spawn(move || {
+    let answered =
+      fs::metadata(&path).is_ok_and(|meta| meta.is_dir()) && fs::read_dir(&path).is_ok();
+    probing.store(false, Ordering::SeqCst);
+    let _ = sender.send(answered);
+  });
+  receiver.recv_timeout(timeout).unwrap_or(false)
+}
+
+pub(crate) fn root_status(app: &tauri::AppHandle, config: &AppConfig) -> RootStatus {
+  let since = app
+    .state::<RootHealth>()
+    .unreachable_since
+    .lock()
+    .ok()
+    .and_then(|since| *since);
+  RootStatus {
+    root: config.receipts_root.clone(),
+    reachable: since.is_none(),
+    unreachable_since: since.map(|since| since.to_rfc3339()),
+    retry_in_secs: app
+      .state::<DashboardScanner>()
+      .retry_in()
+      .filter(|_| since.is_some())
+      .map(|delay| delay.as_secs()),
+  }
+}
+
+// Probes the root now. For the configured root, going away backs the dashboard
+// scanner off (it is what probes again, see `run_dashboard_scanner`) and emits
+// `root-unreachable`; coming back emits `root-recovered` and rescans. A root
+// override is only probed.
+pub(crate) fn probe_receipts_root(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+) -> Result<(), AppError> {
+  let root = require_receipts_root(config)?;
+  let health = app.state::<RootHealth>();
+  if config.root_override.is_some() {
+    let probing = Arc::new(AtomicBool::new(false));
+    if probe_path(Path::new(root), ROOT_PROBE_TIMEOUT, &probing) {
+      return Ok(());
+    }
+    return Err(AppError::RootUnreachable(root.to_string()));
+  }
+  let scanner = app.state::<DashboardScanner>();
+  if probe_path(Path::new(root), ROOT_PROBE_TIMEOUT, &health.probing) {
+    scanner.record_reachable();
+    if let Some(since) = health.mark_reachable() {
+      info!("{} is reachable again", root);
+      let status = RootStatus {
+        unreachable_since: Some(since.to_rfc3339()),
+        ..root_status(app, config)
+      };
+      let _ = app.emit_all("root-recovered", status);
+      scanner.request();
+    }
+    return Ok(());
+  }
+  let delay = scanner.record_unreachable();
+  warn!(
+    "{} did not answer within {}ms; retrying in {}s",
+    root,
+    ROOT_PROBE_TIMEOUT.as_millis(),
+    delay.as_secs()
+  );
+  if health.mark_unreachable() {
+    let _ = app.emit_all("root-unreachable", root_status(app, config));
+  }
+  Err(AppError::RootUnreachable(root.to_string()))
+}
+
+// For commands that write under the root (or would block reading it): fails
+// with `root_unreachable` right away while the root is known to be gone, and
+// otherwise after at most ROOT_PROBE_TIMEOUT.
+pub(crate) fn require_reachable_root(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+) -> Result<(), AppError> {
+  let root = require_receipts_root(config)?;
+  if config.root_override.is_none() && app.state::<RootHealth>().is_unreachable() {
+    return Err(AppError::RootUnreachable(root.to_string()));
+  }
+  probe_receipts_root(app, config)
+}
+
+// Headless runs have no RootHealth; they probe once and stop if that fails.
+pub(crate) fn probe_root_once(config: &AppConfig) -> Result<(), AppError> {
+  let root = require_receipts_root(config)?;
+  let probing = Arc::new(AtomicBool::new(false));
+  if probe_path(Path::new(root), ROOT_PROBE_TIMEOUT, &probing) {
+    Ok(())
+  } else {
+    Err(AppError::RootUnreachable(root.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn root_probe_times_out_and_stale_snapshots_are_flagged() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let probing = Arc::new(AtomicBool::new(false));
+    assert!(probe_path(dir.path(), ROOT_PROBE_TIMEOUT, &probing));
+    assert!(!probing.load(Ordering::SeqCst));
+    assert!(!probe_path(
+      &dir.path().join("missing"),
+      ROOT_PROBE_TIMEOUT,
+      &probing
+    ));
+    fs::write(dir.path().join("file"), b"x").expect("file");
+    assert!(!probe_path(
+      &dir.path().join("file"),
+      ROOT_PROBE_TIMEOUT,
+      &probing
+    ));
+    // A probe still stuck on a hung mount: the next one doesn't wait.
+    probing.store(true, Ordering::SeqCst);
+    let started = Instant::now();
+    assert!(!probe_path(dir.path(), Duration::from_secs(5), &probing));
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    let health = RootHealth::default();
+    assert!(!health.is_unreachable());
+    assert!(health.mark_reachable().is_none());
+    assert!(health.mark_unreachable());
+    assert!(!health.mark_unreachable());
+    assert!(health.is_unreachable());
+    assert!(health.mark_reachable().is_some());
+    assert!(!health.is_unreachable());
+
+    let err = AppError::RootUnreachable("/Volumes/nas/bonuri".to_string());
+    assert_eq!(err.code(), "root_unreachable");
+    assert_eq!(err.details()["path"], "/Volumes/nas/bonuri");
+
+    let config = badge_config("lidl");
+    let snapshot = DashboardSnapshot {
+      inbox_counts: vec![InboxCount {
+        store_id: "lidl".to_string(),
+        count: 3,
+        backlog_warning: None,
+        computed_at: String::new(),
+        stale: false,
+        error: None,
+      }],
+      badges: unread_badges(&config, &HashMap::new(), &SeenState::default()),
+      last_runs: Vec::new(),
+      generated_at: String::new(),
+      stale: false,
+      access_warning: None,
+      generated: Instant::now(),
+    }
+    .into_stale();
+    assert!(snapshot.stale);
+    assert!(snapshot.inbox_counts.iter().all(|count| count.stale));
+    assert_eq!(snapshot.badges.len(), 1);
+    assert!(snapshot.badges.iter().all(|badge| badge.stale));
+  }
+}
@@ -0,0 +1,227 @@
+use crate::*;
+
+// One processed receipt: the image/PDF the worker moved to processed/<store>
+// and its `<image>.json` output. `parsed` is None when there is no (readable)
+// output, e.g. for receipts processed before the worker wrote JSON.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ReceiptDetail {
+  pub(crate) store_id: String,
+  pub(crate) file_name: String,
+  pub(crate) image_path: Option<String>,
+  pub(crate) output_path: Option<String>,
+  pub(crate) parsed: Option<ParsedReceipt>,
+  // The worker's `processing.warnings`, then anything this app couldn't read.
+  pub(crate) warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ParsedReceipt {
+  pub(crate) date: Option<chrono::NaiveDate>,
+  pub(crate) total: Option<f64>,
+  pub(crate) currency: Option<String>,
+  pub(crate) merchant: Option<String>,
+  pub(crate) items: Vec<Value>,
+  pub(crate) raw_text: Option<String>,
+  // The output as written, for fields not lifted out above.
+  pub(crate) output: Value,
+}
+
+// A bare file name in processed/<store>; no separators, no dot files.
+pub(crate) fn is_receipt_file_name(name: &str) -> bool {
+  !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+// `IMG_1.jpg` and `IMG_1.jpg.json` both name the receipt `IMG_1.jpg`.
+// `file_name` may be an id for a name that isn't UTF-8 (see `file_name_id`).
+pub(crate) fn receipt_image_name(file_name: &str) -> OsString {
+  let raw_name = file_name_from_id(file_name);
+  match Path::new(&raw_name).file_stem() {
+    Some(stem) if is_receipt_output(&raw_name.to_string_lossy()) => stem.to_os_string(),
+    _ => raw_name,
+  }
+}
+
+pub(crate) fn parsed_receipt(
+  store_id: &str,
+  output_path: &Path,
+  value: Value,
+  warnings: &mut Vec<String>,
+) -> ParsedReceipt {
+  if let Some(processing) = value
+    .get("processing")
+    .and_then(|processing| processing.get("warnings"))
+    .and_then(|warnings| warnings.as_array())
+  {
+    warnings.extend(processing.iter().map(|warning| match warning {
+      Value::String(text) => text.clone(),
+      other => other.to_string(),
+    }));
+  }
+  let receipt = receipt_total_from(store_id, output_path, &value);
+  let total = receipt.as_ref().and_then(|receipt| receipt.total);
+  if total.is_none() {
+    warnings.push("no parseable total".to_string());
+  }
+  ParsedReceipt {
+    date: receipt.as_ref().and_then(|receipt| receipt.date),
+    total,
+    currency: receipt.and_then(|receipt| receipt.currency),
+    merchant: value
+      .get("merchant")
+      .and_then(|merchant| merchant.get("name"))
+      .and_then(|name| name.as_str())
+      .map(str::to_string),
+    items: value
+      .get("items")
+      .and_then(|items| items.as_array())
+      .cloned()
+      .unwrap_or_default(),
+    raw_text: value
+      .get("raw_text")
+      .and_then(|text| text.as_str())
+      .map(str::to_string),
+    output: value,
+  }
+}
+
+// Both files go through `validate_allowed_path`, so a symlink out of the
+// receipts root is refused like any other path from the frontend.
+pub(crate) fn receipt_detail(
+  config: &AppConfig,
+  store_id: &str,
+  file_name: &str,
+) -> Result<ReceiptDetail, AppError> {
+  let root = require_receipts_root(config)?;
+  require_store(config, store_id)?;
+  if !is_receipt_file_name(&file_name_from_id(file_name).to_string_lossy()) {
+    return Err(AppError::InvalidInput(format!(
+      "Not a receipt file name: {}",
+      file_name
+    )));
+  }
+  let image_name = receipt_image_name(file_name);
+  let mut output_name = image_name.clone();
+  output_name.push(".json");
+  let dir = processed_dir(root, store_id);
+  let image = dir.join(&image_name);
+  let output = dir.join(output_name);
+  if !image.exists() && !output.exists() {
+    return Err(AppError::PathNotFound(image.to_string_lossy().to_string()));
+  }
+
+  let roots = allowed_roots(config);
+  let image_path = if image.exists() {
+    Some(validate_allowed_path(&image, &roots)?)
+  } else {
+    None
+  };
+  let output_path = if output.exists() {
+    Some(validate_allowed_path(&output, &roots)?)
+  } else {
+    None
+  };
+
+  let mut warnings = Vec::new();
+  let parsed = match &output_path {
+    Some(path) => {
+      let raw = fs::read_to_string(path).map_err(|err| AppError::io(path, err))?;
+      match serde_json::from_str::<Value>(&raw) {
+        Ok(value) if value.is_object() => {
+          Some(parsed_receipt(store_id, path, value, &mut warnings))
+        }
+        Ok(_) => {
+          warnings.push("output is not a JSON object".to_string());
+          None
+        }
+        Err(err) => {
+          warnings.push(format!("output is not valid JSON: {}", err));
+          None
+        }
+      }
+    }
+    None => None,
+  };
+  Ok(ReceiptDetail {
+    store_id: store_id.to_string(),
+    file_name: file_name_id(&image_name),
+    image_path: image_path.map(|path| path.to_string_lossy().to_string()),
+    output_path: output_path.map(|path| path.to_string_lossy().to_string()),
+    parsed,
+    warnings,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn receipt_detail_reads_the_output_and_degrades_without_one() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(&processed).expect("processed");
+    fs::write(processed.join("a.jpg"), b"jpg").expect("image");
+    fs::write(
+      processed.join("a.jpg.json"),
+      r#"{"timestamp": "2024-01-05T10:00:00", "total": "45,90", "currency": "ron",
+        "merchant": {"name": "Lidl"}, "items": [{"name": "Lapte"}],
+        "processing": {"warnings": ["total mismatch"]}, "raw_text": "LIDL\nTOTAL 45,90"}"#,
+    )
+    .expect("output");
+    fs::write(processed.join("old.pdf"), b"pdf").expect("old receipt");
+    fs::write(processed.join("bad.jpg.json"), "garbage").expect("garbage");
+    let mut config = badge_config("lidl");
+    config.receipts_root = dir.path().to_string_lossy().to_string();
+
+    for name in ["a.jpg", "a.jpg.json"] {
+      let detail = receipt_detail(&config, "lidl", name).expect("detail");
+      assert_eq!(detail.file_name, "a.jpg");
+      assert!(detail.image_path.expect("image").ends_with("a.jpg"));
+      let parsed = detail.parsed.expect("parsed");
+      assert_eq!(parsed.total, Some(45.9));
+      assert_eq!(parsed.currency.as_deref(), Some("RON"));
+      assert_eq!(parsed.date, chrono::NaiveDate::from_ymd_opt(2024, 1, 5));
+      assert_eq!(parsed.merchant.as_deref(), Some("Lidl"));
+      assert_eq!(parsed.items.len(), 1);
+      assert_eq!(parsed.raw_text.as_deref(), Some("LIDL\nTOTAL 45,90"));
+      assert_eq!(detail.warnings, vec!["total mismatch".to_string()]);
+    }
+
+    let old = receipt_detail(&config, "lidl", "old.pdf").expect("image only");
+    assert!(old.parsed.is_none() && old.output_path.is_none() && old.warnings.is_empty());
+    let bad = receipt_detail(&config, "lidl", "bad.jpg").expect("output only");
+    assert!(bad.parsed.is_none() && bad.image_path.is_none());
+    assert!(bad.warnings[0].starts_with("output is not valid JSON"));
+
+    for name in ["../lidl/a.jpg", ".hidden.jpg", "", "sub\\a.jpg"] {
+      let err = receipt_detail(&config, "lidl", name).unwrap_err();
+      assert_eq!(err.code(), "invalid_input");
+    }
+    assert_eq!(
+      receipt_detail(&config, "lidl", "missing.jpg")
+        .unwrap_err()
+        .code(),
+      "not_found"
+    );
+    assert_eq!(
+      receipt_detail(&config, "kaufland", "a.jpg")
+        .unwrap_err()
+        .code(),
+      "unknown_store"
+    );
+
+    #[cfg(unix)]
+    {
+      let outside = tempfile::tempdir().expect("outside");
+      fs::write(outside.path().join("secret.jpg"), b"x").expect("secret");
+      std::os::unix::fs::symlink(
+        outside.path().join("secret.jpg"),
+        processed.join("link.jpg"),
+      )
+      .expect("symlink");
+      let err = receipt_detail(&config, "lidl", "link.jpg").unwrap_err();
+      assert_eq!(err.code(), "path_not_allowed");
+    }
+  }
+}
@@ -0,0 +1,413 @@
+use crate::*;
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RestoreSelection {
+  Logs,
+  ProcessedJson,
+  State,
+  All,
+}
+
+impl RestoreSelection {
+  pub(crate) fn parse(raw: &str) -> Result<RestoreSelection, AppError> {
+    match raw.trim() {
+      "logs" => Ok(RestoreSelection::Logs),
+      "processed_json" => Ok(RestoreSelection::ProcessedJson),
+      "state" => Ok(RestoreSelection::State),
+      "all" => Ok(RestoreSelection::All),
+      other => Err(AppError::InvalidInput(format!(
+        "what must be logs, processed_json, state or all: {}",
+        other
+      ))),
+    }
+  }
+
+  pub(crate) fn name(self) -> &'static str {
+    match self {
+      RestoreSelection::Logs => "logs",
+      RestoreSelection::ProcessedJson => "processed_json",
+      RestoreSelection::State => "state",
+      RestoreSelection::All => "all",
+    }
+  }
+
+  pub(crate) fn includes(self, entry: &str) -> bool {
+    match self {
+      RestoreSelection::Logs => entry.starts_with("_logs/"),
+      RestoreSelection::ProcessedJson => {
+        entry.starts_with("processed/") && entry.ends_with(".json")
+      }
+      RestoreSelection::State => entry == BACKUP_STATE_ENTRY,
+      RestoreSelection::All => true,
+    }
+  }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct RestoreEntry {
+  // As in the archive; BACKUP_STATE_ENTRY for the seen state.
+  pub(crate) name: String,
+  pub(crate) size: u64,
+  // Restoring would replace a file that exists now.
+  pub(crate) exists: bool,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct RestorePreview {
+  // Passed back to `restore_backup`; stops matching when the archive changes.
+  pub(crate) preview_id: String,
+  pub(crate) archive_path: String,
+  pub(crate) what: String,
+  pub(crate) manifest: BackupManifest,
+  pub(crate) files: Vec<RestoreEntry>,
+  pub(crate) bytes: u64,
+  pub(crate) overwrites: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct RestoreResult {
+  pub(crate) restored: usize,
+  // Existing files left alone because `overwrite` wasn't set.
+  pub(crate) skipped: usize,
+  // Existing files moved to `holding_dir` before being replaced.
+  pub(crate) overwritten: usize,
+  pub(crate) holding_dir: Option<String>,
+  pub(crate) state_restored: bool,
+}
+
+// Opens `archive_path` and checks its manifest. Archives from a newer backup
+// format or a newer app are refused rather than half-understood.
+pub(crate) fn open_backup(
+  archive_path: &Path,
+) -> Result<(zip::ZipArchive<fs::File>, BackupManifest), AppError> {
+  let display = archive_path.to_string_lossy().to_string();
+  if !archive_path.is_absolute() {
+    return Err(AppError::InvalidInput(format!(
+      "Backup path must be absolute: {}",
+      display
+    )));
+  }
+  if !archive_path.is_file() {
+    return Err(AppError::PathNotFound(display));
+  }
+  let not_a_backup = |reason: String| AppError::Parse {
+    path: Some(display.clone()),
+    reason: format!("not a receipts backup: {}", reason),
+  };
+  let file = fs::File::open(archive_path).map_err(|err| AppError::io(archive_path, err))?;
+  let mut archive = zip::ZipArchive::new(file).map_err(|err| not_a_backup(err.to_string()))?;
+  let mut raw = String::new();
+  archive
+    .by_name(BACKUP_MANIFEST_NAME)
+    .map_err(|err| not_a_backup(format!("{}: {}", BACKUP_MANIFEST_NAME, err)))?
+    .read_to_string(&mut raw)
+    .map_err(|err| not_a_backup(format!("{}: {}", BACKUP_MANIFEST_NAME, err)))?;
+  let manifest = serde_json::from_str::<BackupManifest>(&raw)
+    .map_err(|err| not_a_backup(format!("{}: {}", BACKUP_MANIFEST_NAME, err)))?;
+  if manifest.schema_version > BACKUP_SCHEMA_VERSION {
+    return Err(AppError::Unsupported(format!(
+      "{} uses backup format {}; this app reads up to {}",
+      display, manifest.schema_version, BACKUP_SCHEMA_VERSION
+    )));
+  }
+  if let (Some(theirs), Some(ours)) = (
+    parse_version(&manifest.app_version),
+    parse_version(APP_VERSION),
+  ) {
+    if compare_versions(&theirs, &ours) == std::cmp::Ordering::Greater {
+      return Err(AppError::Unsupported(format!(
+        "{} was made by receipts-desktop {}, newer than this app ({})",
+        display, manifest.app_version, APP_VERSION
+      )));
+    }
+  }
+  Ok((archive, manifest))
+}
+
+// (index, entry name, target path, size) for the selected entries. Names that
+// would land outside the root, or on a hidden path such as RESTORE_HOLDING_DIR,
+// are left out.
+pub(crate) fn restore_targets(
+  archive: &mut zip::ZipArchive<fs::File>,
+  root: &Path,
+  state_file: Option<&Path>,
+  what: RestoreSelection,
+) -> Vec<(usize, String, PathBuf, u64)> {
+  let mut targets = Vec::new();
+  for index in 0..archive.len() {
+    let entry = match archive.by_index(index) {
+      Ok(entry) => entry,
+      Err(_) => continue,
+    };
+    let name = entry.name().to_string();
+    if entry.is_dir() || name == BACKUP_MANIFEST_NAME || !what.includes(&name) {
+      continue;
+    }
+    let target = if name == BACKUP_STATE_ENTRY {
+      match state_file {
+        Some(path) => path.to_path_buf(),
+        None => continue,
+      }
+    } else {
+      match entry.enclosed_name() {
+        Some(relative)
+          if !relative.starts_with("_state")
+            && !relative
+              .components()
+              .any(|part| part.as_os_str().to_string_lossy().starts_with('.')) =>
+        {
+          root.join(relative)
+        }
+        _ => continue,
+      }
+    };
+    targets.push((index, name, target, entry.size()));
+  }
+  targets
+}
+
+// Changes with the archive's path, size and modification time, and with `what`.
+pub(crate) fn restore_preview_id(archive_path: &Path, what: RestoreSelection) -> String {
+  let mut hasher = std::collections::hash_map::DefaultHasher::new();
+  archive_path.hash(&mut hasher);
+  if let Ok(meta) = fs::metadata(archive_path) {
+    meta.len().hash(&mut hasher);
+    meta.modified().ok().hash(&mut hasher);
+  }
+  what.name().hash(&mut hasher);
+  format!("{:016x}", hasher.finish())
+}
+
+pub(crate) fn preview_restore_from(
+  root: &Path,
+  state_file: Option<&Path>,
+  archive_path: &Path,
+  what: RestoreSelection,
+) -> Result<RestorePreview, AppError> {
+  let (mut archive, manifest) = open_backup(archive_path)?;
+  let files = restore_targets(&mut archive, root, state_file, what)
+    .into_iter()
+    .map(|(_, name, target, size)| RestoreEntry {
+      name,
+      size,
+      exists: target.exists(),
+    })
+    .collect::<Vec<_>>();
+  Ok(RestorePreview {
+    preview_id: restore_preview_id(archive_path, what),
+    archive_path: archive_path.to_string_lossy().to_string(),
+    what: what.name().to_string(),
+    manifest,
+    bytes: files.iter().map(|file| file.size).sum(),
+    overwrites: files.iter().filter(|file| file.exists).count(),
+    files,
+  })
+}
+
+// Extracts the selected entries into the root, each through `<name>.tmp` next
+// to it. With `overwrite`, a file that exists is first moved to
+// `RESTORE_HOLDING_DIR/<time>/<same path>`; without it, it is skipped. The
+// seen state is returned as read instead of written, since the app holds it in
+// memory.
+pub(crate) fn restore_backup_to(
+  root: &Path,
+  state_file: Option<&Path>,
+  archive_path: &Path,
+  what: RestoreSelection,
+  preview_id: &str,
+  overwrite: bool,
+  progress: &mut dyn FnMut(BackupProgress),
+) -> Result<(RestoreResult, Option<String>), AppError> {
+  if preview_id != restore_preview_id(archive_path, what) {
+    return Err(AppError::InvalidInput(
+      "Preview the restore first (preview_restore); the archive or selection changed since"
+        .to_string(),
+    ));
+  }
+  let (mut archive, _) = open_backup(archive_path)?;
+  let targets = restore_targets(&mut archive, root, state_file, what);
+  let mut report = BackupProgress {
+    files_done: 0,
+    files_total: targets.len(),
+    bytes_done: 0,
+    bytes_total: targets.iter().map(|(_, _, _, size)| size).sum(),
+    done: false,
+  };
+  progress(report.clone());
+  let holding = root
+    .join(RESTORE_HOLDING_DIR)
+    .join(chrono::Local::now().format("%Y%m%d-%H%M%S").to_string());
+  let mut result = RestoreResult {
+    restored: 0,
+    skipped: 0,
+    overwritten: 0,
+    holding_dir: None,
+    state_restored: false,
+  };
+  let mut state = None;
+  let mut last_emit = Instant::now();
+  for (index, name, target, size) in targets {
+    let mut entry = archive.by_index(index).map_err(|err| AppError::Parse {
+      path: Some(archive_path.to_string_lossy().to_string()),
+      reason: format!("{}: {}", name, err),
+    })?;
+    let exists = target.exists();
+    if exists && !overwrite {
+      result.skipped += 1;
+    } else if name == BACKUP_STATE_ENTRY {
+      let mut raw = String::new();
+      entry
+        .read_to_string(&mut raw)
+        .map_err(|err| AppError::io(archive_path, err))?;
+      state = Some(raw);
+    } else {
+      if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|err| AppError::io(parent, err))?;
+      }
+      let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+      let temp = target.with_file_name(format!("{}.tmp", file_name));
+      let written = fs::File::create(&temp)
+        .and_then(|mut file| std::io::copy(&mut entry, &mut file).and_then(|_| file.sync_all()))
+        .map_err(|err| AppError::io(&temp, err));
+      if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+      }
+      if exists {
+        let held = holding.join(target.strip_prefix(root).unwrap_or(&target));
+        if let Some(parent) = held.parent() {
+          fs::create_dir_all(parent).map_err(|err| AppError::io(parent, err))?;
+        }
+        if let Err(err) = fs::rename(&target, &held) {
+          let _ = fs::remove_file(&temp);
+          return Err(AppError::io(&target, err));
+        }
+        result.overwritten += 1;
+        result.holding_dir = Some(holding.to_string_lossy().to_string());
+      }
+      fs::rename(&temp, &target).map_err(|err| AppError::io(&target, err))?;
+      result.restored += 1;
+    }
+    report.files_done += 1;
+    report.bytes_done += size;
+    if last_emit.elapsed() >= Duration::from_millis(250) {
+      last_emit = Instant::now();
+      progress(report.clone());
+    }
+  }
+  report.done = true;
+  progress(report);
+  Ok((result, state))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn restore_needs_a_preview_and_holds_replaced_files() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path().join("receipts");
+    for (folder, name, body) in [
+      ("processed/lidl", "a.jpg", "image"),
+      ("processed/lidl", "a.jpg.json", "{\"total\": 1}"),
+      ("_logs/runs", "1.summary.json", "{}"),
+    ] {
+      let path = root.join(folder);
+      fs::create_dir_all(&path).expect("dir");
+      fs::write(path.join(name), body).expect("file");
+    }
+    let state = dir.path().join("state.json");
+    fs::write(&state, "{}").expect("state");
+    let archive = dir.path().join("backup.zip");
+    write_backup(
+      &root,
+      Some(&state),
+      &archive,
+      true,
+      &AtomicBool::new(false),
+      &mut |_| {},
+    )
+    .expect("backup");
+
+    fs::write(root.join("processed/lidl/a.jpg.json"), "{\"total\": 2}").expect("edit");
+    fs::remove_dir_all(root.join("_logs")).expect("logs");
+    let preview = preview_restore_from(
+      &root,
+      Some(&state),
+      &archive,
+      RestoreSelection::ProcessedJson,
+    )
+    .expect("preview");
+    assert_eq!(preview.manifest.schema_version, BACKUP_SCHEMA_VERSION);
+    assert_eq!(
+      preview.files,
+      vec![RestoreEntry {
+        name: "processed/lidl/a.jpg.json".to_string(),
+        size: 12,
+        exists: true,
+      }]
+    );
+    assert_eq!(preview.overwrites, 1);
+
+    let restore = |what: RestoreSelection, preview_id: &str, overwrite: bool| {
+      restore_backup_to(
+        &root,
+        Some(&state),
+        &archive,
+        what,
+        preview_id,
+        overwrite,
+        &mut |_| {},
+      )
+    };
+    assert!(matches!(
+      restore(RestoreSelection::All, &preview.preview_id, true),
+      Err(AppError::InvalidInput(_))
+    ));
+    let (skipped, _) =
+      restore(RestoreSelection::ProcessedJson, &preview.preview_id, false).expect("restore");
+    assert_eq!((skipped.restored, skipped.skipped), (0, 1));
+
+    let (result, restored_state) =
+      restore(RestoreSelection::ProcessedJson, &preview.preview_id, true).expect("restore");
+    assert_eq!((result.restored, result.overwritten), (1, 1));
+    assert!(restored_state.is_none());
+    assert_eq!(
+      fs::read_to_string(root.join("processed/lidl/a.jpg.json")).expect("restored"),
+      "{\"total\": 1}"
+    );
+    let held = Path::new(result.holding_dir.as_deref().expect("holding"));
+    assert!(held.starts_with(root.join(RESTORE_HOLDING_DIR)));
+    assert_eq!(
+      fs::read_to_string(held.join("processed/lidl/a.jpg.json")).expect("held"),
+      "{\"total\": 2}"
+    );
+
+    let all =
+      preview_restore_from(&root, Some(&state), &archive, RestoreSelection::All).expect("preview");
+    assert_eq!(all.files.len(), 4);
+    let (result, restored_state) =
+      restore(RestoreSelection::All, &all.preview_id, false).expect("restore");
+    assert_eq!((result.restored, result.skipped), (1, 3));
+    assert!(root.join("_logs/runs/1.summary.json").exists());
+    assert!(restored_state.is_none());
+
+    let mut manifest = preview.manifest.clone();
+    manifest.app_version = "999.0.0".to_string();
+    let newer = dir.path().join("newer.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&newer).expect("zip"));
+    zip
+      .start_file(BACKUP_MANIFEST_NAME, zip::write::FileOptions::default())
+      .expect("entry");
+    zip
+      .write_all(serde_json::to_string(&manifest).expect("json").as_bytes())
+      .expect("write");
+    zip.finish().expect("finish");
+    assert!(matches!(open_backup(&newer), Err(AppError::Unsupported(_))));
+  }
+}
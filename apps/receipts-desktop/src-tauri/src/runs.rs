@@ -0,0 +1,505 @@
+use crate::*;
+
+pub(crate) type RunsSignature = (Option<std::time::SystemTime>, usize);
+
+// Issue runs keyed by the runs folder signature (mtime + entry count), so the
+// summaries are only re-read after a run lands.
+#[derive(Default)]
+pub(crate) struct IssueRunsCache(
+  pub(crate) Mutex<Option<(RunsSignature, HashMap<String, StoreIssueRuns>)>>,
+  pub(crate) CacheCounters,
+);
+
+// A run id ordered by when the run happened: the timestamp embedded in the id,
+// else the summary's `started_at`, else the summary file mtime. The raw id
+// breaks ties so the order is total.
+#[derive(Debug, Clone)]
+pub(crate) struct RunId {
+  pub(crate) id: String,
+  pub(crate) started: Option<chrono::DateTime<chrono::Utc>>,
+  pub(crate) modified: Option<std::time::SystemTime>,
+}
+
+impl RunId {
+  pub(crate) fn new(
+    id: &str,
+    started_at: Option<&str>,
+    modified: Option<std::time::SystemTime>,
+  ) -> RunId {
+    RunId {
+      id: id.to_string(),
+      started: parse_embedded_timestamp(id).or_else(|| started_at.and_then(parse_timestamp)),
+      modified,
+    }
+  }
+
+  pub(crate) fn sort_time(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+    self
+      .started
+      .or_else(|| self.modified.map(chrono::DateTime::<chrono::Utc>::from))
+  }
+}
+
+impl Ord for RunId {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    self
+      .sort_time()
+      .cmp(&other.sort_time())
+      .then_with(|| self.id.cmp(&other.id))
+  }
+}
+
+impl PartialOrd for RunId {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for RunId {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == std::cmp::Ordering::Equal
+  }
+}
+
+impl Eq for RunId {}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RunSummary {
+  pub(crate) run_id: RunId,
+  pub(crate) stores: Vec<String>,
+  pub(crate) failure_count: usize,
+  pub(crate) warning_count: usize,
+  pub(crate) processed_count: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StoreIssueRuns {
+  pub(crate) failures: Vec<RunId>,
+  pub(crate) warnings: Vec<RunId>,
+  pub(crate) latest_failure: Option<RunSummary>,
+  pub(crate) latest_warning: Option<RunSummary>,
+  // Runs whose summary reports how many receipts were processed.
+  pub(crate) processed: Vec<RunSummary>,
+}
+
+pub(crate) fn list_run_summaries(
+  receipts_root: &str,
+) -> Vec<(Value, Option<std::time::SystemTime>)> {
+  let runs_dir = Path::new(receipts_root).join("_logs").join("runs");
+  let mut summaries = Vec::new();
+  let entries = match fs::read_dir(runs_dir) {
+    Ok(entries) => entries,
+    Err(_) => return summaries,
+  };
+
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+      continue;
+    }
+    let is_summary = path
+      .file_name()
+      .is_some_and(|name| name.to_string_lossy().ends_with(".summary.json"));
+    if !is_summary {
+      continue;
+    }
+
+    if let Ok(raw) = fs::read_to_string(&path) {
+      if let Ok(value) = serde_json::from_str::<Value>(&raw) {
+        let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+        summaries.push((value, modified));
+      }
+    }
+  }
+
+  summaries
+}
+
+pub(crate) fn extract_run_id(value: &Value) -> Option<String> {
+  value
+    .get("run_id")
+    .and_then(|id| id.as_str())
+    .map(|id| id.to_string())
+}
+
+pub(crate) fn extract_stores(value: &Value) -> Vec<String> {
+  value
+    .get("stores")
+    .and_then(|stores| stores.as_array())
+    .map(|arr| {
+      arr
+        .iter()
+        .filter_map(|item| item.as_str().map(|s| s.to_string()))
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+pub(crate) fn extract_issue_count(value: &Value, key: &str) -> usize {
+  value
+    .get(key)
+    .and_then(|entries| entries.as_array())
+    .map(|arr| arr.len())
+    .unwrap_or(0)
+}
+
+// `processed_count` when the summary has one, else the length of a `processed`
+// array; None when the summary says nothing about processed receipts.
+pub(crate) fn extract_processed_count(value: &Value) -> Option<usize> {
+  if let Some(count) = value
+    .get("processed_count")
+    .and_then(|count| count.as_u64())
+  {
+    return Some(count as usize);
+  }
+  value
+    .get("processed")
+    .and_then(|entries| entries.as_array())
+    .map(|arr| arr.len())
+}
+
+pub(crate) fn local_to_utc(naive: chrono::NaiveDateTime) -> Option<chrono::DateTime<chrono::Utc>> {
+  use chrono::TimeZone;
+  chrono::Local
+    .from_local_datetime(&naive)
+    .earliest()
+    .map(|time| time.with_timezone(&chrono::Utc))
+}
+
+pub(crate) fn parse_timestamp(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+  let value = value.trim();
+  if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+    return Some(time.with_timezone(&chrono::Utc));
+  }
+  ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+    .iter()
+    .find_map(|format| chrono::NaiveDateTime::parse_from_str(value, format).ok())
+    .and_then(local_to_utc)
+}
+
+// Finds a `YYYYMMDD-HHMMSS` style timestamp (`-`, `_`, `T` or no separator)
+// anywhere in the id, e.g. `20240105-093000` or `run_20240105T093000_lidl`.
+pub(crate) fn parse_embedded_timestamp(id: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+  let bytes = id.as_bytes();
+  for start in 0..bytes.len() {
+    if !bytes[start].is_ascii_digit() || (start > 0 && bytes[start - 1].is_ascii_digit()) {
+      continue;
+    }
+    for (len, format) in [
+      (15, "%Y%m%d-%H%M%S"),
+      (15, "%Y%m%d_%H%M%S"),
+      (15, "%Y%m%dT%H%M%S"),
+      (14, "%Y%m%d%H%M%S"),
+    ] {
+      let candidate = match id.get(start..start + len) {
+        Some(candidate) => candidate,
+        None => continue,
+      };
+      if bytes
+        .get(start + len)
+        .map(u8::is_ascii_digit)
+        .unwrap_or(false)
+      {
+        continue;
+      }
+      if let Ok(naive) = chrono::NaiveDateTime::parse_from_str(candidate, format) {
+        return local_to_utc(naive);
+      }
+    }
+  }
+  None
+}
+
+pub(crate) fn load_run_summaries(receipts_root: &str) -> Vec<RunSummary> {
+  list_run_summaries(receipts_root)
+    .into_iter()
+    .filter_map(|(value, modified)| {
+      let started_at = value.get("started_at").and_then(|started| started.as_str());
+      Some(RunSummary {
+        run_id: RunId::new(&extract_run_id(&value)?, started_at, modified),
+        stores: extract_stores(&value),
+        failure_count: extract_issue_count(&value, "failures"),
+        warning_count: extract_issue_count(&value, "warnings"),
+        processed_count: extract_processed_count(&value),
+      })
+    })
+    .collect()
+}
+
+pub(crate) fn issue_runs_by_store(summaries: &[RunSummary]) -> HashMap<String, StoreIssueRuns> {
+  let mut map: HashMap<String, StoreIssueRuns> = HashMap::new();
+
+  for summary in summaries {
+    for store in &summary.stores {
+      let entry = map.entry(store.clone()).or_default();
+      if summary.processed_count.is_some()
+        && !entry
+          .processed
+          .iter()
+          .any(|run| run.run_id.id == summary.run_id.id)
+      {
+        entry.processed.push(summary.clone());
+      }
+      if summary.failure_count > 0 && !entry.failures.iter().any(|run| run.id == summary.run_id.id)
+      {
+        entry.failures.push(summary.run_id.clone());
+        if entry
+          .latest_failure
+          .as_ref()
+          .map(|latest| summary.run_id > latest.run_id)
+          .unwrap_or(true)
+        {
+          entry.latest_failure = Some(summary.clone());
+        }
+      }
+      if summary.warning_count > 0 && !entry.warnings.iter().any(|run| run.id == summary.run_id.id)
+      {
+        entry.warnings.push(summary.run_id.clone());
+        if entry
+          .latest_warning
+          .as_ref()
+          .map(|latest| summary.run_id > latest.run_id)
+          .unwrap_or(true)
+        {
+          entry.latest_warning = Some(summary.clone());
+        }
+      }
+    }
+  }
+
+  map
+}
+
+pub(crate) fn latest_run_id(runs: &[RunId]) -> Option<String> {
+  runs.iter().max().map(|run| run.id.clone())
+}
+
+// Newest summaries first, by summary file mtime.
+pub(crate) fn latest_run_summaries(config: &AppConfig, limit: usize) -> Vec<Value> {
+  let mut summaries = list_run_summaries(&config.receipts_root);
+  summaries.sort_by_key(|summary| std::cmp::Reverse(summary.1));
+  summaries
+    .into_iter()
+    .map(|(value, _)| value)
+    .take(limit)
+    .collect()
+}
+
+pub(crate) fn issue_runs_for(config: &AppConfig) -> HashMap<String, StoreIssueRuns> {
+  issue_runs_by_store(&load_run_summaries(&config.receipts_root))
+}
+
+pub(crate) fn runs_signature(config: &AppConfig) -> RunsSignature {
+  runs_signature_for(&config.receipts_root)
+}
+
+pub(crate) fn runs_signature_for(receipts_root: &str) -> RunsSignature {
+  let runs_dir = Path::new(receipts_root).join("_logs").join("runs");
+  (
+    fs::metadata(&runs_dir)
+      .and_then(|meta| meta.modified())
+      .ok(),
+    fs::read_dir(&runs_dir)
+      .map(|entries| entries.count())
+      .unwrap_or(0),
+  )
+}
+
+pub(crate) fn cached_issue_runs(
+  cache: &IssueRunsCache,
+  config: &AppConfig,
+) -> (HashMap<String, StoreIssueRuns>, bool) {
+  // The cache holds the configured root's runs only.
+  if config.root_override.is_some() {
+    return (issue_runs_for(config), false);
+  }
+  let signature = runs_signature(config);
+  if let Ok(cached) = cache.0.lock() {
+    if let Some((cached_signature, runs)) = cached.as_ref() {
+      if *cached_signature == signature {
+        cache.1.record(true);
+        return (runs.clone(), true);
+      }
+    }
+  }
+  cache.1.record(false);
+  let fresh = issue_runs_for(config);
+  if let Ok(mut cached) = cache.0.lock() {
+    *cached = Some((signature, fresh.clone()));
+  }
+  (fresh, false)
+}
+
+// Summaries are normally `<run_id>.summary.json`; otherwise the runs folder is
+// scanned for a summary carrying that run_id.
+pub(crate) fn find_run_summary(receipts_root: &str, run_id: &str) -> Option<(PathBuf, Value)> {
+  let runs_dir = Path::new(receipts_root).join("_logs").join("runs");
+  let direct = runs_dir.join(format!("{}.summary.json", run_id));
+  if let Some(value) = fs::read_to_string(&direct)
+    .ok()
+    .and_then(|raw| serde_json::from_str::<Value>(&raw).ok())
+  {
+    return Some((direct, value));
+  }
+  fs::read_dir(&runs_dir)
+    .ok()?
+    .flatten()
+    .map(|entry| entry.path())
+    .filter(|path| {
+      path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with(".summary.json"))
+    })
+    .find_map(|path| {
+      let value = serde_json::from_str::<Value>(&fs::read_to_string(&path).ok()?).ok()?;
+      (extract_run_id(&value).as_deref() == Some(run_id)).then_some((path, value))
+    })
+}
+
+// `artifacts.log_file` from the summary (relative paths are taken from the
+// runs folder), else `<run_id>.log` next to the summary.
+pub(crate) fn run_log_path(summary_path: &Path, summary: &Value, run_id: &str) -> PathBuf {
+  let runs_dir = summary_path.parent().unwrap_or(Path::new("."));
+  match summary
+    .get("artifacts")
+    .and_then(|artifacts| artifacts.get("log_file"))
+    .and_then(|log| log.as_str())
+  {
+    Some(log) => runs_dir.join(log),
+    None => runs_dir.join(format!("{}.log", run_id)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn run_ids_with_timestamps_order_by_time() {
+    let early = run_at("20240105-093000", 500);
+    let late = run_at("20240105-101500", 0);
+    let iso = run_at("run_20240106T080000_lidl", 0);
+    let compact = run_at("20231231235959", 900);
+    assert!(early < late);
+    assert!(late < iso);
+    assert!(compact < early);
+    assert_eq!(
+      latest_run_id(&[late.clone(), iso.clone(), early.clone()]),
+      Some(iso.id)
+    );
+  }
+
+  #[test]
+  fn run_ids_without_timestamps_use_started_at_then_mtime() {
+    let started = RunId::new("run_42", Some("2024-01-05T09:30:00Z"), None);
+    let later_started = RunId::new("run_7", Some("2024-01-05T10:00:00Z"), None);
+    assert!(started < later_started);
+    let first = run_at("run_9", 10);
+    let second = run_at("run_10", 20);
+    assert!(first < second);
+    assert_eq!(
+      run_at("run_a", 5).cmp(&run_at("run_b", 5)),
+      std::cmp::Ordering::Less
+    );
+  }
+
+  #[test]
+  fn mixed_run_id_formats_have_a_total_order() {
+    let stamped = run_at("20231114-000000", 0);
+    let numbered_before = run_at("run_1", 0);
+    let numbered_after = run_at("run_2", 10 * 86_400);
+    let mut runs = vec![
+      numbered_after.clone(),
+      stamped.clone(),
+      numbered_before.clone(),
+    ];
+    runs.sort();
+    let ids = runs.iter().map(|run| run.id.as_str()).collect::<Vec<_>>();
+    assert_eq!(ids[2], "run_2");
+    assert!(runs.windows(2).all(|pair| pair[0] <= pair[1]));
+    let mut reversed = runs.clone();
+    reversed.reverse();
+    reversed.sort();
+    assert_eq!(
+      reversed
+        .iter()
+        .map(|run| run.id.clone())
+        .collect::<Vec<_>>(),
+      runs.iter().map(|run| run.id.clone()).collect::<Vec<_>>()
+    );
+    assert_eq!(latest_run_id(&runs), Some("run_2".to_string()));
+  }
+
+  #[test]
+  fn run_artifacts_resolve_by_run_id() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let runs = dir.path().join("_logs").join("runs");
+    fs::create_dir_all(&runs).expect("runs dir");
+    fs::write(
+      runs.join("20240105-093000.summary.json"),
+      r#"{"run_id": "20240105-093000", "artifacts": {"log_file": "20240105-093000.worker.log"}}"#,
+    )
+    .expect("summary");
+    fs::write(
+      runs.join("renamed.summary.json"),
+      r#"{"run_id": "20240106-100000"}"#,
+    )
+    .expect("summary");
+    let root = dir.path().to_string_lossy().to_string();
+
+    let (path, summary) = find_run_summary(&root, "20240105-093000").expect("direct summary");
+    assert_eq!(
+      run_log_path(&path, &summary, "20240105-093000"),
+      runs.join("20240105-093000.worker.log")
+    );
+    let (path, summary) = find_run_summary(&root, "20240106-100000").expect("scanned summary");
+    assert_eq!(path, runs.join("renamed.summary.json"));
+    assert_eq!(
+      run_log_path(&path, &summary, "20240106-100000"),
+      runs.join("20240106-100000.log")
+    );
+    assert!(find_run_summary(&root, "20240107-000000").is_none());
+  }
+
+  #[test]
+  fn summary_listing_reads_only_parseable_summaries() {
+    let dir = runs_fixture(&[
+      (
+        "20240101-093000.summary.json",
+        r#"{"run_id": "20240101-093000", "stores": ["lidl"], "failures": [{}]}"#,
+      ),
+      (
+        "20240102-093000.summary.json",
+        r#"{"run_id": "20240102-093000", "stores": ["lidl"], "processed": [{}, {}]}"#,
+      ),
+      ("no-id.summary.json", r#"{"stores": ["lidl"]}"#),
+      ("broken.summary.json", "{"),
+      ("notes.json", r#"{"run_id": "notes"}"#),
+      (
+        "20240103-093000.summary.txt",
+        r#"{"run_id": "20240103-093000"}"#,
+      ),
+    ]);
+    let root = dir.path().to_string_lossy().to_string();
+
+    assert_eq!(list_run_summaries(&root).len(), 3);
+    let mut summaries = load_run_summaries(&root);
+    summaries.sort_by(|a, b| a.run_id.cmp(&b.run_id));
+    assert_eq!(
+      summaries
+        .iter()
+        .map(|summary| (
+          summary.run_id.id.as_str(),
+          summary.failure_count,
+          summary.processed_count
+        ))
+        .collect::<Vec<_>>(),
+      vec![
+        ("20240101-093000", 1, None),
+        ("20240102-093000", 0, Some(2))
+      ]
+    );
+    assert!(list_run_summaries(&dir.path().join("missing").to_string_lossy()).is_empty());
+  }
+}
@@ -0,0 +1,251 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct DashboardSnapshot {
+  pub(crate) inbox_counts: Vec<InboxCount>,
+  pub(crate) badges: Vec<UnreadBadge>,
+  pub(crate) last_runs: Vec<Value>,
+  pub(crate) generated_at: String,
+  // Served from before RECEIPTS_ROOT stopped answering (see `dashboard_snapshot`).
+  pub(crate) stale: bool,
+  // Folders of enabled stores that exist but can't be read (see `access_warning`).
+  pub(crate) access_warning: Option<AccessWarning>,
+  #[serde(skip)]
+  pub(crate) generated: Instant,
+}
+
+impl DashboardSnapshot {
+  pub(crate) fn into_stale(mut self) -> DashboardSnapshot {
+    self.stale = true;
+    for count in &mut self.inbox_counts {
+      count.stale = true;
+    }
+    for badge in &mut self.badges {
+      badge.stale = true;
+    }
+    self
+  }
+}
+
+#[derive(Default)]
+pub(crate) struct DashboardScanner {
+  pub(crate) snapshot: Mutex<Option<DashboardSnapshot>>,
+  // Set by triggers until the next scan starts, so a burst of them costs one scan.
+  pub(crate) requested: Mutex<bool>,
+  pub(crate) wake: Condvar,
+  // Held for the length of a scan; the loop and on-demand rescans never overlap.
+  pub(crate) scanning: Mutex<()>,
+  // Consecutive scans that found receipts_root unreachable, and when the next
+  // one may run.
+  pub(crate) backoff: Mutex<(u32, Option<Instant>)>,
+}
+
+impl DashboardScanner {
+  pub(crate) fn request(&self) {
+    if let Ok(mut requested) = self.requested.lock() {
+      *requested = true;
+      self.wake.notify_one();
+    }
+  }
+
+  // Waits up to `timeout` for a request; true when one came in.
+  pub(crate) fn wait_for_request(&self, timeout: Duration) -> bool {
+    let requested = match self.requested.lock() {
+      Ok(requested) => requested,
+      Err(_) => return false,
+    };
+    self
+      .wake
+      .wait_timeout_while(requested, timeout, |requested| !*requested)
+      .map(|(requested, _)| *requested)
+      .unwrap_or(false)
+  }
+
+  pub(crate) fn take_request(&self) -> bool {
+    self
+      .requested
+      .lock()
+      .map(|mut requested| std::mem::take(&mut *requested))
+      .unwrap_or(false)
+  }
+
+  pub(crate) fn is_pending(&self) -> bool {
+    self
+      .requested
+      .lock()
+      .map(|requested| *requested)
+      .unwrap_or(false)
+  }
+
+  // Time left before an unreachable root may be tried again.
+  pub(crate) fn retry_in(&self) -> Option<Duration> {
+    let backoff = self.backoff.lock().ok()?;
+    backoff
+      .1
+      .and_then(|retry_at| retry_at.checked_duration_since(Instant::now()))
+  }
+
+  pub(crate) fn record_unreachable(&self) -> Duration {
+    let mut backoff = match self.backoff.lock() {
+      Ok(backoff) => backoff,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+    backoff.0 += 1;
+    let delay = scan_backoff(backoff.0);
+    backoff.1 = Some(Instant::now() + delay);
+    delay
+  }
+
+  pub(crate) fn record_reachable(&self) {
+    if let Ok(mut backoff) = self.backoff.lock() {
+      *backoff = (0, None);
+    }
+  }
+
+  pub(crate) fn latest(&self) -> Option<DashboardSnapshot> {
+    self
+      .snapshot
+      .lock()
+      .ok()
+      .and_then(|snapshot| snapshot.clone())
+  }
+}
+
+// Doubles from DASHBOARD_BACKOFF_START per consecutive failure, capped at
+// DASHBOARD_BACKOFF_MAX.
+pub(crate) fn scan_backoff(failures: u32) -> Duration {
+  DASHBOARD_BACKOFF_START
+    .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+    .min(DASHBOARD_BACKOFF_MAX)
+}
+
+// Rebuilds the snapshot and emits `snapshot-updated`. The root is probed first
+// (see `probe_receipts_root`); while it is unreachable this fails fast until
+// the backoff delay has passed, without touching the (possibly hung) mount.
+pub(crate) fn scan_dashboard(
+  app: &tauri::AppHandle,
+  force_refresh: bool,
+) -> Result<DashboardSnapshot, AppError> {
+  let scanner = app.state::<DashboardScanner>();
+  let _scanning = scanner.scanning.lock().map_err(|err| err.to_string())?;
+  let config = current_config(app);
+  if scanner.retry_in().is_some() {
+    return Err(AppError::RootUnreachable(config.receipts_root));
+  }
+  scanner.take_request();
+  probe_receipts_root(app, &config)?;
+
+  let inbox_counts = inbox_counts(app, &config, force_refresh)?;
+  let (issue_runs, _) = cached_issue_runs(&app.state::<IssueRunsCache>(), &config);
+  let badges = {
+    let seen_state = app.state::<SeenStateStore>();
+    let state = seen_state.0.lock().map_err(|err| err.to_string())?;
+    unread_badges(&config, &issue_runs, &state)
+  };
+  refresh_tray_badge(app, &config, &badges);
+  let snapshot = DashboardSnapshot {
+    inbox_counts,
+    badges,
+    last_runs: latest_runs(app, &config, DASHBOARD_LAST_RUNS),
+    generated_at: chrono::Utc::now().to_rfc3339(),
+    stale: false,
+    access_warning: access_warning(&config),
+    generated: Instant::now(),
+  };
+  if let Ok(mut latest) = scanner.snapshot.lock() {
+    *latest = Some(snapshot.clone());
+  }
+  let _ = app.emit_all("snapshot-updated", &snapshot);
+  Ok(snapshot)
+}
+
+// Serves the latest snapshot, rescanning first when there is none yet, a
+// trigger is pending, it is older than `max_age` seconds, or `force_refresh`
+// is set. While the root is unreachable the last good one comes back right
+// away, flagged `stale`, whatever was asked for.
+pub(crate) fn dashboard_snapshot(
+  app: &tauri::AppHandle,
+  max_age: Option<u64>,
+  force_refresh: bool,
+) -> Result<DashboardSnapshot, AppError> {
+  let scanner = app.state::<DashboardScanner>();
+  let latest = scanner.latest();
+  let fresh = latest.as_ref().filter(|snapshot| {
+    !force_refresh
+      && !scanner.is_pending()
+      && max_age.is_none_or(|secs| snapshot.generated.elapsed() < Duration::from_secs(secs))
+  });
+  if let Some(snapshot) = fresh {
+    return Ok(snapshot.clone());
+  }
+  match scan_dashboard(app, force_refresh) {
+    Ok(snapshot) => Ok(snapshot),
+    Err(AppError::RootUnreachable(root)) => match latest {
+      Some(snapshot) => Ok(snapshot.into_stale()),
+      None => Err(AppError::RootUnreachable(root)),
+    },
+    // Without a freshness requirement, the last good snapshot beats an error.
+    Err(err) => match latest {
+      Some(snapshot) if max_age.is_none() && !force_refresh => Ok(snapshot),
+      _ => Err(err),
+    },
+  }
+}
+
+// Scans every DASHBOARD_SCAN_INTERVAL or as soon as a trigger comes in. While
+// backing off from an unreachable root it sleeps out the delay instead, and
+// triggers from that time are served by the next scan, whose probe is the
+// retry that notices the root coming back. The first scan is
+// startup's (see `startup_scan`).
+pub(crate) fn run_dashboard_scanner(app: tauri::AppHandle) {
+  let scanner = app.state::<DashboardScanner>();
+  startup_scan(&app);
+  loop {
+    match scanner.retry_in() {
+      Some(delay) => thread::sleep(delay),
+      None => {
+        scanner.wait_for_request(DASHBOARD_SCAN_INTERVAL);
+      }
+    }
+    if let Err(err) = scan_dashboard(&app, false) {
+      debug!("dashboard scan failed: {}", err);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dashboard_scanner_coalesces_requests_and_backs_off() {
+    let scanner = DashboardScanner::default();
+    assert!(!scanner.wait_for_request(Duration::from_millis(10)));
+    for _ in 0..3 {
+      scanner.request();
+    }
+    assert!(scanner.wait_for_request(Duration::from_millis(10)));
+    assert!(scanner.take_request());
+    assert!(!scanner.is_pending());
+    assert!(!scanner.wait_for_request(Duration::from_millis(10)));
+
+    let waiter = Arc::new(DashboardScanner::default());
+    let handle = {
+      let waiter = waiter.clone();
+      thread::spawn(move || waiter.wait_for_request(Duration::from_secs(10)))
+    };
+    thread::sleep(Duration::from_millis(20));
+    waiter.request();
+    assert!(handle.join().unwrap());
+
+    assert_eq!(scan_backoff(1), DASHBOARD_BACKOFF_START);
+    assert_eq!(scan_backoff(3), DASHBOARD_BACKOFF_START * 4);
+    assert_eq!(scan_backoff(40), DASHBOARD_BACKOFF_MAX);
+    assert!(scanner.retry_in().is_none());
+    assert_eq!(scanner.record_unreachable(), DASHBOARD_BACKOFF_START);
+    assert_eq!(scanner.record_unreachable(), DASHBOARD_BACKOFF_START * 2);
+    assert!(scanner.retry_in().is_some());
+    scanner.record_reachable();
+    assert!(scanner.retry_in().is_none());
+  }
+}
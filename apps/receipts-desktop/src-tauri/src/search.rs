@@ -0,0 +1,442 @@
+use crate::*;
+use rusqlite::{params, Connection};
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ReceiptMatch {
+  #[serde(flatten)]
+  pub(crate) receipt: ReceiptTotal,
+  pub(crate) merchant: Option<String>,
+  // Text around the first matched word; None when the search had no query.
+  pub(crate) snippet: Option<String>,
+  // The image/PDF when it is still there, else the JSON output.
+  pub(crate) path: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ReceiptSearch {
+  pub(crate) matches: Vec<ReceiptMatch>,
+  // `index` (the run index's full-text table) or `scan` (every output read).
+  pub(crate) source: &'static str,
+  // More receipts matched than `limit`.
+  pub(crate) truncated: bool,
+  // The scan ran out of time; receipts it didn't reach are missing.
+  pub(crate) timed_out: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ReceiptFilter {
+  // Folded words; each has to start a word in the receipt's text.
+  pub(crate) terms: Vec<String>,
+  pub(crate) from: Option<chrono::NaiveDate>,
+  pub(crate) to: Option<chrono::NaiveDate>,
+  pub(crate) min_total: Option<f64>,
+  pub(crate) max_total: Option<f64>,
+}
+
+impl ReceiptFilter {
+  // Receipts without a date fail a date bound and without a total an amount
+  // bound.
+  pub(crate) fn accepts(&self, receipt: &ReceiptTotal) -> bool {
+    let dated = (self.from.is_none() && self.to.is_none())
+      || receipt.date.is_some_and(|date| {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+      });
+    let priced = (self.min_total.is_none() && self.max_total.is_none())
+      || receipt.total.is_some_and(|total| {
+        !self.min_total.is_some_and(|min| total < min)
+          && !self.max_total.is_some_and(|max| total > max)
+      });
+    dated && priced
+  }
+}
+
+// Lowercases and strips the diacritics common in Romanian (and neighbouring)
+// product names, one char for one char so positions carry over to the
+// original text.
+pub(crate) fn fold_char(c: char) -> char {
+  match c.to_lowercase().next().unwrap_or(c) {
+    'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ă' | 'ą' | 'ā' => 'a',
+    'ç' | 'ć' | 'č' => 'c',
+    'ď' | 'đ' => 'd',
+    'è' | 'é' | 'ê' | 'ë' | 'ě' | 'ę' | 'ē' => 'e',
+    'ì' | 'í' | 'î' | 'ï' | 'ī' => 'i',
+    'ł' | 'ľ' => 'l',
+    'ñ' | 'ń' | 'ň' => 'n',
+    'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ő' | 'ō' => 'o',
+    'ř' => 'r',
+    'ș' | 'ş' | 'ś' | 'š' => 's',
+    'ț' | 'ţ' | 'ť' => 't',
+    'ù' | 'ú' | 'û' | 'ü' | 'ů' | 'ű' | 'ū' => 'u',
+    'ý' | 'ÿ' => 'y',
+    'ź' | 'ż' | 'ž' => 'z',
+    folded => folded,
+  }
+}
+
+pub(crate) fn fold_text(text: &str) -> String {
+  text.chars().map(fold_char).collect()
+}
+
+pub(crate) fn search_terms(query: &str) -> Vec<String> {
+  fold_text(query)
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|term| !term.is_empty())
+    .map(str::to_string)
+    .collect()
+}
+
+// Every term as a quoted prefix query; terms are alphanumeric, so there is
+// nothing to escape.
+pub(crate) fn fts_query(terms: &[String]) -> String {
+  terms
+    .iter()
+    .map(|term| format!("\"{}\"*", term))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+// The char position where `term` starts a word in `folded`.
+pub(crate) fn find_term(folded: &[char], term: &str) -> Option<usize> {
+  let term = term.chars().collect::<Vec<_>>();
+  if term.is_empty() || term.len() > folded.len() {
+    return None;
+  }
+  (0..=folded.len() - term.len()).find(|&start| {
+    (start == 0 || !folded[start - 1].is_alphanumeric())
+      && folded[start..start + term.len()] == term[..]
+  })
+}
+
+// Where each term first matches, None when one doesn't match at all.
+pub(crate) fn term_positions(text: &str, terms: &[String]) -> Option<Vec<usize>> {
+  let folded = text.chars().map(fold_char).collect::<Vec<_>>();
+  terms.iter().map(|term| find_term(&folded, term)).collect()
+}
+
+// About RECEIPT_SEARCH_SNIPPET_CHARS of `text` around char `position`, on
+// one line.
+pub(crate) fn snippet_at(text: &str, position: usize) -> String {
+  let chars = text.chars().collect::<Vec<_>>();
+  let half = RECEIPT_SEARCH_SNIPPET_CHARS / 2;
+  let start = position.saturating_sub(half);
+  let end = (start + RECEIPT_SEARCH_SNIPPET_CHARS).min(chars.len());
+  let body = chars[start..end]
+    .iter()
+    .collect::<String>()
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ");
+  format!(
+    "{}{}{}",
+    if start > 0 { "…" } else { "" },
+    body,
+    if end < chars.len() { "…" } else { "" }
+  )
+}
+
+// The merchant name, then the item names, then the OCR text.
+pub(crate) fn receipt_search_text(value: &Value) -> (Option<String>, String) {
+  let merchant = value
+    .get("merchant")
+    .and_then(|merchant| merchant.get("name"))
+    .and_then(|name| name.as_str())
+    .map(str::to_string);
+  let mut lines = merchant.iter().cloned().collect::<Vec<_>>();
+  if let Some(items) = value.get("items").and_then(|items| items.as_array()) {
+    lines.extend(
+      items
+        .iter()
+        .filter_map(|item| item.get("name").and_then(|name| name.as_str()))
+        .map(str::to_string),
+    );
+  }
+  if let Some(raw_text) = value.get("raw_text").and_then(|text| text.as_str()) {
+    lines.push(raw_text.to_string());
+  }
+  (merchant, lines.join("\n"))
+}
+
+pub(crate) fn receipt_match(
+  receipts_root: &str,
+  receipt: ReceiptTotal,
+  merchant: Option<String>,
+  text: &str,
+  terms: &[String],
+) -> Option<ReceiptMatch> {
+  let positions = term_positions(text, terms)?;
+  let dir = processed_dir(receipts_root, &receipt.store_id);
+  let image = dir.join(receipt_image_name(&receipt.file_name));
+  let path = if image.is_file() {
+    image
+  } else {
+    join_file_id(&dir, &receipt.file_name)
+  };
+  Some(ReceiptMatch {
+    snippet: positions
+      .into_iter()
+      .min()
+      .map(|position| snippet_at(text, position)),
+    path: path.to_string_lossy().to_string(),
+    merchant,
+    receipt,
+  })
+}
+
+// Newest receipt first; undated ones last.
+pub(crate) fn finish_search(
+  mut matches: Vec<ReceiptMatch>,
+  source: &'static str,
+  limit: usize,
+  timed_out: bool,
+) -> ReceiptSearch {
+  matches.sort_by(|a, b| {
+    b.receipt
+      .date
+      .cmp(&a.receipt.date)
+      .then_with(|| a.receipt.store_id.cmp(&b.receipt.store_id))
+      .then_with(|| a.receipt.file_name.cmp(&b.receipt.file_name))
+  });
+  let truncated = matches.len() > limit;
+  matches.truncate(limit);
+  ReceiptSearch {
+    matches,
+    source,
+    truncated,
+    timed_out,
+  }
+}
+
+// Reads every output under processed/ until `budget` runs out.
+pub(crate) fn scan_receipts(
+  receipts_root: &str,
+  store_ids: &[String],
+  filter: &ReceiptFilter,
+  limit: usize,
+  budget: Duration,
+) -> ReceiptSearch {
+  let started = Instant::now();
+  let mut matches = Vec::new();
+  let mut timed_out = false;
+  'stores: for store_id in store_ids {
+    let dir = processed_dir(receipts_root, store_id);
+    let mut names = receipt_output_names(&dir);
+    names.sort();
+    for name in names {
+      if started.elapsed() > budget {
+        timed_out = true;
+        break 'stores;
+      }
+      let path = join_file_id(&dir, &name);
+      let value = match fs::read_to_string(&path) {
+        Ok(raw) => serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null),
+        Err(_) => continue,
+      };
+      let receipt = match receipt_total_from(store_id, &path, &value) {
+        Some(receipt) if filter.accepts(&receipt) => receipt,
+        _ => continue,
+      };
+      let (merchant, text) = receipt_search_text(&value);
+      matches.extend(receipt_match(
+        receipts_root,
+        receipt,
+        merchant,
+        &text,
+        &filter.terms,
+      ));
+    }
+  }
+  finish_search(matches, "scan", limit, timed_out)
+}
+
+// Syncs the index's receipt tables first, then lets the full-text table pick
+// the candidates.
+pub(crate) fn indexed_search(
+  conn: &mut Connection,
+  receipts_root: &str,
+  store_ids: &[String],
+  filter: &ReceiptFilter,
+  limit: usize,
+) -> rusqlite::Result<ReceiptSearch> {
+  indexed_receipt_totals(conn, receipts_root, store_ids)?;
+  let mut statement = if filter.terms.is_empty() {
+    conn.prepare(
+      "SELECT t.file_name, t.date, t.date_estimated, t.total, t.currency, s.merchant, s.text
+       FROM receipt_totals t
+       LEFT JOIN receipt_search s ON s.store_id = t.store_id AND s.file_name = t.file_name
+       WHERE t.store_id = ?1",
+    )?
+  } else {
+    conn.prepare(
+      "SELECT t.file_name, t.date, t.date_estimated, t.total, t.currency,
+         receipt_search.merchant, receipt_search.text
+       FROM receipt_search
+       JOIN receipt_totals t
+         ON t.store_id = receipt_search.store_id AND t.file_name = receipt_search.file_name
+       WHERE t.store_id = ?1 AND receipt_search MATCH ?2",
+    )?
+  };
+  let query = fts_query(&filter.terms);
+  let mut matches = Vec::new();
+  for store_id in store_ids {
+    let mut rows = if filter.terms.is_empty() {
+      statement.query(params![store_id])?
+    } else {
+      statement.query(params![store_id, query])?
+    };
+    while let Some(row) = rows.next()? {
+      let receipt = ReceiptTotal {
+        store_id: store_id.clone(),
+        file_name: row.get(0)?,
+        date: row
+          .get::<_, Option<String>>(1)?
+          .and_then(|date| chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()),
+        date_estimated: row.get(2)?,
+        total: row.get(3)?,
+        currency: row.get(4)?,
+      };
+      let merchant = row.get::<_, Option<String>>(5)?;
+      let text = row.get::<_, Option<String>>(6)?.unwrap_or_default();
+      if filter.accepts(&receipt) {
+        matches.extend(receipt_match(
+          receipts_root,
+          receipt,
+          merchant,
+          &text,
+          &filter.terms,
+        ));
+      }
+    }
+  }
+  Ok(finish_search(matches, "index", limit, false))
+}
+
+// The index when its full-text table exists, a time-boxed scan otherwise.
+pub(crate) fn search_receipts_in(
+  app: &tauri::AppHandle,
+  receipts_root: &str,
+  store_ids: &[String],
+  filter: &ReceiptFilter,
+  limit: usize,
+) -> ReceiptSearch {
+  let index = app.state::<RunIndex>();
+  if let Ok(mut conn) = index.0.lock() {
+    if let Some(conn) = conn.as_mut().filter(|conn| has_receipt_search(conn)) {
+      match indexed_search(conn, receipts_root, store_ids, filter, limit) {
+        Ok(search) => return search,
+        Err(err) => warn!("receipt search index failed; scanning processed/: {}", err),
+      }
+    }
+  }
+  scan_receipts(
+    receipts_root,
+    store_ids,
+    filter,
+    limit,
+    RECEIPT_SEARCH_SCAN_BUDGET,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn receipt_search_folds_diacritics_and_agrees_between_scan_and_index() {
+    assert_eq!(fold_text("Pâine ȘOȘ Țuică"), "paine sos tuica");
+    assert_eq!(search_terms("  air-FRYER, "), vec!["air", "fryer"]);
+    assert_eq!(
+      fts_query(&search_terms("ulei măsline")),
+      "\"ulei\"* \"masline\"*"
+    );
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(&processed).expect("processed");
+    fs::write(processed.join("a.jpg"), b"jpg").expect("image");
+    fs::write(
+      processed.join("a.jpg.json"),
+      r#"{"timestamp": "2024-01-05T10:00:00", "total": 449.99, "merchant": {"name": "Lidl"},
+        "items": [{"name": "Friteuză cu aer Silvercrest"}], "raw_text": "LIDL\nTOTAL 449,99"}"#,
+    )
+    .expect("fryer");
+    fs::write(
+      processed.join("b.jpg.json"),
+      r#"{"timestamp": "2024-02-10T10:00:00", "total": 12.5, "merchant": {"name": "Lidl"},
+        "items": [{"name": "Pâine albă"}, {"name": "Ulei de măsline"}]}"#,
+    )
+    .expect("bread");
+    fs::write(
+      processed.join("c.jpg.json"),
+      r#"{"timestamp": "2024-03-01T10:00:00", "total": null, "items": [{"name": "Paine"}]}"#,
+    )
+    .expect("no total");
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string()];
+    let filter = |query: &str| ReceiptFilter {
+      terms: search_terms(query),
+      ..ReceiptFilter::default()
+    };
+    let budget = Duration::from_secs(60);
+    let mut conn = rusqlite::Connection::open_in_memory().expect("index");
+    prepare_index(&conn).expect("schema");
+    assert!(has_receipt_search(&conn));
+
+    let scanned = scan_receipts(&root, &stores, &filter("FRITEUZA"), 10, budget);
+    assert_eq!(scanned.matches.len(), 1);
+    let fryer = &scanned.matches[0];
+    assert!(fryer.path.ends_with("a.jpg"));
+    assert_eq!(
+      fryer.snippet.as_deref(),
+      Some("Lidl Friteuză cu aer Silvercrest LIDL TOTAL 449,99")
+    );
+    let indexed = indexed_search(&mut conn, &root, &stores, &filter("FRITEUZA"), 10).expect("fts");
+    assert_eq!(
+      (indexed.source, &indexed.matches),
+      ("index", &scanned.matches)
+    );
+
+    let bread = filter("pain");
+    let scanned = scan_receipts(&root, &stores, &bread, 10, budget);
+    let names = |search: &ReceiptSearch| {
+      search
+        .matches
+        .iter()
+        .map(|found| found.receipt.file_name.clone())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&scanned), vec!["c.jpg.json", "b.jpg.json"]);
+    assert!(scanned.matches[1].path.ends_with("b.jpg.json"));
+    let indexed = indexed_search(&mut conn, &root, &stores, &bread, 10).expect("fts");
+    assert_eq!(indexed.matches, scanned.matches);
+    // A word has to start with the term.
+    assert!(scan_receipts(&root, &stores, &filter("aine"), 10, budget)
+      .matches
+      .is_empty());
+
+    let priced = ReceiptFilter {
+      min_total: Some(10.0),
+      max_total: Some(100.0),
+      ..bread.clone()
+    };
+    let indexed = indexed_search(&mut conn, &root, &stores, &priced, 10).expect("fts");
+    assert_eq!(names(&indexed), vec!["b.jpg.json"]);
+    let dated = ReceiptFilter {
+      to: chrono::NaiveDate::from_ymd_opt(2024, 2, 1),
+      ..filter("")
+    };
+    assert_eq!(
+      names(&scan_receipts(&root, &stores, &dated, 10, budget)),
+      vec!["a.jpg.json"]
+    );
+
+    let limited = indexed_search(&mut conn, &root, &stores, &filter(""), 2).expect("fts");
+    assert_eq!(names(&limited), vec!["c.jpg.json", "b.jpg.json"]);
+    assert!(limited.truncated && limited.matches[0].snippet.is_none());
+    let timed_out = scan_receipts(&root, &stores, &filter(""), 10, Duration::ZERO);
+    assert!(timed_out.timed_out && timed_out.matches.is_empty());
+
+    fs::remove_file(processed.join("b.jpg.json")).expect("remove");
+    let indexed = indexed_search(&mut conn, &root, &stores, &bread, 10).expect("fts");
+    assert_eq!(names(&indexed), vec!["c.jpg.json"]);
+  }
+}
@@ -0,0 +1,344 @@
+use crate::*;
+use tauri::GlobalShortcutManager;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ShortcutBinding {
+  pub(crate) action: String,
+  pub(crate) accelerator: Option<String>,
+  pub(crate) registered: bool,
+  // Why the accelerator couldn't be registered (usually: taken by the OS or
+  // another app).
+  pub(crate) error: Option<String>,
+}
+
+// What is registered with the OS right now, per action, so a sync only
+// touches the bindings that changed and never registers one twice.
+#[derive(Default)]
+pub(crate) struct ShortcutRegistry(pub(crate) Mutex<HashMap<String, ShortcutBinding>>);
+
+const MODIFIERS: [&str; 13] = [
+  "ctrl",
+  "control",
+  "shift",
+  "alt",
+  "option",
+  "cmd",
+  "command",
+  "super",
+  "cmdorctrl",
+  "cmdorcontrol",
+  "commandorctrl",
+  "commandorcontrol",
+  "meta",
+];
+
+// `Ctrl + Shift + r` -> `Ctrl+Shift+R`. At least one modifier is required so a
+// system-wide binding can't swallow plain typing; Tauri checks the key itself
+// when registering.
+pub(crate) fn normalize_accelerator(raw: &str) -> Result<String, AppError> {
+  let parts = raw.split('+').map(str::trim).collect::<Vec<_>>();
+  let invalid =
+    |reason: &str| AppError::InvalidInput(format!("Invalid shortcut {:?}: {}", raw.trim(), reason));
+  if raw.len() > 64 || parts.iter().any(|part| part.is_empty()) {
+    return Err(invalid("expected modifiers and a key joined by +"));
+  }
+  let (key, modifiers) = parts.split_last().ok_or_else(|| invalid("empty"))?;
+  if modifiers.is_empty() {
+    return Err(invalid("needs at least one modifier"));
+  }
+  if MODIFIERS.contains(&key.to_lowercase().as_str()) {
+    return Err(invalid("the last part has to be a key"));
+  }
+  if let Some(unknown) = modifiers
+    .iter()
+    .find(|part| !MODIFIERS.contains(&part.to_lowercase().as_str()))
+  {
+    return Err(invalid(&format!("unknown modifier {}", unknown)));
+  }
+  let key = if key.chars().count() == 1 {
+    key.to_uppercase()
+  } else {
+    key.to_string()
+  };
+  Ok(
+    modifiers
+      .iter()
+      .map(|part| part.to_string())
+      .chain(std::iter::once(key))
+      .collect::<Vec<_>>()
+      .join("+"),
+  )
+}
+
+// The stored bindings over the defaults; a null in prefs unbinds an action.
+pub(crate) fn shortcut_bindings(
+  prefs: &HashMap<String, Value>,
+) -> std::collections::BTreeMap<String, Option<String>> {
+  let stored = prefs
+    .get(PREF_SHORTCUTS)
+    .and_then(|value| value.as_object());
+  SHORTCUT_ACTIONS
+    .iter()
+    .map(|action| {
+      let accelerator = match stored.and_then(|stored| stored.get(*action)) {
+        Some(value) => value.as_str().map(str::to_string),
+        None => DEFAULT_SHORTCUTS
+          .iter()
+          .find(|(default, _)| default == action)
+          .map(|(_, accelerator)| accelerator.to_string()),
+      };
+      (action.to_string(), accelerator)
+    })
+    .collect()
+}
+
+// For `validate_pref`: known actions, valid accelerators, none bound twice.
+pub(crate) fn validate_shortcut_pref(value: &Value) -> Result<(), String> {
+  let stored = value
+    .as_object()
+    .ok_or("Invalid shortcuts (expected {\"run_all\": \"Ctrl+Shift+R\"})")?;
+  let mut seen = Vec::new();
+  for (action, accelerator) in stored {
+    if !SHORTCUT_ACTIONS.contains(&action.as_str()) {
+      return Err(format!("Unknown shortcut action: {}", action));
+    }
+    let accelerator = match accelerator {
+      Value::Null => continue,
+      Value::String(accelerator) => {
+        normalize_accelerator(accelerator).map_err(|err| err.message())?
+      }
+      other => return Err(format!("Invalid shortcut for {}: {}", action, other)),
+    };
+    if seen.contains(&accelerator.to_lowercase()) {
+      return Err(format!("{} is bound twice", accelerator));
+    }
+    seen.push(accelerator.to_lowercase());
+  }
+  Ok(())
+}
+
+// Brings the OS registrations in line with prefs: bindings that changed are
+// unregistered and registered again, the rest are left alone. Safe to call
+// after any config or prefs change. Returns every action's binding.
+pub(crate) fn sync_shortcuts(app: &tauri::AppHandle) -> Vec<ShortcutBinding> {
+  let wanted = match app.state::<PrefsStore>().prefs.lock() {
+    Ok(prefs) => shortcut_bindings(&prefs),
+    Err(_) => return Vec::new(),
+  };
+  let registry = app.state::<ShortcutRegistry>();
+  let mut registry = match registry.0.lock() {
+    Ok(registry) => registry,
+    Err(_) => return Vec::new(),
+  };
+  let mut manager = app.global_shortcut_manager();
+  for (action, accelerator) in wanted {
+    let current = registry.get(&action);
+    if current.is_some_and(|current| current.accelerator == accelerator && current.registered) {
+      continue;
+    }
+    if let Some(old) = current
+      .filter(|current| current.registered)
+      .and_then(|current| current.accelerator.clone())
+    {
+      if let Err(err) = manager.unregister(&old) {
+        warn!("could not unregister shortcut {}: {}", old, err);
+      }
+    }
+    let mut binding = ShortcutBinding {
+      action: action.clone(),
+      accelerator: accelerator.clone(),
+      registered: false,
+      error: None,
+    };
+    if let Some(accelerator) = accelerator {
+      let handle = app.clone();
+      let name = action.clone();
+      match manager.register(&accelerator, move || trigger_shortcut(&handle, &name)) {
+        Ok(()) => binding.registered = true,
+        Err(err) => {
+          warn!("shortcut {} for {}: {}", accelerator, action, err);
+          binding.error = Some(err.to_string());
+        }
+      }
+    }
+    registry.insert(action, binding);
+  }
+  drop(registry);
+  registered_shortcuts(app)
+}
+
+pub(crate) fn registered_shortcuts(app: &tauri::AppHandle) -> Vec<ShortcutBinding> {
+  let registry = app.state::<ShortcutRegistry>();
+  let registry = match registry.0.lock() {
+    Ok(registry) => registry,
+    Err(_) => return Vec::new(),
+  };
+  SHORTCUT_ACTIONS
+    .iter()
+    .filter_map(|action| registry.get(*action).cloned())
+    .collect()
+}
+
+// Sets (or with None, removes) one action's entry in the stored bindings and
+// returns the entry it replaced.
+pub(crate) fn store_shortcut(
+  prefs: &mut HashMap<String, Value>,
+  action: &str,
+  accelerator: Option<Value>,
+) -> Option<Value> {
+  let stored = prefs
+    .entry(PREF_SHORTCUTS.to_string())
+    .or_insert_with(|| Value::Object(Default::default()));
+  if !stored.is_object() {
+    *stored = Value::Object(Default::default());
+  }
+  let stored = stored.as_object_mut()?;
+  match accelerator {
+    Some(accelerator) => stored.insert(action.to_string(), accelerator),
+    None => stored.remove(action),
+  }
+}
+
+// Runs on the main thread, so anything slow goes to a thread of its own.
+pub(crate) fn trigger_shortcut(app: &tauri::AppHandle, action: &str) {
+  info!("shortcut: {}", action);
+  match action {
+    "run_all" => {
+      if let Some(window) = app.get_window("main") {
+        thread::spawn(move || run_all_from_shortcut(&window));
+      }
+    }
+    "show_window" => {
+      show_main_window(app);
+    }
+    "open_latest_failure" => {
+      let app = app.clone();
+      thread::spawn(move || open_latest_failure(&app));
+    }
+    _ => {}
+  }
+}
+
+pub(crate) fn shortcut_run_message(result: &RunWorkerResult, summary: Option<&Value>) -> String {
+  match summary {
+    Some(summary) => format!(
+      "{} processed, {} failures, {} warnings",
+      extract_processed_count(summary).unwrap_or(0),
+      extract_issue_count(summary, "failures"),
+      extract_issue_count(summary, "warnings")
+    ),
+    None if result.status == "ok" => "Run finished".to_string(),
+    None => format!("Run ended: {}", result.status),
+  }
+}
+
+// Runs every enabled store like the tray's "Run all", without bringing the
+// window up first, then reports how it went.
+pub(crate) fn run_all_from_shortcut(window: &tauri::Window) {
+  let config = current_config(window);
+  let sink = EventSink::from(window);
+  let stores = Vec::new();
+  let started = std::time::SystemTime::now();
+  let message = match start_worker(&sink, &config, &stores)
+    .and_then(|_| spawn_worker(&sink, &config, store_args(&stores), stores.clone()))
+  {
+    Ok(result) => shortcut_run_message(
+      &result,
+      run_summary_since(&config.receipts_root, started).as_ref(),
+    ),
+    Err(err) => {
+      emit_worker_line(&sink, "stderr", err.message(), &stores);
+      err.message()
+    }
+  };
+  report_shortcut(&window.app_handle(), &config, &message);
+}
+
+pub(crate) fn open_latest_failure(app: &tauri::AppHandle) {
+  let config = current_config(app);
+  let latest = issue_runs_for(&config)
+    .into_values()
+    .flat_map(|runs| runs.failures)
+    .max();
+  match latest {
+    Some(run) => handle_deep_link(app, &format!("{}://run/{}", DEEP_LINK_SCHEME, run.id)),
+    None => report_shortcut(app, &config, "No failed runs"),
+  }
+}
+
+// A notification when they are on and it isn't quiet hours, else the window.
+pub(crate) fn report_shortcut(app: &tauri::AppHandle, config: &AppConfig, message: &str) {
+  if config.notifications_enabled && !in_quiet_hours(config, chrono::Local::now().time()) {
+    let _ = tauri::api::notification::Notification::new(&app.config().tauri.bundle.identifier)
+      .title("Receipts")
+      .body(message)
+      .show();
+  } else {
+    show_main_window(app);
+  }
+  let _ = app.emit_all("shortcut-result", message);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn shortcut_accelerators_are_normalized_and_prefs_validated() {
+    assert_eq!(
+      normalize_accelerator(" Ctrl + Shift + r ").expect("valid"),
+      "Ctrl+Shift+R"
+    );
+    assert_eq!(
+      normalize_accelerator("CmdOrCtrl+Alt+F5").expect("valid"),
+      "CmdOrCtrl+Alt+F5"
+    );
+    for invalid in ["R", "Ctrl+", "Ctrl+Shift", "Hyper+R", ""] {
+      assert!(
+        matches!(
+          normalize_accelerator(invalid),
+          Err(AppError::InvalidInput(_))
+        ),
+        "{}",
+        invalid
+      );
+    }
+
+    let mut prefs = HashMap::new();
+    let bindings = shortcut_bindings(&prefs);
+    assert_eq!(bindings["run_all"].as_deref(), Some("CmdOrCtrl+Shift+R"));
+    assert_eq!(bindings["show_window"], None);
+    assert_eq!(
+      store_shortcut(&mut prefs, "run_all", Some(Value::Null)),
+      None
+    );
+    store_shortcut(&mut prefs, "show_window", Some(Value::from("Ctrl+Shift+D")));
+    let bindings = shortcut_bindings(&prefs);
+    assert_eq!(bindings["run_all"], None);
+    assert_eq!(bindings["show_window"].as_deref(), Some("Ctrl+Shift+D"));
+    assert_eq!(
+      store_shortcut(&mut prefs, "run_all", None),
+      Some(Value::Null)
+    );
+    assert_eq!(
+      shortcut_bindings(&prefs)["run_all"].as_deref(),
+      Some("CmdOrCtrl+Shift+R")
+    );
+
+    let config = badge_config("lidl");
+    let pref = |value: Value| validate_pref(&config, PREF_SHORTCUTS, &value);
+    assert!(pref(serde_json::json!({"run_all": "Ctrl+Shift+R", "show_window": null})).is_ok());
+    assert!(pref(serde_json::json!({"launch": "Ctrl+Shift+L"})).is_err());
+    assert!(pref(serde_json::json!({"run_all": "R"})).is_err());
+    assert!(pref(serde_json::json!({"run_all": "Ctrl+R", "show_window": "ctrl+r"})).is_err());
+
+    let err = AppError::ShortcutUnavailable {
+      action: "run_all".to_string(),
+      accelerator: "Ctrl+Shift+R".to_string(),
+      reason: "already registered".to_string(),
+    };
+    assert_eq!(err.code(), "shortcut_unavailable");
+    assert_eq!(err.details()["accelerator"], "Ctrl+Shift+R");
+  }
+}
@@ -0,0 +1,863 @@
+use crate::*;
+
+// What the worker's JSON output next to a processed receipt says it cost.
+// `total` is None when the output has no parseable total.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ReceiptTotal {
+  pub(crate) store_id: String,
+  // The name itself, or an id when it isn't UTF-8 (see `file_name_id`).
+  pub(crate) file_name: String,
+  pub(crate) date: Option<chrono::NaiveDate>,
+  // The output had no date, so `date` is the output file's mtime.
+  pub(crate) date_estimated: bool,
+  pub(crate) total: Option<f64>,
+  pub(crate) currency: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Default)]
+pub(crate) struct SpendingTotals {
+  // One total per currency (see `Conversion`).
+  pub(crate) totals: CurrencyTotals,
+  pub(crate) receipt_count: usize,
+  // Receipts in range whose output has no parseable total.
+  pub(crate) unparsed_count: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct StoreSpending {
+  pub(crate) store_id: String,
+  #[serde(flatten)]
+  pub(crate) totals: SpendingTotals,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct MonthSpending {
+  // "YYYY-MM"
+  pub(crate) month: String,
+  #[serde(flatten)]
+  pub(crate) totals: SpendingTotals,
+}
+
+// Amounts are only ever summed within a currency; `currencies` lists every
+// currency seen on the receipts, before any conversion.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct SpendingSummary {
+  pub(crate) from: Option<String>,
+  pub(crate) to: Option<String>,
+  pub(crate) store_id: Option<String>,
+  pub(crate) convert_to: Option<String>,
+  #[serde(flatten)]
+  pub(crate) totals: SpendingTotals,
+  pub(crate) currencies: Vec<String>,
+  pub(crate) by_store: Vec<StoreSpending>,
+  pub(crate) by_month: Vec<MonthSpending>,
+}
+
+// Numbers as is; strings like "12,50", "1.234,56", "1,234.56" or "45.90 RON".
+// With both separators present, the last one is the decimal point.
+pub(crate) fn parse_amount(value: &Value) -> Option<f64> {
+  if let Some(number) = value.as_f64() {
+    return Some(number).filter(|number| number.is_finite());
+  }
+  let raw = value.as_str()?;
+  let digits = raw
+    .trim()
+    .trim_matches(|c: char| c.is_alphabetic() || c.is_whitespace())
+    .replace([' ', '\u{a0}'], "");
+  let normalized = match (digits.rfind(','), digits.rfind('.')) {
+    (Some(comma), Some(dot)) if comma > dot => digits.replace('.', "").replace(',', "."),
+    (Some(_), Some(_)) => digits.replace(',', ""),
+    (Some(_), None) => digits.replace(',', "."),
+    _ => digits,
+  };
+  normalized
+    .parse::<f64>()
+    .ok()
+    .filter(|number| number.is_finite())
+}
+
+// The date part of `timestamp` (or `date`), e.g. "2024-03-01T18:22:05".
+pub(crate) fn parse_receipt_date(value: &Value) -> Option<chrono::NaiveDate> {
+  let raw = value
+    .get("timestamp")
+    .or_else(|| value.get("date"))
+    .and_then(|field| field.as_str())?;
+  chrono::NaiveDate::parse_from_str(raw.get(..10)?, "%Y-%m-%d").ok()
+}
+
+// The worker writes `<receipt>.json` next to each processed receipt; failed
+// ones get `.error.json` and stay out of processed/.
+pub(crate) fn is_receipt_output(name: &str) -> bool {
+  name.ends_with(".json") && !name.ends_with(".error.json") && !name.starts_with('.')
+}
+
+// Receipts without a date in their output are dated by the output file's mtime;
+// output that isn't JSON counts as a receipt without a total.
+pub(crate) fn read_receipt_output(store_id: &str, path: &Path) -> Option<ReceiptTotal> {
+  let raw = fs::read_to_string(path).ok()?;
+  let value = serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null);
+  receipt_total_from(store_id, path, &value)
+}
+
+pub(crate) fn receipt_total_from(
+  store_id: &str,
+  path: &Path,
+  value: &Value,
+) -> Option<ReceiptTotal> {
+  let parsed = parse_receipt_date(value);
+  let date = parsed.or_else(|| {
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok()?;
+    Some(chrono::DateTime::<chrono::Local>::from(modified).date_naive())
+  });
+  Some(ReceiptTotal {
+    store_id: store_id.to_string(),
+    file_name: file_name_id(path.file_name()?),
+    date,
+    date_estimated: parsed.is_none(),
+    total: value.get("total").and_then(parse_amount),
+    currency: value
+      .get("currency")
+      .and_then(|currency| currency.as_str())
+      .map(|currency| currency.trim().to_uppercase())
+      .filter(|currency| !currency.is_empty()),
+  })
+}
+
+pub(crate) fn processed_dir(receipts_root: &str, store_id: &str) -> PathBuf {
+  Path::new(receipts_root).join("processed").join(store_id)
+}
+
+// Reads one output file at a time; nothing under processed/ is written.
+pub(crate) fn walk_receipt_totals<'a>(
+  receipts_root: &'a str,
+  store_ids: &'a [String],
+) -> impl Iterator<Item = ReceiptTotal> + 'a {
+  store_ids.iter().flat_map(move |store_id| {
+    fs::read_dir(processed_dir(receipts_root, store_id))
+      .into_iter()
+      .flatten()
+      .flatten()
+      .filter(|entry| is_receipt_output(&entry.file_name().to_string_lossy()))
+      .filter_map(move |entry| read_receipt_output(store_id, &entry.path()))
+  })
+}
+
+pub(crate) fn round_cents(amount: f64) -> f64 {
+  (amount * 100.0).round() / 100.0
+}
+
+// One (store, month, currency) bucket of receipts: how many there are, how
+// many have a total, and what those add up to. Kept in cents so buckets can be
+// added to and taken from without drifting. `month` is `YYYY-MM`, or
+// `unknown` for receipts without a date.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MonthStat {
+  pub(crate) store_id: String,
+  pub(crate) month: String,
+  pub(crate) currency: String,
+  pub(crate) receipt_count: usize,
+  pub(crate) priced_count: usize,
+  pub(crate) total_cents: i64,
+}
+
+impl MonthStat {
+  pub(crate) fn of(receipt: &ReceiptTotal) -> MonthStat {
+    MonthStat {
+      store_id: receipt.store_id.clone(),
+      month: receipt
+        .date
+        .map(|date| date.format("%Y-%m").to_string())
+        .unwrap_or_else(|| "unknown".to_string()),
+      currency: receipt_currency(receipt),
+      receipt_count: 1,
+      priced_count: usize::from(receipt.total.is_some()),
+      total_cents: receipt
+        .total
+        .map(|total| (total * 100.0).round() as i64)
+        .unwrap_or(0),
+    }
+  }
+
+  pub(crate) fn total(&self) -> f64 {
+    self.total_cents as f64 / 100.0
+  }
+}
+
+// Whole months only, so the per-month buckets answer the range exactly.
+pub(crate) fn month_aligned(
+  from: Option<chrono::NaiveDate>,
+  to: Option<chrono::NaiveDate>,
+) -> bool {
+  use chrono::Datelike;
+  // The last day there is counts as a month end.
+  let month_end = |to: chrono::NaiveDate| to.succ_opt().map(|next| next.day() == 1);
+  from.is_none_or(|from| from.day() == 1) && to.is_none_or(|to| month_end(to) != Some(false))
+}
+
+pub(crate) fn summarize_spending(
+  receipts: impl IntoIterator<Item = ReceiptTotal>,
+  from: Option<chrono::NaiveDate>,
+  to: Option<chrono::NaiveDate>,
+  conversion: &Conversion,
+) -> SpendingSummary {
+  let stats = receipts
+    .into_iter()
+    .filter(|receipt| {
+      let in_range = receipt
+        .date
+        .is_some_and(|date| from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to));
+      in_range || (from.is_none() && to.is_none())
+    })
+    .map(|receipt| MonthStat::of(&receipt));
+  summarize_month_stats(stats, from, to, conversion)
+}
+
+// `from` and `to` are only reported; the buckets have to be in range already.
+pub(crate) fn summarize_month_stats(
+  stats: impl IntoIterator<Item = MonthStat>,
+  from: Option<chrono::NaiveDate>,
+  to: Option<chrono::NaiveDate>,
+  conversion: &Conversion,
+) -> SpendingSummary {
+  let mut totals = SpendingTotals::default();
+  let mut by_store = std::collections::BTreeMap::<String, SpendingTotals>::new();
+  let mut by_month = std::collections::BTreeMap::<String, SpendingTotals>::new();
+  let mut currencies = std::collections::BTreeSet::new();
+  for stat in stats {
+    let store_totals = by_store.entry(stat.store_id.clone()).or_default();
+    let month_totals = by_month.entry(stat.month.clone()).or_default();
+    let amount = (stat.priced_count > 0).then(|| conversion.apply(&stat.currency, stat.total()));
+    for bucket in [&mut totals, store_totals, month_totals] {
+      bucket.receipt_count += stat.receipt_count;
+      bucket.unparsed_count += stat.receipt_count - stat.priced_count;
+      if let Some((currency, amount)) = &amount {
+        add_amount(&mut bucket.totals, currency.clone(), *amount);
+      }
+    }
+    currencies.insert(stat.currency);
+  }
+  let rounded = |mut totals: SpendingTotals| {
+    totals.totals = rounded_totals(totals.totals);
+    totals
+  };
+  SpendingSummary {
+    from: from.map(|date| date.to_string()),
+    to: to.map(|date| date.to_string()),
+    store_id: None,
+    convert_to: conversion.target.clone(),
+    totals: rounded(totals),
+    currencies: currencies.into_iter().collect(),
+    by_store: by_store
+      .into_iter()
+      .map(|(store_id, totals)| StoreSpending {
+        store_id,
+        totals: rounded(totals),
+      })
+      .collect(),
+    by_month: by_month
+      .into_iter()
+      .map(|(month, totals)| MonthSpending {
+        month,
+        totals: rounded(totals),
+      })
+      .collect(),
+  }
+}
+
+pub(crate) fn parse_date_bound(
+  name: &str,
+  value: Option<&str>,
+) -> Result<Option<chrono::NaiveDate>, AppError> {
+  value
+    .map(str::trim)
+    .filter(|value| !value.is_empty())
+    .map(|value| {
+      chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        AppError::InvalidInput(format!("{} must be a YYYY-MM-DD date: {}", name, value))
+      })
+    })
+    .transpose()
+}
+
+// The one store asked for (which has to be configured), else all of them.
+pub(crate) fn receipt_store_ids(
+  config: &AppConfig,
+  store_id: Option<&str>,
+) -> Result<Vec<String>, AppError> {
+  match store_id {
+    Some(store_id) => {
+      require_store(config, store_id)?;
+      Ok(vec![store_id.to_string()])
+    }
+    None => Ok(config.stores.iter().map(|store| store.id.clone()).collect()),
+  }
+}
+
+// The run index's month_stats buckets, after the same sync as
+// `indexed_receipts`; None when the index is unavailable.
+pub(crate) fn indexed_month_stats_for(
+  app: &tauri::AppHandle,
+  receipts_root: &str,
+  store_ids: &[String],
+) -> Option<Vec<MonthStat>> {
+  let index = app.state::<RunIndex>();
+  let mut conn = index.0.lock().ok()?;
+  match indexed_month_stats(conn.as_mut()?, receipts_root, store_ids) {
+    Ok(stats) => Some(stats),
+    Err(err) => {
+      warn!("month stats index failed; reading receipts: {}", err);
+      None
+    }
+  }
+}
+
+// From the run index's receipt_totals table (synced first, so only changed
+// outputs are re-read); None when the index is unavailable and processed/
+// has to be walked instead.
+pub(crate) fn indexed_receipts(
+  app: &tauri::AppHandle,
+  receipts_root: &str,
+  store_ids: &[String],
+) -> Option<Vec<ReceiptTotal>> {
+  let index = app.state::<RunIndex>();
+  let mut conn = index.0.lock().ok()?;
+  match indexed_receipt_totals(conn.as_mut()?, receipts_root, store_ids) {
+    Ok(receipts) => Some(receipts),
+    Err(err) => {
+      warn!("receipt totals index failed; reading processed/: {}", err);
+      None
+    }
+  }
+}
+
+pub(crate) fn spending_summary(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  from: Option<chrono::NaiveDate>,
+  to: Option<chrono::NaiveDate>,
+  store_id: Option<String>,
+  conversion: &Conversion,
+) -> Result<SpendingSummary, AppError> {
+  let root = require_receipts_root(config)?;
+  let store_ids = receipt_store_ids(config, store_id.as_deref())?;
+  let stats = month_aligned(from, to)
+    .then(|| indexed_month_stats_for(app, root, &store_ids))
+    .flatten();
+  let mut summary = match stats {
+    Some(stats) => {
+      let from_month = from.map(|date| date.format("%Y-%m").to_string());
+      let to_month = to.map(|date| date.format("%Y-%m").to_string());
+      let ranged = from.is_some() || to.is_some();
+      let stats = stats.into_iter().filter(|stat| {
+        (!ranged || stat.month != "unknown")
+          && from_month.as_ref().is_none_or(|from| stat.month >= *from)
+          && to_month.as_ref().is_none_or(|to| stat.month <= *to)
+      });
+      summarize_month_stats(stats, from, to, conversion)
+    }
+    None => match indexed_receipts(app, root, &store_ids) {
+      Some(receipts) => summarize_spending(receipts, from, to, conversion),
+      None => summarize_spending(walk_receipt_totals(root, &store_ids), from, to, conversion),
+    },
+  };
+  summary.store_id = store_id;
+  Ok(summary)
+}
+
+// One currency's figures for a month.
+#[derive(Debug, Serialize, Clone, PartialEq, Default)]
+pub(crate) struct MonthFigures {
+  pub(crate) currency: String,
+  pub(crate) total: f64,
+  pub(crate) receipt_count: usize,
+  // Total over the receipts that have one; 0 when none do.
+  pub(crate) average_basket: f64,
+  pub(crate) previous_total: f64,
+  pub(crate) delta: f64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct StoreMonth {
+  pub(crate) store_id: String,
+  pub(crate) receipt_count: usize,
+  pub(crate) by_currency: Vec<MonthFigures>,
+}
+
+// Every configured store is listed with figures for every currency in
+// `currencies` (the ones seen this month or the previous one), zeros included.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct MonthlyReport {
+  pub(crate) year: i32,
+  pub(crate) month: u32,
+  pub(crate) previous_month: String,
+  pub(crate) convert_to: Option<String>,
+  pub(crate) currencies: Vec<String>,
+  pub(crate) stores: Vec<StoreMonth>,
+  pub(crate) receipt_count: usize,
+  pub(crate) by_currency: Vec<MonthFigures>,
+}
+
+// Reports by (root, year, month, conversion), valid while the stores'
+// processed/ folders keep the signature they had when it was built; a new
+// output changes it.
+#[derive(Default)]
+pub(crate) struct MonthlyReportCache(pub(crate) Mutex<MonthlyReportEntries>);
+
+pub(crate) type MonthlyReportEntries =
+  HashMap<(String, i32, u32, String), (Vec<(String, RunsSignature)>, MonthlyReport)>;
+
+pub(crate) fn processed_signature(
+  receipts_root: &str,
+  store_ids: &[String],
+) -> Vec<(String, RunsSignature)> {
+  store_ids
+    .iter()
+    .map(|store_id| {
+      let dir = processed_dir(receipts_root, store_id);
+      let signature = (
+        fs::metadata(&dir).and_then(|meta| meta.modified()).ok(),
+        fs::read_dir(&dir)
+          .map(|entries| entries.count())
+          .unwrap_or(0),
+      );
+      (store_id.clone(), signature)
+    })
+    .collect()
+}
+
+pub(crate) fn month_start(year: i32, month: u32) -> Result<chrono::NaiveDate, AppError> {
+  chrono::NaiveDate::from_ymd_opt(year, month, 1)
+    .ok_or_else(|| AppError::InvalidInput(format!("Not a valid month: {}-{:02}", year, month)))
+}
+
+pub(crate) fn previous_month(year: i32, month: u32) -> (i32, u32) {
+  if month == 1 {
+    (year - 1, 12)
+  } else {
+    (year, month - 1)
+  }
+}
+
+// Buckets by the receipt's own date, so a receipt processed in a later month
+// still counts for the month it was bought in.
+pub(crate) fn monthly_report(
+  receipts: impl IntoIterator<Item = ReceiptTotal>,
+  store_ids: &[String],
+  year: i32,
+  month: u32,
+  conversion: &Conversion,
+) -> MonthlyReport {
+  let stats = receipts.into_iter().map(|receipt| MonthStat::of(&receipt));
+  monthly_report_from_stats(stats, store_ids, year, month, conversion)
+}
+
+pub(crate) fn monthly_report_from_stats(
+  stats: impl IntoIterator<Item = MonthStat>,
+  store_ids: &[String],
+  year: i32,
+  month: u32,
+  conversion: &Conversion,
+) -> MonthlyReport {
+  let (previous_year, previous) = previous_month(year, month);
+  let current_key = format!("{}-{:02}", year, month);
+  let previous_key = format!("{}-{:02}", previous_year, previous);
+  // (store, currency) -> (total, receipts, receipts with a total, previous month total)
+  let mut sums = HashMap::<(String, String), (f64, usize, usize, f64)>::new();
+  let mut currencies = std::collections::BTreeSet::new();
+  for stat in stats {
+    if !store_ids.contains(&stat.store_id)
+      || (stat.month != current_key && stat.month != previous_key)
+    {
+      continue;
+    }
+    let (currency, amount) = conversion.apply(&stat.currency, stat.total());
+    currencies.insert(currency.clone());
+    let sums = sums.entry((stat.store_id.clone(), currency)).or_default();
+    if stat.month == current_key {
+      sums.1 += stat.receipt_count;
+      sums.0 += amount;
+      sums.2 += stat.priced_count;
+    } else {
+      sums.3 += amount;
+    }
+  }
+  let figures =
+    |currency: &str, (total, receipts, with_total, previous_total): (f64, usize, usize, f64)| {
+      MonthFigures {
+        currency: currency.to_string(),
+        total: round_cents(total),
+        receipt_count: receipts,
+        average_basket: if with_total > 0 {
+          round_cents(total / with_total as f64)
+        } else {
+          0.0
+        },
+        previous_total: round_cents(previous_total),
+        delta: round_cents(total - previous_total),
+      }
+    };
+  let add = |acc: (f64, usize, usize, f64), sums: (f64, usize, usize, f64)| {
+    (
+      acc.0 + sums.0,
+      acc.1 + sums.1,
+      acc.2 + sums.2,
+      acc.3 + sums.3,
+    )
+  };
+  let stores = store_ids
+    .iter()
+    .map(|store_id| {
+      let by_currency = currencies
+        .iter()
+        .map(|currency| {
+          let key = (store_id.clone(), currency.clone());
+          figures(currency, sums.get(&key).copied().unwrap_or_default())
+        })
+        .collect::<Vec<_>>();
+      StoreMonth {
+        store_id: store_id.clone(),
+        receipt_count: by_currency
+          .iter()
+          .map(|figures| figures.receipt_count)
+          .sum(),
+        by_currency,
+      }
+    })
+    .collect::<Vec<_>>();
+  let by_currency = currencies
+    .iter()
+    .map(|currency| {
+      let grand = sums
+        .iter()
+        .filter(|((_, bucket), _)| bucket == currency)
+        .fold((0.0, 0, 0, 0.0), |acc, (_, sums)| add(acc, *sums));
+      figures(currency, grand)
+    })
+    .collect::<Vec<_>>();
+  MonthlyReport {
+    year,
+    month,
+    previous_month: previous_key,
+    convert_to: conversion.target.clone(),
+    currencies: currencies.into_iter().collect(),
+    stores,
+    receipt_count: by_currency
+      .iter()
+      .map(|figures| figures.receipt_count)
+      .sum(),
+    by_currency,
+  }
+}
+
+pub(crate) fn cached_monthly_report(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  year: i32,
+  month: u32,
+  conversion: &Conversion,
+) -> Result<MonthlyReport, AppError> {
+  month_start(year, month)?;
+  let root = require_receipts_root(config)?;
+  let store_ids = config
+    .stores
+    .iter()
+    .map(|store| store.id.clone())
+    .collect::<Vec<_>>();
+  let signature = processed_signature(root, &store_ids);
+  let key = (root.to_string(), year, month, conversion.cache_key());
+  let cache = app.state::<MonthlyReportCache>();
+  if let Ok(cached) = cache.0.lock() {
+    if let Some((cached_signature, report)) = cached.get(&key) {
+      if *cached_signature == signature {
+        return Ok(report.clone());
+      }
+    }
+  }
+  let report = match indexed_month_stats_for(app, root, &store_ids) {
+    Some(stats) => monthly_report_from_stats(stats, &store_ids, year, month, conversion),
+    None => monthly_report(
+      walk_receipt_totals(root, &store_ids),
+      &store_ids,
+      year,
+      month,
+      conversion,
+    ),
+  };
+  if let Ok(mut cached) = cache.0.lock() {
+    cached.insert(key, (signature, report.clone()));
+  }
+  Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn spending_totals_parse_amounts_and_match_between_walk_and_index() {
+    for (raw, expected) in [
+      (serde_json::json!(45.9), Some(45.9)),
+      (serde_json::json!("12,50"), Some(12.5)),
+      (serde_json::json!("1.234,56"), Some(1234.56)),
+      (serde_json::json!("1,234.56 RON"), Some(1234.56)),
+      (serde_json::json!("lei 7"), Some(7.0)),
+      (serde_json::json!("n/a"), None),
+      (serde_json::json!(null), None),
+    ] {
+      assert_eq!(parse_amount(&raw), expected, "{}", raw);
+    }
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(&processed).expect("processed");
+    let outputs = [
+      (
+        "a.jpg.json",
+        r#"{"timestamp": "2024-01-05T10:00:00", "total": 45.9, "currency": "RON"}"#,
+      ),
+      (
+        "b.jpg.json",
+        r#"{"timestamp": "2024-01-20T10:00:00", "total": "12,50"}"#,
+      ),
+      (
+        "c.jpg.json",
+        r#"{"timestamp": "2024-02-01T10:00:00", "total": "1.234,56", "currency": "ron"}"#,
+      ),
+      (
+        "d.jpg.json",
+        r#"{"timestamp": "2024-02-03T10:00:00", "total": null}"#,
+      ),
+      ("e.jpg.json", "not json"),
+      (
+        "f.jpg.error.json",
+        r#"{"timestamp": "2024-02-03T10:00:00", "total": 99}"#,
+      ),
+      ("a.jpg", "image"),
+    ];
+    for (name, raw) in outputs {
+      fs::write(processed.join(name), raw).expect("output");
+    }
+    let listing = |dir: &Path| {
+      let mut files = fs::read_dir(dir)
+        .expect("listing")
+        .flatten()
+        .map(|entry| (entry.file_name(), fs::read(entry.path()).expect("read")))
+        .collect::<Vec<_>>();
+      files.sort();
+      files
+    };
+    let before = listing(&processed);
+
+    let stores = vec!["lidl".to_string()];
+    let root = dir.path().to_string_lossy().to_string();
+    let from = chrono::NaiveDate::from_ymd_opt(2024, 1, 1);
+    let to = chrono::NaiveDate::from_ymd_opt(2024, 2, 28);
+    let same = Conversion::default();
+    let walked = summarize_spending(walk_receipt_totals(&root, &stores), from, to, &same);
+    assert_eq!(walked.totals.receipt_count, 4);
+    assert_eq!(walked.totals.unparsed_count, 1);
+    let totals = |pairs: &[(&str, f64)]| {
+      pairs
+        .iter()
+        .map(|(currency, total)| (currency.to_string(), *total))
+        .collect::<CurrencyTotals>()
+    };
+    // b.jpg has no currency, so it stays apart from the RON receipts.
+    assert_eq!(
+      walked.totals.totals,
+      totals(&[("RON", 1280.46), ("unknown", 12.5)])
+    );
+    assert_eq!(
+      walked.currencies,
+      vec!["RON".to_string(), "unknown".to_string()]
+    );
+    let months = walked
+      .by_month
+      .iter()
+      .map(|month| (month.month.as_str(), month.totals.totals.clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      months,
+      vec![
+        ("2024-01", totals(&[("RON", 45.9), ("unknown", 12.5)])),
+        ("2024-02", totals(&[("RON", 1234.56)])),
+      ]
+    );
+
+    let mut conn = rusqlite::Connection::open_in_memory().expect("index");
+    prepare_index(&conn).expect("schema");
+    let indexed = indexed_receipt_totals(&mut conn, &root, &stores).expect("index");
+    assert_eq!(summarize_spending(indexed, from, to, &same), walked);
+
+    fs::remove_file(processed.join("b.jpg.json")).expect("remove");
+    let indexed = indexed_receipt_totals(&mut conn, &root, &stores).expect("index");
+    let after = summarize_spending(indexed, from, to, &same);
+    assert_eq!(
+      after,
+      summarize_spending(walk_receipt_totals(&root, &stores), from, to, &same)
+    );
+    assert_eq!(after.totals.totals, totals(&[("RON", 1280.46)]));
+
+    fs::write(
+      processed.join("b.jpg.json"),
+      r#"{"timestamp": "2024-01-20T10:00:00", "total": "12,50"}"#,
+    )
+    .expect("restore");
+    assert_eq!(listing(&processed), before);
+    assert!(parse_date_bound("from", Some("2024-13-01")).is_err());
+    assert_eq!(parse_date_bound("to", Some(" ")).expect("empty"), None);
+  }
+
+  #[test]
+  fn monthly_report_buckets_by_receipt_date_and_fills_empty_stores() {
+    let receipt = |store_id: &str, date: &str, total: Option<f64>| ReceiptTotal {
+      store_id: store_id.to_string(),
+      file_name: format!("{}-{}.jpg.json", store_id, date),
+      date: chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok(),
+      date_estimated: false,
+      total,
+      currency: None,
+    };
+    let receipts = vec![
+      receipt("lidl", "2024-01-03", Some(30.0)),
+      receipt("lidl", "2024-01-28", Some(15.5)),
+      receipt("lidl", "2024-01-30", None),
+      // Processed in February, bought in December.
+      receipt("lidl", "2023-12-30", Some(20.0)),
+      receipt("kaufland", "2023-12-15", Some(10.0)),
+      receipt("aldi", "2024-01-10", Some(99.0)),
+    ];
+    let stores = vec![
+      "lidl".to_string(),
+      "kaufland".to_string(),
+      "carrefour".to_string(),
+    ];
+    let report = monthly_report(receipts, &stores, 2024, 1, &Conversion::default());
+    assert_eq!(report.previous_month, "2023-12");
+    assert_eq!(report.currencies, vec!["unknown".to_string()]);
+    let lidl = &report.stores[0].by_currency[0];
+    assert_eq!(
+      (lidl.total, lidl.receipt_count, lidl.average_basket),
+      (45.5, 3, 22.75)
+    );
+    assert_eq!((lidl.previous_total, lidl.delta), (20.0, 25.5));
+    let kaufland = &report.stores[1].by_currency[0];
+    assert_eq!((kaufland.total, kaufland.delta), (0.0, -10.0));
+    assert_eq!(
+      report.stores[2].by_currency,
+      vec![MonthFigures {
+        currency: "unknown".to_string(),
+        ..MonthFigures::default()
+      }]
+    );
+    assert_eq!(report.by_currency[0].total, 45.5);
+    assert_eq!(report.by_currency[0].previous_total, 30.0);
+    assert_eq!(previous_month(2024, 1), (2023, 12));
+    assert!(month_start(2024, 13).is_err());
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let root = dir.path().to_string_lossy().to_string();
+    let before = processed_signature(&root, &stores);
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(&processed).expect("processed");
+    fs::write(processed.join("a.jpg.json"), "{}").expect("output");
+    assert_ne!(processed_signature(&root, &stores), before);
+  }
+
+  #[test]
+  fn aggregates_keep_currencies_apart_unless_converted() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(&processed).expect("processed");
+    for (name, raw) in [
+      (
+        "ron.jpg.json",
+        r#"{"timestamp": "2024-01-05T10:00:00", "total": 100.0, "currency": "RON",
+          "items": [{"name": "Lapte", "category": "groceries", "paid_amount": 100.0}]}"#,
+      ),
+      (
+        "eur.jpg.json",
+        r#"{"timestamp": "2024-01-12T10:00:00", "total": 10.0, "currency": "eur",
+          "items": [{"name": "Café", "category": "groceries", "paid_amount": 10.0}]}"#,
+      ),
+      (
+        "huf.jpg.json",
+        r#"{"timestamp": "2024-01-13T10:00:00", "total": 1000.0, "currency": "HUF"}"#,
+      ),
+      (
+        "none.jpg.json",
+        r#"{"timestamp": "2024-01-20T10:00:00", "total": 7.0}"#,
+      ),
+    ] {
+      fs::write(processed.join(name), raw).expect("output");
+    }
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string()];
+    let totals = |pairs: &[(&str, f64)]| {
+      pairs
+        .iter()
+        .map(|(currency, total)| (currency.to_string(), *total))
+        .collect::<CurrencyTotals>()
+    };
+
+    let same = Conversion::default();
+    let summary = summarize_spending(walk_receipt_totals(&root, &stores), None, None, &same);
+    assert_eq!(
+      summary.totals.totals,
+      totals(&[
+        ("EUR", 10.0),
+        ("HUF", 1000.0),
+        ("RON", 100.0),
+        ("unknown", 7.0)
+      ])
+    );
+    let report = monthly_report(walk_receipt_totals(&root, &stores), &stores, 2024, 1, &same);
+    assert_eq!(report.currencies, vec!["EUR", "HUF", "RON", "unknown"]);
+    assert_eq!(report.receipt_count, 4);
+    assert!(report
+      .by_currency
+      .iter()
+      .all(|figures| figures.receipt_count == 1));
+    let breakdown = category_breakdown(&root, &stores, None, None, &CategoryMapping::new(), &same);
+    assert_eq!(breakdown.totals, totals(&[("EUR", 10.0), ("RON", 100.0)]));
+
+    let mut config = badge_config("lidl");
+    config.currency_rates = normalize_currency_rates(CurrencyRates::from([
+      ("ron".to_string(), 1.0),
+      ("EUR".to_string(), 4.97),
+      ("XXX".to_string(), -1.0),
+    ]));
+    assert_eq!(config.currency_rates.len(), 2);
+    assert_eq!(
+      Conversion::new(&config, Some("usd")).unwrap_err().code(),
+      "invalid_input"
+    );
+    let to_ron = Conversion::new(&config, Some("ron")).expect("conversion");
+    assert_ne!(to_ron.cache_key(), same.cache_key());
+    // HUF has no rate and unknown can't be converted: both keep their own bucket.
+    let summary = summarize_spending(walk_receipt_totals(&root, &stores), None, None, &to_ron);
+    assert_eq!(summary.convert_to.as_deref(), Some("RON"));
+    assert_eq!(
+      summary.totals.totals,
+      totals(&[("HUF", 1000.0), ("RON", 149.7), ("unknown", 7.0)])
+    );
+    let report = monthly_report(
+      walk_receipt_totals(&root, &stores),
+      &stores,
+      2024,
+      1,
+      &to_ron,
+    );
+    assert_eq!(report.currencies, vec!["HUF", "RON", "unknown"]);
+    assert_eq!(report.by_currency[1].total, 149.7);
+    assert_eq!(report.by_currency[1].receipt_count, 2);
+    let breakdown =
+      category_breakdown(&root, &stores, None, None, &CategoryMapping::new(), &to_ron);
+    assert_eq!(breakdown.totals, totals(&[("RON", 149.7)]));
+    assert!(breakdown
+      .discrepancies
+      .iter()
+      .all(|found| found.currency != "EUR"));
+  }
+}
@@ -0,0 +1,200 @@
+use crate::*;
+
+// One `startup-progress` event: each phase is sent once when it starts
+// (`running`) and once when it ends (`ok` or `failed`, with the error).
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct StartupProgress {
+  pub(crate) phase: String,
+  pub(crate) index: usize,
+  pub(crate) total: usize,
+  pub(crate) status: &'static str,
+  // Since the app started, and since this phase started.
+  pub(crate) elapsed_ms: u64,
+  pub(crate) phase_elapsed_ms: u64,
+  pub(crate) error: Option<String>,
+}
+
+// What `get_startup_status` returns, and the `startup-complete` payload once
+// `complete` is set. The window's listeners usually attach after the setup
+// phases have gone by, so it has to be able to ask.
+#[derive(Debug, Serialize, Clone, Default)]
+pub(crate) struct StartupStatus {
+  // The latest event per phase, in phase order.
+  pub(crate) phases: Vec<StartupProgress>,
+  pub(crate) complete: bool,
+  pub(crate) elapsed_ms: Option<u64>,
+  // The first dashboard snapshot; None when the first scan failed.
+  pub(crate) snapshot: Option<DashboardSnapshot>,
+}
+
+impl StartupStatus {
+  pub(crate) fn record(&mut self, progress: StartupProgress) {
+    match self
+      .phases
+      .iter_mut()
+      .find(|phase| phase.phase == progress.phase)
+    {
+      Some(phase) => *phase = progress,
+      None => {
+        self.phases.push(progress);
+        self.phases.sort_by_key(|phase| phase.index);
+      }
+    }
+  }
+
+  pub(crate) fn failed_phases(&self) -> Vec<String> {
+    self
+      .phases
+      .iter()
+      .filter(|phase| phase.status == "failed")
+      .map(|phase| phase.phase.clone())
+      .collect()
+  }
+}
+
+pub(crate) struct StartupState {
+  pub(crate) started: Instant,
+  pub(crate) status: Mutex<StartupStatus>,
+}
+
+impl StartupState {
+  pub(crate) fn new() -> StartupState {
+    StartupState {
+      started: Instant::now(),
+      status: Mutex::new(StartupStatus::default()),
+    }
+  }
+
+  pub(crate) fn progress(
+    &self,
+    phase: &str,
+    phase_started: Instant,
+    status: &'static str,
+    error: Option<String>,
+  ) -> StartupProgress {
+    StartupProgress {
+      phase: phase.to_string(),
+      index: STARTUP_PHASES
+        .iter()
+        .position(|name| *name == phase)
+        .map(|position| position + 1)
+        .unwrap_or(STARTUP_PHASES.len()),
+      total: STARTUP_PHASES.len(),
+      status,
+      elapsed_ms: self.started.elapsed().as_millis() as u64,
+      phase_elapsed_ms: phase_started.elapsed().as_millis() as u64,
+      error,
+    }
+  }
+}
+
+pub(crate) fn emit_startup_progress(app: &tauri::AppHandle, progress: StartupProgress) {
+  if let Ok(mut status) = app.state::<StartupState>().status.lock() {
+    status.record(progress.clone());
+  }
+  let _ = app.emit_all("startup-progress", progress);
+}
+
+// Runs one phase between its two events. `work` returns what it could load
+// plus the error, if any: a failed phase is reported, and startup carries on
+// with whatever it returned.
+pub(crate) fn startup_phase<T>(
+  app: &tauri::AppHandle,
+  phase: &str,
+  work: impl FnOnce() -> (T, Option<String>),
+) -> T {
+  let state = app.state::<StartupState>();
+  let phase_started = Instant::now();
+  emit_startup_progress(app, state.progress(phase, phase_started, "running", None));
+  let (value, error) = work();
+  let status = match &error {
+    Some(error) => {
+      warn!("startup {}: {}", phase, error);
+      "failed"
+    }
+    None => "ok",
+  };
+  let progress = state.progress(phase, phase_started, status, error);
+  info!(
+    "startup {} {} in {}ms",
+    phase, status, progress.phase_elapsed_ms
+  );
+  emit_startup_progress(app, progress);
+  value
+}
+
+// The dashboard scanner's first scan, in the two phases that can be slow on a
+// cold mount: counting the inboxes and reading the recent run summaries (into
+// the run index and issue cache). The snapshot built from them closes startup
+// with `startup-complete`.
+pub(crate) fn startup_scan(app: &tauri::AppHandle) {
+  let config = current_config(app);
+  startup_phase(app, "inbox_scan", || {
+    let result =
+      require_reachable_root(app, &config).and_then(|_| inbox_counts(app, &config, false));
+    ((), result.err().map(|err| err.message()))
+  });
+  startup_phase(app, "run_summaries", || {
+    if let Err(err) = require_reachable_root(app, &config) {
+      return ((), Some(err.message()));
+    }
+    sync_run_index(app, &config);
+    cached_issue_runs(&app.state::<IssueRunsCache>(), &config);
+    latest_runs(app, &config, DASHBOARD_LAST_RUNS);
+    ((), None)
+  });
+  let snapshot = match scan_dashboard(app, false) {
+    Ok(snapshot) => Some(snapshot),
+    Err(err) => {
+      debug!("first dashboard scan failed: {}", err);
+      None
+    }
+  };
+  let state = app.state::<StartupState>();
+  let status = match state.status.lock() {
+    Ok(mut status) => {
+      status.complete = true;
+      status.elapsed_ms = Some(state.started.elapsed().as_millis() as u64);
+      status.snapshot = snapshot;
+      status.clone()
+    }
+    Err(_) => return,
+  };
+  info!(
+    "startup complete in {}ms ({} failed phases)",
+    status.elapsed_ms.unwrap_or_default(),
+    status.failed_phases().len()
+  );
+  let _ = app.emit_all("startup-complete", status);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn startup_status_keeps_the_latest_event_per_phase_in_order() {
+    let state = StartupState::new();
+    let started = Instant::now();
+    let mut status = StartupStatus::default();
+    status.record(state.progress("inbox_scan", started, "running", None));
+    status.record(state.progress("config", started, "ok", None));
+    status.record(state.progress(
+      "inbox_scan",
+      started,
+      "failed",
+      Some("Path not found: /Volumes/Dropbox".to_string()),
+    ));
+    assert_eq!(
+      status
+        .phases
+        .iter()
+        .map(|phase| (phase.phase.as_str(), phase.index, phase.status))
+        .collect::<Vec<_>>(),
+      vec![("config", 1, "ok"), ("inbox_scan", 3, "failed")]
+    );
+    assert!(status.phases.iter().all(|phase| phase.total == 4));
+    assert_eq!(status.failed_phases(), vec!["inbox_scan".to_string()]);
+    assert!(!status.complete);
+  }
+}
@@ -0,0 +1,1154 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct StoreSeenState {
+  // Single-id model from older state files; folded into `failures` / `warnings`
+  // by `migrate_seen_state` at startup.
+  #[serde(default, skip_serializing)]
+  pub(crate) last_seen_failure_run_id: Option<String>,
+  #[serde(default, skip_serializing)]
+  pub(crate) last_seen_warning_run_id: Option<String>,
+  #[serde(default)]
+  pub(crate) failures: SeenRuns,
+  #[serde(default)]
+  pub(crate) warnings: SeenRuns,
+  #[serde(default)]
+  pub(crate) backlog_warning_notified: bool,
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub(crate) file_tags: HashMap<String, FileTags>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub(crate) notified_run_ids: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) snoozed_until: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) last_seen_processed_run_id: Option<String>,
+  // Output pairs (`a|b`, sorted) dismissed with `mark_not_duplicate`.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub(crate) not_duplicates: Vec<String>,
+}
+
+// Issue runs count as seen when their id is in `run_ids` or their summary was
+// written at or before `horizon`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct SeenRuns {
+  #[serde(default)]
+  pub(crate) run_ids: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) horizon: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct FileTags {
+  pub(crate) size: u64,
+  pub(crate) tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct SeenState {
+  pub(crate) stores: HashMap<String, StoreSeenState>,
+  // Entries for stores that left the config, kept until the next app version in
+  // case the removal was accidental; re-adding the store restores them.
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  pub(crate) orphaned: HashMap<String, OrphanedStore>,
+  // Budgets already reported as exceeded, see `budget_notice_key`.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub(crate) budgets_notified: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct OrphanedStore {
+  pub(crate) app_version: String,
+  pub(crate) orphaned_at: String,
+  pub(crate) state: StoreSeenState,
+}
+
+pub(crate) struct SeenStateStore(pub(crate) Mutex<SeenState>);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct StateBackup {
+  pub(crate) name: String,
+  pub(crate) size: u64,
+  pub(crate) modified: Option<String>,
+  pub(crate) valid: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct UnreadBadge {
+  pub(crate) store_id: String,
+  pub(crate) warnings_unread: bool,
+  pub(crate) failures_unread: bool,
+  pub(crate) warnings_unread_count: usize,
+  pub(crate) failures_unread_count: usize,
+  pub(crate) last_warning_run_id: Option<String>,
+  pub(crate) last_failure_run_id: Option<String>,
+  pub(crate) latest_warning_count: usize,
+  pub(crate) latest_failure_count: usize,
+  pub(crate) snoozed_until: Option<String>,
+  pub(crate) processed_unseen_count: Option<usize>,
+  // From the last scan before RECEIPTS_ROOT stopped answering.
+  #[serde(default)]
+  pub(crate) stale: bool,
+}
+
+// Store id of a seen-state key, dropping the root that `AppConfig::seen_key`
+// appends under a root override.
+pub(crate) fn seen_key_store(key: &str) -> &str {
+  key.split_once('@').map_or(key, |(store_id, _)| store_id)
+}
+
+pub(crate) fn state_file_path() -> Option<PathBuf> {
+  state_path(STATE_FILE)
+}
+
+// Returns the loaded state plus a message when state.json was unreadable and
+// the state came from the .bak copy (or was reset).
+pub(crate) fn load_state() -> (SeenState, Option<String>) {
+  match state_file_path() {
+    Some(path) => load_state_from(&path),
+    None => (SeenState::default(), None),
+  }
+}
+
+pub(crate) fn load_state_from(path: &Path) -> (SeenState, Option<String>) {
+  let backup = path.with_extension("json.bak");
+  let error = match fs::read_to_string(path) {
+    Ok(raw) => match serde_json::from_str::<SeenState>(&raw) {
+      Ok(state) => {
+        let _ = fs::write(&backup, raw);
+        return (state, None);
+      }
+      Err(err) => err.to_string(),
+    },
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return (SeenState::default(), None),
+    Err(err) => err.to_string(),
+  };
+
+  if let Ok(raw) = fs::read_to_string(&backup) {
+    if let Ok(state) = serde_json::from_str::<SeenState>(&raw) {
+      return (
+        state,
+        Some(format!(
+          "{} is unreadable ({}); restored from {}",
+          path.to_string_lossy(),
+          error,
+          backup.to_string_lossy()
+        )),
+      );
+    }
+  }
+  (
+    SeenState::default(),
+    Some(format!(
+      "{} is unreadable ({}) and no usable backup exists; starting from empty state",
+      path.to_string_lossy(),
+      error
+    )),
+  )
+}
+
+pub(crate) fn write_synced_temp(path: &Path, raw: &str) -> Result<PathBuf, String> {
+  let temp = path.with_extension("json.tmp");
+  let mut file = fs::File::create(&temp).map_err(|err| err.to_string())?;
+  file
+    .write_all(raw.as_bytes())
+    .map_err(|err| err.to_string())?;
+  file.sync_all().map_err(|err| err.to_string())?;
+  Ok(temp)
+}
+
+pub(crate) fn save_state(state: &SeenState) -> Result<(), String> {
+  let path = state_file_path().ok_or_else(|| AppError::HomeUnavailable.message())?;
+  save_state_to(&path, state)
+}
+
+pub(crate) fn save_state_to(path: &Path, state: &SeenState) -> Result<(), String> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+  }
+  let raw = serde_json::to_string_pretty(state).map_err(|err| err.to_string())?;
+  let temp = write_synced_temp(path, &raw)?;
+  rotate_state_backups(path);
+  fs::rename(&temp, path).map_err(|err| err.to_string())?;
+  Ok(())
+}
+
+// Shifts state.json.1..N up by one and copies the current file into .1, so the
+// last N saved versions stay restorable.
+pub(crate) fn rotate_state_backups(path: &Path) {
+  if !path.exists() {
+    return;
+  }
+  for index in (1..STATE_BACKUP_COUNT).rev() {
+    let from = path.with_extension(format!("json.{}", index));
+    if from.exists() {
+      let _ = fs::rename(&from, path.with_extension(format!("json.{}", index + 1)));
+    }
+  }
+  let _ = fs::copy(path, path.with_extension("json.1"));
+}
+
+pub(crate) fn state_backup_names() -> Vec<String> {
+  let mut names = (1..=STATE_BACKUP_COUNT)
+    .map(|index| format!("{}.{}", STATE_FILE, index))
+    .collect::<Vec<_>>();
+  names.push(format!("{}.bak", STATE_FILE));
+  names
+}
+
+pub(crate) fn state_backups_in(dir: &Path) -> Vec<StateBackup> {
+  let mut backups = Vec::new();
+  for name in state_backup_names() {
+    let path = dir.join(&name);
+    let meta = match fs::metadata(&path) {
+      Ok(meta) => meta,
+      Err(_) => continue,
+    };
+    let valid = fs::read_to_string(&path)
+      .ok()
+      .map(|raw| serde_json::from_str::<SeenState>(&raw).is_ok())
+      .unwrap_or(false);
+    backups.push(StateBackup {
+      name,
+      size: meta.len(),
+      modified: meta
+        .modified()
+        .ok()
+        .map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339()),
+      valid,
+    });
+  }
+  backups
+}
+
+// Only names from `state_backup_names` are read, so a restore can't reach
+// outside the state folder.
+pub(crate) fn read_state_backup(dir: &Path, name: &str) -> Result<SeenState, AppError> {
+  if !state_backup_names().iter().any(|known| known == name) {
+    return Err(AppError::InvalidInput(format!(
+      "Unknown state backup: {}",
+      name
+    )));
+  }
+  let path = dir.join(name);
+  let raw = fs::read_to_string(&path).map_err(|err| AppError::io(&path, err))?;
+  serde_json::from_str::<SeenState>(&raw).map_err(|err| AppError::Parse {
+    path: Some(path.to_string_lossy().to_string()),
+    reason: format!("not a valid state file: {}", err),
+  })
+}
+
+pub(crate) fn file_tags_for(
+  seen: Option<&StoreSeenState>,
+  file_name: &str,
+  size: u64,
+) -> Vec<String> {
+  seen
+    .and_then(|seen| seen.file_tags.get(file_name))
+    .filter(|entry| entry.size == size)
+    .map(|entry| entry.tags.clone())
+    .unwrap_or_default()
+}
+
+// Inbox folder behind a seen-state key: `store@root` under that root, a plain
+// store id under the configured root. None when the root isn't there (an
+// unmounted drive) or the plain key's root is overridden in `config`.
+pub(crate) fn seen_key_inbox(config: &AppConfig, key: &str) -> Option<PathBuf> {
+  let (store_id, root) = match key.split_once('@') {
+    Some((store_id, root)) => (store_id, root),
+    None if config.root_override.is_none() => (key, config.receipts_root.as_str()),
+    None => return None,
+  };
+  let root = Path::new(root);
+  (!root.as_os_str().is_empty() && root.is_dir()).then(|| root.join("inbox").join(store_id))
+}
+
+// Tags are keyed by name + size, so an entry whose file is gone or was replaced
+// by a different upload is dropped. Keys whose root can't be resolved keep
+// their tags.
+pub(crate) fn prune_file_tags(state: &mut SeenState, config: &AppConfig) -> bool {
+  let mut changed = false;
+  for (key, seen) in state.stores.iter_mut() {
+    if seen.file_tags.is_empty() {
+      continue;
+    }
+    let inbox = match seen_key_inbox(config, key) {
+      Some(inbox) => inbox,
+      None => continue,
+    };
+    let before = seen.file_tags.len();
+    seen.file_tags.retain(|file_name, entry| {
+      fs::metadata(join_file_id(&inbox, file_name))
+        .map(|meta| meta.is_file() && meta.len() == entry.size)
+        .unwrap_or(false)
+    });
+    changed |= seen.file_tags.len() != before;
+  }
+  changed
+}
+
+pub(crate) fn active_snooze(seen: &StoreSeenState) -> Option<String> {
+  let until = seen.snoozed_until.as_deref()?;
+  let parsed = chrono::DateTime::parse_from_rfc3339(until).ok()?;
+  if parsed.with_timezone(&chrono::Utc) > chrono::Utc::now() {
+    Some(until.to_string())
+  } else {
+    None
+  }
+}
+
+pub(crate) fn prune_expired_snoozes(state: &mut SeenState) -> bool {
+  let mut changed = false;
+  for seen in state.stores.values_mut() {
+    if seen.snoozed_until.is_some() && active_snooze(seen).is_none() {
+      seen.snoozed_until = None;
+      changed = true;
+    }
+  }
+  changed
+}
+
+// Receipts processed in runs newer than the last seen processed run; None when
+// no summary for the store carries processed data.
+pub(crate) fn processed_unseen_count(
+  runs: &StoreIssueRuns,
+  seen: &StoreSeenState,
+) -> Option<usize> {
+  if runs.processed.is_empty() {
+    return None;
+  }
+  let last_seen = seen.last_seen_processed_run_id.as_deref().map(|id| {
+    runs
+      .processed
+      .iter()
+      .find(|summary| summary.run_id.id == id)
+      .map(|summary| summary.run_id.clone())
+      .unwrap_or_else(|| RunId::new(id, None, None))
+  });
+  Some(
+    runs
+      .processed
+      .iter()
+      .filter(|summary| last_seen.as_ref().is_none_or(|last| summary.run_id > *last))
+      .filter_map(|summary| summary.processed_count)
+      .sum(),
+  )
+}
+
+pub(crate) fn is_run_seen(seen: &SeenRuns, run: &RunId) -> bool {
+  if seen.run_ids.contains(&run.id) {
+    return true;
+  }
+  let horizon = seen
+    .horizon
+    .as_deref()
+    .and_then(|horizon| chrono::DateTime::parse_from_rfc3339(horizon).ok())
+    .map(|horizon| horizon.with_timezone(&chrono::Utc));
+  match (horizon, run.modified) {
+    (Some(horizon), Some(modified)) => chrono::DateTime::<chrono::Utc>::from(modified) <= horizon,
+    _ => false,
+  }
+}
+
+// Adds every known run to the seen set. Ids whose summaries are gone are
+// dropped, and past the cap the oldest runs are folded into the horizon.
+pub(crate) fn mark_runs_seen(seen: &mut SeenRuns, runs: &[RunId], cap: usize) {
+  for run in runs {
+    if !seen.run_ids.contains(&run.id) {
+      seen.run_ids.push(run.id.clone());
+    }
+  }
+  seen
+    .run_ids
+    .retain(|id| runs.iter().any(|run| run.id == *id));
+  cap_seen_runs(seen, runs, cap);
+}
+
+// Trims the seen set to `cap` ids: ids with no known summary go first, then
+// the oldest known runs, whose mtimes move the horizon forward. Returns how
+// many ids were removed.
+pub(crate) fn cap_seen_runs(seen: &mut SeenRuns, runs: &[RunId], cap: usize) -> usize {
+  if seen.run_ids.len() <= cap {
+    return 0;
+  }
+  let overflow = seen.run_ids.len() - cap;
+  let unknown = seen
+    .run_ids
+    .iter()
+    .filter(|id| !runs.iter().any(|run| run.id == **id))
+    .cloned()
+    .collect::<Vec<_>>();
+  let mut by_age = runs
+    .iter()
+    .filter(|run| seen.run_ids.contains(&run.id))
+    .cloned()
+    .collect::<Vec<_>>();
+  by_age.sort();
+  let mut horizon = seen
+    .horizon
+    .as_deref()
+    .and_then(|horizon| chrono::DateTime::parse_from_rfc3339(horizon).ok())
+    .map(|horizon| horizon.with_timezone(&chrono::Utc));
+  for id in unknown.iter().take(overflow) {
+    seen.run_ids.retain(|seen_id| seen_id != id);
+  }
+  for run in by_age
+    .into_iter()
+    .take(overflow.saturating_sub(unknown.len()))
+  {
+    seen.run_ids.retain(|seen_id| *seen_id != run.id);
+    if let Some(modified) = run.modified.map(chrono::DateTime::<chrono::Utc>::from) {
+      horizon = Some(horizon.map_or(modified, |current| current.max(modified)));
+    }
+  }
+  seen.horizon = horizon.map(|horizon| horizon.to_rfc3339());
+  overflow
+}
+
+pub(crate) fn migrate_seen_state(
+  state: &mut SeenState,
+  issue_runs: &HashMap<String, StoreIssueRuns>,
+  cap: usize,
+) -> bool {
+  let mut changed = false;
+  for (store_id, seen) in state.stores.iter_mut() {
+    let runs = issue_runs.get(store_id).cloned().unwrap_or_default();
+    for (legacy, seen_runs, known) in [
+      (
+        seen.last_seen_failure_run_id.take(),
+        &mut seen.failures,
+        &runs.failures,
+      ),
+      (
+        seen.last_seen_warning_run_id.take(),
+        &mut seen.warnings,
+        &runs.warnings,
+      ),
+    ] {
+      let legacy = match legacy {
+        Some(legacy) => legacy,
+        None => continue,
+      };
+      let legacy_run = known
+        .iter()
+        .find(|run| run.id == legacy)
+        .cloned()
+        .unwrap_or_else(|| RunId::new(&legacy, None, None));
+      let older = known
+        .iter()
+        .filter(|run| **run <= legacy_run)
+        .cloned()
+        .collect::<Vec<_>>();
+      mark_runs_seen(seen_runs, &older, cap);
+      if !seen_runs.run_ids.contains(&legacy) {
+        seen_runs.run_ids.push(legacy);
+      }
+      changed = true;
+    }
+  }
+  changed
+}
+
+// Moves entries for stores missing from the config into `orphaned`, restores
+// orphans whose store is back, and drops orphans from an older app version.
+// Returns a log line per change.
+pub(crate) fn prune_orphaned_stores(
+  state: &mut SeenState,
+  config: &AppConfig,
+  app_version: &str,
+) -> Vec<String> {
+  let mut pruned = Vec::new();
+  // An empty registry means stores.json failed to load, not that every store was removed.
+  if config.stores.is_empty() {
+    return pruned;
+  }
+  for store in &config.stores {
+    if let Some(orphan) = state.orphaned.remove(&store.id) {
+      state.stores.entry(store.id.clone()).or_insert(orphan.state);
+      pruned.push(format!(
+        "{}: store is back, restored its orphaned state",
+        store.id
+      ));
+    }
+  }
+  state.orphaned.retain(|store_id, orphan| {
+    let keep = orphan.app_version == app_version;
+    if !keep {
+      pruned.push(format!(
+        "{}: dropped state orphaned under version {}",
+        store_id, orphan.app_version
+      ));
+    }
+    keep
+  });
+  let removed = state
+    .stores
+    .keys()
+    .filter(|key| {
+      !config
+        .stores
+        .iter()
+        .any(|store| store.id == seen_key_store(key))
+    })
+    .cloned()
+    .collect::<Vec<_>>();
+  for store_id in removed {
+    if let Some(seen) = state.stores.remove(&store_id) {
+      state.orphaned.insert(
+        store_id.clone(),
+        OrphanedStore {
+          app_version: app_version.to_string(),
+          orphaned_at: chrono::Utc::now().to_rfc3339(),
+          state: seen,
+        },
+      );
+      pruned.push(format!(
+        "{}: store is not configured, moved its state to orphaned",
+        store_id
+      ));
+    }
+  }
+  pruned
+}
+
+// Applies the configured caps to state files written with a larger cap (or
+// before notified ids were capped).
+pub(crate) fn cap_run_id_sets(
+  state: &mut SeenState,
+  config: &AppConfig,
+  issue_runs: &HashMap<String, StoreIssueRuns>,
+) -> Vec<String> {
+  let mut pruned = Vec::new();
+  for (store_id, seen) in state.stores.iter_mut() {
+    let runs = issue_runs.get(store_id).cloned().unwrap_or_default();
+    let failures = cap_seen_runs(&mut seen.failures, &runs.failures, config.seen_run_ids_cap);
+    let warnings = cap_seen_runs(&mut seen.warnings, &runs.warnings, config.seen_run_ids_cap);
+    let notified = seen
+      .notified_run_ids
+      .len()
+      .saturating_sub(config.notified_run_ids_cap);
+    seen.notified_run_ids.drain(..notified);
+    if failures + warnings + notified > 0 {
+      pruned.push(format!(
+        "{}: trimmed {} seen failure, {} seen warning and {} notified run ids",
+        store_id, failures, warnings, notified
+      ));
+    }
+  }
+  pruned
+}
+
+// Cleanup applied to every state that gets loaded, whether at startup or from a
+// restored backup.
+pub(crate) fn prepare_loaded_state(state: &mut SeenState, config: &AppConfig) -> bool {
+  let issue_runs = issue_runs_for(config);
+  let mut pruned = prune_orphaned_stores(state, config, APP_VERSION);
+  pruned.extend(cap_run_id_sets(state, config, &issue_runs));
+  for line in &pruned {
+    info!("state prune: {}", line);
+  }
+  let pruned_tags = prune_file_tags(state, config);
+  let migrated = migrate_seen_state(state, &issue_runs, config.seen_run_ids_cap);
+  prune_expired_snoozes(state) || pruned_tags || migrated || !pruned.is_empty()
+}
+
+pub(crate) fn unread_run_ids(
+  runs: &[RunId],
+  seen: &SeenRuns,
+  window: Option<Duration>,
+) -> Vec<String> {
+  runs
+    .iter()
+    .filter(|run| {
+      let in_window = match (window, run.modified) {
+        (Some(window), Some(modified)) => {
+          modified.elapsed().map(|age| age <= window).unwrap_or(true)
+        }
+        _ => true,
+      };
+      !is_run_seen(seen, run) && in_window
+    })
+    .map(|run| run.id.clone())
+    .collect()
+}
+
+pub(crate) fn unread_badges(
+  config: &AppConfig,
+  issue_runs: &HashMap<String, StoreIssueRuns>,
+  state: &SeenState,
+) -> Vec<UnreadBadge> {
+  let window = config
+    .badge_window_days
+    .map(|days| Duration::from_secs(days * 86_400));
+  let mut badges = Vec::new();
+  for store in &config.stores {
+    let seen = state
+      .stores
+      .get(&config.seen_key(&store.id))
+      .cloned()
+      .unwrap_or_default();
+    let runs = issue_runs.get(&store.id).cloned().unwrap_or_default();
+    let latest_failure = latest_run_id(&runs.failures);
+    let latest_warning = latest_run_id(&runs.warnings);
+    let snoozed_until = active_snooze(&seen);
+
+    // Flags and counts use the same window, so a flag never shows with a 0 count.
+    let warnings_unread_count = unread_run_ids(&runs.warnings, &seen.warnings, window).len();
+    let failures_unread_count = unread_run_ids(&runs.failures, &seen.failures, window).len();
+
+    badges.push(UnreadBadge {
+      store_id: store.id.clone(),
+      warnings_unread: snoozed_until.is_none() && warnings_unread_count > 0,
+      failures_unread: snoozed_until.is_none() && failures_unread_count > 0,
+      warnings_unread_count,
+      failures_unread_count,
+      last_warning_run_id: latest_warning,
+      last_failure_run_id: latest_failure,
+      latest_warning_count: runs
+        .latest_warning
+        .as_ref()
+        .map(|summary| summary.warning_count)
+        .unwrap_or(0),
+      latest_failure_count: runs
+        .latest_failure
+        .as_ref()
+        .map(|summary| summary.failure_count)
+        .unwrap_or(0),
+      snoozed_until,
+      processed_unseen_count: processed_unseen_count(&runs, &seen),
+      stale: false,
+    });
+  }
+  badges
+}
+
+pub(crate) fn mark_store_seen(
+  seen: &mut StoreSeenState,
+  runs: &StoreIssueRuns,
+  kind: Option<&str>,
+  cap: usize,
+) -> Result<(), String> {
+  let latest_processed = runs.processed.iter().max_by(|a, b| a.run_id.cmp(&b.run_id));
+  match kind {
+    None => {
+      mark_runs_seen(&mut seen.failures, &runs.failures, cap);
+      mark_runs_seen(&mut seen.warnings, &runs.warnings, cap);
+      if let Some(latest) = latest_processed {
+        seen.last_seen_processed_run_id = Some(latest.run_id.id.clone());
+      }
+    }
+    Some("failures") => mark_runs_seen(&mut seen.failures, &runs.failures, cap),
+    Some("warnings") => mark_runs_seen(&mut seen.warnings, &runs.warnings, cap),
+    Some("processed") => {
+      if let Some(latest) = latest_processed {
+        seen.last_seen_processed_run_id = Some(latest.run_id.id.clone());
+      }
+    }
+    Some(other) => return Err(format!("Unknown badge kind: {}", other)),
+  }
+  Ok(())
+}
+
+// Marks a configured store's badges seen under the key `unread_badges` reads;
+// an unknown store fails before any entry is created.
+pub(crate) fn mark_badges_seen(
+  state: &mut SeenState,
+  config: &AppConfig,
+  issue_runs: &HashMap<String, StoreIssueRuns>,
+  store_id: &str,
+  kind: Option<&str>,
+) -> Result<(), AppError> {
+  require_store(config, store_id)?;
+  let runs = issue_runs.get(store_id).cloned().unwrap_or_default();
+  let entry = state.stores.entry(config.seen_key(store_id)).or_default();
+  mark_store_seen(entry, &runs, kind, config.seen_run_ids_cap).map_err(AppError::InvalidInput)
+}
+
+// Every badge kind, processed included, for the given stores (all when None).
+pub(crate) fn mark_all_seen(
+  state: &mut SeenState,
+  config: &AppConfig,
+  issue_runs: &HashMap<String, StoreIssueRuns>,
+  store_ids: Option<&[String]>,
+) -> Result<(), AppError> {
+  for store in &config.stores {
+    if store_ids.is_some_and(|ids| !ids.contains(&store.id)) {
+      continue;
+    }
+    mark_badges_seen(state, config, issue_runs, &store.id, None)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+  use std::time::SystemTime;
+
+  fn processed_at(id: &str, count: usize) -> RunSummary {
+    RunSummary {
+      run_id: run_at(id, 0),
+      stores: vec!["kaufland".to_string()],
+      failure_count: 0,
+      warning_count: 0,
+      processed_count: Some(count),
+    }
+  }
+
+  #[test]
+  fn processed_unseen_count_resets_on_mark_seen() {
+    let config = badge_config("kaufland");
+    let mut issue_runs = issue_fixture("kaufland");
+    let mut state = SeenState::default();
+    assert_eq!(
+      unread_badges(&config, &issue_runs, &state)[0].processed_unseen_count,
+      None
+    );
+
+    issue_runs.get_mut("kaufland").expect("runs").processed = vec![
+      processed_at("20240105-093000", 3),
+      processed_at("20240106-093000", 2),
+    ];
+    assert_eq!(
+      unread_badges(&config, &issue_runs, &state)[0].processed_unseen_count,
+      Some(5)
+    );
+
+    mark_store_seen(
+      kaufland_seen(&mut state),
+      &issue_runs["kaufland"],
+      Some("failures"),
+      config.seen_run_ids_cap,
+    )
+    .expect("mark");
+    assert_eq!(
+      unread_badges(&config, &issue_runs, &state)[0].processed_unseen_count,
+      Some(5)
+    );
+
+    mark_store_seen(
+      kaufland_seen(&mut state),
+      &issue_runs["kaufland"],
+      None,
+      config.seen_run_ids_cap,
+    )
+    .expect("mark");
+    assert_eq!(
+      unread_badges(&config, &issue_runs, &state)[0].processed_unseen_count,
+      Some(0)
+    );
+
+    issue_runs
+      .get_mut("kaufland")
+      .expect("runs")
+      .processed
+      .push(processed_at("20240107-093000", 4));
+    assert_eq!(
+      unread_badges(&config, &issue_runs, &state)[0].processed_unseen_count,
+      Some(4)
+    );
+  }
+
+  #[test]
+  fn marking_one_kind_leaves_the_other_unread() {
+    let config = badge_config("kaufland");
+    let issue_runs = issue_fixture("kaufland");
+    let mut state = SeenState::default();
+    let seen = state.stores.entry("kaufland".to_string()).or_default();
+    mark_store_seen(
+      seen,
+      &issue_runs["kaufland"],
+      Some("warnings"),
+      config.seen_run_ids_cap,
+    )
+    .expect("mark warnings");
+    let badge = &unread_badges(&config, &issue_runs, &state)[0];
+    assert!(!badge.warnings_unread);
+    assert!(badge.failures_unread);
+    assert_eq!(badge.failures_unread_count, 2);
+
+    let seen = state.stores.get_mut("kaufland").expect("seen entry");
+    mark_store_seen(
+      seen,
+      &issue_runs["kaufland"],
+      Some("failures"),
+      config.seen_run_ids_cap,
+    )
+    .expect("mark failures");
+    let badge = &unread_badges(&config, &issue_runs, &state)[0];
+    assert!(!badge.failures_unread);
+    assert_eq!(badge.failures_unread_count, 0);
+    assert!(mark_store_seen(
+      kaufland_seen(&mut state),
+      &issue_runs["kaufland"],
+      Some("both"),
+      config.seen_run_ids_cap
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn marking_an_unknown_store_leaves_the_state_alone() {
+    let mut config = badge_config("kaufland");
+    let issue_runs = issue_fixture("kaufland");
+    let mut state = SeenState::default();
+    let err = mark_badges_seen(&mut state, &config, &issue_runs, "penny", None).unwrap_err();
+    assert_eq!(err.code(), "unknown_store");
+    assert!(state.stores.is_empty());
+
+    // Marked under the same key the badges are read from.
+    config.root_override = Some("/mnt/receipts".to_string());
+    mark_badges_seen(&mut state, &config, &issue_runs, "kaufland", None).expect("mark");
+    assert!(state.stores.contains_key(&config.seen_key("kaufland")));
+    let badge = &unread_badges(&config, &issue_runs, &state)[0];
+    assert!(!badge.failures_unread && !badge.warnings_unread);
+  }
+
+  #[test]
+  fn mark_all_seen_clears_the_processed_count_too() {
+    let mut config = badge_config("kaufland");
+    config.stores.push(StoreConfig {
+      id: "penny".to_string(),
+      ..config.stores[0].clone()
+    });
+    let mut issue_runs = issue_fixture("kaufland");
+    issue_runs.get_mut("kaufland").expect("runs").processed =
+      vec![processed_at("20240105-093000", 4)];
+    issue_runs.insert("penny".to_string(), issue_runs["kaufland"].clone());
+    let mut state = SeenState::default();
+
+    let only = ["kaufland".to_string()];
+    mark_all_seen(&mut state, &config, &issue_runs, Some(&only)).expect("mark");
+    let badges = unread_badges(&config, &issue_runs, &state);
+    assert_eq!(badges[0].processed_unseen_count, Some(0));
+    assert!(!badges[0].failures_unread && !badges[0].warnings_unread);
+    assert_eq!(badges[1].processed_unseen_count, Some(4));
+    assert!(badges[1].failures_unread);
+
+    mark_all_seen(&mut state, &config, &issue_runs, None).expect("mark all");
+    let badges = unread_badges(&config, &issue_runs, &state);
+    assert_eq!(badges[1].processed_unseen_count, Some(0));
+    assert_eq!(
+      state.stores["penny"].last_seen_processed_run_id.as_deref(),
+      Some("20240105-093000")
+    );
+  }
+
+  #[test]
+  fn badge_window_applies_to_flags_and_counts_alike() {
+    let mut config = badge_config("kaufland");
+    config.badge_window_days = Some(7);
+    let mut issue_runs = issue_fixture("kaufland");
+    let state = SeenState::default();
+
+    let badge = &unread_badges(&config, &issue_runs, &state)[0];
+    assert!(!badge.failures_unread && !badge.warnings_unread);
+    assert_eq!(
+      (badge.failures_unread_count, badge.warnings_unread_count),
+      (0, 0)
+    );
+
+    let recent = RunId::new(
+      "20240107-093000",
+      None,
+      Some(SystemTime::now() - Duration::from_secs(3_600)),
+    );
+    issue_runs
+      .get_mut("kaufland")
+      .expect("runs")
+      .failures
+      .push(recent);
+    let badge = &unread_badges(&config, &issue_runs, &state)[0];
+    assert!(badge.failures_unread && !badge.warnings_unread);
+    assert_eq!(badge.failures_unread_count, 1);
+  }
+
+  fn kaufland_seen(state: &mut SeenState) -> &mut StoreSeenState {
+    state.stores.entry("kaufland".to_string()).or_default()
+  }
+
+  #[test]
+  fn partial_mark_seen_under_snooze() {
+    let config = badge_config("kaufland");
+    let issue_runs = issue_fixture("kaufland");
+    let mut state = SeenState::default();
+    let until = (chrono::Utc::now() + chrono::Duration::hours(2)).to_rfc3339();
+    kaufland_seen(&mut state).snoozed_until = Some(until.clone());
+
+    let badge = &unread_badges(&config, &issue_runs, &state)[0];
+    assert_eq!(badge.snoozed_until.as_deref(), Some(until.as_str()));
+    assert!(!badge.failures_unread && !badge.warnings_unread);
+    assert_eq!(badge.failures_unread_count, 2);
+    assert_eq!(badge.warnings_unread_count, 1);
+
+    mark_store_seen(
+      kaufland_seen(&mut state),
+      &issue_runs["kaufland"],
+      Some("warnings"),
+      config.seen_run_ids_cap,
+    )
+    .expect("mark");
+    let badge = &unread_badges(&config, &issue_runs, &state)[0];
+    assert!(!badge.failures_unread);
+    assert_eq!(badge.warnings_unread_count, 0);
+    assert_eq!(badge.failures_unread_count, 2);
+
+    kaufland_seen(&mut state).snoozed_until = None;
+    let badge = &unread_badges(&config, &issue_runs, &state)[0];
+    assert!(badge.snoozed_until.is_none());
+    assert!(badge.failures_unread);
+    assert!(!badge.warnings_unread);
+  }
+
+  // The startup path: load_state_from (which refreshes the .bak), then the
+  // load-time pruning. Returns whether pruning changed anything.
+  fn load_state_fixture(raw: &str, config: &AppConfig) -> (SeenState, bool) {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join(STATE_FILE);
+    fs::write(&path, raw).expect("write state fixture");
+    let (mut state, message) = load_state_from(&path);
+    assert!(message.is_none(), "fixture failed to load: {:?}", message);
+    assert!(path.with_extension("json.bak").is_file());
+    let changed = prepare_loaded_state(&mut state, config);
+    (state, changed)
+  }
+
+  #[test]
+  fn load_moves_removed_stores_to_orphaned_for_one_version() {
+    let config = badge_config("kaufland");
+    let (mut state, pruned) = load_state_fixture(
+      r#"{
+      "stores": {
+        "kaufland": { "failures": { "run_ids": ["20240105-093000"] } },
+        "penny": { "warnings": { "run_ids": ["20240101-080000"] } }
+      },
+      "orphaned": {
+        "profi": { "app_version": "0.0.0-old", "orphaned_at": "2024-01-01T00:00:00Z", "state": {} }
+      }
+    }"#,
+      &config,
+    );
+    assert!(pruned);
+    assert_eq!(state.stores.keys().collect::<Vec<_>>(), vec!["kaufland"]);
+    assert_eq!(state.orphaned.keys().collect::<Vec<_>>(), vec!["penny"]);
+    assert_eq!(state.orphaned["penny"].app_version, APP_VERSION);
+    assert!(!prepare_loaded_state(&mut state, &config));
+    assert!(state.orphaned.contains_key("penny"));
+
+    let mut config = config;
+    let penny = StoreConfig {
+      id: "penny".to_string(),
+      ..config.stores[0].clone()
+    };
+    config.stores.push(penny);
+    prepare_loaded_state(&mut state, &config);
+    assert!(state.orphaned.is_empty());
+    assert_eq!(
+      state.stores["penny"].warnings.run_ids,
+      vec!["20240101-080000".to_string()]
+    );
+
+    let raw = serde_json::to_string(&state).expect("serialize");
+    assert!(!raw.contains("orphaned"));
+  }
+
+  #[test]
+  fn load_caps_seen_and_notified_run_ids() {
+    let mut config = badge_config("kaufland");
+    config.seen_run_ids_cap = 2;
+    config.notified_run_ids_cap = 1;
+    let (mut state, pruned) = load_state_fixture(
+      r#"{
+      "stores": {
+        "kaufland": {
+          "failures": { "run_ids": ["a", "b", "c", "d"] },
+          "notified_run_ids": ["a", "b", "c"]
+        }
+      }
+    }"#,
+      &config,
+    );
+    assert!(pruned);
+    let seen = &state.stores["kaufland"];
+    assert_eq!(
+      seen.failures.run_ids,
+      vec!["c".to_string(), "d".to_string()]
+    );
+    assert_eq!(seen.notified_run_ids, vec!["c".to_string()]);
+    assert!(!prepare_loaded_state(&mut state, &config));
+  }
+
+  #[test]
+  fn badges_from_a_fixture_tree_clear_after_mark_seen() {
+    let dir = runs_fixture(&[
+      (
+        "20240101-093000.summary.json",
+        r#"{"run_id": "20240101-093000", "stores": ["lidl"], "failures": [{}, {}]}"#,
+      ),
+      (
+        "20240102-093000.summary.json",
+        r#"{"run_id": "20240102-093000", "stores": ["lidl"], "warnings": [{}]}"#,
+      ),
+      (
+        "20240103-093000.summary.json",
+        r#"{"run_id": "20240103-093000", "stores": ["lidl", "kaufland"], "failures": [{}]}"#,
+      ),
+    ]);
+    let mut config = badge_config("lidl");
+    config.receipts_root = dir.path().to_string_lossy().to_string();
+    let issue_runs = issue_runs_by_store(&load_run_summaries(&config.receipts_root));
+    let mut state = SeenState::default();
+
+    let badges = unread_badges(&config, &issue_runs, &state);
+    assert_eq!(badges.len(), 1);
+    assert!(badges[0].failures_unread && badges[0].warnings_unread);
+    assert_eq!(badges[0].failures_unread_count, 2);
+    assert_eq!(badges[0].warnings_unread_count, 1);
+    assert_eq!(
+      badges[0].last_failure_run_id.as_deref(),
+      Some("20240103-093000")
+    );
+    assert_eq!(badges[0].latest_failure_count, 1);
+
+    let seen = state.stores.entry("lidl".to_string()).or_default();
+    mark_store_seen(seen, &issue_runs["lidl"], None, DEFAULT_SEEN_RUN_IDS_CAP).expect("mark seen");
+    let badges = unread_badges(&config, &issue_runs, &state);
+    assert!(!badges[0].failures_unread && !badges[0].warnings_unread);
+    assert_eq!(badges[0].failures_unread_count, 0);
+    assert_eq!(badges[0].warnings_unread_count, 0);
+  }
+
+  #[test]
+  fn file_tags_are_pruned_per_root_and_kept_when_the_root_is_missing() {
+    let configured = tempfile::tempdir().expect("configured");
+    let other = tempfile::tempdir().expect("other");
+    for root in [configured.path(), other.path()] {
+      let inbox = root.join("inbox").join("lidl");
+      fs::create_dir_all(&inbox).expect("inbox");
+      fs::write(inbox.join("kept.jpg"), "1234").expect("write");
+    }
+    let mut config = badge_config("lidl");
+    config.receipts_root = configured.path().to_string_lossy().to_string();
+    let tags = |names: &[&str]| -> HashMap<String, FileTags> {
+      names
+        .iter()
+        .map(|name| {
+          let entry = FileTags {
+            size: 4,
+            tags: vec!["tax".to_string()],
+          };
+          (name.to_string(), entry)
+        })
+        .collect()
+    };
+    let other_key = format!("lidl@{}", other.path().to_string_lossy());
+    let gone_key = "lidl@/nowhere/mounted".to_string();
+    let mut state = SeenState::default();
+    for key in ["lidl".to_string(), other_key.clone(), gone_key.clone()] {
+      state.stores.entry(key).or_default().file_tags = tags(&["kept.jpg", "gone.jpg"]);
+    }
+
+    assert!(prune_file_tags(&mut state, &config));
+    assert_eq!(state.stores["lidl"].file_tags.len(), 1);
+    assert!(state.stores[&other_key].file_tags.contains_key("kept.jpg"));
+    assert_eq!(state.stores[&other_key].file_tags.len(), 1);
+    assert_eq!(state.stores[&gone_key].file_tags.len(), 2);
+    assert!(!prune_file_tags(&mut state, &config));
+  }
+
+  #[test]
+  fn state_round_trips_and_recovers_from_a_corrupt_file() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("nested").join(STATE_FILE);
+    let mut state = SeenState::default();
+    let seen = state.stores.entry("lidl".to_string()).or_default();
+    seen.failures.run_ids = vec!["20240101-093000".to_string()];
+    seen.snoozed_until = Some("2024-01-02T00:00:00Z".to_string());
+    seen.file_tags.insert(
+      "receipt.pdf".to_string(),
+      FileTags {
+        size: 8,
+        tags: vec!["tax".to_string()],
+      },
+    );
+
+    save_state_to(&path, &state).expect("save state");
+    let (loaded, message) = load_state_from(&path);
+    assert!(message.is_none());
+    assert_eq!(
+      serde_json::to_value(&loaded).unwrap(),
+      serde_json::to_value(&state).unwrap()
+    );
+
+    save_state_to(&path, &SeenState::default()).expect("save again");
+    assert!(path.with_extension("json.1").exists());
+    fs::write(&path, "{").expect("corrupt state");
+    let (recovered, message) = load_state_from(&path);
+    assert!(message.expect("recovery message").contains("restored from"));
+    assert_eq!(
+      recovered.stores["lidl"].failures.run_ids,
+      vec!["20240101-093000"]
+    );
+
+    let (empty, message) = load_state_from(&dir.path().join("missing.json"));
+    assert!(empty.stores.is_empty() && message.is_none());
+  }
+
+  #[test]
+  fn saves_keep_the_last_versions_newest_first() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join(STATE_FILE);
+    let version = |n: usize| {
+      let mut state = SeenState::default();
+      state
+        .stores
+        .entry("lidl".to_string())
+        .or_default()
+        .failures
+        .run_ids = vec![n.to_string()];
+      state
+    };
+    let saved_version = |name: &str| {
+      read_state_backup(dir.path(), name).expect("backup").stores["lidl"]
+        .failures
+        .run_ids
+        .clone()
+    };
+    for n in 1..=STATE_BACKUP_COUNT + 2 {
+      save_state_to(&path, &version(n)).expect("save");
+    }
+
+    let latest = STATE_BACKUP_COUNT + 2;
+    assert_eq!(
+      saved_version(&format!("{}.1", STATE_FILE)),
+      vec![(latest - 1).to_string()]
+    );
+    assert_eq!(
+      saved_version(&format!("{}.{}", STATE_FILE, STATE_BACKUP_COUNT)),
+      vec![(latest - STATE_BACKUP_COUNT).to_string()]
+    );
+    assert!(!path
+      .with_extension(format!("json.{}", STATE_BACKUP_COUNT + 1))
+      .exists());
+
+    fs::write(path.with_extension("json.2"), "{").expect("corrupt backup");
+    let backups = state_backups_in(dir.path());
+    assert_eq!(backups.len(), STATE_BACKUP_COUNT);
+    assert_eq!(
+      backups
+        .iter()
+        .filter(|backup| !backup.valid)
+        .map(|backup| backup.name.as_str())
+        .collect::<Vec<_>>(),
+      vec!["state.json.2"]
+    );
+    assert!(matches!(
+      read_state_backup(dir.path(), "state.json.2"),
+      Err(AppError::Parse { .. })
+    ));
+    assert!(matches!(
+      read_state_backup(dir.path(), "../state.json"),
+      Err(AppError::InvalidInput(_))
+    ));
+  }
+}
@@ -0,0 +1,78 @@
+// Fixtures shared by the module tests.
+use crate::*;
+use std::time::SystemTime;
+
+pub(crate) fn run_at(id: &str, mtime_offset: u64) -> RunId {
+  RunId::new(
+    id,
+    None,
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + mtime_offset)),
+  )
+}
+
+pub(crate) fn badge_config(store_id: &str) -> AppConfig {
+  AppConfig {
+    receipts_root: String::new(),
+    worker_dir: None,
+    worker_run_cmd: None,
+    import_name_template: DEFAULT_IMPORT_NAME_TEMPLATE.to_string(),
+    worker_supports_file_args: false,
+    backlog_warn_count: None,
+    backlog_warn_days: None,
+    inbox_cache_ttl_secs: DEFAULT_INBOX_CACHE_TTL_SECS,
+    badge_window_days: None,
+    badge_metric: BADGE_METRICS[0].to_string(),
+    notifications_enabled: false,
+    notify_warnings: false,
+    quiet_hours_start: None,
+    quiet_hours_end: None,
+    minimize_to_tray: false,
+    seen_run_ids_cap: DEFAULT_SEEN_RUN_IDS_CAP,
+    notified_run_ids_cap: DEFAULT_NOTIFIED_RUN_IDS_CAP,
+    update_check_hours: None,
+    currency_rates: CurrencyRates::new(),
+    api_enabled: false,
+    api_port: DEFAULT_API_PORT,
+    webhooks: Vec::new(),
+    budgets: Budgets::default(),
+    summary_dir: None,
+    publish_summary_after_run: false,
+    stores: vec![StoreConfig {
+      id: store_id.to_string(),
+      name: store_id.to_string(),
+      enabled: true,
+      backlog_warn_count: None,
+      backlog_warn_days: None,
+      notifications_enabled: None,
+    }],
+    config_ready: false,
+    home_available: true,
+    root_override: None,
+    resolved_roots: None,
+  }
+}
+
+pub(crate) fn issue_fixture(store_id: &str) -> HashMap<String, StoreIssueRuns> {
+  let mut map = HashMap::new();
+  map.insert(
+    store_id.to_string(),
+    StoreIssueRuns {
+      failures: vec![run_at("20240105-093000", 0), run_at("20240106-093000", 0)],
+      warnings: vec![run_at("20240105-093000", 0)],
+      latest_failure: None,
+      latest_warning: None,
+      processed: Vec::new(),
+    },
+  );
+  map
+}
+
+pub(crate) fn runs_fixture(files: &[(&str, &str)]) -> tempfile::TempDir {
+  let dir = tempfile::tempdir().expect("tempdir");
+  let runs = dir.path().join("_logs").join("runs");
+  fs::create_dir_all(&runs).expect("runs dir");
+  for (name, raw) in files {
+    fs::write(runs.join(name), raw).expect("run fixture");
+  }
+  dir
+}
@@ -0,0 +1,377 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct TopSpendingEntry {
+  // The store id, the merchant name folded for grouping, or the normalized
+  // product name; `other` for the remainder.
+  pub(crate) key: String,
+  // The store id, the merchant as first seen on a receipt, or the product name.
+  pub(crate) label: String,
+  pub(crate) currency: String,
+  pub(crate) total: f64,
+  // Receipts for stores and merchants, line items for products.
+  pub(crate) count: usize,
+  // Percent of the currency's entry in `totals`.
+  pub(crate) share: f64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct TopSpending {
+  pub(crate) from: Option<chrono::NaiveDate>,
+  pub(crate) to: Option<chrono::NaiveDate>,
+  pub(crate) group_by: String,
+  pub(crate) convert_to: Option<String>,
+  // Receipt totals for stores and merchants, line-item amounts for products.
+  pub(crate) totals: CurrencyTotals,
+  pub(crate) receipt_count: usize,
+  // Largest first within each currency, at most `limit` per currency.
+  pub(crate) entries: Vec<TopSpendingEntry>,
+  // Everything else, one `other` entry per currency.
+  pub(crate) other: Vec<TopSpendingEntry>,
+  // More than TOP_SPENDING_MAX_KEYS distinct keys came up; the ones past the
+  // cap only count towards `other`.
+  pub(crate) overflowed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TopSpendingGroup {
+  Store,
+  Merchant,
+  Product,
+}
+
+impl TopSpendingGroup {
+  pub(crate) fn parse(raw: &str) -> Result<TopSpendingGroup, AppError> {
+    match raw.trim() {
+      "store" => Ok(TopSpendingGroup::Store),
+      "merchant" => Ok(TopSpendingGroup::Merchant),
+      "product" => Ok(TopSpendingGroup::Product),
+      other => Err(AppError::InvalidInput(format!(
+        "group_by must be store, merchant or product: {}",
+        other
+      ))),
+    }
+  }
+
+  pub(crate) fn name(self) -> &'static str {
+    match self {
+      TopSpendingGroup::Store => "store",
+      TopSpendingGroup::Merchant => "merchant",
+      TopSpendingGroup::Product => "product",
+    }
+  }
+}
+
+// (currency, key) -> (label, total, count) for at most `cap` keys. Amounts for
+// keys that don't fit go into a per-currency overflow bucket, so a year of
+// one-off item names can't grow the map without bound.
+pub(crate) struct BoundedSums {
+  pub(crate) cap: usize,
+  pub(crate) sums: HashMap<(String, String), (String, f64, usize)>,
+  pub(crate) overflow: std::collections::BTreeMap<String, (f64, usize)>,
+}
+
+impl BoundedSums {
+  pub(crate) fn new(cap: usize) -> BoundedSums {
+    BoundedSums {
+      cap,
+      sums: HashMap::new(),
+      overflow: std::collections::BTreeMap::new(),
+    }
+  }
+
+  pub(crate) fn add(&mut self, currency: String, key: String, label: &str, amount: f64) {
+    let key = (currency, key);
+    if self.sums.len() >= self.cap && !self.sums.contains_key(&key) {
+      let overflow = self.overflow.entry(key.0).or_default();
+      overflow.0 += amount;
+      overflow.1 += 1;
+      return;
+    }
+    let entry = self
+      .sums
+      .entry(key)
+      .or_insert_with(|| (label.to_string(), 0.0, 0));
+    entry.1 += amount;
+    entry.2 += 1;
+  }
+}
+
+// Case, diacritics and runs of whitespace don't split a group.
+pub(crate) fn fold_key(text: &str) -> String {
+  fold_text(text)
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+// Splits the sums into the top `limit` per currency and one `other` entry per
+// currency for the rest (overflow included).
+pub(crate) fn rank_top_spending(
+  sums: BoundedSums,
+  limit: usize,
+) -> (CurrencyTotals, Vec<TopSpendingEntry>, Vec<TopSpendingEntry>) {
+  let mut totals = CurrencyTotals::new();
+  let mut by_currency = std::collections::BTreeMap::<String, Vec<TopSpendingEntry>>::new();
+  for ((currency, key), (label, total, count)) in sums.sums {
+    add_amount(&mut totals, currency.clone(), total);
+    by_currency
+      .entry(currency.clone())
+      .or_default()
+      .push(TopSpendingEntry {
+        key,
+        label,
+        currency,
+        total: round_cents(total),
+        count,
+        share: 0.0,
+      });
+  }
+  let mut rest = sums.overflow;
+  for (currency, (total, _)) in &rest {
+    add_amount(&mut totals, currency.clone(), *total);
+    by_currency.entry(currency.clone()).or_default();
+  }
+  let share = |total: f64, currency: &str| {
+    let overall = totals.get(currency).copied().unwrap_or(0.0);
+    if overall == 0.0 {
+      0.0
+    } else {
+      (total / overall * 1000.0).round() / 10.0
+    }
+  };
+  let mut entries = Vec::new();
+  let mut other = Vec::new();
+  for (currency, mut ranked) in by_currency {
+    ranked.sort_by(|a, b| {
+      b.total
+        .partial_cmp(&a.total)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| a.key.cmp(&b.key))
+    });
+    let (mut remainder, mut remainder_count) = rest.remove(&currency).unwrap_or((0.0, 0));
+    for (rank, mut entry) in ranked.into_iter().enumerate() {
+      if rank < limit {
+        entry.share = share(entry.total, &currency);
+        entries.push(entry);
+      } else {
+        remainder += entry.total;
+        remainder_count += entry.count;
+      }
+    }
+    if remainder_count > 0 {
+      other.push(TopSpendingEntry {
+        key: "other".to_string(),
+        label: "Other".to_string(),
+        share: share(remainder, &currency),
+        currency,
+        total: round_cents(remainder),
+        count: remainder_count,
+      });
+    }
+  }
+  (rounded_totals(totals), entries, other)
+}
+
+// Reads every processed output in range; nothing under processed/ is written.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn top_spending(
+  receipts_root: &str,
+  store_ids: &[String],
+  from: Option<chrono::NaiveDate>,
+  to: Option<chrono::NaiveDate>,
+  group_by: TopSpendingGroup,
+  limit: usize,
+  max_keys: usize,
+  conversion: &Conversion,
+) -> TopSpending {
+  let mut sums = BoundedSums::new(max_keys);
+  let mut receipt_count = 0;
+  for store_id in store_ids {
+    let dir = processed_dir(receipts_root, store_id);
+    // Sorted so a merchant's label and what fits under the cap don't depend
+    // on directory order.
+    let mut names = receipt_output_names(&dir);
+    names.sort();
+    for name in names {
+      let path = join_file_id(&dir, &name);
+      let value = match fs::read_to_string(&path) {
+        Ok(raw) => serde_json::from_str::<Value>(&raw).unwrap_or(Value::Null),
+        Err(_) => continue,
+      };
+      let receipt = match receipt_total_from(store_id, &path, &value) {
+        Some(receipt) => receipt,
+        None => continue,
+      };
+      let in_range = receipt
+        .date
+        .is_some_and(|date| from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to));
+      if !in_range {
+        continue;
+      }
+      receipt_count += 1;
+      let currency = receipt_currency(&receipt);
+      match group_by {
+        TopSpendingGroup::Store | TopSpendingGroup::Merchant => {
+          let total = match receipt.total {
+            Some(total) => total,
+            None => continue,
+          };
+          let label = match group_by {
+            TopSpendingGroup::Store => store_id.clone(),
+            _ => value
+              .get("merchant")
+              .and_then(|merchant| merchant.get("name"))
+              .and_then(|name| name.as_str())
+              .map(str::trim)
+              .filter(|name| !name.is_empty())
+              .unwrap_or("Unknown")
+              .to_string(),
+          };
+          let key = match group_by {
+            TopSpendingGroup::Store => label.clone(),
+            _ => fold_key(&label),
+          };
+          let (bucket, converted) = conversion.apply(&currency, total);
+          sums.add(bucket, key, &label, converted);
+        }
+        TopSpendingGroup::Product => {
+          for item in value
+            .get("items")
+            .and_then(|items| items.as_array())
+            .into_iter()
+            .flatten()
+          {
+            let (name, amount) = match (
+              item.get("name").and_then(|name| name.as_str()),
+              item_amount(item),
+            ) {
+              (Some(name), Some(amount)) => (normalize_item_name(name), amount),
+              _ => continue,
+            };
+            if name.is_empty() {
+              continue;
+            }
+            let (bucket, converted) = conversion.apply(&currency, amount);
+            sums.add(bucket, name.clone(), &name, converted);
+          }
+        }
+      }
+    }
+  }
+  let overflowed = !sums.overflow.is_empty();
+  let (totals, entries, other) = rank_top_spending(sums, limit);
+  TopSpending {
+    from,
+    to,
+    group_by: group_by.name().to_string(),
+    convert_to: conversion.target.clone(),
+    totals,
+    receipt_count,
+    entries,
+    other,
+    overflowed,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn top_spending_ranks_groups_and_caps_distinct_products() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    for (store_id, name, raw) in [
+      (
+        "lidl",
+        "a.jpg.json",
+        r#"{"timestamp": "2024-02-01T10:00:00", "total": 60, "currency": "RON",
+          "merchant": {"name": "Lidl Cluj"},
+          "items": [{"name": "LAPTE 1.5% 1 L", "paid_amount": 10},
+                    {"name": "Paine", "paid_amount": 50}]}"#,
+      ),
+      (
+        "emag",
+        "b.jpg.json",
+        r#"{"timestamp": "2024-02-03T10:00:00", "total": 30, "currency": "RON",
+          "merchant": {"name": "Vânzător  Extern"},
+          "items": [{"name": "Lapte 1,5% 1L", "paid_amount": 12},
+                    {"name": "Cablu USB", "paid_amount": 18}]}"#,
+      ),
+      (
+        "emag",
+        "c.jpg.json",
+        r#"{"timestamp": "2024-02-04T10:00:00", "total": 10, "currency": "RON",
+          "merchant": {"name": "vanzator extern"},
+          "items": [{"name": "Baterii", "paid_amount": 10}]}"#,
+      ),
+      (
+        "emag",
+        "d.jpg.json",
+        r#"{"timestamp": "2023-12-04T10:00:00", "total": 999, "currency": "RON"}"#,
+      ),
+    ] {
+      let processed = dir.path().join("processed").join(store_id);
+      fs::create_dir_all(&processed).expect("processed");
+      fs::write(processed.join(name), raw).expect("output");
+    }
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string(), "emag".to_string()];
+    let from = chrono::NaiveDate::from_ymd_opt(2024, 1, 1);
+    let same = Conversion::default();
+    let top = |group_by: &str, limit: usize, max_keys: usize| {
+      let group_by = TopSpendingGroup::parse(group_by).expect("group_by");
+      top_spending(&root, &stores, from, None, group_by, limit, max_keys, &same)
+    };
+    let summary = |entries: &[TopSpendingEntry]| {
+      entries
+        .iter()
+        .map(|entry| (entry.label.clone(), entry.total, entry.count, entry.share))
+        .collect::<Vec<_>>()
+    };
+
+    let by_store = top("store", 10, 100);
+    assert_eq!(by_store.receipt_count, 3);
+    assert_eq!(
+      summary(&by_store.entries),
+      vec![
+        ("lidl".to_string(), 60.0, 1, 60.0),
+        ("emag".to_string(), 40.0, 2, 40.0)
+      ]
+    );
+    let by_merchant = top("merchant", 1, 100);
+    assert_eq!(
+      summary(&by_merchant.entries),
+      vec![("Lidl Cluj".to_string(), 60.0, 1, 60.0)]
+    );
+    assert_eq!(
+      summary(&by_merchant.other),
+      vec![("Other".to_string(), 40.0, 2, 40.0)]
+    );
+
+    let by_product = top("product", 10, 100);
+    assert_eq!(
+      by_product.totals,
+      CurrencyTotals::from([("RON".to_string(), 100.0)])
+    );
+    assert_eq!(by_product.entries[1].label, "lapte 1.5% 1l");
+    assert_eq!(
+      (by_product.entries[1].total, by_product.entries[1].count),
+      (22.0, 2)
+    );
+    assert!(!by_product.overflowed);
+    // Two keys fit; the items seen after that only add to `other`.
+    let capped = top("product", 10, 2);
+    assert!(capped.overflowed);
+    assert_eq!(capped.entries.len(), 2);
+    assert_eq!(capped.totals, by_product.totals);
+    assert_eq!(
+      capped.other[0].total + capped.entries.iter().map(|entry| entry.total).sum::<f64>(),
+      100.0
+    );
+    assert_eq!(
+      TopSpendingGroup::parse("category").unwrap_err().code(),
+      "invalid_input"
+    );
+  }
+}
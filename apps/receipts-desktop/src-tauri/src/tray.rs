@@ -0,0 +1,627 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct StoreStatus {
+  pub(crate) store_id: String,
+  pub(crate) name: String,
+  pub(crate) enabled: bool,
+  pub(crate) inbox_count: usize,
+  pub(crate) oldest_pending_days: Option<u64>,
+  pub(crate) failures_unread: bool,
+  pub(crate) warnings_unread: bool,
+  pub(crate) failures_unread_count: usize,
+  pub(crate) warnings_unread_count: usize,
+  pub(crate) snoozed_until: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct StatusTotals {
+  pub(crate) inbox_count: usize,
+  pub(crate) failures_unread_count: usize,
+  pub(crate) warnings_unread_count: usize,
+  pub(crate) stores_with_issues: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct StatusSnapshot {
+  pub(crate) generated_at: String,
+  pub(crate) stores: Vec<StoreStatus>,
+  pub(crate) totals: StatusTotals,
+  pub(crate) inbox_cached: bool,
+  pub(crate) runs_cached: bool,
+  // From check_data_integrity's count-only mode, at most DATA_INTEGRITY_TTL old.
+  pub(crate) data_issues: Option<DataIntegrityCounts>,
+  // Stays set until the folders can be read again (see `access_warning`).
+  #[serde(default)]
+  pub(crate) access_warning: Option<AccessWarning>,
+}
+
+#[derive(Default)]
+pub(crate) struct PendingNotificationStore(pub(crate) Mutex<Option<String>>);
+
+#[derive(Default)]
+pub(crate) struct BadgeEvents {
+  pub(crate) pending: Mutex<bool>,
+  pub(crate) last: Mutex<Option<Vec<UnreadBadge>>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct StoreNotificationEvent {
+  pub(crate) store_id: String,
+  pub(crate) kind: String,
+  pub(crate) count: usize,
+  pub(crate) run_ids: Vec<String>,
+}
+
+pub(crate) fn parse_quiet_time(value: &str) -> Option<chrono::NaiveTime> {
+  chrono::NaiveTime::parse_from_str(value.trim(), "%H:%M").ok()
+}
+
+pub(crate) fn build_status_snapshot(app: &tauri::AppHandle) -> Result<StatusSnapshot, String> {
+  let config = current_config(app);
+  if app.state::<RootHealth>().is_unreachable() {
+    return Err(AppError::RootUnreachable(config.receipts_root).message());
+  }
+  let (issue_runs, runs_cached) = cached_issue_runs(&app.state::<IssueRunsCache>(), &config);
+  let state = app
+    .state::<SeenStateStore>()
+    .0
+    .lock()
+    .map_err(|err| err.to_string())?
+    .clone();
+  Ok(status_snapshot_from(
+    &config,
+    &issue_runs,
+    runs_cached,
+    &state,
+    &app.state::<InboxCountCache>(),
+    cached_data_integrity_counts(app, &config),
+  ))
+}
+
+// The part that needs no app, shared with `--status` (see `run_headless`).
+pub(crate) fn status_snapshot_from(
+  config: &AppConfig,
+  issue_runs: &HashMap<String, StoreIssueRuns>,
+  runs_cached: bool,
+  state: &SeenState,
+  inbox_cache: &InboxCountCache,
+  data_issues: Option<DataIntegrityCounts>,
+) -> StatusSnapshot {
+  let badges = unread_badges(config, issue_runs, state);
+  let mut inbox_cached = true;
+  let mut totals = StatusTotals::default();
+  let mut stores = Vec::new();
+  for store in &config.stores {
+    let (stats, hit) = cached_inbox_stats(inbox_cache, config, &store.id, false);
+    inbox_cached &= hit;
+    let badge = badges.iter().find(|badge| badge.store_id == store.id);
+    let status = StoreStatus {
+      store_id: store.id.clone(),
+      name: store.name.clone(),
+      enabled: store.enabled,
+      inbox_count: stats.count,
+      oldest_pending_days: stats
+        .oldest
+        .and_then(|oldest| oldest.elapsed().ok())
+        .map(|age| age.as_secs() / 86_400),
+      failures_unread: badge.map(|badge| badge.failures_unread).unwrap_or(false),
+      warnings_unread: badge.map(|badge| badge.warnings_unread).unwrap_or(false),
+      failures_unread_count: badge.map(|badge| badge.failures_unread_count).unwrap_or(0),
+      warnings_unread_count: badge.map(|badge| badge.warnings_unread_count).unwrap_or(0),
+      snoozed_until: badge.and_then(|badge| badge.snoozed_until.clone()),
+    };
+    totals.inbox_count += status.inbox_count;
+    totals.failures_unread_count += status.failures_unread_count;
+    totals.warnings_unread_count += status.warnings_unread_count;
+    if status.failures_unread || status.warnings_unread {
+      totals.stores_with_issues += 1;
+    }
+    stores.push(status);
+  }
+  StatusSnapshot {
+    generated_at: chrono::Utc::now().to_rfc3339(),
+    stores,
+    totals,
+    inbox_cached,
+    runs_cached,
+    data_issues,
+    access_warning: access_warning(config),
+  }
+}
+
+pub(crate) fn emit_status_snapshots(app: tauri::AppHandle) {
+  loop {
+    match build_status_snapshot(&app) {
+      Ok(snapshot) => {
+        let _ = app.emit_all("status-snapshot", snapshot);
+      }
+      Err(err) => warn!("status snapshot failed: {}", err),
+    }
+    thread::sleep(STATUS_SNAPSHOT_INTERVAL);
+  }
+}
+
+pub(crate) fn in_quiet_hours(config: &AppConfig, now: chrono::NaiveTime) -> bool {
+  let start = config
+    .quiet_hours_start
+    .as_deref()
+    .and_then(parse_quiet_time);
+  let end = config.quiet_hours_end.as_deref().and_then(parse_quiet_time);
+  match (start, end) {
+    (Some(start), Some(end)) if start <= end => now >= start && now < end,
+    (Some(start), Some(end)) => now >= start || now < end,
+    _ => false,
+  }
+}
+
+// Records unread runs that haven't been notified yet and returns one event per
+// store and kind. Snoozed stores and stores with notifications off are skipped.
+pub(crate) fn collect_new_issues(
+  config: &AppConfig,
+  issue_runs: &HashMap<String, StoreIssueRuns>,
+  state: &mut SeenState,
+) -> Vec<StoreNotificationEvent> {
+  let window = config
+    .badge_window_days
+    .map(|days| Duration::from_secs(days * 86_400));
+  let mut events = Vec::new();
+  for store in &config.stores {
+    if !store.enabled || !store.notifications_enabled.unwrap_or(true) {
+      continue;
+    }
+    let runs = issue_runs.get(&store.id).cloned().unwrap_or_default();
+    let seen = state.stores.entry(store.id.clone()).or_default();
+    if active_snooze(seen).is_some() {
+      continue;
+    }
+    let mut kinds = vec![("failures", runs.failures, seen.failures.clone())];
+    if config.notify_warnings {
+      kinds.push(("warnings", runs.warnings, seen.warnings.clone()));
+    }
+
+    for (kind, runs, seen_runs) in kinds {
+      let unread = unread_run_ids(&runs, &seen_runs, window);
+      let fresh = unread
+        .iter()
+        .filter(|id| !seen.notified_run_ids.contains(*id))
+        .cloned()
+        .collect::<Vec<_>>();
+      if fresh.is_empty() {
+        continue;
+      }
+      seen.notified_run_ids.extend(fresh.iter().cloned());
+      let overflow = seen
+        .notified_run_ids
+        .len()
+        .saturating_sub(config.notified_run_ids_cap);
+      seen.notified_run_ids.drain(..overflow);
+      events.push(StoreNotificationEvent {
+        store_id: store.id.clone(),
+        kind: kind.to_string(),
+        count: unread.len(),
+        run_ids: fresh,
+      });
+    }
+  }
+  events
+}
+
+pub(crate) fn notify_new_issues(app: &tauri::AppHandle, config: &AppConfig) -> Result<(), String> {
+  if !config.notifications_enabled {
+    return Ok(());
+  }
+  let issue_runs = issue_runs_for(config);
+  let quiet = in_quiet_hours(config, chrono::Local::now().time());
+  let seen_state = app.state::<SeenStateStore>();
+  let mut state = seen_state.0.lock().map_err(|err| err.to_string())?;
+  let events = collect_new_issues(config, &issue_runs, &mut state);
+  if events.is_empty() {
+    return Ok(());
+  }
+
+  // Quiet hours still record the runs so they do not all fire at once later.
+  if !quiet {
+    for event in &events {
+      let name = config
+        .stores
+        .iter()
+        .find(|store| store.id == event.store_id)
+        .map(|store| store.name.as_str())
+        .unwrap_or(event.store_id.as_str());
+      let body = format!("{} new runs with {}", event.count, event.kind);
+      let _ = tauri::api::notification::Notification::new(&app.config().tauri.bundle.identifier)
+        .title(name)
+        .body(body)
+        .show();
+      if let Ok(mut pending) = app.state::<PendingNotificationStore>().0.lock() {
+        *pending = Some(event.store_id.clone());
+      }
+      let _ = app.emit_all("store-notification", event.clone());
+    }
+  }
+  save_state(&state)?;
+  Ok(())
+}
+
+// Coalesces badge changes from the runs watcher and state commands: the first
+// call in a burst schedules one recompute after a short delay, and
+// `badges-updated` is only emitted when the result differs from the last one.
+pub(crate) fn schedule_badges_update(app: &tauri::AppHandle) {
+  app.state::<DashboardScanner>().request();
+  if let Ok(mut pending) = app.state::<BadgeEvents>().pending.lock() {
+    if *pending {
+      return;
+    }
+    *pending = true;
+  }
+  let app = app.clone();
+  thread::spawn(move || {
+    thread::sleep(BADGE_EVENT_DEBOUNCE);
+    let events = app.state::<BadgeEvents>();
+    if let Ok(mut pending) = events.pending.lock() {
+      *pending = false;
+    }
+    let config = current_config(&app);
+    let issue_runs = issue_runs_for(&config);
+    let badges = match app.state::<SeenStateStore>().0.lock() {
+      Ok(state) => unread_badges(&config, &issue_runs, &state),
+      Err(_) => return,
+    };
+    refresh_tray_badge(&app, &config, &badges);
+    refresh_menu_state(&app, None);
+    if let Ok(mut last) = events.last.lock() {
+      if last.as_ref() == Some(&badges) {
+        return;
+      }
+      *last = Some(badges.clone());
+    }
+    let _ = app.emit_all("badges-updated", badges);
+  });
+}
+
+// Polls the runs folder; a change in its mtime or entry count triggers a
+// notification check against the seen state. The folder is followed to where
+// it resolves, so a link (or a root on a volume) that now points somewhere
+// else counts as a change too.
+pub(crate) fn watch_runs_dir(app: tauri::AppHandle) {
+  let mut last_signature = None;
+  loop {
+    let config = current_config(&app);
+    let target = fs::canonicalize(Path::new(&config.receipts_root).join("_logs").join("runs")).ok();
+    let signature = (target, runs_signature(&config));
+    if last_signature.as_ref() != Some(&signature) {
+      last_signature = Some(signature);
+      sync_run_index(&app, &config);
+      // A run landed, so processed/ probably changed: fold the new outputs
+      // into month_stats now rather than on the next report.
+      if let Ok(root) = require_receipts_root(&config) {
+        let store_ids = receipt_store_ids(&config, None).unwrap_or_default();
+        indexed_month_stats_for(&app, root, &store_ids);
+      }
+      if let Err(err) = notify_new_issues(&app, &config) {
+        warn!("notification check failed: {}", err);
+      }
+      schedule_badges_update(&app);
+    }
+    thread::sleep(RUNS_POLL_INTERVAL);
+  }
+}
+
+pub(crate) fn tray_menu(config: &AppConfig) -> SystemTrayMenu {
+  let mut menu = SystemTrayMenu::new();
+  for store in config.stores.iter().filter(|store| store.enabled) {
+    menu = menu.add_item(CustomMenuItem::new(
+      format!("store:{}", store.id),
+      &store.name,
+    ));
+  }
+  menu
+    .add_native_item(SystemTrayMenuItem::Separator)
+    .add_item(CustomMenuItem::new("run_all", "Run all"))
+    .add_item(CustomMenuItem::new("quit", "Quit"))
+}
+
+pub(crate) fn decode_tray_icon() -> Result<(Vec<u8>, u32, u32), String> {
+  let mut decoder = png::Decoder::new(TRAY_ICON);
+  decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+  let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+  let mut buffer = vec![0u8; reader.output_buffer_size()];
+  let info = reader
+    .next_frame(&mut buffer)
+    .map_err(|err| err.to_string())?;
+  buffer.truncate(info.buffer_size());
+  let rgba = match info.color_type {
+    png::ColorType::Rgba => buffer,
+    png::ColorType::Rgb => buffer
+      .chunks(3)
+      .flat_map(|px| [px[0], px[1], px[2], 0xFF])
+      .collect(),
+    png::ColorType::GrayscaleAlpha => buffer
+      .chunks(2)
+      .flat_map(|px| [px[0], px[0], px[0], px[1]])
+      .collect(),
+    png::ColorType::Grayscale => buffer.iter().flat_map(|g| [*g, *g, *g, 0xFF]).collect(),
+    other => return Err(format!("Unsupported tray icon color type: {:?}", other)),
+  };
+  Ok((rgba, info.width, info.height))
+}
+
+// Paints a red dot in the top-right corner of the tray icon.
+pub(crate) fn badge_overlay(rgba: &mut [u8], width: u32, height: u32) {
+  let radius = (width.min(height) / 4) as i64;
+  let (cx, cy) = (width as i64 - radius - 1, radius + 1);
+  for y in 0..height as i64 {
+    for x in 0..width as i64 {
+      let (dx, dy) = (x - cx, y - cy);
+      if dx * dx + dy * dy <= radius * radius {
+        let index = ((y * width as i64 + x) * 4) as usize;
+        rgba[index..index + 4].copy_from_slice(&[0xE5, 0x39, 0x35, 0xFF]);
+      }
+    }
+  }
+}
+
+// The dock/taskbar count for the run-based metrics; "inbox" is set from the
+// inbox counts instead. Snoozed stores don't count.
+pub(crate) fn app_badge_count(metric: &str, badges: &[UnreadBadge]) -> Option<usize> {
+  let active = badges.iter().filter(|badge| badge.snoozed_until.is_none());
+  match metric {
+    "failures" => Some(active.map(|badge| badge.failures_unread_count).sum()),
+    "failures_warnings" => Some(
+      active
+        .map(|badge| badge.failures_unread_count + badge.warnings_unread_count)
+        .sum(),
+    ),
+    _ => None,
+  }
+}
+
+pub(crate) fn refresh_tray_badge(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  badges: &[UnreadBadge],
+) {
+  let unread = badges
+    .iter()
+    .any(|badge| badge.failures_unread || badge.warnings_unread);
+  if let Some(count) = app_badge_count(&config.badge_metric, badges) {
+    set_app_badge(app, count);
+  }
+  let (mut rgba, width, height) = match decode_tray_icon() {
+    Ok(icon) => icon,
+    Err(err) => {
+      error!("tray icon decode failed: {}", err);
+      return;
+    }
+  };
+  if unread {
+    badge_overlay(&mut rgba, width, height);
+  }
+  let _ = app.tray_handle().set_icon(tauri::Icon::Rgba {
+    rgba,
+    width,
+    height,
+  });
+}
+
+pub(crate) fn refresh_tray_counts(
+  app: &tauri::AppHandle,
+  config: &AppConfig,
+  counts: &[InboxCount],
+) {
+  if config.badge_metric == "inbox" {
+    set_app_badge(app, counts.iter().map(|count| count.count).sum());
+  }
+  let tray = app.tray_handle();
+  for count in counts {
+    let name = config
+      .stores
+      .iter()
+      .find(|store| store.id == count.store_id)
+      .map(|store| store.name.as_str())
+      .unwrap_or(count.store_id.as_str());
+    if let Some(item) = tray.try_get_item(&format!("store:{}", count.store_id)) {
+      let _ = item.set_title(format!("{} ({})", name, count.count));
+    }
+  }
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn set_app_badge(app: &tauri::AppHandle, count: usize) {
+  let _ = app.run_on_main_thread(move || unsafe {
+    use cocoa::base::{id, nil};
+    use cocoa::foundation::NSString;
+    use objc::{class, msg_send, sel, sel_impl};
+    let ns_app: id = msg_send![class!(NSApplication), sharedApplication];
+    let dock_tile: id = msg_send![ns_app, dockTile];
+    let label = if count == 0 {
+      nil
+    } else {
+      NSString::alloc(nil).init_str(&count.to_string())
+    };
+    let _: () = msg_send![dock_tile, setBadgeLabel: label];
+  });
+}
+
+// Windows has no numeric taskbar badge without COM overlay plumbing, so the
+// taskbar icon gets the same red dot as the tray icon.
+#[cfg(target_os = "windows")]
+pub(crate) fn set_app_badge(app: &tauri::AppHandle, count: usize) {
+  let window = match app.get_window("main") {
+    Some(window) => window,
+    None => return,
+  };
+  let (mut rgba, width, height) = match decode_tray_icon() {
+    Ok(icon) => icon,
+    Err(_) => return,
+  };
+  if count > 0 {
+    badge_overlay(&mut rgba, width, height);
+  }
+  let _ = window.set_icon(tauri::Icon::Rgba {
+    rgba,
+    width,
+    height,
+  });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+pub(crate) fn set_app_badge(_app: &tauri::AppHandle, _count: usize) {}
+
+pub(crate) fn show_main_window(app: &tauri::AppHandle) -> Option<tauri::Window> {
+  let window = app.get_window("main")?;
+  focus_window(&window);
+  Some(window)
+}
+
+// "Run all" from the tray or the app menu; the output goes to the window's log.
+pub(crate) fn run_all_stores(window: &tauri::Window) {
+  let config = current_config(window);
+  let sink = EventSink::from(window);
+  let stores = Vec::new();
+  if start_worker(&sink, &config, &stores).is_err() {
+    return;
+  }
+  if let Err(err) = spawn_worker(&sink, &config, store_args(&stores), stores.clone()) {
+    emit_worker_line(&sink, "stderr", err.message(), &stores);
+  }
+}
+
+pub(crate) fn handle_tray_event(app: &tauri::AppHandle, event: SystemTrayEvent) {
+  let id = match event {
+    SystemTrayEvent::LeftClick { .. } => {
+      show_main_window(app);
+      return;
+    }
+    SystemTrayEvent::MenuItemClick { id, .. } => id,
+    _ => return,
+  };
+  match id.as_str() {
+    "quit" => {
+      request_quit(app, "tray");
+    }
+    "run_all" => {
+      if let Some(window) = show_main_window(app) {
+        thread::spawn(move || run_all_stores(&window));
+      }
+    }
+    other => {
+      if let Some(store_id) = other.strip_prefix("store:") {
+        if let Some(window) = show_main_window(app) {
+          let _ = window.emit("open-store", store_id.to_string());
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn quiet_hours_wrap_past_midnight() {
+    let at = |h, m| chrono::NaiveTime::from_hms_opt(h, m, 0).expect("time");
+    let mut config = badge_config("lidl");
+    assert!(!in_quiet_hours(&config, at(23, 0)));
+
+    config.quiet_hours_start = Some("22:00".to_string());
+    config.quiet_hours_end = Some("07:00".to_string());
+    assert!(in_quiet_hours(&config, at(22, 0)));
+    assert!(in_quiet_hours(&config, at(3, 30)));
+    assert!(!in_quiet_hours(&config, at(7, 0)));
+    assert!(!in_quiet_hours(&config, at(12, 0)));
+
+    config.quiet_hours_start = Some(" 12:00 ".to_string());
+    config.quiet_hours_end = Some("13:30".to_string());
+    assert!(in_quiet_hours(&config, at(12, 45)));
+    assert!(!in_quiet_hours(&config, at(22, 0)));
+
+    config.quiet_hours_end = Some("half past one".to_string());
+    assert!(!in_quiet_hours(&config, at(12, 45)));
+  }
+
+  #[test]
+  fn new_issues_are_notified_once_and_capped() {
+    let mut config = badge_config("lidl");
+    let issue_runs = issue_fixture("lidl");
+    let mut state = SeenState::default();
+
+    let events = collect_new_issues(&config, &issue_runs, &mut state);
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].kind.as_str(), events[0].count), ("failures", 2));
+    assert_eq!(events[0].run_ids.len(), 2);
+    assert!(collect_new_issues(&config, &issue_runs, &mut state).is_empty());
+
+    config.notify_warnings = true;
+    config.notified_run_ids_cap = 1;
+    let events = collect_new_issues(&config, &issue_runs, &mut state);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, "warnings");
+    assert_eq!(state.stores["lidl"].notified_run_ids.len(), 1);
+  }
+
+  #[test]
+  fn snoozed_or_muted_stores_are_not_notified() {
+    let mut config = badge_config("lidl");
+    let issue_runs = issue_fixture("lidl");
+    let mut state = SeenState::default();
+    let until = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+    state
+      .stores
+      .entry("lidl".to_string())
+      .or_default()
+      .snoozed_until = Some(until);
+    assert!(collect_new_issues(&config, &issue_runs, &mut state).is_empty());
+    assert!(state.stores["lidl"].notified_run_ids.is_empty());
+
+    state.stores.clear();
+    config.stores[0].notifications_enabled = Some(false);
+    assert!(collect_new_issues(&config, &issue_runs, &mut state).is_empty());
+  }
+
+  #[test]
+  fn badge_overlay_only_paints_the_top_right_corner() {
+    let (rgba, width, height) = decode_tray_icon().expect("tray icon");
+    assert_eq!(rgba.len(), (width * height * 4) as usize);
+
+    let (width, height) = (16u32, 16u32);
+    let mut rgba = vec![0u8; (width * height * 4) as usize];
+    badge_overlay(&mut rgba, width, height);
+    let pixel = |x: u32, y: u32| {
+      let index = ((y * width + x) * 4) as usize;
+      rgba[index..index + 4].to_vec()
+    };
+    assert_eq!(pixel(width - 5, 5), vec![0xE5, 0x39, 0x35, 0xFF]);
+    assert_eq!(pixel(0, height - 1), vec![0, 0, 0, 0]);
+    assert_eq!(pixel(0, 0), vec![0, 0, 0, 0]);
+  }
+
+  #[test]
+  fn app_badge_count_skips_snoozed_stores_and_the_inbox_metric() {
+    let mut config = badge_config("lidl");
+    config.stores.push(StoreConfig {
+      id: "aldi".to_string(),
+      name: "Aldi".to_string(),
+      ..config.stores[0].clone()
+    });
+    let mut issue_runs = issue_fixture("lidl");
+    issue_runs.extend(issue_fixture("aldi"));
+    let mut state = SeenState::default();
+    let badges = unread_badges(&config, &issue_runs, &state);
+    assert_eq!(app_badge_count("failures", &badges), Some(4));
+    assert_eq!(app_badge_count("failures_warnings", &badges), Some(6));
+    assert_eq!(app_badge_count("inbox", &badges), None);
+
+    let until = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+    state
+      .stores
+      .entry("aldi".to_string())
+      .or_default()
+      .snoozed_until = Some(until);
+    let badges = unread_badges(&config, &issue_runs, &state);
+    assert_eq!(app_badge_count("failures_warnings", &badges), Some(3));
+  }
+}
@@ -0,0 +1,1049 @@
+use crate::*;
+
+// One step of run_full_update; `output_tail` holds the last lines of its output.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct UpdatePhaseResult {
+  pub(crate) phase: String,
+  pub(crate) status: String,
+  pub(crate) exit_code: Option<i32>,
+  pub(crate) output_tail: Vec<String>,
+}
+
+// `shared_checkout` is true when WORKER_DIR and the app source live in the same
+// git repo, so the worker pull also brought in the app changes.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct FullUpdateResult {
+  pub(crate) status: String,
+  pub(crate) failed_phase: Option<String>,
+  pub(crate) phases: Vec<UpdatePhaseResult>,
+  pub(crate) shared_checkout: bool,
+  pub(crate) worker_commit: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct CancelResult {
+  pub(crate) cancelled: bool,
+  pub(crate) reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct UpdateStatus {
+  pub(crate) status: String,
+  pub(crate) installed_version: String,
+  pub(crate) source_version: Option<String>,
+  pub(crate) source_path: Option<String>,
+  pub(crate) message: Option<String>,
+  pub(crate) git: Option<GitUpdateInfo>,
+  pub(crate) checked_at: String,
+  pub(crate) installed_update: Option<InstalledUpdate>,
+  pub(crate) comparison: Option<String>,
+  pub(crate) installed_semver: Option<String>,
+  pub(crate) source_semver: Option<String>,
+  pub(crate) git_checkout: bool,
+}
+
+// Recorded after the update script exits 0. `status` is "restart_pending", or
+// "update_ineffective" when the installed bundle still reports another version.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InstalledUpdate {
+  pub(crate) status: String,
+  pub(crate) source_version: String,
+  pub(crate) bundle_version: Option<String>,
+  pub(crate) bundle_path: Option<String>,
+  pub(crate) installed_at: String,
+}
+
+#[derive(Default)]
+pub(crate) struct InstalledUpdateState(pub(crate) Mutex<Option<InstalledUpdate>>);
+
+// One line of update-history.jsonl per update attempt. `kind` is "app"
+// (run_update) or "full" (run_full_update); `log_path` holds the captured output.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct UpdateHistoryEntry {
+  pub(crate) timestamp: String,
+  pub(crate) kind: String,
+  pub(crate) previous_version: String,
+  pub(crate) target_version: Option<String>,
+  pub(crate) source_commit: Option<String>,
+  #[serde(default)]
+  pub(crate) worker_commit: Option<String>,
+  pub(crate) duration_ms: u64,
+  pub(crate) status: String,
+  pub(crate) exit_code: Option<i32>,
+  #[serde(default)]
+  pub(crate) failed_phase: Option<String>,
+  pub(crate) log_path: Option<String>,
+}
+
+// Started before the update command runs; `finish` writes the history entry.
+pub(crate) struct UpdateAttempt {
+  pub(crate) kind: &'static str,
+  pub(crate) source_dir: PathBuf,
+  pub(crate) previous_version: String,
+  pub(crate) started_at: chrono::DateTime<chrono::Utc>,
+  pub(crate) started: Instant,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct ChangelogEntry {
+  pub(crate) version: String,
+  pub(crate) date: Option<String>,
+  pub(crate) items: Vec<String>,
+}
+
+// `source` is "changelog", "git" or "none"; `raw` carries the CHANGELOG.md text
+// when no versioned sections could be parsed out of it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct UpdateChangelog {
+  pub(crate) installed_version: String,
+  pub(crate) source_version: Option<String>,
+  pub(crate) source: String,
+  pub(crate) entries: Vec<ChangelogEntry>,
+  pub(crate) raw: Option<String>,
+}
+
+// Keyed by source version; the changelog only changes when the source does.
+#[derive(Default)]
+pub(crate) struct ChangelogCache(pub(crate) Mutex<HashMap<String, UpdateChangelog>>);
+
+#[derive(Default)]
+pub(crate) struct UpdateStatusCache(pub(crate) Mutex<Option<UpdateStatus>>);
+
+// State of the source checkout against origin/main. `stale` means the fetch
+// failed and the counts come from the last fetched origin/main; `error` holds
+// the first failing git command's output. `dirty` covers the app folder only.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub(crate) struct GitUpdateInfo {
+  pub(crate) commits_behind: usize,
+  pub(crate) commits_ahead: usize,
+  pub(crate) touches_app: bool,
+  pub(crate) log: Vec<String>,
+  pub(crate) stale: bool,
+  pub(crate) branch: Option<String>,
+  pub(crate) dirty: bool,
+  pub(crate) diverged: bool,
+  pub(crate) error: Option<String>,
+}
+
+pub(crate) fn read_source_version(source_dir: &Path) -> Result<String, String> {
+  let path = source_dir.join("src-tauri").join("tauri.conf.json");
+  let raw = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+  let value: Value = serde_json::from_str(&raw).map_err(|err| err.to_string())?;
+  value
+    .get("package")
+    .and_then(|pkg| pkg.get("version"))
+    .and_then(|ver| ver.as_str())
+    .map(|ver| ver.to_string())
+    .ok_or_else(|| "Missing package.version in tauri.conf.json".to_string())
+}
+
+pub(crate) fn git_output(dir: &Path, args: &[&str]) -> Result<String, String> {
+  let output = Command::new("git")
+    .args(args)
+    .current_dir(dir)
+    .env("GIT_TERMINAL_PROMPT", "0")
+    .stdin(Stdio::null())
+    .output()
+    .map_err(|err| err.to_string())?;
+  if !output.status.success() {
+    return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+pub(crate) fn is_git_checkout(source_dir: &Path) -> bool {
+  git_output(source_dir, &["rev-parse", "--is-inside-work-tree"]).is_ok()
+}
+
+// Uncommitted changes under the source dir (the desktop app folder).
+pub(crate) fn git_dirty(source_dir: &Path) -> Result<bool, String> {
+  git_output(source_dir, &["status", "--porcelain", "--", "."])
+    .map(|output| !output.trim().is_empty())
+    .map_err(|err| format!("git status failed: {}", err))
+}
+
+pub(crate) fn git_update_info(source_dir: &Path) -> Option<GitUpdateInfo> {
+  if !is_git_checkout(source_dir) {
+    return None;
+  }
+  let mut info = GitUpdateInfo::default();
+  let fail = |info: &mut GitUpdateInfo, err: String| {
+    info.error.get_or_insert(err);
+  };
+  match git_output(source_dir, &["fetch", "--quiet"]) {
+    Ok(_) => {}
+    Err(err) => {
+      warn!("git fetch failed: {}", err);
+      info.stale = true;
+    }
+  }
+  match git_output(source_dir, &["rev-parse", "--abbrev-ref", "HEAD"]) {
+    Ok(branch) => info.branch = Some(branch.trim().to_string()),
+    Err(err) => fail(&mut info, format!("git rev-parse failed: {}", err)),
+  }
+  match git_dirty(source_dir) {
+    Ok(dirty) => info.dirty = dirty,
+    Err(err) => fail(&mut info, err),
+  }
+  match git_output(
+    source_dir,
+    &["rev-list", "--left-right", "--count", "HEAD...origin/main"],
+  ) {
+    Ok(counts) => {
+      let mut counts = counts
+        .split_whitespace()
+        .map(|count| count.parse().unwrap_or(0));
+      info.commits_ahead = counts.next().unwrap_or(0);
+      info.commits_behind = counts.next().unwrap_or(0);
+      info.diverged = info.commits_ahead > 0 && info.commits_behind > 0;
+    }
+    Err(err) => fail(&mut info, format!("git rev-list failed: {}", err)),
+  }
+  // The source dir is the desktop app folder, so `.` limits the log to app changes.
+  match git_output(
+    source_dir,
+    &[
+      "log",
+      "--oneline",
+      "--max-count=20",
+      "HEAD..origin/main",
+      "--",
+      ".",
+    ],
+  ) {
+    Ok(log) => {
+      info.log = log.lines().map(|line| line.to_string()).collect();
+      info.touches_app = !info.log.is_empty();
+    }
+    Err(err) => fail(&mut info, format!("git log failed: {}", err)),
+  }
+  Some(info)
+}
+
+pub(crate) fn update_history_path() -> Option<PathBuf> {
+  state_path(UPDATE_HISTORY_FILE)
+}
+
+// Oldest first, as written; unreadable lines are skipped.
+pub(crate) fn read_update_history() -> Vec<UpdateHistoryEntry> {
+  update_history_path()
+    .and_then(|path| fs::read_to_string(path).ok())
+    .map(|raw| {
+      raw
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+pub(crate) fn append_update_history(entry: &UpdateHistoryEntry) -> Result<(), String> {
+  let path = update_history_path().ok_or_else(|| AppError::HomeUnavailable.message())?;
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+  }
+  let line = serde_json::to_string(entry).map_err(|err| err.to_string())?;
+  let mut file = fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(&path)
+    .map_err(|err| err.to_string())?;
+  writeln!(file, "{}", line).map_err(|err| err.to_string())
+}
+
+pub(crate) fn write_update_log(
+  started_at: &chrono::DateTime<chrono::Utc>,
+  output: &str,
+) -> Result<PathBuf, String> {
+  let dir = state_dir()
+    .map_err(|err| err.message())?
+    .join(UPDATE_LOGS_DIR);
+  fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+  let path = dir.join(format!(
+    "update-{}.log",
+    started_at.format("%Y%m%dT%H%M%SZ")
+  ));
+  fs::write(&path, output).map_err(|err| err.to_string())?;
+  Ok(path)
+}
+
+// Caches the result and emits `update-available` when a check flips from
+// up_to_date to update_available.
+pub(crate) fn record_update_status(app: &tauri::AppHandle, status: &UpdateStatus) {
+  let previous = match app.state::<UpdateStatusCache>().0.lock() {
+    Ok(mut cached) => cached.replace(status.clone()),
+    Err(_) => return,
+  };
+  let was_up_to_date = previous
+    .map(|previous| previous.status == "up_to_date")
+    .unwrap_or(false);
+  if was_up_to_date && status.status == "update_available" {
+    let _ = app.emit_all("update-available", status.clone());
+  }
+}
+
+pub(crate) fn check_updates_periodically(app: tauri::AppHandle) {
+  loop {
+    if let Some(hours) = current_config(&app).update_check_hours {
+      let due = match app.state::<UpdateStatusCache>().0.lock() {
+        Ok(cached) => cached
+          .as_ref()
+          .and_then(|status| chrono::DateTime::parse_from_rfc3339(&status.checked_at).ok())
+          .map(|checked| {
+            chrono::Utc::now().signed_duration_since(checked)
+              >= chrono::Duration::hours(hours as i64)
+          })
+          .unwrap_or(true),
+        Err(_) => false,
+      };
+      let busy = app
+        .state::<CommandsRunning>()
+        .0
+        .lock()
+        .map(|running| *running > 0)
+        .unwrap_or(true);
+      if due && !busy {
+        match check_update_status(&app) {
+          Ok(status) => record_update_status(&app, &status),
+          Err(err) => warn!("update check failed: {}", err),
+        }
+      }
+    }
+    thread::sleep(UPDATE_CHECK_POLL_INTERVAL);
+  }
+}
+
+// Tolerates a leading `v` and missing minor/patch segments (`1.2` -> `1.2.0`).
+pub(crate) fn parse_version(version: &str) -> Option<semver::Version> {
+  let version = version.trim().trim_start_matches('v');
+  let core_end = version.find(['-', '+']).unwrap_or(version.len());
+  let (core, suffix) = version.split_at(core_end);
+  let mut core = core.to_string();
+  for _ in core.split('.').count()..3 {
+    core.push_str(".0");
+  }
+  semver::Version::parse(&format!("{}{}", core, suffix)).ok()
+}
+
+// Semver precedence: pre-releases sort before their release and build
+// metadata is ignored.
+pub(crate) fn compare_versions(a: &semver::Version, b: &semver::Version) -> std::cmp::Ordering {
+  (a.major, a.minor, a.patch)
+    .cmp(&(b.major, b.minor, b.patch))
+    .then_with(|| match (a.pre.is_empty(), b.pre.is_empty()) {
+      (true, true) => std::cmp::Ordering::Equal,
+      (true, false) => std::cmp::Ordering::Greater,
+      (false, true) => std::cmp::Ordering::Less,
+      (false, false) => a.pre.cmp(&b.pre),
+    })
+}
+
+pub(crate) fn version_newer(version: &str, than: &str) -> bool {
+  match (parse_version(version), parse_version(than)) {
+    (Some(version), Some(than)) => compare_versions(&version, &than) == std::cmp::Ordering::Greater,
+    _ => false,
+  }
+}
+
+// Returns (status, comparison): semver when both versions parse, otherwise
+// plain string equality.
+pub(crate) fn update_status_for(installed: &str, source: &str) -> (&'static str, &'static str) {
+  match (parse_version(installed), parse_version(source)) {
+    (Some(installed), Some(source)) => {
+      let status = match compare_versions(&source, &installed) {
+        std::cmp::Ordering::Greater => "update_available",
+        std::cmp::Ordering::Equal => "up_to_date",
+        std::cmp::Ordering::Less => "ahead_of_source",
+      };
+      (status, "semver")
+    }
+    _ if installed.trim() == source.trim() => ("up_to_date", "string"),
+    _ => ("update_available", "string"),
+  }
+}
+
+// Accepts `## [0.4.0] - 2024-01-05`, `## v0.4.0 (2024-01-05)` and similar;
+// `[Unreleased]` and headings without a version are skipped.
+pub(crate) fn parse_changelog_heading(heading: &str) -> Option<(String, Option<String>)> {
+  let tokens = heading
+    .split(|c: char| c.is_whitespace() || "[]()".contains(c))
+    .filter(|token| !token.is_empty() && *token != "-")
+    .collect::<Vec<_>>();
+  let version = tokens
+    .iter()
+    .map(|token| token.trim_start_matches('v'))
+    .find(|token| {
+      token.contains('.')
+        && token
+          .chars()
+          .next()
+          .map(|c| c.is_ascii_digit())
+          .unwrap_or(false)
+    })?;
+  let date = tokens
+    .iter()
+    .find(|token| chrono::NaiveDate::parse_from_str(token, "%Y-%m-%d").is_ok())
+    .map(|token| token.to_string());
+  Some((version.to_string(), date))
+}
+
+// Tolerant Keep-a-Changelog parser: `## <version>` starts an entry, `### Added`
+// style subsections prefix their bullets, and indented lines continue the
+// previous bullet.
+pub(crate) fn parse_changelog(raw: &str) -> Vec<ChangelogEntry> {
+  let mut entries: Vec<ChangelogEntry> = Vec::new();
+  let mut in_entry = false;
+  let mut section: Option<String> = None;
+  for line in raw.lines() {
+    let trimmed = line.trim();
+    if let Some(heading) = trimmed.strip_prefix("## ") {
+      in_entry = match parse_changelog_heading(heading) {
+        Some((version, date)) => {
+          entries.push(ChangelogEntry {
+            version,
+            date,
+            items: Vec::new(),
+          });
+          true
+        }
+        None => false,
+      };
+      section = None;
+      continue;
+    }
+    if !in_entry {
+      continue;
+    }
+    let entry = match entries.last_mut() {
+      Some(entry) => entry,
+      None => continue,
+    };
+    if let Some(name) = trimmed.strip_prefix("### ") {
+      section = Some(name.trim().to_string());
+    } else if let Some(item) = trimmed
+      .strip_prefix("- ")
+      .or_else(|| trimmed.strip_prefix("* "))
+    {
+      entry.items.push(match &section {
+        Some(section) => format!("{}: {}", section, item.trim()),
+        None => item.trim().to_string(),
+      });
+    } else if !trimmed.is_empty() && line.starts_with(char::is_whitespace) {
+      if let Some(last) = entry.items.last_mut() {
+        last.push(' ');
+        last.push_str(trimmed);
+      }
+    }
+  }
+  entries
+}
+
+pub(crate) fn git_changelog(
+  source_dir: &Path,
+  installed_version: &str,
+  source_version: &str,
+) -> Option<ChangelogEntry> {
+  let items = ["v", ""].iter().find_map(|prefix| {
+    let range = format!(
+      "{}{}..{}{}",
+      prefix, installed_version, prefix, source_version
+    );
+    git_output(source_dir, &["log", "--format=%s", &range, "--", "."]).ok()
+  })?;
+  Some(ChangelogEntry {
+    version: source_version.to_string(),
+    date: None,
+    items: items.lines().map(|line| line.to_string()).collect(),
+  })
+}
+
+pub(crate) fn build_update_changelog(
+  installed_version: String,
+  source_dir: &Path,
+  source_version: &str,
+) -> UpdateChangelog {
+  let mut changelog = UpdateChangelog {
+    installed_version,
+    source_version: Some(source_version.to_string()),
+    source: "none".to_string(),
+    entries: Vec::new(),
+    raw: None,
+  };
+  if let Ok(raw) = fs::read_to_string(source_dir.join("CHANGELOG.md")) {
+    let entries = parse_changelog(&raw);
+    changelog.source = "changelog".to_string();
+    if entries.is_empty() {
+      changelog.raw = Some(raw);
+    } else {
+      changelog.entries = entries
+        .into_iter()
+        .filter(|entry| version_newer(&entry.version, &changelog.installed_version))
+        .collect();
+    }
+    return changelog;
+  }
+  if let Some(entry) = git_changelog(source_dir, &changelog.installed_version, source_version) {
+    changelog.source = "git".to_string();
+    changelog.entries = vec![entry];
+  }
+  changelog
+}
+
+pub(crate) fn check_update_status(app: &tauri::AppHandle) -> Result<UpdateStatus, String> {
+  let installed_version = app.package_info().version.to_string();
+  let source_dir = resolve_source_dir();
+
+  if source_dir.is_none() {
+    return Ok(UpdateStatus {
+      status: "source_missing".to_string(),
+      installed_version,
+      source_version: None,
+      source_path: None,
+      message: Some("Source code not found. Set RECEIPTS_APP_SOURCE.".to_string()),
+      git: None,
+      checked_at: chrono::Utc::now().to_rfc3339(),
+      installed_update: None,
+      comparison: None,
+      installed_semver: None,
+      source_semver: None,
+      git_checkout: false,
+    });
+  }
+
+  let source_dir = source_dir.unwrap();
+  let source_version = read_source_version(&source_dir)?;
+  let (status, comparison) = update_status_for(&installed_version, &source_version);
+  let git = git_update_info(&source_dir);
+  let mut status = status.to_string();
+  let mut message = None;
+  let installed_update = app
+    .state::<InstalledUpdateState>()
+    .0
+    .lock()
+    .map_err(|err| err.to_string())?
+    .clone();
+  if let Some(installed) = &installed_update {
+    status = installed.status.clone();
+    message = Some(if installed.status == "update_ineffective" {
+      format!(
+        "The update script finished, but {} still reports version {} (expected {})",
+        installed
+          .bundle_path
+          .as_deref()
+          .unwrap_or("the installed app"),
+        installed.bundle_version.as_deref().unwrap_or("?"),
+        installed.source_version
+      )
+    } else {
+      format!(
+        "Version {} is installed; restart to use it",
+        installed.source_version
+      )
+    });
+  }
+
+  let installed_semver = parse_version(&installed_version).map(|version| version.to_string());
+  let source_semver = parse_version(&source_version).map(|version| version.to_string());
+  Ok(UpdateStatus {
+    status,
+    installed_version,
+    source_version: Some(source_version),
+    source_path: Some(source_dir.to_string_lossy().to_string()),
+    message,
+    git_checkout: git.is_some(),
+    git,
+    checked_at: chrono::Utc::now().to_rfc3339(),
+    installed_update,
+    comparison: Some(comparison.to_string()),
+    installed_semver,
+    source_semver,
+  })
+}
+
+// Pulls a `<key>…</key><string>…</string>` value out of an XML plist.
+pub(crate) fn plist_string(raw: &str, key: &str) -> Option<String> {
+  let after_key = &raw[raw.find(&format!("<key>{}</key>", key))?..];
+  let start = after_key.find("<string>")? + "<string>".len();
+  let end = after_key[start..].find("</string>")?;
+  Some(after_key[start..start + end].trim().to_string())
+}
+
+// Only the macOS bundle can be inspected; elsewhere the version is unknown.
+pub(crate) fn installed_bundle_version() -> (Option<String>, Option<String>) {
+  if !cfg!(target_os = "macos") {
+    return (None, None);
+  }
+  let plist = Path::new(INSTALLED_BUNDLE_PATH)
+    .join("Contents")
+    .join("Info.plist");
+  let version = fs::read_to_string(&plist)
+    .ok()
+    .and_then(|raw| plist_string(&raw, "CFBundleShortVersionString"));
+  (version, Some(plist.to_string_lossy().to_string()))
+}
+
+pub(crate) fn installed_update_for(
+  source_version: String,
+  installed_at: String,
+) -> InstalledUpdate {
+  let (bundle_version, bundle_path) = installed_bundle_version();
+  let effective = bundle_version
+    .as_deref()
+    .map(|version| version == source_version)
+    .unwrap_or(true);
+  InstalledUpdate {
+    status: if effective {
+      "restart_pending"
+    } else {
+      "update_ineffective"
+    }
+    .to_string(),
+    source_version,
+    bundle_version,
+    bundle_path,
+    installed_at,
+  }
+}
+
+pub(crate) fn record_installed_update(
+  app: &tauri::AppHandle,
+  source_dir: &Path,
+) -> Result<(), String> {
+  let source_version = read_source_version(source_dir)?;
+  let installed = installed_update_for(source_version, chrono::Utc::now().to_rfc3339());
+  *app
+    .state::<InstalledUpdateState>()
+    .0
+    .lock()
+    .map_err(|err| err.to_string())? = Some(installed);
+  let status = check_update_status(app)?;
+  record_update_status(app, &status);
+  Ok(())
+}
+
+// The target of the latest successful update, unless this process already runs
+// that version: the install happened but the app was never relaunched into it.
+pub(crate) fn pending_update_target(
+  history: &[UpdateHistoryEntry],
+  running_version: &str,
+) -> Option<UpdateHistoryEntry> {
+  let latest = history.iter().rev().find(|entry| entry.status == "ok")?;
+  let target = latest.target_version.as_deref()?;
+  let current = match (parse_version(target), parse_version(running_version)) {
+    (Some(target), Some(running)) => {
+      compare_versions(&target, &running) == std::cmp::Ordering::Equal
+    }
+    _ => target.trim() == running_version.trim(),
+  };
+  if current {
+    None
+  } else {
+    Some(latest.clone())
+  }
+}
+
+// Restores "restart pending" across an app restart from update-history.jsonl.
+pub(crate) fn restore_installed_update(app: &tauri::AppHandle) {
+  let running_version = app.package_info().version.to_string();
+  let entry = match pending_update_target(&read_update_history(), &running_version) {
+    Some(entry) => entry,
+    None => return,
+  };
+  let target = entry.target_version.unwrap_or_default();
+  if let Ok(mut installed) = app.state::<InstalledUpdateState>().0.lock() {
+    *installed = Some(installed_update_for(target, entry.timestamp));
+  }
+}
+
+impl UpdateAttempt {
+  pub(crate) fn start(
+    app: &tauri::AppHandle,
+    kind: &'static str,
+    source_dir: &Path,
+  ) -> UpdateAttempt {
+    UpdateAttempt {
+      kind,
+      source_dir: source_dir.to_path_buf(),
+      previous_version: app.package_info().version.to_string(),
+      started_at: chrono::Utc::now(),
+      started: Instant::now(),
+    }
+  }
+
+  // Target version and commit are read afterwards, so a pull during the update
+  // is reflected. History write failures are logged, never returned.
+  pub(crate) fn finish(
+    self,
+    status: &str,
+    exit_code: Option<i32>,
+    failed_phase: Option<String>,
+    worker_commit: Option<String>,
+    output: &str,
+  ) {
+    let log_path = match write_update_log(&self.started_at, output) {
+      Ok(path) => Some(path.to_string_lossy().to_string()),
+      Err(err) => {
+        error!("update log write failed: {}", err);
+        None
+      }
+    };
+    let entry = UpdateHistoryEntry {
+      timestamp: self.started_at.to_rfc3339(),
+      kind: self.kind.to_string(),
+      previous_version: self.previous_version,
+      target_version: read_source_version(&self.source_dir).ok(),
+      source_commit: git_output(&self.source_dir, &["rev-parse", "--short", "HEAD"]).ok(),
+      worker_commit,
+      duration_ms: self.started.elapsed().as_millis() as u64,
+      status: status.to_string(),
+      exit_code,
+      failed_phase,
+      log_path,
+    };
+    info!(
+      "{} update finished: {} ({} ms)",
+      entry.kind, entry.status, entry.duration_ms
+    );
+    if let Err(err) = append_update_history(&entry) {
+      error!("update history write failed: {}", err);
+    }
+  }
+}
+
+pub(crate) fn ensure_clean_checkout(dir: &Path, allow_dirty: bool) -> Result<(), String> {
+  if !allow_dirty && is_git_checkout(dir) && git_dirty(dir)? {
+    return Err(format!(
+      "{} has uncommitted changes; commit or stash them, or update with allow_dirty",
+      dir.to_string_lossy()
+    ));
+  }
+  Ok(())
+}
+
+pub(crate) fn app_update_command(source_dir: &Path) -> Result<Command, String> {
+  let mut command = update_command(source_dir)?;
+  command.current_dir(source_dir);
+  // Own process group so cancel_update can stop npm/cargo children too.
+  #[cfg(unix)]
+  {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+  }
+  Ok(command)
+}
+
+pub(crate) fn output_tail(stdout: &str, stderr: &str) -> Vec<String> {
+  let lines = stdout
+    .lines()
+    .chain(stderr.lines())
+    .filter(|line| !line.trim().is_empty())
+    .map(|line| line.to_string())
+    .collect::<Vec<_>>();
+  let skip = lines.len().saturating_sub(UPDATE_OUTPUT_TAIL_LINES);
+  lines.into_iter().skip(skip).collect()
+}
+
+pub(crate) fn venv_python(worker_dir: &Path) -> PathBuf {
+  if cfg!(target_os = "windows") {
+    worker_dir.join(".venv").join("Scripts").join("python.exe")
+  } else {
+    worker_dir.join(".venv").join("bin").join("python")
+  }
+}
+
+// Runs the commands of one phase in order under `stage` (None leaves staging to
+// the command's own markers). Spawn errors count as a failed phase.
+pub(crate) fn run_update_phase(
+  window: &tauri::Window,
+  stages: &Arc<Mutex<StageTracker>>,
+  job: &JobGuard,
+  phase: &str,
+  stage: Option<&str>,
+  commands: Vec<Command>,
+  log: &mut String,
+) -> UpdatePhaseResult {
+  if let Some(stage) = stage {
+    if let Ok(mut tracker) = stages.lock() {
+      let progress = tracker.enter(stage);
+      let _ = window.emit_all(tracker.event, progress);
+    }
+  }
+  let mut result = UpdatePhaseResult {
+    phase: phase.to_string(),
+    status: "ok".to_string(),
+    exit_code: Some(0),
+    output_tail: Vec::new(),
+  };
+  log.push_str(&format!("== {} ==\n", phase));
+  for command in commands {
+    match run_command_stream_with_stages(
+      &EventSink::from(window),
+      command,
+      Vec::new(),
+      true,
+      Some(stages.clone()),
+      Some(job),
+    ) {
+      Ok(run) => {
+        log.push_str(&run.stdout);
+        log.push_str(&run.stderr);
+        result.status = run.status;
+        result.exit_code = run.exit_code;
+        result.output_tail = output_tail(&run.stdout, &run.stderr);
+      }
+      Err(err) => {
+        log.push_str(&err);
+        log.push('\n');
+        result.status = "fail".to_string();
+        result.exit_code = None;
+        result.output_tail = vec![err];
+      }
+    }
+    if job.was_cancelled() {
+      result.status = "cancelled".to_string();
+    }
+    if result.status != "ok" {
+      break;
+    }
+  }
+  result
+}
+
+// Unix runs the bash script directly (shebang). Windows prefers a sibling .ps1
+// or .bat and otherwise runs the bash script through Git Bash.
+pub(crate) fn update_command(source_dir: &Path) -> Result<Command, String> {
+  let scripts = source_dir.join("scripts");
+  let script = scripts.join("update.receipts.operator");
+  #[cfg(target_os = "windows")]
+  {
+    use std::os::windows::process::CommandExt;
+    let ps1 = scripts.join("update.receipts.operator.ps1");
+    if ps1.exists() {
+      let mut command = Command::new("powershell");
+      command
+        .args(["-NoProfile", "-ExecutionPolicy", "Bypass", "-File"])
+        .arg(&ps1);
+      return Ok(command);
+    }
+    let bat = scripts.join("update.receipts.operator.bat");
+    if bat.exists() {
+      // cmd strips the outer pair of quotes, so paths with spaces need two.
+      let mut command = Command::new("cmd");
+      command
+        .arg("/C")
+        .raw_arg(format!("\"\"{}\"\"", bat.to_string_lossy()));
+      return Ok(command);
+    }
+    if script.exists() {
+      if let Some(bash) = find_git_bash() {
+        let mut command = Command::new(bash);
+        command.arg(&script);
+        return Ok(command);
+      }
+    }
+    return Err(format!(
+      "No runnable update script for this platform in {} (add update.receipts.operator.ps1 or .bat, or install Git Bash)",
+      scripts.to_string_lossy()
+    ));
+  }
+  #[cfg(not(target_os = "windows"))]
+  {
+    if !script.exists() {
+      return Err(format!(
+        "Update script not found: {}",
+        script.to_string_lossy()
+      ));
+    }
+    Ok(Command::new(script))
+  }
+}
+
+// Git for Windows ships bash.exe next to its cmd folder. `bash` on PATH is
+// skipped on purpose: System32\bash.exe is the WSL launcher.
+#[cfg(target_os = "windows")]
+pub(crate) fn find_git_bash() -> Option<PathBuf> {
+  let mut candidates = Vec::new();
+  for key in ["ProgramFiles", "ProgramW6432", "ProgramFiles(x86)"] {
+    if let Some(base) = env_var(key) {
+      candidates.push(PathBuf::from(base).join("Git").join("bin").join("bash.exe"));
+    }
+  }
+  if let Some(base) = env_var("LOCALAPPDATA") {
+    candidates.push(
+      PathBuf::from(base)
+        .join("Programs")
+        .join("Git")
+        .join("bin")
+        .join("bash.exe"),
+    );
+  }
+  if let Ok(output) = Command::new("where").arg("git").output() {
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+      if let Some(root) = Path::new(line.trim()).parent().and_then(Path::parent) {
+        candidates.push(root.join("bin").join("bash.exe"));
+      }
+    }
+  }
+  candidates.into_iter().find(|path| path.exists())
+}
+
+// Stopping the script while it replaces the installed bundle can leave no
+// working app, so cancellation is refused from the install-bundle stage on.
+pub(crate) fn cancel_update_job(registry: &ChildRegistry) -> Result<CancelResult, AppError> {
+  let refused = |reason: &str| -> Result<CancelResult, AppError> {
+    Ok(CancelResult {
+      cancelled: false,
+      reason: Some(reason.to_string()),
+    })
+  };
+  let mut jobs = registry.0.lock().map_err(|err| err.to_string())?;
+  let job = match jobs.get("update") {
+    Some(job) => job,
+    None => return refused("No update is running"),
+  };
+  let pid = match job.pid {
+    Some(pid) => pid,
+    None => return refused("The update has not started yet; try again"),
+  };
+  let stage = job.stages.as_ref().and_then(|tracker| {
+    tracker.lock().ok().map(|tracker| {
+      let install_index = tracker.position("install-bundle").unwrap_or(usize::MAX);
+      (tracker.current.clone(), tracker.index, install_index)
+    })
+  });
+  if let Some((name, index, install_index)) = stage {
+    if index >= install_index && name != "running" {
+      return refused(&format!(
+        "The update is in the {} stage; stopping it now could break the installed app",
+        name
+      ));
+    }
+  }
+  kill_process_group(pid)?;
+  job.cancelled.store(true, Ordering::SeqCst);
+  jobs.remove("update");
+  Ok(CancelResult {
+    cancelled: true,
+    reason: None,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn changelog_parses_keep_a_changelog_sections() {
+    let raw = "# Changelog\n\n## [Unreleased]\n- wip\n\n## [0.4.0] - 2024-02-01\n### Added\n- Tray menu\n  with inbox counts\n### Fixed\n* Badge reset\n\n## v0.3.2 (2024-01-10)\n- Older fix\n";
+    let entries = parse_changelog(raw);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].version, "0.4.0");
+    assert_eq!(entries[0].date.as_deref(), Some("2024-02-01"));
+    assert_eq!(
+      entries[0].items,
+      vec![
+        "Added: Tray menu with inbox counts".to_string(),
+        "Fixed: Badge reset".to_string()
+      ]
+    );
+    assert_eq!(entries[1].version, "0.3.2");
+    assert!(version_newer("0.4.0", "0.3.2"));
+    assert!(version_newer("0.10.0", "0.9.1"));
+    assert!(!version_newer("v0.3.2", "0.3.2"));
+    assert!(parse_changelog("just some notes\n- no versions").is_empty());
+  }
+
+  #[test]
+  fn full_update_stages_continue_into_script_markers() {
+    let mut tracker = StageTracker::new("update-progress", &FULL_UPDATE_STAGES);
+    let pull = tracker.enter("worker-pull");
+    assert_eq!((pull.index, pull.total), (1, 7));
+    tracker.enter("worker-deps");
+    let check = tracker
+      .observe("::stage::check-versions")
+      .expect("script stage");
+    assert_eq!((check.index, check.total), (3, 7));
+    assert_eq!(tracker.position("install-bundle"), Some(6));
+
+    let stdout = (1..=30)
+      .map(|line| format!("line {}\n", line))
+      .collect::<String>();
+    let tail = output_tail(&stdout, "error: no matching distribution\n");
+    assert_eq!(tail.len(), UPDATE_OUTPUT_TAIL_LINES);
+    assert_eq!(tail.first().map(String::as_str), Some("line 12"));
+    assert_eq!(
+      tail.last().map(String::as_str),
+      Some("error: no matching distribution")
+    );
+  }
+
+  #[test]
+  fn pending_update_comes_from_latest_successful_attempt() {
+    let attempt = |target: &str, status: &str| UpdateHistoryEntry {
+      timestamp: "2026-10-01T08:00:00+00:00".to_string(),
+      kind: "app".to_string(),
+      previous_version: "0.4.0".to_string(),
+      target_version: Some(target.to_string()),
+      source_commit: None,
+      worker_commit: None,
+      duration_ms: 1000,
+      status: status.to_string(),
+      exit_code: Some(0),
+      failed_phase: None,
+      log_path: None,
+    };
+    let history = vec![attempt("0.5.0", "ok"), attempt("0.6.0", "fail")];
+    let pending = pending_update_target(&history, "0.4.0").expect("restart pending");
+    assert_eq!(pending.target_version.as_deref(), Some("0.5.0"));
+    assert!(pending_update_target(&history, "v0.5").is_none());
+    assert!(pending_update_target(&history[1..], "0.4.0").is_none());
+    assert!(pending_update_target(&[], "0.4.0").is_none());
+  }
+
+  #[test]
+  fn plist_string_reads_bundle_version() {
+    let raw = "<plist><dict>\n  <key>CFBundleName</key>\n  <string>Receipts Operator</string>\n  <key>CFBundleShortVersionString</key>\n  <string>0.4.0</string>\n</dict></plist>";
+    assert_eq!(
+      plist_string(raw, "CFBundleShortVersionString").as_deref(),
+      Some("0.4.0")
+    );
+    assert_eq!(plist_string(raw, "CFBundleVersion"), None);
+  }
+
+  #[test]
+  fn update_status_compares_semver_precedence() {
+    assert_eq!(
+      update_status_for("0.4.9", "0.5.0"),
+      ("update_available", "semver")
+    );
+    assert_eq!(
+      update_status_for("0.5.0-dev", "0.4.9"),
+      ("ahead_of_source", "semver")
+    );
+    assert_eq!(
+      update_status_for("0.5.0-dev", "0.5.0"),
+      ("update_available", "semver")
+    );
+    assert_eq!(
+      update_status_for("0.5.0-alpha.2", "0.5.0-alpha.10"),
+      ("update_available", "semver")
+    );
+    assert_eq!(
+      update_status_for("0.5.0", "0.5.0-rc.1"),
+      ("ahead_of_source", "semver")
+    );
+    assert_eq!(
+      update_status_for("0.5.0+build.7", "0.5.0+build.9"),
+      ("up_to_date", "semver")
+    );
+    assert_eq!(update_status_for("v1.2", "1.2.0"), ("up_to_date", "semver"));
+    assert_eq!(
+      update_status_for("nightly", "nightly"),
+      ("up_to_date", "string")
+    );
+    assert_eq!(
+      update_status_for("nightly", "1.0.0"),
+      ("update_available", "string")
+    );
+  }
+}
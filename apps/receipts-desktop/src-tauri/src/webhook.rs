@@ -0,0 +1,401 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct WebhookConfig {
+  pub(crate) url: String,
+  // Subset of WEBHOOK_EVENTS; empty means every event.
+  #[serde(default)]
+  pub(crate) events: Vec<String>,
+  #[serde(default = "webhook_enabled_default")]
+  pub(crate) enabled: bool,
+}
+
+pub(crate) fn webhook_enabled_default() -> bool {
+  true
+}
+
+// What gets POSTed after a worker run (and, with `test: true`, by test_webhook).
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct WebhookPayload {
+  pub(crate) event: String,
+  // The worker's run id from its summary; None when the run wrote none.
+  pub(crate) run_id: Option<String>,
+  // How the worker process ended: `ok`, `fail` or `panic`.
+  pub(crate) status: String,
+  pub(crate) exit_code: Option<i32>,
+  pub(crate) stores: Vec<String>,
+  pub(crate) failure_count: usize,
+  pub(crate) warning_count: usize,
+  pub(crate) processed_count: Option<usize>,
+  pub(crate) finished_at: String,
+  pub(crate) app_version: String,
+  pub(crate) test: bool,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct WebhookTestResult {
+  pub(crate) status: u16,
+  pub(crate) ok: bool,
+  pub(crate) elapsed_ms: u64,
+}
+
+pub(crate) fn validate_webhook_url(url: &str) -> Result<(), AppError> {
+  let url = url.trim();
+  let rest = url
+    .strip_prefix("https://")
+    .or_else(|| url.strip_prefix("http://"));
+  match rest {
+    Some(rest) if !rest.is_empty() && !rest.starts_with('/') => Ok(()),
+    _ => Err(AppError::InvalidInput(format!(
+      "Webhook URL must start with http:// or https://: {}",
+      url
+    ))),
+  }
+}
+
+// Scheme and host only: webhook paths often carry a secret (Home Assistant
+// webhook ids, tokens in query strings), so that part stays out of the log.
+pub(crate) fn webhook_label(url: &str) -> String {
+  let url = url.trim();
+  let host_start = url.find("://").map(|index| index + 3).unwrap_or(0);
+  let host_end = url[host_start..]
+    .find(['/', '?', '#'])
+    .map(|index| host_start + index)
+    .unwrap_or(url.len());
+  url[..host_end].to_string()
+}
+
+// Drops webhooks with an unusable URL and unknown event names, with a warning
+// for each, so one typo doesn't fail the whole config.
+pub(crate) fn normalize_webhooks(webhooks: Vec<WebhookConfig>) -> Vec<WebhookConfig> {
+  webhooks
+    .into_iter()
+    .filter_map(|mut webhook| {
+      if let Err(err) = validate_webhook_url(&webhook.url) {
+        warn!("webhooks: skipping entry: {}", err);
+        return None;
+      }
+      webhook.url = webhook.url.trim().to_string();
+      webhook.events.retain(|event| {
+        let known = WEBHOOK_EVENTS.contains(&event.as_str());
+        if !known {
+          warn!(
+            "webhooks: unknown event {} for {}",
+            event,
+            webhook_label(&webhook.url)
+          );
+        }
+        known
+      });
+      Some(webhook)
+    })
+    .collect()
+}
+
+// One event per run: `failure` when the worker failed or reported failures,
+// else `warning` when it reported warnings, else `success`.
+pub(crate) fn webhook_event(
+  status: &str,
+  failure_count: usize,
+  warning_count: usize,
+) -> &'static str {
+  if status != "ok" || failure_count > 0 {
+    "failure"
+  } else if warning_count > 0 {
+    "warning"
+  } else {
+    "success"
+  }
+}
+
+pub(crate) fn webhook_wants(webhook: &WebhookConfig, event: &str) -> bool {
+  webhook.enabled
+    && (webhook.events.is_empty() || webhook.events.iter().any(|wanted| wanted == event))
+}
+
+// The newest summary written since the run started (with some slack for
+// coarse mtimes on synced folders).
+pub(crate) fn run_summary_since(
+  receipts_root: &str,
+  started: std::time::SystemTime,
+) -> Option<Value> {
+  let since = started
+    .checked_sub(WEBHOOK_SUMMARY_SLACK)
+    .unwrap_or(started);
+  list_run_summaries(receipts_root)
+    .into_iter()
+    .filter_map(|(value, modified)| Some((modified?, value)))
+    .filter(|(modified, _)| *modified >= since)
+    .max_by_key(|(modified, _)| *modified)
+    .map(|(_, value)| value)
+}
+
+pub(crate) fn run_webhook_payload(
+  result: &RunWorkerResult,
+  stores: &[String],
+  summary: Option<&Value>,
+) -> WebhookPayload {
+  let failure_count = summary
+    .map(|summary| extract_issue_count(summary, "failures"))
+    .unwrap_or(0);
+  let warning_count = summary
+    .map(|summary| extract_issue_count(summary, "warnings"))
+    .unwrap_or(0);
+  WebhookPayload {
+    event: webhook_event(&result.status, failure_count, warning_count).to_string(),
+    run_id: summary.and_then(extract_run_id),
+    status: result.status.clone(),
+    exit_code: result.exit_code,
+    stores: summary
+      .map(extract_stores)
+      .filter(|stores| !stores.is_empty())
+      .unwrap_or_else(|| stores.to_vec()),
+    failure_count,
+    warning_count,
+    processed_count: summary.and_then(extract_processed_count),
+    finished_at: chrono::Utc::now().to_rfc3339(),
+    app_version: APP_VERSION.to_string(),
+    test: false,
+  }
+}
+
+// One POST; Ok with the status for any HTTP response, Err when none came back.
+pub(crate) fn post_webhook(url: &str, payload: &WebhookPayload) -> Result<u16, String> {
+  let body = serde_json::to_string(payload).map_err(|err| err.to_string())?;
+  let agent = ureq::AgentBuilder::new()
+    .timeout(WEBHOOK_TIMEOUT)
+    .redirects(0)
+    .build();
+  match agent
+    .post(url)
+    .set("Content-Type", "application/json")
+    .set("User-Agent", &format!("receipts-desktop/{}", APP_VERSION))
+    .send_string(&body)
+  {
+    Ok(response) => Ok(response.status()),
+    Err(ureq::Error::Status(status, _)) => Ok(status),
+    Err(err) => Err(err.to_string()),
+  }
+}
+
+// Retries once after WEBHOOK_RETRY_DELAY when there was no response or it
+// wasn't a 2xx; every attempt ends up in the app log.
+pub(crate) fn deliver_webhook(url: &str, payload: &WebhookPayload) -> Result<u16, String> {
+  let label = webhook_label(url);
+  let mut outcome = Err(String::new());
+  for attempt in 1..=2 {
+    if attempt > 1 {
+      thread::sleep(WEBHOOK_RETRY_DELAY);
+    }
+    let started = Instant::now();
+    outcome = match post_webhook(url, payload) {
+      Ok(status) if (200..300).contains(&status) => Ok(status),
+      Ok(status) => Err(format!("HTTP {}", status)),
+      Err(err) => Err(err),
+    };
+    match &outcome {
+      Ok(status) => {
+        info!(
+          "webhook {} ({}): {} in {}ms",
+          label,
+          payload.event,
+          status,
+          started.elapsed().as_millis()
+        );
+        break;
+      }
+      Err(err) => warn!(
+        "webhook {} ({}) attempt {} failed: {}",
+        label, payload.event, attempt, err
+      ),
+    }
+  }
+  outcome
+}
+
+// Called once a worker run has finished; the deliveries run on their own
+// thread so neither the command nor the UI waits on a slow endpoint. Headless
+// runs join the returned thread before exiting.
+pub(crate) fn dispatch_run_webhooks(
+  config: &AppConfig,
+  stores: &[String],
+  started: std::time::SystemTime,
+  result: &RunWorkerResult,
+) -> Option<thread::JoinHandle<()>> {
+  if !config.webhooks.iter().any(|webhook| webhook.enabled) {
+    return None;
+  }
+  let summary = run_summary_since(&config.receipts_root, started);
+  let payload = run_webhook_payload(result, stores, summary.as_ref());
+  let urls = config
+    .webhooks
+    .iter()
+    .filter(|webhook| webhook_wants(webhook, &payload.event))
+    .map(|webhook| webhook.url.clone())
+    .collect::<Vec<_>>();
+  if urls.is_empty() {
+    return None;
+  }
+  Some(thread::spawn(move || {
+    for url in urls {
+      let _ = deliver_webhook(&url, &payload);
+    }
+  }))
+}
+
+pub(crate) fn sample_webhook_payload() -> WebhookPayload {
+  WebhookPayload {
+    event: "failure".to_string(),
+    run_id: Some(chrono::Local::now().format("%Y%m%d-%H%M%S").to_string()),
+    status: "ok".to_string(),
+    exit_code: Some(0),
+    stores: vec!["lidl".to_string()],
+    failure_count: 1,
+    warning_count: 2,
+    processed_count: Some(5),
+    finished_at: chrono::Utc::now().to_rfc3339(),
+    app_version: APP_VERSION.to_string(),
+    test: true,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::SystemTime;
+
+  // Answers each connection with the next status and hands back the bodies.
+  fn webhook_endpoint(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<Value>>) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).expect("listener");
+    let url = format!(
+      "http://127.0.0.1:{}/api/webhook/secret-id",
+      listener.local_addr().expect("addr").port()
+    );
+    let handle = thread::spawn(move || {
+      let mut bodies = Vec::new();
+      for status in statuses {
+        let (stream, _) = listener.accept().expect("accept");
+        let mut reader = BufReader::new(stream.try_clone().expect("clone"));
+        let mut length = 0;
+        loop {
+          let mut line = String::new();
+          reader.read_line(&mut line).expect("header");
+          if line.trim().is_empty() {
+            break;
+          }
+          if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+              length = value.trim().parse().expect("length");
+            }
+          }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).expect("body");
+        bodies.push(serde_json::from_slice(&body).expect("json body"));
+        let mut stream = stream;
+        write!(
+          stream,
+          "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+          status
+        )
+        .expect("response");
+      }
+      bodies
+    });
+    (url, handle)
+  }
+
+  #[test]
+  fn webhooks_pick_events_from_the_run_summary_and_retry_once() {
+    assert!(validate_webhook_url("https://ha.local/api/webhook/x").is_ok());
+    assert!(validate_webhook_url("ftp://ha.local").is_err());
+    assert!(validate_webhook_url("http:///path").is_err());
+    assert_eq!(
+      webhook_label("http://ha.local:8123/api/webhook/secret?token=x"),
+      "http://ha.local:8123"
+    );
+    let webhooks = normalize_webhooks(vec![
+      WebhookConfig {
+        url: " http://ha.local/hook ".to_string(),
+        events: vec!["failure".to_string(), "crash".to_string()],
+        enabled: true,
+      },
+      WebhookConfig {
+        url: "ha.local/hook".to_string(),
+        events: Vec::new(),
+        enabled: true,
+      },
+    ]);
+    assert_eq!(webhooks.len(), 1);
+    assert_eq!(webhooks[0].url, "http://ha.local/hook");
+    assert_eq!(webhooks[0].events, vec!["failure".to_string()]);
+    assert!(webhook_wants(&webhooks[0], "failure"));
+    assert!(!webhook_wants(&webhooks[0], "success"));
+    let every = WebhookConfig {
+      url: "http://ha.local".to_string(),
+      events: Vec::new(),
+      enabled: false,
+    };
+    assert!(!webhook_wants(&every, "success"));
+    assert!(webhook_wants(
+      &WebhookConfig {
+        enabled: true,
+        ..every
+      },
+      "success"
+    ));
+
+    assert_eq!(webhook_event("ok", 0, 0), "success");
+    assert_eq!(webhook_event("ok", 0, 3), "warning");
+    assert_eq!(webhook_event("ok", 1, 3), "failure");
+    assert_eq!(webhook_event("fail", 0, 0), "failure");
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let runs = dir.path().join("_logs").join("runs");
+    fs::create_dir_all(&runs).expect("runs");
+    let root = dir.path().to_string_lossy().to_string();
+    let started = SystemTime::now();
+    assert_eq!(run_summary_since(&root, started), None);
+    fs::write(
+      runs.join("20240105-093000.summary.json"),
+      serde_json::json!({
+        "run_id": "20240105-093000",
+        "stores": ["lidl"],
+        "failures": [{"file": "a.jpg"}],
+        "warnings": [],
+        "processed_count": 4,
+      })
+      .to_string(),
+    )
+    .expect("summary");
+    let summary = run_summary_since(&root, started);
+    let result = RunWorkerResult {
+      status: "ok".to_string(),
+      exit_code: Some(0),
+      stdout: String::new(),
+      stderr: String::new(),
+      stage: None,
+    };
+    let payload = run_webhook_payload(&result, &["penny".to_string()], summary.as_ref());
+    assert_eq!(payload.event, "failure");
+    assert_eq!(payload.run_id.as_deref(), Some("20240105-093000"));
+    assert_eq!(payload.stores, vec!["lidl".to_string()]);
+    assert_eq!((payload.failure_count, payload.warning_count), (1, 0));
+    assert_eq!(payload.processed_count, Some(4));
+    let bare = run_webhook_payload(&result, &["penny".to_string()], None);
+    assert_eq!((bare.event.as_str(), bare.run_id), ("success", None));
+    assert_eq!(bare.stores, vec!["penny".to_string()]);
+
+    let (url, endpoint) = webhook_endpoint(vec![500, 204]);
+    assert_eq!(deliver_webhook(&url, &payload), Ok(204));
+    let bodies = endpoint.join().expect("endpoint");
+    assert_eq!(bodies.len(), 2);
+    assert_eq!(bodies[1]["run_id"], "20240105-093000");
+    assert_eq!(bodies[1]["failure_count"], 1);
+    assert_eq!(bodies[1]["test"], false);
+
+    let (url, endpoint) = webhook_endpoint(vec![404]);
+    assert_eq!(post_webhook(&url, &sample_webhook_payload()), Ok(404));
+    assert_eq!(endpoint.join().expect("endpoint")[0]["test"], true);
+  }
+}
@@ -0,0 +1,141 @@
+use crate::*;
+
+// Each run gets one window, labelled from its id. Labels only allow
+// `a-zA-Z0-9-/:_`, so anything else (and `:` itself) is escaped as `:<hex>`.
+pub(crate) fn run_window_label(run_id: &str) -> String {
+  let mut label = RUN_WINDOW_PREFIX.to_string();
+  for c in run_id.chars() {
+    if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+      label.push(c);
+    } else {
+      label.push_str(&format!(":{:x}", c as u32));
+    }
+  }
+  label
+}
+
+pub(crate) fn is_run_window(label: &str) -> bool {
+  label.starts_with(RUN_WINDOW_PREFIX)
+}
+
+// The main window and the run windows keep their geometry under separate keys;
+// run windows share one, so a new one opens where the last was left.
+pub(crate) fn geometry_pref_key(label: &str) -> Option<&'static str> {
+  if label == "main" {
+    Some(PREF_WINDOW_GEOMETRY)
+  } else if is_run_window(label) {
+    Some(PREF_RUN_WINDOW_GEOMETRY)
+  } else {
+    None
+  }
+}
+
+pub(crate) fn focus_window(window: &tauri::Window) {
+  let _ = window.show();
+  let _ = window.unminimize();
+  let _ = window.set_focus();
+}
+
+// Focuses the run's window when it is already open. A new one is created
+// hidden, given the saved run-window geometry (moved down and right a step per
+// run window already open, so they don't cover each other exactly) and then
+// shown on the `#/run/<id>` route.
+pub(crate) fn open_run_window_for(
+  app: &tauri::AppHandle,
+  run_id: &str,
+) -> Result<String, AppError> {
+  let label = run_window_label(run_id);
+  if let Some(window) = app.get_window(&label) {
+    focus_window(&window);
+    return Ok(label);
+  }
+  let window = tauri::WindowBuilder::new(
+    app,
+    label.clone(),
+    tauri::WindowUrl::App(format!("index.html#/run/{}", run_id).into()),
+  )
+  .title(format!("Run {}", run_id))
+  .inner_size(RUN_WINDOW_SIZE.0, RUN_WINDOW_SIZE.1)
+  .visible(false)
+  .build()
+  .map_err(|err| AppError::Other(format!("Could not open a window for {}: {}", run_id, err)))?;
+  let open = app
+    .windows()
+    .keys()
+    .filter(|other| is_run_window(other) && **other != label)
+    .count();
+  let prefs = app
+    .state::<PrefsStore>()
+    .prefs
+    .lock()
+    .map(|prefs| prefs.clone())
+    .unwrap_or_default();
+  restore_window_geometry(&window, &prefs);
+  if open > 0 && !window.is_maximized().unwrap_or(false) {
+    if let Ok(position) = window.outer_position() {
+      let step = RUN_WINDOW_CASCADE * open.min(10) as i32;
+      let _ = window.set_position(tauri::PhysicalPosition::new(
+        position.x + step,
+        position.y + step,
+      ));
+    }
+  }
+  focus_window(&window);
+  info!("opened run window {}", label);
+  Ok(label)
+}
+
+// Closing the main window hides it to the tray when `minimize_to_tray` is on
+// and the run windows stay; otherwise it is a quit (see `request_quit`), and
+// the run windows close with it. Run windows just close.
+pub(crate) fn handle_close_requested(window: &tauri::Window, api: &tauri::CloseRequestApi) {
+  if window.label() != "main" {
+    return;
+  }
+  if current_config(window).minimize_to_tray {
+    let _ = window.hide();
+    api.prevent_close();
+    return;
+  }
+  if !busy_jobs(&window.app_handle()).is_empty() {
+    api.prevent_close();
+    request_quit(&window.app_handle(), "window");
+    return;
+  }
+  for (label, other) in window.windows() {
+    if is_run_window(&label) {
+      let _ = other.close();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::*;
+
+  #[test]
+  fn run_windows_get_one_label_per_run_and_their_own_geometry() {
+    assert_eq!(run_window_label("20240101-120000"), "run-20240101-120000");
+    assert_eq!(run_window_label("lidl.2024:1"), "run-lidl:2e2024:3a1");
+    assert_ne!(run_window_label("a.b"), run_window_label("a_b"));
+    assert!(is_run_window(&run_window_label("a.b")));
+    assert_eq!(geometry_pref_key("main"), Some(PREF_WINDOW_GEOMETRY));
+    assert_eq!(
+      geometry_pref_key(&run_window_label("a")),
+      Some(PREF_RUN_WINDOW_GEOMETRY)
+    );
+    assert_eq!(geometry_pref_key("other"), None);
+
+    let config = badge_config("lidl");
+    let geometry =
+      serde_json::json!({"width": 900, "height": 700, "x": 10, "y": 20, "maximized": false});
+    assert!(validate_pref(&config, PREF_RUN_WINDOW_GEOMETRY, &geometry).is_ok());
+    assert!(validate_pref(
+      &config,
+      PREF_RUN_WINDOW_GEOMETRY,
+      &serde_json::json!({"width": "wide"})
+    )
+    .is_err());
+  }
+}
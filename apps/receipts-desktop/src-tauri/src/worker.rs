@@ -0,0 +1,975 @@
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct RunWorkerResult {
+  pub(crate) status: String,
+  pub(crate) exit_code: Option<i32>,
+  pub(crate) stdout: String,
+  pub(crate) stderr: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub(crate) stage: Option<StageProgress>,
+}
+
+// `status` stays None while the stage is running and is set on the final event.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct StageProgress {
+  pub(crate) stage: String,
+  pub(crate) index: usize,
+  pub(crate) total: usize,
+  pub(crate) elapsed_ms: u64,
+  pub(crate) stage_elapsed_ms: u64,
+  pub(crate) status: Option<String>,
+}
+
+// Cancellable child processes by job name ("update", "worker"). An entry exists from the
+// moment a job is reserved, so it doubles as the job's lock; `pid` is filled in
+// once the process is spawned.
+#[derive(Default)]
+pub(crate) struct ChildRegistry(pub(crate) Mutex<HashMap<String, RunningJob>>);
+
+pub(crate) struct RunningJob {
+  pub(crate) pid: Option<u32>,
+  pub(crate) stages: Option<Arc<Mutex<StageTracker>>>,
+  pub(crate) cancelled: Arc<AtomicBool>,
+}
+
+// Releases the job's registry entry on drop; a cancel only flags it, so the
+// slot stays taken until the job has actually finished.
+pub(crate) struct JobGuard {
+  pub(crate) app: tauri::AppHandle,
+  pub(crate) name: String,
+  pub(crate) cancelled: Arc<AtomicBool>,
+}
+
+// Follows `::stage::<name>` marker lines in command output. Names from `stages`
+// keep their position; unknown names count as the next stage. Without any
+// marker the whole run is a single "running" stage.
+pub(crate) struct StageTracker {
+  pub(crate) event: &'static str,
+  pub(crate) stages: &'static [&'static str],
+  pub(crate) current: String,
+  pub(crate) index: usize,
+  pub(crate) total: usize,
+  pub(crate) started: Instant,
+  pub(crate) stage_started: Instant,
+}
+
+impl StageTracker {
+  pub(crate) fn new(event: &'static str, stages: &'static [&'static str]) -> StageTracker {
+    StageTracker {
+      event,
+      stages,
+      current: "running".to_string(),
+      index: 1,
+      total: 1,
+      started: Instant::now(),
+      stage_started: Instant::now(),
+    }
+  }
+
+  pub(crate) fn progress(&self, status: Option<&str>) -> StageProgress {
+    StageProgress {
+      stage: self.current.clone(),
+      index: self.index,
+      total: self.total,
+      elapsed_ms: self.started.elapsed().as_millis() as u64,
+      stage_elapsed_ms: self.stage_started.elapsed().as_millis() as u64,
+      status: status.map(|status| status.to_string()),
+    }
+  }
+
+  pub(crate) fn observe(&mut self, line: &str) -> Option<StageProgress> {
+    let name = line.trim().strip_prefix(STAGE_MARKER)?.trim();
+    if name.is_empty() {
+      return None;
+    }
+    Some(self.enter(name))
+  }
+
+  pub(crate) fn enter(&mut self, name: &str) -> StageProgress {
+    let first_marker = self.current == "running" && self.total == 1;
+    self.index = match self.position(name) {
+      Some(position) => position,
+      None if first_marker => 1,
+      None => self.index + 1,
+    };
+    self.total = self.stages.len().max(self.index);
+    self.current = name.to_string();
+    self.stage_started = Instant::now();
+    self.progress(None)
+  }
+
+  // 1-based position of a named stage, if it is one of the known stages.
+  pub(crate) fn position(&self, name: &str) -> Option<usize> {
+    self
+      .stages
+      .iter()
+      .position(|stage| *stage == name)
+      .map(|position| position + 1)
+  }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct WorkerStatus {
+  pub(crate) running: bool,
+  pub(crate) pid: Option<u32>,
+  // False until the process has been spawned.
+  pub(crate) cancellable: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct WorkerLogEvent {
+  pub(crate) stream: String,
+  pub(crate) line: String,
+  pub(crate) stores: Vec<String>,
+}
+
+// Worker runs and app updates both go through `run_command_stream_with_stages`; background
+// update checks wait while any of them is in flight.
+#[derive(Default)]
+pub(crate) struct CommandsRunning(pub(crate) Mutex<usize>);
+
+pub(crate) struct CommandGuard(pub(crate) tauri::AppHandle);
+
+impl CommandGuard {
+  pub(crate) fn new(app: tauri::AppHandle) -> CommandGuard {
+    if let Ok(mut running) = app.state::<CommandsRunning>().0.lock() {
+      *running += 1;
+    }
+    CommandGuard(app)
+  }
+}
+
+impl Drop for CommandGuard {
+  fn drop(&mut self) {
+    if let Ok(mut running) = self.0.state::<CommandsRunning>().0.lock() {
+      *running = running.saturating_sub(1);
+    }
+  }
+}
+
+pub(crate) fn worker_config_error(config: &AppConfig) -> Option<String> {
+  if config.config_ready {
+    return None;
+  }
+  let mut missing = Vec::new();
+  if !Path::new(config.receipts_root.trim()).exists() {
+    missing.push(format!("RECEIPTS_ROOT not found: {}", config.receipts_root));
+  }
+  if config
+    .worker_run_cmd
+    .as_deref()
+    .unwrap_or("")
+    .trim()
+    .is_empty()
+  {
+    missing.push("WORKER_RUN_CMD is empty".to_string());
+  } else if let Some(cmd) = &config.worker_run_cmd {
+    if !Path::new(cmd).exists() {
+      missing.push(format!("WORKER_RUN_CMD not found: {}", cmd));
+    }
+  }
+  if config.worker_dir.as_deref().unwrap_or("").trim().is_empty() {
+    missing.push("WORKER_DIR is empty".to_string());
+  } else if let Some(dir) = &config.worker_dir {
+    if !Path::new(dir).exists() {
+      missing.push(format!("WORKER_DIR not found: {}", dir));
+    }
+  }
+  if missing.is_empty() {
+    Some("Missing config: set RECEIPTS_ROOT and WORKER_DIR/WORKER_RUN_CMD".to_string())
+  } else {
+    Some(format!("Missing config: {}", missing.join(" | ")))
+  }
+}
+
+// Where run output and progress go. In the app that is every window, so a run
+// window follows the log as well as the window that started the run; headless
+// (see `run_headless`) log lines go to the terminal and other events to the log.
+#[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum EventSink {
+  App(tauri::AppHandle),
+  // With `json`, stdout is kept for the final result and every line goes to stderr.
+  Terminal { json: bool },
+}
+
+impl EventSink {
+  pub(crate) fn app(&self) -> Option<&tauri::AppHandle> {
+    match self {
+      EventSink::App(app) => Some(app),
+      EventSink::Terminal { .. } => None,
+    }
+  }
+
+  pub(crate) fn emit<S: Serialize + Clone>(&self, event: &str, payload: S) {
+    match self {
+      EventSink::App(app) => {
+        let _ = app.emit_all(event, payload);
+      }
+      EventSink::Terminal { .. } => {
+        debug!(
+          "{}: {}",
+          event,
+          serde_json::to_string(&payload).unwrap_or_default()
+        );
+      }
+    }
+  }
+
+  pub(crate) fn log_line(&self, stream: &str, line: String, stores: &[String]) {
+    match self {
+      EventSink::App(app) => {
+        let _ = app.emit_all(
+          "worker-log",
+          WorkerLogEvent {
+            stream: stream.to_string(),
+            line,
+            stores: stores.to_vec(),
+          },
+        );
+      }
+      EventSink::Terminal { json: false } if stream == "stdout" => println!("{}", line),
+      EventSink::Terminal { .. } => eprintln!("{}", line),
+    }
+  }
+}
+
+impl From<&tauri::Window> for EventSink {
+  fn from(window: &tauri::Window) -> EventSink {
+    EventSink::App(window.app_handle())
+  }
+}
+
+pub(crate) fn emit_worker_line(sink: &EventSink, stream: &str, line: String, stores: &[String]) {
+  sink.log_line(stream, line, stores);
+}
+
+pub(crate) fn store_args(stores: &[String]) -> Vec<String> {
+  let mut args: Vec<String> = Vec::new();
+  if stores.is_empty() {
+    args.push("--all".to_string());
+  } else if stores.len() == 1 {
+    args.push("--store".to_string());
+    args.push(stores.join(","));
+  } else {
+    args.push("--stores".to_string());
+    args.push(stores.join(","));
+  }
+  args
+}
+
+// What a worker run would execute: WORKER_RUN_CMD as is, or `python -m
+// src.runner` in WORKER_DIR with its venv interpreter when there is one.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct WorkerLaunch {
+  pub(crate) mode: &'static str,
+  pub(crate) program: String,
+  pub(crate) args: Vec<String>,
+  pub(crate) current_dir: Option<String>,
+}
+
+pub(crate) fn resolve_worker_launch(config: &AppConfig) -> Result<WorkerLaunch, String> {
+  if let Some(run_cmd) = &config.worker_run_cmd {
+    return Ok(WorkerLaunch {
+      mode: "run_cmd",
+      program: run_cmd.clone(),
+      args: Vec::new(),
+      current_dir: config.worker_dir.clone(),
+    });
+  }
+
+  let worker_dir = config
+    .worker_dir
+    .clone()
+    .ok_or_else(|| "WORKER_DIR is not set".to_string())?;
+  let mut python_path = Path::new(&worker_dir)
+    .join(".venv")
+    .join("bin")
+    .join("python");
+  if !python_path.exists() {
+    python_path = PathBuf::from("python3");
+  }
+  Ok(WorkerLaunch {
+    mode: "python_module",
+    program: python_path.to_string_lossy().to_string(),
+    args: vec!["-m".to_string(), "src.runner".to_string()],
+    current_dir: Some(worker_dir),
+  })
+}
+
+pub(crate) fn build_worker_command(
+  config: &AppConfig,
+  args: Vec<String>,
+) -> Result<Command, String> {
+  let launch = resolve_worker_launch(config)?;
+  let mut command = Command::new(&launch.program);
+  command.args(&launch.args).args(args);
+  if let Some(dir) = &launch.current_dir {
+    command.current_dir(dir);
+  }
+  Ok(command)
+}
+
+pub(crate) fn start_worker(
+  sink: &EventSink,
+  config: &AppConfig,
+  stores: &[String],
+) -> Result<(), AppError> {
+  if let Some(details) = worker_config_error(config) {
+    emit_worker_line(sink, "stderr", details.clone(), stores);
+    return Err(AppError::WorkerNotConfigured(details));
+  }
+  let reachable = match sink.app() {
+    Some(app) => require_reachable_root(app, config),
+    None => probe_root_once(config),
+  };
+  if let Err(err) = reachable {
+    emit_worker_line(sink, "stderr", err.message(), stores);
+    return Err(err);
+  }
+  emit_worker_line(
+    sink,
+    "stdout",
+    format!(
+      "Starting worker: root={} dir={} cmd={}",
+      config.receipts_root,
+      config.worker_dir.clone().unwrap_or_else(|| "—".to_string()),
+      config
+        .worker_run_cmd
+        .clone()
+        .unwrap_or_else(|| "—".to_string())
+    ),
+    stores,
+  );
+  Ok(())
+}
+
+// Headless runs have no job registry, menu or budget alerts to update; they
+// wait for the webhooks to go out instead, since the process exits next.
+pub(crate) fn spawn_worker(
+  sink: &EventSink,
+  config: &AppConfig,
+  args: Vec<String>,
+  stores: Vec<String>,
+) -> Result<RunWorkerResult, AppError> {
+  let started = std::time::SystemTime::now();
+  let job = match sink.app() {
+    Some(app) => Some(reserve_worker_job(app)?),
+    None => None,
+  };
+  let result = run_reserved_worker(sink, config, args, &stores, job.as_ref());
+  release_worker_job(job);
+  finish_worker_run(sink, config, &stores, started, result?)
+}
+
+// Holds the "worker" slot; callers that prepare the inbox for a run take it
+// before touching any file, so a run already going keeps its files.
+pub(crate) fn reserve_worker_job(app: &tauri::AppHandle) -> Result<JobGuard, AppError> {
+  let job = reserve_job(app, "worker", None)?;
+  emit_worker_status(app);
+  Ok(job)
+}
+
+pub(crate) fn release_worker_job(job: Option<JobGuard>) {
+  if let Some(job) = job {
+    let app = job.app.clone();
+    drop(job);
+    emit_worker_status(&app);
+  }
+}
+
+// Runs the worker under `job` (None for headless runs, which have no registry).
+pub(crate) fn run_reserved_worker(
+  sink: &EventSink,
+  config: &AppConfig,
+  args: Vec<String>,
+  stores: &[String],
+  job: Option<&JobGuard>,
+) -> Result<RunWorkerResult, AppError> {
+  let mut command = build_worker_command(config, args).map_err(AppError::WorkerNotConfigured)?;
+  // Own process group so cancel_run stops whatever the runner started too.
+  #[cfg(unix)]
+  if job.is_some() {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+  }
+  let mut result = run_command_stream_with_stages(sink, command, stores.to_vec(), false, None, job)
+    .map_err(AppError::WorkerSpawnFailed)?;
+  if job.is_some_and(|job| job.was_cancelled()) {
+    result.status = "cancelled".to_string();
+  }
+  Ok(result)
+}
+
+// Webhooks, budgets and publishing once the worker slot is free again.
+pub(crate) fn finish_worker_run(
+  sink: &EventSink,
+  config: &AppConfig,
+  stores: &[String],
+  started: std::time::SystemTime,
+  result: RunWorkerResult,
+) -> Result<RunWorkerResult, AppError> {
+  let app = match sink.app() {
+    Some(app) => app.clone(),
+    None => {
+      if let Some(delivery) = dispatch_run_webhooks(config, stores, started, &result) {
+        let _ = delivery.join();
+      }
+      return Ok(result);
+    }
+  };
+  dispatch_run_webhooks(config, stores, started, &result);
+  let publish = config.publish_summary_after_run;
+  thread::spawn(move || {
+    if let Err(err) = check_budgets(&app) {
+      warn!("budgets: {}", err);
+    }
+    if publish {
+      // Failures are logged and emitted by publish_summary itself.
+      let _ = publish_summary(&app, true);
+    }
+  });
+  Ok(result)
+}
+
+pub(crate) fn worker_status(app: &tauri::AppHandle) -> WorkerStatus {
+  let job = app.state::<ChildRegistry>().0.lock().ok().and_then(|jobs| {
+    jobs
+      .get("worker")
+      .map(|job| (job.pid, job.cancelled.load(Ordering::SeqCst)))
+  });
+  WorkerStatus {
+    running: job.is_some(),
+    pid: job.and_then(|(pid, _)| pid),
+    cancellable: matches!(job, Some((Some(_), false))),
+  }
+}
+
+// `worker-status` goes out when a run starts and when it ends (or is
+// cancelled); the app menu follows it.
+pub(crate) fn emit_worker_status(app: &tauri::AppHandle) {
+  let status = worker_status(app);
+  refresh_menu_state(app, Some(&status));
+  let _ = app.emit_all("worker-status", status);
+}
+
+// Stops the running worker and everything it started. The run then ends with
+// status `cancelled`; files the worker already moved stay where they are.
+pub(crate) fn cancel_worker_run(app: &tauri::AppHandle) -> Result<CancelResult, AppError> {
+  let result = cancel_worker_job(&app.state::<ChildRegistry>())?;
+  if result.cancelled {
+    info!("worker run cancelled");
+    emit_worker_status(app);
+  }
+  Ok(result)
+}
+
+// The entry stays until the run's JobGuard drops after the process exits, so
+// no other run can start while this one is still stopping.
+pub(crate) fn cancel_worker_job(registry: &ChildRegistry) -> Result<CancelResult, AppError> {
+  let refused = |reason: &str| -> Result<CancelResult, AppError> {
+    Ok(CancelResult {
+      cancelled: false,
+      reason: Some(reason.to_string()),
+    })
+  };
+  let jobs = registry.0.lock().map_err(|err| err.to_string())?;
+  let job = match jobs.get("worker") {
+    Some(job) => job,
+    None => return refused("No run is in progress"),
+  };
+  if job.cancelled.load(Ordering::SeqCst) {
+    return refused("The run is already stopping");
+  }
+  let pid = match job.pid {
+    Some(pid) => pid,
+    None => return refused("The run has not started yet; try again"),
+  };
+  kill_process_group(pid)?;
+  job.cancelled.store(true, Ordering::SeqCst);
+  Ok(CancelResult {
+    cancelled: true,
+    reason: None,
+  })
+}
+
+pub(crate) fn staging_dir_name(run_id: &str) -> String {
+  format!("{}{}", STAGING_DIR_PREFIX, run_id)
+}
+
+pub(crate) fn is_staging_dir(path: &Path) -> bool {
+  path.is_dir()
+    && path
+      .file_name()
+      .is_some_and(|name| name.to_string_lossy().starts_with(STAGING_DIR_PREFIX))
+}
+
+pub(crate) fn restore_staged_files(staging: &Path) -> Result<usize, String> {
+  let inbox = staging.parent().ok_or("Staging folder has no parent")?;
+  let mut restored = 0usize;
+  for entry in fs::read_dir(staging)
+    .map_err(|err| err.to_string())?
+    .flatten()
+  {
+    // Back under the exact name unless something took it meanwhile.
+    let destination = match inbox.join(entry.file_name()) {
+      path if !path.exists() => path,
+      _ => unique_destination(inbox, entry.file_name()),
+    };
+    fs::rename(entry.path(), destination).map_err(|err| err.to_string())?;
+    restored += 1;
+  }
+  fs::remove_dir(staging).map_err(|err| err.to_string())?;
+  Ok(restored)
+}
+
+pub(crate) fn restore_leftover_staging(config: &AppConfig) {
+  for store in &config.stores {
+    let inbox = Path::new(&config.receipts_root)
+      .join("inbox")
+      .join(&store.id);
+    let entries = match fs::read_dir(&inbox) {
+      Ok(entries) => entries,
+      Err(_) => continue,
+    };
+    for entry in entries.flatten() {
+      let path = entry.path();
+      if !is_staging_dir(&path) {
+        continue;
+      }
+      match restore_staged_files(&path) {
+        Ok(count) => info!(
+          "restored {} staged files from {}",
+          count,
+          path.to_string_lossy()
+        ),
+        Err(err) => error!(
+          "failed to restore staging folder {}: {}",
+          path.to_string_lossy(),
+          err
+        ),
+      }
+    }
+  }
+}
+
+// Timestamp plus random bytes, so two runs started in the same second never
+// share a staging folder.
+pub(crate) fn new_staging_id() -> String {
+  let mut bytes = [0u8; 4];
+  let _ = getrandom::getrandom(&mut bytes);
+  format!(
+    "{}-{}",
+    chrono::Local::now().format("%Y%m%d-%H%M%S"),
+    bytes
+      .iter()
+      .map(|byte| format!("{:02x}", byte))
+      .collect::<String>()
+  )
+}
+
+// Moves the selected files (by file id) into a hidden `.staged-<id>` folder
+// the worker is pointed at with `--inbox`; what it leaves there goes back
+// afterwards, or on the next launch after a crash. The folder must be new.
+pub(crate) fn stage_selected_files(
+  inbox: &Path,
+  selected: &[String],
+  staging_id: &str,
+) -> Result<PathBuf, String> {
+  let staging = inbox.join(staging_dir_name(staging_id));
+  fs::create_dir(&staging).map_err(|err| err.to_string())?;
+  for entry in fs::read_dir(inbox)
+    .map_err(|err| err.to_string())?
+    .flatten()
+  {
+    let path = entry.path();
+    if !is_inbox_candidate(&path) {
+      continue;
+    }
+    if !selected.contains(&file_name_id(&entry.file_name())) {
+      continue;
+    }
+    if let Err(err) = fs::rename(&path, staging.join(entry.file_name())) {
+      let _ = restore_staged_files(&staging);
+      return Err(format!(
+        "Failed to stage {}: {}",
+        entry.file_name().to_string_lossy(),
+        err
+      ));
+    }
+  }
+  Ok(staging)
+}
+
+pub(crate) fn reserve_job(
+  app: &tauri::AppHandle,
+  name: &str,
+  stages: Option<Arc<Mutex<StageTracker>>>,
+) -> Result<JobGuard, AppError> {
+  let registry = app.state::<ChildRegistry>();
+  let mut jobs = registry.0.lock().map_err(|err| err.to_string())?;
+  if jobs.contains_key(name) {
+    return Err(AppError::AlreadyRunning(name.to_string()));
+  }
+  let cancelled = Arc::new(AtomicBool::new(false));
+  jobs.insert(
+    name.to_string(),
+    RunningJob {
+      pid: None,
+      stages,
+      cancelled: cancelled.clone(),
+    },
+  );
+  Ok(JobGuard {
+    app: app.clone(),
+    name: name.to_string(),
+    cancelled,
+  })
+}
+
+impl JobGuard {
+  pub(crate) fn set_pid(&self, pid: u32) {
+    if let Ok(mut jobs) = self.app.state::<ChildRegistry>().0.lock() {
+      if let Some(job) = jobs.get_mut(&self.name) {
+        if Arc::ptr_eq(&job.cancelled, &self.cancelled) {
+          job.pid = Some(pid);
+        }
+      }
+    }
+  }
+
+  pub(crate) fn was_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+}
+
+impl Drop for JobGuard {
+  fn drop(&mut self) {
+    if let Ok(mut jobs) = self.app.state::<ChildRegistry>().0.lock() {
+      let ours = jobs
+        .get(&self.name)
+        .map(|job| Arc::ptr_eq(&job.cancelled, &self.cancelled))
+        .unwrap_or(false);
+      if ours {
+        jobs.remove(&self.name);
+      }
+    }
+  }
+}
+
+pub(crate) fn kill_process_group(pid: u32) -> Result<(), String> {
+  #[cfg(unix)]
+  let status = Command::new("kill")
+    .args(["-TERM", "--", &format!("-{}", pid)])
+    .status();
+  #[cfg(not(unix))]
+  let status = Command::new("taskkill")
+    .args(["/PID", &pid.to_string(), "/T", "/F"])
+    .status();
+  match status {
+    Ok(status) if status.success() => Ok(()),
+    Ok(status) => Err(format!("kill exited with status {}", status)),
+    Err(err) => Err(err.to_string()),
+  }
+}
+
+// Stage marker lines feed the tracker (emitting its event) and are kept out of
+// the log and the captured output.
+pub(crate) fn observe_stage_line(
+  sink: &EventSink,
+  stages: &Option<Arc<Mutex<StageTracker>>>,
+  line: &str,
+) -> bool {
+  let tracker = match stages {
+    Some(tracker) => tracker,
+    None => return false,
+  };
+  if !line.trim_start().starts_with(STAGE_MARKER) {
+    return false;
+  }
+  if let Ok(mut tracker) = tracker.lock() {
+    if let Some(progress) = tracker.observe(line) {
+      sink.emit(tracker.event, progress);
+    }
+  }
+  true
+}
+
+// Output lines decoded lossily (a trailing `\r` dropped), so a line that isn't
+// UTF-8 is shown with `�` instead of being skipped. Stops at EOF or a read error.
+pub(crate) fn lossy_lines(mut reader: impl BufRead) -> impl Iterator<Item = String> {
+  let mut buffer = Vec::new();
+  std::iter::from_fn(move || {
+    buffer.clear();
+    match reader.read_until(b'\n', &mut buffer) {
+      Ok(0) | Err(_) => None,
+      Ok(_) => {
+        if buffer.ends_with(b"\n") {
+          buffer.pop();
+        }
+        if buffer.ends_with(b"\r") {
+          buffer.pop();
+        }
+        Some(String::from_utf8_lossy(&buffer).into_owned())
+      }
+    }
+  })
+}
+
+pub(crate) fn run_command_stream_with_stages(
+  sink: &EventSink,
+  mut command: Command,
+  stores: Vec<String>,
+  stderr_as_stdout: bool,
+  stages: Option<Arc<Mutex<StageTracker>>>,
+  job: Option<&JobGuard>,
+) -> Result<RunWorkerResult, String> {
+  let _running = sink.app().map(|app| CommandGuard::new(app.clone()));
+  let _span =
+    tracing::info_span!("command", program = %command.get_program().to_string_lossy()).entered();
+  let mut child = command
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|err| {
+      error!("spawn failed: {}", err);
+      err.to_string()
+    })?;
+  info!("started pid {}", child.id());
+  if let Some(job) = job {
+    job.set_pid(child.id());
+  }
+
+  let stdout = child.stdout.take().ok_or("Missing stdout")?;
+  let stderr = child.stderr.take().ok_or("Missing stderr")?;
+
+  let stdout_buffer = Arc::new(Mutex::new(String::new()));
+  let stderr_buffer = Arc::new(Mutex::new(String::new()));
+
+  let stdout_clone = stdout_buffer.clone();
+  let stderr_clone = stderr_buffer.clone();
+  let stores_clone = stores.clone();
+  let sink_stdout = sink.clone();
+  let sink_stderr = sink.clone();
+  let stderr_to_stdout = stderr_as_stdout;
+  let stages_stdout = stages.clone();
+  let stages_stderr = stages.clone();
+  if let Some(tracker) = &stages {
+    if let Ok(tracker) = tracker.lock() {
+      sink.emit(tracker.event, tracker.progress(None));
+    }
+  }
+
+  let stdout_handle = thread::spawn(move || {
+    for line in lossy_lines(BufReader::new(stdout)) {
+      if observe_stage_line(&sink_stdout, &stages_stdout, &line) {
+        continue;
+      }
+      sink_stdout.log_line("stdout", line.clone(), &stores_clone);
+      if let Ok(mut buf) = stdout_clone.lock() {
+        buf.push_str(&line);
+        buf.push('\n');
+      }
+    }
+  });
+
+  let stderr_handle = thread::spawn(move || {
+    for line in lossy_lines(BufReader::new(stderr)) {
+      if observe_stage_line(&sink_stderr, &stages_stderr, &line) {
+        continue;
+      }
+      let stream_label = if stderr_to_stdout { "stdout" } else { "stderr" };
+      sink_stderr.log_line(stream_label, line.clone(), &stores);
+      if let Ok(mut buf) = stderr_clone.lock() {
+        buf.push_str(&line);
+        buf.push('\n');
+      }
+    }
+  });
+
+  let status = child.wait().map_err(|err| err.to_string())?;
+  // A reader that panicked lost output (and the panic hook wrote a crash
+  // report); the run is reported as `panic` rather than by its exit status.
+  let stdout_panicked = stdout_handle.join().is_err();
+  let stderr_panicked = stderr_handle.join().is_err();
+
+  let stdout_text = stdout_buffer
+    .lock()
+    .map(|buf| buf.clone())
+    .unwrap_or_default();
+  let stderr_text = stderr_buffer
+    .lock()
+    .map(|buf| buf.clone())
+    .unwrap_or_default();
+
+  if let Some(app) = sink.app() {
+    app
+      .state::<CommandMetrics>()
+      .record_worker_output(stdout_text.len() + stderr_text.len());
+  }
+  let outcome = if stdout_panicked || stderr_panicked {
+    "panic"
+  } else if status.success() {
+    "ok"
+  } else {
+    "fail"
+  };
+  if outcome == "panic" {
+    error!("finished: {}; an output reader thread panicked", status);
+  } else if status.success() {
+    info!("finished: {}", status);
+  } else {
+    warn!(
+      "finished: {}; last stderr line: {}",
+      status,
+      stderr_text.lines().last().unwrap_or("")
+    );
+  }
+  let stage = stages.as_ref().and_then(|tracker| {
+    let tracker = tracker.lock().ok()?;
+    let progress = tracker.progress(Some(outcome));
+    sink.emit(tracker.event, progress.clone());
+    Some(progress)
+  });
+
+  Ok(RunWorkerResult {
+    status: outcome.to_string(),
+    exit_code: status.code(),
+    stdout: stdout_text,
+    stderr: stderr_text,
+    stage,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stage_tracker_follows_markers() {
+    let mut tracker = StageTracker::new("update-progress", &UPDATE_STAGES);
+    assert_eq!(tracker.progress(None).stage, "running");
+    assert_eq!((tracker.index, tracker.total), (1, 1));
+    assert!(tracker.observe("Building Receipts Operator...").is_none());
+
+    let build = tracker.observe("::stage::build").expect("build stage");
+    assert_eq!(
+      (build.stage.as_str(), build.index, build.total),
+      ("build", 3, 5)
+    );
+    let extra = tracker
+      .observe("  ::stage::notarize")
+      .expect("unknown stage");
+    assert_eq!((extra.index, extra.total), (4, 5));
+    let last = tracker
+      .observe("::stage::relaunch")
+      .expect("relaunch stage");
+    assert_eq!((last.index, last.total), (5, 5));
+    assert_eq!(tracker.progress(Some("ok")).status.as_deref(), Some("ok"));
+  }
+
+  #[test]
+  fn staging_moves_only_the_selected_files_and_restores_leftovers() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let inbox = dir.path();
+    for name in ["a.jpg", "b.jpg", "c.png"] {
+      fs::write(inbox.join(name), name).expect("write");
+    }
+    let selected = vec!["a.jpg".to_string(), "c.png".to_string()];
+
+    let staging = stage_selected_files(inbox, &selected, "run-1").expect("stage");
+    assert!(is_staging_dir(&staging));
+    assert!(staging.join("a.jpg").exists() && staging.join("c.png").exists());
+    assert!(!staging.join("b.jpg").exists());
+    assert!(inbox.join("b.jpg").exists() && !inbox.join("a.jpg").exists());
+    assert!(stage_selected_files(inbox, &selected, "run-1").is_err());
+
+    // The worker processed c.png; a new a.jpg arrived meanwhile.
+    fs::remove_file(staging.join("c.png")).expect("remove");
+    fs::write(inbox.join("a.jpg"), "new").expect("write");
+    assert_eq!(restore_staged_files(&staging), Ok(1));
+    assert!(!staging.exists());
+    assert_eq!(
+      fs::read_to_string(inbox.join("a.jpg")).expect("read"),
+      "new"
+    );
+    assert_eq!(fs::read_dir(inbox).expect("read_dir").count(), 3);
+  }
+
+  #[test]
+  fn staging_ids_differ_within_the_same_second() {
+    let first = new_staging_id();
+    let second = new_staging_id();
+    assert_ne!(first, second);
+  }
+
+  #[test]
+  fn cancelled_jobs_keep_their_slot_until_released() {
+    let registry = ChildRegistry::default();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    registry.0.lock().unwrap().insert(
+      "worker".to_string(),
+      RunningJob {
+        pid: None,
+        stages: None,
+        cancelled: cancelled.clone(),
+      },
+    );
+    let early = cancel_worker_job(&registry).expect("cancel");
+    assert!(!early.cancelled);
+    assert!(!cancelled.load(Ordering::SeqCst));
+
+    #[cfg(unix)]
+    {
+      use std::os::unix::process::CommandExt;
+      let mut child = Command::new("sleep")
+        .arg("30")
+        .process_group(0)
+        .spawn()
+        .expect("spawn sleep");
+      registry.0.lock().unwrap().get_mut("worker").unwrap().pid = Some(child.id());
+      assert!(cancel_worker_job(&registry).expect("cancel").cancelled);
+      assert!(!child.wait().expect("wait").success());
+      assert!(cancelled.load(Ordering::SeqCst));
+      assert!(registry.0.lock().unwrap().contains_key("worker"));
+      let again = cancel_worker_job(&registry).expect("cancel again");
+      assert!(!again.cancelled);
+    }
+
+    registry.0.lock().unwrap().remove("worker");
+    let idle = cancel_worker_job(&registry).expect("no run");
+    assert_eq!(idle.reason.as_deref(), Some("No run is in progress"));
+  }
+
+  #[test]
+  fn output_lines_survive_invalid_utf8() {
+    let raw: &[u8] = b"ok\r\nbad \xff byte\nlast";
+    let lines: Vec<String> = lossy_lines(raw).collect();
+    assert_eq!(lines, vec!["ok", "bad \u{fffd} byte", "last"]);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn restoring_a_non_utf8_file_over_a_taken_name_keeps_its_bytes() {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    let dir = tempfile::tempdir().expect("tempdir");
+    let inbox = dir.path();
+    let name = OsStr::from_bytes(b"bon-\xe4.jpg");
+    fs::write(inbox.join(name), "old").expect("write");
+    let staging = stage_selected_files(inbox, &[file_name_id(name)], "raw").expect("stage");
+    fs::write(inbox.join(name), "new").expect("write");
+
+    assert_eq!(restore_staged_files(&staging), Ok(1));
+    let mut names: Vec<Vec<u8>> = fs::read_dir(inbox)
+      .expect("read_dir")
+      .flatten()
+      .map(|entry| entry.file_name().into_vec())
+      .collect();
+    names.sort();
+    assert_eq!(
+      names,
+      vec![b"bon-\xe4-1.jpg".to_vec(), b"bon-\xe4.jpg".to_vec()]
+    );
+  }
+}
@@ -0,0 +1,345 @@
+use crate::*;
+use chrono::Datelike;
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, Worksheet, XlsxError};
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct XlsxSheet {
+  pub(crate) name: String,
+  // Data rows, not counting the header.
+  pub(crate) rows: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct XlsxExport {
+  pub(crate) path: String,
+  pub(crate) year: i32,
+  // The summary first, then one sheet per month that has receipts.
+  pub(crate) sheets: Vec<XlsxSheet>,
+  // Receipts without a date, which can't be placed in a month.
+  pub(crate) undated: usize,
+}
+
+// One receipt row of a month sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct XlsxReceipt {
+  pub(crate) date: chrono::NaiveDate,
+  pub(crate) store_id: String,
+  pub(crate) merchant: String,
+  pub(crate) total: Option<f64>,
+  pub(crate) currency: String,
+  pub(crate) file_name: String,
+}
+
+pub(crate) fn xlsx_error(err: XlsxError) -> AppError {
+  AppError::Other(format!("Excel export failed: {}", err))
+}
+
+// Excel's rules: at most 31 characters, none of `[]:*?/\`, no apostrophe at
+// either end, not `History` (reserved), and unique ignoring case. Clashes get
+// ` (2)`, ` (3)`, ... within the length limit.
+pub(crate) fn xlsx_sheet_name(raw: &str, taken: &[String]) -> String {
+  let cleaned = raw
+    .chars()
+    .map(|c| match c {
+      '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+      c if c.is_control() => '_',
+      c => c,
+    })
+    .collect::<String>();
+  let mut base = cleaned.trim().trim_matches('\'').to_string();
+  if base.is_empty() || base.eq_ignore_ascii_case("history") {
+    base = format!("Sheet {}", taken.len() + 1);
+  }
+  let is_taken = |name: &str| {
+    taken
+      .iter()
+      .any(|used| used.to_lowercase() == name.to_lowercase())
+  };
+  let mut suffix = 1;
+  loop {
+    let tail = if suffix == 1 {
+      String::new()
+    } else {
+      format!(" ({})", suffix)
+    };
+    let keep = XLSX_SHEET_NAME_MAX - tail.chars().count();
+    let head = base.chars().take(keep).collect::<String>();
+    let name = format!("{}{}", head.trim_end().trim_end_matches('\''), tail);
+    if !is_taken(&name) {
+      return name;
+    }
+    suffix += 1;
+  }
+}
+
+pub(crate) fn excel_date(date: chrono::NaiveDate) -> Result<ExcelDateTime, XlsxError> {
+  ExcelDateTime::from_ymd(date.year() as u16, date.month() as u8, date.day() as u8)
+}
+
+pub(crate) fn write_xlsx_header(
+  sheet: &mut Worksheet,
+  columns: &[String],
+  bold: &Format,
+) -> Result<(), XlsxError> {
+  for (col, column) in columns.iter().enumerate() {
+    sheet.write_string_with_format(0, col as u16, column, bold)?;
+    sheet.set_column_width(col as u16, (column.chars().count() + 4).max(12) as f64)?;
+  }
+  sheet.set_freeze_panes(1, 0)?;
+  Ok(())
+}
+
+// Rows are months, columns `<store> <currency>` then `Total <currency>`, so a
+// chart can use the block as is. Months without receipts stay in with zeros.
+pub(crate) fn write_xlsx_summary(
+  sheet: &mut Worksheet,
+  year: i32,
+  store_ids: &[String],
+  months: &[Vec<XlsxReceipt>],
+  formats: (&Format, &Format),
+) -> Result<usize, XlsxError> {
+  let (bold, money) = formats;
+  let currencies = months
+    .iter()
+    .flatten()
+    .filter(|receipt| receipt.total.is_some())
+    .map(|receipt| receipt.currency.clone())
+    .collect::<std::collections::BTreeSet<_>>();
+  let mut columns = vec!["Month".to_string(), "Receipts".to_string()];
+  for currency in &currencies {
+    for store_id in store_ids {
+      columns.push(format!("{} {}", store_id, currency));
+    }
+  }
+  for currency in &currencies {
+    columns.push(format!("Total {}", currency));
+  }
+  write_xlsx_header(sheet, &columns, bold)?;
+  for (index, receipts) in months.iter().enumerate() {
+    let row = index as u32 + 1;
+    sheet.write_string(row, 0, format!("{}-{:02}", year, index + 1))?;
+    sheet.write_number(row, 1, receipts.len() as f64)?;
+    let mut col = 2;
+    for currency in &currencies {
+      for store_id in store_ids {
+        let total = receipts
+          .iter()
+          .filter(|receipt| receipt.store_id == *store_id && receipt.currency == *currency)
+          .filter_map(|receipt| receipt.total)
+          .sum::<f64>();
+        sheet.write_number_with_format(row, col, round_cents(total), money)?;
+        col += 1;
+      }
+    }
+    for currency in &currencies {
+      let total = receipts
+        .iter()
+        .filter(|receipt| receipt.currency == *currency)
+        .filter_map(|receipt| receipt.total)
+        .sum::<f64>();
+      sheet.write_number_with_format(row, col, round_cents(total), money)?;
+      col += 1;
+    }
+  }
+  Ok(months.len())
+}
+
+pub(crate) fn write_xlsx_month(
+  sheet: &mut Worksheet,
+  receipts: &[XlsxReceipt],
+  formats: (&Format, &Format, &Format),
+) -> Result<usize, XlsxError> {
+  let (bold, money, date) = formats;
+  let columns = XLSX_RECEIPT_COLUMNS.map(str::to_string);
+  write_xlsx_header(sheet, &columns, bold)?;
+  for (index, receipt) in receipts.iter().enumerate() {
+    let row = index as u32 + 1;
+    sheet.write_datetime_with_format(row, 0, &excel_date(receipt.date)?, date)?;
+    sheet.write_string(row, 1, &receipt.store_id)?;
+    sheet.write_string(row, 2, &receipt.merchant)?;
+    // Left empty when the receipt has no parseable total.
+    if let Some(total) = receipt.total {
+      sheet.write_number_with_format(row, 3, total, money)?;
+    }
+    sheet.write_string(row, 4, &receipt.currency)?;
+    sheet.write_string(row, 5, &receipt.file_name)?;
+  }
+  Ok(receipts.len())
+}
+
+// Reads processed/ and only ever writes `path`, through a temp file next to it.
+pub(crate) fn export_receipts_xlsx_to(
+  receipts_root: &str,
+  store_ids: &[String],
+  year: i32,
+  path: &Path,
+  overwrite: bool,
+) -> Result<XlsxExport, AppError> {
+  year_start(year)?;
+  if !path.is_absolute() {
+    return Err(AppError::InvalidInput(format!(
+      "Export path must be absolute: {}",
+      path.to_string_lossy()
+    )));
+  }
+  if !overwrite && path.exists() {
+    return Err(AppError::FileExists(path.to_string_lossy().to_string()));
+  }
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(|err| AppError::io(parent, err))?;
+  }
+
+  let mut months = vec![Vec::<XlsxReceipt>::new(); 12];
+  let mut undated = 0;
+  for store_id in store_ids {
+    let dir = processed_dir(receipts_root, store_id);
+    for name in receipt_output_names(&dir) {
+      let file = join_file_id(&dir, &name);
+      let value = match fs::read_to_string(&file).map(|raw| serde_json::from_str::<Value>(&raw)) {
+        Ok(Ok(value)) if value.is_object() => value,
+        _ => continue,
+      };
+      let receipt = match receipt_total_from(store_id, &file, &value) {
+        Some(receipt) => receipt,
+        None => continue,
+      };
+      let date = match receipt.date {
+        Some(date) if date.year() == year => date,
+        Some(_) => continue,
+        None => {
+          undated += 1;
+          continue;
+        }
+      };
+      months[date.month0() as usize].push(XlsxReceipt {
+        date,
+        store_id: store_id.clone(),
+        merchant: csv_text(
+          value
+            .get("merchant")
+            .and_then(|merchant| merchant.get("name")),
+        ),
+        total: receipt.total.map(round_cents),
+        currency: receipt_currency(&receipt),
+        file_name: name,
+      });
+    }
+  }
+  for receipts in &mut months {
+    receipts.sort_by(|a, b| {
+      a.date
+        .cmp(&b.date)
+        .then_with(|| a.store_id.cmp(&b.store_id))
+        .then_with(|| a.file_name.cmp(&b.file_name))
+    });
+  }
+
+  let bold = Format::new().set_bold();
+  let money = Format::new().set_num_format(XLSX_AMOUNT_FORMAT);
+  let date = Format::new().set_num_format(XLSX_DATE_FORMAT);
+  let mut workbook = Workbook::new();
+  let mut sheets = Vec::new();
+  let mut taken = Vec::new();
+  let summary = workbook.add_worksheet();
+  let name = xlsx_sheet_name(&format!("Summary {}", year), &taken);
+  summary.set_name(&name).map_err(xlsx_error)?;
+  let rows =
+    write_xlsx_summary(summary, year, store_ids, &months, (&bold, &money)).map_err(xlsx_error)?;
+  taken.push(name.clone());
+  sheets.push(XlsxSheet { name, rows });
+  for (index, receipts) in months.iter().enumerate() {
+    if receipts.is_empty() {
+      continue;
+    }
+    let sheet = workbook.add_worksheet();
+    let name = xlsx_sheet_name(&format!("{}-{:02}", year, index + 1), &taken);
+    sheet.set_name(&name).map_err(xlsx_error)?;
+    let rows = write_xlsx_month(sheet, receipts, (&bold, &money, &date)).map_err(xlsx_error)?;
+    taken.push(name.clone());
+    sheets.push(XlsxSheet { name, rows });
+  }
+
+  let temp = path.with_extension("xlsx.tmp");
+  let saved = workbook
+    .save(&temp)
+    .map_err(xlsx_error)
+    .and_then(|_| fs::rename(&temp, path).map_err(|err| AppError::io(path, err)));
+  if let Err(err) = saved {
+    let _ = fs::remove_file(&temp);
+    return Err(err);
+  }
+  Ok(XlsxExport {
+    path: path.to_string_lossy().to_string(),
+    year,
+    sheets,
+    undated,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn xlsx_export_writes_a_summary_and_the_months_with_receipts() {
+    let taken = vec!["Summary 2024".to_string(), "2024-01".to_string()];
+    assert_eq!(xlsx_sheet_name("summary 2024", &taken), "summary 2024 (2)");
+    assert_eq!(
+      xlsx_sheet_name("Lidl: [Cluj]/Zorilor?", &[]),
+      "Lidl_ _Cluj__Zorilor_"
+    );
+    assert_eq!(xlsx_sheet_name("'History'", &[]), "Sheet 1");
+    let long = "x".repeat(40);
+    assert_eq!(xlsx_sheet_name(&long, &[]).chars().count(), 31);
+    let clash = xlsx_sheet_name(&long, &[xlsx_sheet_name(&long, &[])]);
+    assert!(clash.ends_with(" (2)") && clash.chars().count() == 31);
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let processed = dir.path().join("processed").join("lidl");
+    fs::create_dir_all(&processed).expect("processed");
+    for (name, raw) in [
+      (
+        "a.jpg.json",
+        r#"{"timestamp": "2024-01-05T10:00:00", "total": "45,90", "currency": "RON"}"#,
+      ),
+      (
+        "b.jpg.json",
+        r#"{"timestamp": "2024-03-06T10:00:00", "total": null}"#,
+      ),
+      (
+        "c.jpg.json",
+        r#"{"timestamp": "2023-12-30T10:00:00", "total": 10}"#,
+      ),
+      ("d.jpg.json", "garbage"),
+    ] {
+      fs::write(processed.join(name), raw).expect("output");
+    }
+    let root = dir.path().to_string_lossy().to_string();
+    let stores = vec!["lidl".to_string()];
+    let out = dir.path().join("exports").join("receipts-2024.xlsx");
+
+    let export = export_receipts_xlsx_to(&root, &stores, 2024, &out, false).expect("export");
+    let sheets = export
+      .sheets
+      .iter()
+      .map(|sheet| (sheet.name.as_str(), sheet.rows))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      sheets,
+      vec![("Summary 2024", 12), ("2024-01", 1), ("2024-03", 1)]
+    );
+    assert!(fs::read(&out).expect("xlsx").starts_with(b"PK"));
+    assert!(!out.with_extension("xlsx.tmp").exists());
+
+    let err = export_receipts_xlsx_to(&root, &stores, 2024, &out, false).unwrap_err();
+    assert_eq!(err.code(), "file_exists");
+    let empty = export_receipts_xlsx_to(&root, &stores, 2022, &out, true).expect("overwrite");
+    assert_eq!(empty.sheets.len(), 1);
+    assert_eq!(
+      export_receipts_xlsx_to(&root, &stores, 2024, Path::new("out.xlsx"), true)
+        .unwrap_err()
+        .code(),
+      "invalid_input"
+    );
+  }
+}
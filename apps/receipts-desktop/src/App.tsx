@@ -14,6 +14,7 @@ type AppConfig = {
   worker_run_cmd: string | null;
   stores: StoreConfig[];
   config_ready: boolean;
+  home_available: boolean;
 };
 
 type InboxCount = {
@@ -49,11 +50,28 @@ type UnreadBadge = {
   last_failure_run_id?: string | null;
 };
 
+type DashboardSnapshot = {
+  inbox_counts: InboxCount[];
+  badges: UnreadBadge[];
+  last_runs: RunSummary[];
+  generated_at: string;
+};
+
+type StageProgress = {
+  stage: string;
+  index: number;
+  total: number;
+  elapsed_ms: number;
+  stage_elapsed_ms: number;
+  status?: string | null;
+};
+
 type RunWorkerResult = {
   status: string;
   exit_code: number | null;
   stdout: string;
   stderr: string;
+  stage?: StageProgress | null;
 };
 
 type WorkerLogEvent = {
@@ -62,12 +80,68 @@ type WorkerLogEvent = {
   stores: string[];
 };
 
+type AppLogs = {
+  path: string;
+  lines: string[];
+};
+
+type CrashReport = {
+  name: string;
+  message: string;
+  contents: string;
+};
+
+type ReceiptsLocation = {
+  path: string;
+  account: string | null;
+  exists: boolean;
+  has_store_folders: boolean;
+};
+
+type InitializedRoot = {
+  receipts_root: string;
+  dirs: { created: string[]; existing: string[] };
+  env_override: boolean;
+};
+
+type Destination = { kind: 'store'; store_id: string } | { kind: 'run'; run_id: string };
+
+type DeepLinkWarning = {
+  url: string;
+  code: string;
+  message: string;
+};
+
+type DeepLinkOutcome =
+  | { type: 'navigate'; destination: Destination }
+  | ({ type: 'warning' } & DeepLinkWarning);
+
 type UpdateStatus = {
-  status: 'up_to_date' | 'update_available' | 'source_missing' | 'error';
+  status:
+    | 'up_to_date'
+    | 'update_available'
+    | 'ahead_of_source'
+    | 'source_missing'
+    | 'restart_pending'
+    | 'update_ineffective'
+    | 'error';
   installed_version: string;
   source_version?: string | null;
   source_path?: string | null;
   message?: string | null;
+  git?: {
+    commits_behind: number;
+    commits_ahead: number;
+    touches_app: boolean;
+    log: string[];
+    stale: boolean;
+    branch?: string | null;
+    dirty: boolean;
+    diverged: boolean;
+    error?: string | null;
+  } | null;
+  git_checkout?: boolean;
+  checked_at?: string;
 };
 
 const AUTO_INTERVAL_MS = 3 * 60 * 1000;
@@ -78,6 +152,20 @@ const DEFAULT_WORKER_DIR =
 const DEFAULT_WORKER_CMD =
   '/Users/xan/Documents/Github repos/life-dashboard/apps/receipts-worker/run.sh';
 
+// Commands reject with a `{ code, message, details }` AppError.
+function errorText(err: unknown, fallback?: string) {
+  if (err instanceof Error) {
+    return err.message;
+  }
+  if (err && typeof err === 'object' && 'message' in err) {
+    return String((err as { message: unknown }).message);
+  }
+  if (typeof err === 'string' && err) {
+    return err;
+  }
+  return fallback ?? String(err);
+}
+
 function fmtDate(value?: string | null) {
   if (!value) return '—';
   const date = new Date(value);
@@ -135,6 +223,14 @@ export default function App() {
   const [notice, setNotice] = useState<string | null>(null);
   const [updateStatus, setUpdateStatus] = useState<UpdateStatus | null>(null);
   const [updateBusy, setUpdateBusy] = useState(false);
+  const [updateStage, setUpdateStage] = useState<StageProgress | null>(null);
+  const [missingLogs, setMissingLogs] = useState<Record<string, boolean>>({});
+  const [appLogs, setAppLogs] = useState<AppLogs | null>(null);
+  const [crashReports, setCrashReports] = useState<CrashReport[]>([]);
+  // Set until the first-run wizard has been completed.
+  const [receiptsLocations, setReceiptsLocations] = useState<ReceiptsLocation[] | null>(null);
+  // Element id of the store or run card a receipts:// link pointed at.
+  const [linkTarget, setLinkTarget] = useState<string | null>(null);
   const [settingsOpen, setSettingsOpen] = useState(false);
   const [receiptsRootInput, setReceiptsRootInput] = useState('');
   const [workerDirInput, setWorkerDirInput] = useState('');
@@ -146,20 +242,21 @@ export default function App() {
 
   const storeRunMap = useMemo(() => buildStoreRunMap(runs), [runs]);
 
-  const refreshUpdateStatus = useCallback(async () => {
+  const refreshUpdateStatus = useCallback(async (force = false) => {
     try {
-      const update = await invoke<UpdateStatus>('get_update_status');
+      const update = await invoke<UpdateStatus>('get_update_status', { force });
       setUpdateStatus(update);
     } catch (err) {
       setUpdateStatus({
         status: 'error',
         installed_version: 'unknown',
-        message: err instanceof Error ? err.message : 'Failed to read update status.'
+        message: errorText(err, 'Failed to read update status.')
       });
     }
   }, []);
 
-  const refreshAll = useCallback(async () => {
+  // maxAge 0 makes the backend rescan instead of answering from its last snapshot.
+  const refreshAll = useCallback(async (maxAge?: number) => {
     setError(null);
     setNotice(null);
     await refreshUpdateStatus();
@@ -167,21 +264,21 @@ export default function App() {
     const errors: string[] = [];
 
     try {
-      const counts = await invoke<InboxCount[]>('get_inbox_counts');
+      const counts = await invoke<InboxCount[]>('get_inbox_counts', { maxAge });
       const countsMap: Record<string, number> = {};
       counts.forEach((entry) => {
         countsMap[entry.store_id] = entry.count;
       });
       setInboxCounts(countsMap);
     } catch (err) {
-      errors.push(err instanceof Error ? err.message : 'Failed to read inbox counts.');
+      errors.push(errorText(err, 'Failed to read inbox counts.'));
     }
 
     try {
       const summaries = await invoke<RunSummary[]>('get_last_runs', { limit: MAX_RUNS });
       setRuns(summaries);
     } catch (err) {
-      errors.push(err instanceof Error ? err.message : 'Failed to read run summaries.');
+      errors.push(errorText(err, 'Failed to read run summaries.'));
     }
 
     try {
@@ -192,7 +289,7 @@ export default function App() {
       });
       setBadges(badgeMap);
     } catch (err) {
-      errors.push(err instanceof Error ? err.message : 'Failed to read badges.');
+      errors.push(errorText(err, 'Failed to read badges.'));
     }
 
     if (errors.length) {
@@ -217,7 +314,7 @@ export default function App() {
           setSettingsOpen(true);
         }
       } catch (err) {
-        setError(err instanceof Error ? err.message : 'Failed to load config.');
+        setError(errorText(err, 'Failed to load config.'));
       }
     })();
   }, []);
@@ -286,6 +383,112 @@ export default function App() {
     };
   }, []);
 
+  useEffect(() => {
+    let active = true;
+    let unlisten: (() => void) | null = null;
+
+    listen<AppConfig>('config-changed', (event) => {
+      setConfig(event.payload);
+    }).then((stop) => {
+      if (!active) {
+        stop();
+        return;
+      }
+      unlisten = stop;
+    });
+
+    return () => {
+      active = false;
+      if (unlisten) unlisten();
+    };
+  }, []);
+
+  useEffect(() => {
+    let active = true;
+    let unlisten: (() => void) | null = null;
+
+    listen<UnreadBadge[]>('badges-updated', (event) => {
+      const badgeMap: Record<string, UnreadBadge> = {};
+      event.payload.forEach((entry) => {
+        badgeMap[entry.store_id] = entry;
+      });
+      setBadges(badgeMap);
+    }).then((stop) => {
+      if (!active) {
+        stop();
+        return;
+      }
+      unlisten = stop;
+    });
+
+    return () => {
+      active = false;
+      if (unlisten) unlisten();
+    };
+  }, []);
+
+  useEffect(() => {
+    let active = true;
+    let unlisten: (() => void) | null = null;
+
+    listen<DashboardSnapshot>('snapshot-updated', (event) => {
+      const countsMap: Record<string, number> = {};
+      event.payload.inbox_counts.forEach((entry) => {
+        countsMap[entry.store_id] = entry.count;
+      });
+      setInboxCounts(countsMap);
+      setRuns(event.payload.last_runs.slice(0, MAX_RUNS));
+      const badgeMap: Record<string, UnreadBadge> = {};
+      event.payload.badges.forEach((entry) => {
+        badgeMap[entry.store_id] = entry;
+      });
+      setBadges(badgeMap);
+    }).then((stop) => {
+      if (!active) {
+        stop();
+        return;
+      }
+      unlisten = stop;
+    });
+
+    return () => {
+      active = false;
+      if (unlisten) unlisten();
+    };
+  }, []);
+
+  useEffect(() => {
+    let active = true;
+    let unlisten: (() => void) | null = null;
+
+    listen<UpdateStatus>('update-available', (event) => {
+      setUpdateStatus(event.payload);
+    }).then((stop) => {
+      if (!active) {
+        stop();
+        return;
+      }
+      unlisten = stop;
+    });
+
+    let unlistenProgress: (() => void) | null = null;
+    listen<StageProgress>('update-progress', (event) => {
+      setUpdateStage(event.payload);
+    }).then((stop) => {
+      if (!active) {
+        stop();
+        return;
+      }
+      unlistenProgress = stop;
+    });
+
+    return () => {
+      active = false;
+      if (unlisten) unlisten();
+      if (unlistenProgress) unlistenProgress();
+    };
+  }, []);
+
   async function runWorker(stores: string[], mode: 'once' | 'auto') {
     setError(null);
     setNotice(null);
@@ -306,23 +509,176 @@ export default function App() {
       const result = await invoke<RunWorkerResult>('run_worker', { stores, mode });
       setLastRunOutput(result);
     } catch (err) {
-      setError(err instanceof Error ? err.message : 'Failed to run worker.');
+      setError(errorText(err, 'Failed to run worker.'));
     } finally {
       const cleared = { ...working };
       stores.forEach((store) => {
         cleared[store] = false;
       });
       setBusyStores(cleared);
-      refreshAll();
+      refreshAll(0);
     }
   }
 
   async function openPath(pathType: string, storeId?: string, filePath?: string) {
+    setError(null);
+    const createIfMissing = ['inbox', 'processed', 'failed'].includes(pathType);
+    try {
+      const opened = await invoke<{ path: string; created: boolean }>('open_path', {
+        pathType,
+        storeId,
+        filePath,
+        createIfMissing,
+      });
+      if (opened.created) {
+        setNotice(`Created ${opened.path} (it was empty so far)`);
+      }
+    } catch (err) {
+      setError(errorText(err));
+    }
+  }
+
+  async function openRunArtifact(runId: string, kind: 'summary' | 'log') {
+    setError(null);
+    try {
+      await invoke('open_run_artifact', { runId, kind });
+    } catch (err) {
+      const code = err && typeof err === 'object' && 'code' in err ? (err as { code: string }).code : null;
+      if (kind === 'log' && code === 'log_not_found') {
+        setMissingLogs((prev) => ({ ...prev, [runId]: true }));
+      }
+      setError(errorText(err));
+    }
+  }
+
+  async function copyPath(pathType: string, storeId?: string, filePath?: string) {
     setError(null);
     try {
-      await invoke('open_path', { pathType, storeId, filePath });
+      const copied = await invoke<{ path: string }>('copy_path_to_clipboard', { pathType, storeId, filePath });
+      setNotice(`Copied ${copied.path}`);
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorText(err));
+    }
+  }
+
+  useEffect(() => {
+    let active = true;
+    let unlisteners: (() => void)[] = [];
+
+    const navigate = (destination: Destination) => {
+      setLinkTarget(
+        destination.kind === 'store' ? `store-${destination.store_id}` : `run-${destination.run_id}`
+      );
+    };
+    const warn = (warning: DeepLinkWarning) => {
+      setError(`Could not open ${warning.url}: ${warning.message}`);
+    };
+
+    // Links that arrived before these listeners were up are queued in the backend.
+    Promise.all([
+      listen<Destination>('navigate', (event) => navigate(event.payload)),
+      listen<DeepLinkWarning>('deep-link-warning', (event) => warn(event.payload))
+    ])
+      .then((stops) => {
+        if (!active) {
+          stops.forEach((stop) => stop());
+          return [];
+        }
+        unlisteners = stops;
+        return invoke<DeepLinkOutcome[]>('take_pending_deep_links');
+      })
+      .then((pending) => {
+        pending.forEach((outcome) => {
+          if (outcome.type === 'navigate') {
+            navigate(outcome.destination);
+          } else {
+            warn(outcome);
+          }
+        });
+      })
+      .catch(() => {});
+
+    return () => {
+      active = false;
+      unlisteners.forEach((stop) => stop());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (!linkTarget) return;
+    document.getElementById(linkTarget)?.scrollIntoView({ behavior: 'smooth', block: 'center' });
+  }, [linkTarget, runs, config]);
+
+  useEffect(() => {
+    invoke<CrashReport[]>('get_crash_reports')
+      .then(setCrashReports)
+      .catch(() => setCrashReports([]));
+  }, []);
+
+  useEffect(() => {
+    invoke<boolean | null>('get_pref', { key: 'system.first_run_completed' })
+      .then((completed) => (completed ? null : invoke<ReceiptsLocation[]>('detect_receipts_locations')))
+      .then((locations) => setReceiptsLocations(locations ?? null))
+      .catch(() => setReceiptsLocations(null));
+  }, []);
+
+  async function chooseReceiptsRoot(path: string) {
+    setError(null);
+    setNotice(null);
+    try {
+      const result = await invoke<InitializedRoot>('initialize_receipts_root', {
+        path,
+        createStructure: true
+      });
+      setReceiptsLocations(null);
+      const nextConfig = await invoke<AppConfig>('get_config');
+      setConfig(nextConfig);
+      setReceiptsRootInput(nextConfig.receipts_root || DEFAULT_RECEIPTS_ROOT);
+      setNotice(
+        result.env_override
+          ? `Saved ${result.receipts_root}; RECEIPTS_ROOT is set and still wins until it is unset.`
+          : `Receipts folder set to ${result.receipts_root} (${result.dirs.created.length} folders created).`
+      );
+    } catch (err) {
+      setError(errorText(err, 'Failed to set up the receipts folder.'));
+    }
+  }
+
+  async function dismissCrashReports() {
+    try {
+      for (const report of crashReports) {
+        await invoke('delete_crash_report', { name: report.name });
+      }
+      setCrashReports([]);
+    } catch (err) {
+      setError(errorText(err, 'Failed to delete crash report.'));
+    }
+  }
+
+  async function loadAppLogs() {
+    setError(null);
+    try {
+      setAppLogs(await invoke<AppLogs>('get_app_logs', { lines: 200, level: 'info' }));
+    } catch (err) {
+      setError(errorText(err));
+    }
+  }
+
+  async function openTerminal(location: string) {
+    setError(null);
+    try {
+      await invoke('open_terminal', { location });
+    } catch (err) {
+      setError(errorText(err));
+    }
+  }
+
+  async function revealPath(pathType: string, storeId?: string, filePath?: string) {
+    setError(null);
+    try {
+      await invoke('reveal_path', { pathType, storeId, filePath });
+    } catch (err) {
+      setError(errorText(err));
     }
   }
 
@@ -333,7 +689,7 @@ export default function App() {
       await invoke('mark_store_badges_seen', { storeId });
       refreshAll();
     } catch (err) {
-      setError(err instanceof Error ? err.message : 'Failed to mark seen.');
+      setError(errorText(err, 'Failed to mark seen.'));
     }
   }
 
@@ -341,15 +697,23 @@ export default function App() {
     setError(null);
     setNotice(null);
     setUpdateBusy(true);
+    setUpdateStage(null);
     try {
-      const result = await invoke<RunWorkerResult>('run_update');
+      const allowDirty =
+        !!updateStatus?.git?.dirty &&
+        window.confirm('The source checkout has uncommitted changes. Build and install them anyway?');
+      if (updateStatus?.git?.dirty && !allowDirty) {
+        return;
+      }
+      const result = await invoke<RunWorkerResult>('run_update', { allowDirty });
       setLastRunOutput(result);
       if (result.status === 'ok') {
         setNotice('Update completed. If the app did not relaunch, close and open it from the Dock.');
       }
+      await refreshUpdateStatus(true);
       refreshAll();
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorText(err));
     } finally {
       setUpdateBusy(false);
     }
@@ -370,7 +734,7 @@ export default function App() {
       setNotice('Settings saved.');
       refreshAll();
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorText(err));
     }
   }
 
@@ -399,13 +763,22 @@ export default function App() {
                     : updateStatus.status === 'update_available'
                       ? `Installed ${updateStatus.installed_version} / Source ${updateStatus.source_version ?? '?'}`
                       : updateStatus.status === 'up_to_date'
-                        ? `Installed ${updateStatus.installed_version}`
+                        ? `Installed ${updateStatus.installed_version}${
+                            updateStatus.git?.touches_app
+                              ? ` / ${updateStatus.git.commits_behind} commits behind origin/main${
+                                  updateStatus.git.stale ? ' (offline)' : ''
+                                }:\n${updateStatus.git.log.join('\n')}`
+                              : ''
+                          }`
                         : updateStatus.message ?? 'Update status error'
                 }
               >
                 {updateStatus.status === 'up_to_date' && 'Up to date'}
                 {updateStatus.status === 'update_available' && 'Update available'}
                 {updateStatus.status === 'source_missing' && 'Source missing'}
+                {updateStatus.status === 'ahead_of_source' && 'Ahead of source'}
+                {updateStatus.status === 'restart_pending' && 'Restart pending'}
+                {updateStatus.status === 'update_ineffective' && 'Update not applied'}
                 {updateStatus.status === 'error' && 'Update error'}
               </span>
             )}
@@ -415,6 +788,11 @@ export default function App() {
           <button className="ghost" onClick={() => setSettingsOpen((prev) => !prev)}>
             {settingsOpen ? 'Close settings' : 'Settings'}
           </button>
+          {updateStatus?.status === 'restart_pending' && (
+            <button className="ghost" onClick={() => invoke('relaunch_app')}>
+              Relaunch
+            </button>
+          )}
           <button
             className="ghost"
             disabled={updateBusy || updateStatus?.status !== 'update_available'}
@@ -429,7 +807,11 @@ export default function App() {
                     : 'Update not available'
             }
           >
-            {updateBusy ? 'Updating...' : 'Update'}
+            {updateBusy
+              ? updateStage
+                ? `Updating... ${updateStage.stage} (${updateStage.index}/${updateStage.total})`
+                : 'Updating...'
+              : 'Update'}
           </button>
           <button
             className="primary"
@@ -446,14 +828,50 @@ export default function App() {
           >
             Process selected
           </button>
-          <button onClick={() => refreshAll()}>Refresh</button>
+          <button onClick={() => refreshAll(0)}>Refresh</button>
           <button onClick={() => openPath('logs')}>Open logs folder</button>
+          <button onClick={() => openTerminal('worker_dir')}>Terminal in worker</button>
           <span className="version-text">Version {updateStatus?.installed_version ?? '1.0.1'}</span>
         </div>
       </header>
 
       {error && <div className="alert error">{error}</div>}
       {notice && <div className="alert success">{notice}</div>}
+      {crashReports.length > 0 && (
+        <div className="alert error">
+          The app crashed last time: {crashReports[0].message || 'unknown error'}.{' '}
+          <button onClick={() => dismissCrashReports()}>Dismiss</button>
+          <details>
+            <summary>Report ({crashReports[0].name})</summary>
+            <pre>{crashReports[0].contents}</pre>
+          </details>
+        </div>
+      )}
+      {receiptsLocations && (
+        <section className="panel">
+          <div className="section-title">
+            <h3>Welcome</h3>
+            <p>Pick the folder receipts are kept in. Store folders are created if missing.</p>
+          </div>
+          {receiptsLocations.length === 0 && <p>No Dropbox folder was found; set the receipts root below.</p>}
+          {receiptsLocations.map((location) => (
+            <div key={location.path}>
+              <code>{location.path}</code>{' '}
+              {location.has_store_folders ? '(has receipts)' : location.exists ? '(empty)' : '(will be created)'}{' '}
+              <button onClick={() => chooseReceiptsRoot(location.path)}>Use this folder</button>
+            </div>
+          ))}
+          <button onClick={() => chooseReceiptsRoot(receiptsRootInput.trim() || DEFAULT_RECEIPTS_ROOT)}>
+            Use the receipts root from settings
+          </button>
+        </section>
+      )}
+      {config && !config.home_available && (
+        <div className="alert error">
+          No home directory was found. Set the receipts root below (or RECEIPTS_ROOT), and
+          RECEIPTS_STATE_DIR to choose where app state is kept.
+        </div>
+      )}
 
       {!config?.config_ready || settingsOpen ? (
         <section className="panel settings">
@@ -525,7 +943,13 @@ export default function App() {
                 const isBusy = busyStores[store.id];
 
                 return (
-                  <div className={`store-card ${store.enabled ? '' : 'disabled'}`} key={store.id}>
+                  <div
+                    id={`store-${store.id}`}
+                    className={`store-card ${store.enabled ? '' : 'disabled'} ${
+                      linkTarget === `store-${store.id}` ? 'link-target' : ''
+                    }`}
+                    key={store.id}
+                  >
                     <div className="store-header">
                       <div>
                         <h2>{store.name}</h2>
@@ -598,6 +1022,8 @@ export default function App() {
                       <button onClick={() => openPath('inbox', store.id)}>Open inbox</button>
                       <button onClick={() => openPath('processed', store.id)}>Open processed</button>
                       <button onClick={() => openPath('failed', store.id)}>Open failed</button>
+                      <button onClick={() => revealPath('failed', store.id)}>Show failed in folder</button>
+                      <button onClick={() => copyPath('failed', store.id)}>Copy failed path</button>
                       <button onClick={() => markSeen(store.id)}>Mark warnings seen</button>
                     </div>
                   </div>
@@ -613,7 +1039,11 @@ export default function App() {
             </div>
             <div className="runs-grid">
               {runs.map((run) => (
-                <div className="run-card" key={run.run_id}>
+                <div
+                  id={`run-${run.run_id}`}
+                  className={`run-card ${linkTarget === `run-${run.run_id}` ? 'link-target' : ''}`}
+                  key={run.run_id}
+                >
                   <div>
                     <h4>{run.result ?? 'unknown'}</h4>
                     <p className="muted">{run.stores?.join(', ') || '—'}</p>
@@ -641,20 +1071,25 @@ export default function App() {
                     </div>
                   </div>
                   <div className="run-actions">
-                    <button
-                      onClick={() =>
-                        run.artifacts?.log_file
-                          ? openPath('logFile', undefined, run.artifacts.log_file)
-                          : openPath('logs')
-                      }
-                    >
+                    <button onClick={() => openRunArtifact(run.run_id, 'log')} disabled={!!missingLogs[run.run_id]}>
                       Open log
                     </button>
+                    <button onClick={() => openRunArtifact(run.run_id, 'summary')}>Open summary</button>
                     {run.failures?.[0]?.error_json && (
                       <button onClick={() => openPath('errorFile', undefined, run.failures?.[0]?.error_json)}>
                         Open error
                       </button>
                     )}
+                    {run.failures?.[0]?.error_json && (
+                      <button onClick={() => revealPath('errorFile', undefined, run.failures?.[0]?.error_json)}>
+                        Show error file
+                      </button>
+                    )}
+                    {run.failures?.[0]?.error_json && (
+                      <button onClick={() => copyPath('errorFile', undefined, run.failures?.[0]?.error_json)}>
+                        Copy error path
+                      </button>
+                    )}
                   </div>
                 </div>
               ))}
@@ -671,6 +1106,17 @@ export default function App() {
             <pre>{logLines.length ? logLines.join('\n') : 'No logs yet. Run a store to stream output.'}</pre>
           </section>
 
+          <section className="panel console secondary">
+            <div className="section-title">
+              <h3>App log</h3>
+              <p>
+                <button onClick={() => loadAppLogs()}>{appLogs ? 'Reload' : 'Show'}</button>
+                <button onClick={() => openPath('appLogs')}>Open folder</button>
+              </p>
+            </div>
+            {appLogs && <pre>{appLogs.lines.length ? appLogs.lines.join('\n') : 'No log entries yet.'}</pre>}
+          </section>
+
           <section className="panel console secondary">
             <div className="section-title">
               <h3>Last run output</h3>
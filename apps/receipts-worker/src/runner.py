@@ -26,6 +26,7 @@ def _parse_args() -> argparse.Namespace:
     parser.add_argument("--no-move", action="store_true", help="Do not move files")
     parser.add_argument("--no-json", action="store_true", help="Do not write JSON artifacts")
     parser.add_argument("--root", help="Override receipts root path")
+    parser.add_argument("--inbox", help="Read images from this folder instead of inbox/<store>")
     parser.add_argument("--batch-size", type=int, default=10, help="Files per batch")
     return parser.parse_args()
 
@@ -58,7 +59,7 @@ def process_store(store: str, args: argparse.Namespace) -> int:
     except ValidationError as exc:
         raise SystemExit(str(exc)) from exc
 
-    inbox_dir = settings.receipts_root / "inbox" / store
+    inbox_dir = Path(args.inbox) if args.inbox else settings.receipts_root / "inbox" / store
     processed_dir = settings.receipts_root / "processed" / store
     failed_dir = settings.receipts_root / "failed" / store
 
@@ -159,6 +160,8 @@ def main() -> int:
     args = _parse_args()
     if not args.store and not args.all:
         raise SystemExit("Use --store <name> or --all")
+    if args.inbox and args.all:
+        raise SystemExit("--inbox needs a single --store")
 
     stores = list_stores() if args.all else [args.store]
     exit_code = 0